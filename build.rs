@@ -0,0 +1,33 @@
+use std::process::Command;
+
+// Embeds the build's commit hash as BUILD_HASH; builds outside a git checkout (e.g. Docker)
+// can pass BUILD_HASH through the environment instead.
+fn main() {
+    println!("cargo:rerun-if-env-changed=BUILD_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    let hash = std::env::var("BUILD_HASH").ok().filter(|h| !h.is_empty()).or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| String::from_utf8(o.stdout).ok())
+            .map(|s| s.trim().to_string())
+    });
+    println!("cargo:rustc-env=BUILD_HASH={}", hash.unwrap_or_else(|| "unknown".to_string()));
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+// gRPC stubs; a vendored protoc is used unless PROTOC points at another
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/llm_serving.proto");
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        // SAFETY: build scripts are single-threaded
+        unsafe { std::env::set_var("PROTOC", protoc) };
+    }
+    tonic_build::compile_protos("proto/llm_serving.proto").expect("compile proto/llm_serving.proto");
+}
@@ -0,0 +1,258 @@
+//! Translation between Anthropic's Messages API and the engine's chat completions, so clients
+//! built on Anthropic SDKs can use this server as-is.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::Value;
+
+use crate::api::{
+    dto::{
+        AnthropicContent, AnthropicContentBlock, AnthropicErrorBody, AnthropicErrorResponse, AnthropicImageSource,
+        AnthropicMessageDelta, AnthropicSystem, AnthropicTextDelta, AnthropicUsage, ChatCompletionMessage,
+        ChatCompletionRequest, ChatCompletionResponse, ChatMessageContent, ChatStreamError, ContentPart, ImageUrl, MessageStreamEvent,
+        MessagesRequest, MessagesResponse, StopSequences, StreamOptions,
+    },
+    error::AppError,
+};
+
+/// `AppError` rendered in Anthropic's error envelope.
+pub struct AnthropicError(pub AppError);
+
+impl From<AppError> for AnthropicError {
+    fn from(err: AppError) -> Self {
+        AnthropicError(err)
+    }
+}
+
+impl AnthropicError {
+    /// The status and error object this error is answered with; streams end with the object
+    /// alone.
+    pub fn into_parts(self) -> (StatusCode, AnthropicErrorBody) {
+        let (status, kind, message) = match self.0 {
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "authentication_error", msg),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, "permission_error", msg),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", msg),
+            AppError::UnprocessableEntity { message, .. } => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_request_error", message),
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large", msg),
+            AppError::NotFound(msg) | AppError::ModelNotFound(msg) => (StatusCode::NOT_FOUND, "not_found_error", msg),
+            AppError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, "overloaded_error", msg),
+            AppError::TooManyRequests(msg) | AppError::RateLimited { message: msg, .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", msg),
+            AppError::Timeout(msg) => (StatusCode::GATEWAY_TIMEOUT, "api_error", msg),
+            AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error", msg),
+        };
+        (status, AnthropicErrorBody { kind: kind.to_string(), message })
+    }
+}
+
+impl IntoResponse for AnthropicError {
+    fn into_response(self) -> Response {
+        let retry_after = self.0.retry_after();
+        let (status, error) = self.into_parts();
+        let body = Json(AnthropicErrorResponse { kind: "error".to_string(), error });
+        match retry_after {
+            Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+/// The equivalent chat completion request; streams always report usage, which
+/// `message_delta` carries.
+pub fn to_chat_request(request: MessagesRequest) -> Result<ChatCompletionRequest, String> {
+    let mut messages = Vec::with_capacity(request.messages.len() + 1);
+    if let Some(system) = request.system {
+        let text = match system {
+            AnthropicSystem::Text(text) => text,
+            AnthropicSystem::Blocks(blocks) => blocks
+                .into_iter()
+                .map(|block| match block {
+                    AnthropicContentBlock::Text { text } => Ok(text),
+                    _ => Err("system blocks must be text".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join("\n"),
+        };
+        messages.push(ChatCompletionMessage { role: "system".to_string(), content: ChatMessageContent::Text(text) });
+    }
+    for message in request.messages {
+        if message.role != "user" && message.role != "assistant" {
+            return Err(format!("messages: role must be \"user\" or \"assistant\", got {:?}", message.role));
+        }
+        let content = match message.content {
+            AnthropicContent::Text(text) => ChatMessageContent::Text(text),
+            AnthropicContent::Blocks(blocks) => ChatMessageContent::Parts(blocks.into_iter().map(content_part).collect::<Result<_, _>>()?),
+        };
+        messages.push(ChatCompletionMessage { role: message.role, content });
+    }
+    Ok(ChatCompletionRequest {
+        model: request.model,
+        messages,
+        stream: Some(request.stream),
+        max_tokens: Some(request.max_tokens),
+        temperature: request.temperature,
+        top_p: request.top_p,
+        stop: request.stop_sequences.map(StopSequences::Many),
+        stream_options: request.stream.then(|| StreamOptions { include_usage: true, ..Default::default() }),
+        user: request.metadata.and_then(|m| m.user_id),
+        ..Default::default()
+    })
+}
+
+fn content_part(block: AnthropicContentBlock) -> Result<ContentPart, String> {
+    match block {
+        AnthropicContentBlock::Text { text } => Ok(ContentPart::Text { text }),
+        AnthropicContentBlock::Image { source } => {
+            let url = match source {
+                AnthropicImageSource::Base64 { media_type, data } => format!("data:{};base64,{}", media_type, data),
+                AnthropicImageSource::Url { url } => url,
+            };
+            Ok(ContentPart::ImageUrl { image_url: ImageUrl { url, detail: None } })
+        }
+        AnthropicContentBlock::Unsupported => Err("only text and image content blocks are supported".to_string()),
+    }
+}
+
+/// Anthropic's name for an OpenAI `finish_reason`; a generation that ended on one of the
+/// request's `stop_sequences` is a `stop_sequence` stop.
+pub fn stop_reason(finish_reason: &str, stop_sequence: Option<&str>) -> &'static str {
+    match (finish_reason, stop_sequence) {
+        ("length", _) => "max_tokens",
+        (_, Some(_)) => "stop_sequence",
+        _ => "end_turn",
+    }
+}
+
+fn message_id() -> String {
+    format!("msg_{}", uuid::Uuid::new_v4().simple())
+}
+
+pub fn from_chat_response(response: ChatCompletionResponse) -> MessagesResponse {
+    let choice = response.choices.into_iter().next();
+    let (text, finish_reason, stop_sequence) =
+        choice.map_or((String::new(), "stop".to_string(), None), |c| (c.message.content, c.finish_reason, c.stop_sequence));
+    MessagesResponse {
+        id: message_id(),
+        kind: "message".to_string(),
+        role: "assistant".to_string(),
+        model: response.model,
+        content: vec![AnthropicContentBlock::Text { text }],
+        stop_reason: Some(stop_reason(&finish_reason, stop_sequence.as_deref()).to_string()),
+        stop_sequence,
+        usage: AnthropicUsage { input_tokens: response.usage.prompt_tokens, output_tokens: response.usage.completion_tokens },
+    }
+}
+
+/// Turns the engine's chat completion chunks into Messages stream events: `message_start`,
+/// one text block's start, deltas and stop, then `message_delta` and `message_stop`.
+pub struct StreamTranslator {
+    model: String,
+    started: bool,
+    finish_reason: Option<String>,
+    stop_sequence: Option<String>,
+    usage: AnthropicUsage,
+    done: bool,
+}
+
+impl StreamTranslator {
+    pub fn new(model: String) -> Self {
+        Self { model, started: false, finish_reason: None, stop_sequence: None, usage: AnthropicUsage::default(), done: false }
+    }
+
+    /// Events for one chunk (or the `[DONE]` sentinel, or the error ending a failed stream).
+    pub fn translate(&mut self, chunk: &str) -> Vec<MessageStreamEvent> {
+        let mut events = Vec::new();
+        if chunk == "[DONE]" {
+            self.done = true;
+            events.extend(self.start());
+            events.push(MessageStreamEvent::ContentBlockStop { index: 0 });
+            events.push(MessageStreamEvent::MessageDelta {
+                delta: AnthropicMessageDelta {
+                    stop_reason: Some(
+                        stop_reason(self.finish_reason.as_deref().unwrap_or("stop"), self.stop_sequence.as_deref()).to_string(),
+                    ),
+                    stop_sequence: self.stop_sequence.clone(),
+                },
+                usage: self.usage,
+            });
+            events.push(MessageStreamEvent::MessageStop);
+            return events;
+        }
+        // A failed generation ends the stream with its error
+        if let Some(e) = ChatStreamError::parse(chunk) {
+            self.done = true;
+            let (_, error) = AnthropicError(e.into()).into_parts();
+            events.push(MessageStreamEvent::Error { error });
+            return events;
+        }
+        let Ok(chunk) = serde_json::from_str::<Value>(chunk) else { return events };
+        if let Some(model) = chunk["model"].as_str() {
+            self.model = model.to_string();
+        }
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = AnthropicUsage {
+                input_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0) as u32,
+                output_tokens: usage["completion_tokens"].as_u64().unwrap_or(0) as u32,
+            };
+        }
+        let choice = &chunk["choices"][0];
+        events.extend(self.start());
+        if let Some(text) = choice["delta"]["content"].as_str() {
+            events.push(MessageStreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: AnthropicTextDelta { kind: "text_delta".to_string(), text: text.to_string() },
+            });
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.finish_reason = Some(reason.to_string());
+            self.stop_sequence = choice["stop_sequence"].as_str().map(str::to_string);
+        }
+        events
+    }
+
+    /// An `error` event if the engine's stream closed before `[DONE]`.
+    pub fn finish(&self) -> Option<MessageStreamEvent> {
+        (!self.done).then(|| MessageStreamEvent::Error {
+            error: AnthropicErrorBody { kind: "api_error".to_string(), message: "generation ended before completing".to_string() },
+        })
+    }
+
+    fn start(&mut self) -> Vec<MessageStreamEvent> {
+        if std::mem::replace(&mut self.started, true) {
+            return Vec::new();
+        }
+        let message = MessagesResponse {
+            id: message_id(),
+            kind: "message".to_string(),
+            role: "assistant".to_string(),
+            model: self.model.clone(),
+            content: Vec::new(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage: AnthropicUsage::default(),
+        };
+        vec![
+            MessageStreamEvent::MessageStart { message },
+            MessageStreamEvent::ContentBlockStart { index: 0, content_block: AnthropicContentBlock::Text { text: String::new() } },
+            MessageStreamEvent::Ping,
+        ]
+    }
+}
+
+impl MessageStreamEvent {
+    /// SSE event name, the same as the payload's `type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageStreamEvent::MessageStart { .. } => "message_start",
+            MessageStreamEvent::ContentBlockStart { .. } => "content_block_start",
+            MessageStreamEvent::Ping => "ping",
+            MessageStreamEvent::ContentBlockDelta { .. } => "content_block_delta",
+            MessageStreamEvent::ContentBlockStop { .. } => "content_block_stop",
+            MessageStreamEvent::MessageDelta { .. } => "message_delta",
+            MessageStreamEvent::MessageStop => "message_stop",
+            MessageStreamEvent::Error { .. } => "error",
+        }
+    }
+}
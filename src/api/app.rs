@@ -0,0 +1,130 @@
+//! The HTTP routers: the inference API, and the management routes served either alongside it
+//! or on a listener of their own.
+
+use std::sync::Arc;
+
+use axum::{extract::DefaultBodyLimit, routing::post, Router};
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::api::{self, limits::{EndpointClass, EndpointLimitLayer}};
+use crate::engine::CoreEngine;
+
+/// The public router and, with `separate_admin`, the router of the admin listener. Otherwise the
+/// `/admin/*` routes and `/admin/metrics` are served on the public one. `/health` answers on
+/// both.
+pub fn routers(engine: Arc<CoreEngine>, prom_handle: PrometheusHandle, separate_admin: bool) -> (Router, Option<Router>) {
+    // Each endpoint class gets its own HTTP concurrency slots and timeout
+    let chat = Router::new()
+        .route("/v1/chat/completions", post(api::routes::chat_completions))
+        .route("/v1/messages", post(api::routes::messages))
+        .route("/v1/ws", axum::routing::get(api::routes::websocket))
+        .route("/v1/realtime", axum::routing::get(api::routes::realtime))
+        .route_layer(EndpointLimitLayer::from_env(EndpointClass::Chat));
+    let embeddings = Router::new()
+        .route("/v1/embeddings", post(api::routes::embeddings))
+        .route("/v1/similarity", post(api::routes::similarity))
+        .route("/v1/rerank", post(api::routes::rerank))
+        .route_layer(EndpointLimitLayer::from_env(EndpointClass::Embeddings));
+    let images = Router::new()
+        .route("/v1/images/generations", post(api::routes::images_generations))
+        .route(
+            "/v1/images/edits",
+            post(api::routes::images_edits).layer(DefaultBodyLimit::max(api::routes::image_max_upload_bytes())),
+        )
+        .route(
+            "/v1/images/variations",
+            post(api::routes::images_variations).layer(DefaultBodyLimit::max(api::routes::image_max_upload_bytes())),
+        )
+        .route("/v1/images/artifacts/:name", axum::routing::get(api::routes::images_artifact))
+        .route_layer(EndpointLimitLayer::from_env(EndpointClass::Images));
+    let audio = Router::new()
+        .route(
+            "/v1/audio/transcriptions",
+            post(api::routes::audio_transcriptions).layer(DefaultBodyLimit::max(api::routes::audio_max_upload_bytes())),
+        )
+        .route("/v1/audio/speech", post(api::routes::audio_speech))
+        .route_layer(EndpointLimitLayer::from_env(EndpointClass::Audio));
+    let admin = Router::new()
+        .route("/admin/models", axum::routing::get(api::routes::admin_models_list))
+        .route("/admin/models/load", post(api::routes::admin_models_load))
+        .route("/admin/models/unload", post(api::routes::admin_models_unload))
+        .route("/admin/models/swap", post(api::routes::admin_models_swap))
+        .route("/admin/models/swaps", axum::routing::get(api::routes::admin_models_swaps_list))
+        .route("/admin/models/swaps/:id", axum::routing::get(api::routes::admin_models_swaps_get))
+        .route("/admin/models/inspect", post(api::routes::admin_models_inspect))
+        .route("/admin/downloads", post(api::routes::admin_downloads_create).get(api::routes::admin_downloads_list))
+        .route("/admin/downloads/:id", axum::routing::get(api::routes::admin_downloads_get))
+        .route("/admin/downloads/:id/cancel", post(api::routes::admin_downloads_cancel))
+        .route("/admin/keys", post(api::routes::admin_keys_create).get(api::routes::admin_keys_list))
+        .route("/admin/keys/:id", axum::routing::get(api::routes::admin_keys_get))
+        .route("/admin/keys/:id/revoke", post(api::routes::admin_keys_revoke))
+        .route("/admin/evals", post(api::routes::admin_evals_create).get(api::routes::admin_evals_list))
+        .route("/admin/evals/:id/runs", post(api::routes::admin_evals_run).get(api::routes::admin_evals_history))
+        .route("/admin/evals/runs/:run_id", axum::routing::get(api::routes::admin_evals_run_get))
+        .route("/admin/canaries", post(api::routes::admin_canaries_create).get(api::routes::admin_canaries_list))
+        .route("/admin/canaries/remove", post(api::routes::admin_canaries_remove))
+        .route("/admin/canaries/audit", axum::routing::get(api::routes::admin_canaries_audit))
+        .route("/admin/fallbacks", post(api::routes::admin_fallbacks_create).get(api::routes::admin_fallbacks_list))
+        .route("/admin/fallbacks/remove", post(api::routes::admin_fallbacks_remove))
+        .route("/admin/splits", post(api::routes::admin_splits_create).get(api::routes::admin_splits_list))
+        .route("/admin/splits/remove", post(api::routes::admin_splits_remove))
+        .route("/admin/events", axum::routing::get(api::routes::admin_events))
+        .route("/admin/stats", axum::routing::get(api::routes::admin_stats))
+        .route("/admin/usage", axum::routing::get(api::routes::admin_usage))
+        .route("/admin/usage/safety/pardon", post(api::routes::admin_safety_pardon))
+        .route("/admin/state/export", axum::routing::get(api::routes::admin_state_export))
+        .route("/admin/state/import", post(api::routes::admin_state_import))
+        .route("/admin/reload", post(api::routes::admin_reload))
+        .route_layer(EndpointLimitLayer::from_env(EndpointClass::Admin))
+        // Scrapes take no admin slots, but need the admin scope like every other admin route
+        .route("/admin/metrics", axum::routing::get({
+            let handle = prom_handle.clone();
+            move || {
+                let body = handle.render();
+                async move {
+                    axum::response::Response::builder()
+                        .header("content-type", "text/plain; version=0.0.4")
+                        .body(axum::body::Body::from(body))
+                        .unwrap()
+                }
+            }
+        }))
+        // Outside the endpoint limits, so refused callers take no admin slots
+        .route_layer(axum::middleware::from_fn_with_state(engine.clone(), api::auth::require_admin));
+
+    let health = Router::new().route("/health", axum::routing::get(|| async { axum::Json(serde_json::json!({"status":"ok"})) }));
+
+    let public = Router::new()
+        .merge(chat)
+        .merge(embeddings)
+        .merge(images)
+        .merge(audio)
+        .route(
+            "/v1/files",
+            post(api::routes::files_upload)
+                .get(api::routes::files_list)
+                .layer(DefaultBodyLimit::max(api::routes::file_max_upload_bytes())),
+        )
+        .route("/v1/files/:id", axum::routing::get(api::routes::files_get).delete(api::routes::files_delete))
+        .route("/v1/files/:id/content", axum::routing::get(api::routes::files_content))
+        .route(
+            "/v1/batches",
+            post(api::routes::batches_create)
+                .get(api::routes::batches_list)
+                .layer(DefaultBodyLimit::max(api::routes::batch_max_upload_bytes())),
+        )
+        .route("/v1/batches/:id", axum::routing::get(api::routes::batches_get))
+        .route("/v1/batches/:id/cancel", post(api::routes::batches_cancel))
+        .route("/v1/batches/:id/output", axum::routing::get(api::routes::batches_output))
+        .route("/v1/server/info", axum::routing::get(api::routes::server_info))
+        .merge(health.clone())
+        // Upload routes set their own, larger limits above
+        .layer(DefaultBodyLimit::max(api::validation::max_body_bytes()));
+
+
+    if separate_admin {
+        (public.with_state(engine.clone()), Some(admin.merge(health).with_state(engine)))
+    } else {
+        (public.merge(admin).with_state(engine), None)
+    }
+}
@@ -0,0 +1,108 @@
+//! Generated images kept in the shared storage backend and handed out as URLs.
+//!
+//! URLs are signed with an expiry by default, so an artifact can be fetched without an API key
+//! by whoever received the link, for a limited time. With a TTL of 0 they are static instead:
+//! unsigned and valid for as long as the object exists.
+
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use sha2::Sha256;
+use std::sync::Arc;
+
+use crate::storage::{self, Storage};
+
+pub struct ArtifactConfig {
+    // Prefix of every URL, e.g. "https://llm.example.com"
+    pub base_url: String,
+    pub signing_key: Vec<u8>,
+    // Seconds a signed URL stays valid; 0 issues static URLs
+    pub url_ttl_secs: u64,
+}
+
+impl ArtifactConfig {
+    /// ENV: PUBLIC_BASE_URL (default http://localhost:3000), ARTIFACT_SIGNING_KEY (default: random
+    /// per process, so signed URLs stop working after a restart), IMAGE_URL_TTL_SECS (default
+    /// 3600; 0 issues static unsigned URLs)
+    pub fn from_env() -> Self {
+        let signing_key = match crate::config::var("ARTIFACT_SIGNING_KEY").ok().filter(|k| !k.is_empty()) {
+            Some(key) => key.into_bytes(),
+            None => uuid::Uuid::new_v4().as_bytes().to_vec(),
+        };
+        Self {
+            base_url: crate::config::var("PUBLIC_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            signing_key,
+            url_ttl_secs: crate::config::var("IMAGE_URL_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600),
+        }
+    }
+}
+
+pub struct ArtifactStore {
+    storage: Arc<dyn Storage>,
+    config: ArtifactConfig,
+}
+
+static ARTIFACTS: Lazy<ArtifactStore> = Lazy::new(|| ArtifactStore::new(storage::scoped("images"), ArtifactConfig::from_env()));
+
+/// The store behind `response_format: "url"` and `GET /v1/images/artifacts/:name`.
+pub fn global() -> &'static ArtifactStore {
+    &ARTIFACTS
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl ArtifactStore {
+    pub fn new(storage: Arc<dyn Storage>, config: ArtifactConfig) -> Self {
+        Self { storage, config }
+    }
+
+    fn mac(&self, name: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.config.signing_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}:{}", name, expires).as_bytes());
+        mac
+    }
+
+    /// Stores a PNG and returns the URL it can be fetched from.
+    pub async fn put_image(&self, bytes: Vec<u8>) -> Result<String, String> {
+        let name = format!("img-{}.png", uuid::Uuid::new_v4().simple());
+        self.storage.put(&name, bytes).await?;
+        Ok(self.url(&name))
+    }
+
+    pub fn url(&self, name: &str) -> String {
+        let base = format!("{}/v1/images/artifacts/{}", self.config.base_url, name);
+        if self.config.url_ttl_secs == 0 {
+            return base;
+        }
+        let expires = now_secs() + self.config.url_ttl_secs;
+        let signature: String = self.mac(name, expires).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}?expires={}&signature={}", base, expires, signature)
+    }
+
+    /// The artifact `name`, or None when it does not exist or the URL is unsigned, tampered
+    /// with or expired (callers cannot tell these apart).
+    pub async fn get_image(&self, name: &str, expires: Option<u64>, signature: Option<&str>) -> Result<Option<Vec<u8>>, String> {
+        if self.config.url_ttl_secs > 0 {
+            let (Some(expires), Some(signature)) = (expires, signature) else { return Ok(None) };
+            let Some(signature) = decode_hex(signature) else { return Ok(None) };
+            if expires < now_secs() || self.mac(name, expires).verify_slice(&signature).is_err() {
+                return Ok(None);
+            }
+        }
+        if storage::validate_key(name).is_err() || name.contains('/') {
+            return Ok(None);
+        }
+        self.storage.get(name).await
+    }
+}
+
+pub(crate) fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -1,39 +1,215 @@
-use axum::http::HeaderMap;
-use governor::{Quota, RateLimiter, state::keyed::DefaultKeyedStateStore, clock::DefaultClock};
-use nonzero_ext::nonzero;
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use governor::{Quota, RateLimiter, state::keyed::DefaultKeyedStateStore, clock::{Clock, DefaultClock}};
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use crate::api::{dto::Priority, error::AppError};
+use crate::engine::{keys::KeyStore, CoreEngine};
+use std::{num::NonZeroU32, sync::{Arc, RwLock}};
 
 type Limiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
-static RATE_LIMITER: Lazy<Limiter> = Lazy::new(|| {
-    // Default: 60 req per minute per key
-    let q = Quota::per_minute(nonzero!(60u32));
-    RateLimiter::keyed(q)
-});
-
-pub fn authorize_request(headers: &HeaderMap) -> Result<(), String> {
-    // Read API_KEYS from env. If empty, auth disabled.
-    let keys_env = std::env::var("API_KEYS").ok().unwrap_or_default();
-    let keys: Vec<String> = keys_env
+/// Requests per minute allowed for each API key unless RATE_LIMIT_PER_MINUTE says otherwise.
+pub const REQUESTS_PER_MINUTE: u32 = 60;
+// The limiter and the rate it was built for; rebuilt when the setting changes
+static RATE_LIMITER: Lazy<RwLock<(u32, Arc<Limiter>)>> = Lazy::new(|| RwLock::new((REQUESTS_PER_MINUTE, Arc::new(limiter(REQUESTS_PER_MINUTE)))));
+
+fn limiter(per_minute: u32) -> Limiter {
+    RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(per_minute).unwrap_or(NonZeroU32::MIN)))
+}
+
+/// ENV: RATE_LIMIT_PER_MINUTE (default 60), requests per minute for each API key
+pub fn requests_per_minute() -> u32 {
+    crate::config::var("RATE_LIMIT_PER_MINUTE").ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(REQUESTS_PER_MINUTE)
+}
+
+// A changed rate starts every key with a full allowance
+fn rate_limiter() -> Arc<Limiter> {
+    let per_minute = requests_per_minute();
+    {
+        let current = RATE_LIMITER.read().unwrap();
+        if current.0 == per_minute {
+            return current.1.clone();
+        }
+    }
+    let mut current = RATE_LIMITER.write().unwrap();
+    if current.0 != per_minute {
+        *current = (per_minute, Arc::new(limiter(per_minute)));
+    }
+    current.1.clone()
+}
+
+// Takes one request from `key`'s allowance; a caller out of allowance is told how long until
+// its next request would be let through
+fn check_rate(key: String) -> Result<(), AuthError> {
+    let limiter = rate_limiter();
+    limiter.check_key(&key).map_err(|not_until| {
+        let wait = not_until.wait_time_from(DefaultClock::default().now());
+        AuthError::RateLimited { retry_after_secs: wait.as_secs() + u64::from(wait.subsec_nanos() > 0) }
+    })
+}
+
+/// Header with the identity of the client certificate a request came with, set by the HTTPS
+/// listeners on every request (see [`crate::tls`]) and trusted only when they verify client
+/// certificates.
+pub const CLIENT_PRINCIPAL_HEADER: &str = "x-client-principal";
+
+// The verified client certificate's identity, when client certificates are checked and no
+// API key is sent along, which then names the caller instead
+fn client_principal(headers: &HeaderMap) -> Option<&str> {
+    if !crate::tls::client_auth_enabled() || api_key(headers).is_some() {
+        return None;
+    }
+    headers.get(CLIENT_PRINCIPAL_HEADER).and_then(|v| v.to_str().ok())
+}
+
+/// What a key may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// The inference API: chat, embeddings, audio, images, files and batches, over HTTP,
+    /// WebSocket and gRPC.
+    Inference,
+    /// The `/admin/*` routes.
+    Admin,
+}
+
+impl Scope {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Scope::Inference => "inference",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+// Keys listed in `var`, accepted alongside those created through `/admin/keys`; enough to
+// create the first of those
+fn configured_keys(var: &str) -> Vec<String> {
+    crate::config::var(var)
+        .ok()
+        .unwrap_or_default()
         .split(',')
         .filter(|s| !s.trim().is_empty())
         .map(|s| s.trim().to_string())
-        .collect();
-    if keys.is_empty() {
-        return Ok(());
+        .collect()
+}
+
+// Whether a known key may call `scope`'s routes; None for an unknown one.
+// ENV: ADMIN_API_KEYS, keys with every scope. Once there is an admin key, in ADMIN_API_KEYS
+// or stored, API_KEYS only grants inference; before, it stays the one list for everything.
+async fn key_allows(keys: &KeyStore, token: &str, scope: Scope) -> Result<Option<bool>, String> {
+    let admin_keys = configured_keys("ADMIN_API_KEYS");
+    if admin_keys.iter().any(|k| k == token) {
+        return Ok(Some(true));
+    }
+    if configured_keys("API_KEYS").iter().any(|k| k == token) {
+        return Ok(Some(scope == Scope::Inference || (admin_keys.is_empty() && !keys.any_with_scope(Scope::Admin))));
     }
-    let auth_header = headers
+    Ok(keys.find(token).await?.map(|key| key.scopes.iter().any(|s| s == scope.as_str())))
+}
+
+/// SHA-256 hex digests of the configured API keys, safe to export.
+pub fn configured_key_hashes() -> Vec<String> {
+    configured_keys("API_KEYS")
+        .into_iter()
+        .chain(configured_keys("ADMIN_API_KEYS"))
+        .map(|k| format!("{:x}", Sha256::digest(k.as_bytes())))
+        .collect()
+}
+
+// The API key: a bearer token, or `x-api-key` as Anthropic clients send it
+fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
         .get("authorization")
         .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    if let Some(token) = auth_header.strip_prefix("Bearer ") {
-        if keys.iter().any(|k| k == token) {
-            // Rate limit per token (if present)
-            if RATE_LIMITER.check_key(&token.to_string()).is_ok() {
-                return Ok(());
-            } else {
-                return Err("Rate limit exceeded".to_string());
-            }
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+}
+
+/// Stable, non-secret identity of the caller: a digest prefix of its API key, its client
+/// certificate's identity when it sent no key, or "anonymous" when it has neither.
+pub fn client_id(headers: &HeaderMap) -> String {
+    if let Some(principal) = client_principal(headers) {
+        return format!("cert:{}", principal);
+    }
+    api_key(headers)
+        .map(|token| format!("key-{}", &format!("{:x}", Sha256::digest(token.as_bytes()))[..12]))
+        .unwrap_or_else(|| crate::engine::scheduler::ANONYMOUS_CLIENT.to_string())
+}
+
+/// Scheduling priority of a request: the one it asked for, capped at its API key's.
+/// ENV: API_KEY_PRIORITIES (e.g. "key1=high,key2=low", where client certificate identities
+/// may stand in for keys); unlisted keys and anonymous callers get "normal"
+pub fn priority(headers: &HeaderMap, requested: Option<Priority>) -> Priority {
+    let ceiling = client_principal(headers)
+        .or_else(|| api_key(headers))
+        .and_then(|token| {
+            crate::config::var("API_KEY_PRIORITIES").ok()?.split(',').find_map(|pair| {
+                let (key, priority) = pair.split_once('=')?;
+                (key.trim() == token).then(|| priority.trim().parse().ok()).flatten()
+            })
+        })
+        .unwrap_or_default();
+    requested.map_or(ceiling, |p| p.min(ceiling))
+}
+
+/// Why a request was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+    /// No API key, or one that is not known.
+    #[error("Unauthorized")]
+    Unauthorized,
+    /// A known key without the scope the route needs.
+    #[error("API key lacks the scope {:?}", .0.as_str())]
+    Forbidden(Scope),
+    /// Over its key's (or certificate's) rate limit, with the seconds until it may retry.
+    #[error("Rate limit exceeded")]
+    RateLimited { retry_after_secs: u64 },
+}
+
+/// Checks the caller may call `scope`'s routes: with auth on (API_KEYS, ADMIN_API_KEYS or
+/// stored keys in `keys`), its key must be known and hold the scope, and it must be within its
+/// rate limit. A verified client certificate sent without a key holds the inference scope, and
+/// the admin scope when listed in TLS_ADMIN_PRINCIPALS; a key sent along with one is checked
+/// like any other and decides alone.
+pub async fn authorize_request(keys: &KeyStore, headers: &HeaderMap, scope: Scope) -> Result<(), AuthError> {
+    // The TLS handshake verified the certificate; its identity is limited like a key.
+    // ENV: TLS_PRINCIPALS, the certificate identities accepted in place of a key (unset: every
+    // verified one); TLS_ADMIN_PRINCIPALS, those that also hold the admin scope
+    if let Some(principal) = client_principal(headers) {
+        let admins = configured_keys("TLS_ADMIN_PRINCIPALS");
+        let admin = admins.iter().any(|p| p == principal);
+        let allowed = configured_keys("TLS_PRINCIPALS");
+        if !admin && !allowed.is_empty() && !allowed.iter().any(|p| p == principal) {
+            return Err(AuthError::Unauthorized);
+        }
+        if scope == Scope::Admin && !admin {
+            return Err(AuthError::Forbidden(scope));
         }
+        return check_rate(format!("cert:{}", principal));
     }
-    Err("Unauthorized".to_string())
+    if configured_keys("API_KEYS").is_empty() && configured_keys("ADMIN_API_KEYS").is_empty() && !keys.any_active() {
+        return Ok(());
+    }
+    let Some(token) = api_key(headers) else { return Err(AuthError::Unauthorized) };
+    let allowed = key_allows(keys, token, scope).await.unwrap_or_else(|e| {
+        // Refused rather than let through while the storage cannot vouch for the key
+        tracing::warn!("could not look up API key: {}", e);
+        None
+    });
+    match allowed {
+        None => Err(AuthError::Unauthorized),
+        Some(false) => Err(AuthError::Forbidden(scope)),
+        // Rate limit per token
+        Some(true) => check_rate(token.to_string()),
+    }
+}
+
+/// Middleware in front of the `/admin` routes: every one of them needs the admin scope.
+pub async fn require_admin(State(engine): State<Arc<CoreEngine>>, request: Request, next: Next) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), request.headers(), Scope::Admin).await?;
+    Ok(next.run(request).await)
 }
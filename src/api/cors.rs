@@ -0,0 +1,88 @@
+//! Cross-origin resource sharing, so browser applications on other origins can call the API.
+//! Off unless allowed origins are configured; preflight requests are answered before routing,
+//! authentication and endpoint limits, and the CORS headers go out with the response head, so
+//! streamed (SSE) responses carry them like any other.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer, ExposeHeaders};
+
+/// Methods allowed unless CORS_ALLOWED_METHODS lists others.
+pub const DEFAULT_METHODS: [&str; 3] = ["GET", "POST", "DELETE"];
+
+// Response headers of this API a browser application may want to read
+const EXPOSED_HEADERS: [&str; 3] = ["x-cache", "x-fallback-from", "retry-after"];
+
+fn list(name: &str) -> Vec<String> {
+    crate::config::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The CORS layer the settings ask for, None when no origin is allowed.
+///
+/// ENV: CORS_ALLOWED_ORIGINS, comma-separated origins such as `https://app.example.com`, or
+/// `*` for any; CORS_ALLOWED_HEADERS, request headers allowed (default: whichever a preflight
+/// asks for); CORS_ALLOWED_METHODS (default GET, POST, DELETE); CORS_ALLOW_CREDENTIALS
+/// (`1`/`true`: cookies and client certificates, which need explicit origins);
+/// CORS_MAX_AGE_SECS, how long browsers may cache a preflight answer (default 600)
+pub fn layer_from_env() -> Result<Option<CorsLayer>, String> {
+    let credentials = crate::config::var("CORS_ALLOW_CREDENTIALS").is_ok_and(|v| v == "1" || v == "true");
+    let max_age = match crate::config::var("CORS_MAX_AGE_SECS") {
+        Ok(secs) => Some(secs.parse().map_err(|_| format!("invalid CORS_MAX_AGE_SECS {:?}", secs))?),
+        Err(_) => None,
+    };
+    layer(&list("CORS_ALLOWED_ORIGINS"), &list("CORS_ALLOWED_HEADERS"), &list("CORS_ALLOWED_METHODS"), credentials, max_age)
+}
+
+/// Builds the CORS layer for `origins`, checking the settings first: tower-http panics on
+/// combinations the CORS specification forbids, such as credentials with any origin.
+pub fn layer(origins: &[String], headers: &[String], methods: &[String], credentials: bool, max_age_secs: Option<u64>) -> Result<Option<CorsLayer>, String> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    let any_origin = origins.iter().any(|o| o == "*");
+    if any_origin && credentials {
+        return Err("CORS credentials need explicit allowed origins, not *".to_string());
+    }
+    let origin = match any_origin {
+        true => AllowOrigin::any(),
+        false => AllowOrigin::list(
+            origins
+                .iter()
+                .map(|o| HeaderValue::from_str(o.trim_end_matches('/')).map_err(|_| format!("invalid CORS origin {:?}", o)))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    let allow_headers = match headers {
+        [] => AllowHeaders::mirror_request(),
+        [any] if any == "*" && !credentials => AllowHeaders::any(),
+        headers => AllowHeaders::list(
+            headers
+                .iter()
+                .map(|h| HeaderName::try_from(h.as_str()).map_err(|_| format!("invalid CORS header {:?}", h)))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    let methods = match methods {
+        [] => DEFAULT_METHODS.iter().map(|m| m.to_string()).collect(),
+        methods => methods.to_vec(),
+    };
+    let methods = methods
+        .iter()
+        .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes()).map_err(|_| format!("invalid CORS method {:?}", m)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_headers(allow_headers)
+            .allow_methods(methods)
+            .allow_credentials(credentials)
+            .expose_headers(ExposeHeaders::list(EXPOSED_HEADERS.map(HeaderName::from_static)))
+            .max_age(Duration::from_secs(max_age_secs.unwrap_or(600))),
+    ))
+}
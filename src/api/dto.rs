@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+use crate::api::error::AppError;
+use crate::engine::EngineError;
+
 // ---- Chat API ----
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct ChatCompletionRequest {
     pub model: String,
     pub messages: Vec<ChatCompletionMessage>,
@@ -12,6 +15,126 @@ pub struct ChatCompletionRequest {
     pub temperature: Option<f32>,
     #[serde(default)]
     pub top_p: Option<f32>,
+    // Choices to generate; only 1 is supported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    // Further sampling controls, named as in llama.cpp's server; each is off when unset
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub min_p: Option<f32>,
+    #[serde(default)]
+    pub typical_p: Option<f32>,
+    #[serde(default)]
+    pub repetition_penalty: Option<f32>,
+    // Mirostat version (0 off, 1 or 2), with target surprise `mirostat_tau` (default 5) and
+    // learning rate `mirostat_eta` (default 0.1)
+    #[serde(default)]
+    pub mirostat: Option<u8>,
+    #[serde(default)]
+    pub mirostat_tau: Option<f32>,
+    #[serde(default)]
+    pub mirostat_eta: Option<f32>,
+    // Per-request generation timeout; capped by the server-wide GENERATION_TIMEOUT_SECS
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    // End-user id supplied by the calling application; content-safety strikes are tracked per user
+    #[serde(default)]
+    pub user: Option<String>,
+    // GBNF grammar that constrains the generated text (llama.cpp models)
+    #[serde(default)]
+    pub grammar: Option<String>,
+    // Generation ends before the first of these strings; it is not part of the reply
+    #[serde(default)]
+    pub stop: Option<StopSequences>,
+    // Caller identity for fair scheduling and usage accounting; set by the handler, never by the body
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // API the request came in through ("chat_completions", "messages", "grpc", ...), labelling
+    // cache metrics; set by the caller
+    #[serde(skip)]
+    pub endpoint: Option<&'static str>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    // `false`: neither answered from nor stored in the response cache; `Cache-Control: no-store`
+    // or `no-cache` sets it too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<bool>,
+}
+
+/// Queueing class of a request: waiting `high` requests are dequeued before `normal` ones, and
+/// those before `low` (batch and background) ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(format!("unknown priority {:?}; expected high, normal or low", other)),
+        }
+    }
+}
+
+// `stop`: a single string or a list of them
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StopSequences {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            StopSequences::One(stop) => vec![stop.clone()],
+            StopSequences::Many(stops) => stops.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub chunking: StreamChunking,
+    // Emit a final chunk carrying token usage before [DONE]
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+// How streamed deltas are split: as the runtime produces them, or re-chunked so that each
+// delta ends on a whitespace boundary (no partial words for display-oriented clients)
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamChunking {
+    #[default]
+    Token,
+    Word,
 }
 
 // OpenAI-compatible Chat content: either string or array of parts
@@ -42,7 +165,7 @@ pub struct ChatCompletionMessage {
     pub content: ChatMessageContent,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ChatCompletionResponse {
     pub id: String,
     pub object: String,
@@ -50,45 +173,81 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<ChatCompletionChoice>,
     pub usage: Usage,
+    // Reported in the `X-Cache` header rather than the body
+    #[serde(skip)]
+    pub cache: CacheStatus,
+    // The model the request named, when its fallback model answered instead; reported in the
+    // `X-Fallback-From` header
+    #[serde(skip)]
+    pub fallback_from: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// Where a chat response came from: the response cache (or an identical request generating at
+/// the same time), a new generation, or a new generation the request asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    #[default]
+    Miss,
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ChatCompletionChoice {
     pub index: u32,
     pub message: ResponseMessage,
     pub finish_reason: String,
+    // The request's stop sequence that ended the generation, when one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ResponseMessage {
     pub role: String,
     pub content: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ChatCompletionChunk {
     pub id: String,
     pub object: String,
     pub created: u64,
     pub model: String,
     pub choices: Vec<ChatCompletionChunkChoice>,
+    // Only set on the trailing usage chunk requested with `stream_options.include_usage`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ChatCompletionChunkChoice {
     pub index: u32,
     pub delta: Delta,
     pub finish_reason: Option<String>,
+    // The request's stop sequence that ended the generation, when one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Delta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<String>,
@@ -96,11 +255,430 @@ pub struct Delta {
     pub content: Option<String>,
 }
 
-// ---- Embeddings API ----
+/// What a chat stream sends instead of further chunks when generation fails; no `[DONE]`
+/// follows it. The error is in OpenAI's format, with the `type` and `code` the same failure
+/// gets as a response.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatStreamError {
+    pub error: ChatStreamErrorBody,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatStreamErrorBody {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub param: Option<String>,
+    pub code: Option<String>,
+}
+
+impl ChatStreamError {
+    pub fn chunk(error: EngineError) -> String {
+        let (_, body) = AppError::from(error).into_parts();
+        serde_json::to_string(&body).unwrap()
+    }
+
+    /// The error, if `chunk` is an error rather than a completion chunk.
+    pub fn parse(chunk: &str) -> Option<EngineError> {
+        let ChatStreamErrorBody { message, kind, code, .. } = serde_json::from_str::<ChatStreamError>(chunk).ok()?.error;
+        Some(match (kind.as_str(), code.as_deref()) {
+            (_, Some("model_not_found")) => EngineError::ModelNotFound(message),
+            (_, Some("not_found")) => EngineError::NotFound(message),
+            (_, Some("overloaded")) => EngineError::Overloaded(message),
+            (_, Some("rate_limit_exceeded")) => EngineError::QuotaExceeded(message),
+            (_, Some("timeout")) => EngineError::Timeout(message),
+            ("invalid_request_error", _) => EngineError::InvalidInput(message),
+            _ => EngineError::Backend(message),
+        })
+    }
+}
+
+// ---- Anthropic Messages API ----
+#[derive(Debug, Deserialize)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(default)]
+    pub system: Option<AnthropicSystem>,
+    pub max_tokens: u32,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default)]
+    pub metadata: Option<AnthropicMetadata>,
+}
+
+// The system prompt: a string or text blocks
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AnthropicSystem {
+    Text(String),
+    Blocks(Vec<AnthropicContentBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicMetadata {
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnthropicMessage {
+    pub role: String,
+    pub content: AnthropicContent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AnthropicContent {
+    Text(String),
+    Blocks(Vec<AnthropicContentBlock>),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContentBlock {
+    Text { text: String },
+    Image { source: AnthropicImageSource },
+    // tool_use, tool_result, documents, ...: parsed so they can be rejected with a clear error
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicImageSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessagesResponse {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub role: String,
+    pub model: String,
+    pub content: Vec<AnthropicContentBlock>,
+    pub stop_reason: Option<String>,
+    pub stop_sequence: Option<String>,
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct AnthropicUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+// Server-sent events of a streamed message; the SSE event name is the `type`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageStreamEvent {
+    MessageStart { message: MessagesResponse },
+    ContentBlockStart { index: u32, content_block: AnthropicContentBlock },
+    Ping,
+    ContentBlockDelta { index: u32, delta: AnthropicTextDelta },
+    ContentBlockStop { index: u32 },
+    MessageDelta { delta: AnthropicMessageDelta, usage: AnthropicUsage },
+    MessageStop,
+    Error { error: AnthropicErrorBody },
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnthropicTextDelta {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnthropicMessageDelta {
+    pub stop_reason: Option<String>,
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnthropicErrorBody {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub message: String,
+}
+
+// Non-streamed errors: {"type": "error", "error": {...}}
+#[derive(Debug, Serialize)]
+pub struct AnthropicErrorResponse {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub error: AnthropicErrorBody,
+}
+
+// ---- WebSocket API ----
+// Client frames on /v1/ws; `id` is chosen by the client and tags every server frame for that request
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum WsClientMessage {
+    #[serde(rename = "chat")]
+    Chat { id: String, request: Box<ChatCompletionRequest> },
+    #[serde(rename = "cancel")]
+    Cancel { id: String },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum WsServerMessage {
+    // One chat completion chunk, as SSE would carry it
+    #[serde(rename = "chat.chunk")]
+    Chunk { id: String, chunk: serde_json::Value },
+    #[serde(rename = "chat.done")]
+    Done { id: String },
+    #[serde(rename = "chat.cancelled")]
+    Cancelled { id: String },
+    // `id` is absent when the frame itself could not be parsed; `error_type` and `code` are
+    // the ones the HTTP API answers the same failure with
+    #[serde(rename = "error")]
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        message: String,
+        error_type: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+    },
+}
+
+// ---- Realtime API ----
 #[derive(Debug, Deserialize)]
+pub struct RealtimeQuery {
+    pub model: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RealtimeTranscriptionConfig {
+    pub model: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RealtimeSession {
+    pub id: String,
+    pub object: String, // "realtime.session"
+    pub model: String,
+    pub modalities: Vec<String>,
+    pub instructions: String,
+    pub voice: String,
+    pub input_audio_format: String,
+    pub output_audio_format: String,
+    pub input_audio_transcription: RealtimeTranscriptionConfig,
+    // Always null: turns end on input_audio_buffer.commit, there is no server VAD
+    pub turn_detection: Option<serde_json::Value>,
+    pub temperature: Option<f32>,
+    pub max_response_output_tokens: Option<u32>,
+    // TTS model that voices audio output; an extension, since these LLMs only produce text
+    pub speech_model: String,
+}
+
+// session.update: fields left out keep their value
+#[derive(Debug, Deserialize, Default)]
+pub struct RealtimeSessionUpdate {
+    pub modalities: Option<Vec<String>>,
+    pub instructions: Option<String>,
+    pub voice: Option<String>,
+    pub input_audio_format: Option<String>,
+    pub output_audio_format: Option<String>,
+    pub input_audio_transcription: Option<RealtimeTranscriptionConfig>,
+    pub temperature: Option<f32>,
+    pub max_response_output_tokens: Option<u32>,
+    pub speech_model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RealtimeItem {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String, // only "message"
+    #[serde(default)]
+    pub status: Option<String>,
+    pub role: String,
+    #[serde(default)]
+    pub content: Vec<RealtimeContent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RealtimeContent {
+    InputText { text: String },
+    // `audio` is base64 pcm16; the server keeps only the transcript
+    InputAudio {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audio: Option<String>,
+        #[serde(default)]
+        transcript: Option<String>,
+    },
+    Text { text: String },
+    Audio {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audio: Option<String>,
+        #[serde(default)]
+        transcript: Option<String>,
+    },
+}
+
+// response.create overrides for one response
+#[derive(Debug, Deserialize, Default)]
+pub struct RealtimeResponseConfig {
+    pub modalities: Option<Vec<String>>,
+    pub instructions: Option<String>,
+    pub voice: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct RealtimeUsage {
+    pub total_tokens: u32,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RealtimeResponse {
+    pub id: String,
+    pub object: String, // "realtime.response"
+    pub status: String, // in_progress, completed, incomplete, cancelled or failed
+    pub output: Vec<RealtimeItem>,
+    pub usage: Option<RealtimeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum RealtimeClientEvent {
+    #[serde(rename = "session.update")]
+    SessionUpdate { session: RealtimeSessionUpdate },
+    #[serde(rename = "input_audio_buffer.append")]
+    InputAudioBufferAppend { audio: String },
+    #[serde(rename = "input_audio_buffer.commit")]
+    InputAudioBufferCommit,
+    #[serde(rename = "input_audio_buffer.clear")]
+    InputAudioBufferClear,
+    #[serde(rename = "conversation.item.create")]
+    ConversationItemCreate { item: RealtimeItem },
+    #[serde(rename = "conversation.item.delete")]
+    ConversationItemDelete { item_id: String },
+    #[serde(rename = "response.create")]
+    ResponseCreate {
+        #[serde(default)]
+        response: RealtimeResponseConfig,
+    },
+    #[serde(rename = "response.cancel")]
+    ResponseCancel,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RealtimeError {
+    #[serde(rename = "type")]
+    pub kind: String, // invalid_request_error, server_error, or the HTTP API's type for the same failure
+    pub code: Option<String>,
+    pub message: String,
+    // The client event that caused it, when it carried an event_id
+    pub event_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum RealtimeServerEvent {
+    #[serde(rename = "session.created")]
+    SessionCreated { session: RealtimeSession },
+    #[serde(rename = "session.updated")]
+    SessionUpdated { session: RealtimeSession },
+    #[serde(rename = "input_audio_buffer.committed")]
+    InputAudioBufferCommitted { previous_item_id: Option<String>, item_id: String },
+    #[serde(rename = "input_audio_buffer.cleared")]
+    InputAudioBufferCleared,
+    #[serde(rename = "conversation.item.created")]
+    ConversationItemCreated { previous_item_id: Option<String>, item: RealtimeItem },
+    #[serde(rename = "conversation.item.deleted")]
+    ConversationItemDeleted { item_id: String },
+    #[serde(rename = "conversation.item.input_audio_transcription.completed")]
+    InputAudioTranscriptionCompleted { item_id: String, content_index: usize, transcript: String },
+    #[serde(rename = "conversation.item.input_audio_transcription.failed")]
+    InputAudioTranscriptionFailed { item_id: String, content_index: usize, error: RealtimeError },
+    #[serde(rename = "response.created")]
+    ResponseCreated { response: RealtimeResponse },
+    #[serde(rename = "response.output_item.added")]
+    ResponseOutputItemAdded { response_id: String, output_index: usize, item: RealtimeItem },
+    #[serde(rename = "response.content_part.added")]
+    ResponseContentPartAdded { response_id: String, item_id: String, output_index: usize, content_index: usize, part: RealtimeContent },
+    #[serde(rename = "response.text.delta")]
+    ResponseTextDelta { response_id: String, item_id: String, output_index: usize, content_index: usize, delta: String },
+    #[serde(rename = "response.text.done")]
+    ResponseTextDone { response_id: String, item_id: String, output_index: usize, content_index: usize, text: String },
+    #[serde(rename = "response.audio_transcript.delta")]
+    ResponseAudioTranscriptDelta { response_id: String, item_id: String, output_index: usize, content_index: usize, delta: String },
+    #[serde(rename = "response.audio_transcript.done")]
+    ResponseAudioTranscriptDone { response_id: String, item_id: String, output_index: usize, content_index: usize, transcript: String },
+    // base64 pcm16 at 24 kHz
+    #[serde(rename = "response.audio.delta")]
+    ResponseAudioDelta { response_id: String, item_id: String, output_index: usize, content_index: usize, delta: String },
+    #[serde(rename = "response.audio.done")]
+    ResponseAudioDone { response_id: String, item_id: String, output_index: usize, content_index: usize },
+    #[serde(rename = "response.content_part.done")]
+    ResponseContentPartDone { response_id: String, item_id: String, output_index: usize, content_index: usize, part: RealtimeContent },
+    #[serde(rename = "response.output_item.done")]
+    ResponseOutputItemDone { response_id: String, output_index: usize, item: RealtimeItem },
+    #[serde(rename = "response.done")]
+    ResponseDone { response: RealtimeResponse },
+    #[serde(rename = "error")]
+    Error { error: RealtimeError },
+}
+
+// Every server event carries a unique event_id
+#[derive(Debug, Serialize)]
+pub struct RealtimeServerFrame {
+    pub event_id: String,
+    #[serde(flatten)]
+    pub event: RealtimeServerEvent,
+}
+
+// ---- Embeddings API ----
+#[derive(Debug, Deserialize, Serialize)]
 pub struct EmbeddingsRequest {
     pub model: String,
-    pub input: Vec<String>,
+    pub input: EmbeddingInput,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    // Truncate (and re-normalize) vectors to this many leading dimensions
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
+
+// OpenAI-compatible embeddings input: a string, an array of strings, token ids, or arrays of token ids;
+// multimodal models also take arrays mixing strings with text and image_url parts
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Text(String),
+    TextBatch(Vec<String>),
+    Tokens(Vec<u32>),
+    TokensBatch(Vec<Vec<u32>>),
+    Mixed(Vec<EmbeddingInputItem>),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum EmbeddingInputItem {
+    Text(String),
+    Part(ContentPart),
 }
 
 #[derive(Debug, Serialize)]
@@ -115,7 +693,22 @@ pub struct EmbeddingsResponse {
 pub struct EmbeddingObject {
     pub object: String,
     pub index: usize,
-    pub embedding: Vec<f32>,
+    pub embedding: EmbeddingVector,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingFormat {
+    #[default]
+    Float,
+    Base64, // little-endian f32 bytes, base64-encoded
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EmbeddingVector {
+    Float(Vec<f32>),
+    Base64(String),
 }
 
 #[derive(Debug, Serialize)]
@@ -124,51 +717,655 @@ pub struct EmbeddingUsage {
     pub total_tokens: u32,
 }
 
-// ---- Images Generation API ----
+// ---- Similarity API ----
+// Either texts to embed with `model`, or vectors the client already has
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SimilarityInput {
+    Texts(Vec<String>),
+    Vectors(Vec<Vec<f32>>),
+}
+
 #[derive(Debug, Deserialize)]
-pub struct ImagesGenerationRequest {
+pub struct SimilarityRequest {
     pub model: String,
-    pub prompt: String,
-    #[serde(default = "default_n")] 
-    pub n: u32,
-    #[serde(default = "default_size")] 
-    pub size: String, // e.g., "512x512"
-    #[serde(default = "default_response_format")] 
-    pub response_format: String, // "b64_json" (default)
+    pub a: SimilarityInput,
+    pub b: SimilarityInput,
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
 }
 
-fn default_n() -> u32 { 1 }
-fn default_size() -> String { "512x512".to_string() }
-fn default_response_format() -> String { "b64_json".to_string() }
+#[derive(Debug, Serialize)]
+pub struct SimilarityResponse {
+    pub object: String, // "similarity"
+    pub model: String,
+    // Cosine similarity of a[i] and b[j] at data[i][j]
+    pub data: Vec<Vec<f32>>,
+    pub usage: EmbeddingUsage,
+}
+
+// ---- Rerank API ----
+#[derive(Debug, Deserialize)]
+pub struct RerankRequest {
+    pub model: String,
+    pub query: String,
+    pub documents: Vec<String>,
+    // Keep only the best `top_n` results; all documents when unset
+    #[serde(default)]
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    pub return_documents: bool,
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
 
 #[derive(Debug, Serialize)]
-pub struct ImagesGenerationResponse {
-    pub created: u64,
-    pub data: Vec<ImageDataObject>,
+pub struct RerankResponse {
+    pub object: String, // "list"
+    pub model: String,
+    // Sorted by descending relevance
+    pub results: Vec<RerankResult>,
+    pub usage: EmbeddingUsage,
 }
 
 #[derive(Debug, Serialize)]
-pub struct ImageDataObject {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub b64_json: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub url: Option<String>,
+pub struct RerankResult {
+    pub index: usize,
+    pub relevance_score: f32,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub revised_prompt: Option<String>,
+    pub document: Option<RerankDocument>,
 }
 
-// ---- Admin API (Dynamic Model Management) ----
-#[derive(Debug, Deserialize)]
-pub struct LoadModelRequest {
+#[derive(Debug, Serialize)]
+pub struct RerankDocument {
+    pub text: String,
+}
+
+// ---- Audio Transcriptions API ----
+// Built by the multipart handler; audio is already decoded to 16 kHz mono PCM
+#[derive(Debug)]
+pub struct TranscriptionRequest {
     pub model: String,
-    pub kind: String, // "llm" | "embedding"
-    pub path: Option<String>,
+    pub samples: Vec<f32>,
+    pub language: Option<String>,
+    pub prompt: Option<String>,
+    pub temperature: f32,
+    pub client_id: Option<String>,
+    // Scheduling priority; the API key's, see API_KEY_PRIORITIES
+    pub priority: Option<Priority>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionFormat {
+    #[default]
+    Json,
+    Text,
+    VerboseJson,
+    Srt,
+    Vtt,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TranscriptionSegment {
+    pub id: usize,
+    pub start: f32, // seconds
+    pub end: f32,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TranscriptionResponse {
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerboseTranscriptionResponse {
+    pub task: String, // "transcribe"
+    pub language: Option<String>,
+    pub duration: f32,
+    pub text: String,
+    pub segments: Vec<TranscriptionSegment>,
 }
 
+// ---- Audio Speech API ----
 #[derive(Debug, Deserialize)]
-pub struct UnloadModelRequest {
+pub struct SpeechRequest {
     pub model: String,
-    pub kind: String, // "llm" | "embedding"
+    pub input: String,
+    pub voice: String,
+    #[serde(default)]
+    pub response_format: SpeechFormat,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
+
+fn default_speed() -> f32 { 1.0 }
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechFormat {
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    #[default]
+    Wav,
+    Pcm,
+}
+
+impl SpeechFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpeechFormat::Mp3 => "mp3",
+            SpeechFormat::Opus => "opus",
+            SpeechFormat::Aac => "aac",
+            SpeechFormat::Flac => "flac",
+            SpeechFormat::Wav => "wav",
+            SpeechFormat::Pcm => "pcm",
+        }
+    }
+}
+
+// ---- Images Generation API ----
+#[derive(Debug, Deserialize)]
+pub struct ImagesGenerationRequest {
+    pub model: String,
+    pub prompt: String,
+    #[serde(default = "default_n")] 
+    pub n: u32,
+    #[serde(default = "default_size")] 
+    pub size: String, // e.g., "512x512"
+    #[serde(default = "default_response_format")] 
+    pub response_format: String, // "b64_json" (default) | "url"
+    #[serde(default)]
+    pub negative_prompt: Option<String>,
+    #[serde(default)]
+    pub steps: Option<u32>, // 1..=150
+    #[serde(default)]
+    pub guidance_scale: Option<f32>, // 0..=30
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // Stream progress events over SSE before the final images
+    #[serde(default)]
+    pub stream: bool,
+    // With `stream`, attach a low-resolution PNG of the image in progress to each event
+    #[serde(default)]
+    pub previews: bool,
+    #[serde(skip)]
+    pub client_id: Option<String>,
+    // Scheduling priority asked for; capped at the API key's, see API_KEY_PRIORITIES
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
+
+fn default_n() -> u32 { 1 }
+fn default_size() -> String { "512x512".to_string() }
+fn default_response_format() -> String { "b64_json".to_string() }
+
+// Query of a signed artifact URL
+#[derive(Debug, Deserialize)]
+pub struct ArtifactQuery {
+    pub expires: Option<u64>,
+    pub signature: Option<String>,
+}
+
+// Built by the multipart handler of /v1/images/edits
+#[derive(Debug)]
+pub struct ImagesEditRequest {
+    pub model: String,
+    pub prompt: String,
+    pub image: Vec<u8>, // PNG
+    pub mask: Option<Vec<u8>>, // PNG with the same dimensions as `image`
+    pub n: u32,
+    pub size: String,
+    pub response_format: String,
+    pub client_id: Option<String>,
+    // Scheduling priority; the API key's, see API_KEY_PRIORITIES
+    pub priority: Option<Priority>,
+}
+
+impl ImagesEditRequest {
+    /// A request with the API defaults for everything but the required fields.
+    pub fn new(model: String, prompt: String, image: Vec<u8>) -> Self {
+        Self { model, prompt, image, mask: None, n: default_n(), size: default_size(), response_format: default_response_format(), client_id: None, priority: None }
+    }
+}
+
+// Built by the multipart handler of /v1/images/variations
+#[derive(Debug)]
+pub struct ImagesVariationRequest {
+    pub model: String,
+    pub image: Vec<u8>, // square PNG
+    pub n: u32,
+    pub size: String,
+    pub response_format: String,
+    pub client_id: Option<String>,
+    // Scheduling priority; the API key's, see API_KEY_PRIORITIES
+    pub priority: Option<Priority>,
+}
+
+impl ImagesVariationRequest {
+    pub fn new(model: String, image: Vec<u8>) -> Self {
+        Self { model, image, n: default_n(), size: default_size(), response_format: default_response_format(), client_id: None, priority: None }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImagesGenerationResponse {
+    pub created: u64,
+    pub data: Vec<ImageDataObject>,
+}
+
+// SSE events of a streamed image generation; `[DONE]` follows the last one
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ImageStreamEvent {
+    #[serde(rename = "image_generation.progress")]
+    Progress {
+        image_index: u32,
+        step: u32,
+        total_steps: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        preview_b64_json: Option<String>,
+    },
+    #[serde(rename = "image_generation.completed")]
+    Completed { created: u64, data: Vec<ImageDataObject> },
+    #[serde(rename = "error")]
+    Error { message: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageDataObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revised_prompt: Option<String>,
+}
+
+// ---- Files API ----
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileObject {
+    pub id: String,
+    pub object: String, // "file"
+    pub bytes: u64,
+    pub created_at: u64,
+    pub filename: String,
+    pub purpose: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileListQuery {
+    pub purpose: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileListResponse {
+    pub object: String, // "list"
+    pub data: Vec<FileObject>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDeletedResponse {
+    pub id: String,
+    pub object: String, // "file"
+    pub deleted: bool,
+}
+
+// ---- Batch API ----
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchRequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Batch {
+    pub id: String,
+    pub object: String, // "batch"
+    pub endpoint: String,
+    pub input_file_id: String,
+    // Set once the job has finished or been cancelled; a file with purpose "batch_output"
+    pub output_file_id: Option<String>,
+    // queued, in_progress, completed, failed, cancelling or cancelled
+    pub status: String,
+    pub created_at: u64,
+    pub in_progress_at: Option<u64>,
+    pub completed_at: Option<u64>,
+    pub failed_at: Option<u64>,
+    pub cancelled_at: Option<u64>,
+    pub request_counts: BatchRequestCounts,
+    // Why the job failed as a whole; failures of single requests are in the output
+    pub error: Option<String>,
+}
+
+// JSON form of POST /v1/batches, for an input file uploaded through /v1/files
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateBatchRequest {
+    pub input_file_id: String,
+    pub endpoint: String,
+    // Accepted for compatibility; jobs run as soon as capacity allows
+    #[serde(default)]
+    pub completion_window: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchListResponse {
+    pub object: String, // "list"
+    pub data: Vec<Batch>,
+}
+
+// One line of the input JSONL
+#[derive(Debug, Deserialize)]
+pub struct BatchRequestLine {
+    pub custom_id: String,
+    pub method: String,
+    pub url: String,
+    pub body: serde_json::Value,
+}
+
+// One line of the output JSONL, in input order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResultLine {
+    pub id: String,
+    pub custom_id: String,
+    pub response: BatchResultResponse,
+}
+
+// What the endpoint would have answered over HTTP
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResultResponse {
+    pub status_code: u16,
+    pub body: serde_json::Value,
+}
+
+// ---- Admin API (Dynamic Model Management) ----
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LoadModelRequest {
+    pub model: String,
+    pub kind: String, // "llm" | "embedding"
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub options: ModelOptions,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct LoadModelResponse {
+    pub status: String,
+    // How long the warmup took, when the model was warmed up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup_ms: Option<u64>,
+}
+
+// Per-model load parameters; flattened into load requests and state snapshots
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct ModelOptions {
+    // Built-in name ("chatml", "llama3", "mistral") or inline Jinja source; ChatML when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_template: Option<String>,
+    // llama.cpp: when the context fills up, drop the oldest tokens (keeping the prompt's
+    // leading system turn) and keep generating instead of stopping
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub context_shift: bool,
+    // llama.cpp: layers offloaded to the GPU, 0 keeping the model on the CPU; llama.cpp's
+    // default when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_gpu_layers: Option<u32>,
+    // llama.cpp: the GPU holding the model, or its small tensors when it is split
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_gpu: Option<u32>,
+    // llama.cpp: how the model is spread over several GPUs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_mode: Option<SplitMode>,
+    // llama.cpp: context window in tokens, 2048 when unset; when batched, the KV cache the
+    // sequences share, 8192 when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_ctx: Option<u32>,
+    // llama.cpp: most prompt tokens evaluated per decode call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_batch: Option<u32>,
+    // llama.cpp: RoPE base frequency and linear scale factor (below 1 stretches the trained
+    // context); the model's own values when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rope_freq_base: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rope_freq_scale: Option<f32>,
+    // LLMs: also serve `/v1/embeddings` from the model's hidden states (llama.cpp)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub embeddings: bool,
+    // ONNX embeddings: vector size, for models whose output shape does not declare it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_dim: Option<u32>,
+    // Remote: model name sent upstream; the load name when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_model: Option<String>,
+    // Remote: upstream endpoint for generations; chat when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_api: Option<RemoteApi>,
+    // Remote: environment variable holding the upstream API key, REMOTE_KEY_<anything>
+    // (REMOTE_API_KEY when unset), so keys never show up in load requests or state snapshots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_api_key_env: Option<String>,
+    // Remote: cap on each upstream request, streams included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_timeout_ms: Option<u64>,
+    // Hugging Face Hub repository (`owner/name`) to download the model from instead of
+    // loading `path`; cached under MODEL_CACHE_DIR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hf_repo: Option<String>,
+    // File within `hf_repo` (e.g. one quantization of a GGUF repository); the whole
+    // repository, as a directory, when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    // Branch, tag or commit of `hf_repo`; `main` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hf_revision: Option<String>,
+    // Unload the model after this long without requests and load it again on the next one;
+    // MODEL_IDLE_TTL_SECS when unset, 0 keeps it loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_ttl_secs: Option<u64>,
+    // LLMs and embedding models: run a one-token generation or a one-input embedding before
+    // serving, so the first request does not pay for page faults and graph compilation;
+    // MODEL_WARMUP when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<bool>,
+    // LLMs: answer identical non-streaming chat requests from the response cache; on when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<bool>,
+    // LLMs: loaded model that answers instead when generation on this one fails, times out or
+    // is overloaded before producing any text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_model: Option<String>,
+}
+
+impl ModelOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.n_ctx == Some(0) {
+            return Err("n_ctx must be positive".to_string());
+        }
+        if self.n_batch == Some(0) {
+            return Err("n_batch must be positive".to_string());
+        }
+        for (name, value) in [("rope_freq_base", self.rope_freq_base), ("rope_freq_scale", self.rope_freq_scale)] {
+            if value.is_some_and(|v| !(v.is_finite() && v > 0.0)) {
+                return Err(format!("{} must be a positive number", name));
+            }
+        }
+        if self.embedding_dim == Some(0) {
+            return Err("embedding_dim must be positive".to_string());
+        }
+        if self.upstream_timeout_ms == Some(0) {
+            return Err("upstream_timeout_ms must be positive".to_string());
+        }
+        if self.fallback_model.as_deref().is_some_and(|m| m.trim().is_empty()) {
+            return Err("fallback_model must not be empty".to_string());
+        }
+        match &self.hf_repo {
+            Some(repo) => {
+                crate::engine::hub::check_repo(repo)?;
+                if let Some(revision) = &self.hf_revision {
+                    crate::engine::hub::check_revision(revision)?;
+                }
+            }
+            None if self.filename.is_some() || self.hf_revision.is_some() => {
+                return Err("filename and hf_revision need hf_repo".to_string());
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMode {
+    // Everything on `main_gpu`
+    None,
+    // Whole layers per GPU
+    Layer,
+    // Rows of each tensor across GPUs
+    Row,
+}
+
+// Which OpenAI-compatible endpoint a remote runtime generates with
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteApi {
+    // `/v1/chat/completions`; the upstream applies its own chat template
+    #[default]
+    Chat,
+    // `/v1/completions` with the prompt rendered here
+    Completions,
+}
+
+impl std::str::FromStr for RemoteApi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "chat" => Ok(Self::Chat),
+            "completions" => Ok(Self::Completions),
+            _ => Err(format!("upstream_api must be chat or completions; got {:?}", s)),
+        }
+    }
+}
+
+impl std::str::FromStr for SplitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => Ok(Self::None),
+            "layer" => Ok(Self::Layer),
+            "row" => Ok(Self::Row),
+            _ => Err(format!("split_mode must be one of none, layer, row; got {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UnloadModelRequest {
+    pub model: String,
+    pub kind: String, // "llm" | "embedding"
+}
+
+// Outcome of `POST /admin/reload`, by model name
+#[derive(Debug, Serialize)]
+pub struct ConfigReload {
+    pub object: String, // "config.reload"
+    pub path: String,
+    pub loaded: Vec<String>,
+    pub unloaded: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InspectModelRequest {
+    // A `.safetensors` file or a directory of them
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TensorInfo {
+    pub name: String,
+    pub dtype: String,
+    pub shape: Vec<u64>,
+    // File holding the tensor, relative to the inspected directory
+    pub file: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SafetensorsFileInfo {
+    pub file: String,
+    pub tensors: usize,
+    pub bytes: u64,
+    // Free-form `__metadata__` of the header (e.g. `format: pt`)
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub metadata: std::collections::BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SafetensorsInspection {
+    pub object: String,
+    pub path: String,
+    pub files: Vec<SafetensorsFileInfo>,
+    pub parameters: u64,
+    // Parameter count per dtype, e.g. {"BF16": 6738415616}
+    pub dtypes: std::collections::BTreeMap<String, u64>,
+    pub tensors: Vec<TensorInfo>,
+}
+
+// Background download of a Hub model, loaded under `model` once complete
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateDownloadRequest {
+    pub model: String,
+    pub kind: String, // "llm" | "embedding" | ...
+    // Must name `hf_repo`; the rest are applied when the model is loaded
+    #[serde(flatten)]
+    pub options: ModelOptions,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DownloadJob {
+    pub id: String,
+    pub object: String,
+    pub model: String,
+    pub kind: String,
+    pub hf_repo: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hf_revision: Option<String>,
+    pub status: String, // "downloading" | "loading" | "completed" | "failed" | "cancelled"
+    pub downloaded_bytes: u64,
+    // Unknown until the hub reports the sizes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    pub files_completed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files_total: Option<usize>,
+    // Where the model was stored, once downloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -177,4 +1374,542 @@ pub struct ModelsListResponse {
     pub embedding: Vec<String>,
     pub multimodal: Vec<String>,
     pub image: Vec<String>,
-}
\ No newline at end of file
+    pub rerank: Vec<String>,
+    pub audio: Vec<String>,
+    pub tts: Vec<String>,
+    // Every registered model with what it serves and how it was loaded
+    pub models: Vec<ModelInfo>,
+    pub memory: MemoryUsage,
+}
+
+// Approximate memory of the loaded models against MODEL_RAM_BUDGET_MB / MODEL_VRAM_BUDGET_MB
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct MemoryUsage {
+    pub ram_used_bytes: u64,
+    pub vram_used_bytes: u64,
+    // Set aside for loads in progress
+    pub ram_reserved_bytes: u64,
+    pub vram_reserved_bytes: u64,
+    // Unlimited when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ram_budget_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vram_budget_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ModelSwap {
+    pub id: String,
+    pub object: String, // "model.swap"
+    pub model: String,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // Backend of the version being replaced
+    pub previous_backend: String,
+    pub status: String, // "loading" | "completed" | "failed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelStatus {
+    // Being loaded; a model being replaced keeps serving until then
+    Loading,
+    Ready,
+    // Unloaded after going unused; the next request loads it again
+    Idle,
+    // Failed HEALTH_CHECK_FAILURES health checks in a row; requests for it are refused
+    Unhealthy,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ModelInfo {
+    pub name: String,
+    pub kind: String,
+    pub backend: String,
+    // "chat", "vision", "embeddings", "rerank", "transcription", "speech", "image_generation"
+    pub capabilities: Vec<String>,
+    pub status: ModelStatus,
+    // Error of the last health check, while checks are failing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe_error: Option<String>,
+    pub loaded_at: u64,
+    // Approximate memory the model takes: its weights, and the KV cache of a GGUF model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ram_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vram_bytes: Option<u64>,
+    // Unix seconds of the last request routed to the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ModelOptions>,
+}
+
+// Per-request breakdown of a multi-turn tool loop
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ToolLoopStats {
+    pub iterations: u32,
+    pub tool_calls: u32,
+    pub tool_time_ms: u64,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_reason: Option<String>,
+}
+
+// ---- Admin Usage API ----
+#[derive(Debug, Serialize, Clone)]
+pub struct CostModelInfo {
+    pub prefill_weight: f64,
+    pub image_cost: f64,
+    pub audio_second_cost: f64,
+    pub default_max_tokens: u32,
+    pub avg_completion_tokens: std::collections::HashMap<String, f64>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ClientUsage {
+    pub client: String,
+    pub requests: u64,
+    // Cost charged at admission from the cost model, and cost measured after completion
+    pub estimated_cost: f64,
+    pub actual_cost: f64,
+    // Requests waiting for a worker and being processed right now
+    pub queued: usize,
+    pub running: usize,
+}
+
+// Escalation step of the content-safety strike policy
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StrikeAction {
+    None,
+    Warn,
+    Throttle,
+    Block,
+}
+
+impl StrikeAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrikeAction::None => "none",
+            StrikeAction::Warn => "warn",
+            StrikeAction::Throttle => "throttle",
+            StrikeAction::Block => "block",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SafetyStatus {
+    // API key client id, or "<client>/<user>" when requests name an end user
+    pub subject: String,
+    pub strikes: usize,
+    pub action: StrikeAction,
+    pub last_violation: u64,
+}
+
+// Metered usage of one API key (its client id) with one model
+#[derive(Debug, Serialize, Clone)]
+pub struct MeteredUsage {
+    pub key: String,
+    pub model: String,
+    pub requests: u64,
+    // Requests that failed, timed out or were cancelled
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub avg_latency_ms: f64,
+}
+
+// `/admin/usage?key=&from=&to=`: narrows `metered` to one key and to the hours overlapping
+// [from, to), in Unix seconds
+#[derive(Debug, Deserialize, Default)]
+pub struct UsageQuery {
+    pub key: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    pub cost_model: CostModelInfo,
+    pub queued: usize,
+    pub data: Vec<ClientUsage>,
+    pub safety: Vec<SafetyStatus>,
+    // Persisted per-key, per-model totals, for chargeback
+    pub metered: Vec<MeteredUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PardonRequest {
+    pub subject: String,
+}
+
+// ---- Admin Stats API ----
+#[derive(Debug, Serialize, Clone)]
+pub struct AccelerationInfo {
+    pub target_arch: String,
+    pub build_profile: String, // "debug" | "release"
+    // SIMD extensions compiled into this binary, and those the host CPU supports
+    pub compiled_simd: Vec<String>,
+    pub detected_simd: Vec<String>,
+    // Supported by the CPU but not compiled in: a silent slowdown
+    pub missing_from_build: Vec<String>,
+    // Compiled in but not supported by the CPU: illegal-instruction crashes
+    pub unsupported_by_cpu: Vec<String>,
+    pub gpu_backends: Vec<String>,
+    pub compat_mode: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub acceleration: AccelerationInfo,
+    pub queued: usize,
+}
+
+// ---- Server Info API ----
+#[derive(Debug, Serialize)]
+pub struct ServerCapabilities {
+    pub chat: bool,
+    pub streaming: bool,
+    pub embeddings: bool,
+    pub rerank: bool,
+    pub vision: bool,
+    pub image_generation: bool,
+    pub tool_calling: bool,
+    pub audio: bool,
+    pub speech: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerInfoResponse {
+    pub object: String, // "server.info"
+    pub version: String,
+    pub build_hash: String,
+    pub features: Vec<String>,
+    pub capabilities: ServerCapabilities,
+    pub endpoints: Vec<String>,
+    // Server clock, for clients that need to detect skew
+    pub time: u64,
+    pub time_ms: u128,
+}
+// ---- Admin Evals API ----
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EvalCase {
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub grader: EvalGrader,
+}
+
+// How a case output is scored: exact string, regex match, or a judge model verdict
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EvalGrader {
+    ExactMatch { expected: String },
+    Regex { pattern: String },
+    LlmJudge { model: String, rubric: String },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateEvalDatasetRequest {
+    pub name: String,
+    pub cases: Vec<EvalCase>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct EvalDatasetInfo {
+    pub id: String,
+    pub name: String,
+    pub cases: usize,
+    pub created: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateEvalRunRequest {
+    pub model: String,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    // Optional gate: the run is marked as failing the gate below this score
+    #[serde(default)]
+    pub min_score: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct EvalRun {
+    pub id: String,
+    pub dataset_id: String,
+    pub model: String,
+    pub status: String, // "running" | "completed" | "failed"
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    pub total: usize,
+    pub passed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gate_passed: Option<bool>,
+    pub results: Vec<EvalCaseResult>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct EvalCaseResult {
+    pub index: usize,
+    pub output: String,
+    pub score: f32,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvalHistoryEntry {
+    pub run_id: String,
+    pub model: String,
+    pub status: String,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    // Score change relative to the previous completed run of the same model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvalHistoryResponse {
+    pub dataset_id: String,
+    pub runs: Vec<EvalHistoryEntry>,
+}
+
+// ---- Admin Canary API ----
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CanaryPolicy {
+    #[serde(default)]
+    pub max_error_rate: Option<f32>,
+    #[serde(default)]
+    pub max_avg_latency_ms: Option<f64>,
+    #[serde(default)]
+    pub min_eval_score: Option<f32>,
+    // Canary requests observed before traffic thresholds are evaluated
+    #[serde(default = "default_canary_min_requests")]
+    pub min_requests: u64,
+    // Judge the thresholds on the canary's requests of the last this many seconds rather than
+    // on all of them since it was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_secs: Option<u64>,
+}
+
+fn default_canary_min_requests() -> u64 { 20 }
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateCanaryRequest {
+    pub model: String,
+    pub baseline: String,
+    pub canary: String,
+    pub weight: f32, // fraction of traffic routed to the canary, 0.0..=1.0
+    pub policy: CanaryPolicy,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CanaryVariantStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub total_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CanaryDeployment {
+    pub model: String,
+    pub baseline: String,
+    pub canary: String,
+    pub weight: f32,
+    pub policy: CanaryPolicy,
+    pub status: String, // "active" | "rolled_back"
+    pub created: u64,
+    pub baseline_stats: CanaryVariantStats,
+    pub canary_stats: CanaryVariantStats,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CanaryDecision {
+    pub timestamp: u64,
+    pub model: String,
+    pub action: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveCanaryRequest {
+    pub model: String,
+}
+
+// ---- Admin Fallback API ----
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct FallbackBackend {
+    pub model: String,
+    // Generations this backend takes at once before requests skip to the next; unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_inflight: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateFallbackRequest {
+    pub model: String,
+    // Tried in order; later backends serve when earlier ones fail or are full
+    pub backends: Vec<FallbackBackend>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FallbackBackendStats {
+    pub model: String,
+    pub inflight: u64,
+    // Requests this backend answered
+    pub served: u64,
+    pub errors: u64,
+    // Requests that skipped it because it was at `max_inflight` or not loaded
+    pub skipped: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FallbackChain {
+    pub model: String,
+    pub backends: Vec<FallbackBackend>,
+    pub created: u64,
+    pub stats: Vec<FallbackBackendStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveFallbackRequest {
+    pub model: String,
+}
+
+// ---- Admin Traffic Split API ----
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SplitVariant {
+    pub model: String,
+    // Share of the requests, in percent; the variants of a split add up to 100
+    pub percent: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateSplitRequest {
+    pub model: String,
+    pub variants: Vec<SplitVariant>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SplitVariantStats {
+    pub model: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrafficSplit {
+    pub model: String,
+    pub variants: Vec<SplitVariant>,
+    pub created: u64,
+    pub stats: Vec<SplitVariantStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveSplitRequest {
+    pub model: String,
+}
+
+// ---- Admin State Snapshot API ----
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ModelSpec {
+    pub model: String,
+    pub kind: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub options: ModelOptions,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuotaSpec {
+    pub requests_per_minute: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServerState {
+    pub models: Vec<ModelSpec>,
+    pub canaries: Vec<CreateCanaryRequest>,
+    #[serde(default)]
+    pub fallbacks: Vec<CreateFallbackRequest>,
+    #[serde(default)]
+    pub splits: Vec<CreateSplitRequest>,
+    // SHA-256 digests only; raw keys never leave the server
+    pub api_key_hashes: Vec<String>,
+    pub quotas: QuotaSpec,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    pub exported_at: u64,
+    pub state: ServerState,
+    // Hex HMAC-SHA256 of `state` under STATE_SIGNING_KEY; absent when no key is configured
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportStateResponse {
+    pub models_loaded: Vec<String>,
+    pub canaries_restored: Vec<String>,
+    pub fallbacks_restored: Vec<String>,
+    pub splits_restored: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+// ---- Admin API Keys API ----
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    // Defaults to ["inference"]
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApiKeyObject {
+    pub id: String,
+    pub object: String, // "api_key"
+    pub name: String,
+    pub scopes: Vec<String>,
+    // Enough of the secret to tell keys apart, e.g. "sk-ab...yz"
+    pub redacted_value: String,
+    pub created_at: u64,
+    // Client id of the caller that created the key
+    pub created_by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<u64>,
+}
+
+// The only response that carries the secret
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub info: ApiKeyObject,
+    pub key: String,
+}
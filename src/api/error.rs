@@ -1,35 +1,127 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
 
+use crate::api::auth::AuthError;
+use crate::engine::EngineError;
+
 #[derive(Debug)]
 pub enum AppError {
     InternalServerError(String),
     BadRequest(String),
+    Unauthorized(String),
+    // A known API key without the scope the route needs
+    Forbidden(String),
     NotFound(String),
+    // A request naming a model that is not loaded
+    ModelNotFound(String),
+    Timeout(String),
+    ServiceUnavailable(String),
+    TooManyRequests(String),
+    // Over the caller's rate limit, which lets it through again after `retry_after_secs`
+    RateLimited { message: String, retry_after_secs: u64 },
+    PayloadTooLarge(String),
+    // A request the validation layer turned away, with the body field at fault when there is one
+    UnprocessableEntity { message: String, param: Option<String> },
+}
+
+/// Seconds clients are told to wait before retrying an overloaded server (ENV: RETRY_AFTER_SECS, default 1).
+pub fn retry_after_secs() -> u64 {
+    crate::config::var("RETRY_AFTER_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(1)
 }
 
+/// Error body in OpenAI's format, `{"error": {"message", "type", "param", "code"}}`, so SDKs
+/// that branch on `error.type` or `error.code` (e.g. to decide whether to retry) work as they
+/// do against OpenAI.
 #[derive(Serialize)]
 pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody {
     pub message: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub param: Option<String>,
+    pub code: Option<&'static str>,
+}
+
+impl AppError {
+    /// Seconds the client should wait before retrying, sent as `Retry-After`, for errors that
+    /// go away by themselves.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::RateLimited { retry_after_secs, .. } => Some(*retry_after_secs),
+            AppError::ServiceUnavailable(_) | AppError::TooManyRequests(_) => Some(retry_after_secs()),
+            _ => None,
+        }
+    }
+
+    /// The status and body this error is answered with.
+    pub fn into_parts(self) -> (StatusCode, ErrorResponse) {
+        let (status, kind, code, message, param) = match self {
+            AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "server_error", None, msg, None),
+            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", None, msg, None),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "authentication_error", Some("invalid_api_key"), msg, None),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, "permission_error", Some("insufficient_scope"), msg, None),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, "invalid_request_error", Some("not_found"), msg, None),
+            AppError::ModelNotFound(msg) => (StatusCode::NOT_FOUND, "invalid_request_error", Some("model_not_found"), msg, None),
+            AppError::Timeout(msg) => (StatusCode::GATEWAY_TIMEOUT, "server_error", Some("timeout"), msg, None),
+            AppError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, "server_error", Some("overloaded"), msg, None),
+            AppError::TooManyRequests(msg) | AppError::RateLimited { message: msg, .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", Some("rate_limit_exceeded"), msg, None)
+            }
+            AppError::PayloadTooLarge(msg) => (StatusCode::PAYLOAD_TOO_LARGE, "invalid_request_error", Some("request_too_large"), msg, None),
+            AppError::UnprocessableEntity { message, param } => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_request_error", None, message, param),
+        };
+        (status, ErrorResponse { error: ErrorBody { message, kind, param, code } })
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-        };
+        let retry_after = self.retry_after();
+        let (status, body) = self.into_parts();
+        let body = Json(body);
 
-        let body = Json(ErrorResponse {
-            message: error_message,
-        });
+        match (status, retry_after) {
+            (StatusCode::UNAUTHORIZED, _) => (status, [(header::WWW_AUTHENTICATE, "Bearer".to_string())], body).into_response(),
+            (_, Some(secs)) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+/// Requests naming a model that is not loaded, or anything else missing, become 404, requests the queue turned away 429
+/// (their key's share is full) or 503, timeouts 504, bad input 400 and runtime failures 500.
+impl From<EngineError> for AppError {
+    fn from(e: EngineError) -> Self {
+        match e {
+            EngineError::ModelNotFound(e) => AppError::ModelNotFound(e),
+            EngineError::NotFound(e) => AppError::NotFound(e),
+            EngineError::Overloaded(e) => AppError::ServiceUnavailable(e),
+            EngineError::QuotaExceeded(e) => AppError::TooManyRequests(e),
+            EngineError::Timeout(e) => AppError::Timeout(e),
+            EngineError::InvalidInput(e) => AppError::BadRequest(e),
+            EngineError::Backend(e) => AppError::InternalServerError(e),
+            EngineError::Streaming => AppError::InternalServerError(e.to_string()),
+        }
+    }
+}
 
-        (status, body).into_response()
+/// Refused requests: 429 over the rate limit, 403 for a key without the route's scope, 401
+/// otherwise.
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthorized => AppError::Unauthorized(e.to_string()),
+            AuthError::Forbidden(_) => AppError::Forbidden(e.to_string()),
+            AuthError::RateLimited { retry_after_secs } => AppError::RateLimited { message: e.to_string(), retry_after_secs },
+        }
     }
 }
 
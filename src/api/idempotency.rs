@@ -0,0 +1,244 @@
+use axum::{http::HeaderMap, response::{IntoResponse, Response}, Json};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io::Write as _,
+    path::PathBuf,
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+
+use crate::api::{auth::client_id, error::AppError};
+use crate::engine::scheduler::ANONYMOUS_CLIENT;
+
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredResult {
+    body_hash: String,
+    response: serde_json::Value,
+    created: u64,
+}
+
+// One line of the store file
+#[derive(Serialize, Deserialize)]
+struct StoredLine {
+    key: String,
+    #[serde(flatten)]
+    result: StoredResult,
+}
+
+#[derive(Default)]
+struct Entries {
+    results: HashMap<String, StoredResult>,
+    // Keys oldest first, for expiry and eviction
+    order: VecDeque<String>,
+    // Lines appended to the file since it was last rewritten
+    appended: usize,
+}
+
+impl Entries {
+    fn insert(&mut self, key: String, result: StoredResult) {
+        if self.results.insert(key.clone(), result).is_none() {
+            self.order.push_back(key);
+        }
+    }
+
+    // Drops the oldest results until at most `max` are left none older than `ttl_secs`
+    fn prune(&mut self, max: usize, ttl_secs: u64) {
+        let now = now_secs();
+        while let Some(key) = self.order.front() {
+            let expired = self.results.get(key).is_none_or(|r| now.saturating_sub(r.created) >= ttl_secs);
+            if !expired && self.results.len() <= max {
+                break;
+            }
+            let key = self.order.pop_front().expect("front exists");
+            self.results.remove(&key);
+        }
+    }
+
+    fn lines(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for key in &self.order {
+            if let Some(result) = self.results.get(key) {
+                bytes.extend(line(key, result));
+            }
+        }
+        bytes
+    }
+}
+
+fn line(key: &str, result: &StoredResult) -> Vec<u8> {
+    let mut line = serde_json::to_vec(&StoredLine { key: key.to_string(), result: result.clone() }).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+enum Write {
+    Append(Vec<u8>),
+    Rewrite(Vec<u8>),
+}
+
+// Applies writes to the store file in order, away from request handling. Appends keep each
+// request's write small; rewrites go to a temporary file renamed over the old one, so a crash
+// leaves one or the other.
+fn write_behind(path: PathBuf, writes: std::sync::mpsc::Receiver<Write>) {
+    let tmp = path.with_extension("tmp");
+    for write in writes {
+        let result = match write {
+            Write::Append(line) => std::fs::OpenOptions::new().create(true).append(true).open(&path).and_then(|mut file| file.write_all(&line)),
+            Write::Rewrite(bytes) => std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, &path)),
+        };
+        if let Err(e) = result {
+            tracing::warn!("failed to persist idempotency store to {:?}: {}", path, e);
+        }
+    }
+}
+
+// Results of successful mutations and generations keyed by "<scope>:<Idempotency-Key>", the
+// oldest dropped past `max_entries`. When IDEMPOTENCY_STORE_PATH is set each result is also
+// appended to that file (one JSON object per line), so retries are still recognised after a
+// restart; the file is rewritten with just the live results once it has grown by `max_entries`.
+pub struct IdempotencyStore {
+    entries: Mutex<Entries>,
+    in_flight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    writes: Option<std::sync::mpsc::Sender<Write>>,
+    ttl_secs: u64,
+    max_entries: usize,
+}
+
+static STORE: Lazy<IdempotencyStore> = Lazy::new(|| {
+    let path = crate::config::var("IDEMPOTENCY_STORE_PATH").ok().map(PathBuf::from);
+    // ENV: IDEMPOTENCY_TTL_SECS (default 24h)
+    let ttl_secs = crate::config::var("IDEMPOTENCY_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(86_400);
+    // ENV: IDEMPOTENCY_MAX_ENTRIES (default 10000)
+    let max_entries = crate::config::var("IDEMPOTENCY_MAX_ENTRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10_000);
+    IdempotencyStore::new(path, ttl_secs, max_entries)
+});
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl IdempotencyStore {
+    pub fn new(path: Option<PathBuf>, ttl_secs: u64, max_entries: usize) -> Self {
+        let mut entries = Entries::default();
+        // Unreadable lines (a write cut short by a crash) are skipped
+        let mut stored: Vec<StoredLine> = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .map(|bytes| bytes.split(|&b| b == b'\n').filter_map(|line| serde_json::from_slice(line).ok()).collect())
+            .unwrap_or_default();
+        stored.sort_by_key(|line| line.result.created);
+        for line in stored {
+            entries.insert(line.key, line.result);
+        }
+        entries.prune(max_entries, ttl_secs);
+        let writes = path.map(|path| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            // Start from a file holding only what was loaded
+            let _ = tx.send(Write::Rewrite(entries.lines()));
+            std::thread::Builder::new()
+                .name("idempotency-store".to_string())
+                .spawn(move || write_behind(path, rx))
+                .expect("failed to spawn idempotency store writer");
+            tx
+        });
+        Self { entries: Mutex::new(entries), in_flight: Mutex::new(HashMap::new()), writes, ttl_secs, max_entries }
+    }
+
+    async fn lookup(&self, key: &str) -> Option<StoredResult> {
+        let mut entries = self.entries.lock().await;
+        entries.prune(self.max_entries, self.ttl_secs);
+        entries.results.get(key).cloned()
+    }
+
+    async fn store(&self, key: String, result: StoredResult) {
+        let mut entries = self.entries.lock().await;
+        let appended = line(&key, &result);
+        entries.insert(key, result);
+        entries.prune(self.max_entries, self.ttl_secs);
+        let Some(writes) = &self.writes else { return };
+        // Sent under the lock, so the file sees writes in the order the map does
+        let write = if entries.appended >= self.max_entries {
+            entries.appended = 0;
+            Write::Rewrite(entries.lines())
+        } else {
+            entries.appended += 1;
+            Write::Append(appended)
+        };
+        let _ = writes.send(write);
+    }
+
+    async fn key_lock(&self, key: &str) -> Arc<Mutex<()>> {
+        let mut in_flight = self.in_flight.lock().await;
+        // Drop locks nobody else is holding or waiting on
+        in_flight.retain(|_, lock| Arc::strong_count(lock) > 1);
+        in_flight.entry(key.to_string()).or_default().clone()
+    }
+}
+
+/// Scope of `endpoint`'s keys for the caller behind `headers`. Anonymous callers cannot be told
+/// apart, so a key from one is refused rather than shared with every other anonymous caller.
+pub fn caller_scope(endpoint: &str, headers: &HeaderMap) -> Result<String, AppError> {
+    let client = client_id(headers);
+    if client == ANONYMOUS_CLIENT && headers.contains_key(IDEMPOTENCY_HEADER) {
+        return Err(AppError::BadRequest("Idempotency-Key needs an API key or client certificate to scope it to".to_string()));
+    }
+    Ok(format!("{}:{}", endpoint, client))
+}
+
+fn hash_body<T: Serialize>(body: &T) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(body).unwrap_or_default());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Runs a mutation (or a billed generation) at most once per `Idempotency-Key` within `scope`.
+///
+/// Requests without the header run normally. A retry with the same key and an identical body
+/// replays the stored response; the same key with a different body is rejected. Only successful
+/// results are stored, so failed attempts can be retried.
+pub async fn idempotent<T, F, Fut>(headers: &HeaderMap, scope: &str, body: &T, f: F) -> Result<Response, AppError>
+where
+    T: Serialize,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<serde_json::Value, AppError>>,
+{
+    let Some(key) = headers.get(IDEMPOTENCY_HEADER).and_then(|v| v.to_str().ok()) else {
+        return f().await.map(|v| Json(v).into_response());
+    };
+    let store = &*STORE;
+    let store_key = format!("{}:{}", scope, key);
+    let body_hash = hash_body(body);
+
+    // Serialize concurrent retries of the same key so the mutation only runs once
+    let lock = store.key_lock(&store_key).await;
+    let _held = lock.lock().await;
+
+    if let Some(stored) = store.lookup(&store_key).await {
+        if stored.body_hash != body_hash {
+            return Err(AppError::BadRequest(format!(
+                "Idempotency-Key {} was already used with a different request body",
+                key
+            )));
+        }
+        let mut response = Json(stored.response).into_response();
+        response.headers_mut().insert(REPLAYED_HEADER, "true".parse().unwrap());
+        return Ok(response);
+    }
+
+    let value = f().await?;
+    store.store(store_key, StoredResult { body_hash, response: value.clone(), created: now_secs() }).await;
+    Ok(Json(value).into_response())
+}
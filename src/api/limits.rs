@@ -0,0 +1,222 @@
+//! Per-endpoint-class HTTP concurrency limits and timeouts.
+//!
+//! Each class gets its own pool of slots, so a flood of cheap embedding calls cannot take the
+//! slots long-lived chat streams need. A slot is held until the response body has been fully
+//! sent (or dropped), which is what makes streams count for their whole lifetime. The timeout
+//! covers the time until response headers; streams are bounded by the generation timeout.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use futures::{future::BoxFuture, StreamExt};
+use metrics::counter;
+use once_cell::sync::Lazy;
+use std::{
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::{Layer, Service};
+
+use crate::api::error::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    Chat,
+    Embeddings,
+    Images,
+    Audio,
+    Admin,
+}
+
+impl EndpointClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndpointClass::Chat => "chat",
+            EndpointClass::Embeddings => "embeddings",
+            EndpointClass::Images => "images",
+            EndpointClass::Audio => "audio",
+            EndpointClass::Admin => "admin",
+        }
+    }
+
+    fn defaults(&self) -> EndpointLimits {
+        let (max_concurrency, timeout_secs) = match self {
+            EndpointClass::Chat => (512, 600),
+            EndpointClass::Embeddings => (256, 30),
+            EndpointClass::Images => (32, 300),
+            EndpointClass::Audio => (16, 600),
+            EndpointClass::Admin => (32, 120),
+        };
+        EndpointLimits { max_concurrency, timeout: Some(Duration::from_secs(timeout_secs)) }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointLimits {
+    pub max_concurrency: usize,
+    // Time allowed until response headers; None waits indefinitely
+    pub timeout: Option<Duration>,
+}
+
+impl EndpointLimits {
+    /// ENV: HTTP_<CLASS>_MAX_CONCURRENCY and HTTP_<CLASS>_TIMEOUT_SECS (0 disables the timeout),
+    /// where CLASS is CHAT, EMBEDDINGS, IMAGES, AUDIO or ADMIN.
+    pub fn from_env(class: EndpointClass) -> Self {
+        let defaults = class.defaults();
+        let prefix = format!("HTTP_{}", class.as_str().to_ascii_uppercase());
+        let env = |suffix: &str| crate::config::var(format!("{}_{}", prefix, suffix)).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            max_concurrency: env("MAX_CONCURRENCY").map_or(defaults.max_concurrency, |v| (v as usize).max(1)),
+            timeout: match env("TIMEOUT_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.timeout,
+            },
+        }
+    }
+}
+
+// Layers built from the settings, updated by `reload`
+static CONFIGURED: Lazy<Mutex<Vec<EndpointLimitLayer>>> = Lazy::new(Default::default);
+
+// A class's slots: the permits of a semaphore, less those owed to a limit lowered while
+// they were held
+struct Slots {
+    free: Arc<Semaphore>,
+    // Held slots to retire instead of releasing
+    owed: AtomicUsize,
+}
+
+impl Slots {
+    fn try_acquire(self: &Arc<Self>) -> Option<Slot> {
+        let permit = self.free.clone().try_acquire_owned().ok()?;
+        Some(Slot { permit: Some(permit), slots: self.clone() })
+    }
+}
+
+// A held slot; released when dropped, or retired if the limit was lowered meanwhile
+struct Slot {
+    permit: Option<OwnedSemaphorePermit>,
+    slots: Arc<Slots>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let owed = self.slots.owed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| owed.checked_sub(1));
+        if let (Some(permit), Ok(_)) = (self.permit.take(), owed) {
+            permit.forget();
+        }
+    }
+}
+
+/// Tower layer enforcing one class's limits; clones share the same slots.
+#[derive(Clone)]
+pub struct EndpointLimitLayer {
+    class: EndpointClass,
+    slots: Arc<Slots>,
+    limits: Arc<Mutex<EndpointLimits>>,
+}
+
+impl EndpointLimitLayer {
+    pub fn new(class: EndpointClass, limits: EndpointLimits) -> Self {
+        let slots = Slots { free: Arc::new(Semaphore::new(limits.max_concurrency)), owed: AtomicUsize::new(0) };
+        Self { class, slots: Arc::new(slots), limits: Arc::new(Mutex::new(limits)) }
+    }
+
+    pub fn from_env(class: EndpointClass) -> Self {
+        let layer = Self::new(class, EndpointLimits::from_env(class));
+        CONFIGURED.lock().unwrap().push(layer.clone());
+        layer
+    }
+
+    /// Changes the limits of requests from now on. Requests already holding a slot keep it;
+    /// free slots beyond a lower limit go at once and held ones as they are released.
+    pub fn set_limits(&self, limits: EndpointLimits) {
+        let mut current = self.limits.lock().unwrap();
+        let (old, new) = (current.max_concurrency, limits.max_concurrency);
+        if new > old {
+            // Held slots still owed to a lower limit are kept rather than added anew
+            let grow = new - old;
+            let owed = self.slots.owed.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| Some(owed.saturating_sub(grow))).unwrap_or_default();
+            self.slots.free.add_permits(grow - owed.min(grow));
+        } else if new < old {
+            let retired = self.slots.free.forget_permits(old - new);
+            self.slots.owed.fetch_add(old - new - retired, Ordering::SeqCst);
+        }
+        *current = limits;
+    }
+}
+
+/// Applies the current HTTP_<CLASS>_* settings to every layer built with `from_env`.
+pub fn reload() {
+    for layer in CONFIGURED.lock().unwrap().iter() {
+        layer.set_limits(EndpointLimits::from_env(layer.class));
+    }
+}
+
+impl<S> Layer<S> for EndpointLimitLayer {
+    type Service = EndpointLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EndpointLimit { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct EndpointLimit<S> {
+    inner: S,
+    layer: EndpointLimitLayer,
+}
+
+impl<S> Service<Request> for EndpointLimit<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // Take the service that was driven to readiness, leave a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let EndpointLimitLayer { class, slots, limits } = self.layer.clone();
+        let timeout = limits.lock().unwrap().timeout;
+        Box::pin(async move {
+            let Some(slot) = slots.try_acquire() else {
+                counter!("http_rejected_total", 1, "class" => class.as_str(), "reason" => "concurrency");
+                return Ok(AppError::ServiceUnavailable(format!("Too many concurrent {} requests; try again later", class.as_str())).into_response());
+            };
+            let response = match timeout {
+                Some(limit) => match tokio::time::timeout(limit, inner.call(request)).await {
+                    Ok(response) => response?,
+                    Err(_) => {
+                        counter!("http_rejected_total", 1, "class" => class.as_str(), "reason" => "timeout");
+                        return Ok(AppError::Timeout(format!("{} request timed out after {}s", class.as_str(), limit.as_secs())).into_response());
+                    }
+                },
+                None => inner.call(request).await?,
+            };
+            // The slot is released when the body stream finishes or the client goes away
+            Ok(response.map(|body| {
+                Body::from_stream(body.into_data_stream().map(move |chunk| {
+                    let _held = &slot;
+                    chunk
+                }))
+            }))
+        })
+    }
+}
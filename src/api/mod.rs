@@ -1,4 +1,13 @@
 pub mod dto;
 pub mod routes;
 pub mod error;
-pub mod auth;
\ No newline at end of file
+pub mod validation;
+pub mod auth;
+pub mod cors;
+pub mod idempotency;
+pub mod limits;
+pub mod artifacts;
+pub mod anthropic;
+pub mod ws;
+pub mod realtime;
+pub mod app;
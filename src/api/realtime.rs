@@ -0,0 +1,567 @@
+//! Realtime API (`/v1/realtime`), modeled on OpenAI's: one conversation per WebSocket. Input
+//! audio is buffered, transcribed on commit and added as a user turn; responses stream from the
+//! chat engine as text deltas and, with the audio modality, as speech synthesized sentence by
+//! sentence while the text is still generating.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::ws::{Message, WebSocket},
+    http::HeaderMap,
+};
+use base64::Engine as _;
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{
+    auth::{authorize_request, client_id, priority, Scope},
+    dto::{
+        ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent, ChatStreamError, Priority, RealtimeClientEvent, RealtimeContent, RealtimeError,
+        RealtimeItem, RealtimeResponse, RealtimeResponseConfig, RealtimeServerEvent, RealtimeServerFrame, RealtimeSession,
+        RealtimeSessionUpdate, RealtimeTranscriptionConfig, RealtimeUsage, SpeechFormat, SpeechRequest, StreamOptions,
+        TranscriptionRequest,
+    },
+    error::AppError,
+};
+use crate::engine::{CoreEngine, EngineError};
+use crate::runtime::audio::{encode_pcm16, resample, SAMPLE_RATE, SPEECH_SAMPLE_RATE};
+
+// Realtime audio is mono pcm16 at 24 kHz in both directions
+const AUDIO_RATE: u32 = SPEECH_SAMPLE_RATE;
+// Uncommitted input audio is capped at 15 minutes
+const MAX_BUFFERED_SAMPLES: usize = AUDIO_RATE as usize * 15 * 60;
+// Synthesized speech goes out in deltas of half a second
+const AUDIO_DELTA_BYTES: usize = AUDIO_RATE as usize;
+// Text without a sentence break is voiced once this much of it has accumulated
+const MAX_SPEECH_SEGMENT_BYTES: usize = 400;
+
+// ENV: REALTIME_TRANSCRIPTION_MODEL (default dummy-audio)
+fn default_transcription_model() -> String {
+    crate::config::var("REALTIME_TRANSCRIPTION_MODEL").unwrap_or_else(|_| "dummy-audio".to_string())
+}
+
+// ENV: REALTIME_SPEECH_MODEL (default dummy-tts)
+fn default_speech_model() -> String {
+    crate::config::var("REALTIME_SPEECH_MODEL").unwrap_or_else(|_| "dummy-tts".to_string())
+}
+
+fn new_id(prefix: &str) -> String {
+    format!("{}_{}", prefix, uuid::Uuid::new_v4().simple())
+}
+
+fn invalid(message: impl Into<String>) -> RealtimeError {
+    RealtimeError { kind: "invalid_request_error".to_string(), code: None, message: message.into(), event_id: None }
+}
+
+// With the type and code the HTTP API answers the same failure with
+fn failed(e: impl Into<AppError>) -> RealtimeError {
+    let (_, body) = e.into().into_parts();
+    RealtimeError { kind: body.error.kind.to_string(), code: body.error.code.map(str::to_string), message: body.error.message, event_id: None }
+}
+
+enum Outbound {
+    Event(RealtimeServerEvent),
+    // Sent instead of the response's `response.done` so the session can take the reply into the
+    // conversation before the client sees that it finished
+    ResponseDone { item: Option<RealtimeItem>, response: RealtimeResponse },
+}
+
+async fn send(sink: &mut SplitSink<WebSocket, Message>, event: RealtimeServerEvent) -> bool {
+    let frame = RealtimeServerFrame { event_id: new_id("event"), event };
+    sink.send(Message::Text(serde_json::to_string(&frame).unwrap())).await.is_ok()
+}
+
+/// Runs one realtime session until the client closes the socket. `headers` are the upgrade
+/// request's; transcriptions and responses are rate limited with them like HTTP calls.
+pub async fn serve(socket: WebSocket, engine: Arc<CoreEngine>, headers: HeaderMap, model: String) {
+    let (mut sink, mut source) = socket.split();
+    let (out_tx, mut out_rx) = mpsc::channel::<Outbound>(100);
+    let mut session = Session::new(engine, headers, model, out_tx);
+    if !send(&mut sink, RealtimeServerEvent::SessionCreated { session: session.config.clone() }).await {
+        return;
+    }
+    'connection: loop {
+        tokio::select! {
+            frame = source.next() => {
+                let text = match frame {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                for event in session.handle(&text).await {
+                    if !send(&mut sink, event).await {
+                        break 'connection;
+                    }
+                }
+            }
+            Some(outbound) = out_rx.recv() => {
+                let event = match outbound {
+                    Outbound::Event(event) => event,
+                    Outbound::ResponseDone { item, response } => {
+                        session.response = None;
+                        session.items.extend(item);
+                        RealtimeServerEvent::ResponseDone { response }
+                    }
+                };
+                if !send(&mut sink, event).await {
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(cancel) = session.response {
+        cancel.cancel();
+    }
+}
+
+struct Session {
+    engine: Arc<CoreEngine>,
+    headers: HeaderMap,
+    client: String,
+    // Realtime traffic is interactive and runs at its API key's priority
+    priority: Priority,
+    out: mpsc::Sender<Outbound>,
+    config: RealtimeSession,
+    items: Vec<RealtimeItem>,
+    // Uncommitted input audio at AUDIO_RATE
+    audio: Vec<f32>,
+    // Set while a response is generating
+    response: Option<CancellationToken>,
+}
+
+impl Session {
+    fn new(engine: Arc<CoreEngine>, headers: HeaderMap, model: String, out: mpsc::Sender<Outbound>) -> Self {
+        let config = RealtimeSession {
+            id: new_id("sess"),
+            object: "realtime.session".to_string(),
+            model,
+            modalities: vec!["text".to_string(), "audio".to_string()],
+            instructions: String::new(),
+            voice: "alloy".to_string(),
+            input_audio_format: "pcm16".to_string(),
+            output_audio_format: "pcm16".to_string(),
+            input_audio_transcription: RealtimeTranscriptionConfig { model: default_transcription_model() },
+            turn_detection: None,
+            temperature: None,
+            max_response_output_tokens: None,
+            speech_model: default_speech_model(),
+        };
+        let (client, priority) = (client_id(&headers), priority(&headers, None));
+        Self { engine, headers, client, priority, out, config, items: Vec::new(), audio: Vec::new(), response: None }
+    }
+
+    /// Events answering one client frame; failures become an `error` event naming its event_id.
+    async fn handle(&mut self, text: &str) -> Vec<RealtimeServerEvent> {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => return vec![RealtimeServerEvent::Error { error: invalid(format!("invalid JSON: {}", e)) }],
+        };
+        let event_id = value["event_id"].as_str().map(str::to_string);
+        let result = match serde_json::from_value::<RealtimeClientEvent>(value) {
+            Ok(event) => self.dispatch(event).await,
+            Err(e) => Err(invalid(format!("invalid event: {}", e))),
+        };
+        result.unwrap_or_else(|error| vec![RealtimeServerEvent::Error { error: RealtimeError { event_id, ..error } }])
+    }
+
+    async fn dispatch(&mut self, event: RealtimeClientEvent) -> Result<Vec<RealtimeServerEvent>, RealtimeError> {
+        match event {
+            RealtimeClientEvent::SessionUpdate { session } => {
+                self.update(session)?;
+                Ok(vec![RealtimeServerEvent::SessionUpdated { session: self.config.clone() }])
+            }
+            RealtimeClientEvent::InputAudioBufferAppend { audio } => {
+                let samples = decode_pcm16(&audio)?;
+                if self.audio.len() + samples.len() > MAX_BUFFERED_SAMPLES {
+                    return Err(invalid("input audio buffer is full; commit or clear it"));
+                }
+                self.audio.extend(samples);
+                Ok(Vec::new())
+            }
+            RealtimeClientEvent::InputAudioBufferCommit => {
+                if self.audio.is_empty() {
+                    return Err(invalid("input audio buffer is empty"));
+                }
+                self.authorize().await?;
+                let samples = std::mem::take(&mut self.audio);
+                let item_id = new_id("item");
+                let previous_item_id = self.last_item_id();
+                let mut item = RealtimeItem {
+                    id: Some(item_id.clone()),
+                    kind: "message".to_string(),
+                    status: Some("completed".to_string()),
+                    role: "user".to_string(),
+                    content: vec![RealtimeContent::InputAudio { audio: None, transcript: None }],
+                };
+                let mut events = vec![
+                    RealtimeServerEvent::InputAudioBufferCommitted { previous_item_id: previous_item_id.clone(), item_id: item_id.clone() },
+                    RealtimeServerEvent::ConversationItemCreated { previous_item_id, item: item.clone() },
+                ];
+                // The item stays in the conversation without a transcript if transcription fails
+                match self.transcribe(samples).await {
+                    Ok(transcript) => {
+                        item.content = vec![RealtimeContent::InputAudio { audio: None, transcript: Some(transcript.clone()) }];
+                        events.push(RealtimeServerEvent::InputAudioTranscriptionCompleted { item_id, content_index: 0, transcript });
+                    }
+                    Err(e) => events.push(RealtimeServerEvent::InputAudioTranscriptionFailed { item_id, content_index: 0, error: failed(e) }),
+                }
+                self.items.push(item);
+                Ok(events)
+            }
+            RealtimeClientEvent::InputAudioBufferClear => {
+                self.audio.clear();
+                Ok(vec![RealtimeServerEvent::InputAudioBufferCleared])
+            }
+            RealtimeClientEvent::ConversationItemCreate { mut item } => {
+                if item.kind != "message" {
+                    return Err(invalid("only message items are supported"));
+                }
+                if !["user", "assistant", "system"].contains(&item.role.as_str()) {
+                    return Err(invalid(format!("item role must be user, assistant or system, got {:?}", item.role)));
+                }
+                // Audio parts are transcribed now; only the transcript is kept
+                for part in &mut item.content {
+                    if let RealtimeContent::InputAudio { audio, transcript } = part
+                        && let Some(audio) = audio.take()
+                    {
+                        self.authorize().await?;
+                        let samples = decode_pcm16(&audio)?;
+                        *transcript = Some(self.transcribe(samples).await.map_err(failed)?);
+                    }
+                }
+                item.id = Some(item.id.unwrap_or_else(|| new_id("item")));
+                item.status = Some("completed".to_string());
+                let previous_item_id = self.last_item_id();
+                self.items.push(item.clone());
+                Ok(vec![RealtimeServerEvent::ConversationItemCreated { previous_item_id, item }])
+            }
+            RealtimeClientEvent::ConversationItemDelete { item_id } => {
+                let index = self
+                    .items
+                    .iter()
+                    .position(|item| item.id.as_deref() == Some(item_id.as_str()))
+                    .ok_or_else(|| invalid(format!("no item with id {}", item_id)))?;
+                self.items.remove(index);
+                Ok(vec![RealtimeServerEvent::ConversationItemDeleted { item_id }])
+            }
+            RealtimeClientEvent::ResponseCreate { response } => {
+                if self.response.is_some() {
+                    return Err(invalid("a response is already in progress"));
+                }
+                self.authorize().await?;
+                let cancel = CancellationToken::new();
+                let (request, output) = self.response_request(response)?;
+                tokio::spawn(run_response(self.engine.clone(), request, output, cancel.clone(), self.out.clone()));
+                self.response = Some(cancel);
+                Ok(Vec::new())
+            }
+            RealtimeClientEvent::ResponseCancel => {
+                // The response task reports `response.done` with status "cancelled"
+                self.response.as_ref().ok_or_else(|| invalid("no response is in progress"))?.cancel();
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn update(&mut self, update: RealtimeSessionUpdate) -> Result<(), RealtimeError> {
+        if let Some(modalities) = update.modalities {
+            validate_modalities(&modalities)?;
+            self.config.modalities = modalities;
+        }
+        for format in [&update.input_audio_format, &update.output_audio_format].into_iter().flatten() {
+            if format != "pcm16" {
+                return Err(invalid(format!("unsupported audio format {:?}; only pcm16 is supported", format)));
+            }
+        }
+        if let Some(instructions) = update.instructions {
+            self.config.instructions = instructions;
+        }
+        if let Some(voice) = update.voice {
+            self.config.voice = voice;
+        }
+        if let Some(transcription) = update.input_audio_transcription {
+            self.config.input_audio_transcription = transcription;
+        }
+        if let Some(speech_model) = update.speech_model {
+            self.config.speech_model = speech_model;
+        }
+        self.config.temperature = update.temperature.or(self.config.temperature);
+        self.config.max_response_output_tokens = update.max_response_output_tokens.or(self.config.max_response_output_tokens);
+        Ok(())
+    }
+
+    async fn authorize(&self) -> Result<(), RealtimeError> {
+        authorize_request(self.engine.keys(), &self.headers, Scope::Inference).await.map_err(failed)
+    }
+
+    fn last_item_id(&self) -> Option<String> {
+        self.items.last().and_then(|item| item.id.clone())
+    }
+
+    async fn transcribe(&self, samples: Vec<f32>) -> Result<String, EngineError> {
+        let response = self
+            .engine
+            .process_transcription_request(TranscriptionRequest {
+                model: self.config.input_audio_transcription.model.clone(),
+                samples: resample(&samples, AUDIO_RATE, SAMPLE_RATE),
+                language: None,
+                prompt: None,
+                temperature: 0.0,
+                client_id: Some(self.client.clone()),
+                priority: Some(self.priority),
+            })
+            .await?;
+        Ok(response.text)
+    }
+
+    // The conversation as a streaming chat request, plus how to present the reply
+    fn response_request(&self, overrides: RealtimeResponseConfig) -> Result<(ChatCompletionRequest, ResponseOutput), RealtimeError> {
+        let modalities = overrides.modalities.unwrap_or_else(|| self.config.modalities.clone());
+        validate_modalities(&modalities)?;
+        let instructions = overrides.instructions.unwrap_or_else(|| self.config.instructions.clone());
+        let mut messages = Vec::with_capacity(self.items.len() + 1);
+        if !instructions.is_empty() {
+            messages.push(ChatCompletionMessage { role: "system".to_string(), content: ChatMessageContent::Text(instructions) });
+        }
+        for item in &self.items {
+            let text: Vec<&str> = item
+                .content
+                .iter()
+                .filter_map(|part| match part {
+                    RealtimeContent::InputText { text } | RealtimeContent::Text { text } => Some(text.as_str()),
+                    RealtimeContent::InputAudio { transcript, .. } | RealtimeContent::Audio { transcript, .. } => transcript.as_deref(),
+                })
+                .collect();
+            if !text.is_empty() {
+                messages.push(ChatCompletionMessage { role: item.role.clone(), content: ChatMessageContent::Text(text.join("\n")) });
+            }
+        }
+        let request = ChatCompletionRequest {
+            model: self.config.model.clone(),
+            messages,
+            stream: Some(true),
+            max_tokens: overrides.max_output_tokens.or(self.config.max_response_output_tokens),
+            temperature: overrides.temperature.or(self.config.temperature),
+            stream_options: Some(StreamOptions { include_usage: true, ..Default::default() }),
+            client_id: Some(self.client.clone()),
+            priority: Some(self.priority),
+            ..Default::default()
+        };
+        let output = ResponseOutput {
+            audio: modalities.iter().any(|m| m == "audio"),
+            voice: overrides.voice.unwrap_or_else(|| self.config.voice.clone()),
+            speech_model: self.config.speech_model.clone(),
+            client: self.client.clone(),
+            priority: self.priority,
+        };
+        Ok((request, output))
+    }
+}
+
+fn validate_modalities(modalities: &[String]) -> Result<(), RealtimeError> {
+    if !modalities.iter().any(|m| m == "text") || modalities.iter().any(|m| m != "text" && m != "audio") {
+        return Err(invalid("modalities must be [\"text\"] or [\"text\", \"audio\"]"));
+    }
+    Ok(())
+}
+
+fn decode_pcm16(audio: &str) -> Result<Vec<f32>, RealtimeError> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(audio)
+        .map_err(|e| invalid(format!("audio is not valid base64: {}", e)))?;
+    if bytes.len() % 2 != 0 {
+        return Err(invalid("audio must be whole 16-bit samples"));
+    }
+    Ok(bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32).collect())
+}
+
+// Takes the next run of text worth voicing: up to a sentence break, or a long run cut at whitespace
+fn next_speech_segment(pending: &mut String) -> Option<String> {
+    let end = pending
+        .char_indices()
+        .zip(pending.chars().skip(1))
+        .find(|((_, c), next)| matches!(c, '.' | '!' | '?' | '\n') && next.is_whitespace())
+        .map(|((i, c), _)| i + c.len_utf8())
+        .or_else(|| {
+            (pending.len() > MAX_SPEECH_SEGMENT_BYTES)
+                .then(|| pending.rfind(char::is_whitespace).filter(|&i| i > 0).unwrap_or(pending.len()))
+        })?;
+    Some(pending.drain(..end).collect())
+}
+
+struct ResponseOutput {
+    audio: bool,
+    voice: String,
+    speech_model: String,
+    client: String,
+    priority: Priority,
+}
+
+// Streams one response: the output item's text (or audio transcript) deltas, speech for each
+// finished sentence, and the closing events
+async fn run_response(
+    engine: Arc<CoreEngine>,
+    request: ChatCompletionRequest,
+    output: ResponseOutput,
+    cancel: CancellationToken,
+    out: mpsc::Sender<Outbound>,
+) {
+    let response_id = new_id("resp");
+    let item_id = new_id("item");
+    let response = |status: &str, output: Vec<RealtimeItem>, usage: Option<RealtimeUsage>| RealtimeResponse {
+        id: response_id.clone(),
+        object: "realtime.response".to_string(),
+        status: status.to_string(),
+        output,
+        usage,
+    };
+    let part = |text: &str| match output.audio {
+        true => RealtimeContent::Audio { audio: None, transcript: Some(text.to_string()) },
+        false => RealtimeContent::Text { text: text.to_string() },
+    };
+    let emit = |event: RealtimeServerEvent| out.send(Outbound::Event(event));
+    let mut item = RealtimeItem {
+        id: Some(item_id.clone()),
+        kind: "message".to_string(),
+        status: Some("in_progress".to_string()),
+        role: "assistant".to_string(),
+        content: Vec::new(),
+    };
+    let _ = emit(RealtimeServerEvent::ResponseCreated { response: response("in_progress", Vec::new(), None) }).await;
+    let _ = emit(RealtimeServerEvent::ResponseOutputItemAdded { response_id: response_id.clone(), output_index: 0, item: item.clone() }).await;
+    let _ = emit(RealtimeServerEvent::ResponseContentPartAdded {
+        response_id: response_id.clone(),
+        item_id: item_id.clone(),
+        output_index: 0,
+        content_index: 0,
+        part: part(""),
+    })
+    .await;
+
+    let speak = |text: String| {
+        let engine = engine.clone();
+        let request = SpeechRequest {
+            model: output.speech_model.clone(),
+            input: text,
+            voice: output.voice.clone(),
+            response_format: SpeechFormat::Pcm,
+            speed: 1.0,
+            client_id: Some(output.client.clone()),
+            priority: Some(output.priority),
+        };
+        let (response_id, item_id, out) = (response_id.clone(), item_id.clone(), out.clone());
+        async move {
+            let speech = engine.process_speech_request(request).await?;
+            let pcm = encode_pcm16(&resample(&speech.samples, speech.sample_rate, AUDIO_RATE));
+            for chunk in pcm.chunks(AUDIO_DELTA_BYTES) {
+                let delta = base64::engine::general_purpose::STANDARD.encode(chunk);
+                let event = RealtimeServerEvent::ResponseAudioDelta {
+                    response_id: response_id.clone(),
+                    item_id: item_id.clone(),
+                    output_index: 0,
+                    content_index: 0,
+                    delta,
+                };
+                let _ = out.send(Outbound::Event(event)).await;
+            }
+            Ok::<_, EngineError>(())
+        }
+    };
+
+    let mut text = String::new();
+    let mut pending = String::new();
+    let mut usage = None;
+    let mut finish_reason = None;
+    let mut done = false;
+    let mut failure = None;
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+    match engine.process_chat_request(request, Some(tx), cancel.clone()).await {
+        Err(e) if e != EngineError::Streaming => failure = Some(failed(e)),
+        _ => {
+            'stream: while let Some(chunk) = rx.recv().await {
+                if chunk == "[DONE]" {
+                    done = true;
+                    break;
+                }
+                if let Some(e) = ChatStreamError::parse(&chunk) {
+                    failure = Some(failed(e));
+                    break;
+                }
+                let Ok(chunk) = serde_json::from_str::<Value>(&chunk) else { continue };
+                if let Some(u) = chunk.get("usage").filter(|u| !u.is_null()) {
+                    let (input, output) = (u["prompt_tokens"].as_u64().unwrap_or(0) as u32, u["completion_tokens"].as_u64().unwrap_or(0) as u32);
+                    usage = Some(RealtimeUsage { total_tokens: input + output, input_tokens: input, output_tokens: output });
+                }
+                let choice = &chunk["choices"][0];
+                if let Some(reason) = choice["finish_reason"].as_str() {
+                    finish_reason = Some(reason.to_string());
+                }
+                let Some(delta) = choice["delta"]["content"].as_str().filter(|d| !d.is_empty()) else { continue };
+                text.push_str(delta);
+                let (response_id, item_id, delta) = (response_id.clone(), item_id.clone(), delta.to_string());
+                let _ = emit(match output.audio {
+                    true => RealtimeServerEvent::ResponseAudioTranscriptDelta { response_id, item_id, output_index: 0, content_index: 0, delta: delta.clone() },
+                    false => RealtimeServerEvent::ResponseTextDelta { response_id, item_id, output_index: 0, content_index: 0, delta: delta.clone() },
+                })
+                .await;
+                if output.audio {
+                    pending.push_str(&delta);
+                    while let Some(segment) = next_speech_segment(&mut pending) {
+                        if !segment.trim().is_empty()
+                            && let Err(e) = speak(segment).await
+                        {
+                            failure = Some(failed(e));
+                            break 'stream;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // Stops generation if speech failed midway
+    drop(rx);
+    if done && failure.is_none() && !pending.trim().is_empty()
+        && let Err(e) = speak(pending).await
+    {
+        failure = Some(failed(e));
+    }
+
+    let status = match (&failure, done) {
+        (Some(_), _) => "failed",
+        (None, true) if finish_reason.as_deref() == Some("length") => "incomplete",
+        (None, true) => "completed",
+        (None, false) if cancel.is_cancelled() => "cancelled",
+        (None, false) => {
+            failure = Some(failed(AppError::InternalServerError("generation ended before completing".to_string())));
+            "failed"
+        }
+    };
+    if let Some(e) = failure {
+        let _ = emit(RealtimeServerEvent::Error { error: e }).await;
+    }
+    let (rid, iid) = (response_id.clone(), item_id.clone());
+    let _ = emit(match output.audio {
+        true => RealtimeServerEvent::ResponseAudioTranscriptDone { response_id: rid, item_id: iid, output_index: 0, content_index: 0, transcript: text.clone() },
+        false => RealtimeServerEvent::ResponseTextDone { response_id: rid, item_id: iid, output_index: 0, content_index: 0, text: text.clone() },
+    })
+    .await;
+    if output.audio {
+        let _ = emit(RealtimeServerEvent::ResponseAudioDone { response_id: response_id.clone(), item_id: item_id.clone(), output_index: 0, content_index: 0 }).await;
+    }
+    let _ = emit(RealtimeServerEvent::ResponseContentPartDone {
+        response_id: response_id.clone(),
+        item_id: item_id.clone(),
+        output_index: 0,
+        content_index: 0,
+        part: part(&text),
+    })
+    .await;
+    item.status = Some(if status == "completed" { "completed" } else { "incomplete" }.to_string());
+    item.content = vec![part(&text)];
+    let _ = emit(RealtimeServerEvent::ResponseOutputItemDone { response_id: response_id.clone(), output_index: 0, item: item.clone() }).await;
+    // Whatever was said, even if cut short, is part of the conversation
+    let kept = (!text.is_empty()).then(|| item.clone());
+    let _ = out.send(Outbound::ResponseDone { item: kept, response: response(status, vec![item], usage) }).await;
+}
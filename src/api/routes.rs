@@ -1,91 +1,843 @@
 use axum::{
-    extract::State,
-    response::{sse::Event, IntoResponse, Response, Sse},
+    extract::{ws::WebSocketUpgrade, FromRequest, Multipart, Path, Query, State},
+    http::header,
+    response::{sse::{Event, KeepAlive}, IntoResponse, Response, Sse},
     Json,
 };
 use futures::StreamExt;
 use std::{convert::Infallible, sync::Arc};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 use crate::api::{
     dto::{
-        ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionRequest,
-        EmbeddingsRequest, LoadModelRequest, UnloadModelRequest, ModelsListResponse,
-        ImagesGenerationRequest, ImagesGenerationResponse, ImageDataObject,
+        CacheStatus, ChatCompletionRequest, InspectModelRequest, MessagesRequest, RealtimeQuery, MessageStreamEvent, EmbeddingsRequest, LoadModelRequest, UnloadModelRequest,
+        ImagesGenerationRequest, ImagesGenerationResponse, ImagesEditRequest, ImagesVariationRequest, ImageDataObject, ImageStreamEvent, ArtifactQuery,
+        CreateApiKeyRequest, CreateDownloadRequest, CreateEvalDatasetRequest, CreateEvalRunRequest, CreateCanaryRequest, RemoveCanaryRequest, CreateFallbackRequest, RemoveFallbackRequest, CreateSplitRequest, RemoveSplitRequest,
+        StateSnapshot, ServerInfoResponse, ServerCapabilities, SimilarityRequest, RerankRequest, PardonRequest, StatsResponse,
+        TranscriptionFormat, TranscriptionRequest, TranscriptionResponse, SpeechFormat, SpeechRequest, BatchListResponse, UsageQuery,
+        CreateBatchRequest, FileDeletedResponse, FileListQuery, FileListResponse,
     },
     error::AppError,
+    validation::{self, ValidJson},
 };
-use crate::engine::CoreEngine; // Import the actual CoreEngine
-use crate::api::auth::authorize_request;
+use crate::engine::{validate_image_sampling, CoreEngine, EngineError}; // Import the actual CoreEngine
+use crate::engine::{batches::validate_batch_input, files::FILE_PURPOSES};
+use crate::api::auth::{authorize_request, client_id, priority, Scope};
+use crate::api::idempotency::{caller_scope, idempotent};
+use crate::api::artifacts;
+use crate::api::anthropic::{self, AnthropicError, StreamTranslator};
+use crate::api::{realtime, ws};
+use crate::runtime::{accel, audio, ImageProgress};
 use axum::http::HeaderMap;
 use base64::Engine as _; // bring encode into scope
+use sha2::Digest as _;
+
+// ENV: SSE_KEEPALIVE_SECS (default 15; 0 disables keep-alive comments)
+fn sse_keep_alive_interval() -> Option<std::time::Duration> {
+    let secs: u64 = crate::config::var("SSE_KEEPALIVE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15);
+    (secs > 0).then(|| std::time::Duration::from_secs(secs))
+}
+
+// Public API surface advertised by /v1/server/info; keep in sync with the router in main.rs
+pub const ENDPOINTS: &[&str] = &[
+    "POST /v1/chat/completions",
+    "POST /v1/messages",
+    "GET /v1/ws",
+    "GET /v1/realtime",
+    "POST /v1/embeddings",
+    "POST /v1/similarity",
+    "POST /v1/rerank",
+    "POST /v1/images/generations",
+    "POST /v1/images/edits",
+    "POST /v1/images/variations",
+    "POST /v1/audio/transcriptions",
+    "POST /v1/audio/speech",
+    "POST /v1/files",
+    "GET /v1/files",
+    "GET /v1/files/:id",
+    "GET /v1/files/:id/content",
+    "DELETE /v1/files/:id",
+    "POST /v1/batches",
+    "GET /v1/batches",
+    "GET /v1/batches/:id",
+    "POST /v1/batches/:id/cancel",
+    "GET /v1/batches/:id/output",
+    "GET /v1/server/info",
+];
+
+fn enabled_features() -> Vec<String> {
+    [
+        ("llama", cfg!(feature = "llama")),
+        ("onnx", cfg!(feature = "onnx")),
+        ("onnx_tokenizer", cfg!(feature = "onnx_tokenizer")),
+        ("llava", cfg!(feature = "llava")),
+        ("whisper", cfg!(feature = "whisper")),
+        ("stable_diffusion", cfg!(feature = "stable_diffusion")),
+        ("clip", cfg!(feature = "clip")),
+        ("grpc", cfg!(feature = "grpc")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
+
+pub async fn server_info(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let models = engine.list_models().await;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap();
+    Ok(Json(ServerInfoResponse {
+        object: "server.info".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        build_hash: env!("BUILD_HASH").to_string(),
+        features: enabled_features(),
+        capabilities: ServerCapabilities {
+            chat: !models.llm.is_empty() || !models.multimodal.is_empty(),
+            streaming: true,
+            embeddings: !models.embedding.is_empty(),
+            rerank: !models.rerank.is_empty(),
+            vision: !models.multimodal.is_empty(),
+            image_generation: !models.image.is_empty(),
+            tool_calling: false,
+            audio: !models.audio.is_empty(),
+            speech: !models.tts.is_empty(),
+        },
+        endpoints: ENDPOINTS.iter().map(|e| e.to_string()).collect(),
+        time: now.as_secs(),
+        time_ms: now.as_millis(),
+    }).into_response())
+}
+
+// `Cache-Control: no-store` or `no-cache` keeps a request away from the response cache
+fn cache_bypassed(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|directive| matches!(directive.trim(), "no-store" | "no-cache")))
+}
+
+// The response, with an `X-Cache` header saying whether the cache answered it and, when a
+// fallback model answered, `X-Fallback-From` naming the model the request asked for
+fn with_served_by(mut response: Response, served: Option<(CacheStatus, Option<String>)>) -> Response {
+    let Some((cache, fallback_from)) = served else { return response };
+    response.headers_mut().insert("x-cache", header::HeaderValue::from_static(cache.as_str()));
+    if let Some(value) = fallback_from.and_then(|model| header::HeaderValue::from_str(&model).ok()) {
+        response.headers_mut().insert("x-fallback-from", value);
+    }
+    response
+}
 
 pub async fn chat_completions(
     headers: HeaderMap,
     State(engine): State<Arc<CoreEngine>>,
-    Json(request): Json<ChatCompletionRequest>,
+    ValidJson(mut request): ValidJson<ChatCompletionRequest>,
 ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.endpoint = Some("chat_completions");
+    request.priority = Some(priority(&headers, request.priority));
+    validation::chat(&request)?;
     if request.stream.unwrap_or(false) {
         let (tx, rx) = mpsc::channel::<String>(100);
 
         // Use the actual CoreEngine's process_chat_request
         // Pass the sender to the engine for streaming
-        let _ = engine.process_chat_request(request, Some(tx)).await;
+        let cancel = CancellationToken::new();
+        if let Err(e) = engine.process_chat_request(request, Some(tx), cancel.clone()).await
+            && e != EngineError::Streaming
+        {
+            return Err(e.into());
+        }
 
-        let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(|data| {
+        // The guard lives as long as the SSE body; when the client disconnects the body is
+        // dropped and the engine is told to stop generating
+        let guard = cancel.drop_guard();
+        let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(move |data| {
+            let _ = &guard;
             Ok::<_, Infallible>(Event::default().data(data)) // Wrap in Ok
         });
 
-        Ok(Sse::new(stream).into_response())
+        // Idle streams (e.g. during prefill) get `: keep-alive` comments so proxies don't drop them
+        match sse_keep_alive_interval() {
+            Some(interval) => Ok(Sse::new(stream)
+                .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+                .into_response()),
+            None => Ok(Sse::new(stream).into_response()),
+        }
     } else {
-        // Use the actual CoreEngine's process_chat_request
-        let response = engine.process_chat_request(request, None).await?;
-        Ok(Json(response).into_response())
+        if cache_bypassed(&headers) {
+            request.cache = Some(false);
+        }
+        // Retries carrying the same Idempotency-Key get the first response rather than a new generation
+        let scope = caller_scope("chat_completions", &headers)?;
+        let body = serde_json::to_value(&request).unwrap_or_default();
+        let mut served = None;
+        let response = idempotent(&headers, &scope, &body, || async {
+            let response = engine.process_chat_request(request, None, CancellationToken::new()).await?;
+            served = Some((response.cache, response.fallback_from.clone()));
+            Ok(serde_json::to_value(response).unwrap())
+        })
+        .await?;
+        Ok(with_served_by(response, served))
+    }
+}
+
+// Chat over a WebSocket; see api::ws for the frame protocol
+pub async fn websocket(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    Ok(upgrade.on_upgrade(move |socket| ws::serve(socket, engine, headers)))
+}
+
+// Realtime session over a WebSocket; see api::realtime for the events
+pub async fn realtime(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Query(query): Query<RealtimeQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    Ok(upgrade.on_upgrade(move |socket| realtime::serve(socket, engine, headers, query.model)))
+}
+
+// Anthropic Messages API over the chat engine
+pub async fn messages(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    // Taken as a result, so a body turned away gets Anthropic's error envelope too
+    request: Result<ValidJson<MessagesRequest>, AppError>,
+) -> Result<Response, AnthropicError> {
+    let ValidJson(request) = request?;
+    authorize_request(engine.keys(), &headers, Scope::Inference).await.map_err(AppError::from)?;
+    let stream = request.stream;
+    let mut request = anthropic::to_chat_request(request).map_err(AppError::BadRequest)?;
+    request.client_id = Some(client_id(&headers));
+    request.endpoint = Some("messages");
+    request.priority = Some(priority(&headers, request.priority));
+    validation::chat(&request)?;
+    if !stream {
+        if cache_bypassed(&headers) {
+            request.cache = Some(false);
+        }
+        let scope = caller_scope("messages", &headers)?;
+        let body = serde_json::to_value(&request).unwrap_or_default();
+        let mut served = None;
+        let response = idempotent(&headers, &scope, &body, || async {
+            let response = engine.process_chat_request(request, None, CancellationToken::new()).await?;
+            served = Some((response.cache, response.fallback_from.clone()));
+            Ok(serde_json::to_value(anthropic::from_chat_response(response)).unwrap())
+        })
+        .await?;
+        return Ok(with_served_by(response, served));
+    }
+
+    let mut translator = StreamTranslator::new(request.model.clone());
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+    let cancel = CancellationToken::new();
+    if let Err(e) = engine.process_chat_request(request, Some(tx), cancel.clone()).await
+        && e != EngineError::Streaming
+    {
+        return Err(AppError::from(e).into());
+    }
+    let (event_tx, event_rx) = mpsc::channel(100);
+    tokio::spawn(async move {
+        while let Some(chunk) = rx.recv().await {
+            for event in translator.translate(&chunk) {
+                if event_tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if let Some(event) = translator.finish() {
+            let _ = event_tx.send(event).await;
+        }
+    });
+    // As for chat: dropping the body when the client disconnects cancels generation
+    let guard = cancel.drop_guard();
+    let stream = tokio_stream::wrappers::ReceiverStream::new(event_rx).map(move |event: MessageStreamEvent| {
+        let _ = &guard;
+        Ok::<_, Infallible>(Event::default().event(event.name()).json_data(&event).unwrap())
+    });
+    match sse_keep_alive_interval() {
+        Some(interval) => Ok(Sse::new(stream)
+            .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+            .into_response()),
+        None => Ok(Sse::new(stream).into_response()),
     }
 }
 
 pub async fn embeddings(
     headers: HeaderMap,
     State(engine): State<Arc<CoreEngine>>,
-    Json(request): Json<EmbeddingsRequest>,
+    ValidJson(mut request): ValidJson<EmbeddingsRequest>,
  ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
-    match engine.process_embedding_request(request).await {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    validation::embeddings(&request)?;
+    let scope = caller_scope("embeddings", &headers)?;
+    let body = serde_json::to_value(&request).unwrap_or_default();
+    idempotent(&headers, &scope, &body, || async {
+        let response = engine.process_embedding_request(request).await?;
+        Ok(serde_json::to_value(response).unwrap())
+    })
+    .await
+ }
+
+pub async fn similarity(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(mut request): Json<SimilarityRequest>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    match engine.process_similarity_request(request).await {
         Ok(resp) => Ok(Json(resp).into_response()),
-        Err(e) => Err(AppError::BadRequest(e)),
+        Err(e) => Err(e.into()),
     }
- }
+}
+
+pub async fn rerank(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(mut request): Json<RerankRequest>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    match engine.process_rerank_request(request).await {
+        Ok(resp) => Ok(Json(resp).into_response()),
+        Err(e) => Err(e.into()),
+    }
+}
 
 pub async fn images_generations(
     headers: HeaderMap,
     State(engine): State<Arc<CoreEngine>>,
-    Json(request): Json<ImagesGenerationRequest>,
+    Json(mut request): Json<ImagesGenerationRequest>,
 ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
-    match engine.process_image_request(request).await {
-        Ok(images) => {
-            let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-            let data: Vec<ImageDataObject> = images.into_iter()
-                .map(|bytes| ImageDataObject {
-                    b64_json: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
-                    url: None,
-                    revised_prompt: None,
-                })
-                .collect();
-            Ok(Json(ImagesGenerationResponse { created, data }).into_response())
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    check_image_response_format(&request.response_format)?;
+    let response_format = request.response_format.clone();
+    if request.stream {
+        // Reject bad parameters with a status code rather than an error event
+        validate_image_sampling(&request).map_err(AppError::BadRequest)?;
+        return Ok(images_stream(engine, request, response_format));
+    }
+    match engine.process_image_request(request, None).await {
+        Ok(images) => images_response(images, &response_format).await,
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Progress events while the images are sampled, then the images (or an error) and `[DONE]`
+fn images_stream(engine: Arc<CoreEngine>, request: ImagesGenerationRequest, response_format: String) -> Response {
+    let (tx, rx) = mpsc::channel::<String>(100);
+    let (progress_tx, mut progress_rx) = mpsc::channel::<ImageProgress>(100);
+    let progress_event = |progress: ImageProgress| {
+        let event = ImageStreamEvent::Progress {
+            image_index: progress.image_index,
+            step: progress.step,
+            total_steps: progress.total_steps,
+            preview_b64_json: progress.preview.map(|png| base64::engine::general_purpose::STANDARD.encode(png)),
+        };
+        serde_json::to_string(&event).unwrap()
+    };
+    tokio::spawn(async move {
+        let generation = engine.process_image_request(request, Some(progress_tx));
+        tokio::pin!(generation);
+        let result = loop {
+            tokio::select! {
+                result = &mut generation => break result,
+                Some(progress) = progress_rx.recv() => {
+                    let _ = tx.send(progress_event(progress)).await;
+                }
+            }
+        };
+        // Reports queued just before the result came back
+        while let Ok(progress) = progress_rx.try_recv() {
+            let _ = tx.send(progress_event(progress)).await;
         }
-        Err(e) => Err(AppError::BadRequest(e)),
+        let event = match result {
+            Ok(images) => match image_data(images, &response_format).await {
+                Ok(data) => ImageStreamEvent::Completed { created: unix_now(), data },
+                Err(e) => ImageStreamEvent::Error { message: e.to_string() },
+            },
+            Err(e) => ImageStreamEvent::Error { message: e.to_string() },
+        };
+        let _ = tx.send(serde_json::to_string(&event).unwrap()).await;
+        let _ = tx.send("[DONE]".to_string()).await;
+    });
+
+    let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(|data| Ok::<_, Infallible>(Event::default().data(data)));
+    // A single diffusion step can take seconds; keep proxies from timing the stream out
+    match sse_keep_alive_interval() {
+        Some(interval) => Sse::new(stream)
+            .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+            .into_response(),
+        None => Sse::new(stream).into_response(),
     }
 }
 
-pub async fn admin_models_list(
+fn unix_now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn check_image_response_format(format: &str) -> Result<(), AppError> {
+    match format {
+        "b64_json" | "url" => Ok(()),
+        other => Err(AppError::BadRequest(format!("response_format {} is not supported; use b64_json or url", other))),
+    }
+}
+
+// Inline base64, or stored as artifacts and returned as URLs
+async fn image_data(images: Vec<Vec<u8>>, response_format: &str) -> Result<Vec<ImageDataObject>, String> {
+    let mut data = Vec::with_capacity(images.len());
+    for bytes in images {
+        data.push(if response_format == "url" {
+            let url = artifacts::global().put_image(bytes).await?;
+            ImageDataObject { b64_json: None, url: Some(url), revised_prompt: None }
+        } else {
+            ImageDataObject { b64_json: Some(base64::engine::general_purpose::STANDARD.encode(bytes)), url: None, revised_prompt: None }
+        });
+    }
+    Ok(data)
+}
+
+async fn images_response(images: Vec<Vec<u8>>, response_format: &str) -> Result<Response, AppError> {
+    let data = image_data(images, response_format).await.map_err(AppError::InternalServerError)?;
+    Ok(Json(ImagesGenerationResponse { created: unix_now(), data }).into_response())
+}
+
+/// Serves an image stored for `response_format: "url"`; the signed URL is the credential.
+pub async fn images_artifact(
+    Path(name): Path<String>,
+    Query(query): Query<ArtifactQuery>,
+) -> Result<Response, AppError> {
+    match artifacts::global().get_image(&name, query.expires, query.signature.as_deref()).await {
+        Ok(Some(bytes)) => Ok(([(header::CONTENT_TYPE, "image/png")], bytes).into_response()),
+        Ok(None) => Err(AppError::NotFound(format!("Image {} not found or link expired", name))),
+        Err(e) => Err(AppError::InternalServerError(e)),
+    }
+}
+
+/// Largest accepted image upload, image and mask together (ENV: IMAGE_MAX_UPLOAD_BYTES, default 25 MiB).
+pub fn image_max_upload_bytes() -> usize {
+    crate::config::var("IMAGE_MAX_UPLOAD_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(25 * 1024 * 1024)
+}
+
+// Multipart body shared by /v1/images/edits and /v1/images/variations
+#[derive(Default)]
+struct ImageUpload {
+    model: Option<String>,
+    prompt: Option<String>,
+    image: Option<Vec<u8>>,
+    mask: Option<Vec<u8>>,
+    // Files uploaded through /v1/files, in place of `image` and `mask`
+    image_file_id: Option<String>,
+    mask_file_id: Option<String>,
+    n: Option<u32>,
+    size: Option<String>,
+    response_format: Option<String>,
+}
+
+impl ImageUpload {
+    async fn read(mut multipart: Multipart) -> Result<Self, AppError> {
+        let mut upload = ImageUpload::default();
+        while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+            let name = field.name().unwrap_or_default().to_string();
+            match name.as_str() {
+                // Newer clients send `image[]`; only the first image is used
+                "image" | "image[]" => {
+                    let bytes = field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+                    upload.image.get_or_insert(bytes.to_vec());
+                }
+                "mask" => upload.mask = Some(field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?.to_vec()),
+                _ => {
+                    let value = field.text().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+                    match name.as_str() {
+                        "model" => upload.model = Some(value),
+                        "prompt" => upload.prompt = Some(value).filter(|p| !p.trim().is_empty()),
+                        "n" => upload.n = Some(value.parse().map_err(|_| AppError::BadRequest("n must be an integer".to_string()))?),
+                        "size" => upload.size = Some(value),
+                        "response_format" => upload.response_format = Some(value),
+                        "image_file_id" => upload.image_file_id = Some(value),
+                        "mask_file_id" => upload.mask_file_id = Some(value),
+                        _ => {} // unknown fields are ignored, as OpenAI does
+                    }
+                }
+            }
+        }
+        if let Some(format) = &upload.response_format {
+            check_image_response_format(format)?;
+        }
+        Ok(upload)
+    }
+
+    // Replaces missing image and mask bytes with the referenced files' contents
+    async fn resolve_files(mut self, engine: &CoreEngine, owner: &str) -> Result<Self, AppError> {
+        if self.image.is_none()
+            && let Some(id) = &self.image_file_id
+        {
+            self.image = Some(file_content(engine, owner, id).await?);
+        }
+        if self.mask.is_none()
+            && let Some(id) = &self.mask_file_id
+        {
+            self.mask = Some(file_content(engine, owner, id).await?);
+        }
+        Ok(self)
+    }
+
+    fn required<T>(value: Option<T>, name: &str) -> Result<T, AppError> {
+        value.ok_or_else(|| AppError::BadRequest(format!("{} is required", name)))
+    }
+}
+
+pub async fn images_edits(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    multipart: Multipart,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let upload = ImageUpload::read(multipart).await?.resolve_files(&engine, &client_id(&headers)).await?;
+    let mut request = ImagesEditRequest::new(
+        ImageUpload::required(upload.model, "model")?,
+        ImageUpload::required(upload.prompt, "prompt")?,
+        ImageUpload::required(upload.image, "image")?,
+    );
+    request.mask = upload.mask;
+    request.n = upload.n.unwrap_or(request.n);
+    request.size = upload.size.unwrap_or(request.size);
+    request.response_format = upload.response_format.unwrap_or(request.response_format);
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    let response_format = request.response_format.clone();
+    let images = engine.process_image_edit_request(request).await?;
+    images_response(images, &response_format).await
+}
+
+pub async fn images_variations(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    multipart: Multipart,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let upload = ImageUpload::read(multipart).await?.resolve_files(&engine, &client_id(&headers)).await?;
+    let mut request = ImagesVariationRequest::new(
+        ImageUpload::required(upload.model, "model")?,
+        ImageUpload::required(upload.image, "image")?,
+    );
+    request.n = upload.n.unwrap_or(request.n);
+    request.size = upload.size.unwrap_or(request.size);
+    request.response_format = upload.response_format.unwrap_or(request.response_format);
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    let response_format = request.response_format.clone();
+    let images = engine.process_image_variation_request(request).await?;
+    images_response(images, &response_format).await
+}
+
+/// Largest accepted audio upload (ENV: AUDIO_MAX_UPLOAD_BYTES, default 25 MiB).
+pub fn audio_max_upload_bytes() -> usize {
+    crate::config::var("AUDIO_MAX_UPLOAD_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(25 * 1024 * 1024)
+}
+
+pub async fn audio_transcriptions(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let (mut model, mut file, mut file_id, mut language, mut prompt) = (None, None, None, None, None);
+    let (mut temperature, mut requested) = (0.0, None);
+    let mut format = TranscriptionFormat::default();
+    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            file = Some(field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?);
+            continue;
+        }
+        let value = field.text().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+        match name.as_str() {
+            "model" => model = Some(value),
+            // A file uploaded through /v1/files, in place of `file`
+            "file_id" => file_id = Some(value),
+            "language" => language = Some(value).filter(|v| !v.is_empty()),
+            "prompt" => prompt = Some(value).filter(|v| !v.is_empty()),
+            "temperature" => temperature = value.parse().map_err(|_| AppError::BadRequest("temperature must be a number".to_string()))?,
+            "priority" => requested = Some(value.parse().map_err(AppError::BadRequest)?),
+            "response_format" => {
+                format = serde_json::from_value(serde_json::Value::String(value))
+                    .map_err(|_| AppError::BadRequest("response_format must be one of json, text, verbose_json, srt, vtt".to_string()))?
+            }
+            _ => {} // unknown fields are ignored, as OpenAI does
+        }
+    }
+    let model = model.ok_or_else(|| AppError::BadRequest("model is required".to_string()))?;
+    let file = match (file, file_id) {
+        (Some(file), _) => file.to_vec(),
+        (None, Some(id)) => file_content(&engine, &client_id(&headers), &id).await?,
+        (None, None) => return Err(AppError::BadRequest("file is required".to_string())),
+    };
+    let samples = audio::decode_wav(&file).map_err(AppError::BadRequest)?;
+
+    let request = TranscriptionRequest {
+        model,
+        samples,
+        language,
+        prompt,
+        temperature,
+        client_id: Some(client_id(&headers)),
+        priority: Some(priority(&headers, requested)),
+    };
+    let response = engine.process_transcription_request(request).await?;
+    Ok(match format {
+        TranscriptionFormat::Json => Json(TranscriptionResponse { text: response.text }).into_response(),
+        TranscriptionFormat::VerboseJson => Json(response).into_response(),
+        TranscriptionFormat::Text => response.text.into_response(),
+        TranscriptionFormat::Srt => audio::format_subtitles(&response.segments, false).into_response(),
+        TranscriptionFormat::Vtt => ([(header::CONTENT_TYPE, "text/vtt")], audio::format_subtitles(&response.segments, true)).into_response(),
+    })
+}
+
+pub async fn audio_speech(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(mut request): Json<SpeechRequest>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    request.client_id = Some(client_id(&headers));
+    request.priority = Some(priority(&headers, request.priority));
+    // Only uncompressed output is produced; there is no lossy audio encoder in the build
+    let format = request.response_format;
+    if !matches!(format, SpeechFormat::Wav | SpeechFormat::Pcm) {
+        return Err(AppError::BadRequest(format!("response_format {} is not supported; use wav or pcm", format.as_str())));
+    }
+    let speech = engine.process_speech_request(request).await?;
+    Ok(match format {
+        SpeechFormat::Pcm => {
+            let samples = audio::resample(&speech.samples, speech.sample_rate, audio::SPEECH_SAMPLE_RATE);
+            ([(header::CONTENT_TYPE, "audio/pcm")], audio::encode_pcm16(&samples)).into_response()
+        }
+        _ => {
+            let wav = audio::encode_wav(&speech.samples, speech.sample_rate).map_err(AppError::InternalServerError)?;
+            ([(header::CONTENT_TYPE, "audio/wav")], wav).into_response()
+        }
+    })
+}
+
+/// Largest accepted file upload (ENV: FILE_MAX_UPLOAD_BYTES, default 100 MiB).
+pub fn file_max_upload_bytes() -> usize {
+    crate::config::var("FILE_MAX_UPLOAD_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(100 * 1024 * 1024)
+}
+
+// Contents of an uploaded file referenced from another request
+async fn file_content(engine: &CoreEngine, owner: &str, id: &str) -> Result<Vec<u8>, AppError> {
+    engine
+        .files()
+        .content(owner, id)
+        .await
+        .map_err(AppError::InternalServerError)?
+        .ok_or_else(|| AppError::NotFound(format!("File {} not found", id)))
+}
+
+pub async fn files_upload(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let scope = caller_scope("files_upload", &headers)?;
+    let (mut file, mut purpose) = (None, None);
+    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            let filename = field.file_name().unwrap_or("upload").to_string();
+            file = Some((filename, field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?.to_vec()));
+            continue;
+        }
+        let value = field.text().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+        if name == "purpose" {
+            purpose = Some(value);
+        }
+    }
+    let (filename, bytes) = file.ok_or_else(|| AppError::BadRequest("file is required".to_string()))?;
+    let purpose = purpose.ok_or_else(|| AppError::BadRequest("purpose is required".to_string()))?;
+    if !FILE_PURPOSES.contains(&purpose.as_str()) {
+        return Err(AppError::BadRequest(format!("purpose must be one of {}", FILE_PURPOSES.join(", "))));
+    }
+    // Retries are matched on a digest of the file rather than its bytes
+    let body = serde_json::json!({"filename": filename, "purpose": purpose, "file_sha256": format!("{:x}", sha2::Sha256::digest(&bytes))});
+    idempotent(&headers, &scope, &body, || async {
+        let file = engine.files().create(&client_id(&headers), &filename, &purpose, bytes).await.map_err(AppError::InternalServerError)?;
+        Ok(serde_json::to_value(file).unwrap_or_default())
+    }).await
+}
+
+pub async fn files_list(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Query(query): Query<FileListQuery>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let data = engine.files().list(&client_id(&headers), query.purpose.as_deref()).await.map_err(AppError::InternalServerError)?;
+    Ok(Json(FileListResponse { object: "list".to_string(), data }).into_response())
+}
+
+pub async fn files_get(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    match engine.files().get(&client_id(&headers), &id).await.map_err(AppError::InternalServerError)? {
+        Some(file) => Ok(Json(file).into_response()),
+        None => Err(AppError::NotFound(format!("File {} not found", id))),
+    }
+}
+
+pub async fn files_content(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let bytes = file_content(&engine, &client_id(&headers), &id).await?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response())
+}
+
+pub async fn files_delete(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    if !engine.files().delete(&client_id(&headers), &id).await.map_err(AppError::InternalServerError)? {
+        return Err(AppError::NotFound(format!("File {} not found", id)));
+    }
+    Ok(Json(FileDeletedResponse { id, object: "file".to_string(), deleted: true }).into_response())
+}
+
+/// Largest accepted batch input file (ENV: BATCH_MAX_UPLOAD_BYTES, default 100 MiB).
+pub fn batch_max_upload_bytes() -> usize {
+    crate::config::var("BATCH_MAX_UPLOAD_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(100 * 1024 * 1024)
+}
+
+// Takes either JSON naming an uploaded file (`input_file_id`), or a multipart upload of the
+// JSONL itself, which is stored as a "batch" file first
+pub async fn batches_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    request: axum::extract::Request,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let owner = client_id(&headers);
+    // Keys are the caller's own, so two clients reusing one cannot see each other's batches
+    let scope = caller_scope("batches_create", &headers)?;
+    let is_multipart = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+    if !is_multipart {
+        let Json(req) = Json::<CreateBatchRequest>::from_request(request, &())
+            .await
+            .map_err(|e| AppError::BadRequest(e.body_text()))?;
+        return idempotent(&headers, &scope, &req, || async {
+            let batch = engine.create_batch(&owner, &req.endpoint, &req.input_file_id).await.map_err(AppError::BadRequest)?;
+            Ok(serde_json::to_value(batch).unwrap_or_default())
+        }).await;
+    }
+
+    let mut multipart = Multipart::from_request(request, &()).await.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let (mut file, mut endpoint) = (None, None);
+    while let Some(field) = multipart.next_field().await.map_err(|e| AppError::BadRequest(e.to_string()))? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "file" {
+            let filename = field.file_name().unwrap_or("batch_input.jsonl").to_string();
+            file = Some((filename, field.bytes().await.map_err(|e| AppError::BadRequest(e.to_string()))?.to_vec()));
+            continue;
+        }
+        let value = field.text().await.map_err(|e| AppError::BadRequest(e.to_string()))?;
+        if name == "endpoint" {
+            endpoint = Some(value);
+        } // completion_window and unknown fields are ignored
+    }
+    let (filename, file) = file.ok_or_else(|| AppError::BadRequest("file is required".to_string()))?;
+    let endpoint = endpoint.ok_or_else(|| AppError::BadRequest("endpoint is required".to_string()))?;
+    // Retries are matched on a digest of the file rather than its bytes
+    let body = serde_json::json!({"endpoint": endpoint, "file_sha256": format!("{:x}", sha2::Sha256::digest(&file))});
+    idempotent(&headers, &scope, &body, || async {
+        // Reject a bad file before storing it
+        validate_batch_input(&file, &endpoint).map_err(AppError::BadRequest)?;
+        let input = engine.files().create(&owner, &filename, "batch", file).await.map_err(AppError::InternalServerError)?;
+        let batch = engine.create_batch(&owner, &endpoint, &input.id).await.map_err(AppError::BadRequest)?;
+        Ok(serde_json::to_value(batch).unwrap_or_default())
+    }).await
+}
+
+pub async fn batches_list(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    let data = engine.batches().list(&client_id(&headers)).await;
+    Ok(Json(BatchListResponse { object: "list".to_string(), data }).into_response())
+}
+
+pub async fn batches_get(
     headers: HeaderMap,
     State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
-    let (llm, embedding, multimodal, image) = engine.list_models().await;
-    Ok(Json(ModelsListResponse { llm, embedding, multimodal, image }).into_response())
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    match engine.batches().get(&client_id(&headers), &id).await {
+        Some(batch) => Ok(Json(batch).into_response()),
+        None => Err(AppError::NotFound(format!("Batch {} not found", id))),
+    }
+}
+
+pub async fn batches_cancel(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    match engine.batches().cancel(&client_id(&headers), &id).await.map_err(AppError::BadRequest)? {
+        Some(batch) => Ok(Json(batch).into_response()),
+        None => Err(AppError::NotFound(format!("Batch {} not found", id))),
+    }
+}
+
+pub async fn batches_output(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    authorize_request(engine.keys(), &headers, Scope::Inference).await?;
+    match engine.batch_output(&client_id(&headers), &id).await.map_err(AppError::BadRequest)? {
+        Some(output) => Ok(([(header::CONTENT_TYPE, "application/jsonl")], output).into_response()),
+        None => Err(AppError::NotFound(format!("Batch {} not found", id))),
+    }
+}
+
+pub async fn admin_models_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    Ok(Json(engine.list_models().await).into_response())
 }
 
 pub async fn admin_models_load(
@@ -93,10 +845,11 @@ pub async fn admin_models_load(
     State(engine): State<Arc<CoreEngine>>,
     Json(req): Json<LoadModelRequest>,
 ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
-    engine.load_model(&req.kind, &req.model, req.path.as_deref()).await
-        .map_err(AppError::BadRequest)?;
-    Ok(Json(serde_json::json!({"status":"ok"})).into_response())
+    idempotent(&headers, "admin_models_load", &req, || async {
+        let loaded = engine.load_model(&req.kind, &req.model, req.path.as_deref(), &req.options).await
+            .map_err(AppError::BadRequest)?;
+        Ok(serde_json::to_value(loaded).unwrap_or_default())
+    }).await
 }
 
 pub async fn admin_models_unload(
@@ -104,8 +857,327 @@ pub async fn admin_models_unload(
     State(engine): State<Arc<CoreEngine>>,
     Json(req): Json<UnloadModelRequest>,
 ) -> Result<Response, AppError> {
-    authorize_request(&headers).map_err(AppError::BadRequest)?;
-    engine.unload_model(&req.kind, &req.model).await
+    idempotent(&headers, "admin_models_unload", &req, || async {
+        engine.unload_model(&req.kind, &req.model).await?;
+        Ok(serde_json::json!({"status":"ok"}))
+    }).await
+}
+
+pub async fn admin_models_swap(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<LoadModelRequest>,
+) -> Result<Response, AppError> {
+    idempotent(&headers, "admin_models_swap", &req, || async {
+        let swap = engine.start_swap(req.clone()).await?;
+        Ok(serde_json::to_value(swap).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_models_swaps_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let swaps = engine.swaps().list().await;
+    Ok(Json(serde_json::json!({"object": "list", "data": swaps})).into_response())
+}
+
+pub async fn admin_models_swaps_get(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let swap = engine.swaps().get(&id).await
+        .ok_or_else(|| AppError::NotFound(format!("Swap {} not found", id)))?;
+    Ok(Json(swap).into_response())
+}
+
+pub async fn admin_models_inspect(
+    Json(req): Json<InspectModelRequest>,
+) -> Result<Response, AppError> {
+    // Reads only the headers, but of every shard of a possibly large checkpoint
+    let inspection = tokio::task::spawn_blocking(move || crate::runtime::safetensors::inspect(&req.path))
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?
         .map_err(AppError::BadRequest)?;
+    Ok(Json(inspection).into_response())
+}
+
+pub async fn admin_downloads_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateDownloadRequest>,
+) -> Result<Response, AppError> {
+    idempotent(&headers, "admin_downloads_create", &req, || async {
+        let job = engine.start_download(req.clone()).await.map_err(AppError::BadRequest)?;
+        Ok(serde_json::to_value(job).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_downloads_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let downloads = engine.downloads().list().await;
+    Ok(Json(serde_json::json!({"object": "list", "data": downloads})).into_response())
+}
+
+pub async fn admin_downloads_get(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let job = engine.downloads().get(&id).await
+        .ok_or_else(|| AppError::NotFound(format!("Download {} not found", id)))?;
+    Ok(Json(job).into_response())
+}
+
+pub async fn admin_downloads_cancel(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    match engine.downloads().cancel(&id).await.map_err(AppError::BadRequest)? {
+        Some(job) => Ok(Json(job).into_response()),
+        None => Err(AppError::NotFound(format!("Download {} not found", id))),
+    }
+}
+
+// Not idempotent: a replayed response would have to keep the secret around
+pub async fn admin_keys_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateApiKeyRequest>,
+) -> Result<Response, AppError> {
+    let created = engine.keys().create(&client_id(&headers), req).await.map_err(AppError::BadRequest)?;
+    Ok(Json(created).into_response())
+}
+
+pub async fn admin_keys_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let keys = engine.keys().list().await.map_err(AppError::InternalServerError)?;
+    Ok(Json(serde_json::json!({"object": "list", "data": keys})).into_response())
+}
+
+pub async fn admin_keys_get(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let key = engine.keys().get(&id).await.map_err(AppError::InternalServerError)?
+        .ok_or_else(|| AppError::NotFound(format!("API key {} not found", id)))?;
+    Ok(Json(key).into_response())
+}
+
+pub async fn admin_keys_revoke(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let key = engine.keys().revoke(&id).await.map_err(AppError::InternalServerError)?
+        .ok_or_else(|| AppError::NotFound(format!("API key {} not found", id)))?;
+    Ok(Json(key).into_response())
+}
+
+pub async fn admin_evals_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateEvalDatasetRequest>,
+) -> Result<Response, AppError> {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    idempotent(&headers, "admin_evals_create", &body, || async {
+        let info = engine.evals().create_dataset(req).await.map_err(AppError::BadRequest)?;
+        Ok(serde_json::to_value(info).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_evals_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let datasets = engine.evals().list_datasets().await;
+    Ok(Json(serde_json::json!({"object": "list", "data": datasets})).into_response())
+}
+
+pub async fn admin_evals_run(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Path(dataset_id): Path<String>,
+    Json(req): Json<CreateEvalRunRequest>,
+) -> Result<Response, AppError> {
+    let body = serde_json::json!({"dataset_id": dataset_id, "run": req});
+    idempotent(&headers, "admin_evals_run", &body, || async {
+        let run = engine.start_eval_run(&dataset_id, req).await?;
+        Ok(serde_json::to_value(run).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_evals_history(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(dataset_id): Path<String>,
+) -> Result<Response, AppError> {
+    let history = engine.evals().history(&dataset_id).await.map_err(AppError::NotFound)?;
+    Ok(Json(history).into_response())
+}
+
+pub async fn admin_evals_run_get(
+    State(engine): State<Arc<CoreEngine>>,
+    Path(run_id): Path<String>,
+) -> Result<Response, AppError> {
+    let run = engine.evals().get_run(&run_id).await
+        .ok_or_else(|| AppError::NotFound(format!("Eval run {} not found", run_id)))?;
+    Ok(Json(run).into_response())
+}
+
+pub async fn admin_canaries_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateCanaryRequest>,
+) -> Result<Response, AppError> {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    idempotent(&headers, "admin_canaries_create", &body, || async {
+        let deployment = engine.create_canary(req).await?;
+        Ok(serde_json::to_value(deployment).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_canaries_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let deployments = engine.canaries().list().await;
+    Ok(Json(serde_json::json!({"object": "list", "data": deployments})).into_response())
+}
+
+pub async fn admin_canaries_remove(
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<RemoveCanaryRequest>,
+) -> Result<Response, AppError> {
+    engine.canaries().remove(&req.model).await.map_err(AppError::NotFound)?;
     Ok(Json(serde_json::json!({"status":"ok"})).into_response())
-}
\ No newline at end of file
+}
+
+pub async fn admin_canaries_audit(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let decisions = engine.canaries().audit_log().await;
+    Ok(Json(serde_json::json!({"object": "list", "data": decisions})).into_response())
+}
+
+pub async fn admin_fallbacks_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateFallbackRequest>,
+) -> Result<Response, AppError> {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    idempotent(&headers, "admin_fallbacks_create", &body, || async {
+        let chain = engine.create_fallback(req).await?;
+        Ok(serde_json::to_value(chain).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_fallbacks_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let chains = engine.fallbacks().list();
+    Ok(Json(serde_json::json!({"object": "list", "data": chains})).into_response())
+}
+
+pub async fn admin_fallbacks_remove(
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<RemoveFallbackRequest>,
+) -> Result<Response, AppError> {
+    engine.remove_fallback(&req.model).await.map_err(AppError::NotFound)?;
+    Ok(Json(serde_json::json!({"status":"ok"})).into_response())
+}
+
+pub async fn admin_splits_create(
+    headers: HeaderMap,
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<CreateSplitRequest>,
+) -> Result<Response, AppError> {
+    let body = serde_json::to_value(&req).unwrap_or_default();
+    idempotent(&headers, "admin_splits_create", &body, || async {
+        let split = engine.create_split(req).await?;
+        Ok(serde_json::to_value(split).unwrap_or_default())
+    }).await
+}
+
+pub async fn admin_splits_list(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let splits = engine.splits().list();
+    Ok(Json(serde_json::json!({"object": "list", "data": splits})).into_response())
+}
+
+pub async fn admin_splits_remove(
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<RemoveSplitRequest>,
+) -> Result<Response, AppError> {
+    engine.splits().remove(&req.model).map_err(AppError::NotFound)?;
+    Ok(Json(serde_json::json!({"status":"ok"})).into_response())
+}
+
+pub async fn admin_state_export(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let snapshot = engine.export_state().await?;
+    Ok(Json(snapshot).into_response())
+}
+
+pub async fn admin_state_import(
+    State(engine): State<Arc<CoreEngine>>,
+    Json(snapshot): Json<StateSnapshot>,
+) -> Result<Response, AppError> {
+    let result = engine.import_state(snapshot).await.map_err(AppError::BadRequest)?;
+    Ok(Json(result).into_response())
+}
+
+pub async fn admin_reload(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let report = engine.reload_config().await.map_err(AppError::BadRequest)?;
+    Ok(Json(report).into_response())
+}
+
+pub async fn admin_stats(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    Ok(Json(StatsResponse { acceleration: accel::report(), queued: engine.queue_depth() }).into_response())
+}
+
+pub async fn admin_usage(
+    State(engine): State<Arc<CoreEngine>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Response, AppError> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from >= to
+    {
+        return Err(AppError::BadRequest("from must be before to".to_string()));
+    }
+    let report = engine.usage_report_for(&query).await.map_err(AppError::InternalServerError)?;
+    Ok(Json(report).into_response())
+}
+
+pub async fn admin_safety_pardon(
+    State(engine): State<Arc<CoreEngine>>,
+    Json(req): Json<PardonRequest>,
+) -> Result<Response, AppError> {
+    let pardoned = engine.safety().pardon(&req.subject);
+    Ok(Json(serde_json::json!({"subject": req.subject, "pardoned": pardoned})).into_response())
+}
+
+pub async fn admin_events(
+    State(engine): State<Arc<CoreEngine>>,
+) -> Result<Response, AppError> {
+    let receiver = engine.events().subscribe();
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(envelope) => Event::default().json_data(&envelope).unwrap_or_default(),
+            // Subscriber fell behind the event buffer; tell it how much it missed and carry on
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                Event::default().comment(format!("lagged {}", skipped))
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+        };
+        Some((Ok::<_, Infallible>(event), receiver))
+    });
+    match sse_keep_alive_interval() {
+        Some(interval) => Ok(Sse::new(stream)
+            .keep_alive(KeepAlive::new().interval(interval).text("keep-alive"))
+            .into_response()),
+        None => Ok(Sse::new(stream).into_response()),
+    }
+}
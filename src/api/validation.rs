@@ -0,0 +1,159 @@
+//! Checks on request bodies before they reach the engine: a cap on body size, well-formed JSON
+//! (optionally without fields the API does not define) and sane values, each failure a 422
+//! naming the field at fault.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    Json,
+};
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::Value;
+
+use crate::api::{dto::{ChatCompletionRequest, EmbeddingInput, EmbeddingsRequest}, error::AppError};
+use crate::engine::validate_chat_request;
+
+/// Roles a chat message may have.
+pub const CHAT_ROLES: [&str; 5] = ["system", "developer", "user", "assistant", "tool"];
+
+/// Largest accepted request body outside the upload endpoints (ENV: MAX_REQUEST_BODY_BYTES,
+/// default 4 MiB).
+pub fn max_body_bytes() -> usize {
+    crate::config::var("MAX_REQUEST_BODY_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(4 * 1024 * 1024)
+}
+
+/// Largest `max_tokens` a request may ask for (ENV: MAX_TOKENS_LIMIT, default 32768); the
+/// model's context still bounds what it generates.
+pub fn max_tokens_limit() -> u32 {
+    crate::config::var("MAX_TOKENS_LIMIT").ok().and_then(|v| v.parse().ok()).unwrap_or(32768)
+}
+
+/// Whether bodies with fields the API does not define are turned away rather than the fields
+/// ignored (ENV: REJECT_UNKNOWN_FIELDS, `1`/`true`).
+pub fn reject_unknown_fields() -> bool {
+    crate::config::var("REJECT_UNKNOWN_FIELDS").is_ok_and(|v| v == "1" || v == "true")
+}
+
+pub fn invalid(param: &str, message: impl Into<String>) -> AppError {
+    AppError::UnprocessableEntity { message: message.into(), param: Some(param.to_string()) }
+}
+
+/// A JSON body whose rejections are structured errors: 413 over the size cap, 422 for malformed
+/// JSON, wrong types and, with REJECT_UNKNOWN_FIELDS, fields `T` does not have.
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, AppError> {
+        if !reject_unknown_fields() {
+            let Json(value) = Json::<T>::from_request(req, state).await.map_err(rejection)?;
+            return Ok(ValidJson(value));
+        }
+        let Json(value) = Json::<Value>::from_request(req, state).await.map_err(rejection)?;
+        if let Value::Object(fields) = &value {
+            let known = field_names::<T>();
+            if let Some(unknown) = fields.keys().find(|k| !known.contains(&k.as_str())) {
+                return Err(invalid(unknown, format!("unknown field {:?}", unknown)));
+            }
+        }
+        // Through the bytes again, so type errors name the path to the field as they do above
+        let bytes = Bytes::from(serde_json::to_vec(&value).map_err(|e| AppError::InternalServerError(e.to_string()))?);
+        let Json(value) = Json::<T>::from_bytes(&bytes).map_err(rejection)?;
+        Ok(ValidJson(value))
+    }
+}
+
+fn rejection(e: JsonRejection) -> AppError {
+    match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(format!("request body is larger than {} bytes", max_body_bytes())),
+        _ => AppError::UnprocessableEntity { message: e.body_text(), param: None },
+    }
+}
+
+/// Checks a chat request's messages and sampling values, then its grammar and the other
+/// controls `validate_chat_request` covers.
+pub fn chat(request: &ChatCompletionRequest) -> Result<(), AppError> {
+    if request.messages.is_empty() {
+        return Err(invalid("messages", "messages must not be empty"));
+    }
+    for (i, message) in request.messages.iter().enumerate() {
+        if !CHAT_ROLES.contains(&message.role.as_str()) {
+            return Err(invalid(&format!("messages[{}].role", i), format!("role must be one of {}, not {:?}", CHAT_ROLES.join(", "), message.role)));
+        }
+    }
+    if request.temperature.is_some_and(|t| !(0.0..=2.0).contains(&t)) {
+        return Err(invalid("temperature", "temperature must be between 0 and 2"));
+    }
+    if request.top_p.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+        return Err(invalid("top_p", "top_p must be greater than 0 and at most 1"));
+    }
+    // Every request gets one choice
+    if request.n.is_some_and(|n| n != 1) {
+        return Err(invalid("n", "n must be 1"));
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        let limit = max_tokens_limit();
+        if max_tokens == 0 || max_tokens > limit {
+            return Err(invalid("max_tokens", format!("max_tokens must be between 1 and {}", limit)));
+        }
+    }
+    validate_chat_request(request).map_err(|message| AppError::UnprocessableEntity { message, param: None })
+}
+
+/// Checks that an embeddings request has something to embed in every entry of its input, and
+/// asks for at least one dimension.
+pub fn embeddings(request: &EmbeddingsRequest) -> Result<(), AppError> {
+    let empty = match &request.input {
+        EmbeddingInput::Text(text) => text.is_empty(),
+        EmbeddingInput::TextBatch(texts) => texts.is_empty() || texts.iter().any(String::is_empty),
+        EmbeddingInput::Tokens(ids) => ids.is_empty(),
+        EmbeddingInput::TokensBatch(batch) => batch.is_empty() || batch.iter().any(Vec::is_empty),
+        EmbeddingInput::Mixed(items) => items.is_empty(),
+    };
+    if empty {
+        return Err(invalid("input", "input must not be empty or hold empty entries"));
+    }
+    if request.dimensions == Some(0) {
+        return Err(invalid("dimensions", "dimensions must be at least 1"));
+    }
+    Ok(())
+}
+
+// The fields a derived `Deserialize` struct accepts, as its implementation hands them to
+// `deserialize_struct`; empty for anything other than a struct
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("fields read"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
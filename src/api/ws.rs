@@ -0,0 +1,149 @@
+//! Chat over a WebSocket (`/v1/ws`), for clients behind proxies that buffer or cut SSE. Clients
+//! send chat requests as JSON frames and receive the same completion chunks SSE would carry,
+//! tagged with the request's id so several requests can share one socket and be cancelled
+//! individually.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::ws::{Message, WebSocket},
+    http::HeaderMap,
+};
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{
+    auth::{authorize_request, client_id, priority, Scope},
+    dto::{ChatCompletionRequest, ChatStreamError, WsClientMessage, WsServerMessage},
+    error::AppError,
+    validation,
+};
+use crate::engine::{CoreEngine, EngineError};
+
+impl WsServerMessage {
+    // The request's last frame; its id may be reused afterwards
+    fn finished_id(&self) -> Option<&str> {
+        match self {
+            WsServerMessage::Chunk { .. } => None,
+            WsServerMessage::Done { id } | WsServerMessage::Cancelled { id } => Some(id),
+            WsServerMessage::Error { id, .. } => id.as_deref(),
+        }
+    }
+}
+
+async fn send(sink: &mut SplitSink<WebSocket, Message>, message: &WsServerMessage) -> bool {
+    sink.send(Message::Text(serde_json::to_string(message).unwrap())).await.is_ok()
+}
+
+fn error(id: Option<String>, e: AppError) -> WsServerMessage {
+    let (_, body) = e.into_parts();
+    WsServerMessage::Error { id, message: body.error.message, error_type: body.error.kind.to_string(), code: body.error.code.map(str::to_string) }
+}
+
+fn invalid(id: Option<String>, message: impl Into<String>) -> WsServerMessage {
+    error(id, AppError::BadRequest(message.into()))
+}
+
+/// Runs one upgraded connection until the client closes it. `headers` are the upgrade
+/// request's; every chat request is authorized (and rate limited) with them.
+pub async fn serve(socket: WebSocket, engine: Arc<CoreEngine>, headers: HeaderMap) {
+    let (mut sink, mut source) = socket.split();
+    let (out_tx, mut out_rx) = mpsc::channel::<WsServerMessage>(100);
+    let mut inflight: HashMap<String, CancellationToken> = HashMap::new();
+    loop {
+        tokio::select! {
+            frame = source.next() => {
+                let text = match frame {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum; binary frames carry nothing we accept
+                    Some(Ok(_)) => continue,
+                };
+                let reply = match serde_json::from_str::<WsClientMessage>(&text) {
+                    Ok(WsClientMessage::Chat { id, request }) => {
+                        if inflight.contains_key(&id) {
+                            Some(invalid(Some(id), "a request with this id is already in flight"))
+                        } else if let Err(e) = authorize_request(engine.keys(), &headers, Scope::Inference).await {
+                            Some(error(Some(id), e.into()))
+                        } else if let Err(e) = validation::chat(&request) {
+                            // Refused like the HTTP API refuses the same body
+                            Some(error(Some(id), e))
+                        } else {
+                            let cancel = CancellationToken::new();
+                            inflight.insert(id.clone(), cancel.clone());
+                            tokio::spawn(run_chat(engine.clone(), id, *request, headers.clone(), cancel, out_tx.clone()));
+                            None
+                        }
+                    }
+                    // The request's task reports `chat.cancelled` once generation has stopped
+                    Ok(WsClientMessage::Cancel { id }) => match inflight.get(&id) {
+                        Some(cancel) => {
+                            cancel.cancel();
+                            None
+                        }
+                        None => Some(invalid(Some(id), "no request with this id is in flight")),
+                    },
+                    Err(e) => Some(invalid(None, format!("invalid message: {}", e))),
+                };
+                if let Some(reply) = reply
+                    && !send(&mut sink, &reply).await
+                {
+                    break;
+                }
+            }
+            Some(message) = out_rx.recv() => {
+                if let Some(id) = message.finished_id() {
+                    inflight.remove(id);
+                }
+                if !send(&mut sink, &message).await {
+                    break;
+                }
+            }
+        }
+    }
+    // The socket is gone; stop whatever it was still waiting for
+    inflight.values().for_each(CancellationToken::cancel);
+}
+
+async fn run_chat(
+    engine: Arc<CoreEngine>,
+    id: String,
+    mut request: ChatCompletionRequest,
+    headers: HeaderMap,
+    cancel: CancellationToken,
+    out: mpsc::Sender<WsServerMessage>,
+) {
+    request.stream = Some(true);
+    request.client_id = Some(client_id(&headers));
+    request.endpoint = Some("ws");
+    // Capped at the key's ceiling, as over HTTP
+    request.priority = Some(priority(&headers, request.priority));
+    let (tx, mut rx) = mpsc::channel::<String>(100);
+    if let Err(e) = engine.process_chat_request(request, Some(tx), cancel.clone()).await
+        && e != EngineError::Streaming
+    {
+        let _ = out.send(error(Some(id), e.into())).await;
+        return;
+    }
+    while let Some(chunk) = rx.recv().await {
+        if chunk == "[DONE]" {
+            let _ = out.send(WsServerMessage::Done { id }).await;
+            return;
+        }
+        if let Some(e) = ChatStreamError::parse(&chunk) {
+            let _ = out.send(error(Some(id), e.into())).await;
+            return;
+        }
+        let Ok(chunk) = serde_json::from_str(&chunk) else { continue };
+        if out.send(WsServerMessage::Chunk { id: id.clone(), chunk }).await.is_err() {
+            return;
+        }
+    }
+    let last = if cancel.is_cancelled() {
+        WsServerMessage::Cancelled { id }
+    } else {
+        error(Some(id), AppError::InternalServerError("generation ended before completing".to_string()))
+    };
+    let _ = out.send(last).await;
+}
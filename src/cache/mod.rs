@@ -0,0 +1,148 @@
+//! Response cache tiers: a shared one, so replicas of the server answer identical chat requests
+//! from each other's generations, and the accounting of each replica's in-memory one.
+//!
+//! Each replica keeps its own in-memory cache in front (which also coalesces identical requests);
+//! a miss there asks the shared backend before generating, and new responses are written to both.
+
+use async_trait::async_trait;
+use metrics::{counter, gauge};
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::api::dto::ChatCompletionResponse;
+
+pub mod redis;
+
+pub use redis::{RedisCache, RedisConfig};
+
+/// Byte values under string keys, each expiring on its own.
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    /// Backend name for logs and metrics ("redis").
+    fn backend(&self) -> &'static str;
+
+    /// The value under `key`, or None if there is none or it expired.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// Stores `value` under `key` for `ttl`, replacing any previous value.
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone)]
+pub enum CacheBackendConfig {
+    Redis(RedisConfig),
+}
+
+impl CacheBackendConfig {
+    /// ENV: RESPONSE_CACHE_BACKEND (memory | redis, default memory); memory keeps responses in
+    /// this process only, redis reads `RedisConfig::from_env`.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match crate::config::var("RESPONSE_CACHE_BACKEND").as_deref().unwrap_or("memory") {
+            "memory" => Ok(None),
+            "redis" => RedisConfig::from_env().map(|config| Some(CacheBackendConfig::Redis(config))),
+            other => Err(format!("unknown RESPONSE_CACHE_BACKEND {:?} (expected memory or redis)", other)),
+        }
+    }
+
+    pub fn build(self) -> Result<Arc<dyn CacheBackend>, String> {
+        match self {
+            CacheBackendConfig::Redis(config) => Ok(Arc::new(RedisCache::new(config)?)),
+        }
+    }
+}
+
+/// The shared backend RESPONSE_CACHE_BACKEND configures, if any, or why the configuration is
+/// unusable. Nothing connects until the first operation.
+pub fn shared_backend() -> Result<Option<Arc<dyn CacheBackend>>, String> {
+    CacheBackendConfig::from_env()?.map(CacheBackendConfig::build).transpose()
+}
+
+/// Chat responses in a [`CacheBackend`]. A backend that fails or holds something unreadable
+/// counts as a miss: the request generates instead of failing.
+pub struct SharedResponses {
+    backend: Arc<dyn CacheBackend>,
+    ttl: Duration,
+}
+
+impl SharedResponses {
+    pub fn new(backend: Arc<dyn CacheBackend>, ttl: Duration) -> Self {
+        Self { backend, ttl }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<ChatCompletionResponse> {
+        let bytes = match self.backend.get(key).await {
+            Ok(bytes) => bytes?,
+            Err(e) => {
+                self.failed("get", &e);
+                return None;
+            }
+        };
+        match serde_json::from_slice(&bytes) {
+            Ok(response) => {
+                counter!("cache_shared_hit_total", 1, "backend" => self.backend.backend());
+                Some(response)
+            }
+            Err(e) => {
+                self.failed("decode", &e.to_string());
+                None
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, response: &ChatCompletionResponse) {
+        let bytes = match serde_json::to_vec(response) {
+            Ok(bytes) => bytes,
+            Err(e) => return self.failed("encode", &e.to_string()),
+        };
+        if let Err(e) = self.backend.put(key, bytes, self.ttl).await {
+            self.failed("put", &e);
+        }
+    }
+
+    fn failed(&self, op: &'static str, error: &str) {
+        counter!("cache_backend_errors_total", 1, "backend" => self.backend.backend(), "op" => op);
+        tracing::warn!("{} response cache {} failed: {}", self.backend.backend(), op, error);
+    }
+}
+
+/// How many responses a replica's in-memory cache holds and roughly how much memory they take,
+/// published as the `response_cache_entries` and `response_cache_bytes` gauges.
+#[derive(Default)]
+pub struct Occupancy {
+    entries: AtomicI64,
+    bytes: AtomicI64,
+}
+
+impl Occupancy {
+    pub fn stored(&self, response: &ChatCompletionResponse) {
+        self.change(1, approximate_bytes(response) as i64);
+    }
+
+    pub fn evicted(&self, response: &ChatCompletionResponse) {
+        self.change(-1, -(approximate_bytes(response) as i64));
+    }
+
+    fn change(&self, entries: i64, bytes: i64) {
+        let entries = self.entries.fetch_add(entries, Ordering::Relaxed) + entries;
+        let bytes = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        gauge!("response_cache_entries", entries as f64);
+        gauge!("response_cache_bytes", bytes as f64);
+    }
+}
+
+// Heap and inline size of a cached response and its key (a hex SHA-256), ignoring allocator
+// slack
+fn approximate_bytes(response: &ChatCompletionResponse) -> usize {
+    let strings = response.id.len() + response.object.len() + response.model.len();
+    let choices: usize = response
+        .choices
+        .iter()
+        .map(|c| std::mem::size_of_val(c) + c.message.role.len() + c.message.content.len() + c.finish_reason.len())
+        .sum();
+    std::mem::size_of::<(String, ChatCompletionResponse)>() + 64 + strings + choices
+}
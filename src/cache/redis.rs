@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, ConnectionInfo, IntoConnectionInfo, SetExpiry, SetOptions};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::cache::CacheBackend;
+
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    pub connection: ConnectionInfo,
+    // Prepended to every key, so replicas of different deployments can share a server
+    pub prefix: String,
+    // Bounds each operation, connecting included; a slow cache must not hold up generation
+    pub timeout: Duration,
+}
+
+impl RedisConfig {
+    /// ENV: RESPONSE_CACHE_REDIS_URL (required; `redis://[[user]:password@]host[:port][/db]`, or
+    /// `rediss://` for TLS), RESPONSE_CACHE_REDIS_PREFIX (default `llm-serving:chat:`),
+    /// RESPONSE_CACHE_REDIS_TIMEOUT_MS (default 250)
+    pub fn from_env() -> Result<Self, String> {
+        let url = crate::config::var("RESPONSE_CACHE_REDIS_URL")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .ok_or("RESPONSE_CACHE_REDIS_URL is required for the redis response cache")?;
+        let mut config = Self::from_url(&url)?;
+        config.prefix = crate::config::var("RESPONSE_CACHE_REDIS_PREFIX").unwrap_or_else(|_| "llm-serving:chat:".to_string());
+        if let Ok(ms) = crate::config::var("RESPONSE_CACHE_REDIS_TIMEOUT_MS") {
+            let ms = ms.parse().map_err(|_| format!("RESPONSE_CACHE_REDIS_TIMEOUT_MS must be a number of milliseconds, not {:?}", ms))?;
+            config.timeout = Duration::from_millis(ms);
+        }
+        Ok(config)
+    }
+
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        let connection = url.into_connection_info().map_err(|e| format!("invalid redis URL {:?}: {}", url, e))?;
+        Ok(Self { connection, prefix: String::new(), timeout: Duration::from_millis(250) })
+    }
+}
+
+/// Values in a Redis server (or anything speaking its protocol), over a multiplexed connection
+/// that is opened on first use and reopened by the client after any error.
+pub struct RedisCache {
+    client: Client,
+    connection: OnceCell<ConnectionManager>,
+    prefix: String,
+    timeout: Duration,
+}
+
+impl RedisCache {
+    pub fn new(config: RedisConfig) -> Result<Self, String> {
+        let client = Client::open(config.connection).map_err(|e| format!("redis response cache: {}", e))?;
+        Ok(Self { client, connection: OnceCell::new(), prefix: config.prefix, timeout: config.timeout })
+    }
+
+    // Runs `op` on the shared connection, connecting first if nothing has yet; the timeout covers
+    // both
+    async fn with_connection<T, F>(&self, op: impl FnOnce(ConnectionManager) -> F) -> Result<T, String>
+    where
+        F: std::future::Future<Output = redis::RedisResult<T>>,
+    {
+        let run = async {
+            let connection = self.connection.get_or_try_init(|| self.client.get_connection_manager()).await?;
+            op(connection.clone()).await
+        };
+        match tokio::time::timeout(self.timeout, run).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("no reply from {} within {:?}", self.client.get_connection_info().addr, self.timeout)),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheBackend for RedisCache {
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let key = format!("{}{}", self.prefix, key);
+        self.with_connection(|mut connection| async move { connection.get(key).await }).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<(), String> {
+        let key = format!("{}{}", self.prefix, key);
+        let options = SetOptions::default().with_expiration(SetExpiry::PX(ttl.as_millis().max(1) as u64));
+        self.with_connection(|mut connection| async move { connection.set_options(key, value, options).await }).await
+    }
+}
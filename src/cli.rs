@@ -0,0 +1,96 @@
+//! Command-line arguments. Each flag stands for a setting the server otherwise reads from the
+//! environment or the configuration file, and takes precedence over both.
+
+use clap::Parser;
+use std::path::{Path, PathBuf};
+
+use crate::api::dto::LoadModelRequest;
+
+#[derive(Debug, Parser)]
+#[command(name = "llm-serving", version, about = "OpenAI-compatible inference server")]
+pub struct Cli {
+    /// Configuration file (TOML or YAML) [env: CONFIG_FILE]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Interface to listen on [default: 0.0.0.0, from LISTEN_ADDR]
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Port to listen on [default: 3000, from LISTEN_ADDR]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Separate address for the admin routes and metrics [env: ADMIN_LISTEN_ADDR]
+    #[arg(long, value_name = "ADDR")]
+    pub admin_addr: Option<String>,
+
+    /// Address of the gRPC API [env: GRPC_ADDR]
+    #[arg(long, value_name = "ADDR")]
+    pub grpc_addr: Option<String>,
+
+    /// Engine worker count [env: ENGINE_WORKERS]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub workers: Option<u64>,
+
+    /// LLM to load at startup, as `[name=]path`; the name defaults to the file name without
+    /// its extension. Repeatable
+    #[arg(long = "model", value_name = "[NAME=]PATH")]
+    pub models: Vec<String>,
+
+    /// Log filter such as `info` or `llm_serving=trace,tower_http=debug` [env: RUST_LOG]
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+}
+
+impl Cli {
+    /// ENV: CONFIG_FILE, overridden by `--config`
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(|| std::env::var_os("CONFIG_FILE").map(PathBuf::from))
+    }
+
+    /// The flags given, as the settings they override. `--host` and `--port` each replace
+    /// their half of `listen`, the address the environment or configuration file gives.
+    pub fn env_vars(&self, listen: &str) -> Vec<(String, String)> {
+        let listen = (self.host.is_some() || self.port.is_some()).then(|| {
+            let (host, port) = listen.rsplit_once(':').unwrap_or((listen, "3000"));
+            let host = self.host.as_deref().unwrap_or(host);
+            let port = self.port.map_or_else(|| port.to_string(), |p| p.to_string());
+            // An IPv6 host needs its brackets back
+            match host.contains(':') && !host.starts_with('[') {
+                true => format!("[{}]:{}", host, port),
+                false => format!("{}:{}", host, port),
+            }
+        });
+        [
+            ("LISTEN_ADDR", listen),
+            ("ADMIN_LISTEN_ADDR", self.admin_addr.clone()),
+            ("GRPC_ADDR", self.grpc_addr.clone()),
+            ("ENGINE_WORKERS", self.workers.map(|n| n.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect()
+    }
+
+    /// The `--model` flags as load requests.
+    pub fn model_requests(&self) -> Result<Vec<LoadModelRequest>, String> {
+        self.models
+            .iter()
+            .map(|arg| {
+                let (name, path) = match arg.split_once('=') {
+                    Some((name, path)) => (name.to_string(), path),
+                    None => {
+                        let stem = Path::new(arg).file_stem().and_then(|s| s.to_str()).filter(|_| !arg.contains("://"));
+                        let name = stem.ok_or_else(|| format!("--model {}: give it a name, as name=path", arg))?;
+                        (name.to_string(), arg.as_str())
+                    }
+                };
+                if name.is_empty() || path.is_empty() {
+                    return Err(format!("--model {}: expected [name=]path", arg));
+                }
+                Ok(LoadModelRequest { model: name, kind: "llm".to_string(), path: Some(path.to_string()), options: Default::default() })
+            })
+            .collect()
+    }
+}
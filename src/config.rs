@@ -0,0 +1,268 @@
+//! Server configuration file: listen addresses, worker counts, cache settings and the models
+//! to load at startup, in TOML or YAML (picked by extension). Settings reach the rest of the
+//! server under the environment variable names it already reads, through [`var`], so a
+//! variable set in the environment still overrides the file.
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env::VarError;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::api::dto::LoadModelRequest;
+use crate::engine::CoreEngine;
+
+// Settings of the installed configuration file, by variable name
+static SETTINGS: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+// Settings given on the command line, which win over the environment and the file
+static OVERRIDES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// A setting: the command line's value for it, the environment variable `name`, or the
+/// configuration file's value, in that order. Drop-in for `std::env::var`.
+pub fn var(name: impl AsRef<str>) -> Result<String, VarError> {
+    let name = name.as_ref();
+    if let Some(value) = OVERRIDES.read().unwrap().get(name) {
+        return Ok(value.clone());
+    }
+    match std::env::var(name) {
+        Err(VarError::NotPresent) => SETTINGS.read().unwrap().get(name).cloned().ok_or(VarError::NotPresent),
+        found => found,
+    }
+}
+
+/// Makes the command line's settings, by variable name, override every other source.
+pub fn set_overrides(vars: Vec<(String, String)>) {
+    OVERRIDES.write().unwrap().extend(vars);
+}
+
+/// Drops every override [`set_overrides`] made, so settings come from the environment and the
+/// configuration file again.
+pub fn clear_overrides() {
+    OVERRIDES.write().unwrap().clear();
+}
+
+/// Puts back the file settings [`Config::install`] replaced.
+pub fn restore_settings(settings: HashMap<String, String>) {
+    *SETTINGS.write().unwrap() = settings;
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub engine: EngineConfig,
+    pub cache: CacheConfig,
+    pub cors: CorsConfig,
+    // Loaded in order at startup, as if by `/admin/models/load`
+    pub models: Vec<LoadModelRequest>,
+    // Any other setting, by environment variable name
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // LISTEN_ADDR
+    pub listen: Option<String>,
+    // GRPC_ADDR
+    pub grpc_listen: Option<String>,
+    // ADMIN_LISTEN_ADDR
+    pub admin_listen: Option<String>,
+    // TLS_CERT_PATH and TLS_KEY_PATH, PEM files; HTTPS when set
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // TLS_CLIENT_CA_PATH, PEM bundle; client certificates are verified when set
+    pub tls_client_ca: Option<String>,
+    // TLS_ADMIN_PRINCIPALS, client certificate identities with the admin scope
+    pub tls_admin_principals: Vec<String>,
+    // API_KEYS; auth is off when empty
+    pub api_keys: Vec<String>,
+    // ADMIN_API_KEYS, keys with the admin scope
+    pub admin_api_keys: Vec<String>,
+    // SHUTDOWN_DRAIN_TIMEOUT_SECS
+    pub drain_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    // ENGINE_WORKERS
+    pub workers: Option<usize>,
+    // CONTINUOUS_BATCH_MAX_SEQS
+    pub continuous_batch_max_seqs: Option<usize>,
+    // GENERATION_TIMEOUT_SECS
+    pub generation_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    // MODEL_CACHE_DIR
+    pub model_dir: Option<String>,
+    // PREFIX_CACHE_ENTRIES
+    pub prefix_cache_entries: Option<usize>,
+    // LLAMA_SESSION_POOL_SIZE
+    pub llama_session_pool_size: Option<usize>,
+    // RESPONSE_CACHE_ENTRIES
+    pub response_cache_entries: Option<u64>,
+    // RESPONSE_CACHE_TTL_SECS
+    pub response_cache_ttl_secs: Option<u64>,
+    // RESPONSE_CACHE_BACKEND
+    pub response_cache_backend: Option<String>,
+    // RESPONSE_CACHE_REDIS_URL
+    pub redis_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    // CORS_ALLOWED_ORIGINS; CORS is off when empty
+    pub allowed_origins: Vec<String>,
+    // CORS_ALLOWED_HEADERS
+    pub allowed_headers: Vec<String>,
+    // CORS_ALLOWED_METHODS
+    pub allowed_methods: Vec<String>,
+    // CORS_ALLOW_CREDENTIALS
+    pub allow_credentials: Option<bool>,
+    // CORS_MAX_AGE_SECS
+    pub max_age_secs: Option<u64>,
+}
+
+impl Config {
+    /// Reads `path` as YAML (`.yaml`, `.yml`) or TOML (anything else).
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Self::from_toml(&text),
+        };
+        config.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, String> {
+        let config: Self = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, addr) in [
+            ("server.listen", &self.server.listen),
+            ("server.grpc_listen", &self.server.grpc_listen),
+            ("server.admin_listen", &self.server.admin_listen),
+        ] {
+            if let Some(addr) = addr
+                && addr.parse::<std::net::SocketAddr>().is_err()
+            {
+                return Err(format!("{} {:?} is not an address such as 0.0.0.0:3000", name, addr));
+            }
+        }
+        let cors = &self.cors;
+        crate::api::cors::layer(
+            &cors.allowed_origins,
+            &cors.allowed_headers,
+            &cors.allowed_methods,
+            cors.allow_credentials.unwrap_or(false),
+            cors.max_age_secs,
+        )
+        .map_err(|e| format!("cors: {}", e))?;
+        if self.engine.workers == Some(0) {
+            return Err("engine.workers must be at least 1".to_string());
+        }
+        if self.server.tls_cert.is_some() != self.server.tls_key.is_some() {
+            return Err("server.tls_cert and server.tls_key must be set together".to_string());
+        }
+        for (i, model) in self.models.iter().enumerate() {
+            if self.models[..i].iter().any(|earlier| earlier.model == model.model) {
+                return Err(format!("model {} is declared twice", model.model));
+            }
+            if !crate::engine::registry::MODEL_KINDS.contains(&model.kind.as_str()) {
+                return Err(format!("model {} has unknown kind {:?}", model.model, model.kind));
+            }
+            model.options.validate().map_err(|e| format!("model {}: {}", model.model, e))?;
+            // Models load in order, and a fallback has to be there first
+            if let Some(fallback) = model.options.fallback_model.as_deref() {
+                if fallback == model.model {
+                    return Err(format!("model {} cannot be its own fallback_model", model.model));
+                }
+                if self.models[i + 1..].iter().any(|later| later.model == fallback) {
+                    return Err(format!("model {} must be declared after its fallback_model {}", model.model, fallback));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The settings as environment variables: the typed ones first, then `env`.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let joined = |list: &Vec<String>| (!list.is_empty()).then(|| list.join(","));
+        let typed = [
+            ("LISTEN_ADDR", self.server.listen.clone()),
+            ("GRPC_ADDR", self.server.grpc_listen.clone()),
+            ("ADMIN_LISTEN_ADDR", self.server.admin_listen.clone()),
+            ("TLS_CERT_PATH", self.server.tls_cert.clone()),
+            ("TLS_KEY_PATH", self.server.tls_key.clone()),
+            ("TLS_CLIENT_CA_PATH", self.server.tls_client_ca.clone()),
+            ("TLS_ADMIN_PRINCIPALS", joined(&self.server.tls_admin_principals)),
+            ("API_KEYS", joined(&self.server.api_keys)),
+            ("ADMIN_API_KEYS", joined(&self.server.admin_api_keys)),
+            ("SHUTDOWN_DRAIN_TIMEOUT_SECS", self.server.drain_timeout_secs.map(|n| n.to_string())),
+            ("ENGINE_WORKERS", self.engine.workers.map(|n| n.to_string())),
+            ("CONTINUOUS_BATCH_MAX_SEQS", self.engine.continuous_batch_max_seqs.map(|n| n.to_string())),
+            ("GENERATION_TIMEOUT_SECS", self.engine.generation_timeout_secs.map(|n| n.to_string())),
+            ("MODEL_CACHE_DIR", self.cache.model_dir.clone()),
+            ("PREFIX_CACHE_ENTRIES", self.cache.prefix_cache_entries.map(|n| n.to_string())),
+            ("LLAMA_SESSION_POOL_SIZE", self.cache.llama_session_pool_size.map(|n| n.to_string())),
+            ("RESPONSE_CACHE_ENTRIES", self.cache.response_cache_entries.map(|n| n.to_string())),
+            ("RESPONSE_CACHE_TTL_SECS", self.cache.response_cache_ttl_secs.map(|n| n.to_string())),
+            ("RESPONSE_CACHE_BACKEND", self.cache.response_cache_backend.clone()),
+            ("RESPONSE_CACHE_REDIS_URL", self.cache.redis_url.clone()),
+            ("CORS_ALLOWED_ORIGINS", joined(&self.cors.allowed_origins)),
+            ("CORS_ALLOWED_HEADERS", joined(&self.cors.allowed_headers)),
+            ("CORS_ALLOWED_METHODS", joined(&self.cors.allowed_methods)),
+            ("CORS_ALLOW_CREDENTIALS", self.cors.allow_credentials.map(|b| b.to_string())),
+            ("CORS_MAX_AGE_SECS", self.cors.max_age_secs.map(|n| n.to_string())),
+        ];
+        typed
+            .into_iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .chain(self.env.iter().map(|(name, value)| (name.clone(), value.clone())))
+            .collect()
+    }
+
+    /// Makes the settings visible through [`var`], replacing those of an earlier file, which
+    /// are returned for [`restore_settings`].
+    pub fn install(&self) -> HashMap<String, String> {
+        std::mem::replace(&mut *SETTINGS.write().unwrap(), self.env_vars().into_iter().collect())
+    }
+
+    /// Loads `models` into `engine` in order, stopping at the first that fails.
+    pub async fn preload(&self, engine: &CoreEngine) -> Result<(), String> {
+        for model in &self.models {
+            engine
+                .load_model(&model.kind, &model.model, model.path.as_deref(), &model.options)
+                .await
+                .map_err(|e| format!("could not load {} model {}: {}", model.kind, model.model, e))?;
+            tracing::info!("loaded {} model {}", model.kind, model.model);
+        }
+        Ok(())
+    }
+}
+
+/// ENV: LISTEN_ADDR (default 0.0.0.0:3000)
+pub fn listen_addr() -> String {
+    var("LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string())
+}
+
+/// ENV: ADMIN_LISTEN_ADDR, where the `/admin/*` routes and metrics are served instead of
+/// LISTEN_ADDR (unset: on LISTEN_ADDR with the rest of the API)
+pub fn admin_listen_addr() -> Option<String> {
+    var("ADMIN_LISTEN_ADDR").ok().filter(|addr| !addr.is_empty())
+}
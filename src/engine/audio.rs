@@ -0,0 +1,70 @@
+use tokio::sync::mpsc;
+
+use crate::api::dto::{SpeechRequest, TranscriptionRequest, VerboseTranscriptionResponse};
+use crate::engine::{reply, CoreEngine, EngineError, EngineRequest};
+use crate::runtime::{audio::duration_secs, AudioTranscriptionRuntime, Speech, SpeechOptions, TranscriptionOptions, TtsRuntime};
+
+/// Longest speech input accepted, in characters (OpenAI's limit).
+pub const MAX_SPEECH_INPUT_CHARS: usize = 4096;
+
+pub async fn transcribe(
+    runtime: &dyn AudioTranscriptionRuntime,
+    request: TranscriptionRequest,
+) -> Result<VerboseTranscriptionResponse, EngineError> {
+    if request.samples.is_empty() {
+        return Err(EngineError::InvalidInput("Audio file contains no samples".to_string()));
+    }
+    let options = TranscriptionOptions {
+        language: request.language,
+        prompt: request.prompt,
+        temperature: request.temperature,
+    };
+    let transcription = runtime.transcribe(&request.samples, &options).await?;
+    Ok(VerboseTranscriptionResponse {
+        task: "transcribe".to_string(),
+        language: transcription.language,
+        duration: duration_secs(&request.samples),
+        text: transcription.text,
+        segments: transcription.segments,
+    })
+}
+
+pub async fn synthesize(runtime: &dyn TtsRuntime, request: SpeechRequest) -> Result<Speech, EngineError> {
+    if request.input.trim().is_empty() {
+        return Err(EngineError::InvalidInput("input must not be empty".to_string()));
+    }
+    if request.input.chars().count() > MAX_SPEECH_INPUT_CHARS {
+        return Err(EngineError::InvalidInput(format!("input is longer than {} characters", MAX_SPEECH_INPUT_CHARS)));
+    }
+    if !(0.25..=4.0).contains(&request.speed) {
+        return Err(EngineError::InvalidInput("speed must be between 0.25 and 4.0".to_string()));
+    }
+    let voices = runtime.voices();
+    if !voices.is_empty() && !voices.contains(&request.voice) {
+        return Err(EngineError::InvalidInput(format!("Unknown voice {}; available: {}", request.voice, voices.join(", "))));
+    }
+    let options = SpeechOptions { voice: request.voice, speed: request.speed };
+    let samples = runtime.synthesize(&request.input, &options).await?;
+    Ok(Speech { samples, sample_rate: runtime.sample_rate() })
+}
+
+impl CoreEngine {
+    pub async fn process_transcription_request(
+        &self,
+        request: TranscriptionRequest,
+    ) -> Result<VerboseTranscriptionResponse, EngineError> {
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::Transcription { request, response_sender }).await?;
+
+        reply(response_receiver).await
+    }
+
+    pub async fn process_speech_request(&self, request: SpeechRequest) -> Result<Speech, EngineError> {
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::Speech { request, response_sender }).await?;
+
+        reply(response_receiver).await
+    }
+}
@@ -0,0 +1,361 @@
+//! Batch jobs: an uploaded JSONL file of chat or embeddings requests run in the background at
+//! low priority, with every answer collected into a JSONL output file.
+//!
+//! Jobs run one at a time in submission order, and each request waits until no interactive
+//! request is queued for a worker. Job state lives in the shared storage under `batches/`, so
+//! finished jobs survive a restart and unfinished ones are run again from the start.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{RwLock, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+use crate::api::dto::{
+    Batch, BatchRequestCounts, BatchRequestLine, BatchResultLine, BatchResultResponse, ChatCompletionRequest, EmbeddingsRequest, Priority,
+};
+use crate::api::{error::AppError, validation};
+use crate::engine::{files::BATCH_OUTPUT_PURPOSE, CoreEngine};
+use crate::storage::Storage;
+
+/// Endpoints a batch may target.
+pub const BATCH_ENDPOINTS: [&str; 2] = ["/v1/chat/completions", "/v1/embeddings"];
+
+/// Most requests one batch may hold (OpenAI's limit).
+pub const MAX_BATCH_REQUESTS: usize = 50_000;
+
+// How often a waiting request checks whether interactive traffic has drained
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+enum BatchRequest {
+    Chat(ChatCompletionRequest),
+    Embeddings(EmbeddingsRequest),
+}
+
+struct ParsedLine {
+    custom_id: String,
+    request: BatchRequest,
+}
+
+/// Validates a batch input file for `endpoint` and returns how many requests it holds.
+pub fn validate_batch_input(input: &[u8], endpoint: &str) -> Result<usize, String> {
+    parse_batch_input(input, endpoint).map(|lines| lines.len())
+}
+
+fn parse_batch_input(input: &[u8], endpoint: &str) -> Result<Vec<ParsedLine>, String> {
+    if !BATCH_ENDPOINTS.contains(&endpoint) {
+        return Err(format!("endpoint must be one of {}", BATCH_ENDPOINTS.join(", ")));
+    }
+    let text = std::str::from_utf8(input).map_err(|_| "batch input must be UTF-8 JSONL".to_string())?;
+    let mut seen = std::collections::HashSet::new();
+    let mut lines = Vec::new();
+    for (index, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let number = index + 1;
+        let line: BatchRequestLine = serde_json::from_str(line).map_err(|e| format!("line {}: {}", number, e))?;
+        if line.method != "POST" {
+            return Err(format!("line {}: method must be POST", number));
+        }
+        if line.url != endpoint {
+            return Err(format!("line {}: url {} does not match the batch endpoint {}", number, line.url, endpoint));
+        }
+        if line.custom_id.is_empty() || !seen.insert(line.custom_id.clone()) {
+            return Err(format!("line {}: custom_id must be non-empty and unique", number));
+        }
+        let request = match endpoint {
+            "/v1/chat/completions" => {
+                let request: ChatCompletionRequest = serde_json::from_value(line.body).map_err(|e| format!("line {}: {}", number, e))?;
+                if request.stream.unwrap_or(false) {
+                    return Err(format!("line {}: streaming is not supported in batches", number));
+                }
+                validation::chat(&request).map_err(|e| invalid_line(number, e))?;
+                BatchRequest::Chat(request)
+            }
+            _ => {
+                let request: EmbeddingsRequest = serde_json::from_value(line.body).map_err(|e| format!("line {}: {}", number, e))?;
+                validation::embeddings(&request).map_err(|e| invalid_line(number, e))?;
+                BatchRequest::Embeddings(request)
+            }
+        };
+        lines.push(ParsedLine { custom_id: line.custom_id, request });
+    }
+    if lines.is_empty() {
+        return Err("batch input contains no requests".to_string());
+    }
+    if lines.len() > MAX_BATCH_REQUESTS {
+        return Err(format!("batch input holds {} requests; the limit is {}", lines.len(), MAX_BATCH_REQUESTS));
+    }
+    Ok(lines)
+}
+
+// A line the HTTP endpoint would refuse, refused with the same message
+fn invalid_line(number: usize, error: AppError) -> String {
+    format!("line {}: {}", number, error.into_parts().1.error.message)
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+// What `batches/<id>/batch.json` holds; the owner is never shown to clients
+#[derive(Serialize, Deserialize, Clone)]
+struct StoredBatch {
+    owner: String,
+    batch: Batch,
+}
+
+fn state_key(id: &str) -> String {
+    format!("{}/batch.json", id)
+}
+
+/// Batch jobs of this server. Counts of a running job are kept in memory and persisted with
+/// each status change.
+pub struct BatchStore {
+    storage: Arc<dyn Storage>,
+    jobs: RwLock<HashMap<String, StoredBatch>>,
+    // One job runs at a time; the semaphore is fair, so jobs start in submission order
+    runner: Arc<Semaphore>,
+}
+
+impl BatchStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage, jobs: RwLock::new(HashMap::new()), runner: Arc::new(Semaphore::new(1)) }
+    }
+
+    /// The batch `id` if it belongs to `owner`.
+    pub async fn get(&self, owner: &str, id: &str) -> Option<Batch> {
+        self.jobs.read().await.get(id).filter(|job| job.owner == owner).map(|job| job.batch.clone())
+    }
+
+    /// `owner`'s batches, newest first.
+    pub async fn list(&self, owner: &str) -> Vec<Batch> {
+        let mut list: Vec<Batch> = self.jobs.read().await.values().filter(|job| job.owner == owner).map(|job| job.batch.clone()).collect();
+        list.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+        list
+    }
+
+    /// Cancels a batch: a queued one at once, a running one after its current request.
+    pub async fn cancel(&self, owner: &str, id: &str) -> Result<Option<Batch>, String> {
+        let status = match self.get(owner, id).await {
+            Some(batch) => batch.status,
+            None => return Ok(None),
+        };
+        let batch = match status.as_str() {
+            "queued" => self.update(id, |b| {
+                b.status = "cancelled".to_string();
+                b.cancelled_at = Some(now_secs());
+            }).await?,
+            "in_progress" => self.update(id, |b| b.status = "cancelling".to_string()).await?,
+            "cancelling" => return Ok(self.get(owner, id).await),
+            other => return Err(format!("batch {} is {} and cannot be cancelled", id, other)),
+        };
+        Ok(Some(batch))
+    }
+
+    async fn status(&self, id: &str) -> String {
+        self.jobs.read().await.get(id).map(|job| job.batch.status.clone()).unwrap_or_default()
+    }
+
+    // Applies `f` and persists the result
+    async fn update<F: FnOnce(&mut Batch)>(&self, id: &str, f: F) -> Result<Batch, String> {
+        let stored = {
+            let mut jobs = self.jobs.write().await;
+            let job = jobs.get_mut(id).ok_or_else(|| format!("batch {} not found", id))?;
+            f(&mut job.batch);
+            job.clone()
+        };
+        self.storage.put(&state_key(id), serde_json::to_vec(&stored).unwrap()).await?;
+        Ok(stored.batch)
+    }
+
+    // Progress is only kept in memory; a restarted job starts over anyway
+    async fn record(&self, id: &str, ok: bool) {
+        if let Some(job) = self.jobs.write().await.get_mut(id) {
+            let counts = &mut job.batch.request_counts;
+            if ok { counts.completed += 1 } else { counts.failed += 1 }
+        }
+    }
+}
+
+impl CoreEngine {
+    pub fn batches(&self) -> &BatchStore {
+        &self.batches
+    }
+
+    /// Queues `owner`'s uploaded file `input_file_id` as a new batch.
+    pub async fn create_batch(self: &Arc<Self>, owner: &str, endpoint: &str, input_file_id: &str) -> Result<Batch, String> {
+        let input = self.files.content(owner, input_file_id).await?.ok_or_else(|| format!("File {} not found", input_file_id))?;
+        let total = validate_batch_input(&input, endpoint)?;
+        let batch = Batch {
+            id: format!("batch_{}", uuid::Uuid::new_v4().simple()),
+            object: "batch".to_string(),
+            endpoint: endpoint.to_string(),
+            input_file_id: input_file_id.to_string(),
+            output_file_id: None,
+            status: "queued".to_string(),
+            created_at: now_secs(),
+            in_progress_at: None,
+            completed_at: None,
+            failed_at: None,
+            cancelled_at: None,
+            request_counts: BatchRequestCounts { total, ..Default::default() },
+            error: None,
+        };
+        let store = &self.batches;
+        let stored = StoredBatch { owner: owner.to_string(), batch: batch.clone() };
+        store.storage.put(&state_key(&batch.id), serde_json::to_vec(&stored).unwrap()).await?;
+        store.jobs.write().await.insert(batch.id.clone(), stored);
+        counter!("batches_total", 1, "endpoint" => endpoint.to_string());
+        self.spawn_batch(batch.id.clone());
+        Ok(batch)
+    }
+
+    /// The output JSONL of `owner`'s batch `id` once it has finished or been cancelled.
+    pub async fn batch_output(&self, owner: &str, id: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(batch) = self.batches.get(owner, id).await else { return Ok(None) };
+        let Some(output_file_id) = batch.output_file_id else {
+            return Err(format!("batch {} has no output while {}", id, batch.status));
+        };
+        self.files.content(owner, &output_file_id).await
+    }
+
+    /// Loads batches persisted by earlier runs and queues the unfinished ones again. Returns how
+    /// many were queued.
+    pub async fn resume_batches(self: &Arc<Self>) -> Result<usize, String> {
+        let store = &self.batches;
+        let mut unfinished = Vec::new();
+        for object in store.storage.list("").await?.into_iter().filter(|o| o.key.ends_with("/batch.json")) {
+            let Some(bytes) = store.storage.get(&object.key).await? else { continue };
+            let stored: StoredBatch = serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", object.key, e))?;
+            let id = stored.batch.id.clone();
+            let status = stored.batch.status.clone();
+            store.jobs.write().await.insert(id.clone(), stored.clone());
+            match status.as_str() {
+                "queued" | "in_progress" => unfinished.push((stored.batch.created_at, id)),
+                "cancelling" => {
+                    store.update(&id, |b| {
+                        b.status = "cancelled".to_string();
+                        b.cancelled_at = Some(now_secs());
+                    }).await?;
+                }
+                _ => {}
+            }
+        }
+        unfinished.sort();
+        for (_, id) in &unfinished {
+            store.update(id, |b| {
+                b.status = "queued".to_string();
+                b.request_counts = BatchRequestCounts { total: b.request_counts.total, ..Default::default() };
+            }).await?;
+            self.spawn_batch(id.clone());
+        }
+        Ok(unfinished.len())
+    }
+
+    fn spawn_batch(self: &Arc<Self>, id: String) {
+        let engine = self.clone();
+        let runner = self.batches.runner.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = runner.acquire_owned().await else { return };
+            if let Err(e) = engine.run_batch(&id).await {
+                tracing::warn!("batch {} failed: {}", id, e);
+                let _ = engine.batches.update(&id, |b| {
+                    b.status = "failed".to_string();
+                    b.failed_at = Some(now_secs());
+                    b.error = Some(e);
+                }).await;
+            }
+        });
+    }
+
+    async fn run_batch(&self, id: &str) -> Result<(), String> {
+        let store = &self.batches;
+        // Cancelled while queued, or left queued for the next run to resume
+        if store.status(id).await != "queued" || self.is_draining() {
+            return Ok(());
+        }
+        let (owner, endpoint, input_file_id) = {
+            let jobs = store.jobs.read().await;
+            let job = jobs.get(id).ok_or_else(|| format!("batch {} not found", id))?;
+            (job.owner.clone(), job.batch.endpoint.clone(), job.batch.input_file_id.clone())
+        };
+        store.update(id, |b| {
+            b.status = "in_progress".to_string();
+            b.in_progress_at = Some(now_secs());
+        }).await?;
+        let input = self.files.content(&owner, &input_file_id).await?.ok_or("the batch input file was deleted")?;
+        let lines = parse_batch_input(&input, &endpoint)?;
+
+        let mut output = Vec::new();
+        for line in lines {
+            if store.status(id).await == "cancelling" {
+                break;
+            }
+            // Interactive requests go first: only submit when nothing is waiting for a worker
+            while self.queue_depth() > 0 {
+                tokio::time::sleep(IDLE_POLL).await;
+            }
+            // A shutting-down server turns new requests away; the job stays in_progress and
+            // the next run resumes it rather than recording those refusals as its results
+            if self.is_draining() {
+                return Ok(());
+            }
+            let response = self.run_batch_request(line.request, &owner).await;
+            if response.status_code != 200 && self.is_draining() {
+                return Ok(());
+            }
+            store.record(id, response.status_code == 200).await;
+            let result = BatchResultLine {
+                id: format!("batch_req_{}", uuid::Uuid::new_v4().simple()),
+                custom_id: line.custom_id,
+                response,
+            };
+            output.extend(serde_json::to_vec(&result).unwrap());
+            output.push(b'\n');
+        }
+        let output = self.files.put(&owner, &format!("{}_output.jsonl", id), BATCH_OUTPUT_PURPOSE, output).await?;
+        let cancelled = store.status(id).await == "cancelling";
+        store.update(id, |b| {
+            let now = Some(now_secs());
+            b.output_file_id = Some(output.id);
+            if cancelled {
+                b.status = "cancelled".to_string();
+                b.cancelled_at = now;
+            } else {
+                b.status = "completed".to_string();
+                b.completed_at = now;
+            }
+        }).await?;
+        Ok(())
+    }
+
+    // Status codes match what the HTTP handlers return for the same outcome
+    async fn run_batch_request(&self, request: BatchRequest, owner: &str) -> BatchResultResponse {
+        let (status_code, body) = match request {
+            BatchRequest::Chat(mut request) => {
+                request.client_id = Some(owner.to_string());
+                request.endpoint = Some("batches");
+                request.priority = Some(Priority::Low);
+                match self.process_chat_request(request, None, CancellationToken::new()).await {
+                    Ok(response) => (200, serde_json::to_value(response).unwrap()),
+                    Err(e) => failed(e.into()),
+                }
+            }
+            BatchRequest::Embeddings(mut request) => {
+                request.client_id = Some(owner.to_string());
+                request.priority = Some(Priority::Low);
+                match self.process_embedding_request(request).await {
+                    Ok(response) => (200, serde_json::to_value(response).unwrap()),
+                    Err(e) => failed(e.into()),
+                }
+            }
+        };
+        BatchResultResponse { status_code, body }
+    }
+}
+
+// A failed request's result line, with the body its HTTP handler would answer with
+fn failed(error: AppError) -> (u16, serde_json::Value) {
+    let (status, body) = error.into_parts();
+    (status.as_u16(), serde_json::to_value(body).unwrap())
+}
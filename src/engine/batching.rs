@@ -0,0 +1,161 @@
+use metrics::histogram;
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::engine::embeddings::EmbeddingBatch;
+use crate::runtime::{EmbeddingRuntime, RuntimeError};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchingConfig {
+    /// Most inputs merged into one `embed()` call; larger requests still run, alone
+    pub max_batch_size: usize,
+    /// How long the first request of a batch waits for company
+    pub max_wait: Duration,
+}
+
+impl BatchingConfig {
+    /// ENV: EMBEDDING_BATCH_MAX_SIZE (default 32), EMBEDDING_BATCH_WAIT_MS (default 5; 0 disables batching)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            max_batch_size: env("EMBEDDING_BATCH_MAX_SIZE").unwrap_or(32).max(1) as usize,
+            max_wait: Duration::from_millis(env("EMBEDDING_BATCH_WAIT_MS").unwrap_or(5)),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.max_batch_size > 1 && !self.max_wait.is_zero()
+    }
+}
+
+struct Pending {
+    runtime: Arc<dyn EmbeddingRuntime>,
+    batch: EmbeddingBatch,
+    reply: oneshot::Sender<Result<Vec<Vec<f32>>, RuntimeError>>,
+}
+
+impl Pending {
+    // Only inputs of the same kind bound for the same runtime instance can share a call
+    fn joins(&self, other: &Pending) -> bool {
+        Arc::ptr_eq(&self.runtime, &other.runtime)
+            && matches!(
+                (&self.batch, &other.batch),
+                (EmbeddingBatch::Texts(_), EmbeddingBatch::Texts(_)) | (EmbeddingBatch::Tokens(_), EmbeddingBatch::Tokens(_))
+            )
+    }
+}
+
+/// Coalesces concurrent embedding requests for the same model into a single runtime call.
+/// Each model gets a collector task that gathers requests until the batch is full or the
+/// wait window closes, then splits the vectors back out to their callers.
+pub struct EmbeddingBatcher {
+    config: BatchingConfig,
+    collectors: Mutex<HashMap<String, mpsc::UnboundedSender<Pending>>>,
+}
+
+impl EmbeddingBatcher {
+    pub fn new(config: BatchingConfig) -> Self {
+        Self { config, collectors: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn embed(
+        &self,
+        model: &str,
+        runtime: Arc<dyn EmbeddingRuntime>,
+        batch: EmbeddingBatch,
+    ) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let multimodal = matches!(batch, EmbeddingBatch::Multimodal(_));
+        if !self.config.enabled() || multimodal || batch.len() >= self.config.max_batch_size {
+            return batch.embed(runtime.as_ref()).await;
+        }
+        let (reply, response) = oneshot::channel();
+        self.collector(model)
+            .send(Pending { runtime, batch, reply })
+            .map_err(|_| RuntimeError::from("Embedding batcher stopped"))?;
+        response.await.map_err(|_| RuntimeError::from("Embedding batch dropped"))?
+    }
+
+    fn collector(&self, model: &str) -> mpsc::UnboundedSender<Pending> {
+        let mut collectors = self.collectors.lock().unwrap();
+        collectors
+            .entry(model.to_string())
+            .or_insert_with(|| {
+                let (tx, rx) = mpsc::unbounded_channel();
+                tokio::spawn(collect(self.config, rx));
+                tx
+            })
+            .clone()
+    }
+}
+
+async fn collect(config: BatchingConfig, mut rx: mpsc::UnboundedReceiver<Pending>) {
+    let mut carried: Option<Pending> = None;
+    loop {
+        let first = match carried.take() {
+            Some(pending) => pending,
+            None => match rx.recv().await {
+                Some(pending) => pending,
+                None => return,
+            },
+        };
+        let deadline = tokio::time::Instant::now() + config.max_wait;
+        let mut size = first.batch.len();
+        let mut group = vec![first];
+        while size < config.max_batch_size {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(pending)) if group[0].joins(&pending) && size + pending.batch.len() <= config.max_batch_size => {
+                    size += pending.batch.len();
+                    group.push(pending);
+                }
+                // Incompatible or too large: it opens the next batch
+                Ok(Some(pending)) => {
+                    carried = Some(pending);
+                    break;
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+        histogram!("embedding_batch_size", size as f64);
+        tokio::spawn(run_group(group));
+    }
+}
+
+async fn run_group(group: Vec<Pending>) {
+    let runtime = group[0].runtime.clone();
+    let lengths: Vec<usize> = group.iter().map(|p| p.batch.len()).collect();
+    let merged = match &group[0].batch {
+        EmbeddingBatch::Texts(_) => EmbeddingBatch::Texts(
+            group.iter().flat_map(|p| match &p.batch {
+                EmbeddingBatch::Texts(texts) => texts.clone(),
+                _ => unreachable!("batches are grouped by kind"),
+            }).collect(),
+        ),
+        EmbeddingBatch::Tokens(_) => EmbeddingBatch::Tokens(
+            group.iter().flat_map(|p| match &p.batch {
+                EmbeddingBatch::Tokens(ids) => ids.clone(),
+                _ => unreachable!("batches are grouped by kind"),
+            }).collect(),
+        ),
+        EmbeddingBatch::Multimodal(_) => unreachable!("multimodal requests are not batched"),
+    };
+    let result = merged.embed(runtime.as_ref()).await.and_then(|vectors| {
+        if vectors.len() == merged.len() {
+            Ok(vectors)
+        } else {
+            Err(RuntimeError::Backend(format!("Runtime returned {} embeddings for {} inputs", vectors.len(), merged.len())))
+        }
+    });
+    match result {
+        Ok(vectors) => {
+            let mut vectors = vectors.into_iter();
+            for (pending, len) in group.into_iter().zip(lengths) {
+                let _ = pending.reply.send(Ok(vectors.by_ref().take(len).collect()));
+            }
+        }
+        Err(e) => {
+            for pending in group {
+                let _ = pending.reply.send(Err(e.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Memory budget for loaded models. Each model's footprint is approximated by the size of its
+//! weights on disk plus, for GGUF models, the KV cache llama.cpp allocates for them at load.
+//! The share of the layers its options offload to a GPU counts against VRAM, the rest against
+//! RAM; remote and dummy models take nothing.
+
+use std::ops::{Add, Sub};
+use std::path::Path;
+
+use crate::api::dto::ModelOptions;
+use crate::engine::continuous::ContinuousBatchingConfig;
+use crate::engine::is_upstream_url;
+use crate::runtime::{gguf, pool::session_pool_size};
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Footprint {
+    pub ram_bytes: u64,
+    pub vram_bytes: u64,
+}
+
+impl Footprint {
+    /// Approximate memory a model at `path` takes once loaded with `options`.
+    pub fn estimate(path: Option<&str>, options: &ModelOptions) -> Self {
+        let Some(path) = path.filter(|p| !is_upstream_url(p)) else { return Self::default() };
+        // Vision models are loaded from a comma-separated vision, projection, LLM triple
+        let parts: Vec<&Path> = path.split(',').map(|part| Path::new(part.trim())).collect();
+        let weights: u64 = parts.iter().map(|part| disk_size(part)).sum();
+        let shape = parts.last().filter(|part| part.is_file()).and_then(|part| gguf::read_shape(part).ok());
+        let bytes = weights + shape.map_or(0, |shape| shape.kv_cache_bytes(kv_cells(options)));
+        let gpu_layers = match crate::runtime::accel::compat_mode() {
+            true => 0,
+            false => options.n_gpu_layers.unwrap_or(0) as u64,
+        };
+        // Without a known shape any offload is taken to be the whole model
+        let vram_bytes = match shape {
+            Some(shape) => bytes * gpu_layers.min(shape.layers) / shape.layers,
+            None if gpu_layers > 0 => bytes,
+            None => 0,
+        };
+        Self { ram_bytes: bytes - vram_bytes, vram_bytes }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// KV cache cells llama.cpp allocates for a model at load: one context shared by the batched
+// generations, or one per pooled session when generations decode alone
+fn kv_cells(options: &ModelOptions) -> u64 {
+    let batched = ContinuousBatchingConfig::from_env().enabled() && !options.context_shift;
+    match batched {
+        true => options.n_ctx.unwrap_or(gguf::BATCH_CTX) as u64,
+        false => options.n_ctx.unwrap_or(gguf::SESSION_CTX) as u64 * session_pool_size().max(1) as u64,
+    }
+}
+
+impl Add for Footprint {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self { ram_bytes: self.ram_bytes + other.ram_bytes, vram_bytes: self.vram_bytes + other.vram_bytes }
+    }
+}
+
+impl Sub for Footprint {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            ram_bytes: self.ram_bytes.saturating_sub(other.ram_bytes),
+            vram_bytes: self.vram_bytes.saturating_sub(other.vram_bytes),
+        }
+    }
+}
+
+impl std::iter::Sum for Footprint {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+// Size of a file, or of every file under a directory
+fn disk_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else { return 0 };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.filter_map(Result::ok).map(|entry| disk_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+/// Memory the loaded models may take together; unlimited when unset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub ram_bytes: Option<u64>,
+    pub vram_bytes: Option<u64>,
+}
+
+impl MemoryBudget {
+    /// ENV: MODEL_RAM_BUDGET_MB, MODEL_VRAM_BUDGET_MB (unset: unlimited), memory the loaded
+    /// models may take in RAM and in VRAM
+    pub fn from_env() -> Self {
+        let mb = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok()).map(|mb| mb * MB);
+        Self { ram_bytes: mb("MODEL_RAM_BUDGET_MB"), vram_bytes: mb("MODEL_VRAM_BUDGET_MB") }
+    }
+
+    pub fn fits(&self, used: Footprint) -> bool {
+        self.ram_bytes.is_none_or(|limit| used.ram_bytes <= limit) && self.vram_bytes.is_none_or(|limit| used.vram_bytes <= limit)
+    }
+
+    /// The part of `used` over the budget.
+    pub fn excess(&self, used: Footprint) -> Footprint {
+        used - Footprint { ram_bytes: self.ram_bytes.unwrap_or(u64::MAX), vram_bytes: self.vram_bytes.unwrap_or(u64::MAX) }
+    }
+}
+
+pub fn megabytes(bytes: u64) -> u64 {
+    bytes.div_ceil(MB)
+}
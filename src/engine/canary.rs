@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::RwLock, time::Instant};
+use metrics::counter;
+use rand::Rng;
+
+use crate::api::dto::{CanaryDecision, CanaryDeployment, CanaryPolicy, CanaryVariantStats, CreateCanaryRequest};
+use crate::engine::{events::{EngineEvent, EventBus}, registry::model_not_found, splits::TrafficSplitter, CoreEngine, EngineError};
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+// A canary request's outcome, kept while it is inside the policy's `window_secs`
+struct Outcome {
+    at: Instant,
+    ok: bool,
+    latency_ms: f64,
+}
+
+// Routes an exposed model name to a baseline or canary runtime and rolls the canary
+// back automatically when its observed behaviour regresses past the policy thresholds.
+// When the name has a traffic split, the split routes and a rollback moves the canary's
+// share of it to the baseline; removing the deployment puts the split back as it was.
+#[derive(Default)]
+pub struct CanaryRouter {
+    deployments: RwLock<HashMap<String, CanaryDeployment>>,
+    // Recent canary outcomes of deployments whose policy has a window
+    recent: Mutex<HashMap<String, VecDeque<Outcome>>>,
+    // Baseline and canary percentages of the name's traffic split before the deployment
+    split_shares: Mutex<HashMap<String, (f32, f32)>>,
+    audit: RwLock<Vec<CanaryDecision>>,
+    events: EventBus,
+    splits: Arc<TrafficSplitter>,
+}
+
+impl CanaryRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_events(events: EventBus) -> Self {
+        Self { events, ..Self::default() }
+    }
+
+    /// Routes through the traffic splits in `splits` for names that have one.
+    pub fn with_splits(mut self, splits: Arc<TrafficSplitter>) -> Self {
+        self.splits = splits;
+        self
+    }
+
+    fn check(req: &CreateCanaryRequest) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&req.weight) {
+            return Err("canary weight must be between 0.0 and 1.0".to_string());
+        }
+        if req.baseline == req.canary {
+            return Err("canary and baseline must be different models".to_string());
+        }
+        if req.policy.window_secs == Some(0) {
+            return Err("window_secs must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Registers a canary for `req.model`, replacing any earlier one. Should the name have a
+    /// traffic split, the canary gets `weight` of what the split gives the baseline and canary
+    /// together.
+    pub async fn create(&self, req: CreateCanaryRequest) -> Result<CanaryDeployment, String> {
+        Self::check(&req)?;
+        let mut deployments = self.deployments.write().await;
+        let earlier = deployments.get(&req.model);
+        if let Some(earlier) = earlier {
+            self.restore_split(earlier);
+        }
+        if self.splits.contains(&req.model) {
+            match self.splits.rebalance(&req.model, &req.baseline, &req.canary, req.weight) {
+                Ok(before) => {
+                    self.split_shares.lock().unwrap().insert(req.model.clone(), before);
+                }
+                Err(e) => {
+                    // The earlier deployment stays, so its weighting comes back
+                    if let Some(earlier) = earlier {
+                        self.reweigh_split(earlier);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let deployment = CanaryDeployment {
+            model: req.model.clone(),
+            baseline: req.baseline,
+            canary: req.canary,
+            weight: req.weight,
+            policy: req.policy,
+            status: "active".to_string(),
+            created: now_secs(),
+            baseline_stats: CanaryVariantStats::default(),
+            canary_stats: CanaryVariantStats::default(),
+        };
+        deployments.insert(req.model.clone(), deployment.clone());
+        drop(deployments);
+        self.recent.lock().unwrap().remove(&req.model);
+        self.log(&req.model, "created", format!("canary {} at weight {:.2}", deployment.canary, deployment.weight)).await;
+        Ok(deployment)
+    }
+
+    pub async fn remove(&self, model: &str) -> Result<(), String> {
+        let deployment = self.deployments.write().await.remove(model)
+            .ok_or_else(|| format!("Canary for model {} not found", model))?;
+        self.restore_split(&deployment);
+        self.recent.lock().unwrap().remove(model);
+        self.log(model, "removed", "deployment removed by operator".to_string()).await;
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Vec<CanaryDeployment> {
+        let mut list: Vec<CanaryDeployment> = self.deployments.read().await.values().cloned().collect();
+        list.sort_by(|a, b| a.model.cmp(&b.model));
+        list
+    }
+
+    pub async fn audit_log(&self) -> Vec<CanaryDecision> {
+        self.audit.read().await.clone()
+    }
+
+    /// Resolves the runtime that should serve `model`: a variant of its traffic split, if it has
+    /// one, else the baseline or canary of its deployment. Other names resolve to themselves.
+    pub async fn resolve(&self, model: &str) -> String {
+        if let Some(variant) = self.splits.resolve(model) {
+            return variant;
+        }
+        let deployments = self.deployments.read().await;
+        match deployments.get(model) {
+            Some(d) if d.status == "active" && rand::thread_rng().r#gen::<f32>() < d.weight => d.canary.clone(),
+            Some(d) => d.baseline.clone(),
+            None => model.to_string(),
+        }
+    }
+
+    /// Records the outcome of a request served for `model` by `variant`, in its traffic split and
+    /// deployment, and applies the deployment's policy.
+    pub async fn record(&self, model: &str, variant: &str, ok: bool, latency_ms: f64) {
+        self.splits.record(model, variant, ok, latency_ms);
+        let breach = {
+            let mut deployments = self.deployments.write().await;
+            let Some(d) = deployments.get_mut(model) else { return };
+            let stats = if variant == d.canary {
+                &mut d.canary_stats
+            } else if variant == d.baseline {
+                &mut d.baseline_stats
+            } else {
+                return;
+            };
+            stats.requests += 1;
+            if !ok { stats.errors += 1; }
+            stats.total_latency_ms += latency_ms;
+            if d.status != "active" || variant != d.canary {
+                None
+            } else if let Some(window) = d.policy.window_secs {
+                let mut recent = self.recent.lock().unwrap();
+                let outcomes = recent.entry(model.to_string()).or_default();
+                outcomes.push_back(Outcome { at: Instant::now(), ok, latency_ms });
+                while outcomes.front().is_some_and(|o| o.at.elapsed() > Duration::from_secs(window)) {
+                    outcomes.pop_front();
+                }
+                let windowed = CanaryVariantStats {
+                    requests: outcomes.len() as u64,
+                    errors: outcomes.iter().filter(|o| !o.ok).count() as u64,
+                    total_latency_ms: outcomes.iter().map(|o| o.latency_ms).sum(),
+                };
+                Self::check_traffic_policy(&d.policy, &windowed)
+                    .map(|breach| format!("{} over the last {}s", breach, window))
+            } else {
+                Self::check_traffic_policy(&d.policy, &d.canary_stats)
+            }
+        };
+        if let Some(reason) = breach {
+            self.rollback(model, reason).await;
+        }
+    }
+
+    /// Applies `min_eval_score` to every active deployment whose canary is `variant`.
+    pub async fn record_eval_score(&self, variant: &str, score: f32) {
+        let breached: Vec<(String, String)> = self.deployments.read().await.values()
+            .filter(|d| d.status == "active" && d.canary == variant)
+            .filter_map(|d| d.policy.min_eval_score
+                .filter(|min| score < *min)
+                .map(|min| (d.model.clone(), format!("eval score {:.3} below minimum {:.3}", score, min))))
+            .collect();
+        for (model, reason) in breached {
+            self.rollback(&model, reason).await;
+        }
+    }
+
+    fn check_traffic_policy(policy: &CanaryPolicy, stats: &CanaryVariantStats) -> Option<String> {
+        if stats.requests < policy.min_requests {
+            return None;
+        }
+        let error_rate = stats.errors as f32 / stats.requests as f32;
+        if let Some(max) = policy.max_error_rate
+            && error_rate > max
+        {
+            return Some(format!("error rate {:.3} exceeds maximum {:.3}", error_rate, max));
+        }
+        let avg_latency = stats.total_latency_ms / stats.requests as f64;
+        if let Some(max) = policy.max_avg_latency_ms
+            && avg_latency > max
+        {
+            return Some(format!("average latency {:.1}ms exceeds maximum {:.1}ms", avg_latency, max));
+        }
+        None
+    }
+
+    async fn rollback(&self, model: &str, reason: String) {
+        let (baseline, canary) = {
+            let mut deployments = self.deployments.write().await;
+            let Some(d) = deployments.get_mut(model) else { return };
+            if d.status != "active" { return; }
+            d.status = "rolled_back".to_string();
+            d.weight = 0.0;
+            (d.baseline.clone(), d.canary.clone())
+        };
+        self.recent.lock().unwrap().remove(model);
+        // A split that still routes to the canary sends its share to the baseline instead
+        if self.splits.contains(model) {
+            let _ = self.splits.rebalance(model, &baseline, &canary, 0.0);
+        }
+        counter!("canary_rollbacks_total", 1);
+        self.events.publish(EngineEvent::CanaryRolledBack { model: model.to_string(), canary, reason: reason.clone() });
+        tracing::warn!("canary for {} rolled back: {}", model, reason);
+        self.log(model, "rollback", reason).await;
+    }
+
+    // Weights the split of the deployment's name as the deployment does (none once rolled back)
+    fn reweigh_split(&self, deployment: &CanaryDeployment) {
+        if let Ok(before) = self.splits.rebalance(&deployment.model, &deployment.baseline, &deployment.canary, deployment.weight) {
+            self.split_shares.lock().unwrap().insert(deployment.model.clone(), before);
+        }
+    }
+
+    // Gives the split of the deployment's name back the shares it had before the deployment
+    fn restore_split(&self, deployment: &CanaryDeployment) {
+        if let Some(before) = self.split_shares.lock().unwrap().remove(&deployment.model) {
+            self.splits.restore(&deployment.model, &deployment.baseline, &deployment.canary, before);
+        }
+    }
+
+    async fn log(&self, model: &str, action: &str, reason: String) {
+        self.audit.write().await.push(CanaryDecision {
+            timestamp: now_secs(),
+            model: model.to_string(),
+            action: action.to_string(),
+            reason,
+        });
+    }
+}
+
+impl CoreEngine {
+    /// Registers a canary for `req.model` between two loaded models.
+    pub async fn create_canary(&self, req: CreateCanaryRequest) -> Result<CanaryDeployment, EngineError> {
+        let models = self.list_models().await;
+        for name in [&req.baseline, &req.canary] {
+            if !models.llm.contains(name) && !models.multimodal.contains(name) {
+                return Err(EngineError::ModelNotFound(model_not_found(name)));
+            }
+        }
+        self.canaries.create(req).await.map_err(EngineError::InvalidInput)
+    }
+
+    pub fn canaries(&self) -> &CanaryRouter {
+        &self.canaries
+    }
+}
@@ -0,0 +1,20 @@
+/// Re-chunks streamed text so every emitted piece ends on a whitespace boundary.
+#[derive(Debug, Default)]
+pub struct WordChunker {
+    pending: String,
+}
+
+impl WordChunker {
+    /// Buffers `token` and returns everything up to and including the last whitespace seen, if any.
+    pub fn push(&mut self, token: &str) -> Option<String> {
+        self.pending.push_str(token);
+        let (idx, ch) = self.pending.char_indices().rev().find(|(_, c)| c.is_whitespace())?;
+        let rest = self.pending.split_off(idx + ch.len_utf8());
+        Some(std::mem::replace(&mut self.pending, rest))
+    }
+
+    /// Flushes the trailing partial word once the stream ends.
+    pub fn finish(self) -> Option<String> {
+        (!self.pending.is_empty()).then_some(self.pending)
+    }
+}
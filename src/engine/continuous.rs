@@ -0,0 +1,240 @@
+use metrics::{gauge, histogram};
+use std::{collections::HashMap, sync::{Arc, Mutex, Weak}};
+use tokio::{runtime::Handle, sync::{mpsc, oneshot, OwnedSemaphorePermit}};
+
+use crate::runtime::{BatchDecodeRuntime, GenerationOptions, LlmRuntime, RuntimeError, SequenceId, StepOutput};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContinuousBatchingConfig {
+    /// Most generations sharing one decode step; the runtime may allow fewer
+    pub max_sequences: usize,
+}
+
+impl ContinuousBatchingConfig {
+    /// ENV: CONTINUOUS_BATCH_MAX_SEQS (default 16; 0 or 1 disables continuous batching)
+    pub fn from_env() -> Self {
+        let max_sequences = crate::config::var("CONTINUOUS_BATCH_MAX_SEQS").ok().and_then(|v| v.parse().ok()).unwrap_or(16);
+        Self { max_sequences }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.max_sequences > 1
+    }
+}
+
+struct Join {
+    decoder: Arc<dyn BatchDecodeRuntime>,
+    prompt: String,
+    options: GenerationOptions,
+    sender: mpsc::Sender<String>,
+    // Worker permit, given back once the sequence has a place in the batch
+    slot: Option<OwnedSemaphorePermit>,
+    done: oneshot::Sender<Result<(), RuntimeError>>,
+}
+
+// The decoder a model's scheduler serves and where to send it generations
+type Scheduler = (Weak<dyn BatchDecodeRuntime>, mpsc::UnboundedSender<Join>);
+
+struct Active {
+    id: SequenceId,
+    options: GenerationOptions,
+    sender: mpsc::Sender<String>,
+    done: oneshot::Sender<Result<(), RuntimeError>>,
+}
+
+/// Runs every generation for a model through one decode loop, so concurrent requests share
+/// forward passes. Sequences join between steps as soon as there is room and leave as soon as
+/// they finish, rather than waiting for the whole batch.
+pub struct ContinuousBatcher {
+    config: ContinuousBatchingConfig,
+    // A reloaded model gets a fresh scheduler
+    schedulers: Mutex<HashMap<String, Scheduler>>,
+}
+
+impl ContinuousBatcher {
+    pub fn new(config: ContinuousBatchingConfig) -> Self {
+        Self { config, schedulers: Mutex::new(HashMap::new()) }
+    }
+
+    /// `runtime.generate_stream`, through the model's decode loop when the runtime has a batch
+    /// decoder. `slot` is released once the generation has a place in the batch.
+    pub async fn generate_stream(
+        &self,
+        model: &str,
+        runtime: &dyn LlmRuntime,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<(), RuntimeError> {
+        match runtime.batch_decoder().filter(|d| self.config.enabled() && d.accepts(options)) {
+            Some(decoder) => self.join(model, decoder, prompt, options, sender, slot).await,
+            None => runtime.generate_stream(prompt, options, sender).await,
+        }
+    }
+
+    /// `runtime.generate`, through the model's decode loop when the runtime has a batch decoder.
+    pub async fn generate(
+        &self,
+        model: &str,
+        runtime: &dyn LlmRuntime,
+        prompt: &str,
+        options: &GenerationOptions,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<String, RuntimeError> {
+        let Some(decoder) = runtime.batch_decoder().filter(|d| self.config.enabled() && d.accepts(options)) else {
+            return runtime.generate(prompt, options).await;
+        };
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.join(model, decoder, prompt, options, tx, slot), collect);
+        result.map(|_| text)
+    }
+
+    async fn join(
+        &self,
+        model: &str,
+        decoder: Arc<dyn BatchDecodeRuntime>,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+        slot: Option<OwnedSemaphorePermit>,
+    ) -> Result<(), RuntimeError> {
+        let (done, result) = oneshot::channel();
+        let join = Join { decoder: decoder.clone(), prompt: prompt.to_string(), options: options.clone(), sender, slot, done };
+        self.scheduler(model, &decoder).send(join).map_err(|_| RuntimeError::from("Decode scheduler stopped"))?;
+        result.await.map_err(|_| RuntimeError::from("Decode scheduler dropped the generation"))?
+    }
+
+    fn scheduler(&self, model: &str, decoder: &Arc<dyn BatchDecodeRuntime>) -> mpsc::UnboundedSender<Join> {
+        let mut schedulers = self.schedulers.lock().unwrap();
+        if let Some((current, tx)) = schedulers.get(model)
+            && Weak::ptr_eq(current, &Arc::downgrade(decoder))
+            && !tx.is_closed()
+        {
+            return tx.clone();
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let max_sequences = self.config.max_sequences.min(decoder.max_sequences()).max(1);
+        let name = model.to_string();
+        // The scheduler thread waits on channels through the runtime its generations come from
+        let handle = Handle::current();
+        std::thread::Builder::new()
+            .name(format!("decode-{}", model))
+            .spawn(move || run(&name, max_sequences, rx, handle))
+            .expect("failed to spawn decode scheduler");
+        schedulers.insert(model.to_string(), (Arc::downgrade(decoder), tx.clone()));
+        tx
+    }
+}
+
+// The decode loop of one model; returns once it is idle and its model has a new scheduler
+fn run(model: &str, max_sequences: usize, mut rx: mpsc::UnboundedReceiver<Join>, handle: Handle) {
+    // Held only while sequences are active, so an unloaded model can be freed when idle
+    let mut decoder: Option<Arc<dyn BatchDecodeRuntime>> = None;
+    let mut active: Vec<Active> = Vec::new();
+    let mut next_id: SequenceId = 0;
+    // A generation that arrived while the scheduler was waiting for room
+    let mut pending: Option<Join> = None;
+    loop {
+        // Block for work only when nothing is decoding; otherwise admit without waiting
+        while active.len() < max_sequences {
+            let join = if let Some(join) = pending.take() {
+                join
+            } else if active.is_empty() {
+                decoder = None;
+                gauge!("decode_active_sequences", 0.0, "model" => model.to_string());
+                match rx.blocking_recv() {
+                    Some(join) => join,
+                    None => return,
+                }
+            } else {
+                match rx.try_recv() {
+                    Ok(join) => join,
+                    Err(_) => break,
+                }
+            };
+            drop(join.slot);
+            if join.options.cancel.is_cancelled() || join.sender.is_closed() {
+                let _ = join.done.send(Ok(()));
+                continue;
+            }
+            next_id += 1;
+            match join.decoder.add_sequence(next_id, &join.prompt, &join.options) {
+                Ok(()) => {
+                    decoder = Some(join.decoder);
+                    active.push(Active { id: next_id, options: join.options, sender: join.sender, done: join.done });
+                }
+                Err(e) => {
+                    let _ = join.done.send(Err(e));
+                }
+            }
+        }
+        let Some(current) = decoder.clone() else { continue };
+
+        // Sequences whose client went away leave; those whose reader is behind sit this step out
+        for i in (0..active.len()).rev() {
+            if active[i].options.cancel.is_cancelled() || active[i].sender.is_closed() {
+                let sequence = active.swap_remove(i);
+                current.remove_sequence(sequence.id);
+                let _ = sequence.done.send(Ok(()));
+            }
+        }
+        let stepping: Vec<usize> = (0..active.len()).filter(|&i| active[i].sender.capacity() > 0).collect();
+        gauge!("decode_active_sequences", active.len() as f64, "model" => model.to_string());
+        if stepping.is_empty() {
+            if !active.is_empty() {
+                let accept = pending.is_none() && active.len() < max_sequences;
+                pending = handle.block_on(wait_for_room(&active, &mut rx, accept)).or(pending);
+            }
+            continue;
+        }
+
+        let ids: Vec<SequenceId> = stepping.iter().map(|&i| active[i].id).collect();
+        histogram!("decode_batch_size", ids.len() as f64, "model" => model.to_string());
+        let mut outputs = current.step(&ids);
+        outputs.resize(ids.len(), StepOutput::Failed("runtime returned too few step outputs".to_string()));
+        let mut finished: Vec<(usize, Result<(), RuntimeError>)> = Vec::new();
+        for (&i, output) in stepping.iter().zip(outputs) {
+            match output {
+                // Capacity was checked above and this loop is the only sender
+                StepOutput::Piece(piece) if !piece.is_empty() => {
+                    let _ = active[i].sender.try_send(piece);
+                }
+                StepOutput::Piece(_) => {}
+                StepOutput::Finished => finished.push((i, Ok(()))),
+                StepOutput::Failed(e) => finished.push((i, Err(RuntimeError::Backend(e)))),
+            }
+        }
+        // Highest index first so the remaining indices stay valid
+        for (i, result) in finished.into_iter().rev() {
+            let sequence = active.swap_remove(i);
+            current.remove_sequence(sequence.id);
+            let _ = sequence.done.send(result);
+        }
+    }
+}
+
+// Waits until a reader catches up or goes away, or a generation is cancelled; with `accept`, a
+// new generation arriving ends the wait too and is returned
+async fn wait_for_room(active: &[Active], rx: &mut mpsc::UnboundedReceiver<Join>, accept: bool) -> Option<Join> {
+    let room = futures::future::select_all(active.iter().map(|sequence| {
+        Box::pin(async move {
+            tokio::select! {
+                // The permit goes back as soon as it is dropped; closed senders return at once
+                _ = sequence.sender.reserve() => {}
+                _ = sequence.options.cancel.cancelled() => {}
+            }
+        })
+    }));
+    tokio::select! {
+        _ = room => None,
+        Some(join) = rx.recv(), if accept => Some(join),
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::{atomic::Ordering, Arc};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use metrics::counter;
+
+use crate::api::dto::{CreateDownloadRequest, DownloadJob};
+use crate::engine::{hub, registry, CoreEngine};
+
+struct Download {
+    job: DownloadJob,
+    progress: Arc<hub::Progress>,
+    cancel: CancellationToken,
+}
+
+impl Download {
+    // The record with the live byte counts filled in
+    fn snapshot(&self) -> DownloadJob {
+        let mut job = self.job.clone();
+        job.downloaded_bytes = self.progress.downloaded_bytes.load(Ordering::Relaxed);
+        job.total_bytes = Some(self.progress.total_bytes.load(Ordering::Relaxed)).filter(|&total| total > 0);
+        job.files_completed = self.progress.files_completed.load(Ordering::Relaxed);
+        job.files_total = Some(self.progress.files_total.load(Ordering::Relaxed)).filter(|&files| files > 0);
+        job
+    }
+}
+
+// In-memory record of background model downloads (no persistence; the files themselves are
+// cached, so a download restarted after a crash resumes where it stopped)
+#[derive(Default)]
+pub struct DownloadStore {
+    downloads: RwLock<Vec<Download>>,
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl DownloadStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn list(&self) -> Vec<DownloadJob> {
+        self.downloads.read().await.iter().map(Download::snapshot).collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<DownloadJob> {
+        self.downloads.read().await.iter().find(|d| d.job.id == id).map(Download::snapshot)
+    }
+
+    /// Stops a running download; the bytes received so far are kept for the next attempt.
+    /// None when there is no such download.
+    pub async fn cancel(&self, id: &str) -> Result<Option<DownloadJob>, String> {
+        let downloads = self.downloads.read().await;
+        let Some(download) = downloads.iter().find(|d| d.job.id == id) else { return Ok(None) };
+        if download.job.status != "downloading" {
+            return Err(format!("Download {} is already {}", id, download.job.status));
+        }
+        download.cancel.cancel();
+        Ok(Some(download.snapshot()))
+    }
+
+    async fn update<F: FnOnce(&mut DownloadJob)>(&self, id: &str, f: F) {
+        if let Some(download) = self.downloads.write().await.iter_mut().find(|d| d.job.id == id) {
+            f(&mut download.job);
+        }
+    }
+}
+
+impl CoreEngine {
+    /// Starts downloading a Hub model in the background and returns its initial
+    /// ("downloading") record. Once the files are in the cache the model is loaded as if by
+    /// `/admin/models/load`, so it is served without a further request.
+    pub async fn start_download(self: &Arc<Self>, req: CreateDownloadRequest) -> Result<DownloadJob, String> {
+        req.options.validate()?;
+        // Checked now rather than after a long download
+        if !registry::MODEL_KINDS.contains(&req.kind.as_str()) {
+            return Err("unknown kind".to_string());
+        }
+        let repo = req.options.hf_repo.clone().ok_or("hf_repo is required")?;
+        let progress = Arc::new(hub::Progress::default());
+        let cancel = CancellationToken::new();
+        let job = DownloadJob {
+            id: format!("download-{}", uuid::Uuid::new_v4()),
+            object: "model.download".to_string(),
+            model: req.model.clone(),
+            kind: req.kind.clone(),
+            hf_repo: repo.clone(),
+            filename: req.options.filename.clone(),
+            hf_revision: req.options.hf_revision.clone(),
+            status: "downloading".to_string(),
+            downloaded_bytes: 0,
+            total_bytes: None,
+            files_completed: 0,
+            files_total: None,
+            path: None,
+            error: None,
+            created: now_secs(),
+            finished: None,
+        };
+        self.downloads.downloads.write().await.push(Download { job: job.clone(), progress: progress.clone(), cancel: cancel.clone() });
+
+        let engine = self.clone();
+        let id = job.id.clone();
+        tokio::spawn(async move {
+            let options = &req.options;
+            let fetched = hub::fetch_with_progress(
+                &hub::HubConfig::from_env(),
+                &repo,
+                options.filename.as_deref(),
+                options.hf_revision.as_deref(),
+                &progress,
+                &cancel,
+            )
+            .await;
+            let result = match fetched {
+                Ok(path) => {
+                    let path = path.display().to_string();
+                    engine.downloads.update(&id, |job| {
+                        job.status = "loading".to_string();
+                        job.path = Some(path);
+                    }).await;
+                    // The files are cached now, so loading finds them without downloading again
+                    engine.load_model(&req.kind, &req.model, None, options).await
+                }
+                Err(e) => Err(e),
+            };
+            let status = match &result {
+                Ok(_) => "completed",
+                Err(_) if cancel.is_cancelled() => "cancelled",
+                Err(_) => "failed",
+            };
+            counter!("model_download_jobs_total", 1, "status" => status);
+            engine.downloads.update(&id, |job| {
+                job.status = status.to_string();
+                job.error = result.err().filter(|_| status == "failed");
+                job.finished = Some(now_secs());
+            }).await;
+        });
+        Ok(job)
+    }
+
+    pub fn downloads(&self) -> &DownloadStore {
+        &self.downloads
+    }
+}
@@ -0,0 +1,220 @@
+use base64::Engine as _;
+
+use crate::api::dto::{
+    ContentPart, EmbeddingInput, EmbeddingInputItem, EmbeddingUsage, EmbeddingVector, EmbeddingsRequest, EncodingFormat, SimilarityInput,
+    SimilarityRequest, SimilarityResponse,
+};
+use crate::engine::{CoreEngine, EngineError};
+use crate::runtime::{approximate_token_count, image::decode_image, image_fetch, EmbeddingRuntime, RuntimeError};
+
+// Usage charged per image input, the price of one low-detail image in OpenAI's vision models
+const IMAGE_INPUT_TOKENS: u32 = 85;
+
+/// Embeddings input normalized to one batch per request, whatever shape the client sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmbeddingBatch {
+    Texts(Vec<String>),
+    Tokens(Vec<Vec<u32>>),
+    // Texts and images in request order; never coalesced with other requests
+    Multimodal(Vec<EmbeddingItem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmbeddingItem {
+    Text(String),
+    // http(s) or data: URL
+    Image(String),
+}
+
+impl From<EmbeddingInput> for EmbeddingBatch {
+    fn from(input: EmbeddingInput) -> Self {
+        match input {
+            EmbeddingInput::Text(text) => EmbeddingBatch::Texts(vec![text]),
+            EmbeddingInput::TextBatch(texts) => EmbeddingBatch::Texts(texts),
+            EmbeddingInput::Tokens(ids) => EmbeddingBatch::Tokens(vec![ids]),
+            EmbeddingInput::TokensBatch(batch) => EmbeddingBatch::Tokens(batch),
+            EmbeddingInput::Mixed(items) => EmbeddingBatch::Multimodal(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        EmbeddingInputItem::Text(text) | EmbeddingInputItem::Part(ContentPart::Text { text }) => EmbeddingItem::Text(text),
+                        EmbeddingInputItem::Part(ContentPart::ImageUrl { image_url }) => EmbeddingItem::Image(image_url.url),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl EmbeddingBatch {
+    pub fn len(&self) -> usize {
+        match self {
+            EmbeddingBatch::Texts(texts) => texts.len(),
+            EmbeddingBatch::Tokens(batch) => batch.len(),
+            EmbeddingBatch::Multimodal(items) => items.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Token estimate without a tokenizer, for scheduling.
+    pub fn approximate_tokens(&self) -> u32 {
+        match self {
+            EmbeddingBatch::Texts(texts) => texts.iter().map(|t| approximate_token_count(t)).sum(),
+            EmbeddingBatch::Tokens(batch) => batch.iter().map(|ids| ids.len() as u32).sum(),
+            EmbeddingBatch::Multimodal(items) => items.iter().map(|item| match item {
+                EmbeddingItem::Text(text) => approximate_token_count(text),
+                EmbeddingItem::Image(_) => IMAGE_INPUT_TOKENS,
+            }).sum(),
+        }
+    }
+
+    /// Exact token count as seen by `runtime`.
+    pub fn count_tokens(&self, runtime: &dyn EmbeddingRuntime) -> u32 {
+        match self {
+            EmbeddingBatch::Texts(texts) => texts.iter().map(|t| runtime.count_tokens(t)).sum(),
+            EmbeddingBatch::Tokens(batch) => batch.iter().map(|ids| ids.len() as u32).sum(),
+            EmbeddingBatch::Multimodal(items) => items.iter().map(|item| match item {
+                EmbeddingItem::Text(text) => runtime.count_tokens(text),
+                EmbeddingItem::Image(_) => IMAGE_INPUT_TOKENS,
+            }).sum(),
+        }
+    }
+
+    pub async fn embed(&self, runtime: &dyn EmbeddingRuntime) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        match self {
+            EmbeddingBatch::Texts(texts) => runtime.embed(texts).await,
+            EmbeddingBatch::Tokens(batch) => runtime.embed_tokens(batch).await,
+            EmbeddingBatch::Multimodal(items) => embed_multimodal(items, runtime).await,
+        }
+    }
+}
+
+// One text call and one image call, with the vectors put back in input order
+async fn embed_multimodal(items: &[EmbeddingItem], runtime: &dyn EmbeddingRuntime) -> Result<Vec<Vec<f32>>, RuntimeError> {
+    let mut texts = Vec::new();
+    let mut urls = Vec::new();
+    for item in items {
+        match item {
+            EmbeddingItem::Text(text) => texts.push(text.clone()),
+            EmbeddingItem::Image(url) => urls.push(url.clone()),
+        }
+    }
+    let text_vectors = if texts.is_empty() { Vec::new() } else { runtime.embed(&texts).await? };
+    let image_vectors = if urls.is_empty() {
+        Vec::new()
+    } else {
+        let bytes = image_fetch::global().fetch_all(&urls).await.map_err(RuntimeError::InvalidInput)?;
+        // Decoding large images is real work; keep it off the async workers
+        let images = tokio::task::spawn_blocking(move || {
+            bytes
+                .iter()
+                .enumerate()
+                .map(|(i, bytes)| decode_image(bytes).map_err(|e| RuntimeError::InvalidInput(format!("image {}: {}", i, e))))
+                .collect::<Result<Vec<_>, RuntimeError>>()
+        })
+        .await
+        .map_err(|e| RuntimeError::Backend(format!("image decode task failed: {}", e)))??;
+        runtime.embed_images(&images).await?
+    };
+    if text_vectors.len() != texts.len() || image_vectors.len() != urls.len() {
+        let returned = text_vectors.len() + image_vectors.len();
+        return Err(RuntimeError::Backend(format!("Runtime returned {} embeddings for {} inputs", returned, items.len())));
+    }
+    let (mut text_vectors, mut image_vectors) = (text_vectors.into_iter(), image_vectors.into_iter());
+    Ok(items
+        .iter()
+        .map(|item| match item {
+            EmbeddingItem::Text(_) => text_vectors.next(),
+            EmbeddingItem::Image(_) => image_vectors.next(),
+        })
+        .map(|v| v.expect("counts checked above"))
+        .collect())
+}
+
+/// Keeps the leading `dimensions` components and L2-normalizes the result, which is how
+/// Matryoshka-trained models expose smaller embeddings.
+pub fn truncate_dimensions(mut vector: Vec<f32>, dimensions: usize) -> Result<Vec<f32>, String> {
+    if dimensions == 0 || dimensions > vector.len() {
+        return Err(format!("dimensions must be between 1 and {}", vector.len()));
+    }
+    vector.truncate(dimensions);
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    Ok(vector)
+}
+
+/// Encodes one embedding in the representation the client asked for.
+pub fn encode_embedding(vector: Vec<f32>, format: EncodingFormat) -> EmbeddingVector {
+    match format {
+        EncodingFormat::Float => EmbeddingVector::Float(vector),
+        EncodingFormat::Base64 => {
+            let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+            EmbeddingVector::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+        }
+    }
+}
+
+/// Cosine similarity; zero when either vector has no magnitude.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+impl CoreEngine {
+    /// Pairwise cosine similarities between two sets, embedding any text sets with `request.model`.
+    pub async fn process_similarity_request(&self, request: SimilarityRequest) -> Result<SimilarityResponse, EngineError> {
+        let mut prompt_tokens = 0;
+        let mut vectors = Vec::with_capacity(2);
+        for input in [request.a, request.b] {
+            match input {
+                SimilarityInput::Vectors(v) => vectors.push(v),
+                SimilarityInput::Texts(texts) => {
+                    let response = self
+                        .process_embedding_request(EmbeddingsRequest {
+                            model: request.model.clone(),
+                            input: EmbeddingInput::TextBatch(texts),
+                            encoding_format: EncodingFormat::Float,
+                            dimensions: None,
+                            client_id: request.client_id.clone(),
+                            priority: request.priority,
+                        })
+                        .await?;
+                    prompt_tokens += response.usage.prompt_tokens;
+                    vectors.push(
+                        response
+                            .data
+                            .into_iter()
+                            .map(|d| match d.embedding {
+                                EmbeddingVector::Float(v) => v,
+                                EmbeddingVector::Base64(_) => unreachable!("requested float encoding"),
+                            })
+                            .collect(),
+                    );
+                }
+            }
+        }
+        let (a, b) = (&vectors[0], &vectors[1]);
+        if let (Some(x), Some(y)) = (a.first(), b.first())
+            && (a.iter().any(|v| v.len() != x.len()) || b.iter().any(|v| v.len() != x.len()) || x.len() != y.len())
+        {
+            return Err(EngineError::InvalidInput("All vectors must have the same dimension".to_string()));
+        }
+        let data = a.iter().map(|x| b.iter().map(|y| cosine_similarity(x, y)).collect()).collect();
+        Ok(SimilarityResponse {
+            object: "similarity".to_string(),
+            model: request.model,
+            data,
+            usage: EmbeddingUsage { prompt_tokens, total_tokens: prompt_tokens },
+        })
+    }
+}
@@ -0,0 +1,45 @@
+//! Errors of the engine's request API, by kind, so each caller can answer them its own way
+//! (HTTP status, gRPC code, batch result) without reading the message.
+
+use crate::engine::STREAM_VIA_SENDER;
+use crate::runtime::RuntimeError;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EngineError {
+    /// The request names a model that is not loaded, or does not serve the endpoint.
+    #[error("{0}")]
+    ModelNotFound(String),
+    /// The request names something else the engine keeps (a dataset, a run) that does not exist.
+    #[error("{0}")]
+    NotFound(String),
+    /// Turned away for now: the queue is full, no worker was free in time, the server is
+    /// shutting down or short of memory, or the model failed its health checks.
+    #[error("{0}")]
+    Overloaded(String),
+    /// The caller's API key has its share of the queue waiting already.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// Generation ran past its timeout.
+    #[error("{0}")]
+    Timeout(String),
+    /// The request itself is at fault and would fail again as sent.
+    #[error("{0}")]
+    InvalidInput(String),
+    /// The runtime failed to serve a valid request.
+    #[error("{0}")]
+    Backend(String),
+    /// Not a failure: a streaming chat request was handed to its sender, which receives the
+    /// chunks and any error.
+    #[error("{}", STREAM_VIA_SENDER)]
+    Streaming,
+}
+
+// What the runtime reported, by whose fault it was
+impl From<RuntimeError> for EngineError {
+    fn from(e: RuntimeError) -> Self {
+        match e {
+            RuntimeError::InvalidInput(e) => EngineError::InvalidInput(e),
+            RuntimeError::Backend(e) => EngineError::Backend(e),
+        }
+    }
+}
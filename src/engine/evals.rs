@@ -0,0 +1,204 @@
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use metrics::counter;
+
+use crate::api::dto::{
+    ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent, CreateEvalDatasetRequest,
+    CreateEvalRunRequest, EvalCase, EvalCaseResult, EvalDatasetInfo, EvalGrader, EvalHistoryEntry,
+    EvalHistoryResponse, EvalRun, Priority,
+};
+use crate::engine::{registry::model_not_found, CoreEngine, EngineError};
+
+struct EvalDataset {
+    info: EvalDatasetInfo,
+    cases: Vec<EvalCase>,
+}
+
+// In-memory store of golden-set datasets and their run history (no persistence)
+#[derive(Default)]
+pub struct EvalStore {
+    datasets: RwLock<HashMap<String, EvalDataset>>,
+    runs: RwLock<Vec<EvalRun>>,
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl EvalStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn create_dataset(&self, req: CreateEvalDatasetRequest) -> Result<EvalDatasetInfo, String> {
+        if req.cases.is_empty() {
+            return Err("eval dataset must contain at least one case".to_string());
+        }
+        for (i, case) in req.cases.iter().enumerate() {
+            if let EvalGrader::Regex { pattern } = &case.grader {
+                regex::Regex::new(pattern).map_err(|e| format!("case {}: invalid regex: {}", i, e))?;
+            }
+        }
+        let info = EvalDatasetInfo {
+            id: format!("evalds-{}", uuid::Uuid::new_v4()),
+            name: req.name,
+            cases: req.cases.len(),
+            created: now_secs(),
+        };
+        self.datasets.write().await.insert(info.id.clone(), EvalDataset { info: info.clone(), cases: req.cases });
+        Ok(info)
+    }
+
+    pub async fn list_datasets(&self) -> Vec<EvalDatasetInfo> {
+        let mut list: Vec<EvalDatasetInfo> = self.datasets.read().await.values().map(|d| d.info.clone()).collect();
+        list.sort_by_key(|d| d.created);
+        list
+    }
+
+    pub async fn get_run(&self, run_id: &str) -> Option<EvalRun> {
+        self.runs.read().await.iter().find(|r| r.id == run_id).cloned()
+    }
+
+    pub async fn history(&self, dataset_id: &str) -> Result<EvalHistoryResponse, String> {
+        if !self.datasets.read().await.contains_key(dataset_id) {
+            return Err(format!("Eval dataset {} not found", dataset_id));
+        }
+        let runs = self.runs.read().await;
+        let mut last_score: HashMap<&str, f32> = HashMap::new();
+        let mut entries = Vec::new();
+        for run in runs.iter().filter(|r| r.dataset_id == dataset_id) {
+            let delta = match (run.score, last_score.get(run.model.as_str())) {
+                (Some(score), Some(prev)) => Some(score - prev),
+                _ => None,
+            };
+            if let Some(score) = run.score {
+                last_score.insert(run.model.as_str(), score);
+            }
+            entries.push(EvalHistoryEntry {
+                run_id: run.id.clone(),
+                model: run.model.clone(),
+                status: run.status.clone(),
+                created: run.created,
+                score: run.score,
+                delta,
+            });
+        }
+        Ok(EvalHistoryResponse { dataset_id: dataset_id.to_string(), runs: entries })
+    }
+
+    async fn update_run<F: FnOnce(&mut EvalRun)>(&self, run_id: &str, f: F) {
+        if let Some(run) = self.runs.write().await.iter_mut().find(|r| r.id == run_id) {
+            f(run);
+        }
+    }
+}
+
+impl CoreEngine {
+    /// Starts an eval run in the background and returns its initial ("running") record.
+    /// Cases are executed one at a time so an eval never holds more than a single worker permit.
+    pub async fn start_eval_run(self: &Arc<Self>, dataset_id: &str, req: CreateEvalRunRequest) -> Result<EvalRun, EngineError> {
+        let cases = {
+            let datasets = self.evals.datasets.read().await;
+            let ds = datasets.get(dataset_id).ok_or_else(|| EngineError::NotFound(format!("Eval dataset {} not found", dataset_id)))?;
+            ds.cases.clone()
+        };
+        let models = self.list_models().await;
+        if !models.llm.contains(&req.model) && !models.multimodal.contains(&req.model) {
+            return Err(EngineError::ModelNotFound(model_not_found(&req.model)));
+        }
+
+        let run = EvalRun {
+            id: format!("evalrun-{}", uuid::Uuid::new_v4()),
+            dataset_id: dataset_id.to_string(),
+            model: req.model.clone(),
+            status: "running".to_string(),
+            created: now_secs(),
+            completed: None,
+            total: cases.len(),
+            passed: 0,
+            score: None,
+            min_score: req.min_score,
+            gate_passed: None,
+            results: Vec::new(),
+        };
+        self.evals.runs.write().await.push(run.clone());
+
+        let engine = self.clone();
+        let run_id = run.id.clone();
+        tokio::spawn(async move {
+            counter!("eval_runs_total", 1);
+            for (index, case) in cases.iter().enumerate() {
+                let result = engine.run_eval_case(index, case, &req).await;
+                engine.evals.update_run(&run_id, |run| {
+                    if result.passed { run.passed += 1; }
+                    run.results.push(result);
+                }).await;
+                // Yield between cases so interactive traffic gets ahead of us in the queue
+                tokio::task::yield_now().await;
+            }
+            let mut final_score = 0.0;
+            engine.evals.update_run(&run_id, |run| {
+                let total_score: f32 = run.results.iter().map(|r| r.score).sum();
+                let score = total_score / run.total.max(1) as f32;
+                final_score = score;
+                run.score = Some(score);
+                run.gate_passed = run.min_score.map(|min| score >= min);
+                run.status = "completed".to_string();
+                run.completed = Some(now_secs());
+            }).await;
+            engine.canaries.record_eval_score(&req.model, final_score).await;
+        });
+        Ok(run)
+    }
+
+    async fn run_eval_case(&self, index: usize, case: &EvalCase, req: &CreateEvalRunRequest) -> EvalCaseResult {
+        let output = match self.eval_generate(&req.model, case.system.as_deref(), &case.prompt, req.max_tokens).await {
+            Ok(output) => output,
+            Err(e) => return EvalCaseResult { index, output: String::new(), score: 0.0, passed: false, error: Some(e.to_string()) },
+        };
+        let graded = match &case.grader {
+            EvalGrader::ExactMatch { expected } => Ok(output.trim() == expected.trim()),
+            EvalGrader::Regex { pattern } => regex::Regex::new(pattern)
+                .map(|re| re.is_match(&output))
+                .map_err(|e| format!("invalid regex: {}", e)),
+            EvalGrader::LlmJudge { model, rubric } => {
+                let judge_prompt = format!(
+                    "{}\n\nPrompt:\n{}\n\nResponse:\n{}\n\nAnswer with PASS or FAIL.",
+                    rubric, case.prompt, output
+                );
+                self.eval_generate(model, None, &judge_prompt, Some(8)).await
+                    .map(|verdict| verdict.trim().to_ascii_uppercase().starts_with("PASS"))
+                    .map_err(|e| e.to_string())
+            }
+        };
+        match graded {
+            Ok(passed) => EvalCaseResult { index, output, score: if passed { 1.0 } else { 0.0 }, passed, error: None },
+            Err(e) => EvalCaseResult { index, output, score: 0.0, passed: false, error: Some(e) },
+        }
+    }
+
+    async fn eval_generate(&self, model: &str, system: Option<&str>, prompt: &str, max_tokens: Option<u32>) -> Result<String, EngineError> {
+        let mut messages = Vec::new();
+        if let Some(system) = system {
+            messages.push(ChatCompletionMessage { role: "system".to_string(), content: ChatMessageContent::Text(system.to_string()) });
+        }
+        messages.push(ChatCompletionMessage { role: "user".to_string(), content: ChatMessageContent::Text(prompt.to_string()) });
+        let request = ChatCompletionRequest {
+            model: model.to_string(),
+            messages,
+            max_tokens,
+            temperature: Some(0.0),
+            client_id: Some("admin:evals".to_string()),
+            endpoint: Some("evals"),
+            priority: Some(Priority::Low),
+            ..Default::default()
+        };
+        let response = self.process_chat_request(request, None, CancellationToken::new()).await?;
+        Ok(response.choices.first().map(|c| c.message.content.clone()).unwrap_or_default())
+    }
+
+    pub fn evals(&self) -> &EvalStore {
+        &self.evals
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::{
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::Instant,
+};
+use tokio::sync::broadcast;
+
+use crate::api::dto::Priority;
+use crate::engine::metering::UsageMeter;
+
+// Slow subscribers past this many buffered events miss the oldest ones
+const EVENT_BUFFER: usize = 1024;
+
+/// Engine events published on /admin/events. `type` is the event name; fields vary by event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    ModelLoaded { model: String, kind: String },
+    ModelUnloaded { model: String, kind: String, reason: String },
+    RequestStarted { request_id: String, endpoint: String, model: String, client: String, priority: Priority },
+    RequestFinished { request_id: String, endpoint: String, model: String, outcome: String, latency_ms: u64 },
+    CacheEvicted { cache: String, reason: String },
+    CanaryRolledBack { model: String, canary: String, reason: String },
+    // A fallback chain's request went past its earlier backends for `reason`
+    FallbackServed { model: String, backend: String, reason: String },
+    LoadShedding { active: bool },
+    // A model failed HEALTH_CHECK_FAILURES health checks in a row, or passes (or was reloaded) again
+    ModelHealth { model: String, healthy: bool, #[serde(skip_serializing_if = "Option::is_none")] error: Option<String> },
+    SafetyStrike { subject: String, strikes: usize, action: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventEnvelope {
+    pub seq: u64,
+    pub timestamp_ms: u128,
+    #[serde(flatten)]
+    pub event: EngineEvent,
+}
+
+/// Fan-out of engine events to any number of subscribers. Publishing never blocks and is a
+/// no-op when nobody is listening.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<EventEnvelope>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender, seq: Arc::new(AtomicU64::new(0)) }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: EngineEvent) {
+        let envelope = EventEnvelope {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            timestamp_ms: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
+            event,
+        };
+        let _ = self.sender.send(envelope);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes `RequestStarted` now and `RequestFinished` when the returned tracker is dropped.
+    pub fn track_request(&self, endpoint: &str, model: &str, client: &str, priority: Priority) -> RequestTracker {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        self.publish(EngineEvent::RequestStarted {
+            request_id: request_id.clone(),
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            client: client.to_string(),
+            priority,
+        });
+        RequestTracker {
+            bus: self.clone(),
+            request_id,
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            client: client.to_string(),
+            outcome: "error",
+            start: Instant::now(),
+            tokens: (0, 0),
+            meter: None,
+        }
+    }
+}
+
+pub struct RequestTracker {
+    bus: EventBus,
+    request_id: String,
+    endpoint: String,
+    model: String,
+    client: String,
+    outcome: &'static str,
+    start: Instant,
+    // Prompt and completion tokens
+    tokens: (u32, u32),
+    meter: Option<Arc<UsageMeter>>,
+}
+
+impl RequestTracker {
+    /// Outcome reported on drop: "ok", "cancelled", "timeout"; "error" unless set.
+    pub fn set_outcome(&mut self, outcome: &'static str) {
+        self.outcome = outcome;
+    }
+
+    /// Prompt and completion tokens the request used, for metering.
+    pub fn set_tokens(&mut self, prompt: u32, completion: u32) {
+        self.tokens = (prompt, completion);
+    }
+
+    /// Also records the request in `meter` when dropped.
+    pub fn metered(mut self, meter: &Arc<UsageMeter>) -> Self {
+        self.meter = Some(meter.clone());
+        self
+    }
+}
+
+impl Drop for RequestTracker {
+    fn drop(&mut self) {
+        let latency_ms = self.start.elapsed().as_millis() as u64;
+        if let Some(meter) = &self.meter {
+            meter.record(&self.client, &self.model, self.outcome == "ok", self.tokens.0, self.tokens.1, latency_ms);
+        }
+        self.bus.publish(EngineEvent::RequestFinished {
+            request_id: std::mem::take(&mut self.request_id),
+            endpoint: std::mem::take(&mut self.endpoint),
+            model: std::mem::take(&mut self.model),
+            outcome: self.outcome.to_string(),
+            latency_ms,
+        });
+    }
+}
@@ -0,0 +1,146 @@
+//! Fallback models: a model loaded with `fallback_model` gets a failover chain of itself and
+//! that model in the `FallbackRouter`, and chat requests for it go down the chain when it fails,
+//! times out before producing any text or is overloaded. Requests on the chain's backends are
+//! counted like those of any fallback chain. Only the chain is taken, so fallbacks of fallbacks
+//! are not followed and two models naming each other cannot loop.
+
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    api::dto::{ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ChatStreamError},
+    engine::{fallback::FallbackRuntime, CoreEngine, EngineError, EngineRequest},
+};
+
+impl CoreEngine {
+    // Answers on the first backend of `chain` that serves the request, naming the model it
+    // stands in for
+    pub(super) async fn respond_with_fallback(
+        &self,
+        request: ChatCompletionRequest,
+        chain: Arc<FallbackRuntime>,
+        cancel: CancellationToken,
+    ) -> Result<ChatCompletionResponse, EngineError> {
+        let mut failures = Vec::new();
+        let mut failed = None;
+        for (i, backend) in chain.backends().iter().enumerate() {
+            let name = backend.name();
+            let attempt = ChatCompletionRequest { model: name.to_string(), ..request.clone() };
+            match self.respond(attempt, cancel.clone()).await {
+                Ok(mut response) => {
+                    chain.record(backend, "served");
+                    chain.served_after(name, &failures);
+                    if i > 0 {
+                        response.fallback_from = Some(chain.model().to_string());
+                    }
+                    return Ok(response);
+                }
+                // Unloaded since the chain was set up
+                Err(EngineError::ModelNotFound(_)) if i > 0 => {
+                    chain.record(backend, "not_loaded");
+                    failures.push(format!("{}: not loaded", name));
+                }
+                Err(e) if falls_back(&e, &cancel) => {
+                    chain.record(backend, "error");
+                    tracing::warn!("{} failed ({}); trying the next model of its fallback chain", name, e);
+                    failures.push(format!("{}: {}", name, e));
+                    failed = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(failed.unwrap_or_else(|| EngineError::Backend(format!("no model could serve the request ({})", failures.join("; ")))))
+    }
+
+    // Streams from the first backend of `chain` that sends text. Until then nothing reaches
+    // `stream_sender`, so the stream only starts once the first text is known to come from the
+    // model that will finish it.
+    pub(super) async fn stream_with_fallback(
+        &self,
+        request: ChatCompletionRequest,
+        chain: Arc<FallbackRuntime>,
+        stream_sender: mpsc::Sender<String>,
+        cancel: CancellationToken,
+    ) -> Result<(), EngineError> {
+        let mut failures = Vec::new();
+        let mut failed = None;
+        for (i, backend) in chain.backends().iter().enumerate() {
+            let name = backend.name();
+            let attempt = ChatCompletionRequest { model: name.to_string(), ..request.clone() };
+            let (tx, mut rx) = mpsc::channel(stream_sender.max_capacity());
+            // Errors the queue returns, rather than ones that arrive on the stream
+            let queued = self.enqueue(EngineRequest::ChatCompletion { request: attempt, response_sender: None, stream_sender: Some(tx), cancel: cancel.clone() }).await;
+            let (error, streamed) = match queued {
+                Err(e) => (e, false),
+                Ok(()) => {
+                    // The role chunk waits to see what follows it
+                    let mut held = Vec::new();
+                    let error = loop {
+                        let Some(chunk) = rx.recv().await else { break None };
+                        if let Some(e) = ChatStreamError::parse(&chunk)
+                            && (falls_back(&e, &cancel) || i > 0 && matches!(e, EngineError::ModelNotFound(_)))
+                        {
+                            break Some(e);
+                        }
+                        let role_only = serde_json::from_str::<ChatCompletionChunk>(&chunk)
+                            .is_ok_and(|c| c.choices.iter().all(|choice| choice.delta.role.is_some() && choice.delta.content.is_none()));
+                        held.push(chunk);
+                        if !role_only {
+                            break None;
+                        }
+                    };
+                    let Some(error) = error else {
+                        // Text, the end of the stream or an error to pass on: this model answers
+                        chain.record(backend, "served");
+                        chain.served_after(name, &failures);
+                        tokio::spawn(async move {
+                            for chunk in held {
+                                if stream_sender.send(chunk).await.is_err() {
+                                    return;
+                                }
+                            }
+                            // Returning drops the engine's side when the client goes away, which stops generation
+                            while let Some(chunk) = rx.recv().await {
+                                if stream_sender.send(chunk).await.is_err() {
+                                    return;
+                                }
+                            }
+                        });
+                        return Ok(());
+                    };
+                    (error, true)
+                }
+            };
+            match error {
+                EngineError::ModelNotFound(_) if i > 0 => {
+                    chain.record(backend, "not_loaded");
+                    failures.push(format!("{}: not loaded", name));
+                }
+                e if falls_back(&e, &cancel) => {
+                    chain.record(backend, "error");
+                    tracing::warn!("{} failed before streaming ({}); trying the next model of its fallback chain", name, e);
+                    failures.push(format!("{}: {}", name, e));
+                    failed = Some((e, streamed));
+                }
+                e => return Err(e),
+            }
+        }
+        // The last failure reaches the client the way it came
+        match failed {
+            Some((e, true)) => {
+                let _ = stream_sender.send(ChatStreamError::chunk(e)).await;
+                Ok(())
+            }
+            Some((e, false)) => Err(e),
+            None => Err(EngineError::Backend(format!("no model could serve the request ({})", failures.join("; ")))),
+        }
+    }
+}
+
+// Whether a request that failed with `error` goes on to the next model: when the model failed,
+// timed out or was overloaded, but not when the client went away or the request itself is at
+// fault
+fn falls_back(error: &EngineError, cancel: &CancellationToken) -> bool {
+    !cancel.is_cancelled() && matches!(error, EngineError::Backend(_) | EngineError::Timeout(_) | EngineError::Overloaded(_))
+}
@@ -0,0 +1,376 @@
+use async_trait::async_trait;
+use metrics::counter;
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, Weak},
+};
+use tokio::sync::{mpsc, RwLock};
+
+use crate::api::dto::{ChatCompletionMessage, ChatMessageContent, CreateFallbackRequest, FallbackBackend, FallbackBackendStats, FallbackChain};
+use crate::engine::{
+    continuous::ContinuousBatcher,
+    events::{EngineEvent, EventBus},
+    registry::{model_not_found, Entries, ModelEntry, ModelRegistry, Runtimes},
+    CoreEngine, EngineError,
+};
+use crate::runtime::{approximate_token_count, prompt::{parse_chatml, ChatTemplate}, GenerationOptions, LlmRuntime, RuntimeError};
+
+type TemplateMap = RwLock<HashMap<String, Arc<ChatTemplate>>>;
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+pub(super) struct Backend {
+    spec: FallbackBackend,
+    inflight: AtomicU64,
+    served: AtomicU64,
+    errors: AtomicU64,
+    skipped: AtomicU64,
+}
+
+// A generation running on a backend; gives its place back when dropped
+struct Slot<'a>(&'a AtomicU64);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Backend {
+    fn new(spec: FallbackBackend) -> Self {
+        Self { spec, inflight: AtomicU64::new(0), served: AtomicU64::new(0), errors: AtomicU64::new(0), skipped: AtomicU64::new(0) }
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.spec.model
+    }
+
+    // None when the backend is already running `max_inflight` generations
+    fn admit(&self) -> Option<Slot<'_>> {
+        let max = self.spec.max_inflight.map(u64::from);
+        self.inflight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| max.is_none_or(|max| n < max).then_some(n + 1))
+            .ok()
+            .map(|_| Slot(&self.inflight))
+    }
+
+    fn stats(&self) -> FallbackBackendStats {
+        FallbackBackendStats {
+            model: self.spec.model.clone(),
+            inflight: self.inflight.load(Ordering::Relaxed),
+            served: self.served.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Serves a gateway model name from an ordered list of backends (say a local model first and
+/// a cloud provider second). A request goes to the first backend that is loaded and has room;
+/// if that backend fails before producing any text, the next one gets the request. Which
+/// backend answered is counted per backend and in `fallback_requests_total`.
+pub struct FallbackRuntime {
+    model: String,
+    created: u64,
+    backends: Vec<Backend>,
+    // Weak: the registry holds this runtime
+    registry: Weak<ModelRegistry>,
+    chat_templates: Arc<TemplateMap>,
+    batcher: Arc<ContinuousBatcher>,
+    events: EventBus,
+}
+
+impl FallbackRuntime {
+    pub(super) fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The backends in the order they are tried.
+    pub(super) fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+
+    fn chain(&self) -> FallbackChain {
+        FallbackChain {
+            model: self.model.clone(),
+            backends: self.backends.iter().map(|b| b.spec.clone()).collect(),
+            created: self.created,
+            stats: self.backends.iter().map(Backend::stats).collect(),
+        }
+    }
+
+    // The gateway renders ChatML; backends with their own chat template get the conversation
+    // re-rendered in it
+    async fn prompt_for(&self, backend: &str, prompt: &str, options: &GenerationOptions) -> Result<(String, GenerationOptions), String> {
+        let Some(template) = self.chat_templates.read().await.get(backend).cloned() else {
+            return Ok((prompt.to_string(), options.clone()));
+        };
+        let messages: Vec<ChatCompletionMessage> = parse_chatml(prompt)
+            .ok_or("prompt is not ChatML")?
+            .into_iter()
+            .map(|(role, content)| ChatCompletionMessage { role, content: ChatMessageContent::Text(content) })
+            .collect();
+        let rendered = template.render(&messages)?;
+        Ok((rendered.prompt, GenerationOptions { keep_prefix: rendered.keep_prefix, content_spans: rendered.content_spans, ..options.clone() }))
+    }
+
+    /// Counts how a request went on `backend`: `served`, `error`, or skipped as `overloaded` or
+    /// `not_loaded`.
+    pub(super) fn record(&self, backend: &Backend, outcome: &'static str) {
+        let count = match outcome {
+            "served" => &backend.served,
+            "error" => &backend.errors,
+            _ => &backend.skipped,
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+        counter!("fallback_requests_total", 1, "model" => self.model.clone(), "backend" => backend.name().to_string(), "outcome" => outcome);
+    }
+
+    /// Reports `backend` answering after the earlier ones failed as `failures` says.
+    pub(super) fn served_after(&self, backend: &str, failures: &[String]) {
+        if failures.is_empty() {
+            return;
+        }
+        tracing::info!("{} served by fallback {} ({})", self.model, backend, failures.join("; "));
+        self.events.publish(EngineEvent::FallbackServed { model: self.model.clone(), backend: backend.to_string(), reason: failures.join("; ") });
+    }
+}
+
+#[async_trait]
+impl LlmRuntime for FallbackRuntime {
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.generate_stream(prompt, options, tx), collect);
+        result.map(|_| text)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let mut failures = Vec::new();
+        for backend in &self.backends {
+            let name = backend.spec.model.as_str();
+            // An idle backend is loaded again for the request
+            let registry = self.registry.upgrade();
+            let _in_use = registry.as_ref().map(|registry| registry.in_use(name));
+            let acquired = match &registry {
+                Some(registry) => registry.acquire(name).await,
+                None => Ok(None),
+            };
+            let runtime = match acquired {
+                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.llm),
+                Err(e) => {
+                    tracing::warn!("fallback {}: {}", self.model, e);
+                    None
+                }
+            };
+            let Some(runtime) = runtime else {
+                self.record(backend, "not_loaded");
+                failures.push(format!("{}: not loaded", name));
+                continue;
+            };
+            let Some(_slot) = backend.admit() else {
+                self.record(backend, "overloaded");
+                failures.push(format!("{}: at capacity", name));
+                continue;
+            };
+            let (prompt, options) = self.prompt_for(name, prompt, options).await?;
+
+            // Text already sent to the client cannot be taken back, so only a backend that
+            // failed before its first piece is retried elsewhere
+            let (tx, mut rx) = mpsc::channel::<String>(64);
+            let forward = async {
+                let mut sent = false;
+                while let Some(piece) = rx.recv().await {
+                    sent = true;
+                    if sender.send(piece).await.is_err() {
+                        break; // receiver dropped
+                    }
+                }
+                sent
+            };
+            let (result, sent) = tokio::join!(self.batcher.generate_stream(name, runtime.as_ref(), &prompt, &options, tx, None), forward);
+            match result {
+                Ok(()) => {
+                    self.record(backend, "served");
+                    self.served_after(name, &failures);
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.record(backend, "error");
+                    if sent || options.cancel.is_cancelled() {
+                        return Err(e);
+                    }
+                    tracing::warn!("{} backend {} failed: {}", self.model, name, e);
+                    failures.push(format!("{}: {}", name, e));
+                }
+            }
+        }
+        Err(RuntimeError::Backend(format!("no backend of {} could serve the request ({})", self.model, failures.join("; "))))
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        // The first loaded backend's tokenizer, when the registry is free to look at
+        let first = self.registry.upgrade().and_then(|registry| self.backends.iter().find_map(|b| registry.try_llm(&b.spec.model)));
+        first.map_or_else(|| approximate_token_count(text), |runtime| runtime.count_tokens(text))
+    }
+}
+
+/// The fallback chains in place, each served by a `FallbackRuntime` registered under its
+/// gateway name, and the failovers of models loaded with a `fallback_model`: chains of the model
+/// and its fallback that the engine runs requests for the model through, registered nowhere.
+pub struct FallbackRouter {
+    chains: Mutex<HashMap<String, Arc<FallbackRuntime>>>,
+    failovers: Mutex<HashMap<String, Arc<FallbackRuntime>>>,
+    registry: Weak<ModelRegistry>,
+    chat_templates: Arc<TemplateMap>,
+    batcher: Arc<ContinuousBatcher>,
+    events: EventBus,
+}
+
+impl FallbackRouter {
+    pub(crate) fn new(registry: &Arc<ModelRegistry>, chat_templates: Arc<TemplateMap>, batcher: Arc<ContinuousBatcher>, events: EventBus) -> Self {
+        Self {
+            chains: Mutex::new(HashMap::new()),
+            failovers: Mutex::new(HashMap::new()),
+            registry: Arc::downgrade(registry),
+            chat_templates,
+            batcher,
+            events,
+        }
+    }
+
+    fn runtime(&self, model: String, backends: Vec<FallbackBackend>) -> Arc<FallbackRuntime> {
+        Arc::new(FallbackRuntime {
+            model,
+            created: now_secs(),
+            backends: backends.into_iter().map(Backend::new).collect(),
+            registry: self.registry.clone(),
+            chat_templates: self.chat_templates.clone(),
+            batcher: self.batcher.clone(),
+            events: self.events.clone(),
+        })
+    }
+
+    /// Checks `req` against the registered `models` and registers its gateway there. A chain
+    /// replaces an earlier chain of the same name but never a model.
+    pub(crate) fn insert(&self, req: CreateFallbackRequest, models: &mut Entries) -> Result<FallbackChain, EngineError> {
+        let mut chains = self.chains.lock().unwrap();
+        if req.backends.is_empty() {
+            return Err(EngineError::InvalidInput("a fallback chain needs at least one backend".to_string()));
+        }
+        if models.contains_key(&req.model) && !chains.contains_key(&req.model) {
+            return Err(EngineError::InvalidInput(format!("{} is already served by a model; pick another gateway name", req.model)));
+        }
+        if chains.values().any(|chain| chain.backends.iter().any(|b| b.spec.model == req.model)) {
+            return Err(EngineError::InvalidInput(format!("{} is a backend of another fallback chain", req.model)));
+        }
+        for (i, backend) in req.backends.iter().enumerate() {
+            if backend.model == req.model || req.backends[..i].iter().any(|b| b.model == backend.model) {
+                return Err(EngineError::InvalidInput(format!("backend {} appears twice in the chain", backend.model)));
+            }
+            if backend.max_inflight == Some(0) {
+                return Err(EngineError::InvalidInput(format!("max_inflight of {} must be positive", backend.model)));
+            }
+            // Chains do not nest, so a request never loops between gateways
+            if chains.contains_key(&backend.model) {
+                return Err(EngineError::InvalidInput(format!("backend {} is itself a fallback chain", backend.model)));
+            }
+            if models.get(&backend.model).is_none_or(|entry| entry.runtimes.llm.is_none()) {
+                return Err(EngineError::ModelNotFound(model_not_found(&backend.model)));
+            }
+        }
+        let runtime = self.runtime(req.model.clone(), req.backends);
+        models.insert(req.model.clone(), ModelEntry::new("llm", "fallback", Runtimes::llm(runtime.clone())));
+        let chain = runtime.chain();
+        chains.insert(req.model, runtime);
+        Ok(chain)
+    }
+
+    pub(crate) fn forget(&self, model: &str) -> bool {
+        self.chains.lock().unwrap().remove(model).is_some()
+    }
+
+    /// Has `fallback` answer for `model` when it fails, or nobody when None.
+    pub(crate) fn set_failover(&self, model: &str, fallback: Option<&str>) {
+        let mut failovers = self.failovers.lock().unwrap();
+        match fallback {
+            Some(fallback) => {
+                let backends = [model, fallback].map(|name| FallbackBackend { model: name.to_string(), max_inflight: None });
+                failovers.insert(model.to_string(), self.runtime(model.to_string(), backends.into()));
+            }
+            None => {
+                failovers.remove(model);
+            }
+        }
+    }
+
+    /// The failover chain of `model`, when it was loaded with a `fallback_model`.
+    pub(crate) fn failover(&self, model: &str) -> Option<Arc<FallbackRuntime>> {
+        self.failovers.lock().unwrap().get(model).cloned()
+    }
+
+    pub fn list(&self) -> Vec<FallbackChain> {
+        let mut list: Vec<FallbackChain> = self.chains.lock().unwrap().values().map(|chain| chain.chain()).collect();
+        list.sort_by(|a, b| a.model.cmp(&b.model));
+        list
+    }
+
+    /// ENV: MODEL_FALLBACKS, `;`-separated chains of the form `gateway=backend[:max_inflight],...`,
+    /// e.g. `chat=local:4,remote`
+    pub fn parse_env(value: &str) -> Result<Vec<CreateFallbackRequest>, String> {
+        value
+            .split(';')
+            .map(str::trim)
+            .filter(|chain| !chain.is_empty())
+            .map(|chain| {
+                let (model, backends) = chain.split_once('=').ok_or_else(|| format!("fallback chain {:?} has no '='", chain))?;
+                let backends = backends
+                    .split(',')
+                    .map(|backend| {
+                        let (model, max_inflight) = match backend.trim().split_once(':') {
+                            Some((model, max)) => (model, Some(max.parse().map_err(|_| format!("invalid max_inflight in {:?}", backend))?)),
+                            None => (backend.trim(), None),
+                        };
+                        Ok(FallbackBackend { model: model.to_string(), max_inflight })
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(CreateFallbackRequest { model: model.trim().to_string(), backends })
+            })
+            .collect()
+    }
+}
+
+impl CoreEngine {
+    pub async fn create_fallback(&self, req: CreateFallbackRequest) -> Result<FallbackChain, EngineError> {
+        let gateway = req.model.clone();
+        let chain = self.fallbacks.insert(req, &mut *self.registry.write().await)?;
+        // Gateways render ChatML; each backend applies its own template
+        self.chat_templates.write().await.remove(&gateway);
+        Ok(chain)
+    }
+
+    pub async fn remove_fallback(&self, model: &str) -> Result<(), String> {
+        if !self.fallbacks.forget(model) {
+            return Err(format!("Fallback chain for model {} not found", model));
+        }
+        self.registry.remove(model).await;
+        Ok(())
+    }
+
+    pub fn fallbacks(&self) -> &FallbackRouter {
+        &self.fallbacks
+    }
+}
@@ -0,0 +1,125 @@
+//! Uploaded files (`/v1/files`): content and metadata kept in the shared storage under
+//! `files/`, referenced by id from batches, transcriptions and image edits.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::dto::FileObject;
+use crate::engine::CoreEngine;
+use crate::storage::Storage;
+
+/// Purposes a client may upload a file for.
+pub const FILE_PURPOSES: [&str; 5] = ["assistants", "batch", "fine-tune", "vision", "user_data"];
+
+/// Purpose of the result files the server writes for finished batches.
+pub const BATCH_OUTPUT_PURPOSE: &str = "batch_output";
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+// What `files/<id>.json` holds; the owner is never shown to clients
+#[derive(Serialize, Deserialize)]
+struct StoredFile {
+    owner: String,
+    file: FileObject,
+}
+
+fn meta_key(id: &str) -> String {
+    format!("{}.json", id)
+}
+
+// Ids come from URLs; anything that is not one of ours cannot name an object
+fn valid_id(id: &str) -> bool {
+    id.starts_with("file-") && id.len() > 5 && id[5..].chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Files of every client; each sees only the ones uploaded with its own API key.
+pub struct FileStore {
+    storage: Arc<dyn Storage>,
+}
+
+impl FileStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Stores an upload; `purpose` must be one of `FILE_PURPOSES`.
+    pub async fn create(&self, owner: &str, filename: &str, purpose: &str, bytes: Vec<u8>) -> Result<FileObject, String> {
+        if !FILE_PURPOSES.contains(&purpose) {
+            return Err(format!("purpose must be one of {}", FILE_PURPOSES.join(", ")));
+        }
+        self.put(owner, filename, purpose, bytes).await
+    }
+
+    // Stores a file with any purpose, including server-written ones
+    pub(crate) async fn put(&self, owner: &str, filename: &str, purpose: &str, bytes: Vec<u8>) -> Result<FileObject, String> {
+        let file = FileObject {
+            id: format!("file-{}", uuid::Uuid::new_v4().simple()),
+            object: "file".to_string(),
+            bytes: bytes.len() as u64,
+            created_at: now_secs(),
+            filename: filename.to_string(),
+            purpose: purpose.to_string(),
+        };
+        // Content first, so metadata never points at a missing object
+        self.storage.put(&file.id, bytes).await?;
+        let stored = StoredFile { owner: owner.to_string(), file: file.clone() };
+        self.storage.put(&meta_key(&file.id), serde_json::to_vec(&stored).unwrap()).await?;
+        Ok(file)
+    }
+
+    async fn stored(&self, owner: &str, id: &str) -> Result<Option<StoredFile>, String> {
+        if !valid_id(id) {
+            return Ok(None);
+        }
+        let Some(bytes) = self.storage.get(&meta_key(id)).await? else { return Ok(None) };
+        let stored: StoredFile = serde_json::from_slice(&bytes).map_err(|e| format!("file {}: {}", id, e))?;
+        Ok((stored.owner == owner).then_some(stored))
+    }
+
+    /// The file `id` if it belongs to `owner`.
+    pub async fn get(&self, owner: &str, id: &str) -> Result<Option<FileObject>, String> {
+        Ok(self.stored(owner, id).await?.map(|s| s.file))
+    }
+
+    /// Contents of the file `id` if it belongs to `owner`.
+    pub async fn content(&self, owner: &str, id: &str) -> Result<Option<Vec<u8>>, String> {
+        if self.stored(owner, id).await?.is_none() {
+            return Ok(None);
+        }
+        self.storage.get(id).await
+    }
+
+    /// Deletes the file `id` if it belongs to `owner`; returns whether it did.
+    pub async fn delete(&self, owner: &str, id: &str) -> Result<bool, String> {
+        if self.stored(owner, id).await?.is_none() {
+            return Ok(false);
+        }
+        // Metadata first: a half-deleted file is then simply gone
+        self.storage.delete(&meta_key(id)).await?;
+        self.storage.delete(id).await?;
+        Ok(true)
+    }
+
+    /// `owner`'s files, newest first, optionally only those with `purpose`.
+    pub async fn list(&self, owner: &str, purpose: Option<&str>) -> Result<Vec<FileObject>, String> {
+        let mut files = Vec::new();
+        for object in self.storage.list("file-").await?.into_iter().filter(|o| o.key.ends_with(".json")) {
+            let Some(bytes) = self.storage.get(&object.key).await? else { continue };
+            let stored: StoredFile = serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", object.key, e))?;
+            if stored.owner == owner && purpose.is_none_or(|p| p == stored.file.purpose) {
+                files.push(stored.file);
+            }
+        }
+        files.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+        Ok(files)
+    }
+}
+
+impl CoreEngine {
+    pub fn files(&self) -> &FileStore {
+        &self.files
+    }
+}
@@ -0,0 +1,98 @@
+use futures::future::join_all;
+use metrics::counter;
+use std::sync::Weak;
+use std::time::Duration;
+
+use crate::engine::{events::{EngineEvent, EventBus}, registry::ModelRegistry, CoreEngine};
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthConfig {
+    // None: no periodic checks
+    pub interval: Option<Duration>,
+    // Failed checks in a row that make a model unhealthy
+    pub failures: u32,
+    // A check taking longer fails
+    pub timeout: Duration,
+    // Load a model that turned unhealthy again from its load parameters
+    pub reload: bool,
+}
+
+impl HealthConfig {
+    /// ENV: HEALTH_CHECK_INTERVAL_SECS (default 0: off), HEALTH_CHECK_FAILURES (default 3),
+    /// HEALTH_CHECK_TIMEOUT_MS (default 10000), HEALTH_CHECK_RELOAD (default 0)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            interval: env("HEALTH_CHECK_INTERVAL_SECS").filter(|secs| *secs > 0).map(Duration::from_secs),
+            failures: env("HEALTH_CHECK_FAILURES").unwrap_or(3).clamp(1, u32::MAX as u64) as u32,
+            timeout: Duration::from_millis(env("HEALTH_CHECK_TIMEOUT_MS").unwrap_or(10_000)),
+            reload: matches!(crate::config::var("HEALTH_CHECK_RELOAD").as_deref(), Ok("1") | Ok("true")),
+        }
+    }
+}
+
+impl CoreEngine {
+    /// Probes every loaded LLM and embedding model now rather than at the next periodic check.
+    /// Returns the models whose health changed, with whether they are healthy now.
+    pub async fn check_model_health(&self) -> Vec<(String, bool)> {
+        check(&self.registry, &self.events, HealthConfig::from_env()).await
+    }
+
+    /// Probes the loaded models every HEALTH_CHECK_INTERVAL_SECS, until the engine is dropped.
+    pub(super) async fn health_checker(registry: Weak<ModelRegistry>, events: EventBus) {
+        loop {
+            // Read each round, so a configuration reload can turn checks on, off or retune them
+            let config = HealthConfig::from_env();
+            tokio::time::sleep(config.interval.unwrap_or(Duration::from_secs(1))).await;
+            let Some(registry) = registry.upgrade() else { return };
+            if config.interval.is_some() {
+                check(&registry, &events, config).await;
+            }
+        }
+    }
+}
+
+// One round of probes, run side by side
+async fn check(registry: &ModelRegistry, events: &EventBus, config: HealthConfig) -> Vec<(String, bool)> {
+    let probes = registry.probe_targets().await.into_iter().map(|(name, kind, runtimes, loaded_ms)| async move {
+        let result = match tokio::time::timeout(config.timeout, super::exercise(&kind, &runtimes)).await {
+            Ok(None) => return None,
+            Ok(Some(result)) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("no answer within {}ms", config.timeout.as_millis())),
+        };
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        counter!("model_health_checks_total", 1, "model" => name.clone(), "outcome" => outcome);
+        let error = result.as_ref().err().cloned();
+        let healthy = registry.record_probe(&name, loaded_ms, result, config.failures).await?;
+        Some((name, healthy, error))
+    });
+    let mut changed = Vec::new();
+    for (name, healthy, error) in join_all(probes).await.into_iter().flatten() {
+        if healthy {
+            tracing::info!("model {} passes its health checks again", name);
+        } else {
+            tracing::warn!("model {} failed {} health checks in a row: {}", name, config.failures, error.as_deref().unwrap_or_default());
+        }
+        events.publish(EngineEvent::ModelHealth { model: name.clone(), healthy, error });
+        let recovered = !healthy && config.reload && reload(registry, events, &name).await;
+        changed.push((name, healthy || recovered));
+    }
+    changed
+}
+
+// Whether loading an unhealthy model again made it healthy
+async fn reload(registry: &ModelRegistry, events: &EventBus, name: &str) -> bool {
+    let result = registry.reload_unhealthy(name).await;
+    counter!("model_health_reloads_total", 1, "outcome" => if result.is_ok() { "ok" } else { "error" });
+    match result {
+        Ok(()) => {
+            tracing::info!("reloaded unhealthy model {}", name);
+            events.publish(EngineEvent::ModelHealth { model: name.to_string(), healthy: true, error: None });
+            true
+        }
+        Err(e) => {
+            tracing::error!("could not reload unhealthy model {}: {}", name, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Fetches model files from the Hugging Face Hub (or a mirror) into a local cache, so load
+//! requests can name a repository instead of a path prepared out of band.
+
+use futures::StreamExt;
+use metrics::counter;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::outbound;
+
+#[derive(Debug, Clone)]
+pub struct HubConfig {
+    // Hub root; a mirror serves the same `/{repo}/resolve/{revision}/{file}` layout
+    pub endpoint: String,
+    pub cache_dir: PathBuf,
+    pub token: Option<String>,
+}
+
+impl HubConfig {
+    /// ENV: HF_ENDPOINT (default https://huggingface.co), MODEL_CACHE_DIR (default ./models),
+    /// HF_TOKEN (or HUGGING_FACE_HUB_TOKEN) for gated and private repositories
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().filter(|v| !v.is_empty());
+        Self {
+            endpoint: env("HF_ENDPOINT").unwrap_or_else(|| "https://huggingface.co".to_string()).trim_end_matches('/').to_string(),
+            cache_dir: PathBuf::from(env("MODEL_CACHE_DIR").unwrap_or_else(|| "./models".to_string())),
+            token: env("HF_TOKEN").or_else(|| env("HUGGING_FACE_HUB_TOKEN")),
+        }
+    }
+}
+
+/// `owner/name`, each part made of letters, digits, `-`, `_` and `.`
+pub fn check_repo(repo: &str) -> Result<(), String> {
+    let valid_part = |part: &str| {
+        !part.is_empty() && !part.starts_with('.') && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    match repo.split_once('/') {
+        Some((owner, name)) if valid_part(owner) && valid_part(name) => Ok(()),
+        _ => Err(format!("invalid hf_repo {:?}; expected owner/name", repo)),
+    }
+}
+
+// Relative paths inside the repository only; they become paths in the cache
+fn check_file(file: &str) -> Result<(), String> {
+    let path = Path::new(file);
+    if file.is_empty() || path.is_absolute() || !path.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+        return Err(format!("invalid repository file {:?}", file));
+    }
+    Ok(())
+}
+
+pub fn check_revision(revision: &str) -> Result<(), String> {
+    if revision.is_empty() || revision.contains("..") || !revision.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/')) {
+        return Err(format!("invalid hf_revision {:?}", revision));
+    }
+    Ok(())
+}
+
+/// Byte and file counts of a fetch in progress, read by the download manager
+#[derive(Debug, Default)]
+pub struct Progress {
+    pub downloaded_bytes: AtomicU64,
+    // Zero until the size of everything being fetched is known
+    pub total_bytes: AtomicU64,
+    pub files_completed: AtomicUsize,
+    pub files_total: AtomicUsize,
+}
+
+/// Local path of `filename` from `repo` at `revision` (`main` when unset), downloading it
+/// on first use. Without a filename the whole repository is fetched and its directory
+/// returned, for checkpoints spread over several files.
+pub async fn fetch(config: &HubConfig, repo: &str, filename: Option<&str>, revision: Option<&str>) -> Result<PathBuf, String> {
+    fetch_with_progress(config, repo, filename, revision, &Progress::default(), &CancellationToken::new()).await
+}
+
+/// [`fetch`], reporting into `progress`. A cancelled or failed download keeps what it
+/// received so far, and the next fetch of the same file resumes from there.
+pub async fn fetch_with_progress(
+    config: &HubConfig,
+    repo: &str,
+    filename: Option<&str>,
+    revision: Option<&str>,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<PathBuf, String> {
+    let revision = revision.unwrap_or("main");
+    check_repo(repo)?;
+    check_revision(revision)?;
+    let dir = config.cache_dir.join(repo.replace('/', "--")).join(revision.replace('/', "--"));
+    match filename {
+        Some(file) => {
+            check_file(file)?;
+            progress.files_total.store(1, Ordering::Relaxed);
+            download(config, repo, revision, file, &dir, progress, true, cancel).await?;
+            Ok(dir.join(file))
+        }
+        None => {
+            let files = list_files(config, repo, revision).await?;
+            progress.files_total.store(files.len(), Ordering::Relaxed);
+            let sizes: Option<u64> = files.iter().map(|(_, size)| *size).sum();
+            progress.total_bytes.store(sizes.unwrap_or(0), Ordering::Relaxed);
+            for (file, _) in &files {
+                check_file(file)?;
+                download(config, repo, revision, file, &dir, progress, false, cancel).await?;
+            }
+            Ok(dir)
+        }
+    }
+}
+
+// Files of the repository with their sizes, when the hub reports them
+async fn list_files(config: &HubConfig, repo: &str, revision: &str) -> Result<Vec<(String, Option<u64>)>, String> {
+    let client = outbound::client("model_download");
+    let mut request = client.get(&format!("{}/api/models/{}/revision/{}?blobs=true", config.endpoint, repo, revision));
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+    let response = client.send(request).await?;
+    if !response.status().is_success() {
+        return Err(format!("list {}@{}: hub returned {}", repo, revision, response.status()));
+    }
+    let info: Value = serde_json::from_slice(&response.bytes_limited(16 << 20).await?)
+        .map_err(|e| format!("list {}@{}: {}", repo, revision, e))?;
+    let files: Vec<(String, Option<u64>)> = info["siblings"]
+        .as_array()
+        .map(|siblings| {
+            siblings.iter().filter_map(|s| Some((s["rfilename"].as_str()?.to_string(), s["size"].as_u64()))).collect()
+        })
+        .unwrap_or_default();
+    if files.is_empty() {
+        return Err(format!("{}@{} has no files", repo, revision));
+    }
+    Ok(files)
+}
+
+// One lock per cache path, so concurrent fetches of a file download it once
+static IN_FLIGHT: Lazy<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[allow(clippy::too_many_arguments)]
+async fn download(
+    config: &HubConfig,
+    repo: &str,
+    revision: &str,
+    file: &str,
+    dir: &Path,
+    progress: &Progress,
+    sets_total: bool,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    let target = dir.join(file);
+    let lock = IN_FLIGHT.lock().unwrap().entry(target.clone()).or_default().clone();
+    let _guard = lock.lock().await;
+    let result = match tokio::fs::metadata(&target).await {
+        Ok(meta) => {
+            progress.downloaded_bytes.fetch_add(meta.len(), Ordering::Relaxed);
+            if sets_total {
+                progress.total_bytes.store(meta.len(), Ordering::Relaxed);
+            }
+            counter!("model_downloads_total", 1, "outcome" => "cached");
+            Ok(())
+        }
+        Err(_) => {
+            let url = format!("{}/{}/resolve/{}/{}", config.endpoint, repo, revision, file);
+            let result = download_to(config, &url, &target, progress, sets_total, cancel).await;
+            counter!("model_downloads_total", 1, "outcome" => match &result {
+                Ok(_) => "downloaded",
+                Err(_) if cancel.is_cancelled() => "cancelled",
+                Err(_) => "error",
+            });
+            result.map_err(|e| format!("download {} from {}@{}: {}", file, repo, revision, e))
+        }
+    };
+    if result.is_ok() {
+        progress.files_completed.fetch_add(1, Ordering::Relaxed);
+    }
+    result
+}
+
+async fn download_to(
+    config: &HubConfig,
+    url: &str,
+    target: &Path,
+    progress: &Progress,
+    sets_total: bool,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    // Bytes received so far live in `<file>.part`, renamed into place once complete so an
+    // interrupted download is never mistaken for a cached file. The ETag they came with is
+    // kept beside them: a resumed request only continues if the file has not changed since.
+    let name = target.file_name().and_then(|n| n.to_str()).unwrap_or("download");
+    let partial = target.with_file_name(format!("{}.part", name));
+    let etag_file = target.with_file_name(format!("{}.part.etag", name));
+    let mut offset = tokio::fs::metadata(&partial).await.map(|m| m.len()).unwrap_or(0);
+    let etag = tokio::fs::read_to_string(&etag_file).await.ok().filter(|_| offset > 0);
+
+    let client = outbound::client("model_download");
+    let mut request = client.get(url);
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(etag) = &etag {
+        request = request.header("range", format!("bytes={}-", offset)).header("if-range", etag.as_str());
+    }
+    let response = client.send(request).await?;
+    let status = response.status();
+    if etag.is_some() && status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is no prefix of this one; start over
+        let _ = tokio::fs::remove_file(&partial).await;
+        let _ = tokio::fs::remove_file(&etag_file).await;
+        return Box::pin(download_to(config, url, target, progress, sets_total, cancel)).await;
+    }
+    if !status.is_success() {
+        return Err(match status.as_u16() {
+            401 | 403 => format!("hub returned {}; gated and private repositories need HF_TOKEN", status),
+            _ => format!("hub returned {}", status),
+        });
+    }
+    // Anything but a partial response is the whole file again
+    let resumed = etag.is_some() && status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+        offset = 0;
+    }
+    if sets_total && let Some(length) = response.response.content_length() {
+        progress.total_bytes.store(offset + length, Ordering::Relaxed);
+    }
+    progress.downloaded_bytes.fetch_add(offset, Ordering::Relaxed);
+
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+    }
+    match response.response.headers().get("etag").and_then(|v| v.to_str().ok()) {
+        Some(etag) => tokio::fs::write(&etag_file, etag).await.map_err(|e| e.to_string())?,
+        None => { let _ = tokio::fs::remove_file(&etag_file).await; }
+    }
+    let mut out = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&partial)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut stream = response.response.bytes_stream();
+    let mut bytes = offset;
+    loop {
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = cancel.cancelled() => return Err("cancelled".to_string()),
+        };
+        let Some(chunk) = chunk else { break };
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        out.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        bytes += chunk.len() as u64;
+        progress.downloaded_bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+    out.sync_all().await.map_err(|e| e.to_string())?;
+    tokio::fs::rename(&partial, target).await.map_err(|e| e.to_string())?;
+    let _ = tokio::fs::remove_file(&etag_file).await;
+    if resumed {
+        tracing::info!("downloaded {} to {} ({} bytes, resumed at {})", url, target.display(), bytes, offset);
+    } else {
+        tracing::info!("downloaded {} to {} ({} bytes)", url, target.display(), bytes);
+    }
+    Ok(())
+}
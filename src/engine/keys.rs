@@ -0,0 +1,252 @@
+//! API keys managed through `/admin/keys`: kept in the shared storage under `keys/` as SHA-256
+//! digests with their name, scopes and creation metadata. The secret itself is only ever shown
+//! in the response that created it.
+//!
+//! Each process caches the active keys and reads them all again every `cache_ttl()`, so a key
+//! revoked through another replica sharing the storage is refused there within that time. A key
+//! the cache does not know is looked up in the storage right away.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::auth::Scope;
+use crate::api::dto::{ApiKeyObject, CreateApiKeyRequest, CreatedApiKey};
+use crate::engine::CoreEngine;
+use crate::storage::Storage;
+
+/// Scopes a key may be granted.
+pub const KEY_SCOPES: [&str; 2] = [Scope::Inference.as_str(), Scope::Admin.as_str()];
+
+/// Scope of keys created without any.
+pub const DEFAULT_SCOPE: &str = Scope::Inference.as_str();
+
+// Unknown tokens remembered at most, so guessing cannot grow the cache without bound
+const MAX_UNKNOWN: usize = 10_000;
+
+/// ENV: API_KEY_CACHE_SECS (default 10), how often the active keys are read again from the
+/// storage; a key revoked through another replica is refused here within that time
+pub fn cache_ttl() -> Duration {
+    Duration::from_secs(crate::config::var("API_KEY_CACHE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(10).max(1))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn digest(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+// What `keys/<id>.json` holds; the digest is never shown to clients
+#[derive(Serialize, Deserialize)]
+struct StoredKey {
+    hash: String,
+    key: ApiKeyObject,
+}
+
+fn meta_key(id: &str) -> String {
+    format!("{}.json", id)
+}
+
+// `keys/hashes/<digest>` holds the id of the key with that digest, so a key can be found
+// from its secret without reading them all
+fn hash_key(hash: &str) -> String {
+    format!("hashes/{}", hash)
+}
+
+// Ids come from URLs; anything that is not one of ours cannot name a key
+fn valid_id(id: &str) -> bool {
+    id.starts_with("key_") && id.len() > 4 && id[4..].chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn redact(secret: &str) -> String {
+    format!("{}...{}", &secret[..5], &secret[secret.len() - 2..])
+}
+
+/// An active key as the cache knows it.
+#[derive(Debug, Clone)]
+pub struct ActiveKey {
+    pub id: String,
+    pub scopes: Vec<String>,
+}
+
+pub struct KeyStore {
+    storage: Arc<dyn Storage>,
+    // Active keys by digest, as of the last sync plus the ones found since
+    active: RwLock<HashMap<String, ActiveKey>>,
+    // Digests looked up in the storage and not found, with when
+    unknown: RwLock<HashMap<String, Instant>>,
+}
+
+impl KeyStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage, active: Default::default(), unknown: Default::default() }
+    }
+
+    /// Reads the active keys again every `cache_ttl()` for as long as the store is alive.
+    pub async fn sync_periodically(keys: Weak<KeyStore>) {
+        loop {
+            tokio::time::sleep(cache_ttl()).await;
+            let Some(keys) = keys.upgrade() else { return };
+            if let Err(e) = keys.load().await {
+                tracing::warn!("could not read API keys: {}", e);
+            }
+        }
+    }
+
+    /// Reads the stored keys, replacing the cached ones, so keys created by earlier runs and
+    /// other replicas are accepted and revoked ones refused. Returns how many are active.
+    pub async fn load(&self) -> Result<usize, String> {
+        let mut active = HashMap::new();
+        for stored in self.all().await? {
+            if stored.key.revoked_at.is_none() {
+                active.insert(stored.hash, ActiveKey { id: stored.key.id, scopes: stored.key.scopes });
+            }
+        }
+        let count = active.len();
+        *self.active.write().unwrap() = active;
+        self.unknown.write().unwrap().clear();
+        Ok(count)
+    }
+
+    /// Whether any stored key is active; with one, requests need a key even without API_KEYS.
+    pub fn any_active(&self) -> bool {
+        !self.active.read().unwrap().is_empty()
+    }
+
+    /// Whether any active stored key holds `scope`.
+    pub fn any_with_scope(&self, scope: Scope) -> bool {
+        self.active.read().unwrap().values().any(|key| key.scopes.iter().any(|s| s == scope.as_str()))
+    }
+
+    /// The active stored key `secret`, looked up in the storage when the cache does not know
+    /// it (e.g. it was just created through another replica).
+    pub async fn find(&self, secret: &str) -> Result<Option<ActiveKey>, String> {
+        let hash = digest(secret);
+        if let Some(key) = self.active.read().unwrap().get(&hash) {
+            return Ok(Some(key.clone()));
+        }
+        if self.unknown.read().unwrap().get(&hash).is_some_and(|at| at.elapsed() < cache_ttl()) {
+            return Ok(None);
+        }
+        let id = match self.storage.get(&hash_key(&hash)).await? {
+            Some(id) => String::from_utf8(id).map_err(|e| format!("key digest {}: {}", hash, e))?,
+            None => String::new(),
+        };
+        match self.stored(&id).await? {
+            Some(stored) if stored.hash == hash && stored.key.revoked_at.is_none() => {
+                let key = ActiveKey { id: stored.key.id, scopes: stored.key.scopes };
+                self.active.write().unwrap().insert(hash, key.clone());
+                Ok(Some(key))
+            }
+            _ => {
+                let mut unknown = self.unknown.write().unwrap();
+                if unknown.len() >= MAX_UNKNOWN {
+                    unknown.clear();
+                }
+                unknown.insert(hash, Instant::now());
+                Ok(None)
+            }
+        }
+    }
+
+    /// The id of the cached key whose digest starts with `prefix`, the part of it `client_id`
+    /// shows.
+    pub fn id_by_digest_prefix(&self, prefix: &str) -> Option<String> {
+        self.active.read().unwrap().iter().find(|(hash, _)| hash.starts_with(prefix)).map(|(_, key)| key.id.clone())
+    }
+
+    /// SHA-256 hex digests of the active stored keys, safe to export.
+    pub fn active_hashes(&self) -> Vec<String> {
+        let mut hashes: Vec<String> = self.active.read().unwrap().keys().cloned().collect();
+        hashes.sort();
+        hashes
+    }
+
+    /// Creates a key for `created_by`, accepted from now on. Scopes must be among `KEY_SCOPES`.
+    pub async fn create(&self, created_by: &str, req: CreateApiKeyRequest) -> Result<CreatedApiKey, String> {
+        let name = req.name.trim();
+        if name.is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        let mut scopes = req.scopes.unwrap_or_else(|| vec![DEFAULT_SCOPE.to_string()]);
+        if scopes.is_empty() {
+            return Err("scopes must not be empty".to_string());
+        }
+        if let Some(scope) = scopes.iter().find(|s| !KEY_SCOPES.contains(&s.as_str())) {
+            return Err(format!("unknown scope {:?}; scopes must be among {}", scope, KEY_SCOPES.join(", ")));
+        }
+        scopes.sort();
+        scopes.dedup();
+
+        let secret = format!("sk-{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        let key = ApiKeyObject {
+            id: format!("key_{}", uuid::Uuid::new_v4().simple()),
+            object: "api_key".to_string(),
+            name: name.to_string(),
+            scopes,
+            redacted_value: redact(&secret),
+            created_at: now_secs(),
+            created_by: created_by.to_string(),
+            revoked_at: None,
+        };
+        let stored = StoredKey { hash: digest(&secret), key: key.clone() };
+        self.storage.put(&meta_key(&key.id), serde_json::to_vec(&stored).unwrap()).await?;
+        self.storage.put(&hash_key(&stored.hash), key.id.clone().into_bytes()).await?;
+        self.active.write().unwrap().insert(stored.hash, ActiveKey { id: key.id.clone(), scopes: key.scopes.clone() });
+        Ok(CreatedApiKey { info: key, key: secret })
+    }
+
+    async fn stored(&self, id: &str) -> Result<Option<StoredKey>, String> {
+        if !valid_id(id) {
+            return Ok(None);
+        }
+        let Some(bytes) = self.storage.get(&meta_key(id)).await? else { return Ok(None) };
+        serde_json::from_slice(&bytes).map(Some).map_err(|e| format!("key {}: {}", id, e))
+    }
+
+    async fn all(&self) -> Result<Vec<StoredKey>, String> {
+        let mut keys = Vec::new();
+        for object in self.storage.list("key_").await?.into_iter().filter(|o| o.key.ends_with(".json")) {
+            let Some(bytes) = self.storage.get(&object.key).await? else { continue };
+            keys.push(serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", object.key, e))?);
+        }
+        Ok(keys)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<ApiKeyObject>, String> {
+        Ok(self.stored(id).await?.map(|s| s.key))
+    }
+
+    /// Every key, revoked ones included, newest first.
+    pub async fn list(&self) -> Result<Vec<ApiKeyObject>, String> {
+        let mut keys: Vec<ApiKeyObject> = self.all().await?.into_iter().map(|s| s.key).collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+        Ok(keys)
+    }
+
+    /// Revokes the key `id`, which is refused from then on here, and by other replicas within
+    /// `cache_ttl()`. Revoking it again changes nothing.
+    pub async fn revoke(&self, id: &str) -> Result<Option<ApiKeyObject>, String> {
+        let Some(mut stored) = self.stored(id).await? else { return Ok(None) };
+        if stored.key.revoked_at.is_none() {
+            // Refused before the record says so, so a failed write cannot leave it usable
+            self.active.write().unwrap().remove(&stored.hash);
+            stored.key.revoked_at = Some(now_secs());
+            self.storage.put(&meta_key(id), serde_json::to_vec(&stored).unwrap()).await?;
+        }
+        Ok(Some(stored.key))
+    }
+}
+
+impl CoreEngine {
+    pub fn keys(&self) -> &Arc<KeyStore> {
+        &self.keys
+    }
+}
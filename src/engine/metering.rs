@@ -0,0 +1,194 @@
+//! Usage metering for chargeback: requests, tokens and latency per API key and model, summed
+//! by the hour and kept in the shared storage under `usage/`. Each process writes its own
+//! `<hour>/<instance>.json`, so replicas sharing the storage never overwrite each other's
+//! counts; reports read and add up all of them when asked for.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::dto::MeteredUsage;
+use crate::engine::{keys::KeyStore, CoreEngine};
+use crate::storage::Storage;
+
+/// Length of the periods usage is summed over; reports cover whole periods.
+pub const BUCKET_SECS: u64 = 3600;
+
+/// ENV: USAGE_FLUSH_SECS (default 10), longest recorded usage waits before it is written to
+/// the storage
+pub fn flush_interval() -> Duration {
+    Duration::from_secs(crate::config::var("USAGE_FLUSH_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(10))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Totals {
+    requests: u64,
+    errors: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    latency_ms: u64,
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.latency_ms += other.latency_ms;
+    }
+}
+
+// One line of a stored period
+#[derive(Serialize, Deserialize)]
+struct Row {
+    key: String,
+    model: String,
+    #[serde(flatten)]
+    totals: Totals,
+}
+
+// Totals by period start, then by (key, model)
+type Periods = BTreeMap<u64, HashMap<(String, String), Totals>>;
+
+pub struct UsageMeter {
+    storage: Arc<dyn Storage>,
+    // Names callers that used a stored key by its id
+    keys: Arc<KeyStore>,
+    instance: String,
+    // This process's own totals, written whole to its files
+    own: Mutex<Periods>,
+    dirty: Mutex<BTreeSet<u64>>,
+    flush_scheduled: AtomicBool,
+}
+
+impl UsageMeter {
+    pub fn new(storage: Arc<dyn Storage>, keys: Arc<KeyStore>) -> Self {
+        Self {
+            storage,
+            keys,
+            instance: uuid::Uuid::new_v4().simple().to_string(),
+            own: Default::default(),
+            dirty: Default::default(),
+            flush_scheduled: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts a finished request of `client` (see `client_id`); written to the storage within
+    /// `flush_interval()`. A stored key is counted under its id, as `/admin/keys` lists it.
+    pub fn record(self: &Arc<Self>, client: &str, model: &str, ok: bool, prompt_tokens: u32, completion_tokens: u32, latency_ms: u64) {
+        let stored = client.strip_prefix("key-").and_then(|prefix| self.keys.id_by_digest_prefix(prefix));
+        let key = stored.as_deref().unwrap_or(client);
+        let period = now_secs() / BUCKET_SECS * BUCKET_SECS;
+        let request = Totals {
+            requests: 1,
+            errors: u64::from(!ok),
+            prompt_tokens: prompt_tokens.into(),
+            completion_tokens: completion_tokens.into(),
+            latency_ms,
+        };
+        self.own.lock().unwrap().entry(period).or_default().entry((key.to_string(), model.to_string())).or_default().add(&request);
+        self.dirty.lock().unwrap().insert(period);
+        if !self.flush_scheduled.swap(true, Ordering::AcqRel) {
+            let meter = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(flush_interval()).await;
+                meter.flush_scheduled.store(false, Ordering::Release);
+                if let Err(e) = meter.flush().await {
+                    tracing::warn!("could not write usage: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Writes the periods counted since the last flush.
+    pub async fn flush(&self) -> Result<(), String> {
+        let periods = std::mem::take(&mut *self.dirty.lock().unwrap());
+        for period in periods {
+            let rows: Vec<Row> = self.own.lock().unwrap().get(&period).into_iter().flatten()
+                .map(|((key, model), totals)| Row { key: key.clone(), model: model.clone(), totals: totals.clone() })
+                .collect();
+            if let Err(e) = self.storage.put(&format!("{}/{}.json", period, self.instance), serde_json::to_vec(&rows).unwrap()).await {
+                // Tried again with the next flush
+                self.dirty.lock().unwrap().insert(period);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    // What earlier runs and other processes stored for the periods in `range`; files that
+    // cannot be read are left out rather than failing the whole report
+    async fn stored(&self, range: std::ops::Range<u64>) -> Result<Periods, String> {
+        let mut stored = Periods::new();
+        for object in self.storage.list("").await? {
+            let Some((period, file)) = object.key.split_once('/') else { continue };
+            let Ok(period) = period.parse::<u64>() else { continue };
+            if !range.contains(&period) || file == format!("{}.json", self.instance) || !file.ends_with(".json") {
+                continue;
+            }
+            let rows: Vec<Row> = match self.storage.get(&object.key).await {
+                Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+                    Ok(rows) => rows,
+                    Err(e) => {
+                        tracing::warn!("skipping unreadable usage file {}: {}", object.key, e);
+                        continue;
+                    }
+                },
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!("skipping usage file {}: {}", object.key, e);
+                    continue;
+                }
+            };
+            let totals = stored.entry(period).or_default();
+            for row in rows {
+                totals.entry((row.key, row.model)).or_default().add(&row.totals);
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Usage per key and model over the periods overlapping `[from, to)` (Unix seconds;
+    /// unbounded when None), only `key`'s when given, sorted by key and model: what every
+    /// process sharing the storage has written so far, and this one's own up to now.
+    pub async fn report(&self, key: Option<&str>, from: Option<u64>, to: Option<u64>) -> Result<Vec<MeteredUsage>, String> {
+        let start = from.map_or(0, |from| from / BUCKET_SECS * BUCKET_SECS);
+        let end = to.unwrap_or(u64::MAX).max(start);
+        let stored = self.stored(start..end).await?;
+        let mut sums: BTreeMap<(String, String), Totals> = BTreeMap::new();
+        for periods in [&stored, &*self.own.lock().unwrap()] {
+            for totals in periods.range(start..end).map(|(_, totals)| totals) {
+                for ((k, model), totals) in totals.iter().filter(|((k, _), _)| key.is_none_or(|key| key == k)) {
+                    sums.entry((k.clone(), model.clone())).or_default().add(totals);
+                }
+            }
+        }
+        Ok(sums.into_iter()
+            .map(|((key, model), t)| MeteredUsage {
+                key,
+                model,
+                requests: t.requests,
+                errors: t.errors,
+                prompt_tokens: t.prompt_tokens,
+                completion_tokens: t.completion_tokens,
+                total_tokens: t.prompt_tokens + t.completion_tokens,
+                avg_latency_ms: t.latency_ms as f64 / t.requests.max(1) as f64,
+            })
+            .collect())
+    }
+}
+
+impl CoreEngine {
+    pub fn metering(&self) -> &Arc<UsageMeter> {
+        &self.metering
+    }
+}
@@ -1,403 +1,1518 @@
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{mpsc, Semaphore, RwLock};
+pub mod audio;
+pub mod batching;
+pub mod budget;
+pub mod canary;
+pub mod continuous;
+pub mod chunking;
+pub mod downloads;
+pub mod embeddings;
+pub mod error;
+pub mod events;
+pub mod fallback;
+pub mod failover;
+pub mod health;
+pub mod hub;
+pub mod registry;
+pub mod reload;
+pub mod scheduler;
+pub mod shutdown;
+pub mod splits;
+pub mod swaps;
+pub mod evals;
+pub mod batches;
+pub mod files;
+pub mod keys;
+pub mod metering;
+pub mod rerank;
+pub mod safety;
+pub mod state;
+pub mod tools;
+pub mod watchdog;
+
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, Arc, Weak}, time::Duration};
+use tokio::sync::{mpsc, oneshot, Semaphore, RwLock};
 use moka::future::Cache;
+use tokio_util::sync::CancellationToken;
 use sha2::{Digest, Sha256};
 use metrics::{counter, histogram};
 
 use crate::{
     api::dto::{
         ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionRequest,
-        ChatCompletionResponse, ChatCompletionChoice, Delta, ResponseMessage, Usage, ChatMessageContent, ContentPart,
-        EmbeddingsRequest, EmbeddingsResponse, EmbeddingObject, EmbeddingUsage,
-        ImagesGenerationRequest,
+        ChatCompletionResponse, ChatCompletionChoice, ChatStreamError, CacheStatus, Delta, ResponseMessage, Usage, ChatMessageContent, ContentPart,
+        StopSequences, StreamChunking, EmbeddingsRequest, EmbeddingsResponse, EmbeddingObject, EmbeddingUsage,
+        ImagesGenerationRequest, ImagesEditRequest, ImagesVariationRequest, LoadModelResponse, ModelOptions, ModelSpec, ModelsListResponse, Priority, RerankRequest, RerankResponse,
+        SpeechRequest, TranscriptionRequest, VerboseTranscriptionResponse,
     },
-    runtime::{dummy::DummyRuntime, dummy_embedding::DummyEmbeddingRuntime, LlmRuntime, EmbeddingRuntime, MultimodalRuntime, ImageGenRuntime, GenerationOptions},
+    cache::{Occupancy, SharedResponses},
+    storage::{Scoped, Storage},
+    runtime::{approximate_token_count, prompt::{render_chat_prompt, ChatTemplate, RenderedPrompt}, dummy::DummyRuntime, dummy_embedding::DummyEmbeddingRuntime, dummy_rerank::DummyRerankRuntime, dummy_audio::DummyAudioRuntime, dummy_tts::DummyTtsRuntime, remote::{RemoteConfig, RemoteRuntime}, audio::duration_secs, image::png_dimensions, sampler::Mirostat, stop::MAX_STOP_SEQUENCES, Speech, ImageGenOptions, ImageProgress, GenerationOptions, RuntimeError},
 };
 #[cfg(feature = "llama")]
 use crate::runtime::llama_cpp::LlamaCppRuntime;
 #[cfg(feature = "onnx")]
 use crate::runtime::onnx_embedding::OnnxEmbeddingRuntime;
+#[cfg(feature = "onnx")]
+use crate::runtime::onnx_rerank::OnnxRerankRuntime;
+#[cfg(feature = "stable_diffusion")]
+use crate::runtime::onnx_sd::OnnxStableDiffusionRuntime;
 #[cfg(feature = "llava")]
 use crate::runtime::llava::LlavaRuntime;
+#[cfg(feature = "whisper")]
+use crate::runtime::whisper::WhisperRuntime;
+use canary::CanaryRouter;
+use continuous::{ContinuousBatcher, ContinuousBatchingConfig};
+use fallback::FallbackRouter;
+use budget::Footprint;
+use splits::TrafficSplitter;
+use registry::{model_not_found, Capability, ChatRoute, Entries, ModelEntry, ModelRegistry, Reservation, Runtimes, MODEL_KINDS};
+use chunking::WordChunker;
+use embeddings::{encode_embedding, truncate_dimensions, EmbeddingBatch};
+pub use error::EngineError;
+use batching::{BatchingConfig, EmbeddingBatcher};
+use events::{EngineEvent, EventBus};
+use safety::{SafetyLedger, SafetyPolicy};
+use scheduler::{Backlog, CostModel, KeyConcurrency, PriorityQueue, QueueLimits, Slot, UsageLedger, QUEUE_TIMEOUT, SHUTTING_DOWN, ANONYMOUS_CLIENT, PREFILL_WEIGHT};
+use downloads::DownloadStore;
+use swaps::SwapStore;
+use evals::EvalStore;
+use batches::BatchStore;
+use files::FileStore;
+use keys::KeyStore;
+use metering::UsageMeter;
+use watchdog::{Admission, InFlight, ShedAction, WatchdogConfig, BUILTIN_MODELS};
 
 pub struct CoreEngine {
-    llm_runtimes: Arc<RwLock<HashMap<String, Arc<dyn LlmRuntime>>>>,
-    embedding_runtimes: Arc<RwLock<HashMap<String, Arc<dyn EmbeddingRuntime>>>>,
-    multimodal_runtimes: Arc<RwLock<HashMap<String, Arc<dyn MultimodalRuntime>>>>,
-    image_runtimes: Arc<RwLock<HashMap<String, Arc<dyn ImageGenRuntime>>>>,
-    request_sender: mpsc::Sender<EngineRequest>,
+    registry: Arc<ModelRegistry>,
+    request_sender: mpsc::Sender<Queued>,
     response_cache: Cache<String, ChatCompletionResponse>,
+    // Non-streaming chat generations in progress, by client and cache key, for identical
+    // requests of the same client to wait on; entries go as soon as the generation ends
+    in_flight: Cache<String, ChatCompletionResponse>,
+    // Replicas' common cache, consulted when `response_cache` misses
+    shared_responses: Option<SharedResponses>,
+    response_occupancy: Arc<Occupancy>,
+    evals: EvalStore,
+    downloads: DownloadStore,
+    swaps: SwapStore,
+    batches: BatchStore,
+    files: FileStore,
+    keys: Arc<KeyStore>,
+    canaries: Arc<CanaryRouter>,
+    fallbacks: FallbackRouter,
+    splits: Arc<TrafficSplitter>,
+    admission: Arc<Admission>,
+    chat_templates: Arc<RwLock<HashMap<String, Arc<ChatTemplate>>>>,
+    cost_model: Arc<CostModel>,
+    usage_ledger: Arc<UsageLedger>,
+    metering: Arc<UsageMeter>,
+    key_concurrency: Arc<KeyConcurrency>,
+    backlog: Arc<Backlog>,
+    inflight: Arc<InFlight>,
+    // Set by `shutdown`; new requests are turned away
+    draining: AtomicBool,
+    events: EventBus,
+    safety: SafetyLedger,
+    config: tokio::sync::Mutex<reload::ConfigSource>,
+}
+
+// Shared state handed to the worker pool
+#[derive(Clone)]
+struct WorkerContext {
+    registry: Arc<ModelRegistry>,
+    canaries: Arc<CanaryRouter>,
+    default_timeout: Option<Duration>,
+    inflight: Arc<InFlight>,
+    chat_templates: Arc<RwLock<HashMap<String, Arc<ChatTemplate>>>>,
+    cost_model: Arc<CostModel>,
+    usage_ledger: Arc<UsageLedger>,
+    metering: Arc<UsageMeter>,
+    key_concurrency: Arc<KeyConcurrency>,
+    backlog: Arc<Backlog>,
+    events: EventBus,
+    embedding_batcher: Arc<EmbeddingBatcher>,
+    continuous_batcher: Arc<ContinuousBatcher>,
+}
+
+// A request on its way to a worker, holding its place in the backlog
+struct Queued {
+    request: EngineRequest,
+    slot: Arc<Slot>,
+    // Told when a worker takes the request up; set when its caller waits at most QUEUE_MAX_WAIT_MS
+    dispatched: Option<oneshot::Sender<()>>,
 }
 
 pub enum EngineRequest {
     ChatCompletion {
         request: ChatCompletionRequest,
-        response_sender: Option<mpsc::Sender<Result<ChatCompletionResponse, String>>>,
+        response_sender: Option<mpsc::Sender<Result<ChatCompletionResponse, EngineError>>>,
         stream_sender: Option<mpsc::Sender<String>>,
+        cancel: CancellationToken,
     },
     Embeddings {
         request: EmbeddingsRequest,
-        response_sender: mpsc::Sender<Result<EmbeddingsResponse, String>>,
+        response_sender: mpsc::Sender<Result<EmbeddingsResponse, EngineError>>,
+    },
+    Rerank {
+        request: RerankRequest,
+        response_sender: mpsc::Sender<Result<RerankResponse, EngineError>>,
+    },
+    Transcription {
+        request: TranscriptionRequest,
+        response_sender: mpsc::Sender<Result<VerboseTranscriptionResponse, EngineError>>,
+    },
+    Speech {
+        request: SpeechRequest,
+        response_sender: mpsc::Sender<Result<Speech, EngineError>>,
     },
     Images {
         request: ImagesGenerationRequest,
-        response_sender: mpsc::Sender<Result<Vec<Vec<u8>>, String>>,
+        progress_sender: Option<mpsc::Sender<ImageProgress>>,
+        response_sender: mpsc::Sender<Result<Vec<Vec<u8>>, EngineError>>,
+    },
+    ImageEdit {
+        request: ImagesEditRequest,
+        response_sender: mpsc::Sender<Result<Vec<Vec<u8>>, EngineError>>,
+    },
+    ImageVariation {
+        request: ImagesVariationRequest,
+        response_sender: mpsc::Sender<Result<Vec<Vec<u8>>, EngineError>>,
     },
 }
 
+impl EngineRequest {
+    // The `endpoint` label of its metrics
+    fn endpoint(&self) -> &'static str {
+        match self {
+            EngineRequest::ChatCompletion { .. } => "chat",
+            EngineRequest::Embeddings { .. } => "embeddings",
+            EngineRequest::Rerank { .. } => "rerank",
+            EngineRequest::Transcription { .. } => "audio_transcriptions",
+            EngineRequest::Speech { .. } => "audio_speech",
+            EngineRequest::Images { .. } => "images",
+            EngineRequest::ImageEdit { .. } => "image_edits",
+            EngineRequest::ImageVariation { .. } => "image_variations",
+        }
+    }
+
+    fn client(&self) -> String {
+        let client = match self {
+            EngineRequest::ChatCompletion { request, .. } => &request.client_id,
+            EngineRequest::Embeddings { request, .. } => &request.client_id,
+            EngineRequest::Rerank { request, .. } => &request.client_id,
+            EngineRequest::Transcription { request, .. } => &request.client_id,
+            EngineRequest::Speech { request, .. } => &request.client_id,
+            EngineRequest::Images { request, .. } => &request.client_id,
+            EngineRequest::ImageEdit { request, .. } => &request.client_id,
+            EngineRequest::ImageVariation { request, .. } => &request.client_id,
+        };
+        client.clone().unwrap_or_else(|| ANONYMOUS_CLIENT.to_string())
+    }
+
+    fn priority(&self) -> Priority {
+        let priority = match self {
+            EngineRequest::ChatCompletion { request, .. } => request.priority,
+            EngineRequest::Embeddings { request, .. } => request.priority,
+            EngineRequest::Rerank { request, .. } => request.priority,
+            EngineRequest::Transcription { request, .. } => request.priority,
+            EngineRequest::Speech { request, .. } => request.priority,
+            EngineRequest::Images { request, .. } => request.priority,
+            EngineRequest::ImageEdit { request, .. } => request.priority,
+            EngineRequest::ImageVariation { request, .. } => request.priority,
+        };
+        priority.unwrap_or_default()
+    }
+
+    fn estimate_cost(&self, cost_model: &CostModel) -> f64 {
+        match self {
+            EngineRequest::ChatCompletion { request, .. } => {
+                let prompt = render_chat_prompt(&request.messages).prompt;
+                cost_model.estimate_chat(&request.model, request.max_tokens, approximate_token_count(&prompt))
+            }
+            EngineRequest::Embeddings { request, .. } => {
+                cost_model.estimate_embeddings(EmbeddingBatch::from(request.input.clone()).approximate_tokens())
+            }
+            EngineRequest::Rerank { request, .. } => cost_model.estimate_embeddings(rerank::approximate_tokens(request)),
+            EngineRequest::Transcription { request, .. } => cost_model.estimate_audio(duration_secs(&request.samples)),
+            EngineRequest::Speech { request, .. } => cost_model.estimate_speech(approximate_token_count(&request.input)),
+            EngineRequest::Images { request, .. } => cost_model.estimate_images(request.n),
+            EngineRequest::ImageEdit { request, .. } => cost_model.estimate_images(request.n),
+            EngineRequest::ImageVariation { request, .. } => cost_model.estimate_images(request.n),
+        }
+    }
+}
+
+// Images per edit or variation request, as OpenAI allows
+fn validate_image_count(n: u32) -> Result<(), String> {
+    if (1..=10).contains(&n) { Ok(()) } else { Err("n must be between 1 and 10".to_string()) }
+}
+
+/// Bounds a diffusion backend can honour in reasonable time.
+pub fn validate_image_sampling(request: &ImagesGenerationRequest) -> Result<(), String> {
+    if let Some(steps) = request.steps
+        && !(1..=150).contains(&steps)
+    {
+        return Err("steps must be between 1 and 150".to_string());
+    }
+    if let Some(scale) = request.guidance_scale
+        && !(0.0..=30.0).contains(&scale)
+    {
+        return Err("guidance_scale must be between 0 and 30".to_string());
+    }
+    Ok(())
+}
+
+/// Checks a chat request's grammar and sampling controls before it is queued.
+pub fn validate_chat_request(request: &ChatCompletionRequest) -> Result<(), String> {
+    if let Some(grammar) = &request.grammar {
+        crate::runtime::grammar::validate(grammar)?;
+    }
+    let fractions = [("top_p", request.top_p), ("typical_p", request.typical_p)];
+    for (name, value) in fractions {
+        if value.is_some_and(|v| !(v > 0.0 && v <= 1.0)) {
+            return Err(format!("{} must be greater than 0 and at most 1", name));
+        }
+    }
+    if request.min_p.is_some_and(|v| !(0.0..=1.0).contains(&v)) {
+        return Err("min_p must be between 0 and 1".to_string());
+    }
+    let positives = [("repetition_penalty", request.repetition_penalty), ("mirostat_tau", request.mirostat_tau), ("mirostat_eta", request.mirostat_eta)];
+    for (name, value) in positives {
+        if value.is_some_and(|v| !(v.is_finite() && v > 0.0)) {
+            return Err(format!("{} must be a positive number", name));
+        }
+    }
+    if request.mirostat.is_some_and(|v| v > 2) {
+        return Err("mirostat must be 0, 1 or 2".to_string());
+    }
+    if let Some(stop) = &request.stop {
+        let stops = stop.to_vec();
+        if stops.len() > MAX_STOP_SEQUENCES {
+            return Err(format!("stop may have at most {} sequences", MAX_STOP_SEQUENCES));
+        }
+        if stops.iter().any(String::is_empty) {
+            return Err("stop sequences must not be empty".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Sampling settings of a chat request; `max_tokens`, `temperature` and `top_p` as OpenAI
+// defines them, the rest off unless set
+fn generation_options(request: &ChatCompletionRequest) -> GenerationOptions {
+    let mut options = GenerationOptions::from_request(request.max_tokens, request.temperature, request.top_p);
+    options.top_k = request.top_k.unwrap_or(0);
+    options.min_p = request.min_p.unwrap_or(0.0);
+    options.typical_p = request.typical_p.unwrap_or(1.0);
+    options.repetition_penalty = request.repetition_penalty.unwrap_or(1.0);
+    options.mirostat = request.mirostat.filter(|&v| v > 0).map(|version| Mirostat {
+        version,
+        tau: request.mirostat_tau.unwrap_or(5.0),
+        eta: request.mirostat_eta.unwrap_or(0.1),
+    });
+    options.grammar = request.grammar.clone();
+    options.stop = request.stop.as_ref().map(StopSequences::to_vec).unwrap_or_default();
+    options
+}
+
+// Model paths that name an OpenAI-compatible server rather than local weights
+fn is_upstream_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// OpenAI's finish_reason for a generation that ended by itself: "length" once the reply used
+// up `max_tokens`, "stop" when the model ended it (EOS, an end-of-turn token or a stop sequence)
+fn finish_reason(completion_tokens: u32, max_tokens: u32) -> &'static str {
+    if completion_tokens >= max_tokens { "length" } else { "stop" }
+}
+
+// The request's stop sequence the runtime ended generation at; end-of-turn markers some
+// runtimes match alongside them do not count
+fn matched_stop(options: &GenerationOptions) -> Option<String> {
+    options.matched_stop.get().filter(|stop| options.stop.contains(stop))
+}
+
+/// Prefix of the error returned when requests are turned away while memory is short.
+pub const MEMORY_PRESSURE: &str = "Server is under memory pressure";
+
+/// Prefix of the error returned when a non-streaming generation exceeds its timeout.
+pub const GENERATION_TIMEOUT: &str = "Generation timed out";
+
+/// Error `process_chat_request` returns once a streaming request has been handed to its sender.
+pub const STREAM_VIA_SENDER: &str = "Streaming response handled via sender";
+
+fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+    Usage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens }
+}
+
+// A request may shorten the server-wide timeout but never extend it
+fn effective_timeout(request_ms: Option<u64>, server: Option<Duration>) -> Option<Duration> {
+    let requested = request_ms.map(Duration::from_millis);
+    match (requested, server) {
+        (Some(r), Some(s)) => Some(r.min(s)),
+        (r, s) => r.or(s),
+    }
+}
+
+async fn sleep_opt(duration: Option<Duration>) {
+    match duration {
+        Some(d) => tokio::time::sleep(d).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Tells whoever waits for a chat request that it failed: the caller of a non-streaming one, or
+// the stream of a streaming one
+async fn chat_failed(
+    response_sender: Option<mpsc::Sender<Result<ChatCompletionResponse, EngineError>>>,
+    stream_sender: Option<mpsc::Sender<String>>,
+    error: EngineError,
+) {
+    if let Some(resp_tx) = response_sender {
+        let _ = resp_tx.send(Err(error)).await;
+    } else if let Some(stream_tx) = stream_sender {
+        let _ = stream_tx.send(ChatStreamError::chunk(error)).await;
+    }
+}
+
+impl Default for CoreEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CoreEngine {
+    /// An engine keeping its keys, files, batches and usage in the storage configured by
+    /// STORAGE_BACKEND.
     pub fn new() -> Self {
+        Self::with_storage(crate::storage::global())
+    }
+
+    /// An engine keeping everything it persists under prefixes of `storage`.
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
         let (request_sender, request_receiver) = mpsc::channel(100); // Channel for incoming requests
 
-        let mut llm_map_init: HashMap<String, Arc<dyn LlmRuntime>> = HashMap::new();
-        // Always have a fallback dummy runtime for development
-        llm_map_init.insert("dummy-model".to_string(), Arc::new(DummyRuntime::new()));
+        let mut models: Entries = HashMap::new();
+        // Always have a fallback dummy runtime for development; it serves text and vision
+        let dummy = Arc::new(DummyRuntime::new());
+        models.insert(
+            "dummy-model".to_string(),
+            ModelEntry::new("llm", "dummy", Runtimes { llm: Some(dummy.clone()), multimodal: Some(dummy), ..Runtimes::default() }),
+        );
         // Attempt to load a real llama.cpp runtime if a valid path is provided via env
         #[cfg(feature = "llama")]
         {
-            if let Ok(model_path) = std::env::var("LLAMA_MODEL_PATH") {
-                if let Ok(llama_runtime) = LlamaCppRuntime::new(&model_path) {
-                    llm_map_init.insert("llama-cpp".to_string(), Arc::new(llama_runtime));
-                } else {
-                    eprintln!("Failed to load LlamaCppRuntime from LLAMA_MODEL_PATH; continuing with dummy-model.");
+            if let Ok(model_path) = crate::config::var("LLAMA_MODEL_PATH") {
+                // ENV: LLAMA_CONTEXT_SHIFT=1 enables context shifting for this model;
+                // LLAMA_N_GPU_LAYERS, LLAMA_MAIN_GPU and LLAMA_SPLIT_MODE set its GPU offload;
+                // LLAMA_N_CTX, LLAMA_N_BATCH, LLAMA_ROPE_FREQ_BASE and LLAMA_ROPE_FREQ_SCALE its context
+                let env = |name: &str| crate::config::var(name).ok();
+                let options = ModelOptions {
+                    context_shift: matches!(env("LLAMA_CONTEXT_SHIFT").as_deref(), Some("1") | Some("true")),
+                    n_gpu_layers: env("LLAMA_N_GPU_LAYERS").and_then(|v| v.parse().ok()),
+                    main_gpu: env("LLAMA_MAIN_GPU").and_then(|v| v.parse().ok()),
+                    split_mode: env("LLAMA_SPLIT_MODE").and_then(|v| v.parse().ok()),
+                    n_ctx: env("LLAMA_N_CTX").and_then(|v| v.parse().ok()),
+                    n_batch: env("LLAMA_N_BATCH").and_then(|v| v.parse().ok()),
+                    rope_freq_base: env("LLAMA_ROPE_FREQ_BASE").and_then(|v| v.parse().ok()),
+                    rope_freq_scale: env("LLAMA_ROPE_FREQ_SCALE").and_then(|v| v.parse().ok()),
+                    ..ModelOptions::default()
+                };
+                let batch_sequences = ContinuousBatchingConfig::from_env().max_sequences;
+                match options.validate().and_then(|_| LlamaCppRuntime::new(&model_path, &options, batch_sequences)) {
+                    Ok(llama_runtime) => {
+                        models.insert("llama-cpp".to_string(), ModelEntry::new("llm", "llama.cpp", Runtimes::llm(Arc::new(llama_runtime))).with_footprint(Footprint::estimate(Some(&model_path), &options)));
+                    }
+                    Err(e) => eprintln!("Failed to load LlamaCppRuntime from LLAMA_MODEL_PATH ({}); continuing with dummy-model.", e),
                 }
             }
         }
-        // Build multimodal runtimes map alongside LLM map
-        let mut mm_map_init: HashMap<String, Arc<dyn MultimodalRuntime>> = HashMap::new();
-        // Ensure dummy exists in both maps
-        if !llm_map_init.contains_key("dummy-model") {
-            llm_map_init.insert("dummy-model".to_string(), Arc::new(DummyRuntime::new()));
+        // ENV: CANDLE_MODEL_PATH, a safetensors model directory served as "candle"
+        #[cfg(feature = "candle")]
+        if let Ok(model_path) = crate::config::var("CANDLE_MODEL_PATH") {
+            match crate::runtime::candle::CandleRuntime::new(&model_path, &ModelOptions::default()) {
+                Ok(candle_runtime) => {
+                    models.insert("candle".to_string(), ModelEntry::new("llm", "candle", Runtimes::llm(Arc::new(candle_runtime))).with_footprint(Footprint::estimate(Some(&model_path), &ModelOptions::default())));
+                }
+                Err(e) => eprintln!("Failed to load CandleRuntime from CANDLE_MODEL_PATH ({}); continuing without it.", e),
+            }
+        }
+        // ENV: ONNX_LLM_MODEL_PATH, an ONNX decoder export served as "onnx-llm"
+        #[cfg(feature = "onnx_tokenizer")]
+        if let Ok(model_path) = crate::config::var("ONNX_LLM_MODEL_PATH") {
+            match crate::runtime::onnx_llm::OnnxLlmRuntime::new(&model_path, &ModelOptions::default()) {
+                Ok(onnx_runtime) => {
+                    models.insert("onnx-llm".to_string(), ModelEntry::new("llm", "onnx", Runtimes::llm(Arc::new(onnx_runtime))).with_footprint(Footprint::estimate(Some(&model_path), &ModelOptions::default())));
+                }
+                Err(e) => eprintln!("Failed to load OnnxLlmRuntime from ONNX_LLM_MODEL_PATH ({}); continuing without it.", e),
+            }
+        }
+        // ENV: REMOTE_BASE_URL, an OpenAI-compatible upstream served as "remote" (REMOTE_MODEL
+        // names the upstream model, REMOTE_API picks chat or completions, REMOTE_TIMEOUT_MS caps
+        // each request, REMOTE_API_KEY authenticates); REMOTE_EMBEDDING_MODEL also serves its
+        // embeddings as "remote-embedding"
+        let remote_options = |upstream_model: Option<String>| ModelOptions {
+            upstream_model,
+            upstream_api: crate::config::var("REMOTE_API").ok().and_then(|v| v.parse().ok()),
+            upstream_timeout_ms: crate::config::var("REMOTE_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()).filter(|&ms| ms > 0),
+            ..ModelOptions::default()
+        };
+        let remote_base_url = crate::config::var("REMOTE_BASE_URL").ok();
+        if let Some(base_url) = &remote_base_url {
+            match RemoteConfig::new(base_url, "remote", &remote_options(crate::config::var("REMOTE_MODEL").ok())) {
+                Ok(config) => {
+                    models.insert("remote".to_string(), ModelEntry::new("llm", "remote", Runtimes::llm(Arc::new(RemoteRuntime::new(config)))));
+                }
+                Err(e) => eprintln!("Failed to configure RemoteRuntime from REMOTE_BASE_URL ({}); continuing without it.", e),
+            }
         }
-        mm_map_init.insert("dummy-model".to_string(), Arc::new(DummyRuntime::new()));
-
-        let llm_runtimes: Arc<RwLock<HashMap<String, Arc<dyn LlmRuntime>>>> = Arc::new(RwLock::new(llm_map_init));
 
         // Embedding runtimes
-        let mut embed_map_init: HashMap<String, Arc<dyn EmbeddingRuntime>> = HashMap::new();
-        embed_map_init.insert("dummy-embedding".to_string(), Arc::new(DummyEmbeddingRuntime::new(384)));
+        models.insert("dummy-embedding".to_string(), ModelEntry::new("embedding", "dummy", Runtimes::embedding(Arc::new(DummyEmbeddingRuntime::new(384)))));
         #[cfg(feature = "onnx")]
-        if let Ok(onnx_model) = std::env::var("ONNX_EMBEDDING_MODEL_PATH") {
-            // Dimension should ideally be inferred; keep 384 default
-            if let Ok(rt) = OnnxEmbeddingRuntime::new(&onnx_model, 384) {
-                embed_map_init.insert("onnx-embedding".to_string(), Arc::new(rt));
+        if let Ok(onnx_model) = crate::config::var("ONNX_EMBEDDING_MODEL_PATH") {
+            // ENV: ONNX_EMBEDDING_DIM, for models that do not declare their output size
+            let dim = crate::config::var("ONNX_EMBEDDING_DIM").ok().and_then(|v| v.parse().ok());
+            match OnnxEmbeddingRuntime::new(&onnx_model, dim) {
+                Ok(rt) => { models.insert("onnx-embedding".to_string(), ModelEntry::new("embedding", "onnx", Runtimes::embedding(Arc::new(rt))).with_footprint(Footprint::estimate(Some(&onnx_model), &ModelOptions::default()))); }
+                Err(e) => eprintln!("Failed to load OnnxEmbeddingRuntime from ONNX_EMBEDDING_MODEL_PATH ({}); continuing without it.", e),
+            }
+        }
+        #[cfg(feature = "clip")]
+        if let Ok(clip_dir) = crate::config::var("CLIP_MODEL_DIR") {
+            match crate::runtime::clip::ClipEmbeddingRuntime::new(&clip_dir) {
+                Ok(rt) => { models.insert("clip".to_string(), ModelEntry::new("embedding", "clip", Runtimes::embedding(Arc::new(rt))).with_footprint(Footprint::estimate(Some(&clip_dir), &ModelOptions::default()))); }
+                Err(e) => eprintln!("Failed to load ClipEmbeddingRuntime from CLIP_MODEL_DIR ({}); continuing without it.", e),
+            }
+        }
+        if let (Some(base_url), Ok(model)) = (&remote_base_url, crate::config::var("REMOTE_EMBEDDING_MODEL")) {
+            match RemoteConfig::new(base_url, "remote-embedding", &remote_options(Some(model))) {
+                Ok(config) => {
+                    models.insert("remote-embedding".to_string(), ModelEntry::new("embedding", "remote", Runtimes::embedding(Arc::new(RemoteRuntime::new(config)))));
+                }
+                Err(e) => eprintln!("Failed to configure RemoteRuntime embeddings from REMOTE_EMBEDDING_MODEL ({}); continuing without it.", e),
+            }
+        }
+        // Rerank runtimes
+        models.insert("dummy-rerank".to_string(), ModelEntry::new("rerank", "dummy", Runtimes::rerank(Arc::new(DummyRerankRuntime::new()))));
+        #[cfg(feature = "onnx")]
+        if let Ok(onnx_model) = crate::config::var("ONNX_RERANK_MODEL_PATH") {
+            match OnnxRerankRuntime::new(&onnx_model) {
+                Ok(rt) => { models.insert("onnx-rerank".to_string(), ModelEntry::new("rerank", "onnx", Runtimes::rerank(Arc::new(rt))).with_footprint(Footprint::estimate(Some(&onnx_model), &ModelOptions::default()))); }
+                Err(e) => eprintln!("Failed to load OnnxRerankRuntime from ONNX_RERANK_MODEL_PATH ({}); continuing without it.", e),
+            }
+        }
+        // Audio transcription runtimes
+        models.insert("dummy-audio".to_string(), ModelEntry::new("audio", "dummy", Runtimes::audio(Arc::new(DummyAudioRuntime::new()))));
+        #[cfg(feature = "whisper")]
+        if let Ok(whisper_model) = crate::config::var("WHISPER_MODEL_PATH") {
+            match WhisperRuntime::new(&whisper_model) {
+                Ok(rt) => { models.insert("whisper".to_string(), ModelEntry::new("audio", "whisper", Runtimes::audio(Arc::new(rt))).with_footprint(Footprint::estimate(Some(&whisper_model), &ModelOptions::default()))); }
+                Err(e) => eprintln!("Failed to load WhisperRuntime from WHISPER_MODEL_PATH ({}); continuing without it.", e),
             }
         }
-        let embedding_runtimes: Arc<RwLock<HashMap<String, Arc<dyn EmbeddingRuntime>>>> = Arc::new(RwLock::new(embed_map_init));
+        // Speech synthesis runtimes
+        models.insert("dummy-tts".to_string(), ModelEntry::new("tts", "dummy", Runtimes::tts(Arc::new(DummyTtsRuntime::new()))));
         // Image runtimes (Phase 4 scaffold)
-        let mut img_map_init: HashMap<String, Arc<dyn ImageGenRuntime>> = HashMap::new();
-        img_map_init.insert("dummy-image".to_string(), Arc::new(crate::runtime::dummy_image::DummyImageRuntime::new()));
-        let image_runtimes: Arc<RwLock<HashMap<String, Arc<dyn ImageGenRuntime>>>> = Arc::new(RwLock::new(img_map_init));
+        models.insert(
+            "dummy-image".to_string(),
+            ModelEntry::new("image", "dummy", Runtimes::image(Arc::new(crate::runtime::dummy_image::DummyImageRuntime::new()))),
+        );
+        #[cfg(feature = "stable_diffusion")]
+        if let Ok(sd_model) = crate::config::var("SD_MODEL_PATH") {
+            match OnnxStableDiffusionRuntime::new(&sd_model) {
+                Ok(rt) => { models.insert("stable-diffusion".to_string(), ModelEntry::new("image", "stable-diffusion", Runtimes::image(Arc::new(rt))).with_footprint(Footprint::estimate(Some(&sd_model), &ModelOptions::default()))); }
+                Err(e) => eprintln!("Failed to load OnnxStableDiffusionRuntime from SD_MODEL_PATH ({}); continuing without it.", e),
+            }
+        }
         #[cfg(feature = "llava")]
         {
             if let (Ok(vision), Ok(proj), Ok(llm)) = (
-                std::env::var("LLAVA_VISION_MODEL_PATH"),
-                std::env::var("LLAVA_PROJECTION_PATH"),
-                std::env::var("LLAMA_MODEL_PATH"),
+                crate::config::var("LLAVA_VISION_MODEL_PATH"),
+                crate::config::var("LLAVA_PROJECTION_PATH"),
+                crate::config::var("LLAMA_MODEL_PATH"),
             ) {
-                if let Ok(rt) = LlavaRuntime::new(&vision, &proj, &llm) {
-                    mm_map_init.insert("llava".to_string(), Arc::new(rt));
+                match LlavaRuntime::new(&vision, &proj, &llm) {
+                    Ok(rt) => {
+                        let footprint = Footprint::estimate(Some(&[vision, proj, llm].join(",")), &ModelOptions::default());
+                        models.insert("llava".to_string(), ModelEntry::new("multimodal", "llava", Runtimes::multimodal(Arc::new(rt))).with_footprint(footprint));
+                    }
+                    Err(e) => eprintln!("Failed to load LlavaRuntime from LLAVA_VISION_MODEL_PATH and LLAVA_PROJECTION_PATH ({}); continuing without it.", e),
                 }
             }
         }
-        let multimodal_runtimes: Arc<RwLock<HashMap<String, Arc<dyn MultimodalRuntime>>>> = Arc::new(RwLock::new(mm_map_init));
+        let events = EventBus::default();
+        let registry = Arc::new(ModelRegistry::new(models, events.clone()));
 
         // Clone runtimes for the worker pool and wrap in Arc for shared access
-        let worker_llm = llm_runtimes.clone();
-        let worker_embed = embedding_runtimes.clone();
-        let worker_mm = multimodal_runtimes.clone();
-        let worker_img = image_runtimes.clone();
-
         // Configure concurrency limit (ENV: ENGINE_WORKERS), default to available_parallelism or 4
-        let workers: usize = std::env::var("ENGINE_WORKERS")
+        let workers: usize = crate::config::var("ENGINE_WORKERS")
             .ok()
             .and_then(|v| v.parse().ok())
+            .filter(|&n| n > 0)
             .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
             .unwrap_or(4);
         let semaphore = Arc::new(Semaphore::new(workers));
+        let chat_templates = Arc::new(RwLock::new(HashMap::new()));
+        let cost_model = Arc::new(CostModel::default());
+        let usage_ledger = Arc::new(UsageLedger::default());
+        let keys = Arc::new(KeyStore::new(Arc::new(Scoped::new(storage.clone(), "keys"))));
+        let metering = Arc::new(UsageMeter::new(Arc::new(Scoped::new(storage.clone(), "usage")), keys.clone()));
+        let key_concurrency = Arc::new(KeyConcurrency::new(workers));
+        let backlog = Arc::new(Backlog::default());
+        let continuous_batcher = Arc::new(ContinuousBatcher::new(ContinuousBatchingConfig::from_env()));
+        let fallbacks = FallbackRouter::new(&registry, chat_templates.clone(), continuous_batcher.clone(), events.clone());
+        if let Ok(value) = crate::config::var("MODEL_FALLBACKS") {
+            let mut models = registry.try_write().expect("registry is free at startup");
+            match FallbackRouter::parse_env(&value) {
+                Ok(chains) => {
+                    for chain in chains {
+                        let name = chain.model.clone();
+                        if let Err(e) = fallbacks.insert(chain, &mut models) {
+                            eprintln!("Failed to set up fallback chain {} from MODEL_FALLBACKS ({}); continuing without it.", name, e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Invalid MODEL_FALLBACKS ({}); continuing without fallback chains.", e),
+            }
+        }
+        let splits = Arc::new(TrafficSplitter::default());
+        let canaries = Arc::new(CanaryRouter::with_events(events.clone()).with_splits(splits.clone()));
+        if let Ok(value) = crate::config::var("MODEL_SPLITS") {
+            let models = registry.try_write().expect("registry is free at startup");
+            match TrafficSplitter::parse_env(&value) {
+                Ok(requests) => {
+                    for split in requests {
+                        let name = split.model.clone();
+                        let missing = split.variants.iter().find(|v| models.get(&v.model).is_none_or(|entry| !entry.runtimes.supports(Capability::Chat)));
+                        let result = match missing {
+                            Some(variant) => Err(model_not_found(&variant.model)),
+                            None => splits.insert(split).map(|_| ()),
+                        };
+                        if let Err(e) = result {
+                            eprintln!("Failed to set up traffic split {} from MODEL_SPLITS ({}); continuing without it.", name, e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Invalid MODEL_SPLITS ({}); continuing without traffic splits.", e),
+            }
+        }
+
+        // Server-wide generation timeout (ENV: GENERATION_TIMEOUT_SECS, default 300; 0 disables)
+        let default_timeout = match crate::config::var("GENERATION_TIMEOUT_SECS").ok().and_then(|v| v.parse::<u64>().ok()) {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(Duration::from_secs(300)),
+        };
+
+        // Clone runtimes and shared state for the worker pool
+        let inflight = Arc::new(InFlight::default());
+        let worker_ctx = WorkerContext {
+            registry: registry.clone(),
+            canaries: canaries.clone(),
+            default_timeout,
+            inflight: inflight.clone(),
+            chat_templates: chat_templates.clone(),
+            cost_model: cost_model.clone(),
+            usage_ledger: usage_ledger.clone(),
+            metering: metering.clone(),
+            key_concurrency: key_concurrency.clone(),
+            backlog: backlog.clone(),
+            events: events.clone(),
+            embedding_batcher: Arc::new(EmbeddingBatcher::new(BatchingConfig::from_env())),
+            continuous_batcher,
+        };
+        let response_occupancy = Arc::new(Occupancy::default());
+        let evictions = response_occupancy.clone();
+        let response_cache = Cache::builder()
+            .max_capacity(response_cache_entries())
+            .time_to_live(response_cache_ttl())
+            .eviction_listener(move |_, response: ChatCompletionResponse, _| evictions.evicted(&response))
+            .build();
+        let shared_responses = match crate::cache::shared_backend() {
+            Ok(backend) => backend.map(|backend| SharedResponses::new(backend, response_cache_ttl())),
+            Err(e) => {
+                eprintln!("Invalid response cache configuration ({}); continuing without the shared cache.", e);
+                None
+            }
+        };
+        let admission = Arc::new(Admission::default());
 
-        tokio::spawn(Self::worker_pool(worker_llm, worker_embed, worker_mm, worker_img, request_receiver, semaphore));
+        if let Some(config) = WatchdogConfig::from_env() {
+            tokio::spawn(Self::memory_watchdog(config, worker_ctx.clone(), response_cache.clone(), admission.clone()));
+        }
+        tokio::spawn(Self::idle_sweeper(Arc::downgrade(&registry), events.clone()));
+        tokio::spawn(Self::health_checker(Arc::downgrade(&registry), events.clone()));
+        tokio::spawn(KeyStore::sync_periodically(Arc::downgrade(&keys)));
+        tokio::spawn(Self::worker_pool(worker_ctx, request_receiver, semaphore));
 
         CoreEngine {
-            llm_runtimes,
-            embedding_runtimes,
-            multimodal_runtimes,
-            image_runtimes,
+            registry,
             request_sender,
-            response_cache: Cache::builder()
-                .max_capacity(10_000)
-                .time_to_live(std::time::Duration::from_secs(60))
-                .build(),
+            response_cache,
+            in_flight: Cache::builder().build(),
+            shared_responses,
+            response_occupancy,
+            evals: EvalStore::new(),
+            downloads: DownloadStore::new(),
+            swaps: SwapStore::new(),
+            batches: BatchStore::new(Arc::new(Scoped::new(storage.clone(), "batches"))),
+            files: FileStore::new(Arc::new(Scoped::new(storage, "files"))),
+            keys,
+            canaries,
+            fallbacks,
+            splits,
+            admission,
+            chat_templates,
+            cost_model,
+            usage_ledger,
+            metering,
+            key_concurrency,
+            backlog,
+            inflight,
+            draining: AtomicBool::new(false),
+            events,
+            safety: SafetyLedger::new(SafetyPolicy::from_env()),
+            config: Default::default(),
         }
     }
 
+    /// Samples process RSS and sheds load progressively: above the soft limit new requests are
+    /// refused; each consecutive tick above the hard limit escalates from evicting caches, to
+    /// cancelling the newest generations, to unloading the least recently used model.
+    async fn memory_watchdog(
+        config: WatchdogConfig,
+        ctx: WorkerContext,
+        response_cache: Cache<String, ChatCompletionResponse>,
+        admission: Arc<Admission>,
+    ) {
+        let mut hard_ticks: u32 = 0;
+        let mut interval = tokio::time::interval(config.interval);
+        loop {
+            interval.tick().await;
+            let Some(rss) = watchdog::current_rss_bytes() else { continue };
+            watchdog::record_rss(rss);
+
+            let over_soft = rss >= config.soft_limit_bytes;
+            if over_soft != admission.is_shedding() {
+                tracing::warn!("memory watchdog: rss={}MB, admitting new requests: {}", rss / (1024 * 1024), !over_soft);
+                ctx.events.publish(EngineEvent::LoadShedding { active: over_soft });
+            }
+            admission.set_shedding(over_soft);
+            if rss < config.hard_limit_bytes {
+                hard_ticks = 0;
+                continue;
+            }
+
+            hard_ticks += 1;
+            let action = ShedAction::for_tick(hard_ticks);
+            watchdog::record_action(action);
+            match action {
+                ShedAction::EvictCaches => {
+                    response_cache.invalidate_all();
+                    ctx.events.publish(EngineEvent::CacheEvicted { cache: "response".to_string(), reason: "memory_pressure".to_string() });
+                    tracing::warn!("memory watchdog: evicted response cache");
+                }
+                ShedAction::CancelGenerations => {
+                    let cancelled = ctx.inflight.cancel_newest((ctx.inflight.len() / 2).max(1));
+                    tracing::warn!("memory watchdog: cancelled {} in-flight generations", cancelled);
+                }
+                ShedAction::UnloadModel => {
+                    if let Some(victim) = ctx.registry.least_recently_used(BUILTIN_MODELS).await {
+                        let kind = ctx.registry.remove(&victim).await.map_or_else(|| "any".to_string(), |entry| entry.kind);
+                        ctx.events.publish(EngineEvent::ModelUnloaded {
+                            model: victim.clone(),
+                            kind,
+                            reason: "memory_pressure".to_string(),
+                        });
+                        tracing::error!("memory watchdog: unloaded least recently used model {}", victim);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Unloads models that went unused for their idle TTL, until the engine is dropped.
+    async fn idle_sweeper(registry: Weak<ModelRegistry>, events: EventBus) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let Some(registry) = registry.upgrade() else { return };
+            unload_idle(&registry, &events).await;
+        }
+    }
+
+    /// Unloads the models idle past their TTL now rather than at the next check; they are
+    /// loaded again by the next request for them. Returns their names.
+    pub async fn unload_idle_models(&self) -> Vec<String> {
+        unload_idle(&self.registry, &self.events).await
+    }
+
     async fn worker_pool(
-        llm_runtimes: Arc<RwLock<HashMap<String, Arc<dyn LlmRuntime>>>>,
-        embedding_runtimes: Arc<RwLock<HashMap<String, Arc<dyn EmbeddingRuntime>>>>,
-        multimodal_runtimes: Arc<RwLock<HashMap<String, Arc<dyn MultimodalRuntime>>>>,
-        image_runtimes: Arc<RwLock<HashMap<String, Arc<dyn ImageGenRuntime>>>>,
-        mut request_receiver: mpsc::Receiver<EngineRequest>,
+        ctx: WorkerContext,
+        mut request_receiver: mpsc::Receiver<Queued>,
         semaphore: Arc<Semaphore>,
     ) {
-        while let Some(req) = request_receiver.recv().await {
-            let llm_map = llm_runtimes.clone();
-            let embed_map = embedding_runtimes.clone();
-            let mm_map = multimodal_runtimes.clone();
-            let img_map = image_runtimes.clone();
-            let semaphore_clone = semaphore.clone();
-            // Acquire a permit and process the request concurrently
+        // Requests wait in cost-weighted fair queues, one per priority, and are dispatched as
+        // worker permits free up, highest priority first, moving up as they age
+        let mut queue = PriorityQueue::default();
+        let mut open = true;
+        loop {
+            let (queued, permit) = tokio::select! {
+                biased;
+                received = request_receiver.recv(), if open => {
+                    match received {
+                        Some(queued) => {
+                            let client = queued.request.client();
+                            let cost = queued.request.estimate_cost(&ctx.cost_model);
+                            ctx.usage_ledger.charge_estimate(&client, cost);
+                            queue.push(queued.request.priority(), &client, cost, queued);
+                        }
+                        None => open = false,
+                    }
+                    continue;
+                }
+                // Requests whose callers stopped waiting leave the queue rather than wait for a worker
+                _ = ctx.backlog.abandoned() => {
+                    queue.retain(|queued: &Queued| !queued.dispatched.as_ref().is_some_and(oneshot::Sender::is_closed));
+                    continue;
+                }
+                permit = semaphore.clone().acquire_owned(), if !queue.is_empty() => {
+                    let permit = permit.expect("semaphore closed");
+                    // Clients holding more than their share of the workers wait while others are queued
+                    if let Some(aging) = scheduler::priority_aging() {
+                        queue.age(std::time::Instant::now(), aging);
+                    }
+                    let share = ctx.key_concurrency.fair_share(queue.clients());
+                    let queued = queue.pop_fair(|client| ctx.key_concurrency.running(client) < share).expect("queue is not empty");
+                    (queued, permit)
+                }
+                else => break,
+            };
+            let Queued { request: req, slot, dispatched } = queued;
+            // Running before it leaves the queue, so a draining server never sees it in neither
+            let running = ctx.key_concurrency.start(&req.client());
+            slot.release();
+            // The caller stopped waiting and has already been told the server is overloaded
+            if dispatched.is_some_and(|dispatched| dispatched.send(()).is_err()) {
+                continue;
+            }
+            let canaries = ctx.canaries.clone();
+            let default_timeout = ctx.default_timeout;
+            let inflight = ctx.inflight.clone();
+            let chat_templates = ctx.chat_templates.clone();
+            let registry = ctx.registry.clone();
+            let cost_model = ctx.cost_model.clone();
+            let usage_ledger = ctx.usage_ledger.clone();
+            let metering = ctx.metering.clone();
+            let events = ctx.events.clone();
+            let embedding_batcher = ctx.embedding_batcher.clone();
+            let continuous_batcher = ctx.continuous_batcher.clone();
+            // Process the request concurrently while holding its permit; batched generations
+            // hand it back once they join their model's decode loop
             tokio::spawn(async move {
-                let _permit = semaphore_clone.acquire_owned().await.expect("semaphore closed");
-                match req {
-                    EngineRequest::ChatCompletion { request, response_sender, stream_sender } => {
-                        counter!("requests_total", 1, "endpoint" => "chat");
-                        let model_name = request.model.clone();
-                        // Lookup both runtimes (LLM and Multimodal) for the given model name
-                        let (llm_runtime_opt, mm_runtime_opt) = {
-                            let llm = llm_map.read().await;
-                            let mm = mm_map.read().await;
-                            (llm.get(&model_name).cloned(), mm.get(&model_name).cloned())
-                        };
-                        if llm_runtime_opt.is_some() || mm_runtime_opt.is_some() {
-                            let (prompt, image_urls) = match request.messages.last().map(|m| m.content.clone()) {
-                                Some(ChatMessageContent::Text(content)) => (content, Vec::new()),
-                                Some(ChatMessageContent::Parts(parts)) => {
-                                    let mut text_acc = String::new();
-                                    let mut urls = Vec::new();
-                                    for p in parts {
-                                        match p {
-                                            ContentPart::Text { text } => text_acc.push_str(&text),
-                                            ContentPart::ImageUrl { image_url } => urls.push(image_url.url),
+                let _running = running;
+                let mut permit = Some(permit);
+                let client = req.client();
+                let endpoint = req.endpoint();
+                let priority = req.priority();
+                // Generations wind down on their own cancellation token; other requests are
+                // dropped when theirs fires, by shedding or at the end of a shutdown
+                let (cancel, abortable) = match &req {
+                    EngineRequest::ChatCompletion { cancel, .. } => (cancel.clone(), false),
+                    _ => (CancellationToken::new(), true),
+                };
+                let _inflight = inflight.register(cancel.clone());
+                let work = async move {
+                    match req {
+                        EngineRequest::ChatCompletion { request, response_sender, stream_sender, cancel } => {
+                            if cancel.is_cancelled() {
+                                // Client went away while the request was queued
+                                counter!("requests_cancelled_total", 1, "endpoint" => "chat");
+                                return;
+                            }
+                            counter!("requests_total", 1, "endpoint" => "chat");
+                            // Traffic splits and canary deployments may route the exposed name to a different runtime
+                            let requested_model = request.model.clone();
+                            let model_name = canaries.resolve(&requested_model).await;
+                            let mut tracker = events.track_request("chat", &model_name, &client, priority).metered(&metering);
+                            // The model may serve text, vision or both
+                            let _in_use = registry.in_use(&model_name);
+                            let runtimes = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.filter(|runtimes| runtimes.supports(Capability::Chat)),
+                                Err(e) => return chat_failed(response_sender, stream_sender, e).await,
+                            };
+                            if let Some(runtimes) = runtimes {
+                                let rendered = match chat_templates.read().await.get(&model_name) {
+                                    Some(template) => template.render(&request.messages),
+                                    None => Ok(render_chat_prompt(&request.messages)),
+                                };
+                                let RenderedPrompt { prompt, images, keep_prefix, content_spans } = match rendered {
+                                    Ok(rendered) => rendered,
+                                    Err(e) => {
+                                        tracing::warn!("failed to render prompt for {}: {}", model_name, e);
+                                        return chat_failed(response_sender, stream_sender, EngineError::InvalidInput(e)).await;
+                                    }
+                                };
+                                let mut gen_opts = generation_options(&request);
+                                // Child token: fired on client disconnect (parent) or when the timeout elapses
+                                gen_opts.cancel = cancel.child_token();
+                                gen_opts.keep_prefix = keep_prefix;
+                                gen_opts.content_spans = content_spans;
+                                let timeout = effective_timeout(request.timeout_ms, default_timeout);
+                                let route = runtimes.chat(!images.is_empty()).expect("chat models have a text or vision runtime");
+                                let count_tokens = |text: &str| route.count_tokens(text);
+                                let prompt_tokens = count_tokens(&prompt);
+
+                                if let Some(stream_tx) = stream_sender {
+                                    let start = std::time::Instant::now();
+                                    // Stream role first
+                                    let id = uuid::Uuid::new_v4().to_string();
+                                    let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+                                    let role_chunk = ChatCompletionChunk {
+                                        id: id.clone(),
+                                        object: "chat.completion.chunk".to_string(),
+                                        created,
+                                        model: model_name.clone(),
+                                        choices: vec![ChatCompletionChunkChoice {
+                                            index: 0,
+                                            delta: Delta { role: Some("assistant".to_string()), content: None },
+                                            finish_reason: None,
+                                            stop_sequence: None,
+                                        }],
+                                        usage: None,
+                                    };
+                                    let _ = stream_tx.send(serde_json::to_string(&role_chunk).unwrap()).await;
+
+                                    // Run the runtime's streaming generation and forward each piece as its own chunk
+                                    let (token_tx, mut token_rx) = mpsc::channel::<String>(64);
+                                    let generation = async {
+                                        match &route {
+                                            ChatRoute::Text(llm_rt) => {
+                                                continuous_batcher.generate_stream(&model_name, llm_rt.as_ref(), &prompt, &gen_opts, token_tx, permit.take()).await
+                                            }
+                                            ChatRoute::Vision(mm_rt) => mm_rt.generate_from_vision_stream(&prompt, &images, &gen_opts, token_tx).await,
+                                        }
+                                    };
+                                    let content_chunk = |text: String| {
+                                        serde_json::to_string(&ChatCompletionChunk {
+                                            id: id.clone(),
+                                            object: "chat.completion.chunk".to_string(),
+                                            created,
+                                            model: model_name.clone(),
+                                            choices: vec![ChatCompletionChunkChoice {
+                                                index: 0,
+                                                delta: Delta { role: None, content: Some(text) },
+                                                finish_reason: None,
+                                                stop_sequence: None,
+                                            }],
+                                            usage: None,
+                                        }).unwrap()
+                                    };
+                                    let chunking = request.stream_options.as_ref().map(|o| o.chunking).unwrap_or_default();
+                                    let forward = async {
+                                        let mut chunker = (chunking == StreamChunking::Word).then(WordChunker::default);
+                                        let mut completion = String::new();
+                                        while let Some(token) = token_rx.recv().await {
+                                            completion.push_str(&token);
+                                            let piece = match chunker.as_mut() {
+                                                Some(c) => c.push(&token),
+                                                None => Some(token),
+                                            };
+                                            if let Some(text) = piece
+                                                && stream_tx.send(content_chunk(text)).await.is_err()
+                                            {
+                                                cancel.cancel(); // SSE receiver closed
+                                            }
                                         }
+                                        if let Some(rest) = chunker.and_then(WordChunker::finish) {
+                                            let _ = stream_tx.send(content_chunk(rest)).await;
+                                        }
+                                        completion
+                                    };
+                                    // Abort at the next await point if the client disconnects, even for runtimes
+                                    // that don't check the token themselves
+                                    let generation = async {
+                                        tokio::select! {
+                                            result = generation => result.map(|_| "stop"),
+                                            _ = cancel.cancelled() => Err(RuntimeError::from("cancelled")),
+                                            // On timeout keep what was streamed so far and finish as truncated
+                                            _ = sleep_opt(timeout) => {
+                                                counter!("generation_timeouts_total", 1, "endpoint" => "chat");
+                                                gen_opts.cancel.cancel();
+                                                Ok("length")
+                                            }
+                                        }
+                                    };
+                                    let (generated, completion) = tokio::join!(generation, forward);
+                                    if cancel.is_cancelled() {
+                                        counter!("requests_cancelled_total", 1, "endpoint" => "chat");
+                                        tracker.set_outcome("cancelled");
+                                        return;
                                     }
-                                    (text_acc, urls)
+                                    tracker.set_outcome(match generated {
+                                        Ok("length") => "timeout",
+                                        Ok(_) => "ok",
+                                        Err(_) => "error",
+                                    });
+                                    canaries.record(&requested_model, &model_name, generated.is_ok(), start.elapsed().as_millis() as f64).await;
+                                    let completion_tokens = count_tokens(&completion);
+                                    tracker.set_tokens(prompt_tokens, completion_tokens);
+                                    cost_model.observe_completion(&model_name, completion_tokens);
+                                    usage_ledger.charge_actual(&client, completion_tokens as f64 + prompt_tokens as f64 * PREFILL_WEIGHT);
+                                    // A failed generation, or one that timed out before any text, ends
+                                    // the stream with an error instead of `[DONE]`
+                                    let finish_reason = match generated {
+                                        Ok("stop") => finish_reason(completion_tokens, gen_opts.max_tokens),
+                                        Ok("length") if completion.is_empty() => {
+                                            let elapsed = timeout.unwrap_or_default().as_millis();
+                                            let _ = stream_tx.send(ChatStreamError::chunk(EngineError::Timeout(format!("{} after {}ms", GENERATION_TIMEOUT, elapsed)))).await;
+                                            return;
+                                        }
+                                        Ok(reason) => reason,
+                                        Err(e) => {
+                                            let _ = stream_tx.send(ChatStreamError::chunk(e.into())).await;
+                                            return;
+                                        }
+                                    };
+
+                                    let done_chunk = ChatCompletionChunk {
+                                        id: id.clone(),
+                                        object: "chat.completion.chunk".to_string(),
+                                        created,
+                                        model: model_name.clone(),
+                                        choices: vec![ChatCompletionChunkChoice {
+                                            index: 0,
+                                            delta: Delta { role: None, content: None },
+                                            finish_reason: Some(finish_reason.to_string()),
+                                            stop_sequence: (finish_reason == "stop").then(|| matched_stop(&gen_opts)).flatten(),
+                                        }],
+                                        usage: None,
+                                    };
+                                    let _ = stream_tx.send(serde_json::to_string(&done_chunk).unwrap()).await;
+                                    if request.stream_options.as_ref().is_some_and(|o| o.include_usage) {
+                                        let usage_chunk = ChatCompletionChunk {
+                                            id: id.clone(),
+                                            object: "chat.completion.chunk".to_string(),
+                                            created,
+                                            model: model_name.clone(),
+                                            choices: Vec::new(),
+                                            usage: Some(usage(prompt_tokens, completion_tokens)),
+                                        };
+                                        let _ = stream_tx.send(serde_json::to_string(&usage_chunk).unwrap()).await;
+                                    }
+                                    // Optional: client often expects a [DONE] sentinel per OpenAI semantics
+                                    let _ = stream_tx.send("[DONE]".to_string()).await;
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "chat"
+                                    );
+                                } else if let Some(resp_tx) = response_sender {
+                                    let start = std::time::Instant::now();
+                                    let generation = async {
+                                        match &route {
+                                            ChatRoute::Text(llm_rt) => {
+                                                continuous_batcher.generate(&model_name, llm_rt.as_ref(), &prompt, &gen_opts, permit.take()).await
+                                            }
+                                            ChatRoute::Vision(mm_rt) => mm_rt.generate_from_vision(&prompt, &images, &gen_opts).await,
+                                        }
+                                    };
+                                    let result = tokio::select! {
+                                        result = generation => result,
+                                        _ = sleep_opt(timeout) => {
+                                            counter!("generation_timeouts_total", 1, "endpoint" => "chat");
+                                            gen_opts.cancel.cancel();
+                                            let elapsed = timeout.unwrap_or_default().as_millis();
+                                            canaries.record(&requested_model, &model_name, false, start.elapsed().as_millis() as f64).await;
+                                            tracker.set_outcome("timeout");
+                                            // Nothing reaches the client, but the prompt was still prefilled
+                                            tracker.set_tokens(prompt_tokens, 0);
+                                            usage_ledger.charge_actual(&client, prompt_tokens as f64 * PREFILL_WEIGHT);
+                                            let _ = resp_tx.send(Err(EngineError::Timeout(format!("{} after {}ms", GENERATION_TIMEOUT, elapsed)))).await;
+                                            return;
+                                        }
+                                    };
+                                    canaries.record(&requested_model, &model_name, result.is_ok(), start.elapsed().as_millis() as f64).await;
+                                    let generated = match result {
+                                        Ok(generated) => generated,
+                                        Err(e) => {
+                                            usage_ledger.charge_actual(&client, prompt_tokens as f64 * PREFILL_WEIGHT);
+                                            let _ = resp_tx.send(Err(e.into())).await;
+                                            return;
+                                        }
+                                    };
+                                    tracker.set_outcome("ok");
+                                    let completion_tokens = count_tokens(&generated);
+                                    tracker.set_tokens(prompt_tokens, completion_tokens);
+                                    cost_model.observe_completion(&model_name, completion_tokens);
+                                    usage_ledger.charge_actual(&client, completion_tokens as f64 + prompt_tokens as f64 * PREFILL_WEIGHT);
+                                    let finish_reason = finish_reason(completion_tokens, gen_opts.max_tokens);
+                                    let response = ChatCompletionResponse {
+                                        id: uuid::Uuid::new_v4().to_string(),
+                                        object: "chat.completion".to_string(),
+                                        created: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+                                        model: model_name,
+                                        choices: vec![ChatCompletionChoice {
+                                            index: 0,
+                                            message: ResponseMessage { role: "assistant".to_string(), content: generated.clone() },
+                                            finish_reason: finish_reason.to_string(),
+                                            stop_sequence: (finish_reason == "stop").then(|| matched_stop(&gen_opts)).flatten(),
+                                        }],
+                                        usage: usage(prompt_tokens, completion_tokens),
+                                        cache: CacheStatus::Miss,
+                                        fallback_from: None,
+                                    };
+                                    let _ = resp_tx.send(Ok(response)).await;
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "chat"
+                                    );
+                                }
+                            } else {
+                                chat_failed(response_sender, stream_sender, EngineError::ModelNotFound(model_not_found(&model_name))).await;
+                            }
+                        }
+                        EngineRequest::Embeddings { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "embeddings");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("embeddings", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.embedding),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
                                 }
-                                None => (String::new(), Vec::new()),
                             };
-                            let gen_opts = GenerationOptions::from_request(request.max_tokens, request.temperature, request.top_p);
-
-                            if let Some(stream_tx) = stream_sender {
+                            if let Some(runtime) = runtime_opt {
                                 let start = std::time::Instant::now();
-                                // Stream role first
-                                let id = uuid::Uuid::new_v4().to_string();
-                                let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-                                let role_chunk = ChatCompletionChunk {
-                                    id: id.clone(),
-                                    object: "chat.completion.chunk".to_string(),
-                                    created,
-                                    model: model_name.clone(),
-                                    choices: vec![ChatCompletionChunkChoice {
-                                        index: 0,
-                                        delta: Delta { role: Some("assistant".to_string()), content: None },
-                                        finish_reason: None,
-                                    }],
-                                };
-                                let _ = stream_tx.send(serde_json::to_string(&role_chunk).unwrap()).await;
-
-                                // Generate full text (simple runtime API), then send in one content chunk
-                                let generated = if image_urls.is_empty() {
-                                    if let Some(ref llm_rt) = llm_runtime_opt {
-                                        llm_rt.generate(&prompt, &gen_opts).await
-                                    } else {
-                                        Err("Model requires images".to_string())
+                                let encoding_format = request.encoding_format;
+                                let batch = EmbeddingBatch::from(request.input);
+                                let prompt_tokens = batch.count_tokens(runtime.as_ref());
+                                let dimensions = request.dimensions;
+                                let result = embedding_batcher.embed(&model_name, runtime, batch).await.and_then(|vectors| match dimensions {
+                                    Some(d) => vectors.into_iter().map(|v| truncate_dimensions(v, d).map_err(RuntimeError::InvalidInput)).collect(),
+                                    None => Ok(vectors),
+                                });
+                                usage_ledger.charge_actual(&client, cost_model.estimate_embeddings(prompt_tokens));
+                                tracker.set_tokens(prompt_tokens, 0);
+                                match result {
+                                    Ok(vectors) => {
+                                        tracker.set_outcome("ok");
+                                        let data: Vec<EmbeddingObject> = vectors
+                                            .into_iter()
+                                            .enumerate()
+                                            .map(|(i, v)| EmbeddingObject { object: "embedding".to_string(), index: i, embedding: encode_embedding(v, encoding_format) })
+                                            .collect();
+                                        let response = EmbeddingsResponse {
+                                            data,
+                                            model: model_name,
+                                            object: "list".to_string(),
+                                            usage: EmbeddingUsage { prompt_tokens, total_tokens: prompt_tokens },
+                                        };
+                                    let _ = response_sender.send(Ok(response)).await;
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "embeddings"
+                                    );
                                     }
-                                } else {
-                                    if let Some(ref mm_rt) = mm_runtime_opt {
-                                        mm_rt.generate_from_vision(&prompt, &image_urls, &gen_opts).await
-                                    } else if let Some(ref llm_rt) = llm_runtime_opt {
-                                        // Fallback: ignore images if only LLM exists for compatibility
-                                        llm_rt.generate(&prompt, &gen_opts).await
-                                    } else {
-                                        Err("Model not available".to_string())
-                                    }
-                                }.unwrap_or_else(|e| format!("[error: {}]", e));
-                                let content_chunk = ChatCompletionChunk {
-                                    id: id.clone(),
-                                    object: "chat.completion.chunk".to_string(),
-                                    created,
-                                    model: model_name.clone(),
-                                    choices: vec![ChatCompletionChunkChoice {
-                                        index: 0,
-                                        delta: Delta { role: None, content: Some(generated) },
-                                        finish_reason: None,
-                                    }],
-                                };
-                                let _ = stream_tx.send(serde_json::to_string(&content_chunk).unwrap()).await;
-
-                                let done_chunk = ChatCompletionChunk {
-                                    id: id.clone(),
-                                    object: "chat.completion.chunk".to_string(),
-                                    created,
-                                    model: model_name.clone(),
-                                    choices: vec![ChatCompletionChunkChoice {
-                                        index: 0,
-                                        delta: Delta { role: None, content: None },
-                                        finish_reason: Some("stop".to_string()),
-                                    }],
+                                    Err(e) => { let _ = response_sender.send(Err(e.into())).await; }
+                                }
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
+                            }
+                        }
+                        EngineRequest::Rerank { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "rerank");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("rerank", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.rerank),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
+                                let start = std::time::Instant::now();
+                                let result = rerank::rerank(runtime.as_ref(), request).await;
+                                if let Ok(response) = &result {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_embeddings(response.usage.prompt_tokens));
+                                    tracker.set_tokens(response.usage.prompt_tokens, 0);
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "rerank"
+                                    );
+                                }
+                                let _ = response_sender.send(result).await;
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
+                            }
+                        }
+                        EngineRequest::Transcription { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "audio_transcriptions");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("audio_transcriptions", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.audio),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
+                                let start = std::time::Instant::now();
+                                let result = audio::transcribe(runtime.as_ref(), request).await;
+                                if let Ok(response) = &result {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_audio(response.duration));
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "audio_transcriptions"
+                                    );
+                                }
+                                let _ = response_sender.send(result).await;
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
+                            }
+                        }
+                        EngineRequest::Speech { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "audio_speech");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("audio_speech", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.tts),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
+                                let start = std::time::Instant::now();
+                                let input_tokens = approximate_token_count(&request.input);
+                                let result = audio::synthesize(runtime.as_ref(), request).await;
+                                if result.is_ok() {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_speech(input_tokens));
+                                    tracker.set_tokens(input_tokens, 0);
+                                    histogram!(
+                                        "request_latency_ms",
+                                        start.elapsed().as_millis() as f64,
+                                        "endpoint" => "audio_speech"
+                                    );
+                                }
+                                let _ = response_sender.send(result).await;
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
+                            }
+                        }
+                        EngineRequest::Images { request, progress_sender, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "images");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("images", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.image),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
+                                let start = std::time::Instant::now();
+                                let options = ImageGenOptions {
+                                    n: request.n,
+                                    size: request.size.clone(),
+                                    negative_prompt: request.negative_prompt.clone(),
+                                    steps: request.steps,
+                                    guidance_scale: request.guidance_scale,
+                                    seed: request.seed,
+                                    progress: progress_sender,
+                                    previews: request.previews,
                                 };
-                                let _ = stream_tx.send(serde_json::to_string(&done_chunk).unwrap()).await;
-                                // Optional: client often expects a [DONE] sentinel per OpenAI semantics
-                                let _ = stream_tx.send("[DONE]".to_string()).await;
+                                let result = runtime.generate_images(&request.prompt, &options).await.map_err(EngineError::from);
+                                if let Ok(images) = &result {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_images(images.len() as u32));
+                                }
+                                let _ = response_sender.send(result).await;
                                 histogram!(
                                     "request_latency_ms",
                                     start.elapsed().as_millis() as f64,
-                                    "endpoint" => "chat"
+                                    "endpoint" => "images"
                                 );
-                            } else if let Some(resp_tx) = response_sender {
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
+                            }
+                        }
+                        EngineRequest::ImageEdit { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "image_edits");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("image_edits", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.image),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
                                 let start = std::time::Instant::now();
-                                let generated = if image_urls.is_empty() {
-                                    if let Some(ref llm_rt) = llm_runtime_opt {
-                                        llm_rt.generate(&prompt, &gen_opts).await.unwrap_or_default()
-                                    } else {
-                                        String::from("[error: Model requires images]")
-                                    }
-                                } else {
-                                    if let Some(ref mm_rt) = mm_runtime_opt {
-                                        mm_rt.generate_from_vision(&prompt, &image_urls, &gen_opts).await.unwrap_or_default()
-                                    } else if let Some(ref llm_rt) = llm_runtime_opt {
-                                        llm_rt.generate(&prompt, &gen_opts).await.unwrap_or_default()
-                                    } else {
-                                        String::from("[error: Model not available]")
-                                    }
-                                };
-                                let response = ChatCompletionResponse {
-                                    id: uuid::Uuid::new_v4().to_string(),
-                                    object: "chat.completion".to_string(),
-                                    created: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                                    model: model_name,
-                                    choices: vec![ChatCompletionChoice {
-                                        index: 0,
-                                        message: ResponseMessage { role: "assistant".to_string(), content: generated.clone() },
-                                        finish_reason: "stop".to_string(),
-                                    }],
-                                    usage: Usage {
-                                        prompt_tokens: 0,
-                                        completion_tokens: 0,
-                                        total_tokens: 0,
-                                    },
-                                };
-                                let _ = resp_tx.send(Ok(response)).await;
+                                let options = ImageGenOptions::new(request.n, request.size.clone());
+                                let result = runtime.edit_image(&request.image, request.mask.as_deref(), &request.prompt, &options).await.map_err(EngineError::from);
+                                if let Ok(images) = &result {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_images(images.len() as u32));
+                                }
+                                let _ = response_sender.send(result).await;
                                 histogram!(
                                     "request_latency_ms",
                                     start.elapsed().as_millis() as f64,
-                                    "endpoint" => "chat"
+                                    "endpoint" => "image_edits"
                                 );
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
                             }
-                        } else if let Some(resp_tx) = response_sender {
-                            let _ = resp_tx.send(Err(format!("Model {} not found", model_name))).await;
                         }
-                    }
-                    EngineRequest::Embeddings { request, response_sender } => {
-                        counter!("requests_total", 1, "endpoint" => "embeddings");
-                        let model_name = request.model.clone();
-                        let runtime_opt = {
-                            let map = embed_map.read().await;
-                            map.get(&model_name).cloned()
-                        };
-                        if let Some(runtime) = runtime_opt {
-                            let start = std::time::Instant::now();
-                            let inputs = request.input.clone();
-                            let result = runtime.embed(&inputs).await;
-                            match result {
-                                Ok(vectors) => {
-                                    let data: Vec<EmbeddingObject> = vectors
-                                        .into_iter()
-                                        .enumerate()
-                                        .map(|(i, v)| EmbeddingObject { object: "embedding".to_string(), index: i, embedding: v })
-                                        .collect();
-                                    let response = EmbeddingsResponse {
-                                        data,
-                                        model: model_name,
-                                        object: "list".to_string(),
-                                        usage: EmbeddingUsage { prompt_tokens: 0, total_tokens: 0 },
-                                    };
-                                let _ = response_sender.send(Ok(response)).await;
+                        EngineRequest::ImageVariation { request, response_sender } => {
+                            counter!("requests_total", 1, "endpoint" => "image_variations");
+                            let model_name = request.model.clone();
+                            let mut tracker = events.track_request("image_variations", &model_name, &client, priority).metered(&metering);
+                            let _in_use = registry.in_use(&model_name);
+                            let runtime_opt = match registry.acquire(&model_name).await {
+                                Ok(runtimes) => runtimes.and_then(|runtimes| runtimes.image),
+                                Err(e) => {
+                                    let _ = response_sender.send(Err(e)).await;
+                                    return;
+                                }
+                            };
+                            if let Some(runtime) = runtime_opt {
+                                let start = std::time::Instant::now();
+                                let options = ImageGenOptions::new(request.n, request.size.clone());
+                                let result = runtime.generate_variations(&request.image, &options).await.map_err(EngineError::from);
+                                if let Ok(images) = &result {
+                                    tracker.set_outcome("ok");
+                                    usage_ledger.charge_actual(&client, cost_model.estimate_images(images.len() as u32));
+                                }
+                                let _ = response_sender.send(result).await;
                                 histogram!(
                                     "request_latency_ms",
                                     start.elapsed().as_millis() as f64,
-                                    "endpoint" => "embeddings"
+                                    "endpoint" => "image_variations"
                                 );
-                                }
-                                Err(e) => { let _ = response_sender.send(Err(e)).await; }
+                            } else {
+                                let _ = response_sender.send(Err(EngineError::ModelNotFound(model_not_found(&model_name)))).await;
                             }
-                        } else {
-                            let _ = response_sender.send(Err(format!("Model {} not found", model_name))).await;
                         }
                     }
-                    EngineRequest::Images { request, response_sender } => {
-                        counter!("requests_total", 1, "endpoint" => "images");
-                        let model_name = request.model.clone();
-                        let runtime_opt = {
-                            let map = img_map.read().await;
-                            map.get(&model_name).cloned()
-                        };
-                        if let Some(runtime) = runtime_opt {
-                            let start = std::time::Instant::now();
-                            let n = request.n;
-                            let prompt = request.prompt.clone();
-                            let size = request.size.clone();
-                            let result = runtime.generate_images(&prompt, n, &size).await;
-                            let _ = response_sender.send(result).await;
-                            histogram!(
-                                "request_latency_ms",
-                                start.elapsed().as_millis() as f64,
-                                "endpoint" => "images"
-                            );
-                        } else {
-                            let _ = response_sender.send(Err(format!("Model {} not found", model_name))).await;
-                        }
+                };
+                if abortable {
+                    tokio::select! {
+                        _ = work => {}
+                        _ = cancel.cancelled() => counter!("requests_cancelled_total", 1, "endpoint" => endpoint),
                     }
+                } else {
+                    work.await;
                 }
                 // _permit dropped here, releasing capacity
             });
         }
     }
 
+    /// Submits a chat request to the worker pool. `cancel` aborts in-progress generation;
+    /// for non-streaming calls it also fires if this future is dropped before completion.
     pub async fn process_chat_request(
         &self,
         request: ChatCompletionRequest,
         stream_sender: Option<mpsc::Sender<String>>,
-    ) -> Result<ChatCompletionResponse, String> {
+        cancel: CancellationToken,
+    ) -> Result<ChatCompletionResponse, EngineError> {
+        self.check_admission()?;
+        self.moderate(&request).map_err(EngineError::InvalidInput)?;
+        validate_chat_request(&request).map_err(EngineError::InvalidInput)?;
+        let fallback = self.fallbacks.failover(&request.model);
+        if let Some(stream_sender) = stream_sender {
+            match fallback {
+                Some(fallback) => self.stream_with_fallback(request, fallback, stream_sender, cancel).await?,
+                None => {
+                    let stream_sender = Some(stream_sender);
+                    self.enqueue(EngineRequest::ChatCompletion { request, response_sender: None, stream_sender, cancel }).await?
+                }
+            }
+            // For streaming, we don't return a ChatCompletionResponse directly
+            // The response is sent via the stream_sender
+            return Err(EngineError::Streaming);
+        }
+        Ok(match fallback {
+            Some(fallback) => self.respond_with_fallback(request, fallback, cancel).await?,
+            None => self.respond(request, cancel).await?,
+        })
+    }
+
+    // A non-streaming chat response, from the response cache when it may be
+    async fn respond(&self, request: ChatCompletionRequest, cancel: CancellationToken) -> Result<ChatCompletionResponse, EngineError> {
+        let labels = [("model", self.metric_model(&request.model).await), ("endpoint", request.endpoint.unwrap_or("other").to_string())];
+        if request.cache == Some(false) || !self.caches_responses(&request.model).await {
+            counter!("cache_bypass_total", 1, &labels);
+            let mut response = self.generate_chat(request, cancel).await?;
+            response.cache = CacheStatus::Bypass;
+            return Ok(response);
+        }
+
         // Cache only non-streaming responses
-        let cache_key = if stream_sender.is_none() {
-            Some(Self::hash_chat_request(&request))
+        let key = Self::hash_chat_request(&request);
+        let (client, start) = (request.client_id.clone().unwrap_or_else(|| ANONYMOUS_CLIENT.to_string()), std::time::Instant::now());
+        if let Some(mut resp) = self.response_cache.get(&key).await {
+            counter!("cache_hit_total", 1, &labels);
+            resp.cache = CacheStatus::Hit;
+            self.meter_cache_hit(&client, &resp, start);
+            return Ok(resp);
+        }
+        counter!("cache_miss_total", 1, &labels);
+        // Identical requests of the same client arriving while this one generates wait for its
+        // response instead of generating their own; should its caller go away, one of them takes
+        // over. Other clients generate theirs, so each is queued and charged as itself.
+        let generated = &AtomicBool::new(false);
+        let labels = &labels;
+        let scoped = format!("{}:{}", client, key);
+        let result = self
+            .in_flight
+            .try_get_with(scoped.clone(), async move {
+                generated.store(true, Ordering::Relaxed);
+                let shared = self.shared_responses.as_ref();
+                if let Some(shared) = shared
+                    && let Some(response) = shared.get(&key).await
+                {
+                    let response = ChatCompletionResponse { cache: CacheStatus::Hit, ..response };
+                    self.response_occupancy.stored(&response);
+                    self.response_cache.insert(key, response.clone()).await;
+                    return Ok(response);
+                }
+                let result = self.generate_chat(request, cancel).await;
+                if let Ok(response) = &result {
+                    counter!("cache_store_total", 1, labels);
+                    self.response_occupancy.stored(response);
+                    self.response_cache.insert(key.clone(), response.clone()).await;
+                    if let Some(shared) = shared {
+                        shared.put(&key, response).await;
+                    }
+                }
+                result
+            })
+            .await;
+        let coalesced = !generated.load(Ordering::Relaxed);
+        if coalesced {
+            counter!("requests_coalesced_total", 1, labels);
         } else {
-            None
+            self.in_flight.invalidate(&scoped).await;
+        }
+        let result = match result {
+            Ok(resp) if coalesced => Ok(ChatCompletionResponse { cache: CacheStatus::Hit, ..resp }),
+            result => result.map_err(|e| (*e).clone()),
         };
-
-        if let Some(ref key) = cache_key {
-            if let Some(resp) = self.response_cache.get(key).await {
-                counter!("cache_hit_total", 1);
-                return Ok(resp);
+        if let Ok(resp) = &result
+            && matches!(resp.cache, CacheStatus::Hit)
+        {
+            self.meter_cache_hit(&client, resp, start);
+            // A waiter costs what the generation it shares did
+            if coalesced {
+                let usage = &resp.usage;
+                self.usage_ledger.charge_reused(&client, usage.completion_tokens as f64 + usage.prompt_tokens as f64 * PREFILL_WEIGHT);
             }
-            counter!("cache_miss_total", 1);
         }
+        result
+    }
+
+    // Responses reused from the cache never reach a worker, so they are metered here, with the
+    // tokens of the generation they reuse
+    fn meter_cache_hit(&self, client: &str, response: &ChatCompletionResponse, start: std::time::Instant) {
+        let usage = &response.usage;
+        self.metering.record(client, &response.model, true, usage.prompt_tokens, usage.completion_tokens, start.elapsed().as_millis() as u64);
+    }
 
+    // `model` as a metric label: the name of a model or traffic split that exists, or "unknown",
+    // so names made up by callers cannot grow the label set
+    async fn metric_model(&self, model: &str) -> String {
+        if self.splits.contains(model) || self.registry.get(model).await.is_some() {
+            model.to_string()
+        } else {
+            "unknown".to_string()
+        }
+    }
+
+    // Whether chat responses for `model` go through the response cache: not when it holds
+    // no entries, for models loaded with `response_cache` off, nor for traffic splits, whose
+    // variants are compared by their own generations
+    async fn caches_responses(&self, model: &str) -> bool {
+        self.response_cache.policy().max_capacity() != Some(0) && !self.splits.contains(model) && self.registry.caches_responses(model).await
+    }
+
+    // Runs a non-streaming chat request on a worker and waits for its response
+    async fn generate_chat(&self, request: ChatCompletionRequest, cancel: CancellationToken) -> Result<ChatCompletionResponse, EngineError> {
         let (response_sender, mut response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::ChatCompletion {
+            request,
+            response_sender: Some(response_sender),
+            stream_sender: None,
+            cancel: cancel.clone(),
+        })
+        .await?;
+        let guard = cancel.drop_guard();
+        let result = response_receiver.recv().await.ok_or_else(|| EngineError::Backend("Engine response channel closed".to_string()))?;
+        guard.disarm();
+        result
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    // Hands a request to the worker pool, unless too many are already waiting. With a maximum
+    // queue wait, returns once a worker has taken it up, or fails when none did in time.
+    async fn enqueue(&self, request: EngineRequest) -> Result<(), EngineError> {
+        if self.is_draining() {
+            counter!("queue_rejections_total", 1, "reason" => "shutting_down");
+            return Err(EngineError::Overloaded(SHUTTING_DOWN.to_string()));
+        }
+        let limits = QueueLimits::from_env();
+        let slot = Arc::new(self.backlog.admit(&request.client(), limits)?);
+        let (dispatched, started) = match limits.max_wait {
+            Some(_) => {
+                let (dispatched, started) = oneshot::channel();
+                (Some(dispatched), Some(started))
+            }
+            None => (None, None),
+        };
         self.request_sender
-            .send(EngineRequest::ChatCompletion {
-                request,
-                response_sender: if stream_sender.is_none() { Some(response_sender) } else { None },
-                stream_sender: stream_sender.clone(), // Clone stream_sender
-            })
+            .send(Queued { request, slot: slot.clone(), dispatched })
             .await
-            .map_err(|e| format!("Failed to send request to engine: {}", e))?;
-        
-        if stream_sender.is_none() {
-            let result = response_receiver
-                .recv()
-                .await
-                .ok_or("Engine response channel closed".to_string())?;
-            if let (Some(key), Ok(resp)) = (cache_key, &result) {
-                self.response_cache.insert(key, resp.clone()).await;
-                counter!("cache_store_total", 1);
-            }
-            result
-        } else {
-            // For streaming, we don't return a ChatCompletionResponse directly
-            // The response is sent via the stream_sender
-            Err("Streaming response handled via sender".to_string())
+            .map_err(|e| EngineError::Backend(format!("Failed to send request to engine: {}", e)))?;
+        if let (Some(max_wait), Some(started)) = (limits.max_wait, started)
+            && tokio::time::timeout(max_wait, started).await.is_err()
+        {
+            self.backlog.abandon(&slot);
+            counter!("queue_rejections_total", 1, "reason" => "wait_timeout");
+            return Err(EngineError::Overloaded(format!("{}: no worker was free within {}ms; try again later", QUEUE_TIMEOUT, max_wait.as_millis())));
+        }
+        Ok(())
+    }
+
+    fn check_admission(&self) -> Result<(), EngineError> {
+        if self.admission.is_shedding() {
+            counter!("requests_shed_total", 1);
+            return Err(EngineError::Overloaded(format!("{}; try again later", MEMORY_PRESSURE)));
         }
+        Ok(())
     }
 
     fn hash_chat_request(req: &ChatCompletionRequest) -> String {
@@ -420,113 +1535,403 @@ impl CoreEngine {
         if let Some(mt) = req.max_tokens { hasher.update(mt.to_le_bytes()); }
         if let Some(t) = req.temperature { hasher.update(t.to_le_bytes()); }
         if let Some(tp) = req.top_p { hasher.update(tp.to_le_bytes()); }
+        if let Some(k) = req.top_k { hasher.update(k.to_le_bytes()); }
+        for p in [req.min_p, req.typical_p, req.repetition_penalty, req.mirostat_tau, req.mirostat_eta].into_iter().flatten() {
+            hasher.update(p.to_le_bytes());
+        }
+        if let Some(m) = req.mirostat { hasher.update([m]); }
+        if let Some(g) = &req.grammar { hasher.update(g.as_bytes()); }
+        for s in req.stop.iter().flat_map(StopSequences::to_vec) {
+            hasher.update(s.as_bytes());
+            hasher.update([0]);
+        }
         format!("{:x}", hasher.finalize())
     }
 
     pub async fn process_embedding_request(
         &self,
         request: EmbeddingsRequest,
-    ) -> Result<EmbeddingsResponse, String> {
-        let (response_sender, mut response_receiver) = mpsc::channel(1);
-        self.request_sender
-            .send(EngineRequest::Embeddings { request, response_sender })
-            .await
-            .map_err(|e| format!("Failed to send request to engine: {}", e))?;
+    ) -> Result<EmbeddingsResponse, EngineError> {
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::Embeddings { request, response_sender }).await?;
 
-        response_receiver
-            .recv()
-            .await
-            .ok_or("Engine response channel closed".to_string())?
+        reply(response_receiver).await
     }
 
+    /// With a `progress` sender the runtime reports each sampling step there as it goes.
     pub async fn process_image_request(
         &self,
         request: ImagesGenerationRequest,
-    ) -> Result<Vec<Vec<u8>>, String> {
-        let (response_sender, mut response_receiver) = mpsc::channel(1);
-        self.request_sender
-            .send(EngineRequest::Images { request, response_sender })
-            .await
-            .map_err(|e| format!("Failed to send request to engine: {}", e))?;
+        progress: Option<mpsc::Sender<ImageProgress>>,
+    ) -> Result<Vec<Vec<u8>>, EngineError> {
+        validate_image_sampling(&request).map_err(EngineError::InvalidInput)?;
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::Images { request, progress_sender: progress, response_sender }).await?;
 
-        response_receiver
-            .recv()
-            .await
-            .ok_or("Engine response channel closed".to_string())?
+        reply(response_receiver).await
+    }
+
+    pub async fn process_image_edit_request(&self, request: ImagesEditRequest) -> Result<Vec<Vec<u8>>, EngineError> {
+        validate_image_count(request.n).map_err(EngineError::InvalidInput)?;
+        let (width, height) = png_dimensions(&request.image).map_err(EngineError::InvalidInput)?;
+        if let Some(mask) = &request.mask {
+            let (mask_width, mask_height) = png_dimensions(mask).map_err(|e| EngineError::InvalidInput(format!("mask: {}", e)))?;
+            if (mask_width, mask_height) != (width, height) {
+                return Err(EngineError::InvalidInput(format!("mask is {}x{} but the image is {}x{}", mask_width, mask_height, width, height)));
+            }
+        }
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::ImageEdit { request, response_sender }).await?;
+
+        reply(response_receiver).await
+    }
+
+    pub async fn process_image_variation_request(&self, request: ImagesVariationRequest) -> Result<Vec<Vec<u8>>, EngineError> {
+        validate_image_count(request.n).map_err(EngineError::InvalidInput)?;
+        let (width, height) = png_dimensions(&request.image).map_err(EngineError::InvalidInput)?;
+        if width != height {
+            return Err(EngineError::InvalidInput(format!("image must be square, got {}x{}", width, height)));
+        }
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::ImageVariation { request, response_sender }).await?;
+
+        reply(response_receiver).await
     }
 
     // Admin helpers (simple; no persistence)
-    pub async fn list_models(&self) -> (Vec<String>, Vec<String>, Vec<String>, Vec<String>) {
-        let llm = { self.llm_runtimes.read().await.keys().cloned().collect::<Vec<_>>() };
-        let embedding = { self.embedding_runtimes.read().await.keys().cloned().collect::<Vec<_>>() };
-        let multimodal = { self.multimodal_runtimes.read().await.keys().cloned().collect::<Vec<_>>() };
-        let image = { self.image_runtimes.read().await.keys().cloned().collect::<Vec<_>>() };
-        (llm, embedding, multimodal, image)
-    }
-
-    pub async fn load_model(&self, kind: &str, name: &str, path: Option<&str>) -> Result<(), String> {
-        match kind {
-            "llm" => {
-                #[cfg(feature = "llama")]
-                if let Some(p) = path {
-                    let rt = LlamaCppRuntime::new(p).map_err(|e| format!("load llama: {}", e))?;
-                    self.llm_runtimes.write().await.insert(name.to_string(), Arc::new(rt));
-                    return Ok(());
-                }
-                // fallback: dummy
-                self.llm_runtimes.write().await.insert(name.to_string(), Arc::new(DummyRuntime::new()));
-                Ok(())
+    pub async fn list_models(&self) -> ModelsListResponse {
+        self.registry.list().await
+    }
+
+    pub async fn load_model(&self, kind: &str, name: &str, path: Option<&str>, options: &ModelOptions) -> Result<LoadModelResponse, String> {
+        let template = check_load(kind, path, options)?;
+        self.check_fallback(name, options, &[]).await?;
+        let loading = self.registry.begin_load(name, kind).await;
+        match self.prepare_model(kind, name, path, options, template).await {
+            Ok(model) => {
+                let loaded = self.install_model(model).await;
+                loading.finish();
+                Ok(loaded)
             }
-            "embedding" => {
-                #[cfg(feature = "onnx")]
-                if let Some(p) = path {
-                    if let Ok(rt) = OnnxEmbeddingRuntime::new(p, 384) {
-                        self.embedding_runtimes.write().await.insert(name.to_string(), Arc::new(rt));
-                        return Ok(());
-                    }
-                }
-                // fallback: dummy
-                self.embedding_runtimes.write().await.insert(name.to_string(), Arc::new(DummyEmbeddingRuntime::new(384)));
-                Ok(())
+            Err(e) => {
+                loading.abort().await;
+                Err(e)
             }
-            "multimodal" => {
-                #[cfg(feature = "llava")]
-                {
-                    // If explicit path triple provided as comma-separated, parse; else try env
-                    if let Some(p) = path {
-                        let parts: Vec<&str> = p.split(',').collect();
-                        if parts.len() == 3 {
-                            let rt = crate::runtime::llava::LlavaRuntime::new(parts[0], parts[1], parts[2])
-                                .map_err(|e| format!("load llava: {}", e))?;
-                            self.multimodal_runtimes.write().await.insert(name.to_string(), Arc::new(rt));
-                            return Ok(());
-                        }
-                    }
-                    if let (Ok(vision), Ok(proj), Ok(llm)) = (
-                        std::env::var("LLAVA_VISION_MODEL_PATH"),
-                        std::env::var("LLAVA_PROJECTION_PATH"),
-                        std::env::var("LLAMA_MODEL_PATH"),
-                    ) {
-                        let rt = crate::runtime::llava::LlavaRuntime::new(&vision, &proj, &llm)
-                            .map_err(|e| format!("load llava: {}", e))?;
-                        self.multimodal_runtimes.write().await.insert(name.to_string(), Arc::new(rt));
-                        return Ok(());
-                    }
-                }
-                // fallback: dummy runtime also implements MultimodalRuntime
-                self.multimodal_runtimes.write().await.insert(name.to_string(), Arc::new(DummyRuntime::new()));
-                Ok(())
+        }
+    }
+
+    // The fallback model a load names must be a loaded chat model other than the one loading,
+    // or one of `declared`, loading alongside it
+    async fn check_fallback(&self, name: &str, options: &ModelOptions, declared: &[&str]) -> Result<(), String> {
+        let Some(fallback) = options.fallback_model.as_deref() else { return Ok(()) };
+        if fallback == name {
+            return Err(format!("{} cannot be its own fallback_model", name));
+        }
+        if declared.contains(&fallback) || self.registry.serves(fallback, "llm").await || self.registry.serves(fallback, "multimodal").await {
+            return Ok(());
+        }
+        Err(format!("fallback_model {} is not a loaded LLM", fallback))
+    }
+
+    // Opens a model and warms it up without registering it, so it can still be dropped
+    async fn prepare_model(
+        &self,
+        kind: &str,
+        name: &str,
+        path: Option<&str>,
+        options: &ModelOptions,
+        template: Option<ChatTemplate>,
+    ) -> Result<PreparedModel<'_>, String> {
+        let (backend, runtimes, reservation) = open_model(&self.registry, kind, name, path, options).await?;
+        // Before the model is registered, so no request meets it cold
+        let warmup = match options.warmup.unwrap_or_else(warmup_by_default) && backend != "remote" {
+            true => warm_up(kind, &runtimes).await,
+            false => None,
+        };
+        let spec = ModelSpec { model: name.to_string(), kind: kind.to_string(), path: path.map(|p| p.to_string()), options: options.clone() };
+        Ok(PreparedModel { spec, template, backend, runtimes, reservation, warmup })
+    }
+
+    // Registers a prepared model in place of any earlier version
+    async fn install_model(&self, model: PreparedModel<'_>) -> LoadModelResponse {
+        let PreparedModel { spec, template, backend, runtimes, reservation, warmup } = model;
+        let (name, kind) = (spec.model.clone(), spec.kind.clone());
+        // A model loaded under a gateway's name takes over from the chain
+        self.fallbacks.forget(&name);
+        self.fallbacks.set_failover(&name, spec.options.fallback_model.as_deref());
+        let entry = ModelEntry::new(&kind, backend, runtimes).with_spec(spec).with_footprint(reservation.footprint);
+        self.registry.insert(&name, entry).await;
+        drop(reservation);
+        match template {
+            Some(t) => { self.chat_templates.write().await.insert(name.clone(), Arc::new(t)); }
+            None => { self.chat_templates.write().await.remove(&name); }
+        }
+        self.events.publish(EngineEvent::ModelLoaded { model: name, kind });
+        LoadModelResponse { status: "ok".to_string(), warmup_ms: warmup.map(|took| took.as_millis() as u64) }
+    }
+
+    pub async fn unload_model(&self, kind: &str, name: &str) -> Result<(), EngineError> {
+        if !MODEL_KINDS.contains(&kind) {
+            return Err(EngineError::InvalidInput("unknown kind".to_string()));
+        }
+        // Only a model serving `kind` is unloaded, along with whatever else it serves
+        let mut models = self.registry.write().await;
+        match models.get(name) {
+            None => return Err(EngineError::ModelNotFound(model_not_found(name))),
+            Some(entry) if !entry.serves(kind) => {
+                return Err(EngineError::InvalidInput(format!("{} is a {} model, not {}", name, entry.kind, kind)));
+            }
+            Some(_) => {}
+        }
+        models.remove(name);
+        drop(models);
+        self.fallbacks.forget(name);
+        self.fallbacks.set_failover(name, None);
+        self.chat_templates.write().await.remove(name);
+        self.events.publish(EngineEvent::ModelUnloaded {
+            model: name.to_string(),
+            kind: kind.to_string(),
+            reason: "admin".to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// A worker's answer to a queued request.
+pub(crate) async fn reply<T>(mut receiver: mpsc::Receiver<Result<T, EngineError>>) -> Result<T, EngineError> {
+    receiver.recv().await.unwrap_or_else(|| Err(EngineError::Backend("Engine response channel closed".to_string())))
+}
+
+/// ENV: MODEL_IDLE_TTL_SECS (default 0: never), how long a model loaded through the admin API
+/// or the configuration file may go without requests before it is unloaded
+fn idle_ttl() -> Option<Duration> {
+    let secs = crate::config::var("MODEL_IDLE_TTL_SECS").ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+async fn unload_idle(registry: &ModelRegistry, events: &EventBus) -> Vec<String> {
+    let unloaded = registry.unload_idle(idle_ttl()).await;
+    for (model, kind) in &unloaded {
+        tracing::info!("unloaded idle {} model {}", kind, model);
+        events.publish(EngineEvent::ModelUnloaded { model: model.clone(), kind: kind.clone(), reason: "idle".to_string() });
+    }
+    unloaded.into_iter().map(|(model, _)| model).collect()
+}
+
+/// ENV: RESPONSE_CACHE_ENTRIES (default 10000), how many chat responses the response cache
+/// holds; 0 disables it
+fn response_cache_entries() -> u64 {
+    crate::config::var("RESPONSE_CACHE_ENTRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(10_000)
+}
+
+/// ENV: RESPONSE_CACHE_TTL_SECS (default 60), how long a cached chat response is served
+fn response_cache_ttl() -> Duration {
+    Duration::from_secs(crate::config::var("RESPONSE_CACHE_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60))
+}
+
+/// ENV: MODEL_WARMUP (default 1), whether LLMs and embedding models are warmed up after
+/// loading unless their options say otherwise
+fn warmup_by_default() -> bool {
+    !matches!(crate::config::var("MODEL_WARMUP").as_deref(), Ok("0") | Ok("false"))
+}
+
+// Runs a one-token generation or a one-input embedding through the runtimes a load `kind`
+// fills, which maps the weights in and compiles the compute graphs. Returns how long it took;
+// None when there is nothing to warm up or it failed, which leaves the model to warm up on
+// its first request instead.
+async fn warm_up(kind: &str, runtimes: &Runtimes) -> Option<Duration> {
+    let start = std::time::Instant::now();
+    match exercise(kind, runtimes).await? {
+        Ok(()) => {
+            let took = start.elapsed();
+            histogram!("model_warmup_ms", took.as_millis() as f64, "kind" => kind.to_string());
+            Some(took)
+        }
+        Err(e) => {
+            tracing::warn!("warmup of {} model failed: {}", kind, e);
+            None
+        }
+    }
+}
+
+// A one-token generation or a one-input embedding through the runtimes a load `kind` fills;
+// None for kinds without either
+async fn exercise(kind: &str, runtimes: &Runtimes) -> Option<Result<(), RuntimeError>> {
+    let result = match (kind, &runtimes.llm, &runtimes.embedding) {
+        ("llm", Some(llm), embedder) => {
+            let generated = llm.generate("Hello", &GenerationOptions::from_request(Some(1), Some(0.0), None)).await;
+            match embedder {
+                Some(embedder) => generated.and(embedder.embed(&["Hello".to_string()]).await.map(drop)),
+                None => generated.map(drop),
             }
-            _ => Err("unknown kind".to_string()),
         }
+        ("embedding", _, Some(embedder)) => embedder.embed(&["Hello".to_string()]).await.map(drop),
+        _ => return None,
+    };
+    Some(result)
+}
+
+// A model opened and warmed up, not yet registered
+struct PreparedModel<'r> {
+    spec: ModelSpec,
+    template: Option<ChatTemplate>,
+    backend: &'static str,
+    runtimes: Runtimes,
+    reservation: Reservation<'r>,
+    warmup: Option<Duration>,
+}
+
+// Refuses load parameters that are wrong whatever the model; returns the chat template they name
+fn check_load(kind: &str, path: Option<&str>, options: &ModelOptions) -> Result<Option<ChatTemplate>, String> {
+    options.validate()?;
+    if !MODEL_KINDS.contains(&kind) {
+        return Err("unknown kind".to_string());
+    }
+    let template = options.chat_template.as_deref().map(ChatTemplate::resolve).transpose()?;
+    if options.hf_repo.is_some() && path.is_some() {
+        return Err("give either path or hf_repo, not both".to_string());
+    }
+    if options.embeddings && kind != "llm" {
+        return Err("embeddings applies to llm models".to_string());
     }
+    Ok(template)
+}
+
+/// Fetches a model from the Hub when its options name a repository, sets aside memory for it
+/// in `registry` and loads it with the backend for `kind`.
+pub(crate) async fn open_model<'r>(
+    registry: &'r ModelRegistry,
+    kind: &str,
+    name: &str,
+    path: Option<&str>,
+    options: &ModelOptions,
+) -> Result<(&'static str, Runtimes, Reservation<'r>), String> {
+    let fetched = match &options.hf_repo {
+        Some(repo) => {
+            let local = hub::fetch(&hub::HubConfig::from_env(), repo, options.filename.as_deref(), options.hf_revision.as_deref()).await?;
+            Some(local.to_str().ok_or("model cache path is not UTF-8")?.to_string())
+        }
+        None => None,
+    };
+    let path = fetched.as_deref().or(path);
+    let reservation = registry.reserve(name, Footprint::estimate(path, options)).await?;
+    let (backend, mut runtimes) = load_runtime(kind, name, path, options).await?;
+    if options.embeddings {
+        let embedder = runtimes.llm.as_ref().and_then(|llm| llm.embedder());
+        runtimes.embedding = Some(embedder.ok_or_else(|| format!("the {} backend cannot serve embeddings", backend))?);
+    }
+    Ok((backend, runtimes, reservation))
+}
 
-    pub async fn unload_model(&self, kind: &str, name: &str) -> Result<(), String> {
-        match kind {
-            "llm" => { self.llm_runtimes.write().await.remove(name); Ok(()) }
-            "embedding" => { self.embedding_runtimes.write().await.remove(name); Ok(()) }
-            "multimodal" => { self.multimodal_runtimes.write().await.remove(name); Ok(()) }
-            _ => Err("unknown kind".to_string()),
+// The backend for `kind` that can serve `path`, falling back to the dummy runtimes
+async fn load_runtime(kind: &str, name: &str, path: Option<&str>, options: &ModelOptions) -> Result<(&'static str, Runtimes), String> {
+    match kind {
+        "llm" => {
+            if let Some(url) = path.filter(|p| is_upstream_url(p)) {
+                let rt = RemoteRuntime::new(RemoteConfig::new(url, name, options)?);
+                return Ok(("remote", Runtimes::llm(Arc::new(rt))));
+            }
+            // A directory holds an ONNX decoder export or a safetensors checkpoint; a file is a GGUF model
+            #[cfg(feature = "onnx_tokenizer")]
+            if let Some(p) = path.filter(|p| {
+                crate::runtime::onnx_llm::MODEL_FILES.iter().any(|file| std::path::Path::new(p).join(file).exists())
+            }) {
+                let rt = crate::runtime::onnx_llm::OnnxLlmRuntime::new(p, options).map_err(|e| format!("load onnx llm: {}", e))?;
+                return Ok(("onnx", Runtimes::llm(Arc::new(rt))));
+            }
+            #[cfg(feature = "candle")]
+            if let Some(p) = path.filter(|p| std::path::Path::new(p).is_dir()) {
+                let rt = crate::runtime::candle::CandleRuntime::new(p, options).map_err(|e| format!("load candle: {}", e))?;
+                return Ok(("candle", Runtimes::llm(Arc::new(rt))));
+            }
+            #[cfg(feature = "llama")]
+            if let Some(p) = path {
+                let batch_sequences = ContinuousBatchingConfig::from_env().max_sequences;
+                let rt = LlamaCppRuntime::new(p, options, batch_sequences).map_err(|e| format!("load llama: {}", e))?;
+                return Ok(("llama.cpp", Runtimes::llm(Arc::new(rt))));
+            }
+            // fallback: dummy
+            Ok(("dummy", Runtimes::llm(Arc::new(DummyRuntime::new()))))
+        }
+        "embedding" => {
+            if let Some(url) = path.filter(|p| is_upstream_url(p)) {
+                let rt = RemoteRuntime::new(RemoteConfig::new(url, name, options)?);
+                return Ok(("remote", Runtimes::embedding(Arc::new(rt))));
+            }
+            // A directory holds a CLIP export; a file is a text embedding model
+            #[cfg(feature = "clip")]
+            if let Some(p) = path.filter(|p| std::path::Path::new(p).is_dir()) {
+                let rt = crate::runtime::clip::ClipEmbeddingRuntime::new(p).map_err(|e| format!("load clip: {}", e))?;
+                return Ok(("clip", Runtimes::embedding(Arc::new(rt))));
+            }
+            #[cfg(feature = "onnx")]
+            if let Some(p) = path {
+                let rt = OnnxEmbeddingRuntime::new(p, options.embedding_dim.map(|d| d as usize)).map_err(|e| format!("load embedding model: {}", e))?;
+                return Ok(("onnx", Runtimes::embedding(Arc::new(rt))));
+            }
+            // fallback: dummy
+            Ok(("dummy", Runtimes::embedding(Arc::new(DummyEmbeddingRuntime::new(384)))))
+        }
+        "rerank" => {
+            #[cfg(feature = "onnx")]
+            if let Some(p) = path {
+                let rt = OnnxRerankRuntime::new(p).map_err(|e| format!("load reranker: {}", e))?;
+                return Ok(("onnx", Runtimes::rerank(Arc::new(rt))));
+            }
+            // fallback: dummy
+            Ok(("dummy", Runtimes::rerank(Arc::new(DummyRerankRuntime::new()))))
+        }
+        "audio" => {
+            #[cfg(feature = "whisper")]
+            if let Some(p) = path {
+                let rt = WhisperRuntime::new(p).map_err(|e| format!("load whisper: {}", e))?;
+                return Ok(("whisper", Runtimes::audio(Arc::new(rt))));
+            }
+            // fallback: dummy
+            Ok(("dummy", Runtimes::audio(Arc::new(DummyAudioRuntime::new()))))
         }
+        "tts" => {
+            // No native backend yet; every tts model is served by the dummy synthesizer
+            Ok(("dummy", Runtimes::tts(Arc::new(DummyTtsRuntime::new()))))
+        }
+        "image" => {
+            #[cfg(feature = "stable_diffusion")]
+            if let Some(p) = path {
+                let rt = OnnxStableDiffusionRuntime::new(p).map_err(|e| format!("load stable diffusion: {}", e))?;
+                return Ok(("stable-diffusion", Runtimes::image(Arc::new(rt))));
+            }
+            // fallback: dummy
+            Ok(("dummy", Runtimes::image(Arc::new(crate::runtime::dummy_image::DummyImageRuntime::new()))))
+        }
+        "multimodal" => {
+            #[cfg(feature = "llava")]
+            {
+                // If explicit path triple provided as comma-separated, parse; else try env
+                if let Some(p) = path {
+                    let parts: Vec<&str> = p.split(',').collect();
+                    if parts.len() == 3 {
+                        let rt = crate::runtime::llava::LlavaRuntime::new(parts[0], parts[1], parts[2])
+                            .map_err(|e| format!("load llava: {}", e))?;
+                        return Ok(("llava", Runtimes::multimodal(Arc::new(rt))));
+                    }
+                }
+                if let (Ok(vision), Ok(proj), Ok(llm)) = (
+                    crate::config::var("LLAVA_VISION_MODEL_PATH"),
+                    crate::config::var("LLAVA_PROJECTION_PATH"),
+                    crate::config::var("LLAMA_MODEL_PATH"),
+                ) {
+                    let rt = crate::runtime::llava::LlavaRuntime::new(&vision, &proj, &llm)
+                        .map_err(|e| format!("load llava: {}", e))?;
+                    return Ok(("llava", Runtimes::multimodal(Arc::new(rt))));
+                }
+            }
+            // fallback: dummy runtime also implements MultimodalRuntime
+            Ok(("dummy", Runtimes::multimodal(Arc::new(DummyRuntime::new()))))
+        }
+        _ => Err("unknown kind".to_string()),
     }
-}
\ No newline at end of file
+}
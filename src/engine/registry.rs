@@ -0,0 +1,775 @@
+use metrics::counter;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard, RwLock, RwLockWriteGuard};
+
+use super::budget::{megabytes, Footprint, MemoryBudget};
+use super::events::{EngineEvent, EventBus};
+use super::EngineError;
+use crate::api::dto::{MemoryUsage, ModelInfo, ModelSpec, ModelStatus, ModelsListResponse};
+use crate::runtime::{
+    AudioTranscriptionRuntime, EmbeddingRuntime, ImageGenRuntime, LlmRuntime, MultimodalRuntime, RerankRuntime, TtsRuntime,
+};
+
+pub(crate) type Entries = HashMap<String, ModelEntry>;
+
+/// Error prefix of requests for a model that failed its health checks.
+pub const MODEL_UNHEALTHY: &str = "Model is unhealthy";
+
+/// Error of a request naming a model that is not loaded.
+pub fn model_not_found(name: &str) -> String {
+    format!("Model {} not found", name)
+}
+
+/// Kinds accepted by `/admin/models/load`.
+pub const MODEL_KINDS: [&str; 7] = ["llm", "embedding", "rerank", "audio", "tts", "image", "multimodal"];
+
+/// What a model can serve, one slot per API surface. A backend implementing several runtime
+/// traits (the dummy model is both a text and a vision model) fills several slots with the
+/// same instance.
+#[derive(Clone, Default)]
+pub struct Runtimes {
+    pub llm: Option<Arc<dyn LlmRuntime>>,
+    pub multimodal: Option<Arc<dyn MultimodalRuntime>>,
+    pub embedding: Option<Arc<dyn EmbeddingRuntime>>,
+    pub rerank: Option<Arc<dyn RerankRuntime>>,
+    pub audio: Option<Arc<dyn AudioTranscriptionRuntime>>,
+    pub tts: Option<Arc<dyn TtsRuntime>>,
+    pub image: Option<Arc<dyn ImageGenRuntime>>,
+}
+
+impl Runtimes {
+    pub fn llm(runtime: Arc<dyn LlmRuntime>) -> Self {
+        Self { llm: Some(runtime), ..Self::default() }
+    }
+
+    pub fn multimodal(runtime: Arc<dyn MultimodalRuntime>) -> Self {
+        Self { multimodal: Some(runtime), ..Self::default() }
+    }
+
+    pub fn embedding(runtime: Arc<dyn EmbeddingRuntime>) -> Self {
+        Self { embedding: Some(runtime), ..Self::default() }
+    }
+
+    pub fn rerank(runtime: Arc<dyn RerankRuntime>) -> Self {
+        Self { rerank: Some(runtime), ..Self::default() }
+    }
+
+    pub fn audio(runtime: Arc<dyn AudioTranscriptionRuntime>) -> Self {
+        Self { audio: Some(runtime), ..Self::default() }
+    }
+
+    pub fn tts(runtime: Arc<dyn TtsRuntime>) -> Self {
+        Self { tts: Some(runtime), ..Self::default() }
+    }
+
+    pub fn image(runtime: Arc<dyn ImageGenRuntime>) -> Self {
+        Self { image: Some(runtime), ..Self::default() }
+    }
+
+    /// Whether the slot behind a load `kind` ("llm", "embedding", ...) is filled.
+    pub fn serves(&self, kind: &str) -> bool {
+        match kind {
+            "llm" => self.llm.is_some(),
+            "multimodal" => self.multimodal.is_some(),
+            "embedding" => self.embedding.is_some(),
+            "rerank" => self.rerank.is_some(),
+            "audio" => self.audio.is_some(),
+            "tts" => self.tts.is_some(),
+            "image" => self.image.is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            // A vision model answers text-only chats too
+            Capability::Chat => self.llm.is_some() || self.multimodal.is_some(),
+            Capability::Vision => self.multimodal.is_some(),
+            Capability::Embeddings => self.embedding.is_some(),
+            Capability::Rerank => self.rerank.is_some(),
+            Capability::Transcription => self.audio.is_some(),
+            Capability::Speech => self.tts.is_some(),
+            Capability::ImageGeneration => self.image.is_some(),
+        }
+    }
+
+    /// The runtime that answers a chat, with or without images. Text goes to the text runtime
+    /// when there is one, since it batches; images go to the vision runtime, or are dropped
+    /// for a text-only model.
+    pub fn chat(&self, has_images: bool) -> Option<ChatRoute> {
+        match (&self.llm, &self.multimodal) {
+            (_, Some(vision)) if has_images => Some(ChatRoute::Vision(vision.clone())),
+            (Some(text), _) => Some(ChatRoute::Text(text.clone())),
+            (None, Some(vision)) => Some(ChatRoute::Vision(vision.clone())),
+            (None, None) => None,
+        }
+    }
+
+    fn capabilities(&self) -> Vec<String> {
+        Capability::ALL.into_iter().filter(|c| self.supports(*c)).map(|c| c.as_str().to_string()).collect()
+    }
+}
+
+/// What a request needs from a model, independent of the kind it was loaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Chat,
+    Vision,
+    Embeddings,
+    Rerank,
+    Transcription,
+    Speech,
+    ImageGeneration,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 7] = [
+        Capability::Chat,
+        Capability::Vision,
+        Capability::Embeddings,
+        Capability::Rerank,
+        Capability::Transcription,
+        Capability::Speech,
+        Capability::ImageGeneration,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Chat => "chat",
+            Capability::Vision => "vision",
+            Capability::Embeddings => "embeddings",
+            Capability::Rerank => "rerank",
+            Capability::Transcription => "transcription",
+            Capability::Speech => "speech",
+            Capability::ImageGeneration => "image_generation",
+        }
+    }
+}
+
+pub enum ChatRoute {
+    Text(Arc<dyn LlmRuntime>),
+    Vision(Arc<dyn MultimodalRuntime>),
+}
+
+impl ChatRoute {
+    pub fn count_tokens(&self, text: &str) -> u32 {
+        match self {
+            ChatRoute::Text(runtime) => runtime.count_tokens(text),
+            ChatRoute::Vision(runtime) => runtime.count_tokens(text),
+        }
+    }
+}
+
+pub struct ModelEntry {
+    // Empty while the model is idle
+    pub runtimes: Runtimes,
+    // Load kind ("llm", "embedding", ...) the model was registered as
+    pub kind: String,
+    // Implementation serving it, e.g. "llama.cpp", "remote" or "dummy"
+    pub backend: &'static str,
+    // Load parameters of models loaded through the admin API; None for built-in and
+    // environment-configured models, which state snapshots leave out
+    pub spec: Option<ModelSpec>,
+    pub status: ModelStatus,
+    pub loaded_at: u64,
+    // Approximate memory it takes, counted against the budget; nothing while idle
+    pub footprint: Footprint,
+    // Unix milliseconds of the last load and of the last request routed to the model; 0
+    // before the first
+    loaded_ms: u64,
+    last_used: AtomicU64,
+    // What an idle model served before it was unloaded, so it is listed as before
+    idle: Option<Served>,
+    // Held while an idle model is loaded again, so concurrent requests load it once
+    waking: Arc<Mutex<()>>,
+    // Health probes failed in a row and the last one's error; past the threshold the model is
+    // unhealthy and requests for it are refused until a probe passes or it is loaded again
+    probe_failures: u32,
+    probe_error: Option<String>,
+    unhealthy: bool,
+}
+
+struct Served {
+    kinds: Vec<&'static str>,
+    capabilities: Vec<String>,
+}
+
+impl ModelEntry {
+    pub fn new(kind: &str, backend: &'static str, runtimes: Runtimes) -> Self {
+        Self {
+            runtimes,
+            kind: kind.to_string(),
+            backend,
+            spec: None,
+            status: ModelStatus::Ready,
+            loaded_at: now_secs(),
+            footprint: Footprint::default(),
+            loaded_ms: now_millis(),
+            last_used: AtomicU64::new(0),
+            idle: None,
+            waking: Arc::default(),
+            probe_failures: 0,
+            probe_error: None,
+            unhealthy: false,
+        }
+    }
+
+    pub fn with_spec(mut self, spec: ModelSpec) -> Self {
+        self.spec = Some(spec);
+        self
+    }
+
+    pub fn with_footprint(mut self, footprint: Footprint) -> Self {
+        self.footprint = footprint;
+        self
+    }
+
+    /// Whether the model serves a load `kind`, idle or not.
+    pub fn serves(&self, kind: &str) -> bool {
+        match &self.idle {
+            Some(served) => served.kinds.contains(&kind),
+            None => self.runtimes.serves(kind),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle.is_some()
+    }
+
+    // Unix milliseconds since which the model has gone unused
+    fn unused_since(&self) -> u64 {
+        self.last_used.load(Ordering::Relaxed).max(self.loaded_ms)
+    }
+
+    // Drops the runtimes, keeping what they served for listing
+    fn make_idle(&mut self) {
+        let kinds = MODEL_KINDS.into_iter().filter(|kind| self.runtimes.serves(kind)).collect();
+        self.idle = Some(Served { kinds, capabilities: self.runtimes.capabilities() });
+        self.runtimes = Runtimes::default();
+        self.footprint = Footprint::default();
+        // Loaded again on demand, it starts out healthy
+        self.set_healthy();
+    }
+
+    fn set_healthy(&mut self) {
+        self.probe_failures = 0;
+        self.probe_error = None;
+        self.unhealthy = false;
+    }
+
+    // How long the model may go unused before it is unloaded; None to keep it loaded. Only
+    // models with load parameters can be loaded again, so the others stay.
+    fn idle_ttl(&self, default: Option<Duration>) -> Option<Duration> {
+        let spec = self.spec.as_ref()?;
+        match spec.options.idle_ttl_secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        }
+    }
+}
+
+// "<n> MB of RAM and <n> MB of VRAM, over the budget of ..."
+fn over_budget(needed: Footprint, budget: &MemoryBudget) -> String {
+    let limit = |bytes: Option<u64>| bytes.map_or("unlimited".to_string(), |b| format!("{} MB", megabytes(b)));
+    format!(
+        "{} MB of RAM and {} MB of VRAM, over the budget of {} RAM and {} VRAM",
+        megabytes(needed.ram_bytes),
+        megabytes(needed.vram_bytes),
+        limit(budget.ram_bytes),
+        limit(budget.vram_bytes)
+    )
+}
+
+fn now_secs() -> u64 {
+    now_millis() / 1000
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Every model the engine serves, by name. One name is one model, whatever it serves; an
+/// entry still loading has no runtimes yet, or keeps serving its previous ones while it is
+/// being replaced.
+#[derive(Default)]
+pub struct ModelRegistry {
+    entries: RwLock<Entries>,
+    // Footprints of the loads in progress
+    reserved: std::sync::Mutex<Footprint>,
+    // Signalled when a load in progress ends, to loads queued behind it
+    released: Notify,
+    // Requests being served by each model
+    in_use: std::sync::Mutex<HashMap<String, usize>>,
+    // Taken in turn by the loads of each name
+    loads: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    events: EventBus,
+}
+
+/// A load of one model in progress; other loads of the name wait until it ends. Dropped
+/// before [`finish`](Self::finish), the load failed and the entry is put back as it was.
+pub struct Loading<'a> {
+    registry: &'a ModelRegistry,
+    name: String,
+    // Status of the entry being replaced, None for a new name
+    previous: Option<ModelStatus>,
+    // Held until the load ends
+    turn: Option<OwnedMutexGuard<()>>,
+}
+
+impl Loading<'_> {
+    /// Ends the load once the new version is registered.
+    pub fn finish(mut self) {
+        self.end();
+    }
+
+    /// Ends a failed load.
+    pub async fn abort(mut self) {
+        let mut entries = self.registry.entries.write().await;
+        self.restore(&mut entries);
+        drop(entries);
+        self.end();
+    }
+
+    // Puts the entry back as it was before the load, unless a new version is in place
+    fn restore(&self, entries: &mut Entries) {
+        let Some(entry) = entries.get_mut(&self.name).filter(|entry| entry.status == ModelStatus::Loading) else { return };
+        match self.previous {
+            Some(status) => entry.status = status,
+            None => {
+                entries.remove(&self.name);
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        let Some(turn) = self.turn.take() else { return };
+        let mut loads = self.registry.loads.lock().unwrap();
+        // Nobody else waits for a turn
+        if Arc::strong_count(OwnedMutexGuard::mutex(&turn)) == 2 {
+            loads.remove(&self.name);
+        }
+    }
+}
+
+impl Drop for Loading<'_> {
+    fn drop(&mut self) {
+        if self.turn.is_none() {
+            return;
+        }
+        // Given up halfway by a caller that went away; the registry is rarely held for long
+        if let Ok(mut entries) = self.registry.entries.try_write() {
+            self.restore(&mut entries);
+        }
+        self.end();
+    }
+}
+
+/// A request being served by a model, counted until dropped.
+pub struct InUse<'a> {
+    registry: &'a ModelRegistry,
+    name: String,
+}
+
+impl Drop for InUse<'_> {
+    fn drop(&mut self) {
+        let mut in_use = self.registry.in_use.lock().unwrap();
+        if let Some(count) = in_use.get_mut(&self.name) {
+            *count -= 1;
+            if *count == 0 {
+                in_use.remove(&self.name);
+            }
+        }
+    }
+}
+
+/// Memory set aside for a model being loaded, given back when dropped: by then the model is
+/// in the registry with its footprint, or failed to load.
+pub struct Reservation<'a> {
+    registry: &'a ModelRegistry,
+    pub footprint: Footprint,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.footprint.is_zero() {
+            return;
+        }
+        let mut reserved = self.registry.reserved.lock().unwrap();
+        *reserved = *reserved - self.footprint;
+        self.registry.released.notify_waiters();
+    }
+}
+
+impl ModelRegistry {
+    pub fn new(entries: Entries, events: EventBus) -> Self {
+        Self { entries: RwLock::new(entries), events, ..Self::default() }
+    }
+
+    /// Sets aside `footprint` for a model about to be loaded. Over the budget, the least
+    /// recently used other models that can be loaded again and serve no request are made idle
+    /// to free room; a load that still does not fit waits for the loads in progress, and fails
+    /// when there are none.
+    pub async fn reserve(&self, name: &str, footprint: Footprint) -> Result<Reservation<'_>, String> {
+        loop {
+            let budget = MemoryBudget::from_env();
+            if footprint.is_zero() || budget == MemoryBudget::default() {
+                return Ok(Reservation { registry: self, footprint: Footprint::default() });
+            }
+            if !budget.fits(footprint) {
+                return Err(format!("loading {} needs {}", name, over_budget(footprint, &budget)));
+            }
+            let released = self.released.notified();
+            {
+                let mut entries = self.entries.write().await;
+                let mut reserved = self.reserved.lock().unwrap();
+                let mut used = entries.values().map(|entry| entry.footprint).sum::<Footprint>() + *reserved + footprint;
+                while !budget.fits(used) {
+                    let excess = budget.excess(used);
+                    let frees = |entry: &ModelEntry| {
+                        (excess.ram_bytes > 0 && entry.footprint.ram_bytes > 0) || (excess.vram_bytes > 0 && entry.footprint.vram_bytes > 0)
+                    };
+                    let victim = entries
+                        .iter_mut()
+                        .filter(|(victim, entry)| {
+                            *victim != name && entry.spec.is_some() && entry.status == ModelStatus::Ready && !entry.is_idle() && !self.is_busy(victim) && frees(entry)
+                        })
+                        .min_by_key(|(_, entry)| entry.unused_since());
+                    let Some((victim, entry)) = victim else { break };
+                    used = used - entry.footprint;
+                    entry.make_idle();
+                    counter!("model_budget_evictions_total", 1);
+                    tracing::warn!("memory budget: made {} idle to load {}", victim, name);
+                    self.events.publish(EngineEvent::ModelUnloaded {
+                        model: victim.clone(),
+                        kind: entry.kind.clone(),
+                        reason: "memory_budget".to_string(),
+                    });
+                }
+                if budget.fits(used) {
+                    *reserved = *reserved + footprint;
+                    return Ok(Reservation { registry: self, footprint });
+                }
+                if reserved.is_zero() {
+                    counter!("model_budget_rejections_total", 1);
+                    return Err(format!(
+                        "loading {} would bring the models to {}, and none can be made idle",
+                        name,
+                        over_budget(used, &budget)
+                    ));
+                }
+            }
+            // The loads in progress hold the rest; one of them may fail and give it back
+            tracing::info!("memory budget: {} waits for the loads in progress", name);
+            released.await;
+        }
+    }
+
+    // Memory the loaded models take against the budget
+    fn memory(&self, entries: &Entries) -> MemoryUsage {
+        let used: Footprint = entries.values().map(|entry| entry.footprint).sum();
+        let reserved = *self.reserved.lock().unwrap();
+        let budget = MemoryBudget::from_env();
+        MemoryUsage {
+            ram_used_bytes: used.ram_bytes,
+            vram_used_bytes: used.vram_bytes,
+            ram_reserved_bytes: reserved.ram_bytes,
+            vram_reserved_bytes: reserved.vram_bytes,
+            ram_budget_bytes: budget.ram_bytes,
+            vram_budget_bytes: budget.vram_bytes,
+        }
+    }
+
+    /// Counts a request against `name` until the returned guard drops, so health checks, idle
+    /// unloading and budget evictions leave the model alone meanwhile.
+    pub fn in_use(&self, name: &str) -> InUse<'_> {
+        *self.in_use.lock().unwrap().entry(name.to_string()).or_default() += 1;
+        InUse { registry: self, name: name.to_string() }
+    }
+
+    /// Whether any request is being served by `name`.
+    pub fn is_busy(&self, name: &str) -> bool {
+        self.in_use.lock().unwrap().contains_key(name)
+    }
+
+    /// The runtimes of `name`, cheap to clone and safe to hold across generations.
+    pub async fn get(&self, name: &str) -> Option<Runtimes> {
+        self.entries.read().await.get(name).map(|entry| entry.runtimes.clone())
+    }
+
+    /// The runtimes of `name` for a request: records the use and, when the model is idle,
+    /// loads it again first.
+    pub async fn acquire(&self, name: &str) -> Result<Option<Runtimes>, EngineError> {
+        let waking = {
+            let entries = self.entries.read().await;
+            let Some(entry) = entries.get(name) else { return Ok(None) };
+            if entry.unhealthy {
+                let reason = entry.probe_error.as_deref().unwrap_or("health checks failed");
+                return Err(EngineError::Overloaded(format!("{}: {} ({})", MODEL_UNHEALTHY, name, reason)));
+            }
+            entry.last_used.store(now_millis(), Ordering::Relaxed);
+            if !entry.is_idle() {
+                return Ok(Some(entry.runtimes.clone()));
+            }
+            entry.waking.clone()
+        };
+        let _waking = waking.lock().await;
+        // A request that waited here finds the model loaded by the one before it
+        let spec = {
+            let entries = self.entries.read().await;
+            let Some(entry) = entries.get(name) else { return Ok(None) };
+            match &entry.spec {
+                Some(spec) if entry.is_idle() => spec.clone(),
+                _ => return Ok(Some(entry.runtimes.clone())),
+            }
+        };
+        let opened = super::open_model(self, &spec.kind, name, spec.path.as_deref(), &spec.options).await;
+        counter!("model_idle_reloads_total", 1, "outcome" => if opened.is_ok() { "ok" } else { "error" });
+        let (backend, runtimes, reservation) = opened.map_err(|e| {
+            tracing::error!("could not load idle model {} again: {}", name, e);
+            EngineError::Backend(format!("Failed to load idle model {}: {}", name, e))
+        })?;
+        let mut entries = self.entries.write().await;
+        let Some(entry) = entries.get_mut(name) else { return Ok(None) };
+        // Replaced through the admin API meanwhile
+        if !entry.is_idle() {
+            return Ok(Some(entry.runtimes.clone()));
+        }
+        tracing::info!("loaded idle model {} again", name);
+        entry.runtimes = runtimes.clone();
+        entry.backend = backend;
+        entry.footprint = reservation.footprint;
+        entry.idle = None;
+        entry.loaded_ms = now_millis();
+        entry.loaded_at = entry.loaded_ms / 1000;
+        Ok(Some(runtimes))
+    }
+
+    /// Loaded models to probe, with their kind, runtimes and load time (which tells whether
+    /// they were replaced by the time the probe finishes). Models serving requests are left
+    /// out: a probe would wait behind them and fail on its timeout, not on the model.
+    pub async fn probe_targets(&self) -> Vec<(String, String, Runtimes, u64)> {
+        let entries = self.entries.read().await;
+        entries
+            .iter()
+            .filter(|(name, entry)| entry.status == ModelStatus::Ready && !entry.is_idle() && !self.is_busy(name))
+            .map(|(name, entry)| (name.clone(), entry.kind.clone(), entry.runtimes.clone(), entry.loaded_ms))
+            .collect()
+    }
+
+    /// Records the outcome of a probe of `name` as loaded at `loaded_ms`. Returns Some(false)
+    /// when this makes the model unhealthy, its `failures`th failure in a row, and Some(true)
+    /// when an unhealthy model passes again.
+    pub async fn record_probe(&self, name: &str, loaded_ms: u64, result: Result<(), String>, failures: u32) -> Option<bool> {
+        let mut entries = self.entries.write().await;
+        let entry = entries.get_mut(name).filter(|entry| entry.loaded_ms == loaded_ms)?;
+        match result {
+            Ok(()) => {
+                let recovered = entry.unhealthy;
+                entry.set_healthy();
+                recovered.then_some(true)
+            }
+            Err(e) => {
+                entry.probe_failures += 1;
+                entry.probe_error = Some(e);
+                let turned = !entry.unhealthy && entry.probe_failures >= failures.max(1);
+                entry.unhealthy |= turned;
+                turned.then_some(false)
+            }
+        }
+    }
+
+    /// Loads an unhealthy model again from its load parameters. The runtimes that failed stay
+    /// in place until the new ones are ready, so a failed reload leaves the model as it was and
+    /// later health checks can still find it recovered.
+    pub async fn reload_unhealthy(&self, name: &str) -> Result<(), String> {
+        let waking = match self.entries.read().await.get(name) {
+            Some(entry) if entry.unhealthy && entry.spec.is_some() => entry.waking.clone(),
+            _ => return Err(format!("{} is not an unhealthy model with load parameters", name)),
+        };
+        let _waking = waking.lock().await;
+        let spec = {
+            let entries = self.entries.read().await;
+            let Some(entry) = entries.get(name).filter(|entry| entry.unhealthy) else { return Ok(()) };
+            entry.spec.clone().ok_or("no load parameters")?
+        };
+        let (backend, runtimes, reservation) = super::open_model(self, &spec.kind, name, spec.path.as_deref(), &spec.options).await?;
+        let mut entries = self.entries.write().await;
+        let Some(entry) = entries.get_mut(name) else { return Ok(()) };
+        // Replaced through the admin API meanwhile
+        if !entry.unhealthy {
+            return Ok(());
+        }
+        entry.runtimes = runtimes;
+        entry.backend = backend;
+        entry.footprint = reservation.footprint;
+        entry.loaded_ms = now_millis();
+        entry.loaded_at = entry.loaded_ms / 1000;
+        entry.set_healthy();
+        Ok(())
+    }
+
+    /// Unloads the models no request used for their idle TTL (`default` unless their load
+    /// options set one), keeping their entries so the next request loads them again. Returns
+    /// the names and kinds of the models unloaded.
+    pub async fn unload_idle(&self, default: Option<Duration>) -> Vec<(String, String)> {
+        let now = now_millis();
+        // A model serving requests counts as used now, so its TTL runs from when the last ends
+        for (name, entry) in self.entries.read().await.iter() {
+            if self.is_busy(name) {
+                entry.last_used.store(now, Ordering::Relaxed);
+            }
+        }
+        let expired = |entry: &ModelEntry| {
+            !entry.is_idle()
+                && entry.status == ModelStatus::Ready
+                && entry.idle_ttl(default).is_some_and(|ttl| now.saturating_sub(entry.unused_since()) >= ttl.as_millis() as u64)
+        };
+        if !self.entries.read().await.values().any(expired) {
+            return Vec::new();
+        }
+        let mut entries = self.entries.write().await;
+        let mut unloaded = Vec::new();
+        for (name, entry) in entries.iter_mut().filter(|(name, entry)| expired(entry) && !self.is_busy(name)) {
+            entry.make_idle();
+            unloaded.push((name.clone(), entry.kind.clone()));
+        }
+        counter!("model_idle_unloads_total", unloaded.len() as u64);
+        unloaded
+    }
+
+    /// The loaded model that went unused the longest, leaving out `except`.
+    pub async fn least_recently_used(&self, except: &[&str]) -> Option<String> {
+        let entries = self.entries.read().await;
+        entries
+            .iter()
+            .filter(|(name, entry)| !entry.is_idle() && !except.contains(&name.as_str()))
+            .min_by_key(|(_, entry)| entry.unused_since())
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Whether `name` serves a load `kind`, idle or not.
+    pub async fn serves(&self, name: &str, kind: &str) -> bool {
+        self.entries.read().await.get(name).is_some_and(|entry| entry.serves(kind))
+    }
+
+    /// Whether chat responses of `name` may be cached; only its load options turn that off.
+    pub async fn caches_responses(&self, name: &str) -> bool {
+        let entries = self.entries.read().await;
+        let spec = entries.get(name).and_then(|entry| entry.spec.as_ref());
+        spec.is_none_or(|spec| spec.options.response_cache != Some(false))
+    }
+
+    pub async fn llm(&self, name: &str) -> Option<Arc<dyn LlmRuntime>> {
+        self.entries.read().await.get(name).and_then(|entry| entry.runtimes.llm.clone())
+    }
+
+    pub async fn insert(&self, name: &str, entry: ModelEntry) {
+        self.entries.write().await.insert(name.to_string(), entry);
+    }
+
+    pub async fn remove(&self, name: &str) -> Option<ModelEntry> {
+        self.entries.write().await.remove(name)
+    }
+
+    pub async fn names(&self) -> Vec<String> {
+        self.entries.read().await.keys().cloned().collect()
+    }
+
+    /// Marks `name` as loading as `kind`, once any other load of it has ended. An earlier
+    /// entry stays in place (and serves) until the load finishes.
+    pub async fn begin_load(&self, name: &str, kind: &str) -> Loading<'_> {
+        let turn = self.loads.lock().unwrap().entry(name.to_string()).or_default().clone();
+        let turn = turn.lock_owned().await;
+        let mut entries = self.entries.write().await;
+        let previous = match entries.get_mut(name) {
+            Some(entry) => Some(std::mem::replace(&mut entry.status, ModelStatus::Loading)),
+            None => {
+                let mut entry = ModelEntry::new(kind, "pending", Runtimes::default());
+                entry.status = ModelStatus::Loading;
+                entries.insert(name.to_string(), entry);
+                None
+            }
+        };
+        Loading { registry: self, name: name.to_string(), previous, turn: Some(turn) }
+    }
+
+    /// Backend of the model to be replaced by a swap to a new version: one that is registered
+    /// and not in the middle of another load.
+    pub async fn swappable(&self, name: &str) -> Result<&'static str, EngineError> {
+        match self.entries.read().await.get(name) {
+            None => Err(EngineError::ModelNotFound(format!("{}; load it with /admin/models/load first", model_not_found(name)))),
+            Some(entry) if entry.status == ModelStatus::Loading => Err(EngineError::InvalidInput(format!("{} is already being loaded", name))),
+            Some(entry) => Ok(entry.backend),
+        }
+    }
+
+    /// Load parameters of the admin-loaded models, for state snapshots.
+    pub async fn specs(&self) -> Vec<ModelSpec> {
+        self.entries.read().await.values().filter_map(|entry| entry.spec.clone()).collect()
+    }
+
+    pub async fn list(&self) -> ModelsListResponse {
+        let entries = self.entries.read().await;
+        let named = |kind: &str| {
+            let mut names: Vec<String> = entries.iter().filter(|(_, e)| e.serves(kind)).map(|(name, _)| name.clone()).collect();
+            names.sort();
+            names
+        };
+        let mut models: Vec<ModelInfo> = entries
+            .iter()
+            .map(|(name, entry)| ModelInfo {
+                name: name.clone(),
+                kind: entry.kind.clone(),
+                backend: entry.backend.to_string(),
+                capabilities: match &entry.idle {
+                    Some(served) => served.capabilities.clone(),
+                    None => entry.runtimes.capabilities(),
+                },
+                status: match entry.status {
+                    ModelStatus::Ready if entry.is_idle() => ModelStatus::Idle,
+                    ModelStatus::Ready if entry.unhealthy => ModelStatus::Unhealthy,
+                    status => status,
+                },
+                probe_error: entry.probe_error.clone(),
+                loaded_at: entry.loaded_at,
+                ram_bytes: Some(entry.footprint.ram_bytes).filter(|bytes| *bytes > 0),
+                vram_bytes: Some(entry.footprint.vram_bytes).filter(|bytes| *bytes > 0),
+                last_used_at: Some(entry.last_used.load(Ordering::Relaxed) / 1000).filter(|secs| *secs > 0),
+                path: entry.spec.as_ref().and_then(|spec| spec.path.clone()),
+                options: entry.spec.as_ref().map(|spec| spec.options.clone()).filter(|options| *options != Default::default()),
+            })
+            .collect();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        ModelsListResponse {
+            llm: named("llm"),
+            embedding: named("embedding"),
+            multimodal: named("multimodal"),
+            image: named("image"),
+            rerank: named("rerank"),
+            audio: named("audio"),
+            tts: named("tts"),
+            models,
+            memory: self.memory(&entries),
+        }
+    }
+
+    pub(crate) async fn write(&self) -> RwLockWriteGuard<'_, Entries> {
+        self.entries.write().await
+    }
+
+    pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, Entries>> {
+        self.entries.try_write().ok()
+    }
+
+    pub(crate) fn try_llm(&self, name: &str) -> Option<Arc<dyn LlmRuntime>> {
+        self.entries.try_read().ok()?.get(name).and_then(|entry| entry.runtimes.llm.clone())
+    }
+}
@@ -0,0 +1,156 @@
+use metrics::counter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::api::dto::{ConfigReload, LoadModelRequest};
+use crate::config::Config;
+use crate::engine::{check_load, CoreEngine};
+
+/// The configuration file the server runs with and the models it declared.
+#[derive(Default)]
+pub struct ConfigSource {
+    path: Option<PathBuf>,
+    models: Vec<LoadModelRequest>,
+}
+
+/// ENV: CONFIG_RELOAD_INTERVAL_SECS, how often the configuration file is checked for changes
+/// (default 0: only `POST /admin/reload` reloads it)
+fn reload_interval() -> Option<Duration> {
+    let secs = crate::config::var("CONFIG_RELOAD_INTERVAL_SECS").ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl CoreEngine {
+    /// Installs `config`, read from `path`, and loads the models it declares.
+    pub async fn apply_config(&self, path: PathBuf, config: Config) -> Result<(), String> {
+        let mut source = self.config.lock().await;
+        config.install();
+        config.preload(self).await?;
+        *source = ConfigSource { path: Some(path), models: config.models };
+        Ok(())
+    }
+
+    /// Reads the configuration file again and applies it as a whole. Every added or changed
+    /// model is loaded alongside the version it replaces; once all of them are ready the new
+    /// versions take over, models no longer declared are unloaded and settings read per use
+    /// (API keys, endpoint limits, cache settings) take effect. A model that fails to load
+    /// leaves the models and settings as they were.
+    pub async fn reload_config(&self) -> Result<ConfigReload, String> {
+        let result = self.apply_reload().await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        counter!("config_reloads_total", 1, "outcome" => outcome);
+        result
+    }
+
+    async fn apply_reload(&self) -> Result<ConfigReload, String> {
+        let mut source = self.config.lock().await;
+        let path = source.path.clone().ok_or("the server was started without a configuration file")?;
+        let config = Config::load(&path)?;
+        // The models load with the settings declared alongside them
+        let previous = config.install();
+
+        let mut report = ConfigReload {
+            object: "config.reload".to_string(),
+            path: path.display().to_string(),
+            loaded: Vec::new(),
+            unloaded: Vec::new(),
+            unchanged: Vec::new(),
+        };
+        let mut prepared = Vec::new();
+        let mut failed = Vec::new();
+        let declared: Vec<&str> = config.models.iter().map(|model| model.model.as_str()).collect();
+        for model in &config.models {
+            let previous = source.models.iter().find(|old| old.model == model.model);
+            // Unloaded through the admin API since: loaded again
+            let serving = self.registry.serves(&model.model, &model.kind).await;
+            if previous == Some(model) && serving {
+                report.unchanged.push(model.model.clone());
+                continue;
+            }
+            let path = model.path.as_deref();
+            // A fallback this reload unloads does not count as loaded
+            let dropped = model.options.fallback_model.as_deref()
+                .filter(|fallback| !declared.contains(fallback) && source.models.iter().any(|old| old.model == *fallback));
+            let checked = match (check_load(&model.kind, path, &model.options), dropped) {
+                (Err(e), _) => Err(e),
+                (Ok(_), Some(fallback)) => Err(format!("fallback_model {} is no longer declared", fallback)),
+                (Ok(template), None) => self.check_fallback(&model.model, &model.options, &declared).await.map(|()| template),
+            };
+            let loaded = match checked {
+                Ok(template) => {
+                    let loading = self.registry.begin_load(&model.model, &model.kind).await;
+                    match self.prepare_model(&model.kind, &model.model, path, &model.options, template).await {
+                        Ok(prepared) => Ok((loading, prepared)),
+                        Err(e) => {
+                            loading.abort().await;
+                            Err(e)
+                        }
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            match loaded {
+                Ok(model) => prepared.push(model),
+                Err(e) => {
+                    tracing::error!("config reload: could not load {} model {}: {}", model.kind, model.model, e);
+                    failed.push(format!("{}: {}", model.model, e));
+                }
+            }
+        }
+        if !failed.is_empty() {
+            // Dropping the prepared models gives back their memory
+            for (loading, model) in prepared {
+                drop(model);
+                loading.abort().await;
+            }
+            crate::config::restore_settings(previous);
+            return Err(format!("nothing was changed; could not load {}", failed.join("; ")));
+        }
+
+        crate::api::limits::reload();
+        for old in &source.models {
+            if !config.models.iter().any(|model| model.model == old.model) {
+                match self.unload_model(&old.kind, &old.model).await {
+                    Ok(()) => report.unloaded.push(old.model.clone()),
+                    // Unloaded through the admin API since
+                    Err(e) => tracing::warn!("config reload: {} was not unloaded: {}", old.model, e),
+                }
+            }
+        }
+        for (loading, model) in prepared {
+            report.loaded.push(model.spec.model.clone());
+            self.install_model(model).await;
+            loading.finish();
+        }
+        source.models = config.models;
+        tracing::info!("reloaded {}: {} loaded, {} unloaded", report.path, report.loaded.len(), report.unloaded.len());
+        Ok(report)
+    }
+
+    /// Reloads the configuration file whenever it changes, when CONFIG_RELOAD_INTERVAL_SECS
+    /// is set.
+    pub async fn watch_config(self: &Arc<Self>) {
+        let (Some(interval), Some(path)) = (reload_interval(), self.config.lock().await.path.clone()) else { return };
+        let engine = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut seen = modified(&path);
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(engine) = engine.upgrade() else { return };
+                let current = modified(&path);
+                if current == seen {
+                    continue;
+                }
+                seen = current;
+                if let Err(e) = engine.reload_config().await {
+                    tracing::error!("config reload failed: {}", e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,58 @@
+use tokio::sync::mpsc;
+
+use crate::api::dto::{EmbeddingUsage, RerankDocument, RerankRequest, RerankResponse, RerankResult};
+use crate::engine::{reply, CoreEngine, EngineError, EngineRequest};
+use crate::runtime::{approximate_token_count, RerankRuntime};
+
+/// Token estimate without a tokenizer, for scheduling: the query is encoded once per document.
+pub fn approximate_tokens(request: &RerankRequest) -> u32 {
+    let query = approximate_token_count(&request.query);
+    request.documents.iter().map(|d| query + approximate_token_count(d)).sum()
+}
+
+/// Scores every document and returns them best first, trimmed to `top_n`.
+pub async fn rerank(runtime: &dyn RerankRuntime, request: RerankRequest) -> Result<RerankResponse, EngineError> {
+    if request.documents.is_empty() {
+        return Err(EngineError::InvalidInput("documents must not be empty".to_string()));
+    }
+    let scores = runtime.score(&request.query, &request.documents).await?;
+    if scores.len() != request.documents.len() {
+        return Err(EngineError::Backend(format!("Runtime returned {} scores for {} documents", scores.len(), request.documents.len())));
+    }
+    let query_tokens = runtime.count_tokens(&request.query);
+    let prompt_tokens = request.documents.iter().map(|d| query_tokens + runtime.count_tokens(d)).sum();
+
+    let mut ranked: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+    // Stable sort keeps the original order among equal scores
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(request.top_n.unwrap_or(ranked.len()));
+    let mut documents: Vec<Option<String>> = request.documents.into_iter().map(Some).collect();
+    let results = ranked
+        .into_iter()
+        .map(|(index, relevance_score)| RerankResult {
+            index,
+            relevance_score,
+            document: if request.return_documents {
+                documents[index].take().map(|text| RerankDocument { text })
+            } else {
+                None
+            },
+        })
+        .collect();
+    Ok(RerankResponse {
+        object: "list".to_string(),
+        model: request.model,
+        results,
+        usage: EmbeddingUsage { prompt_tokens, total_tokens: prompt_tokens },
+    })
+}
+
+impl CoreEngine {
+    pub async fn process_rerank_request(&self, request: RerankRequest) -> Result<RerankResponse, EngineError> {
+        self.check_admission()?;
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        self.enqueue(EngineRequest::Rerank { request, response_sender }).await?;
+
+        reply(response_receiver).await
+    }
+}
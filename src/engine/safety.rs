@@ -0,0 +1,261 @@
+use metrics::counter;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::api::dto::{ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent, ContentPart, SafetyStatus, StrikeAction};
+use crate::engine::{events::EngineEvent, scheduler::ANONYMOUS_CLIENT, CoreEngine};
+
+#[derive(Debug, Clone)]
+pub struct SafetyPolicy {
+    // Case-insensitive whole-word match over message text; None disables moderation
+    pub blocklist: Option<Regex>,
+    pub warn_strikes: usize,
+    pub throttle_strikes: usize,
+    pub block_strikes: usize,
+    // Strikes older than this no longer count
+    pub strike_ttl: Duration,
+    // Minimum gap between requests of a throttled subject
+    pub throttle_interval: Duration,
+    pub state_path: Option<String>,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            blocklist: None,
+            warn_strikes: 1,
+            throttle_strikes: 3,
+            block_strikes: 5,
+            strike_ttl: Duration::from_secs(86_400),
+            throttle_interval: Duration::from_secs(10),
+            state_path: None,
+        }
+    }
+}
+
+impl SafetyPolicy {
+    /// ENV: SAFETY_BLOCKLIST (comma-separated terms), SAFETY_WARN_STRIKES (default 1),
+    /// SAFETY_THROTTLE_STRIKES (default 3), SAFETY_BLOCK_STRIKES (default 5),
+    /// SAFETY_STRIKE_TTL_SECS (default 86400), SAFETY_THROTTLE_INTERVAL_MS (default 10000),
+    /// SAFETY_STATE_PATH (strike file, not persisted when unset)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let defaults = Self::default();
+        let terms: Vec<String> = crate::config::var("SAFETY_BLOCKLIST")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect();
+        Self {
+            blocklist: Self::blocklist_regex(&terms),
+            warn_strikes: env("SAFETY_WARN_STRIKES").map_or(defaults.warn_strikes, |v| v as usize),
+            throttle_strikes: env("SAFETY_THROTTLE_STRIKES").map_or(defaults.throttle_strikes, |v| v as usize),
+            block_strikes: env("SAFETY_BLOCK_STRIKES").map_or(defaults.block_strikes, |v| v as usize),
+            strike_ttl: env("SAFETY_STRIKE_TTL_SECS").map_or(defaults.strike_ttl, Duration::from_secs),
+            throttle_interval: env("SAFETY_THROTTLE_INTERVAL_MS").map_or(defaults.throttle_interval, Duration::from_millis),
+            state_path: crate::config::var("SAFETY_STATE_PATH").ok().filter(|p| !p.is_empty()),
+        }
+    }
+
+    pub fn blocklist_regex(terms: &[String]) -> Option<Regex> {
+        if terms.is_empty() {
+            return None;
+        }
+        let alternation = terms.iter().map(|t| regex::escape(t)).collect::<Vec<_>>().join("|");
+        Regex::new(&format!(r"(?i)\b(?:{})\b", alternation)).ok()
+    }
+
+    /// First blocklisted term found in the conversation, if any.
+    pub fn violation(&self, messages: &[ChatCompletionMessage]) -> Option<String> {
+        let blocklist = self.blocklist.as_ref()?;
+        messages.iter().find_map(|m| match &m.content {
+            ChatMessageContent::Text(text) => blocklist.find(text).map(|hit| hit.as_str().to_lowercase()),
+            ChatMessageContent::Parts(parts) => parts.iter().find_map(|p| match p {
+                ContentPart::Text { text } => blocklist.find(text).map(|hit| hit.as_str().to_lowercase()),
+                ContentPart::ImageUrl { .. } => None,
+            }),
+        })
+    }
+
+    /// Action for a subject holding `strikes` live strikes.
+    pub fn action(&self, strikes: usize) -> StrikeAction {
+        if strikes == 0 {
+            StrikeAction::None
+        } else if strikes >= self.block_strikes {
+            StrikeAction::Block
+        } else if strikes >= self.throttle_strikes {
+            StrikeAction::Throttle
+        } else if strikes >= self.warn_strikes {
+            StrikeAction::Warn
+        } else {
+            StrikeAction::None
+        }
+    }
+}
+
+/// Who strikes are counted against: the API key, narrowed to the end user when the request
+/// names one (apps serving many users behind one key).
+pub fn subject(request: &ChatCompletionRequest) -> String {
+    let client = request.client_id.as_deref().unwrap_or(ANONYMOUS_CLIENT);
+    match request.user.as_deref().filter(|u| !u.is_empty()) {
+        Some(user) => format!("{}/{}", client, user),
+        None => client.to_string(),
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SubjectRecord {
+    // Unix seconds of each violation still inside the strike TTL
+    strikes: Vec<u64>,
+    #[serde(skip)]
+    last_request_ms: u128,
+}
+
+/// Strike counts per subject, escalating warn → throttle → block as violations accumulate.
+pub struct SafetyLedger {
+    policy: SafetyPolicy,
+    subjects: Mutex<HashMap<String, SubjectRecord>>,
+}
+
+impl SafetyLedger {
+    /// Restores strikes from `policy.state_path` when it exists.
+    pub fn new(policy: SafetyPolicy) -> Self {
+        let subjects = policy
+            .state_path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(subjects) => Some(subjects),
+                Err(e) => {
+                    tracing::warn!("ignoring unreadable safety state: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self { policy, subjects: Mutex::new(subjects) }
+    }
+
+    pub fn policy(&self) -> &SafetyPolicy {
+        &self.policy
+    }
+
+    fn live_strikes(&self, record: &mut SubjectRecord) -> usize {
+        let cutoff = now_secs().saturating_sub(self.policy.strike_ttl.as_secs());
+        record.strikes.retain(|&at| at > cutoff);
+        record.strikes.len()
+    }
+
+    /// Refuses blocked subjects, and throttled ones that come back too soon.
+    pub fn admit(&self, subject: &str) -> Result<(), String> {
+        let mut subjects = self.subjects.lock().unwrap();
+        let Some(record) = subjects.get_mut(subject) else { return Ok(()) };
+        let strikes = self.live_strikes(record);
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        match self.policy.action(strikes) {
+            StrikeAction::Block => {
+                counter!("safety_refusals_total", 1, "action" => "block");
+                Err("Access suspended after repeated content policy violations".to_string())
+            }
+            StrikeAction::Throttle if now_ms < record.last_request_ms + self.policy.throttle_interval.as_millis() => {
+                counter!("safety_refusals_total", 1, "action" => "throttle");
+                let wait_ms = record.last_request_ms + self.policy.throttle_interval.as_millis() - now_ms;
+                Err(format!("Throttled after content policy violations; retry in {}ms", wait_ms))
+            }
+            _ => {
+                record.last_request_ms = now_ms;
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds a strike and returns the subject's strike count and resulting action.
+    pub fn record_violation(&self, subject: &str) -> (usize, StrikeAction) {
+        let result = {
+            let mut subjects = self.subjects.lock().unwrap();
+            let record = subjects.entry(subject.to_string()).or_default();
+            record.strikes.push(now_secs());
+            let strikes = self.live_strikes(record);
+            (strikes, self.policy.action(strikes))
+        };
+        counter!("safety_violations_total", 1, "action" => result.1.as_str());
+        self.persist();
+        result
+    }
+
+    /// Clears a subject's strikes; false when it had none.
+    pub fn pardon(&self, subject: &str) -> bool {
+        let removed = self.subjects.lock().unwrap().remove(subject).is_some();
+        if removed {
+            self.persist();
+        }
+        removed
+    }
+
+    pub fn snapshot(&self) -> Vec<SafetyStatus> {
+        let mut subjects = self.subjects.lock().unwrap();
+        let mut list: Vec<SafetyStatus> = subjects
+            .iter_mut()
+            .filter_map(|(subject, record)| {
+                let strikes = self.live_strikes(record);
+                (strikes > 0).then(|| SafetyStatus {
+                    subject: subject.clone(),
+                    strikes,
+                    action: self.policy.action(strikes),
+                    last_violation: record.strikes.last().copied().unwrap_or_default(),
+                })
+            })
+            .collect();
+        list.sort_by(|a, b| a.subject.cmp(&b.subject));
+        list
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.policy.state_path else { return };
+        let bytes = match serde_json::to_vec(&*self.subjects.lock().unwrap()) {
+            Ok(bytes) => bytes,
+            Err(e) => return tracing::warn!("serialize safety state: {}", e),
+        };
+        // Write-then-rename so a crash never leaves a truncated file behind
+        let tmp = format!("{}.tmp", path);
+        if let Err(e) = std::fs::write(&tmp, bytes).and_then(|_| std::fs::rename(&tmp, path)) {
+            tracing::warn!("persist safety state to {}: {}", path, e);
+        }
+    }
+}
+
+impl CoreEngine {
+    /// Applies the strike policy to a chat request: refuses blocked or throttled subjects, and
+    /// records a strike (rejecting the request) when the conversation hits the blocklist.
+    pub(crate) fn moderate(&self, request: &ChatCompletionRequest) -> Result<(), String> {
+        let subject = subject(request);
+        // Server-initiated traffic (evals, probes) is not subject to the strike policy
+        if subject.starts_with("admin:") {
+            return Ok(());
+        }
+        self.safety.admit(&subject)?;
+        let Some(term) = self.safety.policy().violation(&request.messages) else { return Ok(()) };
+        let (strikes, action) = self.safety.record_violation(&subject);
+        tracing::info!("content policy violation by {} ({:?}, strike {})", subject, term, strikes);
+        self.events.publish(EngineEvent::SafetyStrike { subject, strikes, action: action.as_str().to_string() });
+        Err(match action {
+            StrikeAction::Block => "Request rejected by content policy; access is now suspended".to_string(),
+            StrikeAction::Throttle => "Request rejected by content policy; further requests will be throttled".to_string(),
+            _ => format!("Request rejected by content policy (strike {} of {})", strikes, self.safety.policy().block_strikes),
+        })
+    }
+
+    pub fn safety(&self) -> &SafetyLedger {
+        &self.safety
+    }
+}
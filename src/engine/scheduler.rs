@@ -0,0 +1,570 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use metrics::counter;
+use tokio::sync::{futures::Notified, Notify};
+
+use crate::api::dto::{ClientUsage, CostModelInfo, Priority, UsageQuery, UsageResponse};
+use crate::engine::{CoreEngine, EngineError};
+use crate::runtime::DEFAULT_MAX_TOKENS;
+
+pub const ANONYMOUS_CLIENT: &str = "anonymous";
+// Prompt tokens are processed in one batched forward pass, far cheaper than decoding
+pub const PREFILL_WEIGHT: f64 = 0.1;
+// Flat cost of one generated image, in decode-token equivalents
+pub const IMAGE_COST: f64 = 1000.0;
+// Cost of one second of audio to transcribe, in decode-token equivalents
+pub const AUDIO_SECOND_COST: f64 = 20.0;
+// Smoothing factor of the per-model completion length average
+const HISTORY_ALPHA: f64 = 0.2;
+/// Error prefixes of requests turned away because the queue is full, overall or for their key,
+/// of requests no worker took up within QUEUE_MAX_WAIT_MS, and of those arriving while the
+/// server shuts down.
+pub const QUEUE_FULL: &str = "Request queue is full";
+pub const KEY_QUEUE_FULL: &str = "Too many queued requests for this API key";
+pub const QUEUE_TIMEOUT: &str = "Server overloaded";
+pub const SHUTTING_DOWN: &str = "Server is shutting down";
+
+/// Predicts what a request will cost in decode-token equivalents.
+///
+/// Chat requests are charged for the decode tokens they are expected to produce: `max_tokens`,
+/// lowered to the model's observed average completion length once there is history, plus a
+/// small prefill charge for the prompt.
+#[derive(Default)]
+pub struct CostModel {
+    avg_completion_tokens: Mutex<HashMap<String, f64>>,
+}
+
+impl CostModel {
+    pub fn estimate_chat(&self, model: &str, max_tokens: Option<u32>, prompt_tokens: u32) -> f64 {
+        let cap = max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as f64;
+        let predicted = self.avg_completion_tokens.lock().unwrap().get(model).map_or(cap, |avg| avg.min(cap));
+        predicted + prompt_tokens as f64 * PREFILL_WEIGHT
+    }
+
+    pub fn estimate_embeddings(&self, prompt_tokens: u32) -> f64 {
+        (prompt_tokens as f64 * PREFILL_WEIGHT).max(1.0)
+    }
+
+    pub fn estimate_images(&self, n: u32) -> f64 {
+        n as f64 * IMAGE_COST
+    }
+
+    pub fn estimate_speech(&self, input_tokens: u32) -> f64 {
+        (input_tokens as f64).max(1.0)
+    }
+
+    pub fn estimate_audio(&self, seconds: f32) -> f64 {
+        (seconds as f64 * AUDIO_SECOND_COST).max(1.0)
+    }
+
+    pub fn observe_completion(&self, model: &str, completion_tokens: u32) {
+        let mut history = self.avg_completion_tokens.lock().unwrap();
+        let sample = completion_tokens as f64;
+        history
+            .entry(model.to_string())
+            .and_modify(|avg| *avg += HISTORY_ALPHA * (sample - *avg))
+            .or_insert(sample);
+    }
+
+    pub fn info(&self) -> CostModelInfo {
+        CostModelInfo {
+            prefill_weight: PREFILL_WEIGHT,
+            image_cost: IMAGE_COST,
+            audio_second_cost: AUDIO_SECOND_COST,
+            default_max_tokens: DEFAULT_MAX_TOKENS,
+            avg_completion_tokens: self.avg_completion_tokens.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Per-client cost consumption, reported by /admin/usage.
+#[derive(Default)]
+pub struct UsageLedger {
+    clients: Mutex<HashMap<String, ClientUsage>>,
+}
+
+impl UsageLedger {
+    pub fn charge_estimate(&self, client: &str, cost: f64) {
+        let mut clients = self.clients.lock().unwrap();
+        let entry = clients.entry(client.to_string()).or_insert_with(|| ClientUsage { client: client.to_string(), ..Default::default() });
+        entry.requests += 1;
+        entry.estimated_cost += cost;
+    }
+
+    pub fn charge_actual(&self, client: &str, cost: f64) {
+        let mut clients = self.clients.lock().unwrap();
+        let entry = clients.entry(client.to_string()).or_insert_with(|| ClientUsage { client: client.to_string(), ..Default::default() });
+        entry.actual_cost += cost;
+    }
+
+    /// Charges a request answered with the result of another one's generation, which it did
+    /// not queue for.
+    pub fn charge_reused(&self, client: &str, cost: f64) {
+        let mut clients = self.clients.lock().unwrap();
+        let entry = clients.entry(client.to_string()).or_insert_with(|| ClientUsage { client: client.to_string(), ..Default::default() });
+        entry.requests += 1;
+        entry.estimated_cost += cost;
+        entry.actual_cost += cost;
+    }
+
+    pub fn snapshot(&self) -> Vec<ClientUsage> {
+        let mut list: Vec<ClientUsage> = self.clients.lock().unwrap().values().cloned().collect();
+        list.sort_by(|a, b| a.client.cmp(&b.client));
+        list
+    }
+}
+
+struct Entry<T> {
+    finish: f64,
+    start: f64,
+    seq: u64,
+    // When the entry joined this queue
+    entered: Instant,
+    item: T,
+}
+
+// The first queued entry of a client
+struct Head {
+    finish: f64,
+    seq: u64,
+    client: String,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    // Reversed so the max-heap pops the smallest finish tag, FIFO among ties
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.finish.total_cmp(&self.finish).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Weighted fair queue over clients, where each entry's weight is its estimated cost.
+///
+/// An entry's finish tag is `max(virtual time, client's last finish tag) + cost`, and entries
+/// are served in finish-tag order. A client submitting expensive requests therefore waits
+/// proportionally longer than one submitting cheap requests, and an idle client re-enters at
+/// the current virtual time instead of cashing in credit from its idle period.
+///
+/// A client's tags only grow, so its entries queue in order of arrival and only the first of
+/// each client competes for the next turn: popping costs the log of the number of clients, and
+/// passing over ineligible clients never touches the entries queued behind them.
+pub struct FairQueue<T> {
+    queues: HashMap<String, VecDeque<Entry<T>>>,
+    heads: BinaryHeap<Head>,
+    len: usize,
+    last_finish: HashMap<String, f64>,
+    virtual_time: f64,
+    seq: u64,
+    // No entry entered before this, so nothing needs scanning until it is old enough
+    oldest: Option<Instant>,
+}
+
+impl<T> Default for FairQueue<T> {
+    fn default() -> Self {
+        Self { queues: HashMap::new(), heads: BinaryHeap::new(), len: 0, last_finish: HashMap::new(), virtual_time: 0.0, seq: 0, oldest: None }
+    }
+}
+
+impl<T> FairQueue<T> {
+    pub fn push(&mut self, client: &str, cost: f64, item: T) {
+        self.push_at(client, cost, item, Instant::now());
+    }
+
+    fn push_at(&mut self, client: &str, cost: f64, item: T, entered: Instant) {
+        let last = self.last_finish.get(client).copied().unwrap_or(0.0);
+        let start = last.max(self.virtual_time);
+        let finish = start + cost.max(0.0);
+        self.last_finish.insert(client.to_string(), finish);
+        self.seq += 1;
+        self.oldest = Some(self.oldest.map_or(entered, |oldest| oldest.min(entered)));
+        let queue = self.queues.entry(client.to_string()).or_default();
+        if queue.is_empty() {
+            self.heads.push(Head { finish, seq: self.seq, client: client.to_string() });
+        }
+        queue.push_back(Entry { finish, start, seq: self.seq, entered, item });
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_first(|_| true)
+    }
+
+    /// Drops the entries `keep` turns down.
+    pub fn retain(&mut self, keep: impl Fn(&T) -> bool) {
+        for queue in self.queues.values_mut() {
+            queue.retain(|entry| keep(&entry.item));
+        }
+        self.rebuild();
+    }
+
+    /// Pops the first entry in finish-tag order whose client is `eligible`.
+    pub fn pop_first(&mut self, eligible: impl Fn(&str) -> bool) -> Option<T> {
+        let mut skipped = Vec::new();
+        let head = loop {
+            match self.heads.pop() {
+                Some(head) if eligible(&head.client) => break Some(head),
+                Some(head) => skipped.push(head),
+                None => break None,
+            }
+        };
+        self.heads.extend(skipped);
+        let head = head?;
+        let queue = self.queues.get_mut(&head.client).expect("every head has a queue");
+        let entry = queue.pop_front().expect("every head has an entry");
+        match queue.front() {
+            Some(next) => self.heads.push(Head { finish: next.finish, seq: next.seq, client: head.client }),
+            None => {
+                self.queues.remove(&head.client);
+            }
+        }
+        self.len -= 1;
+        self.virtual_time = self.virtual_time.max(entry.start);
+        // Clients with nothing queued past the virtual time no longer need their tag
+        let virtual_time = self.virtual_time;
+        self.last_finish.retain(|_, finish| *finish > virtual_time);
+        Some(entry.item)
+    }
+
+    // Takes out the entries that entered at or before `before`, with their clients and costs,
+    // in the order they were queued
+    fn take_entered_by(&mut self, before: Instant) -> Vec<(String, f64, T)> {
+        if self.oldest.is_none_or(|oldest| oldest > before) {
+            return Vec::new();
+        }
+        let mut taken = Vec::new();
+        for (client, queue) in &mut self.queues {
+            let (old, kept): (VecDeque<_>, VecDeque<_>) = std::mem::take(queue).into_iter().partition(|entry| entry.entered <= before);
+            *queue = kept;
+            taken.extend(old.into_iter().map(|entry| (entry.seq, client.clone(), entry.finish - entry.start, entry.item)));
+        }
+        self.rebuild();
+        taken.sort_by_key(|(seq, ..)| *seq);
+        taken.into_iter().map(|(_, client, cost, item)| (client, cost, item)).collect()
+    }
+
+    // Recomputes the heads, length and oldest entry after entries were taken out of the middle
+    // of the queues
+    fn rebuild(&mut self) {
+        self.queues.retain(|_, queue| !queue.is_empty());
+        self.heads = self.queues.iter().map(|(client, queue)| Head { finish: queue[0].finish, seq: queue[0].seq, client: client.clone() }).collect();
+        self.len = self.queues.values().map(VecDeque::len).sum();
+        self.oldest = self.queues.values().flatten().map(|entry| entry.entered).min();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Clients with anything queued, once each.
+    pub fn clients(&self) -> impl Iterator<Item = &str> {
+        self.queues.keys().map(String::as_str)
+    }
+}
+
+/// Fair queues by priority: entries of a higher priority are served first, and entries of the
+/// same priority share the workers fairly between their clients. An entry that has waited
+/// `age` long at its priority moves up one, so lower priorities are not starved by a steady
+/// stream of higher ones.
+pub struct PriorityQueue<T> {
+    // Indexed by `Priority::ALL`, highest first
+    levels: [FairQueue<T>; 3],
+}
+
+impl<T> Default for PriorityQueue<T> {
+    fn default() -> Self {
+        Self { levels: Default::default() }
+    }
+}
+
+impl<T> PriorityQueue<T> {
+    pub fn push(&mut self, priority: Priority, client: &str, cost: f64, item: T) {
+        let level = Priority::ALL.iter().position(|p| *p == priority).expect("every priority has a level");
+        self.levels[level].push(client, cost, item);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.levels.iter_mut().find_map(FairQueue::pop)
+    }
+
+    /// Moves the entries that have waited `aging` or longer at their priority, as of `now`, up
+    /// one priority, where they queue behind that priority's entries of the same client.
+    pub fn age(&mut self, now: Instant, aging: Duration) {
+        let Some(before) = now.checked_sub(aging) else { return };
+        // From the top, so nothing moves up twice at once
+        for level in 1..self.levels.len() {
+            for (client, cost, item) in self.levels[level].take_entered_by(before) {
+                self.levels[level - 1].push_at(&client, cost, item, now);
+            }
+        }
+    }
+
+    /// Pops from the highest priority with anything queued, preferring the clients `eligible`
+    /// says may take another worker; the others are served when nobody else is waiting.
+    pub fn pop_fair(&mut self, eligible: impl Fn(&str) -> bool) -> Option<T> {
+        let level = self.levels.iter_mut().find(|level| !level.is_empty())?;
+        level.pop_first(eligible).or_else(|| level.pop())
+    }
+
+    pub fn retain(&mut self, keep: impl Fn(&T) -> bool) {
+        for level in &mut self.levels {
+            level.retain(&keep);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.iter().map(FairQueue::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.iter().all(FairQueue::is_empty)
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = &str> {
+        self.levels.iter().flat_map(FairQueue::clients)
+    }
+}
+
+/// Requests each client has running, so that no client holds more than its share of the
+/// workers while others wait.
+pub struct KeyConcurrency {
+    workers: usize,
+    running: Mutex<HashMap<String, usize>>,
+    // Signalled whenever a request stops running
+    finished: Notify,
+}
+
+/// One running request; its client's count drops when it is dropped.
+pub struct Running {
+    concurrency: Arc<KeyConcurrency>,
+    client: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = self.concurrency.running.lock().unwrap();
+        if let Some(count) = running.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                running.remove(&self.client);
+            }
+        }
+        self.concurrency.finished.notify_waiters();
+    }
+}
+
+impl KeyConcurrency {
+    pub fn new(workers: usize) -> Self {
+        Self { workers: workers.max(1), running: Mutex::new(HashMap::new()), finished: Notify::new() }
+    }
+
+    pub fn start(self: &Arc<Self>, client: &str) -> Running {
+        *self.running.lock().unwrap().entry(client.to_string()).or_default() += 1;
+        Running { concurrency: self.clone(), client: client.to_string() }
+    }
+
+    pub fn running(&self, client: &str) -> usize {
+        self.running.lock().unwrap().get(client).copied().unwrap_or(0)
+    }
+
+    /// Workers each client may hold while `waiting` clients have requests queued: an even split
+    /// between them and the clients already running.
+    pub fn fair_share<'a>(&self, waiting: impl Iterator<Item = &'a str>) -> usize {
+        let mut active: HashSet<&str> = waiting.collect();
+        let running = self.running.lock().unwrap();
+        active.extend(running.keys().map(String::as_str));
+        self.workers.div_ceil(active.len().max(1))
+    }
+
+    /// Requests running across all clients.
+    pub fn total(&self) -> usize {
+        self.running.lock().unwrap().values().sum()
+    }
+
+    /// Resolves when a request next stops running.
+    pub fn finished(&self) -> Notified<'_> {
+        self.finished.notified()
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, usize> {
+        self.running.lock().unwrap().clone()
+    }
+}
+
+/// How many requests may wait for a worker before new ones are turned away, and for how long.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueLimits {
+    pub max_depth: Option<usize>,
+    pub max_per_key: Option<usize>,
+    pub max_wait: Option<Duration>,
+}
+
+impl QueueLimits {
+    /// ENV: QUEUE_MAX_DEPTH, QUEUE_MAX_PER_KEY (unset: unlimited), requests allowed to wait in
+    /// total and for each API key; QUEUE_MAX_WAIT_MS (unset or 0: unlimited), how long one may wait
+    pub fn from_env() -> Self {
+        let limit = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse().ok());
+        Self {
+            max_depth: limit("QUEUE_MAX_DEPTH"),
+            max_per_key: limit("QUEUE_MAX_PER_KEY"),
+            max_wait: limit("QUEUE_MAX_WAIT_MS").filter(|&ms| ms > 0).map(|ms| Duration::from_millis(ms as u64)),
+        }
+    }
+}
+
+/// ENV: QUEUE_PRIORITY_AGING_MS (default 30000, 0: never), how long a request waits at its
+/// priority before it is dispatched as if it had the next higher one
+pub fn priority_aging() -> Option<Duration> {
+    let ms = crate::config::var("QUEUE_PRIORITY_AGING_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(30_000u64);
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+#[derive(Default)]
+struct Waiting {
+    total: usize,
+    clients: HashMap<String, usize>,
+}
+
+/// Requests admitted to the queue and not yet handed to a worker, in total and per client.
+#[derive(Default)]
+pub struct Backlog {
+    waiting: Mutex<Waiting>,
+    // Signalled whenever a request leaves the queue
+    released: Notify,
+    // Signalled when a caller stops waiting for a worker, so its request leaves the queue too
+    abandoned: Notify,
+}
+
+/// A request's place in the backlog, given up when a worker takes the request, when its caller
+/// stops waiting, or when it is dropped.
+pub struct Slot {
+    backlog: Arc<Backlog>,
+    client: String,
+    released: AtomicBool,
+}
+
+impl Slot {
+    pub fn release(&self) {
+        if !self.released.swap(true, AtomicOrdering::Relaxed) {
+            self.backlog.release(&self.client);
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Backlog {
+    /// Counts a new request for `client`, or turns it away when the queue is over `limits`.
+    pub fn admit(self: &Arc<Self>, client: &str, limits: QueueLimits) -> Result<Slot, EngineError> {
+        let mut waiting = self.waiting.lock().unwrap();
+        let queued = waiting.clients.get(client).copied().unwrap_or(0);
+        if limits.max_per_key.is_some_and(|max| queued >= max) {
+            counter!("queue_rejections_total", 1, "reason" => "key_queue_full");
+            return Err(EngineError::QuotaExceeded(format!("{} ({} waiting); try again later", KEY_QUEUE_FULL, queued)));
+        }
+        if limits.max_depth.is_some_and(|max| waiting.total >= max) {
+            counter!("queue_rejections_total", 1, "reason" => "queue_full");
+            return Err(EngineError::Overloaded(format!("{} ({} waiting); try again later", QUEUE_FULL, waiting.total)));
+        }
+        waiting.total += 1;
+        *waiting.clients.entry(client.to_string()).or_default() += 1;
+        Ok(Slot { backlog: self.clone(), client: client.to_string(), released: AtomicBool::new(false) })
+    }
+
+    fn release(&self, client: &str) {
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting.total = waiting.total.saturating_sub(1);
+        if let Some(count) = waiting.clients.get_mut(client) {
+            *count -= 1;
+            if *count == 0 {
+                waiting.clients.remove(client);
+            }
+        }
+        self.released.notify_waiters();
+    }
+
+    /// Resolves when a request next leaves the queue.
+    pub fn released(&self) -> Notified<'_> {
+        self.released.notified()
+    }
+
+    /// Gives up `slot` for a caller that stopped waiting for a worker.
+    pub fn abandon(&self, slot: &Slot) {
+        slot.release();
+        self.abandoned.notify_one();
+    }
+
+    /// Resolves once a caller has stopped waiting since the last time it resolved.
+    pub async fn abandoned(&self) {
+        self.abandoned.notified().await
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.lock().unwrap().total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, usize> {
+        self.waiting.lock().unwrap().clients.clone()
+    }
+}
+
+impl CoreEngine {
+    /// Requests waiting for a worker.
+    pub fn queue_depth(&self) -> usize {
+        self.backlog.len()
+    }
+
+    pub async fn usage_report(&self) -> Result<UsageResponse, String> {
+        self.usage_report_for(&UsageQuery::default()).await
+    }
+
+    /// The usage report with its metered usage narrowed to `query`.
+    pub async fn usage_report_for(&self, query: &UsageQuery) -> Result<UsageResponse, String> {
+        let metered = self.metering.report(query.key.as_deref(), query.from, query.to).await?;
+        let (queued, running) = (self.backlog.snapshot(), self.key_concurrency.snapshot());
+        let mut data = self.usage_ledger.snapshot();
+        for usage in &mut data {
+            usage.queued = queued.get(&usage.client).copied().unwrap_or(0);
+            usage.running = running.get(&usage.client).copied().unwrap_or(0);
+        }
+        Ok(UsageResponse {
+            cost_model: self.cost_model.info(),
+            queued: self.queue_depth(),
+            data,
+            safety: self.safety.snapshot(),
+            metered,
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use metrics::{counter, histogram};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::engine::{events::EngineEvent, CoreEngine};
+
+// How long cancelled generations get to wind down once the drain timeout has passed
+const CANCEL_GRACE: Duration = Duration::from_secs(2);
+
+/// ENV: SHUTDOWN_DRAIN_TIMEOUT_SECS (default 30), how long a shutting-down server waits for
+/// queued and running requests before cancelling the generations still going
+pub fn drain_timeout() -> Duration {
+    Duration::from_secs(crate::config::var("SHUTDOWN_DRAIN_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30))
+}
+
+impl CoreEngine {
+    /// Turns away new requests, waits up to `timeout` for the queued and running ones to
+    /// finish, cancels the generations still running after that and unloads every model.
+    /// Returns whether everything finished within `timeout`.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.draining.store(true, Ordering::Relaxed);
+        let started = Instant::now();
+        let drained = self.wait_idle(timeout).await;
+        histogram!("shutdown_drain_ms", started.elapsed().as_millis() as f64);
+        if !drained {
+            let cancelled = self.inflight.cancel_newest(usize::MAX);
+            counter!("shutdown_cancelled_total", cancelled as u64);
+            tracing::warn!("shutdown: cancelled {} generations still running after {:?}", cancelled, timeout);
+            self.wait_idle(CANCEL_GRACE).await;
+        }
+        for name in self.registry.names().await {
+            let Some(entry) = self.registry.remove(&name).await else { continue };
+            self.events.publish(EngineEvent::ModelUnloaded { model: name, kind: entry.kind, reason: "shutdown".to_string() });
+        }
+        drained
+    }
+
+    /// Whether the server is shutting down and turning requests away.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    // Waits until no request is queued or running; false if that takes longer than `timeout`
+    async fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Listening before looking, so a request ending in between is not missed
+            let (released, finished) = (self.backlog.released(), self.key_concurrency.finished());
+            tokio::pin!(released, finished);
+            released.as_mut().enable();
+            finished.as_mut().enable();
+            if self.backlog.is_empty() && self.key_concurrency.total() == 0 {
+                return true;
+            }
+            tokio::select! {
+                _ = released => {}
+                _ = finished => {}
+                _ = tokio::time::sleep_until(deadline) => return false,
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use metrics::{counter, histogram};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicU64, Ordering}, RwLock},
+};
+
+use crate::api::dto::{CreateSplitRequest, SplitVariant, SplitVariantStats, TrafficSplit};
+use crate::engine::{registry::model_not_found, CoreEngine, EngineError};
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+// Outcomes of the requests a variant served; counted under the read lock, so requests never
+// wait on each other to record theirs
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_us: AtomicU64,
+}
+
+impl Counters {
+    fn stats(&self, model: &str) -> SplitVariantStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        let latency_ms = self.latency_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        let per_request = |total: f64| if requests == 0 { 0.0 } else { total / requests as f64 };
+        SplitVariantStats { model: model.to_string(), requests, errors, error_rate: per_request(errors as f64), avg_latency_ms: per_request(latency_ms) }
+    }
+}
+
+struct Split {
+    variants: Vec<SplitVariant>,
+    created: u64,
+    // One per variant, in the same order
+    counters: Vec<Counters>,
+}
+
+impl Split {
+    fn listing(&self, model: &str) -> TrafficSplit {
+        let stats = self.variants.iter().zip(&self.counters).map(|(variant, counters)| counters.stats(&variant.model)).collect();
+        TrafficSplit { model: model.to_string(), variants: self.variants.clone(), created: self.created, stats }
+    }
+}
+
+/// Spreads the requests for an exposed model name over several loaded models by percentage,
+/// e.g. 90% to `v1` and 10% to `v2`, and keeps request, error and latency figures per variant
+/// to compare them by. A split holds until an operator changes it, unless a canary deployment
+/// on the same name moves a failing variant's share back to its baseline.
+#[derive(Default)]
+pub struct TrafficSplitter {
+    splits: RwLock<HashMap<String, Split>>,
+}
+
+impl TrafficSplitter {
+    /// Checks and registers `req`, replacing an earlier split of the same name.
+    pub(crate) fn insert(&self, req: CreateSplitRequest) -> Result<TrafficSplit, String> {
+        if req.variants.is_empty() {
+            return Err("a traffic split needs at least one variant".to_string());
+        }
+        for (i, variant) in req.variants.iter().enumerate() {
+            if req.variants[..i].iter().any(|v| v.model == variant.model) {
+                return Err(format!("variant {} appears twice in the split", variant.model));
+            }
+            if !(0.0..=100.0).contains(&variant.percent) {
+                return Err(format!("percent of {} must be between 0 and 100", variant.model));
+            }
+        }
+        let total: f32 = req.variants.iter().map(|v| v.percent).sum();
+        if (total - 100.0).abs() > 0.01 {
+            return Err(format!("variant percentages add up to {}, not 100", total));
+        }
+        let split = Split {
+            counters: req.variants.iter().map(|_| Counters::default()).collect(),
+            variants: req.variants,
+            created: now_secs(),
+        };
+        let listing = split.listing(&req.model);
+        self.splits.write().unwrap().insert(req.model, split);
+        Ok(listing)
+    }
+
+    pub fn remove(&self, model: &str) -> Result<(), String> {
+        self.splits.write().unwrap().remove(model).map(|_| ()).ok_or_else(|| format!("Traffic split for model {} not found", model))
+    }
+
+    pub fn list(&self) -> Vec<TrafficSplit> {
+        let mut list: Vec<TrafficSplit> = self.splits.read().unwrap().iter().map(|(model, split)| split.listing(model)).collect();
+        list.sort_by(|a, b| a.model.cmp(&b.model));
+        list
+    }
+
+    pub fn contains(&self, model: &str) -> bool {
+        self.splits.read().unwrap().contains_key(model)
+    }
+
+    /// Picks the variant that serves this request for `model`, None for names without a split.
+    pub fn resolve(&self, model: &str) -> Option<String> {
+        let splits = self.splits.read().unwrap();
+        let split = splits.get(model)?;
+        let mut roll = rand::thread_rng().r#gen::<f32>() * 100.0;
+        for variant in &split.variants {
+            if roll < variant.percent {
+                return Some(variant.model.clone());
+            }
+            roll -= variant.percent;
+        }
+        // Rounding can leave the roll just past the last share
+        split.variants.iter().rfind(|v| v.percent > 0.0).map(|v| v.model.clone())
+    }
+
+    /// Gives `canary` `weight` (0.0..=1.0) of the share `baseline` and `canary` have together in
+    /// the split of `model`, and `baseline` the rest. Returns the percentages they had before.
+    pub fn rebalance(&self, model: &str, baseline: &str, canary: &str, weight: f32) -> Result<(f32, f32), String> {
+        let mut splits = self.splits.write().unwrap();
+        let split = splits.get_mut(model).ok_or_else(|| format!("Traffic split for model {} not found", model))?;
+        let share = |name: &str| {
+            split.variants.iter().position(|v| v.model == name).ok_or_else(|| format!("{} is not a variant of the traffic split for {}", name, model))
+        };
+        let (b, c) = (share(baseline)?, share(canary)?);
+        let before = (split.variants[b].percent, split.variants[c].percent);
+        let pool = before.0 + before.1;
+        split.variants[c].percent = pool * weight;
+        split.variants[b].percent = pool - split.variants[c].percent;
+        Ok(before)
+    }
+
+    /// Puts back the percentages `rebalance` returned, provided the split of `model` still gives
+    /// `baseline` and `canary` the same share together; one an operator has changed since stays.
+    pub fn restore(&self, model: &str, baseline: &str, canary: &str, (baseline_percent, canary_percent): (f32, f32)) {
+        let mut splits = self.splits.write().unwrap();
+        let Some(split) = splits.get_mut(model) else { return };
+        let (Some(b), Some(c)) = (
+            split.variants.iter().position(|v| v.model == baseline),
+            split.variants.iter().position(|v| v.model == canary),
+        ) else {
+            return;
+        };
+        if (split.variants[b].percent + split.variants[c].percent - (baseline_percent + canary_percent)).abs() > 0.01 {
+            return;
+        }
+        split.variants[b].percent = baseline_percent;
+        split.variants[c].percent = canary_percent;
+    }
+
+    /// Records the outcome of a request for `model` that `variant` served.
+    pub fn record(&self, model: &str, variant: &str, ok: bool, latency_ms: f64) {
+        {
+            let splits = self.splits.read().unwrap();
+            let Some(split) = splits.get(model) else { return };
+            let Some(i) = split.variants.iter().position(|v| v.model == variant) else { return };
+            let counters = &split.counters[i];
+            counters.requests.fetch_add(1, Ordering::Relaxed);
+            if !ok {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            counters.latency_us.fetch_add((latency_ms * 1000.0) as u64, Ordering::Relaxed);
+        }
+        let outcome = if ok { "ok" } else { "error" };
+        counter!("split_requests_total", 1, "model" => model.to_string(), "variant" => variant.to_string(), "outcome" => outcome);
+        histogram!("split_latency_ms", latency_ms, "model" => model.to_string(), "variant" => variant.to_string());
+    }
+
+    /// ENV: MODEL_SPLITS, `;`-separated splits of the form `model=variant:percent,...`,
+    /// e.g. `chat=v1:90,v2:10`
+    pub fn parse_env(value: &str) -> Result<Vec<CreateSplitRequest>, String> {
+        value
+            .split(';')
+            .map(str::trim)
+            .filter(|split| !split.is_empty())
+            .map(|split| {
+                let (model, variants) = split.split_once('=').ok_or_else(|| format!("traffic split {:?} has no '='", split))?;
+                let variants = variants
+                    .split(',')
+                    .map(|variant| {
+                        let (model, percent) = variant.trim().split_once(':').ok_or_else(|| format!("variant {:?} has no ':percent'", variant))?;
+                        let percent = percent.parse().map_err(|_| format!("invalid percent in {:?}", variant))?;
+                        Ok(SplitVariant { model: model.to_string(), percent })
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(CreateSplitRequest { model: model.trim().to_string(), variants })
+            })
+            .collect()
+    }
+}
+
+impl CoreEngine {
+    pub async fn create_split(&self, req: CreateSplitRequest) -> Result<TrafficSplit, EngineError> {
+        let models = self.list_models().await;
+        for variant in &req.variants {
+            if !models.llm.contains(&variant.model) && !models.multimodal.contains(&variant.model) {
+                return Err(EngineError::ModelNotFound(model_not_found(&variant.model)));
+            }
+        }
+        self.splits.insert(req).map_err(EngineError::InvalidInput)
+    }
+
+    pub fn splits(&self) -> &TrafficSplitter {
+        &self.splits
+    }
+}
@@ -0,0 +1,114 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::api::artifacts::decode_hex;
+use crate::api::auth::{configured_key_hashes, requests_per_minute};
+use crate::api::dto::{CreateCanaryRequest, CreateFallbackRequest, CreateSplitRequest, ImportStateResponse, ModelSpec, QuotaSpec, ServerState, StateSnapshot};
+use crate::engine::CoreEngine;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+fn signing_key() -> Option<Vec<u8>> {
+    crate::config::var("STATE_SIGNING_KEY").ok().filter(|k| !k.is_empty()).map(String::into_bytes)
+}
+
+// The models of a snapshot, each after the fallback_model it names, so its fallback is loaded
+// when it is
+fn fallbacks_first(mut specs: Vec<ModelSpec>) -> Vec<ModelSpec> {
+    let mut ordered = Vec::with_capacity(specs.len());
+    while !specs.is_empty() {
+        let waits = |spec: &ModelSpec| spec.options.fallback_model.as_ref().is_some_and(|fallback| specs.iter().any(|other| &other.model == fallback));
+        // A cycle loads in any order and fails its check
+        let next = specs.iter().position(|spec| !waits(spec)).unwrap_or(0);
+        ordered.push(specs.remove(next));
+    }
+    ordered
+}
+
+fn mac(state: &ServerState, key: &[u8]) -> Result<Hmac<Sha256>, String> {
+    let payload = serde_json::to_vec(state).map_err(|e| format!("serialize state: {}", e))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| format!("signing key: {}", e))?;
+    mac.update(&payload);
+    Ok(mac)
+}
+
+impl CoreEngine {
+    /// Snapshot of the dynamic configuration: admin-loaded models, canary routes, fallback chains,
+    /// traffic splits, hashed keys and quotas.
+    pub async fn export_state(&self) -> Result<StateSnapshot, String> {
+        let mut models: Vec<ModelSpec> = self.registry.specs().await;
+        models.sort_by(|a, b| (&a.kind, &a.model).cmp(&(&b.kind, &b.model)));
+        let canaries = self.canaries.list().await.into_iter()
+            .map(|d| CreateCanaryRequest { model: d.model, baseline: d.baseline, canary: d.canary, weight: d.weight, policy: d.policy })
+            .collect();
+        let state = ServerState {
+            models,
+            canaries,
+            fallbacks: self.fallbacks.list().into_iter().map(|c| CreateFallbackRequest { model: c.model, backends: c.backends }).collect(),
+            splits: self.splits.list().into_iter().map(|s| CreateSplitRequest { model: s.model, variants: s.variants }).collect(),
+            api_key_hashes: configured_key_hashes().into_iter().chain(self.keys().active_hashes()).collect(),
+            quotas: QuotaSpec { requests_per_minute: requests_per_minute() },
+        };
+        let signature = match signing_key() {
+            Some(key) => Some(mac(&state, &key)?.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()),
+            None => None,
+        };
+        Ok(StateSnapshot {
+            version: SNAPSHOT_VERSION,
+            exported_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+            state,
+            signature,
+        })
+    }
+
+    /// Applies a snapshot: loads its models, then recreates its canary routes, fallback chains and
+    /// traffic splits.
+    /// When a signing key is configured the snapshot must carry a matching signature.
+    pub async fn import_state(&self, snapshot: StateSnapshot) -> Result<ImportStateResponse, String> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!("unsupported snapshot version {}", snapshot.version));
+        }
+        if let Some(key) = signing_key() {
+            let provided = snapshot.signature.as_deref().ok_or("snapshot is not signed")?;
+            // Compared in constant time, so a forger learns nothing from how long a refusal takes
+            let mac = mac(&snapshot.state, &key)?;
+            if decode_hex(provided).is_none_or(|signature| mac.verify_slice(&signature).is_err()) {
+                return Err("snapshot signature mismatch".to_string());
+            }
+        }
+
+        let mut response = ImportStateResponse { models_loaded: Vec::new(), canaries_restored: Vec::new(), fallbacks_restored: Vec::new(), splits_restored: Vec::new(), skipped: Vec::new() };
+        for spec in fallbacks_first(snapshot.state.models) {
+            match self.load_model(&spec.kind, &spec.model, spec.path.as_deref(), &spec.options).await {
+                Ok(_) => response.models_loaded.push(spec.model),
+                Err(e) => response.skipped.push(format!("model {}: {}", spec.model, e)),
+            }
+        }
+        for canary in snapshot.state.canaries {
+            let name = canary.model.clone();
+            match self.create_canary(canary).await {
+                Ok(_) => response.canaries_restored.push(name),
+                Err(e) => response.skipped.push(format!("canary {}: {}", name, e)),
+            }
+        }
+        for chain in snapshot.state.fallbacks {
+            let name = chain.model.clone();
+            match self.create_fallback(chain).await {
+                Ok(_) => response.fallbacks_restored.push(name),
+                Err(e) => response.skipped.push(format!("fallback {}: {}", name, e)),
+            }
+        }
+        for split in snapshot.state.splits {
+            let name = split.model.clone();
+            match self.create_split(split).await {
+                Ok(_) => response.splits_restored.push(name),
+                Err(e) => response.skipped.push(format!("split {}: {}", name, e)),
+            }
+        }
+        // Keys (only digests are exported) and quotas are only exported for reference
+        if !snapshot.state.api_key_hashes.is_empty() {
+            response.skipped.push("api keys: managed via API_KEYS and /admin/keys".to_string());
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use metrics::counter;
+
+use crate::api::dto::{LoadModelRequest, ModelSwap};
+use crate::engine::{registry, CoreEngine, EngineError};
+
+// In-memory record of model swaps (no persistence; a swap interrupted by a restart leaves the
+// previous version registered, or nothing if it was not restored). Only the last
+// `MODEL_SWAP_HISTORY` finished swaps are kept.
+pub struct SwapStore {
+    swaps: RwLock<Vec<ModelSwap>>,
+    history: usize,
+}
+
+impl Default for SwapStore {
+    /// ENV: MODEL_SWAP_HISTORY (finished swaps kept, default 100)
+    fn default() -> Self {
+        let history = crate::config::var("MODEL_SWAP_HISTORY").ok().and_then(|v| v.parse().ok()).unwrap_or(100);
+        Self { swaps: RwLock::new(Vec::new()), history }
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+impl SwapStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn list(&self) -> Vec<ModelSwap> {
+        self.swaps.read().await.clone()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<ModelSwap> {
+        self.swaps.read().await.iter().find(|s| s.id == id).cloned()
+    }
+
+    // Records `swap` unless another swap of the same model is still loading
+    async fn start(&self, swap: ModelSwap) -> Result<(), String> {
+        let mut swaps = self.swaps.write().await;
+        if let Some(running) = swaps.iter().find(|s| s.model == swap.model && s.status == "loading") {
+            return Err(format!("{} is already being swapped by {}", swap.model, running.id));
+        }
+        swaps.push(swap);
+        Ok(())
+    }
+
+    // Applies `f` to a running swap as it finishes, then forgets the finished swaps past the
+    // history (the first ones, as swaps are kept oldest first)
+    async fn finish<F: FnOnce(&mut ModelSwap)>(&self, id: &str, f: F) {
+        let mut swaps = self.swaps.write().await;
+        if let Some(swap) = swaps.iter_mut().find(|s| s.id == id) {
+            f(swap);
+        }
+        let finished = swaps.iter().filter(|s| s.status != "loading").count();
+        let mut excess = finished.saturating_sub(self.history);
+        swaps.retain(|s| {
+            let drop = excess > 0 && s.status != "loading";
+            excess -= drop as usize;
+            !drop
+        });
+    }
+}
+
+impl CoreEngine {
+    /// Loads a new version of a registered model in the background and returns the initial
+    /// ("loading") record. The previous version keeps serving the name until the new one is
+    /// ready and takes its registry entry in one step, so no request finds the name missing;
+    /// if the load fails the previous version stays in place.
+    pub async fn start_swap(self: &Arc<Self>, req: LoadModelRequest) -> Result<ModelSwap, EngineError> {
+        // Checked now rather than after a long load
+        req.options.validate().map_err(EngineError::InvalidInput)?;
+        if !registry::MODEL_KINDS.contains(&req.kind.as_str()) {
+            return Err(EngineError::InvalidInput("unknown kind".to_string()));
+        }
+        let previous_backend = self.registry.swappable(&req.model).await?;
+        let swap = ModelSwap {
+            id: format!("swap-{}", uuid::Uuid::new_v4()),
+            object: "model.swap".to_string(),
+            model: req.model.clone(),
+            kind: req.kind.clone(),
+            path: req.path.clone(),
+            previous_backend: previous_backend.to_string(),
+            status: "loading".to_string(),
+            warmup_ms: None,
+            error: None,
+            created: now_secs(),
+            finished: None,
+        };
+        self.swaps.start(swap.clone()).await.map_err(EngineError::InvalidInput)?;
+
+        let engine = self.clone();
+        let id = swap.id.clone();
+        tokio::spawn(async move {
+            let result = engine.load_model(&req.kind, &req.model, req.path.as_deref(), &req.options).await;
+            let status = if result.is_ok() { "completed" } else { "failed" };
+            counter!("model_swaps_total", 1, "status" => status);
+            if let Err(e) = &result {
+                tracing::warn!("swap of {} failed, keeping the previous version: {}", req.model, e);
+            }
+            engine.swaps.finish(&id, |swap| {
+                swap.status = status.to_string();
+                match result {
+                    Ok(loaded) => swap.warmup_ms = loaded.warmup_ms,
+                    Err(e) => swap.error = Some(e),
+                }
+                swap.finished = Some(now_secs());
+            }).await;
+        });
+        Ok(swap)
+    }
+
+    pub fn swaps(&self) -> &SwapStore {
+        &self.swaps
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use crate::api::dto::ToolLoopStats;
+
+// Limits and accounting for multi-turn tool loops (generate -> run tools -> generate ...).
+// Server-side tool execution is not implemented yet; the loop is expected to call
+// `ToolLoopBudget::begin_iteration` before each generation pass, `record_tool` after each tool
+// call and `record_tokens` after each pass, stop on the first error, and report `stats()`.
+
+#[derive(Debug, Clone)]
+pub struct ToolLoopLimits {
+    pub max_iterations: u32,
+    pub max_tool_time: Duration,
+    pub max_total_tokens: u32,
+}
+
+impl Default for ToolLoopLimits {
+    fn default() -> Self {
+        Self { max_iterations: 8, max_tool_time: Duration::from_secs(30), max_total_tokens: 16_384 }
+    }
+}
+
+impl ToolLoopLimits {
+    /// ENV: TOOL_LOOP_MAX_ITERATIONS (default 8), TOOL_LOOP_MAX_TOOL_TIME_MS (default 30000),
+    /// TOOL_LOOP_MAX_TOKENS (default 16384)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let default = Self::default();
+        Self {
+            max_iterations: env("TOOL_LOOP_MAX_ITERATIONS").map_or(default.max_iterations, |v| v as u32),
+            max_tool_time: env("TOOL_LOOP_MAX_TOOL_TIME_MS").map_or(default.max_tool_time, Duration::from_millis),
+            max_total_tokens: env("TOOL_LOOP_MAX_TOKENS").map_or(default.max_total_tokens, |v| v as u32),
+        }
+    }
+
+    /// Per-request overrides may tighten the server limits but never relax them.
+    pub fn tightened(&self, max_iterations: Option<u32>, max_total_tokens: Option<u32>) -> Self {
+        Self {
+            max_iterations: max_iterations.map_or(self.max_iterations, |v| v.min(self.max_iterations)),
+            max_tool_time: self.max_tool_time,
+            max_total_tokens: max_total_tokens.map_or(self.max_total_tokens, |v| v.min(self.max_total_tokens)),
+        }
+    }
+}
+
+/// Running totals of one request's tool loop, checked against its limits.
+#[derive(Debug)]
+pub struct ToolLoopBudget {
+    limits: ToolLoopLimits,
+    stats: ToolLoopStats,
+    tool_time: Duration,
+}
+
+impl ToolLoopBudget {
+    pub fn new(limits: ToolLoopLimits) -> Self {
+        Self { limits, stats: ToolLoopStats::default(), tool_time: Duration::ZERO }
+    }
+
+    pub fn begin_iteration(&mut self) -> Result<(), String> {
+        if self.stats.iterations >= self.limits.max_iterations {
+            return self.stop(format!("tool loop exceeded {} iterations", self.limits.max_iterations));
+        }
+        self.stats.iterations += 1;
+        Ok(())
+    }
+
+    pub fn record_tool(&mut self, elapsed: Duration) -> Result<(), String> {
+        self.stats.tool_calls += 1;
+        self.tool_time += elapsed;
+        self.stats.tool_time_ms = self.tool_time.as_millis() as u64;
+        if self.tool_time > self.limits.max_tool_time {
+            return self.stop(format!("tool execution exceeded {}ms", self.limits.max_tool_time.as_millis()));
+        }
+        Ok(())
+    }
+
+    pub fn record_tokens(&mut self, prompt_tokens: u32, completion_tokens: u32) -> Result<(), String> {
+        self.stats.prompt_tokens += prompt_tokens;
+        self.stats.completion_tokens += completion_tokens;
+        let total = self.stats.prompt_tokens + self.stats.completion_tokens;
+        if total > self.limits.max_total_tokens {
+            return self.stop(format!("tool loop exceeded token budget of {}", self.limits.max_total_tokens));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> &ToolLoopStats {
+        &self.stats
+    }
+
+    fn stop(&mut self, reason: String) -> Result<(), String> {
+        self.stats.stopped_reason = Some(reason.clone());
+        Err(reason)
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use metrics::{counter, gauge};
+use tokio_util::sync::CancellationToken;
+
+// Built-in development runtimes hold no real memory and are never evicted
+pub const BUILTIN_MODELS: &[&str] = &["dummy-model", "dummy-embedding", "dummy-image"];
+
+/// Resident set size of the current process, read from `/proc/self/statm` (Linux only).
+/// VRAM is not observable without backend-specific bindings, so the watchdog only tracks RSS.
+pub fn current_rss_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(resident_pages * 4096)
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    pub soft_limit_bytes: u64,
+    pub hard_limit_bytes: u64,
+    pub interval: Duration,
+}
+
+impl WatchdogConfig {
+    /// ENV: MEMORY_SOFT_LIMIT_MB, MEMORY_HARD_LIMIT_MB (watchdog disabled unless at least one is set),
+    /// MEMORY_WATCHDOG_INTERVAL_MS (default 1000)
+    pub fn from_env() -> Option<Self> {
+        let mb = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok()).map(|v| v * 1024 * 1024);
+        let soft = mb("MEMORY_SOFT_LIMIT_MB");
+        let hard = mb("MEMORY_HARD_LIMIT_MB");
+        if soft.is_none() && hard.is_none() {
+            return None;
+        }
+        let interval_ms = crate::config::var("MEMORY_WATCHDOG_INTERVAL_MS").ok().and_then(|v| v.parse().ok()).unwrap_or(1000);
+        Some(Self {
+            soft_limit_bytes: soft.or(hard).unwrap(),
+            hard_limit_bytes: hard.unwrap_or(u64::MAX),
+            interval: Duration::from_millis(interval_ms),
+        })
+    }
+}
+
+/// Cancellation handles of in-flight generations, newest last.
+#[derive(Default)]
+pub struct InFlight {
+    next_id: AtomicU64,
+    entries: Mutex<Vec<(u64, CancellationToken)>>,
+}
+
+pub struct InFlightGuard {
+    registry: Arc<InFlight>,
+    id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.registry.entries.lock().unwrap().retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl InFlight {
+    pub fn register(self: &Arc<Self>, cancel: CancellationToken) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.entries.lock().unwrap().push((id, cancel));
+        InFlightGuard { registry: self.clone(), id }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cancels the most recently started generations (least work lost) and returns how many were cancelled.
+    pub fn cancel_newest(&self, count: usize) -> usize {
+        let entries = self.entries.lock().unwrap();
+        let mut cancelled = 0;
+        for (_, token) in entries.iter().rev().filter(|(_, t)| !t.is_cancelled()).take(count) {
+            token.cancel();
+            cancelled += 1;
+        }
+        cancelled
+    }
+}
+
+/// Admission flag flipped by the watchdog while memory is above the soft limit.
+#[derive(Default)]
+pub struct Admission {
+    shedding: AtomicBool,
+}
+
+impl Admission {
+    pub fn is_shedding(&self) -> bool {
+        self.shedding.load(Ordering::Relaxed)
+    }
+
+    pub fn set_shedding(&self, shedding: bool) {
+        self.shedding.store(shedding, Ordering::Relaxed);
+    }
+}
+
+/// Escalation step applied on consecutive ticks above the hard limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShedAction {
+    EvictCaches,
+    CancelGenerations,
+    UnloadModel,
+}
+
+impl ShedAction {
+    pub fn for_tick(consecutive_hard_ticks: u32) -> Self {
+        match consecutive_hard_ticks {
+            0 | 1 => ShedAction::EvictCaches,
+            2 => ShedAction::CancelGenerations,
+            _ => ShedAction::UnloadModel,
+        }
+    }
+}
+
+pub fn record_rss(rss: u64) {
+    gauge!("process_rss_bytes", rss as f64);
+}
+
+pub fn record_action(action: ShedAction) {
+    let name = match action {
+        ShedAction::EvictCaches => "evict_caches",
+        ShedAction::CancelGenerations => "cancel_generations",
+        ShedAction::UnloadModel => "unload_model",
+    };
+    counter!("memory_watchdog_actions_total", 1, "action" => name);
+}
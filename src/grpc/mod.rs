@@ -0,0 +1,315 @@
+//! gRPC API (feature `grpc`): chat, embeddings and model management over tonic, sharing the
+//! `CoreEngine` with the HTTP server. Messages are translated to the HTTP DTOs so both surfaces
+//! go through the same scheduling, safety and accounting.
+
+// Every tonic handler returns `Result<_, Status>`; boxing it in helpers would buy nothing
+#![allow(clippy::result_large_err)]
+
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt};
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, Response, Status};
+
+use crate::api::{
+    auth::{authorize_request, client_id, priority, AuthError, Scope, CLIENT_PRINCIPAL_HEADER},
+    dto::{
+        ChatCompletionChunk, ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent, ChatStreamError, ContentPart,
+        EmbeddingInput, EmbeddingVector, EmbeddingsRequest, EncodingFormat, ImageUrl, ModelOptions, Priority, StopSequences, StreamOptions,
+    },
+    error::{AppError, ErrorResponse},
+    validation,
+};
+use crate::engine::{CoreEngine, EngineError};
+
+pub mod pb {
+    tonic::include_proto!("llmserving.v1");
+}
+
+use pb::{
+    inference_server::{Inference, InferenceServer},
+    models_server::{Models, ModelsServer},
+};
+
+/// ENV: GRPC_ADDR (default 0.0.0.0:50051)
+pub fn addr_from_env() -> Result<SocketAddr, String> {
+    let addr = crate::config::var("GRPC_ADDR").unwrap_or_else(|_| "0.0.0.0:50051".to_string());
+    addr.parse().map_err(|e| format!("invalid GRPC_ADDR {:?}: {}", addr, e))
+}
+
+/// Serves the gRPC API on `listener` until the process exits.
+pub async fn serve(engine: Arc<CoreEngine>, listener: TcpListener) -> Result<(), String> {
+    serve_with_shutdown(engine, listener, std::future::pending()).await
+}
+
+/// Like [`serve`], but stops taking calls once `signal` resolves and returns when the calls in
+/// progress have finished.
+pub async fn serve_with_shutdown(engine: Arc<CoreEngine>, listener: TcpListener, signal: impl Future<Output = ()>) -> Result<(), String> {
+    run(engine, listener, signal, true).await
+}
+
+/// Like [`serve_with_shutdown`], but without the `Models` service, for when management is kept
+/// to the admin listener.
+pub async fn serve_inference_with_shutdown(engine: Arc<CoreEngine>, listener: TcpListener, signal: impl Future<Output = ()>) -> Result<(), String> {
+    run(engine, listener, signal, false).await
+}
+
+async fn run(engine: Arc<CoreEngine>, listener: TcpListener, signal: impl Future<Output = ()>, models: bool) -> Result<(), String> {
+    tonic::transport::Server::builder()
+        .add_service(InferenceServer::new(InferenceService { engine: engine.clone() }))
+        .add_optional_service(models.then(|| ModelsServer::new(ModelsService { engine })))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), signal)
+        .await
+        .map_err(|e| format!("gRPC server error: {}", e))
+}
+
+// Same keys, scopes and rate limits as HTTP; returns the caller's client id and the priority
+// its request runs at
+async fn authorize<T>(engine: &CoreEngine, request: &Request<T>, scope: Scope, requested: Option<&str>) -> Result<(String, Priority), Status> {
+    let mut headers = request.metadata().clone().into_headers();
+    // Served without TLS, so no client certificate vouches for a principal
+    headers.remove(CLIENT_PRINCIPAL_HEADER);
+    authorize_request(engine.keys(), &headers, scope).await.map_err(|e| match e {
+        AuthError::Unauthorized => Status::unauthenticated(e.to_string()),
+        AuthError::Forbidden(_) => Status::permission_denied(e.to_string()),
+        AuthError::RateLimited { .. } => Status::resource_exhausted(e.to_string()),
+    })?;
+    let requested = requested.map(str::parse).transpose().map_err(Status::invalid_argument)?;
+    Ok((client_id(&headers), priority(&headers, requested)))
+}
+
+// Engine errors with the codes matching their HTTP statuses
+fn engine_status(e: EngineError) -> Status {
+    match e {
+        EngineError::ModelNotFound(e) | EngineError::NotFound(e) => Status::not_found(e),
+        EngineError::Overloaded(e) => Status::unavailable(e),
+        EngineError::QuotaExceeded(e) => Status::resource_exhausted(e),
+        EngineError::Timeout(e) => Status::deadline_exceeded(e),
+        EngineError::InvalidInput(e) => Status::invalid_argument(e),
+        EngineError::Backend(e) => Status::internal(e),
+        EngineError::Streaming => Status::internal(e.to_string()),
+    }
+}
+
+// Requests the HTTP validation layer would turn away, as INVALID_ARGUMENT naming the field at
+// fault
+fn invalid_request(e: AppError) -> Status {
+    let (_, ErrorResponse { error }) = e.into_parts();
+    match error.param {
+        Some(param) => Status::invalid_argument(format!("{}: {}", param, error.message)),
+        None => Status::invalid_argument(error.message),
+    }
+}
+
+fn usage(usage: &crate::api::dto::Usage) -> pb::Usage {
+    pb::Usage { prompt_tokens: usage.prompt_tokens, completion_tokens: usage.completion_tokens, total_tokens: usage.total_tokens }
+}
+
+fn chat_request(request: pb::ChatRequest, (client, priority): (String, Priority), stream: bool) -> ChatCompletionRequest {
+    let messages = request
+        .messages
+        .into_iter()
+        .map(|m| {
+            let content = if m.image_urls.is_empty() {
+                ChatMessageContent::Text(m.content)
+            } else {
+                let mut parts = vec![ContentPart::Text { text: m.content }];
+                parts.extend(m.image_urls.into_iter().map(|url| ContentPart::ImageUrl { image_url: ImageUrl { url, detail: None } }));
+                ChatMessageContent::Parts(parts)
+            };
+            ChatCompletionMessage { role: m.role, content }
+        })
+        .collect();
+    ChatCompletionRequest {
+        model: request.model,
+        messages,
+        stream: Some(stream),
+        max_tokens: request.max_tokens,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        n: None,
+        top_k: request.top_k,
+        min_p: request.min_p,
+        typical_p: request.typical_p,
+        repetition_penalty: request.repetition_penalty,
+        // Out-of-range versions are rejected by the engine
+        mirostat: request.mirostat.map(|v| v.min(u8::MAX as u32) as u8),
+        mirostat_tau: request.mirostat_tau,
+        mirostat_eta: request.mirostat_eta,
+        timeout_ms: request.timeout_ms,
+        stream_options: stream.then(|| StreamOptions { include_usage: true, ..Default::default() }),
+        user: (!request.user.is_empty()).then_some(request.user),
+        grammar: request.grammar,
+        stop: (!request.stop.is_empty()).then_some(StopSequences::Many(request.stop)),
+        client_id: Some(client),
+        endpoint: Some("grpc"),
+        priority: Some(priority),
+        cache: request.cache,
+    }
+}
+
+// The engine's chunk as a gRPC message, or the status of a failed generation; the role chunk
+// and `[DONE]` carry nothing to send
+fn chat_chunk(chunk: &str) -> Option<Result<pb::ChatChunk, Status>> {
+    if let Some(e) = ChatStreamError::parse(chunk) {
+        return Some(Err(engine_status(e)));
+    }
+    let chunk: ChatCompletionChunk = serde_json::from_str(chunk).ok()?;
+    let choice = chunk.choices.first();
+    let delta = choice.and_then(|c| c.delta.content.clone()).unwrap_or_default();
+    let finish_reason = choice.and_then(|c| c.finish_reason.clone()).unwrap_or_default();
+    if delta.is_empty() && finish_reason.is_empty() && chunk.usage.is_none() {
+        return None;
+    }
+    Some(Ok(pb::ChatChunk { id: chunk.id, model: chunk.model, delta, finish_reason, usage: chunk.usage.as_ref().map(usage) }))
+}
+
+struct InferenceService {
+    engine: Arc<CoreEngine>,
+}
+
+type ChatChunkStream = Pin<Box<dyn Stream<Item = Result<pb::ChatChunk, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Inference for InferenceService {
+    async fn chat(&self, request: Request<pb::ChatRequest>) -> Result<Response<pb::ChatResponse>, Status> {
+        let caller = authorize(&self.engine, &request, Scope::Inference, request.get_ref().priority.as_deref()).await?;
+        let request = chat_request(request.into_inner(), caller, false);
+        validation::chat(&request).map_err(invalid_request)?;
+        let response = self.engine.process_chat_request(request, None, CancellationToken::new()).await.map_err(engine_status)?;
+        let choice = response.choices.into_iter().next();
+        Ok(Response::new(pb::ChatResponse {
+            id: response.id,
+            model: response.model,
+            created: response.created,
+            content: choice.as_ref().map(|c| c.message.content.clone()).unwrap_or_default(),
+            finish_reason: choice.map(|c| c.finish_reason).unwrap_or_default(),
+            usage: Some(usage(&response.usage)),
+        }))
+    }
+
+    type ChatStreamStream = ChatChunkStream;
+
+    async fn chat_stream(&self, request: Request<pb::ChatRequest>) -> Result<Response<Self::ChatStreamStream>, Status> {
+        let caller = authorize(&self.engine, &request, Scope::Inference, request.get_ref().priority.as_deref()).await?;
+        let request = chat_request(request.into_inner(), caller, true);
+        validation::chat(&request).map_err(invalid_request)?;
+        let (tx, rx) = mpsc::channel::<String>(100);
+        let cancel = CancellationToken::new();
+        if let Err(e) = self.engine.process_chat_request(request, Some(tx), cancel.clone()).await
+            && e != EngineError::Streaming
+        {
+            return Err(engine_status(e));
+        }
+        // Dropping the response stream (client cancelled or disconnected) stops generation
+        let guard = cancel.drop_guard();
+        let stream = ReceiverStream::new(rx).filter_map(move |chunk| {
+            let _ = &guard;
+            std::future::ready(chat_chunk(&chunk))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn embed(&self, request: Request<pb::EmbedRequest>) -> Result<Response<pb::EmbedResponse>, Status> {
+        let (client, priority) = authorize(&self.engine, &request, Scope::Inference, request.get_ref().priority.as_deref()).await?;
+        let request = request.into_inner();
+        let request = EmbeddingsRequest {
+            model: request.model,
+            input: EmbeddingInput::TextBatch(request.inputs),
+            encoding_format: EncodingFormat::Float,
+            dimensions: request.dimensions.map(|d| d as usize),
+            client_id: Some(client),
+            priority: Some(priority),
+        };
+        validation::embeddings(&request).map_err(invalid_request)?;
+        let response = self.engine.process_embedding_request(request).await.map_err(engine_status)?;
+        let data = response
+            .data
+            .into_iter()
+            .map(|d| match d.embedding {
+                EmbeddingVector::Float(values) => pb::Embedding { values },
+                EmbeddingVector::Base64(_) => unreachable!("requested float encoding"),
+            })
+            .collect();
+        let total = response.usage.total_tokens;
+        Ok(Response::new(pb::EmbedResponse {
+            model: response.model,
+            data,
+            usage: Some(pb::Usage { prompt_tokens: response.usage.prompt_tokens, completion_tokens: 0, total_tokens: total }),
+        }))
+    }
+}
+
+// Model management, like the `/admin/models` routes, needs the admin scope
+struct ModelsService {
+    engine: Arc<CoreEngine>,
+}
+
+#[tonic::async_trait]
+impl Models for ModelsService {
+    async fn list(&self, request: Request<pb::ListModelsRequest>) -> Result<Response<pb::ListModelsResponse>, Status> {
+        authorize(&self.engine, &request, Scope::Admin, None).await?;
+        let list = self.engine.list_models().await;
+        let kinds = [
+            ("llm", list.llm),
+            ("embedding", list.embedding),
+            ("multimodal", list.multimodal),
+            ("image", list.image),
+            ("rerank", list.rerank),
+            ("audio", list.audio),
+            ("tts", list.tts),
+        ];
+        let mut models: Vec<pb::Model> = kinds
+            .into_iter()
+            .flat_map(|(kind, names)| names.into_iter().map(move |name| pb::Model { name, kind: kind.to_string() }))
+            .collect();
+        models.sort_by(|a, b| (&a.kind, &a.name).cmp(&(&b.kind, &b.name)));
+        Ok(Response::new(pb::ListModelsResponse { models }))
+    }
+
+    async fn load(&self, request: Request<pb::LoadModelRequest>) -> Result<Response<pb::LoadModelResponse>, Status> {
+        authorize(&self.engine, &request, Scope::Admin, None).await?;
+        let request = request.into_inner();
+        let split_mode = request.split_mode.as_deref().map(str::parse).transpose().map_err(Status::invalid_argument)?;
+        let upstream_api = request.upstream_api.as_deref().map(str::parse).transpose().map_err(Status::invalid_argument)?;
+        let options = ModelOptions {
+            chat_template: request.chat_template,
+            context_shift: request.context_shift,
+            n_gpu_layers: request.n_gpu_layers,
+            main_gpu: request.main_gpu,
+            split_mode,
+            n_ctx: request.n_ctx,
+            n_batch: request.n_batch,
+            rope_freq_base: request.rope_freq_base,
+            rope_freq_scale: request.rope_freq_scale,
+            embeddings: request.embeddings,
+            embedding_dim: request.embedding_dim,
+            upstream_model: request.upstream_model,
+            upstream_api,
+            upstream_api_key_env: request.upstream_api_key_env,
+            upstream_timeout_ms: request.upstream_timeout_ms,
+            hf_repo: request.hf_repo,
+            filename: request.filename,
+            hf_revision: request.hf_revision,
+            idle_ttl_secs: request.idle_ttl_secs,
+            warmup: request.warmup,
+            response_cache: request.response_cache,
+            fallback_model: request.fallback_model,
+        };
+        let loaded = self
+            .engine
+            .load_model(&request.kind, &request.model, request.path.as_deref(), &options)
+            .await
+            .map_err(Status::invalid_argument)?;
+        Ok(Response::new(pb::LoadModelResponse { warmup_ms: loaded.warmup_ms }))
+    }
+
+    async fn unload(&self, request: Request<pb::UnloadModelRequest>) -> Result<Response<pb::UnloadModelResponse>, Status> {
+        authorize(&self.engine, &request, Scope::Admin, None).await?;
+        let request = request.into_inner();
+        self.engine.unload_model(&request.kind, &request.model).await.map_err(engine_status)?;
+        Ok(Response::new(pb::UnloadModelResponse {}))
+    }
+}
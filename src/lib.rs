@@ -1,3 +1,11 @@
 pub mod api;
+pub mod cache;
+pub mod cli;
+pub mod config;
 pub mod engine;
 pub mod runtime;
+pub mod outbound;
+pub mod storage;
+pub mod tls;
+#[cfg(feature = "grpc")]
+pub mod grpc;
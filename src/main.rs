@@ -1,53 +1,210 @@
-mod api;
-mod engine;
-mod runtime;
-
-use axum::{routing::post, Router};
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use axum::Router;
+use clap::Parser;
+use std::{sync::Arc, time::Duration};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_rustls::rustls::{server::danger::ClientCertVerifier, ServerConfig};
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::engine::CoreEngine;
+use llm_serving::{api, cli::Cli, config::Config, engine::{shutdown::drain_timeout, CoreEngine}, tls::CertStore};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+    let models = cli.model_requests().unwrap_or_else(|e| panic!("{}", e));
+    // Settings from the configuration file and the command line apply before anything reads them
+    let config = cli.config_path().map(|path| {
+        let config = Config::load(&path).unwrap_or_else(|e| panic!("invalid configuration: {}", e));
+        config.install();
+        (path, config)
+    });
+    llm_serving::config::set_overrides(cli.env_vars(&llm_serving::config::listen_addr()));
+    let filter = match &cli.log_level {
+        Some(level) => tracing_subscriber::EnvFilter::try_new(level).unwrap_or_else(|e| panic!("invalid --log-level: {}", e)),
+        None => tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "llm_serving=debug".into()),
+    };
     tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "llm_serving=debug".into()),
-        )
+        .with(filter)
         .with(tracing_subscriber::fmt::layer())
         .init();
 
     // Metrics exporter
     let prom_handle: PrometheusHandle = PrometheusBuilder::new().install_recorder().unwrap();
 
+    llm_serving::runtime::accel::log_startup();
+    // Resolve the storage backend now so a bad configuration fails at startup, not on first use
+    tracing::info!("storage backend: {}", llm_serving::storage::global().backend());
+    if let Err(e) = llm_serving::cache::shared_backend() {
+        fail(format!("invalid response cache configuration: {}", e));
+    }
     let engine = Arc::new(CoreEngine::new());
+    if let Some((path, config)) = config {
+        if let Err(e) = engine.apply_config(path, config).await {
+            panic!("{}", e);
+        }
+        engine.watch_config().await;
+    }
+    for model in models {
+        if let Err(e) = engine.load_model(&model.kind, &model.model, model.path.as_deref(), &model.options).await {
+            panic!("could not load model {}: {}", model.model, e);
+        }
+        tracing::info!("loaded llm model {}", model.model);
+    }
+    match engine.keys().load().await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("loaded {} API keys", n),
+        Err(e) => fail(format!("could not load API keys: {}", e)),
+    }
+    match engine.resume_batches().await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("resumed {} unfinished batches", n),
+        Err(e) => tracing::error!("could not resume batches: {}", e),
+    }
+
+    // Cancelled on SIGTERM or Ctrl-C: both servers stop accepting and the engine drains
+    let stopping = CancellationToken::new();
 
-    let app = Router::new()
-        .route("/v1/chat/completions", post(api::routes::chat_completions))
-        .route("/v1/embeddings", post(api::routes::embeddings))
-        .route("/v1/images/generations", post(api::routes::images_generations))
-        .route("/admin/models", axum::routing::get(api::routes::admin_models_list))
-        .route("/admin/models/load", post(api::routes::admin_models_load))
-        .route("/admin/models/unload", post(api::routes::admin_models_unload))
-        .route("/admin/metrics", axum::routing::get({
-            let handle = prom_handle.clone();
-            move || {
-                let body = handle.render();
-                async move {
-                    axum::response::Response::builder()
-                        .header("content-type", "text/plain; version=0.0.4")
-                        .body(axum::body::Body::from(body))
-                        .unwrap()
-                }
+    // The gRPC API runs on its own port against the same engine
+    #[cfg(feature = "grpc")]
+    {
+        let addr = llm_serving::grpc::addr_from_env().unwrap_or_else(|e| fail(e));
+        let listener = bind(&addr.to_string(), "gRPC").await;
+        tracing::info!("gRPC listening on {}", listener.local_addr().unwrap_or(addr));
+        let engine = engine.clone();
+        let stopping = stopping.clone().cancelled_owned();
+        // Model management stays off the public port once the admin API has a listener of its own
+        let separate_admin = llm_serving::config::admin_listen_addr().is_some();
+        tokio::spawn(async move {
+            let served = if separate_admin {
+                llm_serving::grpc::serve_inference_with_shutdown(engine, listener, stopping).await
+            } else {
+                llm_serving::grpc::serve_with_shutdown(engine, listener, stopping).await
+            };
+            if let Err(e) = served {
+                tracing::error!("{}", e);
             }
-        }))
-        .route("/health", axum::routing::get(|| async { axum::Json(serde_json::json!({"status":"ok"})) }))
-        .with_state(engine);
+        });
+    }
+
+    // With a certificate configured both listeners serve HTTPS; it and the client CAs are read again when their files change
+    let tls = llm_serving::tls::paths_from_env().unwrap_or_else(|e| fail(format!("invalid TLS configuration: {}", e))).map(|(cert, key)| {
+        let store = CertStore::load(&cert, &key).unwrap_or_else(|e| fail(format!("invalid TLS certificate: {}", e)));
+        store.watch();
+        let clients = llm_serving::tls::client_verifier_from_env().unwrap_or_else(|e| fail(format!("invalid TLS client CA: {}", e)));
+        let clients = clients.map(|clients| {
+            clients.watch();
+            clients as Arc<dyn ClientCertVerifier>
+        });
+        store.server_config(clients)
+    });
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    // The management routes either share the public listener or get their own, which can be
+    // bound to a private interface or firewalled off from inference traffic
+    let admin_addr = llm_serving::config::admin_listen_addr();
+    let (mut app, admin) = api::app::routers(engine.clone(), prom_handle.clone(), admin_addr.is_some());
+    let mut admin_server = match (admin_addr, admin) {
+        (Some(addr), Some(admin)) => {
+            let listener = bind(&addr, "admin").await;
+            tracing::info!("admin API listening on {}://{}", scheme, listener.local_addr().map_or(addr, |local| local.to_string()));
+            Some(spawn_server(listener, admin, tls.clone(), &stopping))
+        }
+        _ => None,
+    };
+    // Outermost, so preflights are answered before authentication and endpoint limits
+    if let Some(cors) = api::cors::layer_from_env().unwrap_or_else(|e| fail(format!("invalid CORS configuration: {}", e))) {
+        app = app.layer(cors);
+    }
+
+    let addr = llm_serving::config::listen_addr();
+    let listener = bind(&addr, "HTTP").await;
+    tracing::debug!("listening on {}://{}", scheme, listener.local_addr().map_or(addr, |local| local.to_string()));
+    let mut server = spawn_server(listener, app, tls, &stopping);
+    let admin_stopped = async {
+        match &mut admin_server {
+            Some(server) => server.await,
+            None => std::future::pending().await,
+        }
+    };
+    // A listener that stops on its own still drains the engine, then exits with a failure status
+    let failure = tokio::select! {
+        _ = shutdown_signal() => None,
+        result = &mut server => Some(format!("HTTP server stopped: {}", stopped(result))),
+        result = admin_stopped => Some(format!("admin HTTP server stopped: {}", stopped(result))),
+    };
+    if let Some(failure) = &failure {
+        tracing::error!("{}", failure);
+    }
+
+    tracing::info!("shutting down: draining requests");
+    stopping.cancel();
+    let drained = engine.shutdown(drain_timeout()).await;
+    if let Err(e) = engine.metering().flush().await {
+        tracing::error!("could not write usage: {}", e);
+    }
+    // Responses still streaming end with their generations; clients that stopped reading are not waited for
+    let open = std::iter::once(server).chain(admin_server).filter(|server| !server.is_finished());
+    if tokio::time::timeout(Duration::from_secs(5), futures::future::join_all(open)).await.is_err() {
+        tracing::warn!("shutdown: closing connections still open");
+    }
+    // ENV: METRICS_SNAPSHOT_PATH, where the final metrics are written in the Prometheus text
+    // format, e.g. for node_exporter's textfile collector
+    if let Ok(path) = llm_serving::config::var("METRICS_SNAPSHOT_PATH")
+        && let Err(e) = std::fs::write(&path, prom_handle.render())
+    {
+        tracing::error!("could not write metrics to {}: {}", path, e);
+    }
+    tracing::info!("shutdown complete (drained: {})", drained);
+    if failure.is_some() {
+        std::process::exit(1);
+    }
+}
+
+// Why a server task ended
+fn stopped(result: Result<std::io::Result<()>, tokio::task::JoinError>) -> String {
+    match result {
+        Ok(Ok(())) => "no error".to_string(),
+        Ok(Err(e)) => e.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Serves `app` on `listener`, over HTTPS when `tls` is given, until `stopping` is cancelled.
+fn spawn_server(listener: TcpListener, app: Router, tls: Option<Arc<ServerConfig>>, stopping: &CancellationToken) -> JoinHandle<std::io::Result<()>> {
+    let signal = stopping.clone().cancelled_owned();
+    match tls {
+        Some(tls) => tokio::spawn(llm_serving::tls::serve(listener, app, tls, signal)),
+        None => tokio::spawn(axum::serve(listener, app).with_graceful_shutdown(signal).into_future()),
+    }
+}
+
+/// Listens on `addr` for the `what` API, exiting with the reason when the address is taken or
+/// not ours.
+async fn bind(addr: &str, what: &str) -> TcpListener {
+    TcpListener::bind(addr).await.unwrap_or_else(|e| fail(format!("could not listen for the {} API on {}: {}", what, addr, e)))
+}
+
+/// Logs why the server cannot go on and exits with a failure status.
+fn fail(message: impl std::fmt::Display) -> ! {
+    tracing::error!("{}", message);
+    std::process::exit(1)
+}
 
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+/// Resolves on Ctrl-C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let interrupt = async { tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl-C") };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
\ No newline at end of file
@@ -0,0 +1,347 @@
+//! Factory for every outbound HTTP client (remote runtimes, webhooks, image fetching).
+//!
+//! All clients share one configuration, one DNS cache and one per-host connection limit so
+//! operators can tune and audit outbound behaviour in a single place.
+
+use futures::StreamExt;
+use metrics::{counter, histogram};
+use once_cell::sync::Lazy;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone)]
+pub struct OutboundConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub request_timeout: Option<Duration>,
+    pub max_connections_per_host: usize,
+    pub dns_cache_ttl: Duration,
+}
+
+impl OutboundConfig {
+    /// ENV: OUTBOUND_CONNECT_TIMEOUT_MS (default 5000), OUTBOUND_READ_TIMEOUT_MS (default 60000),
+    /// OUTBOUND_REQUEST_TIMEOUT_MS (default unset = no overall cap), OUTBOUND_MAX_CONNECTIONS_PER_HOST
+    /// (default 32), OUTBOUND_DNS_CACHE_SECS (default 60; 0 disables caching)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            connect_timeout: Duration::from_millis(env("OUTBOUND_CONNECT_TIMEOUT_MS").unwrap_or(5_000)),
+            read_timeout: Duration::from_millis(env("OUTBOUND_READ_TIMEOUT_MS").unwrap_or(60_000)),
+            request_timeout: env("OUTBOUND_REQUEST_TIMEOUT_MS").map(Duration::from_millis),
+            max_connections_per_host: env("OUTBOUND_MAX_CONNECTIONS_PER_HOST").unwrap_or(32).max(1) as usize,
+            dns_cache_ttl: Duration::from_secs(env("OUTBOUND_DNS_CACHE_SECS").unwrap_or(60)),
+        }
+    }
+}
+
+static CONFIG: Lazy<OutboundConfig> = Lazy::new(OutboundConfig::from_env);
+static RESOLVER: Lazy<Arc<CachingResolver>> = Lazy::new(|| Arc::new(CachingResolver::new(CONFIG.dns_cache_ttl)));
+static HOST_LIMITS: Lazy<HostLimits> = Lazy::new(|| HostLimits::new(CONFIG.max_connections_per_host));
+// Keyed by purpose and whether the client is restricted to public addresses
+static CLIENTS: Lazy<Mutex<HashMap<(&'static str, bool), OutboundClient>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn config() -> &'static OutboundConfig {
+    &CONFIG
+}
+
+/// Shared client for `purpose` (e.g. "remote_runtime", "webhook", "image_fetch"); the purpose
+/// labels outbound metrics.
+pub fn client(purpose: &'static str) -> OutboundClient {
+    CLIENTS
+        .lock()
+        .unwrap()
+        .entry((purpose, false))
+        .or_insert_with(|| OutboundClient::build(purpose, &CONFIG, false))
+        .clone()
+}
+
+/// Like [`client`], for URLs supplied by API callers: connections (including redirects) only
+/// go to public addresses, so a request cannot reach internal services (SSRF). Callers must
+/// still reject URLs whose host is a non-public IP literal, which never goes through DNS; see
+/// [`check_public_url`].
+pub fn public_client(purpose: &'static str) -> OutboundClient {
+    CLIENTS
+        .lock()
+        .unwrap()
+        .entry((purpose, true))
+        .or_insert_with(|| OutboundClient::build(purpose, &CONFIG, true))
+        .clone()
+}
+
+/// False for loopback, private, link-local, shared, documentation, multicast and other
+/// special-purpose ranges.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)) // shared address space (CGNAT)
+                || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+                || (a == 198 && (b == 18 || b == 19)) // benchmarking
+                || a >= 240) // reserved
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let segments = v6.segments();
+            // NAT64 embeds an IPv4 address in the low 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [_, _, _, _, _, _, hi, lo] = segments;
+                return is_public_ip(IpAddr::V4(std::net::Ipv4Addr::from(((hi as u32) << 16) | lo as u32)));
+            }
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (segments[0] & 0xfe00) == 0xfc00 // unique local
+                || (segments[0] & 0xffc0) == 0xfe80 // link-local
+                || (segments[0] & 0xffc0) == 0xfec0 // site-local (deprecated)
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8)) // documentation
+        }
+    }
+}
+
+/// Rejects URLs that are not http(s) or whose host is a non-public IP literal.
+pub fn check_public_url(url: &reqwest::Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("URL scheme {} is not allowed", url.scheme()));
+    }
+    let host = url.host_str().ok_or("URL has no host")?;
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()
+        && !is_public_ip(ip)
+    {
+        return Err(format!("{} is not a public address", host));
+    }
+    Ok(())
+}
+
+type DnsCache = HashMap<String, (Instant, Vec<SocketAddr>)>;
+
+// Hostnames come from client-supplied URLs, so the cache is bounded
+const DNS_CACHE_MAX_ENTRIES: usize = 1024;
+
+/// Resolves through tokio and caches answers for the configured TTL.
+#[derive(Clone)]
+pub struct CachingResolver {
+    ttl: Duration,
+    max_entries: usize,
+    cache: Arc<Mutex<DnsCache>>,
+}
+
+impl CachingResolver {
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_capacity(ttl, DNS_CACHE_MAX_ENTRIES)
+    }
+
+    /// A resolver caching at most `max_entries` hosts.
+    pub fn with_capacity(ttl: Duration, max_entries: usize) -> Self {
+        Self { ttl, max_entries, cache: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub async fn lookup(&self, host: &str) -> std::io::Result<Vec<SocketAddr>> {
+        if let Some((at, addrs)) = self.cache.lock().unwrap().get(host)
+            && at.elapsed() < self.ttl
+        {
+            return Ok(addrs.clone());
+        }
+        // Port is a placeholder; the client substitutes the URL's port
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+        if !self.ttl.is_zero() && self.max_entries > 0 {
+            let mut cache = self.cache.lock().unwrap();
+            cache.retain(|_, (at, _)| at.elapsed() < self.ttl);
+            // Still full of live answers: make room by dropping the oldest
+            if cache.len() >= self.max_entries
+                && !cache.contains_key(host)
+                && let Some(oldest) = cache.iter().min_by_key(|(_, (at, _))| *at).map(|(host, _)| host.clone())
+            {
+                cache.remove(&oldest);
+            }
+            cache.insert(host.to_string(), (Instant::now(), addrs.clone()));
+        }
+        Ok(addrs)
+    }
+
+    /// Hosts whose answers are cached, expired ones included until the next insert.
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = resolver.lookup(&host).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Resolves through the shared cache but only ever returns public addresses.
+struct PublicResolver(Arc<CachingResolver>);
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.0.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = resolver.lookup(&host).await?.into_iter().filter(|a| is_public_ip(a.ip())).collect();
+            if addrs.is_empty() {
+                let message = format!("{} does not resolve to a public address", host);
+                return Err(Box::new(std::io::Error::new(std::io::ErrorKind::PermissionDenied, message)) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Caps concurrent requests per host. Hosts come from client-supplied URLs, so only those with a
+/// request running or waiting keep an entry.
+pub struct HostLimits {
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    pub fn new(max_per_host: usize) -> Self {
+        Self { max_per_host, hosts: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let semaphore = {
+            let mut hosts = self.hosts.lock().unwrap();
+            // Permits and waiters hold the semaphore too; drop those nobody is using
+            hosts.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+            hosts.entry(host.to_string()).or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host))).clone()
+        };
+        semaphore.acquire_owned().await.expect("host semaphore closed")
+    }
+
+    /// Hosts currently tracked.
+    pub fn len(&self) -> usize {
+        self.hosts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Clone)]
+pub struct OutboundClient {
+    purpose: &'static str,
+    client: reqwest::Client,
+}
+
+impl OutboundClient {
+    fn build(purpose: &'static str, config: &OutboundConfig, public_only: bool) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(concat!("llm-serving/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout)
+            .pool_max_idle_per_host(config.max_connections_per_host);
+        builder = if public_only {
+            // Redirect targets are resolved through the same filter; IP literals are checked here.
+            // No proxy: it would resolve names itself, past the filter
+            builder.no_proxy().dns_resolver(Arc::new(PublicResolver(RESOLVER.clone()))).redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 5 {
+                    attempt.error("too many redirects")
+                } else if let Err(e) = check_public_url(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            }))
+        } else {
+            builder.dns_resolver(RESOLVER.clone())
+        };
+        if let Some(timeout) = config.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        let client = builder.build().expect("outbound HTTP client configuration is valid");
+        Self { purpose, client }
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    /// Sends `request` once a connection slot for its host is free. The slot is held until the
+    /// returned response is dropped.
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> Result<OutboundResponse, String> {
+        let request = request.build().map_err(|e| format!("invalid outbound request: {}", e))?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let permit = HOST_LIMITS.acquire(&host).await;
+        let start = Instant::now();
+        let result = self.client.execute(request).await;
+        histogram!("outbound_request_latency_ms", start.elapsed().as_millis() as f64, "purpose" => self.purpose);
+        match result {
+            Ok(response) => {
+                counter!("outbound_requests_total", 1, "purpose" => self.purpose, "outcome" => "ok");
+                Ok(OutboundResponse { response, _permit: permit })
+            }
+            Err(e) => {
+                let outcome = if e.is_timeout() { "timeout" } else if e.is_connect() { "connect_error" } else { "error" };
+                counter!("outbound_requests_total", 1, "purpose" => self.purpose, "outcome" => outcome);
+                // reqwest's own message omits the cause (DNS, TLS, refused connection...)
+                let mut message = e.to_string();
+                let mut source = std::error::Error::source(&e);
+                while let Some(cause) = source {
+                    message.push_str(&format!(": {}", cause));
+                    source = cause.source();
+                }
+                Err(format!("outbound request to {} failed: {}", host, message))
+            }
+        }
+    }
+}
+
+pub struct OutboundResponse {
+    pub response: reqwest::Response,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl OutboundResponse {
+    pub fn status(&self) -> reqwest::StatusCode {
+        self.response.status()
+    }
+
+    /// Reads the body, failing once it grows past `max_bytes`.
+    pub async fn bytes_limited(self, max_bytes: usize) -> Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        let mut stream = self.response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("read outbound response: {}", e))?;
+            if body.len() + chunk.len() > max_bytes {
+                return Err(format!("response body exceeds {} bytes", max_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,130 @@
+//! Which SIMD and accelerator backends the inference runtimes are actually using.
+//!
+//! A binary built without the instruction sets the host supports (or a debug build) runs
+//! several times slower without any error, so the report compares the two and logs mismatches.
+
+use once_cell::sync::Lazy;
+
+use crate::api::dto::AccelerationInfo;
+
+// (name, compiled into this binary, supported by the host CPU)
+macro_rules! x86_features {
+    ($($name:tt),*) => {
+        vec![$((
+            $name,
+            cfg!(target_feature = $name),
+            {
+                #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+                { std::arch::is_x86_feature_detected!($name) }
+                #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+                { false }
+            },
+        )),*]
+    };
+}
+
+macro_rules! aarch64_features {
+    ($($name:tt),*) => {
+        vec![$((
+            $name,
+            cfg!(target_feature = $name),
+            {
+                #[cfg(target_arch = "aarch64")]
+                { std::arch::is_aarch64_feature_detected!($name) }
+                #[cfg(not(target_arch = "aarch64"))]
+                { false }
+            },
+        )),*]
+    };
+}
+
+fn simd_features() -> Vec<(&'static str, bool, bool)> {
+    if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        x86_features!("sse4.2", "avx", "avx2", "fma", "f16c", "avx512f", "avx512bw", "avx512vnni")
+    } else if cfg!(target_arch = "aarch64") {
+        aarch64_features!("neon", "dotprod", "fp16", "i8mm", "sve")
+    } else {
+        Vec::new()
+    }
+}
+
+/// ENV: ACCEL_COMPAT_MODE=1 keeps every runtime on the plain CPU path (no GPU offload or
+/// accelerator execution providers), for hosts where a misdetected backend crashes or misbehaves.
+pub fn compat_mode() -> bool {
+    matches!(crate::config::var("ACCEL_COMPAT_MODE").as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Accelerator execution providers ONNX Runtime can use on this host.
+#[cfg(feature = "onnx")]
+fn onnx_providers() -> Vec<String> {
+    use ort::execution_providers::{
+        CUDAExecutionProvider, CoreMLExecutionProvider, DirectMLExecutionProvider, ExecutionProvider,
+        ROCmExecutionProvider, TensorRTExecutionProvider,
+    };
+    let candidates: [(&str, bool); 5] = [
+        ("cuda", CUDAExecutionProvider::default().is_available().unwrap_or(false)),
+        ("tensorrt", TensorRTExecutionProvider::default().is_available().unwrap_or(false)),
+        ("rocm", ROCmExecutionProvider::default().is_available().unwrap_or(false)),
+        ("coreml", CoreMLExecutionProvider::default().is_available().unwrap_or(false)),
+        ("directml", DirectMLExecutionProvider::default().is_available().unwrap_or(false)),
+    ];
+    candidates.iter().filter(|(_, available)| *available).map(|(name, _)| format!("onnx:{}", name)).collect()
+}
+
+#[cfg(not(feature = "onnx"))]
+fn onnx_providers() -> Vec<String> {
+    Vec::new()
+}
+
+fn build_report() -> AccelerationInfo {
+    let features = simd_features();
+    let names = |pick: fn(&(&str, bool, bool)) -> bool| -> Vec<String> {
+        features.iter().filter(|f| pick(f)).map(|f| f.0.to_string()).collect()
+    };
+    let compat = compat_mode();
+    AccelerationInfo {
+        target_arch: std::env::consts::ARCH.to_string(),
+        build_profile: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
+        compiled_simd: names(|f| f.1),
+        detected_simd: names(|f| f.2),
+        missing_from_build: names(|f| f.2 && !f.1),
+        unsupported_by_cpu: names(|f| f.1 && !f.2),
+        gpu_backends: if compat { Vec::new() } else { onnx_providers() },
+        compat_mode: compat,
+    }
+}
+
+static REPORT: Lazy<AccelerationInfo> = Lazy::new(build_report);
+
+pub fn report() -> AccelerationInfo {
+    REPORT.clone()
+}
+
+/// Logs the acceleration summary once at startup, warning about builds that leave performance
+/// on the table.
+pub fn log_startup() {
+    let report = report();
+    tracing::info!(
+        "acceleration: arch={} profile={} simd=[{}] gpu=[{}] compat_mode={}",
+        report.target_arch,
+        report.build_profile,
+        report.compiled_simd.join(","),
+        report.gpu_backends.join(","),
+        report.compat_mode
+    );
+    if report.build_profile == "debug" {
+        tracing::warn!("running a debug build; inference will be much slower than a release build");
+    }
+    if !report.missing_from_build.is_empty() {
+        tracing::warn!(
+            "CPU supports [{}] but this binary was built without them; rebuild with RUSTFLAGS=\"-C target-cpu=native\"",
+            report.missing_from_build.join(",")
+        );
+    }
+    if !report.unsupported_by_cpu.is_empty() {
+        tracing::error!(
+            "binary was built for [{}] which this CPU lacks; expect crashes, set ACCEL_COMPAT_MODE=1 and rebuild",
+            report.unsupported_by_cpu.join(",")
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::Cursor;
+
+use crate::api::dto::TranscriptionSegment;
+
+/// Sample rate every transcription runtime consumes (whisper's native rate).
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// Default speech synthesis rate, and the rate of raw `pcm` speech responses (as OpenAI's).
+pub const SPEECH_SAMPLE_RATE: u32 = 24_000;
+
+/// Decodes a WAV upload into mono f32 PCM at `SAMPLE_RATE`, downmixing and resampling as needed.
+pub fn decode_wav(bytes: &[u8]) -> Result<Vec<f32>, String> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes))
+        .map_err(|e| format!("Unsupported audio file (expected WAV): {}", e))?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.map(|v| v as f32 / scale)).collect::<Result<_, _>>()
+        }
+    }
+    .map_err(|e| format!("Corrupt audio file: {}", e))?;
+
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok(resample(&mono, spec.sample_rate, SAMPLE_RATE))
+}
+
+/// Linear-interpolation resampler; adequate for speech going into a 16 kHz model.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (samples.len() as f64 / ratio).floor() as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let idx = pos as usize;
+            let frac = (pos - idx as f64) as f32;
+            let a = samples[idx];
+            let b = samples.get(idx + 1).copied().unwrap_or(a);
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Encodes mono PCM as a 16-bit WAV file.
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>, String> {
+    let spec = hound::WavSpec { channels: 1, sample_rate, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec).map_err(|e| format!("wav encode error: {}", e))?;
+    for &sample in samples {
+        writer.write_sample(to_i16(sample)).map_err(|e| format!("wav encode error: {}", e))?;
+    }
+    writer.finalize().map_err(|e| format!("wav encode error: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+/// Encodes mono PCM as raw signed 16-bit little-endian samples.
+pub fn encode_pcm16(samples: &[f32]) -> Vec<u8> {
+    samples.iter().flat_map(|&s| to_i16(s).to_le_bytes()).collect()
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Duration in seconds of `SAMPLE_RATE` mono PCM.
+pub fn duration_secs(samples: &[f32]) -> f32 {
+    samples.len() as f32 / SAMPLE_RATE as f32
+}
+
+/// Renders segments as SubRip (`vtt == false`) or WebVTT subtitles.
+pub fn format_subtitles(segments: &[TranscriptionSegment], vtt: bool) -> String {
+    let timestamp = |secs: f32| {
+        let ms = (secs.max(0.0) * 1000.0).round() as u64;
+        let (h, m, s, ms) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+        if vtt { format!("{:02}:{:02}:{:02}.{:03}", h, m, s, ms) } else { format!("{:02}:{:02}:{:02},{:03}", h, m, s, ms) }
+    };
+    let mut out = if vtt { "WEBVTT\n\n".to_string() } else { String::new() };
+    for (i, segment) in segments.iter().enumerate() {
+        if !vtt {
+            out.push_str(&format!("{}\n", i + 1));
+        }
+        out.push_str(&format!("{} --> {}\n{}\n\n", timestamp(segment.start), timestamp(segment.end), segment.text.trim()));
+    }
+    out
+}
@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::{llama, mistral, phi, phi3};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{path::Path, sync::{Arc, Mutex}};
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc;
+
+use crate::api::dto::ModelOptions;
+use crate::runtime::{
+    decoding::{end_tokens, TextStream},
+    safetensors,
+    sampler::Sampler,
+    stop::StopMatcher,
+    GenerationOptions, LlmRuntime, RuntimeError,
+};
+
+// Context used when neither the model config nor `n_ctx` sets one
+const DEFAULT_CONTEXT: usize = 4096;
+
+fn candle_error(e: candle_core::Error) -> String {
+    format!("candle error: {}", e)
+}
+
+enum Model {
+    // The llama KV cache lives outside the model and is rebuilt per generation
+    Llama { model: llama::Llama, config: llama::Config, cache: llama::Cache },
+    Mistral(mistral::Model),
+    Phi(phi::Model),
+    Phi3(phi3::Model),
+}
+
+impl Model {
+    // Logits for the token after `input`, which starts at position `pos`
+    fn forward(&mut self, input: &Tensor, pos: usize) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Llama { model, cache, .. } => model.forward(input, pos, cache),
+            Model::Mistral(model) => model.forward(input, pos),
+            // Phi tracks positions in its own cache
+            Model::Phi(model) => model.forward(input),
+            Model::Phi3(model) => model.forward(input, pos),
+        }
+    }
+
+    fn clear(&mut self, dtype: DType, device: &Device) -> candle_core::Result<()> {
+        match self {
+            Model::Llama { config, cache, .. } => *cache = llama::Cache::new(true, dtype, config, device)?,
+            Model::Mistral(model) => model.clear_kv_cache(),
+            Model::Phi(model) => model.clear_kv_cache(),
+            Model::Phi3(model) => model.clear_kv_cache(),
+        }
+        Ok(())
+    }
+}
+
+struct Inner {
+    // One generation at a time: the KV cache belongs to the model
+    model: Mutex<Model>,
+    tokenizer: Tokenizer,
+    device: Device,
+    dtype: DType,
+    // EOS and end-of-turn token ids
+    end_tokens: Vec<u32>,
+    max_context: usize,
+}
+
+/// Pure-Rust backend on candle for Hugging Face safetensors checkpoints of Llama, Mistral and
+/// Phi models. `model_path` is a directory with `config.json`, `tokenizer.json` and the
+/// `*.safetensors` weights. Runs on CUDA or Metal when candle was built with them.
+pub struct CandleRuntime {
+    inner: Arc<Inner>,
+}
+
+impl CandleRuntime {
+    /// `options.main_gpu` picks the GPU, `n_gpu_layers: 0` keeps the model on the CPU and
+    /// `n_ctx` overrides the context length from the config.
+    pub fn new(model_path: &str, options: &ModelOptions) -> Result<Self, String> {
+        let dir = Path::new(model_path);
+        let config_json = std::fs::read_to_string(dir.join("config.json"))
+            .map_err(|e| format!("Failed to read {}/config.json: {}", model_path, e))?;
+        let config: serde_json::Value = serde_json::from_str(&config_json).map_err(|e| format!("Invalid config.json: {}", e))?;
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| format!("Failed to load tokenizer.json: {}", e))?;
+
+        let weights = safetensors::weight_files(dir)?;
+        // Checked up front: candle reports a corrupt or truncated shard far less clearly
+        for file in &weights {
+            safetensors::read_header(file)?;
+        }
+
+        let device = device(options)?;
+        let dtype = if device.is_cpu() {
+            DType::F32
+        } else if device.is_metal() {
+            DType::F16
+        } else {
+            DType::BF16
+        };
+        // SAFETY: the weight files are not modified while mapped
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&weights, dtype, &device) }.map_err(candle_error)?;
+        let parse = |e: serde_json::Error| format!("Unsupported config.json: {}", e);
+        let model_type = config["model_type"].as_str().unwrap_or_default();
+        let model = match model_type {
+            "llama" => {
+                let llama_config: llama::LlamaConfig = serde_json::from_str(&config_json).map_err(parse)?;
+                let config = llama_config.into_config(false);
+                let cache = llama::Cache::new(true, dtype, &config, &device).map_err(candle_error)?;
+                Model::Llama { model: llama::Llama::load(vb, &config).map_err(candle_error)?, config, cache }
+            }
+            "mistral" => {
+                let config: mistral::Config = serde_json::from_str(&config_json).map_err(parse)?;
+                Model::Mistral(mistral::Model::new(&config, vb).map_err(candle_error)?)
+            }
+            "phi" => {
+                let config: phi::Config = serde_json::from_str(&config_json).map_err(parse)?;
+                Model::Phi(phi::Model::new(&config, vb).map_err(candle_error)?)
+            }
+            "phi3" => {
+                let config: phi3::Config = serde_json::from_str(&config_json).map_err(parse)?;
+                Model::Phi3(phi3::Model::new(&config, vb).map_err(candle_error)?)
+            }
+            other => return Err(format!("Unsupported model_type {:?}; expected llama, mistral, phi or phi3", other)),
+        };
+
+        let end_tokens = end_tokens(&config, |token| tokenizer.token_to_id(token));
+
+        let max_context = options
+            .n_ctx
+            .map(|n| n as usize)
+            .or(config["max_position_embeddings"].as_u64().map(|n| n as usize))
+            .unwrap_or(DEFAULT_CONTEXT);
+        tracing::info!("candle model {} ({}) on {:?}, {:?}", model_path, model_type, device, dtype);
+        Ok(Self { inner: Arc::new(Inner { model: Mutex::new(model), tokenizer, device, dtype, end_tokens, max_context }) })
+    }
+}
+
+fn device(options: &ModelOptions) -> Result<Device, String> {
+    if crate::runtime::accel::compat_mode() || options.n_gpu_layers == Some(0) {
+        return Ok(Device::Cpu);
+    }
+    let ordinal = options.main_gpu.unwrap_or(0) as usize;
+    if candle_core::utils::cuda_is_available() {
+        return Device::new_cuda(ordinal).map_err(candle_error);
+    }
+    if candle_core::utils::metal_is_available() {
+        return Device::new_metal(ordinal).map_err(candle_error);
+    }
+    Ok(Device::Cpu)
+}
+
+impl Inner {
+    fn decode(&self, tokens: &[u32]) -> Result<String, String> {
+        self.tokenizer.decode(tokens, true).map_err(|e| format!("tokenizer error: {}", e))
+    }
+
+    // Blocking; hands text to `on_piece` as it decodes and stops early when it returns false
+    fn generate(&self, prompt: &str, options: &GenerationOptions, mut on_piece: impl FnMut(String) -> bool) -> Result<(), RuntimeError> {
+        let encoding = self.tokenizer.encode(prompt, true).map_err(|e| format!("tokenizer error: {}", e))?;
+        let prompt_tokens = encoding.get_ids().to_vec();
+        if prompt_tokens.is_empty() {
+            return Err(RuntimeError::InvalidInput("prompt is empty".to_string()));
+        }
+        if prompt_tokens.len() >= self.max_context {
+            return Err(RuntimeError::InvalidInput(format!("prompt needs {} tokens; the context holds {}", prompt_tokens.len(), self.max_context)));
+        }
+        let mut model = self.model.lock().unwrap();
+        model.clear(self.dtype, &self.device).map_err(candle_error)?;
+
+        let mut sampler = Sampler::new(options, StdRng::from_entropy());
+        let mut stops = StopMatcher::new(&options.stop).reporting_to(&options.matched_stop);
+        let mut text = TextStream::default();
+        let mut input = prompt_tokens.clone();
+        let mut pos = 0;
+        for _ in 0..options.max_tokens {
+            if options.cancel.is_cancelled() || pos + input.len() >= self.max_context {
+                break;
+            }
+            let tensor = Tensor::new(input.as_slice(), &self.device).and_then(|t| t.unsqueeze(0)).map_err(candle_error)?;
+            // Only the last position's logits come back, whatever the architecture's shape
+            let logits = model
+                .forward(&tensor, pos)
+                .and_then(|l| l.flatten_all())
+                .and_then(|l| l.to_dtype(DType::F32))
+                .and_then(|l| l.to_vec1::<f32>())
+                .map_err(candle_error)?;
+            pos += input.len();
+            let Some(token) = sampler.sample(&logits).map(|t| t as u32) else { break };
+            if self.end_tokens.contains(&token) {
+                break;
+            }
+            let piece = stops.push(&text.push(token, |tokens| self.decode(tokens))?);
+            if !piece.is_empty() && !on_piece(piece) {
+                return Ok(());
+            }
+            if stops.is_stopped() {
+                return Ok(());
+            }
+            input = vec![token];
+        }
+        let rest = stops.push(&text.finish(|tokens| self.decode(tokens))?) + &stops.finish();
+        if !rest.is_empty() {
+            on_piece(rest);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmRuntime for CandleRuntime {
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.generate_stream(prompt, options, tx), collect);
+        result.map(|_| text)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let inner = self.inner.clone();
+        let prompt = prompt.to_string();
+        let options = options.clone();
+        // Decoding is compute-bound and synchronous; keep it off the async workers
+        tokio::task::spawn_blocking(move || inner.generate(&prompt, &options, |piece| sender.blocking_send(piece).is_ok()))
+            .await
+            .map_err(|e| RuntimeError::Backend(format!("candle task failed: {}", e)))?
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        match self.inner.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.get_ids().len() as u32,
+            Err(_) => crate::runtime::approximate_token_count(text),
+        }
+    }
+}
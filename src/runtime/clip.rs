@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use ndarray::{Array2, Array4, ArrayD, Axis};
+use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
+use std::{path::Path, sync::Arc};
+use tokenizers::Tokenizer;
+
+use crate::runtime::{
+    image::RgbImage,
+    vision::{self, ImageDetail, SquareMode, VisionInputSpec},
+    EmbeddingRuntime, RuntimeError,
+};
+
+// CLIP text context length
+const MAX_TEXT_TOKENS: usize = 77;
+// ViT-B/32 and ViT-L/14 take 224px unless the export says otherwise
+const DEFAULT_IMAGE_SIZE: usize = 224;
+
+struct Inner {
+    _env: Environment,
+    text: Session,
+    vision: Session,
+    tokenizer: Tokenizer,
+    input_spec: VisionInputSpec,
+}
+
+/// CLIP dual encoder: text and images are embedded into the same normalized space, so either
+/// can be searched with the other.
+///
+/// Loads `text_model.onnx` (a text model with projection: `input_ids` and optionally
+/// `attention_mask` in, `text_embeds` [batch, dim] first out), `vision_model.onnx`
+/// (`pixel_values` [batch, 3, S, S] in, `image_embeds` [batch, dim] first out) and
+/// `tokenizer.json` from one directory.
+pub struct ClipEmbeddingRuntime {
+    inner: Arc<Inner>,
+}
+
+impl ClipEmbeddingRuntime {
+    pub fn new(model_dir: &str) -> Result<Self, String> {
+        let dir = Path::new(model_dir);
+        let env = Environment::builder().with_name("clip").build().map_err(|e| format!("ORT env error: {}", e))?;
+        let load = |file: &str| {
+            SessionBuilder::new(&env)
+                .with_model_from_file(dir.join(file))
+                .map_err(|e| format!("ORT load {} error: {}", file, e))
+        };
+        let text = load("text_model.onnx")?;
+        let vision = load("vision_model.onnx")?;
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| format!("load tokenizer error: {}", e))?;
+        let image_size = vision
+            .inputs
+            .first()
+            .and_then(|input| input.input_type.tensor_dimensions().and_then(|dims| dims.last().copied()))
+            .filter(|&size| size > 0)
+            .map_or(DEFAULT_IMAGE_SIZE, |size| size as usize);
+        // CLIP was trained on centre crops and sees one view per image
+        let input_spec = VisionInputSpec::clip(image_size, SquareMode::CenterCrop);
+        Ok(Self { inner: Arc::new(Inner { _env: env, text, vision, tokenizer, input_spec }) })
+    }
+}
+
+impl Inner {
+    fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), true).map_err(|e| format!("tokenize error: {}", e))?;
+        let batch = encodings.len();
+        let mut input_ids = Array2::<i64>::zeros((batch, MAX_TEXT_TOKENS));
+        let mut attention = Array2::<i64>::zeros((batch, MAX_TEXT_TOKENS));
+        for (b, encoding) in encodings.iter().enumerate() {
+            let ids = encoding.get_ids();
+            if ids.is_empty() {
+                continue;
+            }
+            // Keep the end-of-text token, which the text model pools from, when truncating
+            let len = ids.len().min(MAX_TEXT_TOKENS);
+            for (t, &id) in ids[..len - 1].iter().chain(ids.last()).enumerate() {
+                input_ids[(b, t)] = id as i64;
+                attention[(b, t)] = 1;
+            }
+        }
+        let ids_tensor = Value::from_array(input_ids.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let attention_tensor = Value::from_array(attention.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let mut inputs = vec![("input_ids", &ids_tensor)];
+        if self.text.inputs.iter().any(|i| i.name == "attention_mask") {
+            inputs.push(("attention_mask", &attention_tensor));
+        }
+        let outputs = self.text.run(inputs).map_err(|e| format!("ort text run error: {}", e))?;
+        let embeds: ArrayD<f32> = outputs.get(0).ok_or("text model produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+        rows(embeds, batch)
+    }
+
+    fn embed_images(&self, images: &[RgbImage]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let size = self.input_spec.size;
+        let mut pixels = Vec::with_capacity(images.len() * 3 * size * size);
+        for image in images {
+            pixels.extend(vision::preprocess(image, ImageDetail::Low, &self.input_spec).remove(0));
+        }
+        let pixels = Array4::from_shape_vec((images.len(), 3, size, size), pixels).map_err(|e| e.to_string())?;
+        let input = Value::from_array(pixels.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let name = self.vision.inputs.first().map_or("pixel_values", |i| i.name.as_str());
+        let outputs = self.vision.run(vec![(name, &input)]).map_err(|e| format!("ort vision run error: {}", e))?;
+        let embeds: ArrayD<f32> = outputs.get(0).ok_or("vision model produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+        rows(embeds, images.len()).map_err(RuntimeError::Backend)
+    }
+}
+
+// L2-normalized rows of a [batch, dim] output
+fn rows(embeds: ArrayD<f32>, batch: usize) -> Result<Vec<Vec<f32>>, String> {
+    let embeds = embeds
+        .into_dimensionality::<ndarray::Ix2>()
+        .map_err(|_| "expected [batch, dim] embeddings; export the model with its projection".to_string())?;
+    if embeds.shape()[0] != batch {
+        return Err(format!("model returned {} embeddings for {} inputs", embeds.shape()[0], batch));
+    }
+    Ok(embeds
+        .axis_iter(Axis(0))
+        .map(|row| {
+            let norm = row.iter().map(|v| v * v).sum::<f32>().sqrt();
+            row.iter().map(|v| if norm > 0.0 { v / norm } else { *v }).collect()
+        })
+        .collect())
+}
+
+#[async_trait]
+impl EmbeddingRuntime for ClipEmbeddingRuntime {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let inner = self.inner.clone();
+        let inputs = inputs.to_vec();
+        tokio::task::spawn_blocking(move || inner.embed_texts(&inputs))
+            .await
+            .map_err(|e| RuntimeError::Backend(format!("clip task failed: {}", e)))?
+            .map_err(RuntimeError::Backend)
+    }
+
+    async fn embed_images(&self, images: &[RgbImage]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let inner = self.inner.clone();
+        let images = images.to_vec();
+        tokio::task::spawn_blocking(move || inner.embed_images(&images))
+            .await
+            .map_err(|e| RuntimeError::Backend(format!("clip task failed: {}", e)))?
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        self.inner.tokenizer.encode(text, true).map_or(0, |e| e.len().min(MAX_TEXT_TOKENS) as u32)
+    }
+}
@@ -0,0 +1,38 @@
+//! Context shifting: when generation fills the context window, drop the oldest half of the
+//! tokens after the kept prefix (the system turns) and keep decoding, as llama.cpp's `main`
+//! example does for infinite generation.
+
+use metrics::counter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextShift {
+    // Tokens at the start of the context that are never discarded
+    pub n_keep: usize,
+    // Tokens discarded right after the kept prefix
+    pub n_discard: usize,
+}
+
+/// Plans a shift for a context holding `n_past` of `n_ctx` tokens, or None if the context still
+/// has room or there is nothing left to discard. The kept prefix is capped so a shift always
+/// frees at least a quarter of the window.
+pub fn plan(n_past: usize, n_ctx: usize, n_keep: usize) -> Option<ContextShift> {
+    if n_past < n_ctx {
+        return None;
+    }
+    let n_keep = n_keep.min(n_ctx / 2);
+    let n_discard = n_past.saturating_sub(n_keep) / 2;
+    (n_discard > 0).then_some(ContextShift { n_keep, n_discard })
+}
+
+/// Applies a plan to a token list, returning the shifted context.
+pub fn apply<T: Clone>(tokens: &[T], shift: ContextShift) -> Vec<T> {
+    let keep = shift.n_keep.min(tokens.len());
+    let resume = (keep + shift.n_discard).min(tokens.len());
+    tokens[..keep].iter().chain(&tokens[resume..]).cloned().collect()
+}
+
+pub fn record(model: &str, shift: ContextShift) {
+    tracing::debug!("context shift on {}: kept {} tokens, discarded {}", model, shift.n_keep, shift.n_discard);
+    counter!("llm_context_shifts_total", 1, "model" => model.to_string());
+    counter!("llm_context_shift_discarded_tokens_total", shift.n_discard as u64, "model" => model.to_string());
+}
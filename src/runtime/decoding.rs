@@ -0,0 +1,53 @@
+//! Pieces shared by runtimes that run their own token loop over a Hugging Face tokenizer:
+//! turning sampled tokens into text as they arrive, and knowing which tokens end a generation.
+
+use crate::runtime::stop::END_OF_TURN_MARKERS;
+
+/// Turns generated tokens into text as they arrive. Tokenizers only produce the right spacing
+/// and whole characters when decoding runs of tokens, so each new token's text is the
+/// difference between decoding the pending run with and without it.
+#[derive(Debug, Default)]
+pub struct TextStream {
+    tokens: Vec<u32>,
+    // Start of the run not yet emitted, and where its emitted part ends
+    start: usize,
+    emitted: usize,
+}
+
+impl TextStream {
+    /// Adds `token` and returns the text it completes; empty while a character is still split.
+    pub fn push(&mut self, token: u32, decode: impl Fn(&[u32]) -> Result<String, String>) -> Result<String, String> {
+        let before = decode(&self.tokens[self.start..self.emitted])?;
+        self.tokens.push(token);
+        let after = decode(&self.tokens[self.start..])?;
+        // A character split across tokens decodes as U+FFFD until it is complete
+        if after.len() > before.len() && after.is_char_boundary(before.len()) && !after.ends_with('\u{FFFD}') {
+            self.start = self.emitted;
+            self.emitted = self.tokens.len();
+            return Ok(after[before.len()..].to_string());
+        }
+        Ok(String::new())
+    }
+
+    /// Text of the tokens not emitted yet, for when generation ends.
+    pub fn finish(&mut self, decode: impl Fn(&[u32]) -> Result<String, String>) -> Result<String, String> {
+        let before = decode(&self.tokens[self.start..self.emitted])?;
+        let after = decode(&self.tokens[self.start..])?;
+        self.start = self.tokens.len();
+        self.emitted = self.tokens.len();
+        Ok(after.get(before.len()..).unwrap_or_default().to_string())
+    }
+}
+
+/// Tokens that end a generation: `eos_token_id` from a model's `config.json` (a number or a
+/// list) plus the end-of-turn markers and `</s>` where the vocabulary has them.
+pub fn end_tokens(config: &serde_json::Value, token_to_id: impl Fn(&str) -> Option<u32>) -> Vec<u32> {
+    let mut tokens: Vec<u32> = match &config["eos_token_id"] {
+        serde_json::Value::Array(ids) => ids.iter().filter_map(|id| id.as_u64()).map(|id| id as u32).collect(),
+        id => id.as_u64().map(|id| id as u32).into_iter().collect(),
+    };
+    tokens.extend(END_OF_TURN_MARKERS.iter().chain(&["</s>"]).filter_map(|marker| token_to_id(marker)));
+    tokens.sort_unstable();
+    tokens.dedup();
+    tokens
+}
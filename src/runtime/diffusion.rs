@@ -0,0 +1,128 @@
+//! Sampling math shared by diffusion image runtimes; independent of the inference backend.
+
+// Stable Diffusion 1.x/2.x training schedule ("scaled_linear" betas)
+const TRAIN_TIMESTEPS: usize = 1000;
+const BETA_START: f64 = 0.00085;
+const BETA_END: f64 = 0.012;
+
+/// Euler discrete sampler (as diffusers' `EulerDiscreteScheduler`) for epsilon-predicting
+/// models, with timesteps spaced evenly over the training schedule.
+#[derive(Debug, Clone)]
+pub struct EulerScheduler {
+    timesteps: Vec<f32>,
+    // One per timestep plus a trailing 0
+    sigmas: Vec<f32>,
+}
+
+impl EulerScheduler {
+    pub fn new(steps: usize) -> Self {
+        let steps = steps.max(1);
+        let sqrt_start = BETA_START.sqrt();
+        let sqrt_end = BETA_END.sqrt();
+        let mut alphas_cumprod = Vec::with_capacity(TRAIN_TIMESTEPS);
+        let mut product = 1.0f64;
+        for i in 0..TRAIN_TIMESTEPS {
+            let beta = (sqrt_start + (sqrt_end - sqrt_start) * i as f64 / (TRAIN_TIMESTEPS - 1) as f64).powi(2);
+            product *= 1.0 - beta;
+            alphas_cumprod.push(product);
+        }
+        let train_sigmas: Vec<f64> = alphas_cumprod.iter().map(|ac| ((1.0 - ac) / ac).sqrt()).collect();
+
+        // Evenly spaced from the noisiest timestep down to 0, sigma interpolated between steps
+        let last = (TRAIN_TIMESTEPS - 1) as f64;
+        let timesteps: Vec<f64> = (0..steps)
+            .map(|i| if steps == 1 { last } else { last - last * i as f64 / (steps - 1) as f64 })
+            .collect();
+        let mut sigmas: Vec<f32> = timesteps
+            .iter()
+            .map(|&t| {
+                let low = t.floor() as usize;
+                let high = (low + 1).min(TRAIN_TIMESTEPS - 1);
+                let frac = t - low as f64;
+                (train_sigmas[low] * (1.0 - frac) + train_sigmas[high] * frac) as f32
+            })
+            .collect();
+        sigmas.push(0.0);
+        Self { timesteps: timesteps.into_iter().map(|t| t as f32).collect(), sigmas }
+    }
+
+    /// Timestep values fed to the denoiser, noisiest first.
+    pub fn timesteps(&self) -> &[f32] {
+        &self.timesteps
+    }
+
+    pub fn sigmas(&self) -> &[f32] {
+        &self.sigmas
+    }
+
+    /// Standard deviation of the initial latent noise.
+    pub fn init_noise_sigma(&self) -> f32 {
+        (self.sigmas[0].powi(2) + 1.0).sqrt()
+    }
+
+    /// Scales the latents before the denoiser call of step `index`.
+    pub fn scale_model_input(&self, latents: &[f32], index: usize) -> Vec<f32> {
+        let scale = (self.sigmas[index].powi(2) + 1.0).sqrt();
+        latents.iter().map(|x| x / scale).collect()
+    }
+
+    /// Advances `latents` one step given the predicted noise of step `index`.
+    pub fn step(&self, noise: &[f32], index: usize, latents: &mut [f32]) {
+        let dt = self.sigmas[index + 1] - self.sigmas[index];
+        for (x, eps) in latents.iter_mut().zip(noise) {
+            *x += eps * dt;
+        }
+    }
+}
+
+/// Classifier-free guidance: pushes the conditional prediction away from the unconditional one.
+pub fn guide(uncond: &[f32], cond: &[f32], guidance_scale: f32) -> Vec<f32> {
+    uncond.iter().zip(cond).map(|(u, c)| u + guidance_scale * (c - u)).collect()
+}
+
+/// Standard normal samples from `rng` (Box-Muller).
+pub fn gaussian_noise(rng: &mut impl rand::Rng, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|_| {
+            let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+            let u2: f32 = rng.r#gen();
+            (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos()
+        })
+        .collect()
+}
+
+/// Converts a decoded image in [-1, 1], laid out as CHW, to interleaved 8-bit RGB.
+pub fn chw_to_rgb(pixels: &[f32], width: usize, height: usize) -> Vec<u8> {
+    let plane = width * height;
+    let mut rgb = Vec::with_capacity(plane * 3);
+    for i in 0..plane {
+        for c in 0..3 {
+            let value = pixels.get(c * plane + i).copied().unwrap_or(0.0);
+            rgb.push(((value / 2.0 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    rgb
+}
+
+// Least-squares map from SD 1.x/2.x latent channels to RGB in [-1, 1]
+const LATENT_RGB_FACTORS: [[f32; 3]; 4] = [
+    [0.3512, 0.2297, 0.3227],
+    [0.3250, 0.4974, 0.2350],
+    [-0.2829, 0.1762, 0.2721],
+    [-0.2120, -0.2616, -0.7177],
+];
+
+/// A cheap 1/8-scale RGB approximation of 4-channel CHW `latents`, without running the VAE.
+pub fn latent_preview(latents: &[f32], width: usize, height: usize) -> Vec<u8> {
+    let plane = width * height;
+    let mut pixels = vec![0.0f32; plane * 3];
+    for (channel, factors) in LATENT_RGB_FACTORS.iter().enumerate() {
+        for i in 0..plane {
+            let value = latents.get(channel * plane + i).copied().unwrap_or(0.0);
+            for (c, factor) in factors.iter().enumerate() {
+                pixels[c * plane + i] += value * factor;
+            }
+        }
+    }
+    chw_to_rgb(&pixels, width, height)
+}
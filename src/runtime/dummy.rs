@@ -1,20 +1,92 @@
 use async_trait::async_trait;
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Mutex}};
+use tokio::sync::mpsc;
 
-use crate::runtime::{LlmRuntime, MultimodalRuntime, GenerationOptions};
+use crate::runtime::{
+    dummy_embedding::DummyEmbeddingRuntime, prompt::last_user_turn, stop::StopMatcher, vision::ImageInput, BatchDecodeRuntime,
+    EmbeddingRuntime, GenerationOptions, LlmRuntime, MultimodalRuntime, RuntimeError, SequenceId, StepOutput,
+};
 
-pub struct DummyRuntime;
+#[derive(Default)]
+pub struct DummyRuntime {
+    decoder: Arc<DummyDecoder>,
+}
 
 impl DummyRuntime {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+}
+
+fn echo(prompt: &str, options: &GenerationOptions) -> String {
+    // Echo the latest user turn rather than the whole rendered conversation
+    let truncated: String = last_user_turn(prompt).chars().take(options.max_tokens as usize).collect();
+    let mut stops = StopMatcher::new(&options.stop).reporting_to(&options.matched_stop);
+    let mut echoed = stops.push(&format!("Echo: {}", truncated));
+    echoed.push_str(&stops.finish());
+    echoed
+}
+
+// Hands out the echo one word per step, as `generate_stream` does
+#[derive(Default)]
+struct DummyDecoder {
+    sequences: Mutex<HashMap<SequenceId, VecDeque<String>>>,
+}
+
+impl BatchDecodeRuntime for DummyDecoder {
+    fn max_sequences(&self) -> usize {
+        usize::MAX
+    }
+
+    fn add_sequence(&self, id: SequenceId, prompt: &str, options: &GenerationOptions) -> Result<(), RuntimeError> {
+        let words = echo(prompt, options).split_inclusive(' ').map(str::to_string).collect();
+        self.sequences.lock().unwrap().insert(id, words);
+        Ok(())
+    }
+
+    fn step(&self, ids: &[SequenceId]) -> Vec<StepOutput> {
+        let mut sequences = self.sequences.lock().unwrap();
+        ids.iter()
+            .map(|id| match sequences.get_mut(id).map(VecDeque::pop_front) {
+                Some(Some(word)) => StepOutput::Piece(word),
+                Some(None) => StepOutput::Finished,
+                None => StepOutput::Failed(format!("unknown sequence {}", id)),
+            })
+            .collect()
+    }
+
+    fn remove_sequence(&self, id: SequenceId) {
+        self.sequences.lock().unwrap().remove(&id);
     }
 }
 
 #[async_trait]
 impl LlmRuntime for DummyRuntime {
-    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, String> {
-        let truncated: String = prompt.chars().take(options.max_tokens as usize).collect();
-        Ok(format!("Echo: {}", truncated))
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        Ok(echo(prompt, options))
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let generated = self.generate(prompt, options).await?;
+        for token in generated.split_inclusive(' ') {
+            if options.cancel.is_cancelled() || sender.send(token.to_string()).await.is_err() {
+                break; // receiver dropped
+            }
+        }
+        Ok(())
+    }
+
+    fn batch_decoder(&self) -> Option<Arc<dyn BatchDecodeRuntime>> {
+        Some(self.decoder.clone())
+    }
+
+    fn embedder(&self) -> Option<Arc<dyn EmbeddingRuntime>> {
+        Some(Arc::new(DummyEmbeddingRuntime::new(384)))
     }
 }
 
@@ -23,12 +95,12 @@ impl MultimodalRuntime for DummyRuntime {
     async fn generate_from_vision(
         &self,
         text: &str,
-        image_urls: &[String],
+        images: &[ImageInput],
         options: &GenerationOptions,
-    ) -> Result<String, String> {
-        let mut response = format!("Echo(Vision): {}", text);
-        if !image_urls.is_empty() {
-            response.push_str(&format!(" | images={}", image_urls.len()));
+    ) -> Result<String, RuntimeError> {
+        let mut response = format!("Echo(Vision): {}", last_user_turn(text));
+        if !images.is_empty() {
+            response.push_str(&format!(" | images={}", images.len()));
         }
         let truncated: String = response.chars().take(options.max_tokens as usize).collect();
         Ok(truncated)
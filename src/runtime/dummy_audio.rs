@@ -0,0 +1,25 @@
+use async_trait::async_trait;
+
+use crate::api::dto::TranscriptionSegment;
+use crate::runtime::{audio::duration_secs, AudioTranscriptionRuntime, RuntimeError, Transcription, TranscriptionOptions};
+
+/// Describes the audio instead of transcribing it.
+#[derive(Default)]
+pub struct DummyAudioRuntime;
+
+impl DummyAudioRuntime {
+    pub fn new() -> Self { Self }
+}
+
+#[async_trait]
+impl AudioTranscriptionRuntime for DummyAudioRuntime {
+    async fn transcribe(&self, samples: &[f32], options: &TranscriptionOptions) -> Result<Transcription, RuntimeError> {
+        let duration = duration_secs(samples);
+        let text = format!("Transcribed {:.1}s of audio", duration);
+        Ok(Transcription {
+            segments: vec![TranscriptionSegment { id: 0, start: 0.0, end: duration, text: text.clone() }],
+            text,
+            language: Some(options.language.clone().unwrap_or_else(|| "en".to_string())),
+        })
+    }
+}
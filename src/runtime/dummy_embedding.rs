@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 
-use crate::runtime::EmbeddingRuntime;
+use crate::runtime::{image::RgbImage, EmbeddingRuntime, RuntimeError};
 
 pub struct DummyEmbeddingRuntime {
     dimension: usize,
@@ -12,31 +12,50 @@ impl DummyEmbeddingRuntime {
     }
 }
 
-#[async_trait]
-impl EmbeddingRuntime for DummyEmbeddingRuntime {
-    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
-        let mut results: Vec<Vec<f32>> = Vec::with_capacity(inputs.len());
-        for text in inputs {
-            let mut vec = vec![0.0_f32; self.dimension];
-            let mut hash: u64 = 1469598103934665603; // FNV offset basis
-            for b in text.as_bytes() {
-                hash ^= *b as u64;
-                hash = hash.wrapping_mul(1099511628211);
-            }
-            // Fill vector deterministically from hash
-            for i in 0..self.dimension {
-                let v = ((hash.rotate_left((i % 64) as u32) % 1000) as f32) / 1000.0;
-                vec[i] = v;
-            }
-            // L2 normalize
-            let norm: f32 = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
-            if norm > 0.0 {
-                for v in &mut vec {
-                    *v /= norm;
-                }
+impl DummyEmbeddingRuntime {
+    fn embed_bytes(&self, bytes: &[u8]) -> Vec<f32> {
+        let mut vec = vec![0.0_f32; self.dimension];
+        let mut hash: u64 = 1469598103934665603; // FNV offset basis
+        for b in bytes {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(1099511628211);
+        }
+        // Fill vector deterministically from hash
+        for (i, slot) in vec.iter_mut().enumerate() {
+            *slot = ((hash.rotate_left((i % 64) as u32) % 1000) as f32) / 1000.0;
+        }
+        // L2 normalize
+        let norm: f32 = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for v in &mut vec {
+                *v /= norm;
             }
-            results.push(vec);
         }
-        Ok(results)
+        vec
+    }
+}
+
+#[async_trait]
+impl EmbeddingRuntime for DummyEmbeddingRuntime {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        Ok(inputs.iter().map(|text| self.embed_bytes(text.as_bytes())).collect())
+    }
+
+    async fn embed_tokens(&self, inputs: &[Vec<u32>]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        Ok(inputs
+            .iter()
+            .map(|ids| self.embed_bytes(&ids.iter().flat_map(|id| id.to_le_bytes()).collect::<Vec<u8>>()))
+            .collect())
+    }
+
+    async fn embed_images(&self, images: &[RgbImage]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        Ok(images
+            .iter()
+            .map(|image| {
+                let mut bytes = format!("image:{}x{}:", image.width, image.height).into_bytes();
+                bytes.extend(&image.pixels);
+                self.embed_bytes(&bytes)
+            })
+            .collect())
     }
 }
@@ -1,7 +1,11 @@
 use async_trait::async_trait;
 
-use crate::runtime::ImageGenRuntime;
+use crate::runtime::{ImageGenOptions, ImageGenRuntime, ImageProgress, RuntimeError};
 
+// Steps reported when the request does not choose
+const DUMMY_STEPS: u32 = 4;
+
+#[derive(Default)]
 pub struct DummyImageRuntime;
 
 impl DummyImageRuntime {
@@ -10,11 +14,32 @@ impl DummyImageRuntime {
 
 #[async_trait]
 impl ImageGenRuntime for DummyImageRuntime {
-    async fn generate_images(&self, _prompt: &str, n: u32, size: &str) -> Result<Vec<Vec<u8>>, String> {
-        // Returns n placeholder PNG-like byte arrays tagged with size
+    async fn generate_images(&self, _prompt: &str, options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        // Returns n placeholder PNG-like byte arrays tagged with size (and seed, when given)
         let mut result = Vec::new();
-        let header = format!("DUMMY_PNG:{}:", size).into_bytes();
-        for _ in 0..n { result.push(header.clone()); }
+        let total_steps = options.steps.unwrap_or(DUMMY_STEPS);
+        for i in 0..options.n as u64 {
+            for step in 1..=total_steps {
+                let preview = options.previews.then(|| format!("DUMMY_PREVIEW:{}:{}:", i, step).into_bytes());
+                options.report(ImageProgress { image_index: i as u32, step, total_steps, preview });
+            }
+            let mut header = format!("DUMMY_PNG:{}:", options.size);
+            if let Some(seed) = options.seed {
+                header.push_str(&format!("seed={}:", seed.wrapping_add(i)));
+            }
+            result.push(header.into_bytes());
+        }
         Ok(result)
     }
+
+    async fn edit_image(&self, image: &[u8], mask: Option<&[u8]>, _prompt: &str, options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        // Tagged with the source size so callers can tell the input reached the runtime
+        let header = format!("DUMMY_PNG:{}:edit:{}:{}:", options.size, image.len(), if mask.is_some() { "masked" } else { "unmasked" }).into_bytes();
+        Ok(vec![header; options.n as usize])
+    }
+
+    async fn generate_variations(&self, image: &[u8], options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        let header = format!("DUMMY_PNG:{}:variation:{}:", options.size, image.len()).into_bytes();
+        Ok(vec![header; options.n as usize])
+    }
 }
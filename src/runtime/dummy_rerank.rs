@@ -0,0 +1,38 @@
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+use crate::runtime::{RerankRuntime, RuntimeError};
+
+/// Scores documents by the fraction of query terms they contain.
+#[derive(Default)]
+pub struct DummyRerankRuntime;
+
+impl DummyRerankRuntime {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+#[async_trait]
+impl RerankRuntime for DummyRerankRuntime {
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>, RuntimeError> {
+        let query = terms(query);
+        if query.is_empty() {
+            return Ok(vec![0.0; documents.len()]);
+        }
+        Ok(documents
+            .iter()
+            .map(|doc| {
+                let doc = terms(doc);
+                query.iter().filter(|t| doc.contains(*t)).count() as f32 / query.len() as f32
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+
+use crate::runtime::{audio::SPEECH_SAMPLE_RATE, RuntimeError, SpeechOptions, TtsRuntime};
+
+// OpenAI's voice names, so clients written against its API work unchanged
+const VOICES: [&str; 6] = ["alloy", "echo", "fable", "onyx", "nova", "shimmer"];
+// Length of one character of input at speed 1.0
+const SECS_PER_CHAR: f32 = 0.06;
+
+/// Renders each character as a short tone (silence for whitespace), pitched per voice.
+#[derive(Default)]
+pub struct DummyTtsRuntime;
+
+impl DummyTtsRuntime {
+    pub fn new() -> Self { Self }
+}
+
+#[async_trait]
+impl TtsRuntime for DummyTtsRuntime {
+    async fn synthesize(&self, text: &str, options: &SpeechOptions) -> Result<Vec<f32>, RuntimeError> {
+        let voice = VOICES.iter().position(|v| *v == options.voice).unwrap_or(0);
+        let frequency = 160.0 + 40.0 * voice as f32;
+        let per_char = (SECS_PER_CHAR / options.speed * SPEECH_SAMPLE_RATE as f32) as usize;
+        let mut samples = Vec::with_capacity(per_char * text.chars().count());
+        for c in text.chars() {
+            for i in 0..per_char {
+                let t = i as f32 / SPEECH_SAMPLE_RATE as f32;
+                samples.push(if c.is_whitespace() { 0.0 } else { (t * frequency * std::f32::consts::TAU).sin() * 0.2 });
+            }
+        }
+        Ok(samples)
+    }
+
+    fn voices(&self) -> Vec<String> {
+        VOICES.iter().map(|v| v.to_string()).collect()
+    }
+}
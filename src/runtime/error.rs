@@ -0,0 +1,26 @@
+//! Errors runtimes report, by whose fault they are, so the engine can answer them without
+//! reading the message.
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RuntimeError {
+    /// The input cannot be served as sent: too long for the context, in a format the model
+    /// does not take, or asking for something the backend does not support.
+    #[error("{0}")]
+    InvalidInput(String),
+    /// The backend failed on an input it should have served.
+    #[error("{0}")]
+    Backend(String),
+}
+
+// Failures of the libraries runtimes are built on, which report text
+impl From<String> for RuntimeError {
+    fn from(e: String) -> Self {
+        RuntimeError::Backend(e)
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(e: &str) -> Self {
+        RuntimeError::Backend(e.to_string())
+    }
+}
@@ -0,0 +1,174 @@
+//! Reader for the metadata of GGUF model files: a magic and version, tensor and key-value
+//! counts, then typed key-value pairs. Only the transformer's shape is kept, which is what
+//! sizing its KV cache takes; tensors and the (large) tokenizer arrays are skipped.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+// Keys and strings longer than this are not metadata worth reading
+const MAX_STRING_BYTES: u64 = 1 << 20;
+
+/// Bytes per KV cache element; llama.cpp keeps K and V in f16 unless told otherwise.
+const KV_ELEMENT_BYTES: u64 = 2;
+
+/// KV cache cells of the context a batch decoder shares when the model sets no `n_ctx`.
+pub const BATCH_CTX: u32 = 8192;
+
+/// Cells of each llama.cpp session context when the model sets no `n_ctx`, llama.cpp's default.
+pub const SESSION_CTX: u32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelShape {
+    pub layers: u64,
+    // Key and value widths of one layer's cache cell, summed over the KV heads
+    pub key_width: u64,
+    pub value_width: u64,
+}
+
+impl ModelShape {
+    /// Memory a KV cache of `cells` positions takes across every layer.
+    pub fn kv_cache_bytes(&self, cells: u64) -> u64 {
+        self.layers * cells * (self.key_width + self.value_width) * KV_ELEMENT_BYTES
+    }
+}
+
+/// The shape of the transformer in a GGUF file, read from the metadata of its architecture.
+pub fn read_shape(path: &Path) -> Result<ModelShape, String> {
+    let fail = |e: String| format!("{}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| fail(e.to_string()))?;
+    let metadata = read_metadata(&mut BufReader::new(file)).map_err(fail)?;
+    let arch = metadata.architecture.ok_or_else(|| fail("no general.architecture".to_string()))?;
+    let key = |name: &str| metadata.numbers.get(&format!("{}.{}", arch, name)).copied();
+    let need = |name: &str| key(name).filter(|n| *n > 0).ok_or_else(|| fail(format!("no {}.{}", arch, name)));
+    let layers = need("block_count")?;
+    let heads = need("attention.head_count")?;
+    let head_width = need("embedding_length")? / heads;
+    let kv_heads = key("attention.head_count_kv").filter(|n| *n > 0).unwrap_or(heads);
+    Ok(ModelShape {
+        layers,
+        key_width: key("attention.key_length").unwrap_or(head_width) * kv_heads,
+        value_width: key("attention.value_length").unwrap_or(head_width) * kv_heads,
+    })
+}
+
+#[derive(Default)]
+struct Metadata {
+    architecture: Option<String>,
+    // Unsigned integer values; arrays and the rest are skipped
+    numbers: HashMap<String, u64>,
+}
+
+fn read_metadata(reader: &mut (impl Read + Seek)) -> Result<Metadata, String> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).map_err(|_| "too short for a GGUF header".to_string())?;
+    if &magic != b"GGUF" {
+        return Err("not a GGUF file".to_string());
+    }
+    let version = read_u32(reader)?;
+    if !(2..=3).contains(&version) {
+        return Err(format!("unsupported GGUF version {}", version));
+    }
+    let _tensors = read_u64(reader)?;
+    let pairs = read_u64(reader)?;
+    let mut metadata = Metadata::default();
+    for _ in 0..pairs {
+        let key = read_string(reader)?;
+        match (key.as_str(), read_u32(reader)?) {
+            ("general.architecture", STRING) => metadata.architecture = Some(read_string(reader)?),
+            (_, kind @ (UINT8 | UINT16 | UINT32 | UINT64 | INT32 | INT64)) => {
+                let value = read_integer(reader, kind)?;
+                metadata.numbers.insert(key, value);
+            }
+            (_, kind) => skip_value(reader, kind)?,
+        }
+    }
+    Ok(metadata)
+}
+
+// Value types of the format
+const UINT8: u32 = 0;
+const INT8: u32 = 1;
+const UINT16: u32 = 2;
+const INT16: u32 = 3;
+const UINT32: u32 = 4;
+const INT32: u32 = 5;
+const FLOAT32: u32 = 6;
+const BOOL: u32 = 7;
+const STRING: u32 = 8;
+const ARRAY: u32 = 9;
+const UINT64: u32 = 10;
+const INT64: u32 = 11;
+const FLOAT64: u32 = 12;
+
+fn scalar_size(kind: u32) -> Option<u64> {
+    Some(match kind {
+        UINT8 | INT8 | BOOL => 1,
+        UINT16 | INT16 => 2,
+        UINT32 | INT32 | FLOAT32 => 4,
+        UINT64 | INT64 | FLOAT64 => 8,
+        _ => return None,
+    })
+}
+
+fn read_integer(reader: &mut impl Read, kind: u32) -> Result<u64, String> {
+    let size = scalar_size(kind).unwrap_or(8) as usize;
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes[..size]).map_err(|e| e.to_string())?;
+    let value = u64::from_le_bytes(bytes);
+    // A negative count is no count
+    Ok(match kind {
+        INT32 => (value as u32 as i32).max(0) as u64,
+        INT64 => (value as i64).max(0) as u64,
+        _ => value,
+    })
+}
+
+fn skip_value(reader: &mut (impl Read + Seek), kind: u32) -> Result<(), String> {
+    match kind {
+        STRING => {
+            let len = read_u64(reader)?;
+            skip(reader, len)
+        }
+        ARRAY => {
+            let item = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            match scalar_size(item) {
+                Some(size) => skip(reader, size.saturating_mul(len)),
+                None => (0..len).try_for_each(|_| skip_value(reader, item)),
+            }
+        }
+        _ => {
+            let size = scalar_size(kind).ok_or_else(|| format!("unknown value type {}", kind))?;
+            skip(reader, size)
+        }
+    }
+}
+
+fn skip(reader: &mut impl Seek, bytes: u64) -> Result<(), String> {
+    let bytes = i64::try_from(bytes).map_err(|_| "truncated metadata".to_string())?;
+    reader.seek_relative(bytes).map_err(|e| e.to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).map_err(|_| "truncated metadata".to_string())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, String> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes).map_err(|_| "truncated metadata".to_string())?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_BYTES {
+        return Err(format!("a {} byte string in the metadata", len));
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes).map_err(|_| "truncated metadata".to_string())?;
+    String::from_utf8(bytes).map_err(|_| "a metadata string is not UTF-8".to_string())
+}
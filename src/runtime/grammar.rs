@@ -0,0 +1,96 @@
+//! Checks on GBNF grammars (llama.cpp's grammar format) supplied with a request, so a grammar
+//! the runtime would reject fails the request up front instead of midway through generation.
+
+use std::collections::HashSet;
+
+/// Longest grammar a request may carry.
+pub const MAX_GRAMMAR_BYTES: usize = 64 * 1024;
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Structural check of a GBNF grammar: literals and character classes are closed, parentheses
+/// balance, a `root` rule exists and every referenced rule is defined. Rule bodies are otherwise
+/// left to the runtime's parser.
+pub fn validate(grammar: &str) -> Result<(), String> {
+    if grammar.len() > MAX_GRAMMAR_BYTES {
+        return Err(format!("grammar is longer than {} bytes", MAX_GRAMMAR_BYTES));
+    }
+    let chars: Vec<char> = grammar.chars().collect();
+    let mut defined = HashSet::new();
+    let mut referenced = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            open @ ('"' | '[') => {
+                let close = if open == '"' { '"' } else { ']' };
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(format!("grammar has an unterminated {}", if open == '"' { "string" } else { "character class" })),
+                        Some('\\') => i += 2,
+                        Some(&c) if c == close => break,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+            }
+            // Repetition bounds such as {2,5}
+            '{' => {
+                while i < chars.len() && chars[i] != '}' {
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err("grammar has an unterminated repetition".to_string());
+                }
+                i += 1;
+            }
+            '(' => {
+                depth += 1;
+                i += 1;
+            }
+            ')' => {
+                depth = depth.checked_sub(1).ok_or("grammar has unbalanced parentheses")?;
+                i += 1;
+            }
+            c if is_name_char(c) => {
+                let start = i;
+                while i < chars.len() && is_name_char(chars[i]) {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                let mut next = i;
+                while next < chars.len() && (chars[next] == ' ' || chars[next] == '\t') {
+                    next += 1;
+                }
+                if chars[next..].starts_with(&[':', ':', '=']) {
+                    if depth > 0 {
+                        return Err(format!("grammar rule {} starts inside parentheses", name));
+                    }
+                    defined.insert(name);
+                    i = next + 3;
+                } else {
+                    referenced.push(name);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    if depth > 0 {
+        return Err("grammar has unbalanced parentheses".to_string());
+    }
+    if !defined.contains("root") {
+        return Err("grammar has no root rule".to_string());
+    }
+    match referenced.into_iter().find(|name| !defined.contains(name)) {
+        Some(name) => Err(format!("grammar references undefined rule {}", name)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,231 @@
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Width and height of a PNG, read from its IHDR chunk; fails for anything that is not a PNG.
+pub fn png_dimensions(bytes: &[u8]) -> Result<(u32, u32), String> {
+    if bytes.len() < 24 || !bytes.starts_with(PNG_SIGNATURE) || &bytes[12..16] != b"IHDR" {
+        return Err("Unsupported image file (expected PNG)".to_string());
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(bytes[20..24].try_into().unwrap());
+    Ok((width, height))
+}
+
+/// Parses an OpenAI-style `size` such as "512x512".
+pub fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size {:?}; expected WIDTHxHEIGHT", size);
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+/// Encodes 8-bit RGB pixels (row-major, `width * height * 3` bytes) as a PNG.
+///
+/// The image data is stored uncompressed; generated images are served once and size matters
+/// less than not pulling in an image codec.
+pub fn encode_png(rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let row = width as usize * 3;
+    if rgb.len() != row * height as usize {
+        return Err(format!("expected {} bytes of RGB for {}x{}, got {}", row * height as usize, width, height, rgb.len()));
+    }
+    // Each scanline is prefixed with filter type 0 (none)
+    let mut raw = Vec::with_capacity((row + 1) * height as usize);
+    for line in rgb.chunks(row) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    // zlib stream made of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if raw.is_empty() { vec![&[]] } else { raw.chunks(u16::MAX as usize).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push(u8::from(i + 1 == blocks.len()));
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(height.to_be_bytes());
+    ihdr.extend([8, 2, 0, 0, 0]); // 8-bit depth, truecolor, no interlace
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    for (kind, data) in [(b"IHDR", ihdr.as_slice()), (b"IDAT", zlib.as_slice()), (b"IEND", &[][..])] {
+        png.extend((data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend(crc.to_be_bytes());
+    }
+    Ok(png)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Decoded 8-bit RGB pixels, row-major (`width * height * 3` bytes).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// Larger inputs are refused before their pixels are allocated
+pub const MAX_DECODED_PIXELS: u64 = 40_000_000;
+
+pub(crate) fn check_pixel_count(width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("image has no pixels".to_string());
+    }
+    if width as u64 * height as u64 > MAX_DECODED_PIXELS {
+        return Err(format!("image is {}x{}; at most {} pixels are accepted", width, height, MAX_DECODED_PIXELS));
+    }
+    Ok(())
+}
+
+/// Decodes a PNG or JPEG to RGB; alpha is dropped.
+pub fn decode_image(bytes: &[u8]) -> Result<RgbImage, String> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(bytes)
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        super::jpeg::decode_jpeg(bytes)
+    } else {
+        Err("Unsupported image format (expected PNG or JPEG)".to_string())
+    }
+}
+
+/// Decodes a non-interlaced PNG of any color type and bit depth to RGB.
+pub fn decode_png(bytes: &[u8]) -> Result<RgbImage, String> {
+    let (width, height) = png_dimensions(bytes)?;
+    check_pixel_count(width, height)?;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    while offset + 8 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = &bytes[offset + 4..offset + 8];
+        let data = bytes.get(offset + 8..offset + 8 + len).ok_or("truncated PNG chunk")?;
+        match kind {
+            b"IHDR" if len >= 13 => header = Some((data[8], data[9], data[12])),
+            b"PLTE" => palette = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        offset += 12 + len;
+    }
+    let (depth, color_type, interlace) = header.ok_or("PNG has no IHDR chunk")?;
+    if interlace != 0 {
+        return Err("interlaced PNG is not supported".to_string());
+    }
+    let channels = match (color_type, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (2, 8 | 16) => 3,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8 | 16) => 2,
+        (6, 8 | 16) => 4,
+        _ => return Err(format!("invalid PNG color type {} with bit depth {}", color_type, depth)),
+    };
+    if color_type == 3 && palette.is_empty() {
+        return Err("palette PNG has no PLTE chunk".to_string());
+    }
+
+    let bits_per_pixel = channels * depth as usize;
+    let stride = (width as usize * bits_per_pixel).div_ceil(8);
+    // Filters look back one whole pixel, or one byte below 8 bits per pixel
+    let step = bits_per_pixel.div_ceil(8);
+    let expected = (stride + 1) * height as usize;
+    let mut raw = Vec::with_capacity(expected);
+    // Read at most what the header promises, so a crafted stream cannot inflate without bound
+    std::io::Read::read_to_end(&mut std::io::Read::take(flate2::read::ZlibDecoder::new(compressed.as_slice()), expected as u64), &mut raw)
+        .map_err(|e| format!("corrupt PNG data: {}", e))?;
+    if raw.len() < expected {
+        return Err("truncated PNG image data".to_string());
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+    let mut previous = vec![0u8; stride];
+    let mut line = vec![0u8; stride];
+    for row in raw.chunks_exact(stride + 1) {
+        line.copy_from_slice(&row[1..]);
+        unfilter(row[0], &mut line, &previous, step)?;
+        for x in 0..width as usize {
+            let sample = |channel: usize| -> u8 {
+                let index = x * channels + channel;
+                match depth {
+                    8 => line[index],
+                    // Keep the most significant byte
+                    16 => line[index * 2],
+                    _ => {
+                        let bit = index * depth as usize;
+                        let value = (line[bit / 8] >> (8 - depth as usize - bit % 8)) & ((1 << depth) - 1);
+                        if color_type == 3 { value } else { (value as u32 * 255 / ((1 << depth) - 1)) as u8 }
+                    }
+                }
+            };
+            match color_type {
+                0 | 4 => pixels.extend([sample(0); 3]),
+                3 => {
+                    let entry = sample(0) as usize * 3;
+                    let rgb = palette.get(entry..entry + 3).ok_or("PNG palette index out of range")?;
+                    pixels.extend_from_slice(rgb);
+                }
+                _ => pixels.extend([sample(0), sample(1), sample(2)]),
+            }
+        }
+        std::mem::swap(&mut previous, &mut line);
+    }
+    Ok(RgbImage { width, height, pixels })
+}
+
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], step: usize) -> Result<(), String> {
+    for i in 0..line.len() {
+        let left = if i >= step { line[i - step] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= step { previous[i - step] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => {
+                let p = left as i16 + up as i16 - up_left as i16;
+                let (pa, pb, pc) = ((p - left as i16).abs(), (p - up as i16).abs(), (p - up_left as i16).abs());
+                if pa <= pb && pa <= pc { left } else if pb <= pc { up } else { up_left }
+            }
+            other => return Err(format!("invalid PNG filter type {}", other)),
+        };
+        line[i] = line[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Acquisition of the images requests refer to (`image_url`), shared by multimodal runtimes:
+//! base64 `data:` URIs are decoded in place, http(s) URLs are fetched with a size cap and a
+//! timeout through a client that can only reach public addresses.
+
+use base64::Engine as _;
+use metrics::counter;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+
+use crate::outbound::{self, OutboundClient};
+
+#[derive(Debug, Clone)]
+pub struct ImageFetchConfig {
+    // Largest accepted image, fetched or inline
+    pub max_bytes: usize,
+    // Whole fetch, from connecting to the last body byte
+    pub timeout: Duration,
+    // Let URLs reach loopback and private networks (local development only)
+    pub allow_private: bool,
+}
+
+impl ImageFetchConfig {
+    /// ENV: IMAGE_FETCH_MAX_BYTES (default 20 MiB), IMAGE_FETCH_TIMEOUT_MS (default 10000),
+    /// IMAGE_FETCH_ALLOW_PRIVATE (default 0)
+    pub fn from_env() -> Self {
+        let env = |name: &str| crate::config::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        Self {
+            max_bytes: env("IMAGE_FETCH_MAX_BYTES").unwrap_or(20 * 1024 * 1024) as usize,
+            timeout: Duration::from_millis(env("IMAGE_FETCH_TIMEOUT_MS").unwrap_or(10_000)),
+            allow_private: crate::config::var("IMAGE_FETCH_ALLOW_PRIVATE").is_ok_and(|v| v == "1" || v == "true"),
+        }
+    }
+}
+
+pub struct ImageFetcher {
+    config: ImageFetchConfig,
+    client: OutboundClient,
+}
+
+static FETCHER: Lazy<ImageFetcher> = Lazy::new(|| ImageFetcher::new(ImageFetchConfig::from_env()));
+
+pub fn global() -> &'static ImageFetcher {
+    &FETCHER
+}
+
+impl ImageFetcher {
+    pub fn new(config: ImageFetchConfig) -> Self {
+        let client = if config.allow_private { outbound::client("image_fetch") } else { outbound::public_client("image_fetch") };
+        Self { config, client }
+    }
+
+    /// Raw bytes (still encoded, e.g. PNG) of the image at `url`.
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, String> {
+        let (source, result) = if url.starts_with("data:") {
+            ("data_uri", self.decode_data_uri(url))
+        } else {
+            ("http", self.fetch_http(url).await)
+        };
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        counter!("image_fetch_total", 1, "source" => source, "outcome" => outcome);
+        result
+    }
+
+    /// Every image of a request, fetched concurrently; errors name the failing image.
+    pub async fn fetch_all(&self, urls: &[String]) -> Result<Vec<Vec<u8>>, String> {
+        let fetches = urls.iter().enumerate().map(|(i, url)| async move { self.fetch(url).await.map_err(|e| format!("image {}: {}", i, e)) });
+        futures::future::try_join_all(fetches).await
+    }
+
+    fn decode_data_uri(&self, uri: &str) -> Result<Vec<u8>, String> {
+        let rest = &uri["data:".len()..];
+        let (media_type, payload) = rest.split_once(";base64,").ok_or("image data URI must be base64-encoded")?;
+        if !media_type.is_empty() && !media_type.starts_with("image/") {
+            return Err(format!("data URI has media type {}; expected an image", media_type));
+        }
+        // Base64 is 4/3 the size of what it encodes
+        if payload.len() / 4 * 3 > self.config.max_bytes {
+            return Err(format!("image exceeds {} bytes", self.config.max_bytes));
+        }
+        base64::engine::general_purpose::STANDARD.decode(payload.trim()).map_err(|e| format!("invalid image data URI: {}", e))
+    }
+
+    async fn fetch_http(&self, url: &str) -> Result<Vec<u8>, String> {
+        let parsed = reqwest::Url::parse(url).map_err(|_| "image_url must be an http(s) URL or a data URI".to_string())?;
+        if self.config.allow_private {
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err("image_url must be an http(s) URL or a data URI".to_string());
+            }
+        } else {
+            outbound::check_public_url(&parsed)?;
+        }
+        let fetch = async {
+            let response = self.client.send(self.client.get(url)).await?;
+            if !response.status().is_success() {
+                return Err(format!("fetching image returned HTTP {}", response.status()));
+            }
+            response.bytes_limited(self.config.max_bytes).await.map_err(|e| format!("image {}", e))
+        };
+        tokio::time::timeout(self.config.timeout, fetch)
+            .await
+            .map_err(|_| format!("fetching image timed out after {} ms", self.config.timeout.as_millis()))?
+    }
+}
@@ -0,0 +1,360 @@
+//! Baseline (sequential, Huffman-coded, 8-bit) JPEG decoding; enough for photos fed to vision
+//! models. Progressive and arithmetic-coded files are refused.
+
+use crate::runtime::image::{check_pixel_count, RgbImage};
+
+// Position in a block of the n-th coefficient of the zig-zag scan
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+#[derive(Clone, Default)]
+struct Huffman {
+    // Per code length (1..=16): largest code, or -1 when there is none
+    max_code: [i32; 17],
+    // Per code length: index into `values` of its first code, minus that code
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Self {
+        let mut table = Huffman { max_code: [-1; 17], offset: [0; 17], values: values.to_vec() };
+        let (mut code, mut index) = (0i32, 0i32);
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            table.offset[length] = index - code;
+            if count > 0 {
+                code += count;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    // Huffman table selectors of the current scan
+    dc_table: usize,
+    ac_table: usize,
+    dc_pred: i32,
+    // Decoded samples, padded to whole MCUs
+    plane: Vec<u8>,
+    plane_width: usize,
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos, bits: 0, count: 0 }
+    }
+
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            let mut byte = 0;
+            // A marker ends the entropy-coded data; pad with zeros past it
+            if self.pos < self.data.len() && !(self.data[self.pos] == 0xFF && self.data.get(self.pos + 1).is_some_and(|&b| b != 0)) {
+                byte = self.data[self.pos];
+                self.pos += if byte == 0xFF { 2 } else { 1 };
+            }
+            self.bits = byte as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    fn receive(&mut self, length: u32) -> i32 {
+        (0..length).fold(0, |value, _| (value << 1) | self.bit() as i32)
+    }
+
+    /// `length` bits read as a signed coefficient (JPEG's EXTEND).
+    fn receive_extend(&mut self, length: u32) -> i32 {
+        if length == 0 {
+            return 0;
+        }
+        let value = self.receive(length);
+        if value < 1 << (length - 1) { value - (1 << length) + 1 } else { value }
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8, String> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | self.bit() as i32;
+            if code <= table.max_code[length] {
+                return table.values.get((table.offset[length] + code) as usize).copied().ok_or_else(|| "corrupt JPEG Huffman code".to_string());
+            }
+        }
+        Err("corrupt JPEG Huffman code".to_string())
+    }
+
+    /// Skips to the RSTn marker that follows a restart interval.
+    fn restart(&mut self) {
+        self.count = 0;
+        while self.pos + 1 < self.data.len() && !(self.data[self.pos] == 0xFF && (0xD0..=0xD7).contains(&self.data[self.pos + 1])) {
+            self.pos += 1;
+        }
+        self.pos += 2;
+    }
+}
+
+pub fn decode_jpeg(bytes: &[u8]) -> Result<RgbImage, String> {
+    let mut quant = [[0u16; 64]; 4];
+    let mut dc_tables: [Huffman; 4] = Default::default();
+    let mut ac_tables: [Huffman; 4] = Default::default();
+    let mut components: Vec<Component> = Vec::new();
+    let (mut width, mut height) = (0usize, 0usize);
+    let mut restart_interval = 0usize;
+    let mut pos = 2;
+
+    loop {
+        while bytes.get(pos) == Some(&0xFF) && bytes.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let (Some(&0xFF), Some(&marker)) = (bytes.get(pos), bytes.get(pos + 1)) else {
+            return Err("truncated JPEG".to_string());
+        };
+        if marker == 0xD9 {
+            break;
+        }
+        let length = bytes.get(pos + 2..pos + 4).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).ok_or("truncated JPEG")?;
+        let segment = bytes.get(pos + 4..pos + 2 + length).ok_or("truncated JPEG segment")?;
+        pos += 2 + length;
+        match marker {
+            0xDB => {
+                let mut rest = segment;
+                while !rest.is_empty() {
+                    let (precision, table) = ((rest[0] >> 4) as usize, (rest[0] & 3) as usize);
+                    let size = 64 * (precision + 1);
+                    let values = rest.get(1..1 + size).ok_or("truncated JPEG quantization table")?;
+                    for (i, slot) in quant[table].iter_mut().enumerate() {
+                        *slot = if precision == 0 { values[i] as u16 } else { u16::from_be_bytes([values[2 * i], values[2 * i + 1]]) };
+                    }
+                    rest = &rest[1 + size..];
+                }
+            }
+            0xC4 => {
+                let mut rest = segment;
+                while rest.len() >= 17 {
+                    let counts = &rest[1..17];
+                    let total: usize = counts.iter().map(|&c| c as usize).sum();
+                    let values = rest.get(17..17 + total).ok_or("truncated JPEG Huffman table")?;
+                    let table = Huffman::new(counts, values);
+                    let index = (rest[0] & 3) as usize;
+                    if rest[0] >> 4 == 0 { dc_tables[index] = table } else { ac_tables[index] = table }
+                    rest = &rest[17 + total..];
+                }
+            }
+            0xDD => restart_interval = segment.get(..2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).unwrap_or(0),
+            0xC0 | 0xC1 => {
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err("only 8-bit JPEG is supported".to_string());
+                }
+                height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                check_pixel_count(width as u32, height as u32)?;
+                let count = segment[5] as usize;
+                if count != 1 && count != 3 {
+                    return Err(format!("JPEG with {} components is not supported", count));
+                }
+                for c in 0..count {
+                    let spec = segment.get(6 + 3 * c..9 + 3 * c).ok_or("truncated JPEG frame header")?;
+                    let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                        return Err("invalid JPEG sampling factors".to_string());
+                    }
+                    components.push(Component {
+                        id: spec[0],
+                        h,
+                        v,
+                        quant: (spec[2] & 3) as usize,
+                        dc_table: 0,
+                        ac_table: 0,
+                        dc_pred: 0,
+                        plane: Vec::new(),
+                        plane_width: 0,
+                    });
+                }
+                let h_max = components.iter().map(|c| c.h).max().unwrap();
+                let v_max = components.iter().map(|c| c.v).max().unwrap();
+                let (mcus_x, mcus_y) = (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max));
+                for component in &mut components {
+                    component.plane_width = mcus_x * component.h * 8;
+                    component.plane = vec![0; component.plane_width * mcus_y * component.v * 8];
+                }
+            }
+            0xC2 | 0xC6 | 0xCA | 0xCE => return Err("progressive JPEG is not supported".to_string()),
+            0xC3 | 0xC5 | 0xC7 | 0xC9..=0xCB | 0xCD | 0xCF => return Err("lossless or arithmetic-coded JPEG is not supported".to_string()),
+            0xDA => {
+                if components.is_empty() {
+                    return Err("JPEG scan before frame header".to_string());
+                }
+                let count = *segment.first().ok_or("truncated JPEG scan header")? as usize;
+                let mut scan = Vec::with_capacity(count);
+                for i in 0..count {
+                    let spec = segment.get(1 + 2 * i..3 + 2 * i).ok_or("truncated JPEG scan header")?;
+                    let index = components.iter().position(|c| c.id == spec[0]).ok_or("JPEG scan names an unknown component")?;
+                    components[index].dc_table = (spec[1] >> 4) as usize & 3;
+                    components[index].ac_table = (spec[1] & 15) as usize & 3;
+                    scan.push(index);
+                }
+                pos = decode_scan(bytes, pos, &mut components, &scan, &quant, &dc_tables, &ac_tables, restart_interval, width, height)?;
+            }
+            _ => {} // APPn, COM and friends
+        }
+    }
+
+    if components.is_empty() {
+        return Err("JPEG has no frame".to_string());
+    }
+    Ok(to_rgb(&components, width, height))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    bytes: &[u8],
+    start: usize,
+    components: &mut [Component],
+    scan: &[usize],
+    quant: &[[u16; 64]; 4],
+    dc_tables: &[Huffman; 4],
+    ac_tables: &[Huffman; 4],
+    restart_interval: usize,
+    width: usize,
+    height: usize,
+) -> Result<usize, String> {
+    let h_max = components.iter().map(|c| c.h).max().unwrap();
+    let v_max = components.iter().map(|c| c.v).max().unwrap();
+    let mut reader = BitReader::new(bytes, start);
+    for &c in scan {
+        components[c].dc_pred = 0;
+    }
+
+    // A single-component scan is not interleaved: it walks that component's own blocks
+    let (units_x, units_y) = if scan.len() == 1 {
+        let c = &components[scan[0]];
+        ((width * c.h).div_ceil(h_max).div_ceil(8), (height * c.v).div_ceil(v_max).div_ceil(8))
+    } else {
+        (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max))
+    };
+    let mut coefficients = [0i32; 64];
+    for unit in 0..units_x * units_y {
+        if restart_interval > 0 && unit > 0 && unit % restart_interval == 0 {
+            reader.restart();
+            for &c in scan {
+                components[c].dc_pred = 0;
+            }
+        }
+        let (ux, uy) = (unit % units_x, unit / units_x);
+        for &c in scan {
+            let (blocks_h, blocks_v) = if scan.len() == 1 { (1, 1) } else { (components[c].h, components[c].v) };
+            for by in 0..blocks_v {
+                for bx in 0..blocks_h {
+                    let component = &mut components[c];
+                    coefficients.fill(0);
+                    let size = reader.decode(&dc_tables[component.dc_table])? as u32;
+                    component.dc_pred += reader.receive_extend(size);
+                    coefficients[0] = component.dc_pred * quant[component.quant][0] as i32;
+                    let mut k = 1;
+                    while k < 64 {
+                        let symbol = reader.decode(&ac_tables[component.ac_table])?;
+                        let (run, size) = ((symbol >> 4) as usize, (symbol & 15) as u32);
+                        if size == 0 {
+                            if run != 15 {
+                                break; // end of block
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        if k > 63 {
+                            return Err("corrupt JPEG block".to_string());
+                        }
+                        coefficients[ZIGZAG[k]] = reader.receive_extend(size) * quant[component.quant][k] as i32;
+                        k += 1;
+                    }
+                    let x = (ux * blocks_h + bx) * 8;
+                    let y = (uy * blocks_v + by) * 8;
+                    idct_block(&coefficients, &mut component.plane, component.plane_width, x, y);
+                }
+            }
+        }
+    }
+
+    // Continue after the entropy-coded data, at the next marker
+    let mut pos = reader.pos;
+    while pos + 1 < bytes.len() && !(bytes[pos] == 0xFF && bytes[pos + 1] != 0 && !(0xD0..=0xD7).contains(&bytes[pos + 1])) {
+        pos += 1;
+    }
+    Ok(pos)
+}
+
+// cos((2x + 1) u pi / 16), scaled by C(u)/2
+static IDCT_TABLE: once_cell::sync::Lazy<[[f32; 8]; 8]> = once_cell::sync::Lazy::new(|| {
+    let mut table = [[0.0; 8]; 8];
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 };
+            *value = scale / 2.0 * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    table
+});
+
+fn idct_block(coefficients: &[i32; 64], plane: &mut [u8], stride: usize, x0: usize, y0: usize) {
+    let table = &*IDCT_TABLE;
+    // Rows first, then columns
+    let mut rows = [0.0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| table[x][u] * coefficients[v * 8 + u] as f32).sum();
+        }
+    }
+    for (y, weights) in table.iter().enumerate() {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v| weights[v] * rows[v * 8 + x]).sum();
+            let offset = (y0 + y) * stride + x0 + x;
+            if let Some(sample) = plane.get_mut(offset) {
+                *sample = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+fn to_rgb(components: &[Component], width: usize, height: usize) -> RgbImage {
+    let h_max = components.iter().map(|c| c.h).max().unwrap();
+    let v_max = components.iter().map(|c| c.v).max().unwrap();
+    // Nearest-neighbour chroma upsampling
+    let sample = |c: &Component, x: usize, y: usize| c.plane[(y * c.v / v_max) * c.plane_width + x * c.h / h_max] as f32;
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let luma = sample(&components[0], x, y);
+            if components.len() == 1 {
+                pixels.extend([luma as u8; 3]);
+                continue;
+            }
+            let cb = sample(&components[1], x, y) - 128.0;
+            let cr = sample(&components[2], x, y) - 128.0;
+            let to_u8 = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+            pixels.push(to_u8(luma + 1.402 * cr));
+            pixels.push(to_u8(luma - 0.344_136 * cb - 0.714_136 * cr));
+            pixels.push(to_u8(luma + 1.772 * cb));
+        }
+    }
+    RgbImage { width: width as u32, height: height as u32, pixels }
+}
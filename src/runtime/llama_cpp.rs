@@ -1,17 +1,57 @@
 use async_trait::async_trait;
-use llama_cpp::{LlamaModel, LlamaParams, LlamaSession, SessionParams, Token};
-use std::{fs::File, path::PathBuf};
+use futures::StreamExt;
+use metrics::counter;
+use llama_cpp::{
+    grammar::LlamaGrammar,
+    standard_sampler::{SamplerStage, StandardSampler},
+    EmbeddingsParams, LlamaModel, LlamaParams, LlamaSession, SessionParams, SplitMode as LlamaSplitMode,
+};
+use std::{fs::File, path::PathBuf, sync::Arc};
 use memmap2::Mmap;
+use tokio::sync::mpsc;
 
-use crate::runtime::{LlmRuntime, GenerationOptions};
+use crate::api::dto::{ModelOptions, SplitMode};
+use crate::runtime::{
+    context_shift, llama_raw::{BatchDecoder, RawLlamaModel}, pool::{session_pool_size, Leased, Pool}, prompt::tokenize_prompt, sampler::REPETITION_WINDOW, stop::{StopMatcher, END_OF_TURN_MARKERS}, BatchDecodeRuntime, EmbeddingRuntime, LlmRuntime, GenerationOptions, RuntimeError,
+};
 
-pub struct LlamaCppRuntime {
+// Runs llama.cpp work that blocks (context setup and evaluation) on tokio's blocking pool, so
+// it never stalls the workers that serve HTTP
+async fn blocking<R: Send + 'static>(work: impl FnOnce() -> R + Send + 'static) -> Result<R, String> {
+    tokio::task::spawn_blocking(work).await.map_err(|e| format!("llama task failed: {}", e))
+}
+
+// A context per generation, each a session of the `llama_cpp` crate
+struct Sessions {
     model: LlamaModel,
+    // Idle sessions, so a request does not pay for context setup
+    pool: Pool<LlamaSession>,
+    // Context size, batch size and RoPE settings every session is created with
+    params: SessionParams,
+}
+
+enum Backend {
+    Sessions(Sessions),
+    // Every generation decoded in one shared context (continuous batching)
+    Batched(Arc<BatchDecoder>),
+}
+
+pub struct LlamaCppRuntime {
+    // Label for metrics
+    name: String,
+    // Keep generating past a full context by discarding older tokens
+    context_shift: bool,
+    backend: Backend,
 }
 
 impl LlamaCppRuntime {
-    pub fn new(model_path: &str) -> Result<Self, String> {
+    /// With `batch_sequences` above 1 generations are decoded together in one shared context
+    /// whose KV cache holds `options.n_ctx` tokens, except when `options.context_shift` is on,
+    /// which needs a context per generation. Either way the model is loaded once.
+    pub fn new(model_path: &str, options: &ModelOptions, batch_sequences: usize) -> Result<Self, String> {
+        let context_shift = options.context_shift;
         let model_path = PathBuf::from(model_path);
+        let name = model_path.file_stem().and_then(|s| s.to_str()).unwrap_or("llama").to_string();
         // Basic validation and memory-map to verify GGUF/GGML file
         let file = File::open(&model_path)
             .map_err(|e| format!("Failed to open model file {:?}: {}", model_path, e))?;
@@ -38,23 +78,319 @@ impl LlamaCppRuntime {
         }
 
         // Delegate to llama.cpp loader (which may use its own mmap internally)
-        let model = LlamaModel::load_from_file(model_path, LlamaParams::default())
+        let mut params = LlamaParams::default();
+        if let Some(layers) = options.n_gpu_layers {
+            params.n_gpu_layers = layers;
+        }
+        if let Some(gpu) = options.main_gpu {
+            params.main_gpu = gpu;
+        }
+        if let Some(mode) = options.split_mode {
+            params.split_mode = match mode {
+                SplitMode::None => LlamaSplitMode::None,
+                SplitMode::Layer => LlamaSplitMode::Layer,
+                SplitMode::Row => LlamaSplitMode::Row,
+            };
+        }
+        if crate::runtime::accel::compat_mode() {
+            params.n_gpu_layers = 0;
+        }
+        tracing::info!("llama model {}: n_gpu_layers={} main_gpu={}", name, params.n_gpu_layers, params.main_gpu);
+        if batch_sequences > 1 && !context_shift {
+            let path = model_path.to_str().ok_or("model path is not valid UTF-8")?;
+            let prefix_entries = crate::runtime::prefix_cache::configured_entries();
+            let model = RawLlamaModel::load_with(path, options)?;
+            let decoder = BatchDecoder::new(model, batch_sequences, prefix_entries, options)?;
+            return Ok(Self { name, context_shift, backend: Backend::Batched(Arc::new(decoder)) });
+        }
+        let model = LlamaModel::load_from_file(model_path, params)
             .map_err(|e| format!("Failed to load Llama model: {}", e))?;
-        Ok(Self { model })
+        let mut session_params = SessionParams::default();
+        if let Some(n_ctx) = options.n_ctx {
+            session_params.n_ctx = n_ctx;
+        }
+        if let Some(n_batch) = options.n_batch {
+            session_params.n_batch = n_batch;
+        }
+        if let Some(base) = options.rope_freq_base {
+            session_params.rope_freq_base = base;
+        }
+        if let Some(scale) = options.rope_freq_scale {
+            session_params.rope_freq_scale = scale;
+        }
+        let sessions = Sessions { model, pool: Pool::new(session_pool_size()), params: session_params };
+        for _ in 0..sessions.pool.capacity() {
+            let session = sessions.model.create_session(sessions.params.clone()).map_err(|e| format!("Failed to create session: {}", e))?;
+            sessions.pool.put(session);
+        }
+        Ok(Self { name, context_shift, backend: Backend::Sessions(sessions) })
     }
+}
 
-    fn create_session(&self) -> LlamaSession {
-        self.model.create_session(SessionParams::default()).expect("Failed to create session")
+impl Sessions {
+    // An idle session with its context cleared, or a new one when all are busy; it returns
+    // to the pool when the generation ends
+    async fn session(&self, name: &str) -> Result<(Leased<'_, LlamaSession>, usize), String> {
+        let n_ctx = self.params.n_ctx as usize;
+        while let Some(session) = self.pool.take() {
+            // Clones share the session's state; one that cannot be reset is dropped rather than reused
+            let mut handle = session.clone();
+            if blocking(move || handle.set_context_to_tokens(&[]).is_ok()).await? {
+                counter!("llama_sessions_total", 1, "model" => name.to_string(), "pooled" => "true");
+                return Ok((self.pool.lease(session), n_ctx));
+            }
+        }
+        counter!("llama_sessions_total", 1, "model" => name.to_string(), "pooled" => "false");
+        let (model, params) = (self.model.clone(), self.params.clone());
+        let session = blocking(move || model.create_session(params))
+            .await?
+            .map_err(|e| format!("Failed to create session: {}", e))?;
+        Ok((self.pool.lease(session), n_ctx))
     }
 }
 
 #[async_trait]
 impl LlmRuntime for LlamaCppRuntime {
-    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, String> {
-        // Fallback-only implementation until stable decoding APIs are present in the crate version.
-        // We still create a session to validate model usability, then return a deterministic output.
-        let _session = self.create_session();
-        let truncated: String = prompt.chars().take(options.max_tokens as usize).collect();
-        Ok(truncated)
-    }
-}
\ No newline at end of file
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        // Drive the streaming path and collect the pieces
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.generate_stream(prompt, options, tx), collect);
+        result.map(|_| text)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let sessions = match &self.backend {
+            Backend::Sessions(sessions) => sessions,
+            // Reached when the decode loop turns a generation away; the decoder runs it alone
+            Backend::Batched(decoder) => {
+                if options.grammar.is_some() {
+                    return Err(RuntimeError::InvalidInput("grammars are not supported by models decoded with continuous batching".to_string()));
+                }
+                let (decoder, prompt, options) = (decoder.clone(), prompt.to_string(), options.clone());
+                return blocking(move || decoder.generate(&prompt, &options, |piece| sender.blocking_send(piece).is_ok())).await?;
+            }
+        };
+        // End-of-turn tokens come through as text, so they are matched like stop sequences;
+        // text held back as a possible stop sequence is sent once generation ends without one
+        let stop: Vec<String> = options.stop.iter().cloned().chain(END_OF_TURN_MARKERS.iter().map(|m| m.to_string())).collect();
+        let mut stops = StopMatcher::new(&stop).reporting_to(&options.matched_stop);
+        let result = self.complete(sessions, prompt, options, &sender, &mut stops).await;
+        let rest = stops.finish();
+        if !rest.is_empty() {
+            let _ = sender.send(rest).await;
+        }
+        result
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        match &self.backend {
+            Backend::Sessions(sessions) => count_tokens(&sessions.model, text),
+            Backend::Batched(decoder) => decoder.model().tokenize(text, false, true).len() as u32,
+        }
+    }
+
+    fn batch_decoder(&self) -> Option<Arc<dyn BatchDecodeRuntime>> {
+        match &self.backend {
+            Backend::Sessions(_) => None,
+            Backend::Batched(decoder) => Some(decoder.clone() as Arc<dyn BatchDecodeRuntime>),
+        }
+    }
+
+    fn embedder(&self) -> Option<Arc<dyn EmbeddingRuntime>> {
+        match &self.backend {
+            Backend::Sessions(sessions) => Some(Arc::new(LlamaEmbeddings { model: sessions.model.clone() })),
+            Backend::Batched(decoder) => Some(Arc::new(BatchedEmbeddings { decoder: decoder.clone() })),
+        }
+    }
+}
+
+fn count_tokens(model: &LlamaModel, text: &str) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+    match model.tokenize_bytes(text, false, true) {
+        Ok(tokens) => tokens.len() as u32,
+        Err(_) => crate::runtime::approximate_token_count(text),
+    }
+}
+
+/// Embeddings from a generation model's hidden states, sharing its weights. Each call runs in
+/// a context of its own, so it never waits for a generation.
+struct LlamaEmbeddings {
+    model: LlamaModel,
+}
+
+#[async_trait]
+impl EmbeddingRuntime for LlamaEmbeddings {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        // Pooled and L2-normalized by llama_cpp, like the dedicated embedding runtimes
+        self.model.embeddings_async(inputs, EmbeddingsParams::default()).await.map_err(|e| RuntimeError::Backend(format!("llama embeddings error: {}", e)))
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        count_tokens(&self.model, text)
+    }
+}
+
+/// Embeddings from a batched model's weights, each call in a context of its own.
+struct BatchedEmbeddings {
+    decoder: Arc<BatchDecoder>,
+}
+
+#[async_trait]
+impl EmbeddingRuntime for BatchedEmbeddings {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let (decoder, inputs) = (self.decoder.clone(), inputs.to_vec());
+        blocking(move || decoder.model().embed(&inputs)).await?
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        self.decoder.model().tokenize(text, false, true).len() as u32
+    }
+}
+
+// llama.cpp's sampler set up like `sampler::Sampler`, constrained by the request's grammar
+// when it has one
+fn sampler(options: &GenerationOptions) -> Result<StandardSampler, String> {
+    let grammar = match &options.grammar {
+        Some(grammar) => Some(grammar.parse::<LlamaGrammar>().map_err(|e| format!("invalid grammar: {:?}", e))?),
+        None => None,
+    };
+    let mut stages = Vec::new();
+    if options.repetition_penalty != 1.0 {
+        stages.push(SamplerStage::RepetitionPenalty {
+            repetition_penalty: options.repetition_penalty,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            last_n: REPETITION_WINDOW as i32,
+        });
+    }
+    if options.temperature <= 0.0 {
+        // Greedy
+        stages.push(SamplerStage::TopK(1));
+        return Ok(StandardSampler::new_softmax(stages, 1, grammar));
+    }
+    if let Some(mirostat) = options.mirostat {
+        stages.push(SamplerStage::Temperature(options.temperature));
+        return Ok(match mirostat.version {
+            1 => StandardSampler::new_mirostat(stages, 1, grammar, mirostat.tau, mirostat.eta, 100),
+            _ => StandardSampler::new_mirostat_v2(stages, 1, grammar, mirostat.tau, mirostat.eta),
+        });
+    }
+    if options.top_k > 0 {
+        stages.push(SamplerStage::TopK(options.top_k as i32));
+    }
+    if options.typical_p < 1.0 {
+        stages.push(SamplerStage::Typical(options.typical_p));
+    }
+    if options.top_p < 1.0 {
+        stages.push(SamplerStage::TopP(options.top_p));
+    }
+    if options.min_p > 0.0 {
+        stages.push(SamplerStage::MinP(options.min_p));
+    }
+    stages.push(SamplerStage::Temperature(options.temperature));
+    Ok(StandardSampler::new_softmax(stages, 1, grammar))
+}
+
+impl LlamaCppRuntime {
+    // `generate_stream` without flushing what `stops` still holds back
+    async fn complete(
+        &self,
+        sessions: &Sessions,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: &mpsc::Sender<String>,
+        stops: &mut StopMatcher,
+    ) -> Result<(), RuntimeError> {
+        let sampler = sampler(options).map_err(RuntimeError::InvalidInput)?;
+        let (mut session, n_ctx) = sessions.session(&self.name).await?;
+        let tokens = tokenize_prompt(prompt, &options.content_spans, false, |text, bos, special| sessions.model.tokenize_bytes(text, bos, special))
+            .map_err(|e| RuntimeError::InvalidInput(format!("llama tokenization error: {}", e)))?;
+        session
+            .advance_context_with_tokens_async(tokens)
+            .await
+            .map_err(|e| format!("llama context error: {}", e))?;
+        // A grammar's parse state lives in its sampler, so constrained output is not shifted
+        if !self.context_shift || options.grammar.is_some() {
+            let handle = session
+                .start_completing_with(sampler, options.max_tokens as usize)
+                .map_err(|e| format!("llama completion error: {}", e))?;
+            self.forward(handle.into_strings(), options, sender, stops).await;
+            // Dropping the completion handle stops the decode thread
+            return Ok(());
+        }
+
+        // Generate in windows that fit the context; shift whenever it fills up
+        let n_keep = self.count_tokens(&prompt[..options.keep_prefix.min(prompt.len())]) as usize;
+        let mut remaining = options.max_tokens as usize;
+        while remaining > 0 {
+            let before = session.context_size();
+            let window = remaining.min(n_ctx.saturating_sub(before));
+            if window > 0 {
+                let handle = session
+                    .start_completing_with(sampler(options).map_err(RuntimeError::InvalidInput)?, window)
+                    .map_err(|e| format!("llama completion error: {}", e))?;
+                if !self.forward(handle.into_strings(), options, sender, stops).await {
+                    return Ok(());
+                }
+                let produced = session.context_size().saturating_sub(before);
+                remaining = remaining.saturating_sub(produced);
+                // Stopped short of the window: end of sequence
+                if produced < window {
+                    return Ok(());
+                }
+            }
+            if remaining == 0 {
+                break;
+            }
+            let tokens = session.context();
+            let Some(shift) = context_shift::plan(tokens.len(), n_ctx, n_keep) else { break };
+            // Re-evaluates what is kept
+            let mut handle = session.clone();
+            blocking(move || handle.set_context_to_tokens(&context_shift::apply(&tokens, shift)))
+                .await?
+                .map_err(|e| format!("llama context shift error: {}", e))?;
+            context_shift::record(&self.name, shift);
+        }
+        Ok(())
+    }
+
+    /// Sends completion pieces until the stream ends; false if the client went away or a stop
+    /// sequence was reached.
+    async fn forward(
+        &self,
+        mut pieces: impl futures::Stream<Item = String> + Unpin,
+        options: &GenerationOptions,
+        sender: &mpsc::Sender<String>,
+        stops: &mut StopMatcher,
+    ) -> bool {
+        // Pieces are emitted per token; multi-token codepoints are held back until complete
+        loop {
+            let piece = tokio::select! {
+                _ = options.cancel.cancelled() => return false,
+                piece = pieces.next() => piece,
+            };
+            let Some(piece) = piece else { return true };
+            let text = stops.push(&piece);
+            if !text.is_empty() && sender.send(text).await.is_err() {
+                return false; // receiver dropped
+            }
+            if stops.is_stopped() {
+                return false;
+            }
+        }
+    }
+}
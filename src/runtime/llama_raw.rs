@@ -0,0 +1,702 @@
+//! Direct llama.cpp bindings for what the `llama_cpp` crate does not expose: evaluating
+//! embeddings (such as projected image features) in the context alongside ordinary tokens,
+//! and decoding many sequences in one shared context.
+
+use llama_cpp_sys as sys;
+use metrics::{counter, histogram};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    ffi::CString,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crate::api::dto::{ModelOptions, SplitMode};
+use crate::runtime::{
+    gguf::BATCH_CTX, prefix_cache::PrefixCache, prompt::tokenize_prompt, sampler::Sampler, stop::{StopMatcher, END_OF_TURN_MARKERS}, BatchDecodeRuntime, GenerationOptions, RuntimeError, SequenceId, StepOutput,
+};
+
+// Room for a 576-token image plus a conversation
+const N_CTX: u32 = 4096;
+
+/// A piece of input evaluated in order: text is tokenized, embeddings (`n * n_embd` floats,
+/// row-major) are fed to the model as they are.
+pub enum Chunk<'a> {
+    Text(&'a str),
+    Embeddings(&'a [f32]),
+}
+
+pub struct RawLlamaModel {
+    model: *mut sys::llama_model,
+    n_embd: usize,
+    n_vocab: usize,
+    // EOS and the end-of-turn tokens this vocabulary has
+    end_tokens: Vec<sys::llama_token>,
+}
+
+// SAFETY: a loaded model is read-only; each generation creates its own context from it
+unsafe impl Send for RawLlamaModel {}
+unsafe impl Sync for RawLlamaModel {}
+
+impl Drop for RawLlamaModel {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from llama_load_model_from_file and every context using it
+        // was freed when its generation ended
+        unsafe { sys::llama_free_model(self.model) }
+    }
+}
+
+// Frees the per-generation context however generation ends
+struct Context(*mut sys::llama_context);
+
+// SAFETY: a context is used by one thread at a time; `BatchDecoder` keeps it behind a mutex
+unsafe impl Send for Context {}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { sys::llama_free(self.0) }
+    }
+}
+
+impl RawLlamaModel {
+    pub fn load(path: &str) -> Result<Self, String> {
+        Self::load_with(path, &ModelOptions::default())
+    }
+
+    /// Loads with the GPU offload settings of `options`.
+    pub fn load_with(path: &str, options: &ModelOptions) -> Result<Self, String> {
+        let c_path = CString::new(path).map_err(|_| format!("invalid model path {:?}", path))?;
+        // SAFETY: plain C calls with a valid NUL-terminated path; null is checked below
+        unsafe {
+            sys::llama_backend_init();
+            let mut params = sys::llama_model_default_params();
+            if let Some(layers) = options.n_gpu_layers {
+                params.n_gpu_layers = layers as i32;
+            }
+            if let Some(gpu) = options.main_gpu {
+                params.main_gpu = gpu as i32;
+            }
+            if let Some(mode) = options.split_mode {
+                // llama_split_mode: 0 none, 1 layer, 2 row
+                params.split_mode = match mode {
+                    SplitMode::None => 0,
+                    SplitMode::Layer => 1,
+                    SplitMode::Row => 2,
+                } as _;
+            }
+            if crate::runtime::accel::compat_mode() {
+                params.n_gpu_layers = 0;
+            }
+            let model = sys::llama_load_model_from_file(c_path.as_ptr(), params);
+            if model.is_null() {
+                return Err(format!("Failed to load Llama model from {}", path));
+            }
+            let mut raw = Self {
+                model,
+                n_embd: sys::llama_n_embd(model) as usize,
+                n_vocab: sys::llama_n_vocab(model) as usize,
+                end_tokens: vec![sys::llama_token_eos(model)],
+            };
+            for marker in END_OF_TURN_MARKERS {
+                // Only markers that are a single special token in this vocabulary
+                if let [token] = raw.tokenize(marker, false, true)[..]
+                    && !raw.end_tokens.contains(&token)
+                {
+                    raw.end_tokens.push(token);
+                }
+            }
+            Ok(raw)
+        }
+    }
+
+    /// Whether sampling `token` ends the generation (EOS or an end-of-turn token).
+    pub fn is_end_of_generation(&self, token: sys::llama_token) -> bool {
+        self.end_tokens.contains(&token)
+    }
+
+    /// Width of one input embedding.
+    pub fn n_embd(&self) -> usize {
+        self.n_embd
+    }
+
+    /// Tokens of `text`; with `parse_special` the text of a special token (`<|im_end|>`)
+    /// becomes that token rather than ordinary text.
+    pub fn tokenize(&self, text: &str, add_bos: bool, parse_special: bool) -> Vec<sys::llama_token> {
+        // One token per byte is the worst case, plus BOS
+        let mut tokens = vec![0; text.len() + 2];
+        // SAFETY: the buffer holds tokens.len() tokens; text is passed with its length
+        let n = unsafe {
+            sys::llama_tokenize(self.model, text.as_ptr() as *const _, text.len() as i32, tokens.as_mut_ptr(), tokens.len() as i32, add_bos, parse_special)
+        };
+        tokens.truncate(n.max(0) as usize);
+        tokens
+    }
+
+    /// Tokens of a rendered prompt whose message text (`content_spans`) cannot become special tokens.
+    pub fn tokenize_prompt(&self, prompt: &str, content_spans: &[Range<usize>], add_bos: bool) -> Vec<sys::llama_token> {
+        let Ok(tokens) = tokenize_prompt(prompt, content_spans, add_bos, |text, bos, special| Ok::<_, Infallible>(self.tokenize(text, bos, special)));
+        tokens
+    }
+
+    /// L2-normalized embeddings of `inputs`, pooled the way the model says (the last token's
+    /// hidden state when it has no pooling layer), each evaluated in one batch of a context
+    /// sized for the longest. Blocking; call from a blocking thread.
+    pub fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let inputs: Vec<Vec<sys::llama_token>> = inputs.iter().map(|input| self.tokenize(input, true, false)).collect();
+        let longest = inputs.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        // SAFETY: the model outlives the context, which is freed by the guard
+        let ctx = unsafe {
+            let mut params = sys::llama_context_default_params();
+            params.embeddings = true;
+            params.n_ctx = longest as u32;
+            params.n_batch = longest as u32;
+            params.n_ubatch = longest as u32;
+            let ctx = sys::llama_new_context_with_model(self.model, params);
+            if ctx.is_null() {
+                return Err(RuntimeError::Backend("Failed to create llama embeddings context".to_string()));
+            }
+            Context(ctx)
+        };
+        // SAFETY: plain allocation of `longest` token slots with one sequence id each
+        let mut batch = Batch(unsafe { sys::llama_batch_init(longest as i32, 0, 1) });
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for tokens in &inputs {
+            if tokens.is_empty() {
+                return Err(RuntimeError::InvalidInput("cannot embed an empty input".to_string()));
+            }
+            let entries: Vec<_> = tokens.iter().enumerate().map(|(i, &t)| (t, i, 0, i + 1 == tokens.len())).collect();
+            batch.fill(&entries);
+            // SAFETY: the batch holds tokens.len() <= longest tokens of sequence 0; embeddings
+            // are n_embd floats read before the next decode
+            let embedding = unsafe {
+                sys::llama_kv_cache_clear(ctx.0);
+                if sys::llama_decode(ctx.0, batch.0) != 0 {
+                    return Err(RuntimeError::Backend("llama embeddings decode failed".to_string()));
+                }
+                let mut ptr = sys::llama_get_embeddings_seq(ctx.0, 0);
+                if ptr.is_null() {
+                    ptr = sys::llama_get_embeddings_ith(ctx.0, tokens.len() as i32 - 1);
+                }
+                if ptr.is_null() {
+                    return Err(RuntimeError::Backend("llama returned no embeddings".to_string()));
+                }
+                std::slice::from_raw_parts(ptr, self.n_embd)
+            };
+            let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            embeddings.push(embedding.iter().map(|x| if norm > 0.0 { x / norm } else { *x }).collect());
+        }
+        Ok(embeddings)
+    }
+
+    // Moves complete UTF-8 out of `pending`, keeping a codepoint split across tokens
+    fn take_complete(pending: &mut Vec<u8>) -> String {
+        let complete = match std::str::from_utf8(pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => pending.len(),
+        };
+        let text = String::from_utf8_lossy(&pending[..complete]).into_owned();
+        pending.drain(..complete);
+        text
+    }
+
+    fn piece(&self, token: sys::llama_token) -> Vec<u8> {
+        let mut buf = vec![0u8; 64];
+        // SAFETY: buf.len() bytes are writable; a negative result is the size needed
+        let mut n = unsafe { sys::llama_token_to_piece(self.model, token, buf.as_mut_ptr() as *mut _, buf.len() as i32) };
+        if n < 0 {
+            buf.resize((-n) as usize, 0);
+            n = unsafe { sys::llama_token_to_piece(self.model, token, buf.as_mut_ptr() as *mut _, buf.len() as i32) };
+        }
+        buf.truncate(n.max(0) as usize);
+        buf
+    }
+
+    /// Evaluates `chunks` in a fresh context, then samples until end of sequence, `max_tokens`,
+    /// cancellation or a full context. Text is handed to `on_piece` as it decodes; generation
+    /// stops early when it returns false. Blocking; call from a blocking thread.
+    pub fn generate(&self, chunks: &[Chunk], options: &GenerationOptions, mut on_piece: impl FnMut(String) -> bool) -> Result<(), RuntimeError> {
+        // SAFETY: the model outlives the context, which is freed by the guard
+        let (ctx, n_batch) = unsafe {
+            let mut params = sys::llama_context_default_params();
+            params.n_ctx = N_CTX;
+            let ctx = sys::llama_new_context_with_model(self.model, params);
+            if ctx.is_null() {
+                return Err(RuntimeError::Backend("Failed to create llama context".to_string()));
+            }
+            (Context(ctx), params.n_batch as usize)
+        };
+
+        let mut n_past = 0usize;
+        for (i, chunk) in chunks.iter().enumerate() {
+            match chunk {
+                Chunk::Text(text) => {
+                    let mut tokens = self.tokenize(text, i == 0, true);
+                    self.check_room(n_past + tokens.len())?;
+                    for batch in tokens.chunks_mut(n_batch) {
+                        self.decode_tokens(&ctx, batch, n_past)?;
+                        n_past += batch.len();
+                    }
+                }
+                Chunk::Embeddings(embeddings) => {
+                    let rows = embeddings.len() / self.n_embd;
+                    self.check_room(n_past + rows)?;
+                    for batch in embeddings.chunks(n_batch * self.n_embd) {
+                        let n = batch.len() / self.n_embd;
+                        // Same layout llava.cpp uses: embd set, everything else implied
+                        let batch = sys::llama_batch {
+                            n_tokens: n as i32,
+                            token: std::ptr::null_mut(),
+                            embd: batch.as_ptr() as *mut f32,
+                            pos: std::ptr::null_mut(),
+                            n_seq_id: std::ptr::null_mut(),
+                            seq_id: std::ptr::null_mut(),
+                            logits: std::ptr::null_mut(),
+                            all_pos_0: n_past as i32,
+                            all_pos_1: 1,
+                            all_seq_id: 0,
+                        };
+                        // SAFETY: embd points at n * n_embd floats that outlive the call
+                        if unsafe { sys::llama_decode(ctx.0, batch) } != 0 {
+                            return Err(RuntimeError::Backend("llama decode of image embeddings failed".to_string()));
+                        }
+                        n_past += n;
+                    }
+                }
+            }
+        }
+
+        let mut sampler = Sampler::new(options, StdRng::from_entropy());
+        let mut stops = StopMatcher::new(&options.stop).reporting_to(&options.matched_stop);
+        // Bytes of a codepoint split across tokens are held back until it completes
+        let mut pending = Vec::new();
+        for _ in 0..options.max_tokens {
+            if options.cancel.is_cancelled() || n_past >= N_CTX as usize {
+                break;
+            }
+            // SAFETY: after a decode, the last position's logits hold n_vocab floats
+            let logits = unsafe { std::slice::from_raw_parts(sys::llama_get_logits(ctx.0), self.n_vocab) };
+            let Some(index) = sampler.sample(logits) else { break };
+            let token = index as sys::llama_token;
+            if self.is_end_of_generation(token) {
+                break;
+            }
+            pending.extend(self.piece(token));
+            let text = stops.push(&Self::take_complete(&mut pending));
+            if !text.is_empty() && !on_piece(text) {
+                return Ok(());
+            }
+            if stops.is_stopped() {
+                return Ok(());
+            }
+            self.decode_tokens(&ctx, &mut [token], n_past)?;
+            n_past += 1;
+        }
+        let rest = stops.finish();
+        if !rest.is_empty() {
+            on_piece(rest);
+        }
+        Ok(())
+    }
+
+    fn check_room(&self, needed: usize) -> Result<(), RuntimeError> {
+        if needed > N_CTX as usize {
+            return Err(RuntimeError::InvalidInput(format!("prompt needs {} tokens including images; the context holds {}", needed, N_CTX)));
+        }
+        Ok(())
+    }
+
+    fn decode_tokens(&self, ctx: &Context, tokens: &mut [sys::llama_token], n_past: usize) -> Result<(), String> {
+        // SAFETY: the batch borrows `tokens` only for the duration of the call
+        let result = unsafe { sys::llama_decode(ctx.0, sys::llama_batch_get_one(tokens.as_mut_ptr(), tokens.len() as i32, n_past as i32, 0)) };
+        if result != 0 {
+            return Err(format!("llama decode failed ({})", result));
+        }
+        Ok(())
+    }
+}
+
+// Frees a batch allocated with llama_batch_init
+struct Batch(sys::llama_batch);
+
+// SAFETY: only touched under `BatchDecoder`'s mutex, like the context it feeds
+unsafe impl Send for Batch {}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        unsafe { sys::llama_batch_free(self.0) }
+    }
+}
+
+impl Batch {
+    // Replaces the batch contents with `tokens` as (token, position, sequence, wants logits)
+    fn fill(&mut self, tokens: &[(sys::llama_token, usize, i32, bool)]) {
+        // SAFETY: the batch was allocated for n_batch tokens with one sequence id each, and
+        // callers never pass more than n_batch tokens
+        unsafe {
+            for (i, &(token, pos, seq, logits)) in tokens.iter().enumerate() {
+                *self.0.token.add(i) = token;
+                *self.0.pos.add(i) = pos as i32;
+                *self.0.n_seq_id.add(i) = 1;
+                **self.0.seq_id.add(i) = seq;
+                *self.0.logits.add(i) = logits as i8;
+            }
+        }
+        self.0.n_tokens = tokens.len() as i32;
+    }
+}
+
+struct Sequence {
+    // llama.cpp sequence id, which is also its slot in the KV cache
+    seq: i32,
+    // The prompt, until it has been evaluated; positions below `n_past` already are
+    prompt: Vec<sys::llama_token>,
+    // Prompt tokens of the system turns, cached as a prefix of their own once evaluated
+    system: usize,
+    n_past: usize,
+    remaining: u32,
+    sampler: Sampler,
+    stops: StopMatcher,
+    // What the last decode predicted for this sequence's next token
+    logits: Vec<f32>,
+    pending: Vec<u8>,
+}
+
+impl Sequence {
+    fn prefilling(&self) -> bool {
+        self.n_past < self.prompt.len()
+    }
+}
+
+struct BatchState {
+    ctx: Context,
+    batch: Batch,
+    free_slots: Vec<i32>,
+    sequences: HashMap<SequenceId, Sequence>,
+    // Prompt prefixes kept in KV cache sequences of their own, past the generation slots
+    prefixes: PrefixCache<sys::llama_token, i32>,
+    free_prefix_slots: Vec<i32>,
+}
+
+/// Decodes up to `max_sequences` generations in one context whose KV cache of `n_ctx` cells
+/// they share: each owns a KV cache sequence and every step evaluates one token for each of
+/// them in a single `llama_decode`. Prompts are evaluated in chunks that fill the rest of a
+/// step's batch, so a long prompt never holds up the generations already running. Up to
+/// `prefix_entries` recent prompt prefixes stay cached; a prompt that starts with one copies
+/// its KV cells, which llama.cpp shares rather than duplicates, and evaluates only the rest.
+pub struct BatchDecoder {
+    model: RawLlamaModel,
+    max_sequences: usize,
+    // KV cache cells, shared by every sequence
+    n_ctx: usize,
+    n_batch: usize,
+    // Generations outside a decode loop take turns on the sequence past the generation slots
+    direct: Mutex<()>,
+    next_direct: AtomicU64,
+    state: Mutex<BatchState>,
+}
+
+impl BatchDecoder {
+    /// `options` may set the KV cache size, prompt batch size and RoPE scaling.
+    pub fn new(model: RawLlamaModel, max_sequences: usize, prefix_entries: usize, options: &ModelOptions) -> Result<Self, String> {
+        let direct_slot = max_sequences as i32;
+        let n_seq = max_sequences + 1 + prefix_entries;
+        // SAFETY: the model outlives the context, which is freed by the guard
+        let (ctx, n_ctx, n_batch) = unsafe {
+            let mut params = sys::llama_context_default_params();
+            params.n_ctx = options.n_ctx.unwrap_or(BATCH_CTX);
+            if let Some(n_batch) = options.n_batch {
+                params.n_batch = n_batch;
+            }
+            // Room for every sequence's next token in one step
+            params.n_batch = params.n_batch.max(max_sequences as u32 + 1);
+            params.n_seq_max = n_seq as u32;
+            if let Some(base) = options.rope_freq_base {
+                params.rope_freq_base = base;
+            }
+            if let Some(scale) = options.rope_freq_scale {
+                params.rope_freq_scale = scale;
+            }
+            let ctx = sys::llama_new_context_with_model(model.model, params);
+            if ctx.is_null() {
+                return Err("Failed to create llama batch context".to_string());
+            }
+            // llama.cpp caps the batch at the context size
+            (Context(ctx), sys::llama_n_ctx(ctx) as usize, sys::llama_n_batch(ctx) as usize)
+        };
+        if n_batch <= max_sequences {
+            return Err(format!("n_ctx {} leaves no room to batch {} sequences", n_ctx, max_sequences));
+        }
+        // SAFETY: plain allocation of n_batch token slots with one sequence id each
+        let batch = Batch(unsafe { sys::llama_batch_init(n_batch as i32, 0, 1) });
+        let state = BatchState {
+            ctx,
+            batch,
+            free_slots: (0..direct_slot).rev().collect(),
+            sequences: HashMap::new(),
+            prefixes: PrefixCache::new(prefix_entries),
+            free_prefix_slots: (direct_slot + 1..n_seq as i32).rev().collect(),
+        };
+        Ok(Self { model, max_sequences, n_ctx, n_batch, direct: Mutex::new(()), next_direct: AtomicU64::new(1 << 63), state: Mutex::new(state) })
+    }
+
+    pub fn model(&self) -> &RawLlamaModel {
+        &self.model
+    }
+
+    /// Decodes one generation outside any decode loop, in the shared context; such generations
+    /// take turns. Text is handed to `on_piece` as it decodes; generation stops early when it
+    /// returns false. Blocking; call from a blocking thread.
+    pub fn generate(&self, prompt: &str, options: &GenerationOptions, mut on_piece: impl FnMut(String) -> bool) -> Result<(), RuntimeError> {
+        let _turn = self.direct.lock().unwrap_or_else(PoisonError::into_inner);
+        let id = self.next_direct.fetch_add(1, Ordering::Relaxed);
+        self.start(id, prompt, options, Some(self.max_sequences as i32))?;
+        let result = loop {
+            if options.cancel.is_cancelled() {
+                break Ok(());
+            }
+            match self.step(&[id]).pop() {
+                Some(StepOutput::Piece(piece)) => {
+                    if !piece.is_empty() && !on_piece(piece) {
+                        break Ok(());
+                    }
+                }
+                Some(StepOutput::Finished) | None => break Ok(()),
+                Some(StepOutput::Failed(e)) => break Err(RuntimeError::Backend(e)),
+            }
+        };
+        self.remove_sequence(id);
+        result
+    }
+
+    // Sets up sequence `id` in `slot`, or a free generation slot; its prompt is evaluated by
+    // the steps that follow
+    fn start(&self, id: SequenceId, prompt: &str, options: &GenerationOptions, slot: Option<i32>) -> Result<(), RuntimeError> {
+        let tokens = self.model.tokenize_prompt(prompt, &options.content_spans, true);
+        if tokens.is_empty() || tokens.len() >= self.n_ctx {
+            return Err(RuntimeError::InvalidInput(format!("prompt needs {} tokens; the context holds {}", tokens.len(), self.n_ctx)));
+        }
+        // The system turns are worth caching on their own when they tokenize the same alone
+        let system_spans: Vec<_> = options.content_spans.iter().filter(|span| span.end <= options.keep_prefix).cloned().collect();
+        let system = prompt
+            .get(..options.keep_prefix)
+            .filter(|p| !p.is_empty())
+            .map(|p| self.model.tokenize_prompt(p, &system_spans, true))
+            .filter(|t| t.len() < tokens.len() && tokens.starts_with(t))
+            .map_or(0, |t| t.len());
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let seq = match slot {
+            Some(seq) => seq,
+            None => state.free_slots.pop().ok_or("no free sequence slot")?,
+        };
+        Self::clear(state, seq);
+
+        // The last prompt token is always evaluated, for the logits of the first generated one
+        let cached = match state.prefixes.lookup(&tokens[..tokens.len() - 1]) {
+            Some((len, &slot)) => {
+                Self::copy(state, slot, seq, len);
+                counter!("prefix_cache_hits_total", 1);
+                histogram!("prefix_cache_reused_tokens", len as f64);
+                len
+            }
+            None => {
+                counter!("prefix_cache_misses_total", 1);
+                0
+            }
+        };
+        state.sequences.insert(id, Sequence {
+            seq,
+            prompt: tokens,
+            system,
+            n_past: cached,
+            remaining: options.max_tokens,
+            sampler: Sampler::new(options, StdRng::from_entropy()),
+            stops: StopMatcher::new(&options.stop).reporting_to(&options.matched_stop),
+            logits: Vec::new(),
+            pending: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn decode(&self, state: &mut BatchState) -> Result<(), String> {
+        // SAFETY: the batch is filled and owned by `state`, which also holds the context
+        let mut result = unsafe { sys::llama_decode(state.ctx.0, state.batch.0) };
+        // 1: no free KV cells for the batch. Cells only cached prefixes hold are the ones that
+        // can be given back, so the prefixes go and the batch is tried once more
+        if result == 1 && !state.prefixes.is_empty() {
+            while let Some(slot) = state.prefixes.evict() {
+                Self::clear(state, slot);
+                state.free_prefix_slots.push(slot);
+            }
+            result = unsafe { sys::llama_decode(state.ctx.0, state.batch.0) };
+        }
+        match result {
+            0 => Ok(()),
+            1 => Err(format!("the KV cache of {} cells is full", self.n_ctx)),
+            _ => Err(format!("llama decode failed ({})", result)),
+        }
+    }
+
+    // Copies the logits the last decode produced for batch position `i`
+    fn logits(&self, state: &BatchState, i: usize) -> Vec<f32> {
+        // SAFETY: position i requested logits in the last decode, so n_vocab floats are there
+        unsafe { std::slice::from_raw_parts(sys::llama_get_logits_ith(state.ctx.0, i as i32), self.model.n_vocab).to_vec() }
+    }
+
+    fn clear(state: &BatchState, seq: i32) {
+        // SAFETY: removes every cached position of `seq`; any sequence id below n_seq_max is valid
+        unsafe {
+            sys::llama_kv_cache_seq_rm(state.ctx.0, seq, -1, -1);
+        }
+    }
+
+    // Shares the first `len` cached positions of sequence `from` with sequence `to`
+    fn copy(state: &BatchState, from: i32, to: i32, len: usize) {
+        // SAFETY: both ids are below n_seq_max; positions past what `from` holds are ignored
+        unsafe {
+            sys::llama_kv_cache_seq_cp(state.ctx.0, from, to, 0, len as i32);
+        }
+    }
+
+    // Keeps `prefix`, already evaluated in sequence `seq`, for later prompts that start with it
+    fn remember(state: &mut BatchState, seq: i32, prefix: &[sys::llama_token]) {
+        if prefix.is_empty() || state.prefixes.touch(prefix) {
+            return;
+        }
+        if state.prefixes.is_full()
+            && let Some(slot) = state.prefixes.evict()
+        {
+            Self::clear(state, slot);
+            state.free_prefix_slots.push(slot);
+        }
+        let Some(slot) = state.free_prefix_slots.pop() else { return };
+        Self::copy(state, seq, slot, prefix.len());
+        if let Err(slot) = state.prefixes.insert(prefix.to_vec(), slot) {
+            Self::clear(state, slot);
+            state.free_prefix_slots.push(slot);
+        }
+    }
+}
+
+impl BatchDecodeRuntime for BatchDecoder {
+    fn max_sequences(&self) -> usize {
+        self.max_sequences
+    }
+
+    fn add_sequence(&self, id: SequenceId, prompt: &str, options: &GenerationOptions) -> Result<(), RuntimeError> {
+        self.start(id, prompt, options, None)
+    }
+
+    fn step(&self, ids: &[SequenceId]) -> Vec<StepOutput> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let mut outputs = Vec::with_capacity(ids.len());
+        // (output index, batch position, sequence) of every sequence that continues
+        let mut decoding = Vec::new();
+        // (output index, sequence) of every sequence still evaluating its prompt
+        let mut prefilling = Vec::new();
+        let mut entries = Vec::new();
+        for (index, id) in ids.iter().enumerate() {
+            let Some(sequence) = state.sequences.get_mut(id) else {
+                outputs.push(StepOutput::Failed(format!("unknown sequence {}", id)));
+                continue;
+            };
+            if sequence.prefilling() {
+                prefilling.push((index, *id));
+                outputs.push(StepOutput::Piece(String::new()));
+                continue;
+            }
+            let sampled = if sequence.remaining == 0 || sequence.n_past >= self.n_ctx {
+                None
+            } else {
+                sequence.sampler.sample(&sequence.logits)
+            };
+            let Some(token) = sampled.map(|index| index as sys::llama_token).filter(|&t| !self.model.is_end_of_generation(t)) else {
+                // Text held back as a possible stop sequence goes out before the sequence finishes
+                let rest = sequence.stops.finish();
+                sequence.remaining = 0;
+                outputs.push(if rest.is_empty() { StepOutput::Finished } else { StepOutput::Piece(rest) });
+                continue;
+            };
+            sequence.pending.extend(self.model.piece(token));
+            outputs.push(StepOutput::Piece(sequence.stops.push(&RawLlamaModel::take_complete(&mut sequence.pending))));
+            if sequence.stops.is_stopped() {
+                // Finishes on the next step, without decoding the token
+                sequence.remaining = 0;
+                continue;
+            }
+            sequence.remaining -= 1;
+            decoding.push((index, entries.len(), *id));
+            entries.push((token, sequence.n_past, sequence.seq, true));
+        }
+
+        // Prompts fill what the generating sequences leave of the batch: (output index, sequence,
+        // prompt evaluated up to, batch position of its last token when that ends the prompt)
+        let mut chunks = Vec::new();
+        for (index, id) in prefilling {
+            let room = self.n_batch - entries.len();
+            if room == 0 {
+                break;
+            }
+            let sequence = &state.sequences[&id];
+            let end = sequence.prompt.len().min(sequence.n_past + room);
+            let last = end == sequence.prompt.len();
+            for pos in sequence.n_past..end {
+                entries.push((sequence.prompt[pos], pos, sequence.seq, last && pos + 1 == end));
+            }
+            chunks.push((index, id, end, last.then(|| entries.len() - 1)));
+        }
+        if entries.is_empty() {
+            return outputs;
+        }
+
+        // Every continuing sequence advances in the same forward pass
+        state.batch.fill(&entries);
+        if let Err(e) = self.decode(state) {
+            for index in decoding.iter().map(|&(index, ..)| index).chain(chunks.iter().map(|&(index, ..)| index)) {
+                outputs[index] = StepOutput::Failed(e.clone());
+            }
+            return outputs;
+        }
+        for (_, i, id) in decoding {
+            let logits = self.logits(state, i);
+            let sequence = state.sequences.get_mut(&id).expect("decoded sequences exist");
+            sequence.logits = logits;
+            sequence.n_past += 1;
+        }
+        for (_, id, end, last) in chunks {
+            let logits = last.map(|i| self.logits(state, i));
+            let sequence = state.sequences.get_mut(&id).expect("prefilled sequences exist");
+            sequence.n_past = end;
+            let Some(logits) = logits else { continue };
+            sequence.logits = logits;
+            // The prompt is evaluated; its prefixes may serve later prompts
+            let (seq, system, prompt) = (sequence.seq, sequence.system, std::mem::take(&mut sequence.prompt));
+            Self::remember(state, seq, &prompt[..system]);
+            Self::remember(state, seq, &prompt[..prompt.len() - 1]);
+        }
+        outputs
+    }
+
+    fn remove_sequence(&self, id: SequenceId) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(sequence) = state.sequences.remove(&id) {
+            Self::clear(&state, sequence.seq);
+            // The direct slot is not handed out by `add_sequence`
+            if (sequence.seq as usize) < self.max_sequences {
+                state.free_slots.push(sequence.seq);
+            }
+        }
+    }
+
+    // Grammars are only enforced by the session sampler
+    fn accepts(&self, options: &GenerationOptions) -> bool {
+        options.grammar.is_none()
+    }
+}
@@ -1,52 +1,100 @@
 use async_trait::async_trait;
+use ndarray::{Array4, ArrayD};
+use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
+use std::{path::Path, sync::Arc};
+use tokio::sync::mpsc;
 
-use crate::runtime::{MultimodalRuntime, GenerationOptions};
+use crate::runtime::{
+    image::{decode_image, RgbImage},
+    image_fetch,
+    llama_raw::{Chunk, RawLlamaModel},
+    prompt::split_for_images,
+    vision::{self, ImageDetail, ImageInput, SquareMode, VisionInputSpec},
+    GenerationOptions, MultimodalRuntime, RuntimeError,
+};
 
-#[cfg(feature = "llama")]
-use crate::runtime::llama_cpp::LlamaCppRuntime;
-#[cfg(feature = "onnx")]
-use ort::{environment::Environment, session::{Session, builder::SessionBuilder}};
-#[cfg(feature = "onnx")]
-use std::path::Path;
+// CLIP ViT-L/14 at 336px, as used by LLaVA-1.5
+const DEFAULT_IMAGE_SIZE: usize = 336;
 
-// LLaVA runtime: loads vision encoder + projection (ONNX) and delegates text generation to llama.cpp
+struct Inner {
+    _env: Environment,
+    vision: Session,
+    projection: Session,
+    llm: RawLlamaModel,
+    // Padded to a square with the mean colour, as LLaVA-1.5 does
+    input_spec: VisionInputSpec,
+}
+
+/// LLaVA: a CLIP vision encoder and projection (ONNX) turn each image into embeddings that are
+/// evaluated in the llama.cpp context in place of the image, so the language model sees it.
+///
+/// The vision model takes `pixel_values` [1, 3, S, S] and must output the features of the layer
+/// LLaVA selects (the penultimate one), [1, 1 + patches, D] or [1, patches, D]; the projection
+/// maps [1, patches, D] to the language model's embedding width.
 pub struct LlavaRuntime {
-    #[cfg(feature = "onnx")]
-    vision_env: Environment,
-    #[cfg(feature = "onnx")]
-    vision_session: Session,
-    #[cfg(feature = "onnx")]
-    projection_session: Session,
-    #[cfg(feature = "llama")]
-    llm: LlamaCppRuntime,
+    inner: Arc<Inner>,
 }
 
 impl LlavaRuntime {
     pub fn new(vision_model_path: &str, proj_path: &str, llm_model_path: &str) -> Result<Self, String> {
-        #[cfg(feature = "onnx")]
-        let vision_env = Environment::builder().with_name("llava-vision").build().map_err(|e| format!("ORT env error: {}", e))?;
-        #[cfg(feature = "onnx")]
-        let vision_session = SessionBuilder::new(&vision_env)
+        let env = Environment::builder().with_name("llava-vision").build().map_err(|e| format!("ORT env error: {}", e))?;
+        let vision = SessionBuilder::new(&env)
             .with_model_from_file(Path::new(vision_model_path))
             .map_err(|e| format!("ORT load vision model error: {}", e))?;
-        #[cfg(feature = "onnx")]
-        let projection_session = SessionBuilder::new(&vision_env)
+        let projection = SessionBuilder::new(&env)
             .with_model_from_file(Path::new(proj_path))
             .map_err(|e| format!("ORT load projection model error: {}", e))?;
+        let image_size = vision
+            .inputs
+            .first()
+            .and_then(|input| input.input_type.tensor_dimensions().and_then(|dims| dims.last().copied()))
+            .filter(|&size| size > 0)
+            .map_or(DEFAULT_IMAGE_SIZE, |size| size as usize);
+        let llm = RawLlamaModel::load(llm_model_path)?;
+        let input_spec = VisionInputSpec::clip(image_size, SquareMode::Pad);
+        Ok(Self { inner: Arc::new(Inner { _env: env, vision, projection, llm, input_spec }) })
+    }
+}
+
+impl Inner {
+    /// Image embeddings for the language model, `patches * n_embd` floats per view, the views
+    /// (overview, then tiles for high detail) concatenated.
+    fn embed_image(&self, image: &RgbImage, detail: ImageDetail) -> Result<Vec<f32>, String> {
+        let mut embeddings = Vec::new();
+        for view in vision::preprocess(image, detail, &self.input_spec) {
+            embeddings.extend(self.embed_view(view)?);
+        }
+        Ok(embeddings)
+    }
 
-        #[cfg(feature = "llama")]
-        let llm = LlamaCppRuntime::new(llm_model_path)?;
+    fn embed_view(&self, pixels: Vec<f32>) -> Result<Vec<f32>, String> {
+        let size = self.input_spec.size;
+        let pixels = Array4::from_shape_vec((1, 3, size, size), pixels).map_err(|e| e.to_string())?;
+        let input = Value::from_array(pixels.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let vision_input = self.vision.inputs.first().map_or("pixel_values", |i| i.name.as_str());
+        let outputs = self.vision.run(vec![(vision_input, &input)]).map_err(|e| format!("ort vision run error: {}", e))?;
+        let features: ArrayD<f32> = outputs.get(0).ok_or("vision model produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+        let shape = features.shape().to_vec();
+        let (tokens, width) = match shape.as_slice() {
+            [1, tokens, width] => (*tokens, *width),
+            other => return Err(format!("unexpected vision output shape {:?}", other)),
+        };
+        // Drop the class token when the encoder kept it
+        let grid = (tokens.saturating_sub(1) as f64).sqrt() as usize;
+        let skip = usize::from(tokens > 1 && grid * grid == tokens - 1);
+        let patches = tokens - skip;
+        let features: Vec<f32> = features.iter().copied().skip(skip * width).collect();
+        let features = ndarray::Array3::from_shape_vec((1, patches, width), features).map_err(|e| e.to_string())?;
 
-        Ok(Self {
-            #[cfg(feature = "onnx")]
-            vision_env,
-            #[cfg(feature = "onnx")]
-            vision_session,
-            #[cfg(feature = "onnx")]
-            projection_session,
-            #[cfg(feature = "llama")]
-            llm,
-        })
+        let input = Value::from_array(features.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let projection_input = self.projection.inputs.first().map_or("image_features", |i| i.name.as_str());
+        let outputs = self.projection.run(vec![(projection_input, &input)]).map_err(|e| format!("ort projection run error: {}", e))?;
+        let embeddings: ArrayD<f32> = outputs.get(0).ok_or("projection produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+        let n_embd = *embeddings.shape().last().unwrap_or(&0);
+        if n_embd != self.llm.n_embd() {
+            return Err(format!("projection outputs {}-wide embeddings but the language model takes {}", n_embd, self.llm.n_embd()));
+        }
+        Ok(embeddings.iter().copied().collect())
     }
 }
 
@@ -55,30 +103,59 @@ impl MultimodalRuntime for LlavaRuntime {
     async fn generate_from_vision(
         &self,
         text: &str,
-        image_urls: &[String],
+        images: &[ImageInput],
         options: &GenerationOptions,
-    ) -> Result<String, String> {
-        // NOTE: For now, we do not execute the vision encoder path to keep
-        // default builds fast and stable. We augment the prompt with image count
-        // and delegate to the LLM runtime. A future change will run vision -> projection
-        // to obtain visual tokens and condition generation.
-
-        let mut augmented_prompt = String::new();
-        if !image_urls.is_empty() {
-            augmented_prompt.push_str(&format!("[images:{}] ", image_urls.len()));
-        }
-        augmented_prompt.push_str(text);
+    ) -> Result<String, RuntimeError> {
+        // Drive the streaming path and collect the pieces
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.generate_from_vision_stream(text, images, options, tx), collect);
+        result.map(|_| text)
+    }
 
-        #[cfg(feature = "llama")]
-        {
-            return self.llm.generate(&augmented_prompt, &options).await;
-        }
+    async fn generate_from_vision_stream(
+        &self,
+        text: &str,
+        images: &[ImageInput],
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let urls: Vec<String> = images.iter().map(|image| image.url.clone()).collect();
+        let details: Vec<ImageDetail> = images.iter().map(|image| image.detail).collect();
+        let images = image_fetch::global().fetch_all(&urls).await.map_err(RuntimeError::InvalidInput)?;
+        let inner = self.inner.clone();
+        let text = text.to_string();
+        let options = options.clone();
+        // Vision encoding and decoding are synchronous compute; keep them off the async workers
+        tokio::task::spawn_blocking(move || {
+            let embeddings = images
+                .iter()
+                .zip(details)
+                .enumerate()
+                .map(|(i, (bytes, detail))| {
+                    let image = decode_image(bytes).map_err(|e| RuntimeError::InvalidInput(format!("image {}: {}", i, e)))?;
+                    inner.embed_image(&image, detail).map_err(|e| RuntimeError::Backend(format!("image {}: {}", i, e)))
+                })
+                .collect::<Result<Vec<_>, RuntimeError>>()?;
+            let segments = split_for_images(&text, embeddings.len());
+            let mut chunks = vec![Chunk::Text(segments[0])];
+            for (embedding, segment) in embeddings.iter().zip(&segments[1..]) {
+                chunks.push(Chunk::Embeddings(embedding));
+                chunks.push(Chunk::Text(segment));
+            }
+            inner.llm.generate(&chunks, &options, |piece| sender.blocking_send(piece).is_ok())
+        })
+        .await
+        .map_err(|e| RuntimeError::Backend(format!("llava task failed: {}", e)))?
+    }
 
-        #[allow(unreachable_code)]
-        {
-            // Fallback (should not happen with llava feature), mimic truncation behavior
-            let truncated: String = augmented_prompt.chars().take(options.max_tokens as usize).collect();
-            Ok(truncated)
-        }
+    fn count_tokens(&self, text: &str) -> u32 {
+        self.inner.llm.tokenize(text, false, true).len() as u32
     }
 }
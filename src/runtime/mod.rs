@@ -1,39 +1,312 @@
 use async_trait::async_trait;
+use std::{ops::Range, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
+use crate::api::dto::TranscriptionSegment;
+use crate::runtime::{image::RgbImage, sampler::Mirostat, vision::ImageInput};
+
+pub mod accel;
+pub mod audio;
+#[cfg(feature = "candle")]
+pub mod candle;
+pub mod context_shift;
+pub mod decoding;
 #[cfg(feature = "llama")]
 pub mod llama_cpp;
 pub mod dummy;
 pub mod dummy_embedding;
+pub mod dummy_rerank;
+pub mod dummy_audio;
+pub mod dummy_tts;
+pub mod image;
+pub mod image_fetch;
+pub mod vision;
+pub mod jpeg;
+pub mod diffusion;
+pub mod grammar;
 pub mod sampler;
+pub mod prompt;
+pub mod prefix_cache;
+pub mod remote;
+pub mod safetensors;
+pub mod gguf;
+pub mod stop;
+pub mod pool;
 #[cfg(feature = "onnx")]
 pub mod onnx_embedding;
+#[cfg(feature = "onnx")]
+pub mod onnx_rerank;
+#[cfg(feature = "onnx_tokenizer")]
+pub mod onnx_llm;
 #[cfg(feature = "llava")]
 pub mod llava;
+#[cfg(feature = "llama")]
+pub mod llama_raw;
+#[cfg(feature = "whisper")]
+pub mod whisper;
+#[cfg(feature = "stable_diffusion")]
+pub mod onnx_sd;
+#[cfg(feature = "clip")]
+pub mod clip;
 pub mod dummy_image;
+pub mod error;
+
+pub use error::RuntimeError;
+
+/// `max_tokens` applied when a request does not set one.
+pub const DEFAULT_MAX_TOKENS: u32 = 100;
+
+/// Rough token estimate (~4 characters per token) for runtimes without a tokenizer.
+pub fn approximate_token_count(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
 
 #[async_trait]
 pub trait MultimodalRuntime: Send + Sync {
     async fn generate_from_vision(
         &self,
         text: &str,
-        image_urls: &[String],
+        images: &[ImageInput],
+        options: &GenerationOptions,
+    ) -> Result<String, RuntimeError>;
+
+    /// Streams generated text pieces into `sender` as they are produced.
+    /// The default implementation emits the whole completion as a single piece.
+    async fn generate_from_vision_stream(
+        &self,
+        text: &str,
+        images: &[ImageInput],
         options: &GenerationOptions,
-    ) -> Result<String, String>;
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let generated = self.generate_from_vision(text, images, options).await?;
+        let _ = sender.send(generated).await;
+        Ok(())
+    }
+
+    /// Number of tokens `text` occupies for this model; used for `usage` accounting.
+    fn count_tokens(&self, text: &str) -> u32 {
+        approximate_token_count(text)
+    }
 }
 
 #[async_trait]
 pub trait LlmRuntime: Send + Sync {
-    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, String>;
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError>;
+
+    /// Streams generated tokens into `sender` as they are decoded.
+    /// The default implementation emits the whole completion as a single piece.
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let generated = self.generate(prompt, options).await?;
+        let _ = sender.send(generated).await;
+        Ok(())
+    }
+
+    /// Number of tokens `text` occupies for this model; used for `usage` accounting.
+    fn count_tokens(&self, text: &str) -> u32 {
+        approximate_token_count(text)
+    }
+
+    /// Decoder that advances concurrent generations in shared forward passes, for runtimes
+    /// that have one. Generations then go through the engine's continuous batching scheduler
+    /// instead of `generate_stream`.
+    fn batch_decoder(&self) -> Option<Arc<dyn BatchDecodeRuntime>> {
+        None
+    }
+
+    /// Embeddings from this model's hidden states, for runtimes that can produce them. An LLM
+    /// loaded with `"embeddings": true` serves `/v1/embeddings` through it.
+    fn embedder(&self) -> Option<Arc<dyn EmbeddingRuntime>> {
+        None
+    }
+}
+
+/// Identifies one generation inside a `BatchDecodeRuntime`.
+pub type SequenceId = u64;
+
+/// What one decode step produced for one sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutput {
+    // May be empty while a character spans several tokens or the prompt is still being evaluated
+    Piece(String),
+    // End of sequence, `max_tokens` or a full context
+    Finished,
+    Failed(String),
+}
+
+/// Token-by-token decoding of many sequences at once. Calls block and come from a single
+/// scheduler thread per model; sequences join and leave between steps.
+pub trait BatchDecodeRuntime: Send + Sync {
+    /// Most sequences one step may carry.
+    fn max_sequences(&self) -> usize;
+
+    /// Starts sequence `id` with `prompt`. Long prompts should be evaluated over the following
+    /// steps rather than here, where every other sequence waits.
+    fn add_sequence(&self, id: SequenceId, prompt: &str, options: &GenerationOptions) -> Result<(), RuntimeError>;
+
+    /// Samples and decodes one token for each of `ids` in a single forward pass; one output per id.
+    fn step(&self, ids: &[SequenceId]) -> Vec<StepOutput>;
+
+    /// Frees the state of sequence `id`; called once for every added sequence.
+    fn remove_sequence(&self, id: SequenceId);
+
+    /// Whether a generation with `options` can be decoded here; others take the runtime's own
+    /// `generate_stream`.
+    fn accepts(&self, options: &GenerationOptions) -> bool {
+        let _ = options;
+        true
+    }
 }
 
 #[async_trait]
 pub trait EmbeddingRuntime: Send + Sync {
-    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String>;
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError>;
+
+    /// Embeds pre-tokenized inputs. Runtimes without a tokenizer cannot interpret token ids.
+    async fn embed_tokens(&self, inputs: &[Vec<u32>]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let _ = inputs;
+        Err(RuntimeError::InvalidInput("This model does not accept token id input".to_string()))
+    }
+
+    /// Embeds images into the same space as `embed`, for multimodal models such as CLIP.
+    async fn embed_images(&self, images: &[RgbImage]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let _ = images;
+        Err(RuntimeError::InvalidInput("This model does not accept image input".to_string()))
+    }
+
+    /// Number of tokens `text` occupies for this model; used for `usage` accounting.
+    fn count_tokens(&self, text: &str) -> u32 {
+        approximate_token_count(text)
+    }
+}
+
+#[async_trait]
+pub trait RerankRuntime: Send + Sync {
+    /// Relevance of each document to `query`, in document order; higher is more relevant.
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>, RuntimeError>;
+
+    /// Number of tokens `text` occupies for this model; used for `usage` accounting.
+    fn count_tokens(&self, text: &str) -> u32 {
+        approximate_token_count(text)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptionOptions {
+    // ISO-639-1 hint; the runtime detects the language when unset
+    pub language: Option<String>,
+    // Text that conditions the decoder (spellings, previous context)
+    pub prompt: Option<String>,
+    pub temperature: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Transcription {
+    pub text: String,
+    pub language: Option<String>,
+    pub segments: Vec<TranscriptionSegment>,
+}
+
+#[async_trait]
+pub trait AudioTranscriptionRuntime: Send + Sync {
+    /// Transcribes mono PCM sampled at `audio::SAMPLE_RATE`.
+    async fn transcribe(&self, samples: &[f32], options: &TranscriptionOptions) -> Result<Transcription, RuntimeError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SpeechOptions {
+    pub voice: String,
+    // Playback rate multiplier, 0.25 to 4.0
+    pub speed: f32,
+}
+
+// Synthesized mono PCM
+#[derive(Debug, Clone, Default)]
+pub struct Speech {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+#[async_trait]
+pub trait TtsRuntime: Send + Sync {
+    /// Synthesizes `text` as mono PCM at `sample_rate()`.
+    async fn synthesize(&self, text: &str, options: &SpeechOptions) -> Result<Vec<f32>, RuntimeError>;
+
+    fn sample_rate(&self) -> u32 {
+        audio::SPEECH_SAMPLE_RATE
+    }
+
+    /// Voices this runtime accepts; an empty list accepts any voice.
+    fn voices(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// How an image request should be sampled. `None` fields leave the choice to the backend,
+/// which also ignores controls it has no use for.
+#[derive(Debug, Clone)]
+pub struct ImageGenOptions {
+    pub n: u32,
+    pub size: String,
+    // What the image should steer away from; replaces the empty unconditional prompt
+    pub negative_prompt: Option<String>,
+    pub steps: Option<u32>,
+    pub guidance_scale: Option<f32>,
+    // Same seed, prompt and options give the same images; image i uses seed + i
+    pub seed: Option<u64>,
+    // Where runtimes report sampling progress, when the caller streams it
+    pub progress: Option<mpsc::Sender<ImageProgress>>,
+    // Attach a rough preview of the image in progress to each report
+    pub previews: bool,
+}
+
+impl ImageGenOptions {
+    pub fn new(n: u32, size: impl Into<String>) -> Self {
+        Self { n, size: size.into(), negative_prompt: None, steps: None, guidance_scale: None, seed: None, progress: None, previews: false }
+    }
+
+    /// Reports progress without waiting: a report is dropped when the receiver is behind, so
+    /// a slow client never stalls sampling. Callable from blocking threads.
+    pub fn report(&self, progress: ImageProgress) {
+        if let Some(sender) = &self.progress {
+            let _ = sender.try_send(progress);
+        }
+    }
+}
+
+/// One finished sampling step of one image.
+#[derive(Debug, Clone)]
+pub struct ImageProgress {
+    pub image_index: u32,
+    // 1-based
+    pub step: u32,
+    pub total_steps: u32,
+    // PNG, present only when `previews` was requested
+    pub preview: Option<Vec<u8>>,
 }
 
 #[async_trait]
 pub trait ImageGenRuntime: Send + Sync {
-    async fn generate_images(&self, prompt: &str, n: u32, size: &str) -> Result<Vec<Vec<u8>>, String>;
+    async fn generate_images(&self, prompt: &str, options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError>;
+
+    /// Repaints `image` (PNG) following `prompt`. With a `mask` (PNG of the same size) only its
+    /// fully transparent pixels may change.
+    async fn edit_image(&self, image: &[u8], mask: Option<&[u8]>, prompt: &str, options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        let _ = (image, mask, prompt, options);
+        Err(RuntimeError::InvalidInput("This image model does not support edits".to_string()))
+    }
+
+    /// Produces `options.n` images similar to `image` (PNG).
+    async fn generate_variations(&self, image: &[u8], options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        let _ = (image, options);
+        Err(RuntimeError::InvalidInput("This image model does not support variations".to_string()))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,14 +314,48 @@ pub struct GenerationOptions {
     pub max_tokens: u32,
     pub temperature: f32,
     pub top_p: f32,
+    // Only the k most likely tokens; 0 keeps all
+    pub top_k: u32,
+    // Drops tokens less likely than min_p times the most likely one; 0 keeps all
+    pub min_p: f32,
+    // Locally typical sampling mass; 1 disables it
+    pub typical_p: f32,
+    // Divides the logits of recently generated tokens; 1 disables it
+    pub repetition_penalty: f32,
+    // Replaces top_k, top_p, min_p and typical_p when set
+    pub mirostat: Option<Mirostat>,
+    // Cancelled when the client goes away; runtimes should stop decoding once it fires
+    pub cancel: CancellationToken,
+    // Byte length of the prompt prefix (system turns) that must survive a context shift
+    pub keep_prefix: usize,
+    // Byte ranges of the prompt holding message text, which must not tokenize into special
+    // tokens; see `prompt::tokenize_prompt`
+    pub content_spans: Vec<Range<usize>>,
+    // GBNF grammar the output must match; runtimes without constrained sampling ignore it
+    pub grammar: Option<String>,
+    // Generation ends before the first of these; see `stop::StopMatcher`
+    pub stop: Vec<String>,
+    // Where the runtime's `StopMatcher` records the stop string it ended generation at
+    pub matched_stop: stop::MatchedStop,
 }
 
 impl GenerationOptions {
     pub fn from_request(max_tokens: Option<u32>, temperature: Option<f32>, top_p: Option<f32>) -> Self {
         Self {
-            max_tokens: max_tokens.unwrap_or(100),
+            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
             temperature: temperature.unwrap_or(1.0),
             top_p: top_p.unwrap_or(1.0),
+            top_k: 0,
+            min_p: 0.0,
+            typical_p: 1.0,
+            repetition_penalty: 1.0,
+            mirostat: None,
+            cancel: CancellationToken::new(),
+            keep_prefix: 0,
+            content_spans: Vec::new(),
+            grammar: None,
+            stop: Vec::new(),
+            matched_stop: stop::MatchedStop::default(),
         }
     }
 }
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use std::path::Path;
 
-use crate::runtime::EmbeddingRuntime;
+use crate::runtime::{EmbeddingRuntime, RuntimeError};
 
 #[cfg(feature = "onnx")]
 use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
@@ -21,18 +21,37 @@ pub struct OnnxEmbeddingRuntime {
 }
 
 impl OnnxEmbeddingRuntime {
-    pub fn new(model_path: &str, dim: usize) -> Result<Self, String> {
+    /// `dim` is only needed when the model's first output does not declare its last
+    /// dimension; when both are known they must agree.
+    pub fn new(model_path: &str, dim: Option<usize>) -> Result<Self, String> {
         #[cfg(feature = "onnx")]
         {
             let env = Environment::builder().with_name("onnx-embed").build().map_err(|e| format!("ORT env error: {}", e))?;
             let session = SessionBuilder::new(&env)
                 .with_model_from_file(Path::new(model_path))
                 .map_err(|e| format!("ORT load model error: {}", e))?;
+            // [batch, dim] or [batch, seq, hidden]; dynamic axes are reported as -1
+            let declared = session
+                .outputs
+                .first()
+                .and_then(|output| output.output_type.tensor_dimensions().and_then(|dims| dims.last().copied()))
+                .filter(|&d| d > 0)
+                .map(|d| d as usize);
+            let dim = match (declared, dim) {
+                (Some(declared), Some(given)) if declared != given => {
+                    return Err(format!("{} produces {}-dimensional embeddings, not {}", model_path, declared, given));
+                }
+                (Some(d), _) | (None, Some(d)) => d,
+                (None, None) => {
+                    return Err(format!("cannot infer the embedding dimension of {}; set it explicitly", model_path));
+                }
+            };
             #[cfg(feature = "onnx_tokenizer")]
-            let tokenizer = match std::env::var("ONNX_EMBEDDING_TOKENIZER_PATH") {
+            let tokenizer = match crate::config::var("ONNX_EMBEDDING_TOKENIZER_PATH") {
                 Ok(tok_path) => Some(Tokenizer::from_file(tok_path).map_err(|e| format!("load tokenizer error: {}", e))?),
                 Err(_) => None,
             };
+            tracing::info!("onnx embedding model {} ({} dimensions)", model_path, dim);
             Ok(Self {
                 env,
                 session,
@@ -47,11 +66,16 @@ impl OnnxEmbeddingRuntime {
             Err("onnx feature not enabled".to_string())
         }
     }
+
+    /// Size of the vectors this model produces.
+    pub fn dimension(&self) -> usize {
+        self.dim
+    }
 }
 
 #[async_trait]
 impl EmbeddingRuntime for OnnxEmbeddingRuntime {
-    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
         #[cfg(feature = "onnx")]
         {
             // Simple path: if tokenizer not available, return zero vectors to avoid breaking default tests.
@@ -139,7 +163,29 @@ impl EmbeddingRuntime for OnnxEmbeddingRuntime {
         #[cfg(not(feature = "onnx"))]
         {
             let _ = inputs;
-            Err("onnx feature not enabled".to_string())
+            Err(RuntimeError::Backend("onnx feature not enabled".to_string()))
+        }
+    }
+
+    async fn embed_tokens(&self, inputs: &[Vec<u32>]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        // Round-trip through the tokenizer so token ids share the text input path
+        #[cfg(feature = "onnx_tokenizer")]
+        if let Some(tk) = &self.tokenizer {
+            let texts = inputs
+                .iter()
+                .map(|ids| tk.decode(ids, true).map_err(|e| format!("detokenize error: {}", e)))
+                .collect::<Result<Vec<String>, String>>()?;
+            return self.embed(&texts).await;
+        }
+        let _ = inputs;
+        Err(RuntimeError::InvalidInput("Token id input requires ONNX_EMBEDDING_TOKENIZER_PATH".to_string()))
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        #[cfg(feature = "onnx_tokenizer")]
+        if let Some(encoding) = self.tokenizer.as_ref().and_then(|tk| tk.encode(text, true).ok()) {
+            return encoding.len() as u32;
         }
+        crate::runtime::approximate_token_count(text)
     }
 }
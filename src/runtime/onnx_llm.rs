@@ -0,0 +1,267 @@
+use async_trait::async_trait;
+use ndarray::{Array1, Array2, ArrayD, Axis, IxDyn};
+use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{path::Path, sync::Arc};
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc;
+
+use crate::api::dto::ModelOptions;
+use crate::runtime::{
+    decoding::{end_tokens, TextStream},
+    sampler::Sampler,
+    stop::StopMatcher,
+    GenerationOptions, LlmRuntime, RuntimeError,
+};
+
+/// File names tried for the decoder, Optimum's merged export first.
+pub const MODEL_FILES: &[&str] = &["decoder_model_merged.onnx", "model.onnx", "decoder_model.onnx"];
+
+// Context used when neither the model config nor `n_ctx` sets one
+const DEFAULT_CONTEXT: usize = 2048;
+
+fn ort_error(what: &str) -> impl Fn(ort::Error) -> String + '_ {
+    move |e| format!("ort {} error: {}", what, e)
+}
+
+// A past key/value input and the output that carries its next value
+struct CacheSlot {
+    input: String,
+    output: usize,
+    // [batch, heads, past, head_dim] with past 0, the cache before the first step
+    empty_shape: Vec<usize>,
+}
+
+struct Inner {
+    _env: Environment,
+    session: Session,
+    tokenizer: Tokenizer,
+    cache: Vec<CacheSlot>,
+    has_position_ids: bool,
+    // Merged exports switch between the prompt and cached branches with this input
+    has_cache_branch: bool,
+    // EOS and end-of-turn token ids
+    end_tokens: Vec<u32>,
+    max_context: usize,
+}
+
+/// Decoder-only text generation on ONNX Runtime, for small models exported with Hugging Face
+/// Optimum (`optimum-cli export onnx --task text-generation-with-past`). `model_path` is a
+/// directory with the decoder (one of [`MODEL_FILES`]), `tokenizer.json` and `config.json`.
+///
+/// The decoder takes `input_ids`, `attention_mask`, optionally `position_ids`, and a
+/// `past_key_values.N.key`/`.value` pair per layer; it returns `logits` first and the grown
+/// cache as `present.N.key`/`.value`, which is fed back on the next step.
+pub struct OnnxLlmRuntime {
+    inner: Arc<Inner>,
+}
+
+impl OnnxLlmRuntime {
+    /// `n_ctx` overrides the context length from the config.
+    pub fn new(model_path: &str, options: &ModelOptions) -> Result<Self, String> {
+        let dir = Path::new(model_path);
+        let model_file = MODEL_FILES
+            .iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.exists())
+            .ok_or_else(|| format!("No decoder in {}; expected one of {}", model_path, MODEL_FILES.join(", ")))?;
+        let config: serde_json::Value = match std::fs::read_to_string(dir.join("config.json")) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid config.json: {}", e))?,
+            Err(_) => serde_json::Value::Null,
+        };
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| format!("load tokenizer error: {}", e))?;
+
+        let env = Environment::builder().with_name("onnx-llm").build().map_err(|e| format!("ORT env error: {}", e))?;
+        let session = SessionBuilder::new(&env)
+            .with_model_from_file(&model_file)
+            .map_err(|e| format!("ORT load {} error: {}", model_file.display(), e))?;
+
+        let has_input = |name: &str| session.inputs.iter().any(|i| i.name == name);
+        if !has_input("input_ids") {
+            return Err(format!("{} has no input_ids input; is it a text decoder?", model_file.display()));
+        }
+        let mut cache = Vec::new();
+        for input in session.inputs.iter().filter(|i| i.name.starts_with("past_key_values")) {
+            // past_key_values.0.key pairs with present.0.key
+            let present = input.name.replacen("past_key_values", "present", 1);
+            let output = session
+                .outputs
+                .iter()
+                .position(|o| o.name == present)
+                .ok_or_else(|| format!("{} has input {} but no {} output", model_file.display(), input.name, present))?;
+            let dims = input.input_type.tensor_dimensions().cloned().unwrap_or_default();
+            cache.push(CacheSlot { input: input.name.clone(), output, empty_shape: empty_cache_shape(&dims, &config)? });
+        }
+        if cache.is_empty() {
+            tracing::warn!("{} has no KV cache inputs; every step reruns the whole sequence", model_file.display());
+        }
+
+        let end_tokens = end_tokens(&config, |token| tokenizer.token_to_id(token));
+        let max_context = options
+            .n_ctx
+            .map(|n| n as usize)
+            .or(config["max_position_embeddings"].as_u64().map(|n| n as usize))
+            .unwrap_or(DEFAULT_CONTEXT);
+        tracing::info!("onnx llm {} with {} cache tensors", model_file.display(), cache.len());
+        Ok(Self {
+            inner: Arc::new(Inner {
+                has_position_ids: has_input("position_ids"),
+                has_cache_branch: has_input("use_cache_branch"),
+                _env: env,
+                session,
+                tokenizer,
+                cache,
+                end_tokens,
+                max_context,
+            }),
+        })
+    }
+}
+
+// Shape of an empty cache tensor. Exports usually fix heads and head_dim; where they are
+// dynamic they come from the config.
+fn empty_cache_shape(dims: &[i64], config: &serde_json::Value) -> Result<Vec<usize>, String> {
+    if dims.len() != 4 {
+        return Err(format!("expected [batch, heads, past, head_dim] cache inputs, got {} dims", dims.len()));
+    }
+    let attention_heads = config["num_attention_heads"].as_u64();
+    let heads = Some(dims[1]).filter(|&d| d > 0).map(|d| d as u64).or(config["num_key_value_heads"].as_u64()).or(attention_heads);
+    let head_dim = Some(dims[3]).filter(|&d| d > 0).map(|d| d as u64).or(config["head_dim"].as_u64()).or_else(|| {
+        Some(config["hidden_size"].as_u64()? / attention_heads?)
+    });
+    match (heads, head_dim) {
+        (Some(heads), Some(head_dim)) => Ok(vec![1, heads as usize, 0, head_dim as usize]),
+        _ => Err("cache shape is dynamic and config.json does not give the head count and size".to_string()),
+    }
+}
+
+impl Inner {
+    fn decode(&self, tokens: &[u32]) -> Result<String, String> {
+        self.tokenizer.decode(tokens, true).map_err(|e| format!("tokenizer error: {}", e))
+    }
+
+    // Runs `input` starting at position `pos` over `past`, replacing it with the grown cache,
+    // and returns the logits for the next token
+    fn forward(&self, input: &[u32], pos: usize, past: &mut Vec<ArrayD<f32>>) -> Result<Vec<f32>, String> {
+        let tensor = ort_error("tensor");
+        let n = input.len();
+        let input_ids = Array2::from_shape_vec((1, n), input.iter().map(|&t| t as i64).collect()).map_err(|e| e.to_string())?;
+        let attention = Array2::<i64>::ones((1, pos + n));
+        let positions = Array2::from_shape_vec((1, n), (pos..pos + n).map(|p| p as i64).collect()).map_err(|e| e.to_string())?;
+        let cache_branch = Array1::from_elem(1, pos > 0);
+
+        let ids_value = Value::from_array(input_ids.view()).map_err(&tensor)?;
+        let attention_value = Value::from_array(attention.view()).map_err(&tensor)?;
+        let positions_value = Value::from_array(positions.view()).map_err(&tensor)?;
+        let branch_value = Value::from_array(cache_branch.view()).map_err(&tensor)?;
+        let past_values = past.iter().map(|p| Value::from_array(p.view())).collect::<Result<Vec<_>, _>>().map_err(&tensor)?;
+
+        let mut inputs = vec![("input_ids", &ids_value), ("attention_mask", &attention_value)];
+        if self.has_position_ids {
+            inputs.push(("position_ids", &positions_value));
+        }
+        if self.has_cache_branch {
+            inputs.push(("use_cache_branch", &branch_value));
+        }
+        inputs.extend(self.cache.iter().zip(&past_values).map(|(slot, value)| (slot.input.as_str(), value)));
+        let outputs = self.session.run(inputs).map_err(ort_error("run"))?;
+
+        let logits: ArrayD<f32> = outputs.get(0).ok_or("model produced no logits".to_string())?.try_extract().map_err(ort_error("extract"))?;
+        // [batch, seq, vocab]; the last position predicts the next token
+        if logits.ndim() != 3 {
+            return Err(format!("expected [batch, seq, vocab] logits, got {:?}", logits.shape()));
+        }
+        let last = logits.index_axis(Axis(0), 0).index_axis(Axis(0), logits.shape()[1] - 1).to_vec();
+        for (slot, past) in self.cache.iter().zip(past.iter_mut()) {
+            *past = outputs.get(slot.output).ok_or_else(|| format!("model produced no {}", slot.input))?.try_extract().map_err(ort_error("extract"))?;
+        }
+        Ok(last)
+    }
+
+    // Blocking; hands text to `on_piece` as it decodes and stops early when it returns false
+    fn generate(&self, prompt: &str, options: &GenerationOptions, mut on_piece: impl FnMut(String) -> bool) -> Result<(), RuntimeError> {
+        let encoding = self.tokenizer.encode(prompt, true).map_err(|e| format!("tokenizer error: {}", e))?;
+        let prompt_tokens = encoding.get_ids().to_vec();
+        if prompt_tokens.is_empty() {
+            return Err(RuntimeError::InvalidInput("prompt is empty".to_string()));
+        }
+        if prompt_tokens.len() >= self.max_context {
+            return Err(RuntimeError::InvalidInput(format!("prompt needs {} tokens; the context holds {}", prompt_tokens.len(), self.max_context)));
+        }
+        let mut past: Vec<ArrayD<f32>> = self.cache.iter().map(|slot| ArrayD::zeros(IxDyn(&slot.empty_shape))).collect();
+
+        let mut sampler = Sampler::new(options, StdRng::from_entropy());
+        let mut stops = StopMatcher::new(&options.stop).reporting_to(&options.matched_stop);
+        let mut text = TextStream::default();
+        let mut sequence = prompt_tokens.clone();
+        let mut input = prompt_tokens;
+        let mut pos = 0;
+        for _ in 0..options.max_tokens {
+            if options.cancel.is_cancelled() || pos + input.len() >= self.max_context {
+                break;
+            }
+            let logits = self.forward(&input, pos, &mut past)?;
+            let Some(token) = sampler.sample(&logits).map(|t| t as u32) else { break };
+            if self.end_tokens.contains(&token) {
+                break;
+            }
+            let piece = stops.push(&text.push(token, |tokens| self.decode(tokens))?);
+            if !piece.is_empty() && !on_piece(piece) {
+                return Ok(());
+            }
+            if stops.is_stopped() {
+                return Ok(());
+            }
+            sequence.push(token);
+            if self.cache.is_empty() {
+                // Without a cache every step sees the whole sequence from the start
+                input = sequence.clone();
+            } else {
+                pos += input.len();
+                input = vec![token];
+            }
+        }
+        let rest = stops.push(&text.finish(|tokens| self.decode(tokens))?) + &stops.finish();
+        if !rest.is_empty() {
+            on_piece(rest);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmRuntime for OnnxLlmRuntime {
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        let (tx, mut rx) = mpsc::channel::<String>(64);
+        let collect = async {
+            let mut out = String::new();
+            while let Some(piece) = rx.recv().await {
+                out.push_str(&piece);
+            }
+            out
+        };
+        let (result, text) = tokio::join!(self.generate_stream(prompt, options, tx), collect);
+        result.map(|_| text)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let inner = self.inner.clone();
+        let prompt = prompt.to_string();
+        let options = options.clone();
+        tokio::task::spawn_blocking(move || inner.generate(&prompt, &options, |piece| sender.blocking_send(piece).is_ok()))
+            .await
+            .map_err(|e| RuntimeError::Backend(format!("onnx llm task failed: {}", e)))?
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        match self.inner.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.get_ids().len() as u32,
+            Err(_) => crate::runtime::approximate_token_count(text),
+        }
+    }
+}
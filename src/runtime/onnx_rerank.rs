@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use std::path::Path;
+
+use crate::runtime::{RerankRuntime, RuntimeError};
+
+#[cfg(feature = "onnx_tokenizer")]
+use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
+#[cfg(feature = "onnx_tokenizer")]
+use tokenizers::Tokenizer;
+#[cfg(feature = "onnx_tokenizer")]
+use ndarray::Array2;
+
+/// Cross-encoder reranker (e.g. ms-marco MiniLM / bge-reranker exported to ONNX): each
+/// (query, document) pair is encoded together and the model emits one relevance logit.
+pub struct OnnxRerankRuntime {
+    #[cfg(feature = "onnx_tokenizer")]
+    env: Environment,
+    #[cfg(feature = "onnx_tokenizer")]
+    session: Session,
+    #[cfg(feature = "onnx_tokenizer")]
+    tokenizer: Tokenizer,
+}
+
+impl OnnxRerankRuntime {
+    /// ENV: ONNX_RERANK_TOKENIZER_PATH (tokenizer.json of the cross-encoder, required)
+    pub fn new(model_path: &str) -> Result<Self, String> {
+        #[cfg(feature = "onnx_tokenizer")]
+        {
+            let env = Environment::builder().with_name("onnx-rerank").build().map_err(|e| format!("ORT env error: {}", e))?;
+            let session = SessionBuilder::new(&env)
+                .with_model_from_file(Path::new(model_path))
+                .map_err(|e| format!("ORT load model error: {}", e))?;
+            let tok_path = crate::config::var("ONNX_RERANK_TOKENIZER_PATH")
+                .map_err(|_| "ONNX_RERANK_TOKENIZER_PATH is required for rerank models".to_string())?;
+            let tokenizer = Tokenizer::from_file(tok_path).map_err(|e| format!("load tokenizer error: {}", e))?;
+            Ok(Self { env, session, tokenizer })
+        }
+        #[cfg(not(feature = "onnx_tokenizer"))]
+        {
+            let _ = Path::new(model_path);
+            Err("rerank models require the onnx_tokenizer feature".to_string())
+        }
+    }
+}
+
+#[async_trait]
+impl RerankRuntime for OnnxRerankRuntime {
+    async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<f32>, RuntimeError> {
+        #[cfg(feature = "onnx_tokenizer")]
+        {
+            if documents.is_empty() {
+                return Ok(Vec::new());
+            }
+            let pairs: Vec<(String, String)> = documents.iter().map(|d| (query.to_string(), d.clone())).collect();
+            let encodings = self.tokenizer.encode_batch(pairs, true).map_err(|e| format!("tokenize error: {}", e))?;
+            let max_len = encodings.iter().map(|e| e.len()).max().unwrap_or(0);
+            let batch = encodings.len();
+            let mut input_ids = Array2::<i64>::zeros((batch, max_len));
+            let mut attention = Array2::<i64>::zeros((batch, max_len));
+            let mut type_ids = Array2::<i64>::zeros((batch, max_len));
+            for (b, enc) in encodings.iter().enumerate() {
+                for (t, (&id, &type_id)) in enc.get_ids().iter().zip(enc.get_type_ids()).enumerate() {
+                    input_ids[(b, t)] = id as i64;
+                    attention[(b, t)] = 1;
+                    type_ids[(b, t)] = type_id as i64;
+                }
+            }
+
+            let input_ids_tensor = Value::from_array(input_ids.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+            let attention_tensor = Value::from_array(attention.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+            let type_ids_tensor = Value::from_array(type_ids.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+            // XLM-R based rerankers have no token_type_ids input
+            let mut inputs = vec![("input_ids", &input_ids_tensor), ("attention_mask", &attention_tensor)];
+            if self.session.inputs.iter().any(|i| i.name == "token_type_ids") {
+                inputs.push(("token_type_ids", &type_ids_tensor));
+            }
+            let outputs = self.session.run(inputs).map_err(|e| format!("ort run error: {}", e))?;
+
+            let logits = outputs.get(0).ok_or("rerank model produced no output".to_string())?;
+            let arr: ndarray::ArrayD<f32> = logits.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+            // [batch] or [batch, 1]; squash logits into (0, 1) relevance scores
+            let scores: Vec<f32> = arr.iter().map(|logit| 1.0 / (1.0 + (-logit).exp())).collect();
+            if scores.len() != batch {
+                return Err(RuntimeError::Backend(format!("unexpected rerank output shape {:?}", arr.shape())));
+            }
+            Ok(scores)
+        }
+        #[cfg(not(feature = "onnx_tokenizer"))]
+        {
+            let _ = (query, documents);
+            Err(RuntimeError::Backend("rerank models require the onnx_tokenizer feature".to_string()))
+        }
+    }
+
+    fn count_tokens(&self, text: &str) -> u32 {
+        #[cfg(feature = "onnx_tokenizer")]
+        if let Ok(encoding) = self.tokenizer.encode(text, true) {
+            return encoding.len() as u32;
+        }
+        crate::runtime::approximate_token_count(text)
+    }
+}
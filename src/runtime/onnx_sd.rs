@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use ndarray::{Array1, Array2, Array4, ArrayD};
+use ort::{environment::Environment, session::{Session, builder::SessionBuilder}, value::Value};
+use rand::SeedableRng;
+use std::{path::Path, sync::Arc};
+use tokenizers::Tokenizer;
+
+use crate::runtime::{
+    diffusion::{chw_to_rgb, gaussian_noise, guide, latent_preview, EulerScheduler},
+    image::{encode_png, parse_size},
+    ImageGenOptions, ImageGenRuntime, ImageProgress, RuntimeError,
+};
+
+// CLIP text encoder context length
+const MAX_PROMPT_TOKENS: usize = 77;
+// SD 1.x/2.x VAE latent scaling
+const VAE_SCALE: f32 = 0.18215;
+const DEFAULT_STEPS: usize = 25;
+const DEFAULT_GUIDANCE: f32 = 7.5;
+
+// Per-request sampling settings with the defaults filled in
+struct Sampling {
+    negative_prompt: String,
+    steps: usize,
+    guidance_scale: f32,
+    // For progress reports
+    options: ImageGenOptions,
+}
+
+struct Pipeline {
+    _env: Environment,
+    text_encoder: Session,
+    unet: Session,
+    vae_decoder: Session,
+    tokenizer: Tokenizer,
+    // Some exports take the timestep as int64, others as float
+    int_timestep: bool,
+}
+
+/// Stable Diffusion through ONNX Runtime, from a diffusers/Optimum ONNX export directory with
+/// `text_encoder/model.onnx`, `unet/model.onnx`, `vae_decoder/model.onnx` and
+/// `tokenizer/tokenizer.json`.
+pub struct OnnxStableDiffusionRuntime {
+    pipeline: Arc<Pipeline>,
+}
+
+impl OnnxStableDiffusionRuntime {
+    pub fn new(model_dir: &str) -> Result<Self, String> {
+        let dir = Path::new(model_dir);
+        let env = Environment::builder().with_name("onnx-sd").build().map_err(|e| format!("ORT env error: {}", e))?;
+        let load = |part: &str| {
+            SessionBuilder::new(&env)
+                .with_model_from_file(dir.join(part).join("model.onnx"))
+                .map_err(|e| format!("ORT load {} error: {}", part, e))
+        };
+        let text_encoder = load("text_encoder")?;
+        let unet = load("unet")?;
+        let vae_decoder = load("vae_decoder")?;
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer").join("tokenizer.json"))
+            .map_err(|e| format!("load tokenizer error: {}", e))?;
+        let int_timestep = unet
+            .inputs
+            .iter()
+            .find(|i| i.name == "timestep")
+            .is_some_and(|i| format!("{:?}", i.input_type).contains("Int64"));
+        Ok(Self { pipeline: Arc::new(Pipeline { _env: env, text_encoder, unet, vae_decoder, tokenizer, int_timestep }) })
+    }
+}
+
+impl Pipeline {
+    /// CLIP hidden states for `text`, padded to the full context.
+    fn encode_text(&self, text: &str) -> Result<ArrayD<f32>, String> {
+        let encoding = self.tokenizer.encode(text, true).map_err(|e| format!("tokenize error: {}", e))?;
+        let mut ids: Vec<i32> = encoding.get_ids().iter().take(MAX_PROMPT_TOKENS).map(|&id| id as i32).collect();
+        // CLIP pads with its end-of-text token
+        let pad = ids.last().copied().unwrap_or(49407);
+        ids.resize(MAX_PROMPT_TOKENS, pad);
+        let input_ids = Array2::from_shape_vec((1, MAX_PROMPT_TOKENS), ids).map_err(|e| e.to_string())?;
+        let input = Value::from_array(input_ids.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let outputs = self.text_encoder.run(vec![("input_ids", &input)]).map_err(|e| format!("ort run error: {}", e))?;
+        let hidden = outputs.get(0).ok_or("text encoder produced no output".to_string())?;
+        hidden.try_extract().map_err(|e| format!("ort extract error: {}", e))
+    }
+
+    fn generate(&self, prompt: &str, sampling: &Sampling, width: usize, height: usize, seed: u64, image_index: u32) -> Result<Vec<u8>, RuntimeError> {
+        let cond = self.encode_text(prompt)?;
+        let uncond = self.encode_text(&sampling.negative_prompt)?;
+        let hidden_dim = *cond.shape().last().ok_or("empty text embedding".to_string())?;
+        let mut context: Vec<f32> = uncond.iter().copied().collect();
+        context.extend(cond.iter().copied());
+        let context = ndarray::Array3::from_shape_vec((2, MAX_PROMPT_TOKENS, hidden_dim), context).map_err(|e| e.to_string())?;
+
+        let scheduler = EulerScheduler::new(sampling.steps);
+        let latent_shape = (1, 4, height / 8, width / 8);
+        let latent_len = 4 * (height / 8) * (width / 8);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut latents: Vec<f32> = gaussian_noise(&mut rng, latent_len).iter().map(|x| x * scheduler.init_noise_sigma()).collect();
+
+        for (index, &t) in scheduler.timesteps().iter().enumerate() {
+            // One batch of two: unconditional, then prompt-conditioned
+            let scaled = scheduler.scale_model_input(&latents, index);
+            let mut batch = scaled.clone();
+            batch.extend_from_slice(&scaled);
+            let sample = Array4::from_shape_vec((2, latent_shape.1, latent_shape.2, latent_shape.3), batch).map_err(|e| e.to_string())?;
+            let sample = Value::from_array(sample.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+            let context_value = Value::from_array(context.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+            let int_t = Array1::from_vec(vec![t.round() as i64]);
+            let float_t = Array1::from_vec(vec![t]);
+            let timestep = if self.int_timestep { Value::from_array(int_t.view()) } else { Value::from_array(float_t.view()) }
+                .map_err(|e| format!("ort tensor error: {}", e))?;
+            let outputs = self
+                .unet
+                .run(vec![("sample", &sample), ("timestep", &timestep), ("encoder_hidden_states", &context_value)])
+                .map_err(|e| format!("ort run error: {}", e))?;
+            let noise: ArrayD<f32> = outputs.get(0).ok_or("unet produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+            let noise: Vec<f32> = noise.iter().copied().collect();
+            let guided = guide(&noise[..latent_len], &noise[latent_len..], sampling.guidance_scale);
+            scheduler.step(&guided, index, &mut latents);
+            let preview = if sampling.options.previews {
+                encode_png(&latent_preview(&latents, width / 8, height / 8), (width / 8) as u32, (height / 8) as u32).ok()
+            } else {
+                None
+            };
+            sampling.options.report(ImageProgress {
+                image_index,
+                step: index as u32 + 1,
+                total_steps: scheduler.timesteps().len() as u32,
+                preview,
+            });
+        }
+
+        let latents: Vec<f32> = latents.iter().map(|x| x / VAE_SCALE).collect();
+        let latents = Array4::from_shape_vec(latent_shape, latents).map_err(|e| e.to_string())?;
+        let latents = Value::from_array(latents.view()).map_err(|e| format!("ort tensor error: {}", e))?;
+        let outputs = self.vae_decoder.run(vec![("latent_sample", &latents)]).map_err(|e| format!("ort run error: {}", e))?;
+        let pixels: ArrayD<f32> = outputs.get(0).ok_or("vae decoder produced no output".to_string())?.try_extract().map_err(|e| format!("ort extract error: {}", e))?;
+        let pixels: Vec<f32> = pixels.iter().copied().collect();
+        encode_png(&chw_to_rgb(&pixels, width, height), width as u32, height as u32).map_err(RuntimeError::Backend)
+    }
+}
+
+#[async_trait]
+impl ImageGenRuntime for OnnxStableDiffusionRuntime {
+    async fn generate_images(&self, prompt: &str, options: &ImageGenOptions) -> Result<Vec<Vec<u8>>, RuntimeError> {
+        let (width, height) = parse_size(&options.size).map_err(RuntimeError::InvalidInput)?;
+        if width % 64 != 0 || height % 64 != 0 {
+            return Err(RuntimeError::InvalidInput(format!("size must be a multiple of 64 in both dimensions, got {}", options.size)));
+        }
+        let pipeline = self.pipeline.clone();
+        let prompt = prompt.to_string();
+        let sampling = Sampling {
+            negative_prompt: options.negative_prompt.clone().unwrap_or_default(),
+            steps: options.steps.map_or(DEFAULT_STEPS, |s| s as usize),
+            guidance_scale: options.guidance_scale.unwrap_or(DEFAULT_GUIDANCE),
+            options: options.clone(),
+        };
+        let n = options.n;
+        let seed: u64 = options.seed.unwrap_or_else(rand::random);
+        // Each image is a few seconds of synchronous compute; keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            (0..n as u64)
+                .map(|i| pipeline.generate(&prompt, &sampling, width as usize, height as usize, seed.wrapping_add(i), i as u32))
+                .collect()
+        })
+        .await
+        .map_err(|e| RuntimeError::Backend(format!("stable diffusion task failed: {}", e)))?
+    }
+}
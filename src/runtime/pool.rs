@@ -0,0 +1,77 @@
+//! Reusable per-request resources (such as llama.cpp sessions) that are costly to set up.
+
+use std::{ops::{Deref, DerefMut}, sync::Mutex};
+
+/// Sessions a llama.cpp model keeps for generations that decode alone.
+/// ENV: LLAMA_SESSION_POOL_SIZE (default 2; 0 creates a session per request)
+pub fn session_pool_size() -> usize {
+    crate::config::var("LLAMA_SESSION_POOL_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(2)
+}
+
+/// Idle items waiting to be reused, at most `capacity` of them. Taking from an empty pool is
+/// not an error: the caller makes a new item, which joins the pool when it is returned.
+pub struct Pool<T> {
+    idle: Mutex<Vec<T>>,
+    capacity: usize,
+}
+
+impl<T> Pool<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self { idle: Mutex::new(Vec::with_capacity(capacity)), capacity }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// The most recently returned item, if any is idle.
+    pub fn take(&self) -> Option<T> {
+        self.idle.lock().unwrap().pop()
+    }
+
+    /// Keeps `item` for reuse, or drops it when the pool is full.
+    pub fn put(&self, item: T) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.capacity {
+            idle.push(item);
+        }
+    }
+
+    /// Wraps `item` so it goes back to the pool when dropped.
+    pub fn lease(&self, item: T) -> Leased<'_, T> {
+        Leased { pool: self, item: Some(item) }
+    }
+}
+
+/// An item on loan from a `Pool`.
+pub struct Leased<'a, T> {
+    pool: &'a Pool<T>,
+    item: Option<T>,
+}
+
+impl<T> Deref for Leased<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().expect("present until dropped")
+    }
+}
+
+impl<T> DerefMut for Leased<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().expect("present until dropped")
+    }
+}
+
+impl<T> Drop for Leased<'_, T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.put(item);
+        }
+    }
+}
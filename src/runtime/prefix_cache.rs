@@ -0,0 +1,97 @@
+//! Token prefixes whose KV state a runtime keeps around, so prompts that start the same way
+//! (a shared system prompt, a conversation that keeps growing) skip re-evaluating that part.
+
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}};
+
+/// ENV: PREFIX_CACHE_ENTRIES (default 4; 0 disables prefix caching)
+pub fn configured_entries() -> usize {
+    crate::config::var("PREFIX_CACHE_ENTRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(4)
+}
+
+struct Entry<T, V> {
+    tokens: Vec<T>,
+    value: V,
+    last_used: u64,
+}
+
+/// Least recently used prefixes, keyed by a hash of their tokens. `V` is whatever the runtime
+/// needs to find the cached state again, such as a KV cache sequence id.
+pub struct PrefixCache<T, V> {
+    capacity: usize,
+    entries: HashMap<u64, Entry<T, V>>,
+    clock: u64,
+}
+
+fn prefix_hash<T: Hash>(tokens: &[T]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tokens.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: Hash + Eq + Clone, V> PrefixCache<T, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), clock: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.capacity
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// The longest cached prefix of `tokens`, as its length and value, marked as just used.
+    pub fn lookup(&mut self, tokens: &[T]) -> Option<(usize, &V)> {
+        let mut lengths: Vec<usize> = self.entries.values().map(|e| e.tokens.len()).filter(|&n| n > 0 && n <= tokens.len()).collect();
+        lengths.sort_unstable_by(|a, b| b.cmp(a));
+        lengths.dedup();
+        // A hash match is confirmed against the stored tokens
+        let key = lengths.into_iter().map(|n| prefix_hash(&tokens[..n])).find(|key| {
+            self.entries.get(key).is_some_and(|e| tokens.starts_with(&e.tokens))
+        })?;
+        let now = self.tick();
+        let entry = self.entries.get_mut(&key).expect("found above");
+        entry.last_used = now;
+        Some((entry.tokens.len(), &entry.value))
+    }
+
+    /// Whether exactly `tokens` is cached; marks it as just used when it is.
+    pub fn touch(&mut self, tokens: &[T]) -> bool {
+        let now = self.tick();
+        match self.entries.get_mut(&prefix_hash(tokens)) {
+            Some(entry) if entry.tokens == tokens => {
+                entry.last_used = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the least recently used prefix and returns its value.
+    pub fn evict(&mut self) -> Option<V> {
+        let key = *self.entries.iter().min_by_key(|(_, e)| e.last_used)?.0;
+        self.entries.remove(&key).map(|e| e.value)
+    }
+
+    /// Caches `tokens`. Returns `value` back when the prefix is already cached or the cache is
+    /// full (`evict` first to make room).
+    pub fn insert(&mut self, tokens: Vec<T>, value: V) -> Result<(), V> {
+        let key = prefix_hash(&tokens);
+        if self.is_full() || self.entries.contains_key(&key) {
+            return Err(value);
+        }
+        let last_used = self.tick();
+        self.entries.insert(key, Entry { tokens, value, last_used });
+        Ok(())
+    }
+}
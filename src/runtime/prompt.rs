@@ -0,0 +1,283 @@
+use std::ops::Range;
+
+use crate::api::dto::{ChatCompletionMessage, ChatMessageContent, ContentPart};
+use crate::runtime::vision::{ImageDetail, ImageInput};
+
+const TURN_START: &str = "<|im_start|>";
+const TURN_END: &str = "<|im_end|>";
+
+/// A conversation flattened into a single prompt plus the images referenced anywhere in it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderedPrompt {
+    pub prompt: String,
+    pub images: Vec<ImageInput>,
+    // Byte length of the leading system turns; context shifting never discards this prefix
+    pub keep_prefix: usize,
+    // Byte ranges of message text in `prompt`; see `tokenize_prompt`
+    pub content_spans: Vec<Range<usize>>,
+}
+
+fn message_text(content: &ChatMessageContent, images: &mut Vec<ImageInput>) -> String {
+    match content {
+        ChatMessageContent::Text(text) => text.clone(),
+        ChatMessageContent::Parts(parts) => {
+            let mut text_acc = String::new();
+            for p in parts {
+                match p {
+                    ContentPart::Text { text } => text_acc.push_str(text),
+                    ContentPart::ImageUrl { image_url } => images.push(ImageInput {
+                        url: image_url.url.clone(),
+                        detail: ImageDetail::parse(image_url.detail.as_deref()),
+                    }),
+                }
+            }
+            text_acc
+        }
+    }
+}
+
+/// Renders the whole conversation (system, user, assistant and tool turns) in ChatML and
+/// opens an assistant turn for the model to complete.
+pub fn render_chat_prompt(messages: &[ChatCompletionMessage]) -> RenderedPrompt {
+    let mut rendered = RenderedPrompt::default();
+    let mut leading_system = true;
+    for m in messages {
+        let text = message_text(&m.content, &mut rendered.images);
+        rendered.prompt.push_str(&format!("{}{}\n", TURN_START, m.role));
+        if !text.is_empty() {
+            rendered.content_spans.push(rendered.prompt.len()..rendered.prompt.len() + text.len());
+        }
+        rendered.prompt.push_str(&format!("{}{}\n", text, TURN_END));
+        leading_system &= m.role == "system";
+        if leading_system {
+            rendered.keep_prefix = rendered.prompt.len();
+        }
+    }
+    rendered.prompt.push_str(&format!("{}assistant\n", TURN_START));
+    rendered
+}
+
+/// Content of the final user turn of a rendered prompt, or the prompt itself when it is not ChatML.
+pub fn last_user_turn(prompt: &str) -> &str {
+    let marker = format!("{}user\n", TURN_START);
+    match prompt.rfind(&marker) {
+        Some(start) => {
+            let turn = &prompt[start + marker.len()..];
+            turn.find(TURN_END).map_or(turn, |end| &turn[..end])
+        }
+        None => prompt,
+    }
+}
+
+/// Turns of a ChatML prompt as (role, content), without the open assistant turn it ends with;
+/// None when the prompt is not ChatML.
+pub fn parse_chatml(prompt: &str) -> Option<Vec<(String, String)>> {
+    let mut turns = Vec::new();
+    let opener = format!("{}assistant\n", TURN_START);
+    let mut rest = prompt.strip_suffix(&opener).unwrap_or(prompt);
+    while !rest.is_empty() {
+        let turn = rest.strip_prefix(TURN_START)?;
+        let (role, turn) = turn.split_once('\n')?;
+        let end = turn.find(TURN_END)?;
+        turns.push((role.to_string(), turn[..end].to_string()));
+        let after = &turn[end + TURN_END.len()..];
+        rest = after.strip_prefix('\n').unwrap_or(after);
+    }
+    (!turns.is_empty()).then_some(turns)
+}
+
+/// Marks where an image goes in prompts of vision models (the LLaVA convention).
+pub const IMAGE_PLACEHOLDER: &str = "<image>";
+
+/// Splits `prompt` around `n_images` images: the result has `n_images + 1` text segments and
+/// image `i` goes between segments `i` and `i + 1`. Images take the place of the prompt's
+/// `<image>` placeholders in order (any beyond the last placeholder follow it); without
+/// placeholders they open the final ChatML user turn, or else the prompt.
+pub fn split_for_images(prompt: &str, n_images: usize) -> Vec<&str> {
+    if n_images == 0 {
+        return vec![prompt];
+    }
+    let mut segments = Vec::with_capacity(n_images + 1);
+    let mut rest = prompt;
+    if prompt.contains(IMAGE_PLACEHOLDER) {
+        while segments.len() < n_images
+            && let Some(index) = rest.find(IMAGE_PLACEHOLDER)
+        {
+            segments.push(&rest[..index]);
+            rest = &rest[index + IMAGE_PLACEHOLDER.len()..];
+        }
+    } else {
+        let marker = format!("{}user\n", TURN_START);
+        let index = prompt.rfind(&marker).map_or(0, |start| start + marker.len());
+        segments.push(&prompt[..index]);
+        rest = &prompt[index..];
+    }
+    segments.resize(n_images, "");
+    segments.push(rest);
+    segments
+}
+
+const CHATML_TEMPLATE: &str = "{% for m in messages %}<|im_start|>{{ m.role }}\n{{ m.content }}<|im_end|>\n{% endfor %}\
+{% if add_generation_prompt %}<|im_start|>assistant\n{% endif %}";
+
+const LLAMA3_TEMPLATE: &str = "{{ bos_token }}{% for m in messages %}<|start_header_id|>{{ m.role }}<|end_header_id|>\n\n\
+{{ m.content | trim }}<|eot_id|>{% endfor %}\
+{% if add_generation_prompt %}<|start_header_id|>assistant<|end_header_id|>\n\n{% endif %}";
+
+// Mistral has no system role; the system prompt is folded into the first user turn
+const MISTRAL_TEMPLATE: &str = "{% if messages and messages[0].role == 'system' %}\
+{% set system = messages[0].content ~ '\n\n' %}{% set turns = messages[1:] %}\
+{% else %}{% set system = '' %}{% set turns = messages %}{% endif %}\
+{{ bos_token }}{% for m in turns %}\
+{% if m.role == 'user' %}[INST] {% if loop.first %}{{ system }}{% endif %}{{ m.content }} [/INST]\
+{% elif m.role == 'assistant' %}{{ m.content }}{{ eos_token }}{% endif %}{% endfor %}";
+
+/// A Jinja chat template (Hugging Face `chat_template` conventions) used to flatten `messages`
+/// for a specific model.
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    source: String,
+    bos_token: String,
+    eos_token: String,
+}
+
+impl ChatTemplate {
+    /// Resolves a built-in template name (`chatml`, `llama3`, `mistral`) or treats `spec` as
+    /// inline Jinja source. The template is test-rendered so syntax errors surface at load time.
+    pub fn resolve(spec: &str) -> Result<Self, String> {
+        let (source, bos, eos) = match spec {
+            "chatml" => (CHATML_TEMPLATE, "", ""),
+            "llama3" => (LLAMA3_TEMPLATE, "<|begin_of_text|>", "<|eot_id|>"),
+            "mistral" => (MISTRAL_TEMPLATE, "<s>", "</s>"),
+            inline => (inline, "", ""),
+        };
+        let template = Self { source: source.to_string(), bos_token: bos.to_string(), eos_token: eos.to_string() };
+        template.render(&[ChatCompletionMessage {
+            role: "user".to_string(),
+            content: ChatMessageContent::Text("ping".to_string()),
+        }])?;
+        Ok(template)
+    }
+
+    pub fn render(&self, messages: &[ChatCompletionMessage]) -> Result<RenderedPrompt, String> {
+        let mut images = Vec::new();
+        let (prompt, content_spans) = self.render_str(messages, true, &mut images)?;
+        // The system prefix is whatever the leading system turns render to on their own, as far
+        // as it agrees with the full prompt (templates may fold the system prompt into a user turn)
+        let system_turns = messages.iter().take_while(|m| m.role == "system").count();
+        let keep_prefix = if system_turns == 0 {
+            0
+        } else {
+            let (system_only, _) = self.render_str(&messages[..system_turns], false, &mut Vec::new())?;
+            let common = prompt.bytes().zip(system_only.bytes()).take_while(|(a, b)| a == b).count();
+            // Stay on a char boundary
+            (0..=common).rev().find(|&i| prompt.is_char_boundary(i)).unwrap_or(0)
+        };
+        Ok(RenderedPrompt { prompt, images, keep_prefix, content_spans })
+    }
+
+    // Message text goes in as placeholders, filled in once the template has rendered, so where
+    // each message landed is known exactly whatever its text spells. Surrounding whitespace stays
+    // outside the placeholder, which keeps `trim` working; other filters see the placeholder.
+    fn render_str(&self, messages: &[ChatCompletionMessage], add_generation_prompt: bool, images: &mut Vec<ImageInput>) -> Result<(String, Vec<Range<usize>>), String> {
+        let texts: Vec<String> = messages.iter().map(|m| message_text(&m.content, images)).collect();
+        let turns: Vec<minijinja::Value> = messages
+            .iter()
+            .zip(&texts)
+            .enumerate()
+            .map(|(i, (m, text))| {
+                let start = text.len() - text.trim_start().len();
+                let end = start + text.trim().len();
+                let content = format!("{}{}{}{}{}", &text[..start], PLACEHOLDER_OPEN, i, PLACEHOLDER_CLOSE, &text[end..]);
+                minijinja::context! { role => m.role, content => content }
+            })
+            .collect();
+        let mut env = minijinja::Environment::new();
+        env.add_function("raise_exception", |msg: String| -> Result<String, minijinja::Error> {
+            Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, msg))
+        });
+        let rendered = env
+            .render_str(&self.source, minijinja::context! {
+                messages => turns,
+                bos_token => self.bos_token,
+                eos_token => self.eos_token,
+                add_generation_prompt => add_generation_prompt,
+            })
+            .map_err(|e| format!("chat template: {}", e))?;
+        Ok(fill_placeholders(&rendered, &texts))
+    }
+}
+
+// Private-use characters around a message index, standing in for the message's text
+const PLACEHOLDER_OPEN: char = '\u{E000}';
+const PLACEHOLDER_CLOSE: char = '\u{E001}';
+
+// Replaces the placeholders in `rendered` with the (trimmed) message texts they stand for,
+// returning the prompt and where each text went
+fn fill_placeholders(rendered: &str, texts: &[String]) -> (String, Vec<Range<usize>>) {
+    let mut prompt = String::with_capacity(rendered.len() + texts.iter().map(String::len).sum::<usize>());
+    let mut spans = Vec::new();
+    let mut rest = rendered;
+    while let Some(open) = rest.find(PLACEHOLDER_OPEN) {
+        prompt.push_str(&rest[..open]);
+        let after = &rest[open + PLACEHOLDER_OPEN.len_utf8()..];
+        let text = after
+            .find(PLACEHOLDER_CLOSE)
+            .and_then(|close| Some((close, texts.get(after[..close].parse::<usize>().ok()?)?)));
+        let Some((close, text)) = text else {
+            // Not one of ours; kept as the template wrote it
+            prompt.push(PLACEHOLDER_OPEN);
+            rest = after;
+            continue;
+        };
+        let text = text.trim();
+        if !text.is_empty() {
+            spans.push(prompt.len()..prompt.len() + text.len());
+        }
+        prompt.push_str(text);
+        rest = &after[close + PLACEHOLDER_CLOSE.len_utf8()..];
+    }
+    prompt.push_str(rest);
+    (prompt, spans)
+}
+
+/// Tokenizes a rendered prompt so only its template can produce special tokens: message text
+/// (`content_spans`) that would parse into one, such as a user typing `<|im_end|>`, is tokenized
+/// as plain text. `tokenize(text, add_bos, parse_special)` is the model's tokenizer. A prompt
+/// whose messages spell no special token is tokenized in one piece, so its tokens are unchanged.
+pub fn tokenize_prompt<T: PartialEq, E>(
+    prompt: &str,
+    content_spans: &[Range<usize>],
+    add_bos: bool,
+    mut tokenize: impl FnMut(&str, bool, bool) -> Result<Vec<T>, E>,
+) -> Result<Vec<T>, E> {
+    let mut spans: Vec<Range<usize>> = content_spans.iter().filter(|span| !span.is_empty() && prompt.get((*span).clone()).is_some()).cloned().collect();
+    spans.sort_by_key(|span| span.start);
+    let mut spells_special = false;
+    for span in &spans {
+        let text = &prompt[span.clone()];
+        if tokenize(text, false, true)? != tokenize(text, false, false)? {
+            spells_special = true;
+            break;
+        }
+    }
+    if !spells_special {
+        return tokenize(prompt, add_bos, true);
+    }
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    for span in spans {
+        if span.start < at {
+            continue; // overlaps the previous span
+        }
+        if at < span.start {
+            tokens.extend(tokenize(&prompt[at..span.start], add_bos && at == 0, true)?);
+        }
+        tokens.extend(tokenize(&prompt[span.clone()], add_bos && span.start == 0, false)?);
+        at = span.end;
+    }
+    if at < prompt.len() {
+        tokens.extend(tokenize(&prompt[at..], false, true)?);
+    }
+    Ok(tokens)
+}
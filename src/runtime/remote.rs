@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::api::dto::{ModelOptions, RemoteApi};
+use crate::outbound::{self, OutboundClient, OutboundResponse};
+use crate::runtime::{prompt::parse_chatml, EmbeddingRuntime, GenerationOptions, LlmRuntime, RuntimeError};
+
+// Upstream error bodies are quoted in our errors, within reason
+const MAX_ERROR_BYTES: usize = 4096;
+// Non-streamed responses are JSON of a bounded completion or embedding batch
+const MAX_RESPONSE_BYTES: usize = 64 << 20;
+
+#[derive(Debug, Clone)]
+pub struct RemoteConfig {
+    // OpenAI-compatible API root, ending in `/v1`
+    pub base_url: String,
+    pub api_key: Option<String>,
+    // Model name sent upstream
+    pub model: String,
+    pub api: RemoteApi,
+    // Cap on each upstream request, streams included; the outbound defaults otherwise
+    pub timeout: Option<Duration>,
+}
+
+/// Prefix of the variables `upstream_api_key_env` may name. Callers choose the upstream URL,
+/// so letting them name any variable would send any secret in the environment to them.
+pub const REMOTE_KEY_PREFIX: &str = "REMOTE_KEY_";
+
+impl RemoteConfig {
+    /// `base_url` may be the server root or its `/v1` API root. The key is read from the
+    /// environment variable named by `upstream_api_key_env`, which must start with
+    /// `REMOTE_KEY_`, or REMOTE_API_KEY.
+    pub fn new(base_url: &str, name: &str, options: &ModelOptions) -> Result<Self, String> {
+        let url = reqwest::Url::parse(base_url).map_err(|e| format!("invalid upstream URL {:?}: {}", base_url, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("upstream URL must be http(s); got {}", url.scheme()));
+        }
+        let root = base_url.trim_end_matches('/');
+        let key_env = match options.upstream_api_key_env.as_deref() {
+            None => "REMOTE_API_KEY",
+            Some(name) if name.len() > REMOTE_KEY_PREFIX.len() && name.starts_with(REMOTE_KEY_PREFIX) => name,
+            Some(name) => return Err(format!("upstream_api_key_env must name a {}* variable; got {:?}", REMOTE_KEY_PREFIX, name)),
+        };
+        Ok(Self {
+            base_url: if root.ends_with("/v1") { root.to_string() } else { format!("{}/v1", root) },
+            api_key: crate::config::var(key_env).ok().filter(|key| !key.is_empty()),
+            model: options.upstream_model.clone().unwrap_or_else(|| name.to_string()),
+            api: options.upstream_api.unwrap_or_default(),
+            timeout: options.upstream_timeout_ms.map(Duration::from_millis),
+        })
+    }
+}
+
+/// Forwards generations and embeddings to an OpenAI-compatible server (vLLM, TGI, Ollama,
+/// OpenAI itself), so one endpoint can front local and remote backends alike. Streams are
+/// passed through piece by piece as the upstream sends them.
+///
+/// In chat mode the rendered ChatML prompt is split back into messages and the upstream
+/// applies its own template; prompts rendered with another template go up as one user turn.
+pub struct RemoteRuntime {
+    config: RemoteConfig,
+    client: OutboundClient,
+}
+
+impl RemoteRuntime {
+    pub fn new(config: RemoteConfig) -> Self {
+        Self { config, client: outbound::client("remote_runtime") }
+    }
+
+    fn body(&self, prompt: &str, options: &GenerationOptions, stream: bool) -> Value {
+        let mut body = json!({
+            "model": self.config.model,
+            "max_tokens": options.max_tokens,
+            "temperature": options.temperature,
+            "top_p": options.top_p,
+            "stream": stream,
+        });
+        match self.config.api {
+            RemoteApi::Chat => {
+                let turns = parse_chatml(prompt).unwrap_or_else(|| vec![("user".to_string(), prompt.to_string())]);
+                body["messages"] = turns.into_iter().map(|(role, content)| json!({ "role": role, "content": content })).collect();
+            }
+            RemoteApi::Completions => body["prompt"] = json!(prompt),
+        }
+        if !options.stop.is_empty() {
+            body["stop"] = json!(options.stop);
+        }
+        // Not part of the OpenAI API; sent only when set, for servers that take them (vLLM, Ollama)
+        if options.top_k > 0 {
+            body["top_k"] = json!(options.top_k);
+        }
+        if options.min_p > 0.0 {
+            body["min_p"] = json!(options.min_p);
+        }
+        if options.repetition_penalty != 1.0 {
+            body["repetition_penalty"] = json!(options.repetition_penalty);
+        }
+        body
+    }
+
+    // An upstream refusing the request as sent (4xx other than for its own credentials or
+    // limits) is the request's fault; anything else the upstream's
+    async fn post(&self, path: &str, body: &Value) -> Result<OutboundResponse, RuntimeError> {
+        let mut request = self.client.post(&format!("{}{}", self.config.base_url, path)).json(body);
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+        if let Some(timeout) = self.config.timeout {
+            request = request.timeout(timeout);
+        }
+        let response = self.client.send(request).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.bytes_limited(MAX_ERROR_BYTES).await.unwrap_or_default();
+            let message = format!("upstream returned {}: {}", status, String::from_utf8_lossy(&body).trim());
+            return Err(match status.as_u16() {
+                401 | 403 | 429 => RuntimeError::Backend(message),
+                400..=499 => RuntimeError::InvalidInput(message),
+                _ => RuntimeError::Backend(message),
+            });
+        }
+        Ok(response)
+    }
+
+    async fn post_json(&self, path: &str, body: &Value) -> Result<Value, RuntimeError> {
+        let bytes = self.post(path, body).await?.bytes_limited(MAX_RESPONSE_BYTES).await?;
+        serde_json::from_slice(&bytes).map_err(|e| RuntimeError::Backend(format!("invalid upstream response: {}", e)))
+    }
+
+    // Text of the first choice of a completion or of one streamed chunk
+    fn choice_text<'a>(&self, response: &'a Value, stream: bool) -> Option<&'a str> {
+        let choice = &response["choices"][0];
+        match (self.config.api, stream) {
+            (RemoteApi::Chat, false) => choice["message"]["content"].as_str(),
+            (RemoteApi::Chat, true) => choice["delta"]["content"].as_str(),
+            (RemoteApi::Completions, _) => choice["text"].as_str(),
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self.config.api {
+            RemoteApi::Chat => "/chat/completions",
+            RemoteApi::Completions => "/completions",
+        }
+    }
+}
+
+#[async_trait]
+impl LlmRuntime for RemoteRuntime {
+    async fn generate(&self, prompt: &str, options: &GenerationOptions) -> Result<String, RuntimeError> {
+        let response = self.post_json(self.path(), &self.body(prompt, options, false)).await?;
+        Ok(self.choice_text(&response, false).unwrap_or_default().to_string())
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        options: &GenerationOptions,
+        sender: mpsc::Sender<String>,
+    ) -> Result<(), RuntimeError> {
+        let response = self.post(self.path(), &self.body(prompt, options, true)).await?;
+        let mut stream = response.response.bytes_stream();
+        // Server-sent events; a chunk may end mid-line
+        let mut buffer = Vec::new();
+        loop {
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                // Dropping the stream closes the upstream connection, which ends its generation
+                _ = options.cancel.cancelled() => return Ok(()),
+            };
+            let Some(chunk) = chunk else { return Ok(()) };
+            buffer.extend_from_slice(&chunk.map_err(|e| format!("read upstream stream: {}", e))?);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else { continue };
+                if data == "[DONE]" {
+                    return Ok(());
+                }
+                let event: Value = serde_json::from_str(data).map_err(|e| format!("invalid upstream event: {}", e))?;
+                if let Some(error) = event.get("error") {
+                    return Err(RuntimeError::Backend(format!("upstream error: {}", error)));
+                }
+                if let Some(text) = self.choice_text(&event, true).filter(|text| !text.is_empty())
+                    && sender.send(text.to_string()).await.is_err()
+                {
+                    return Ok(()); // receiver dropped
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingRuntime for RemoteRuntime {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        let response = self.post_json("/embeddings", &json!({ "model": self.config.model, "input": inputs })).await?;
+        let data = response["data"].as_array().ok_or("upstream response has no data")?;
+        let mut embeddings = vec![None; inputs.len()];
+        for (position, item) in data.iter().enumerate() {
+            // Results carry their input's index; fall back to the order they came in
+            let index = item["index"].as_u64().map_or(position, |i| i as usize);
+            let embedding = item["embedding"]
+                .as_array()
+                .ok_or("upstream embedding is not a list of numbers")?
+                .iter()
+                .map(|v| v.as_f64().map(|v| v as f32))
+                .collect::<Option<Vec<f32>>>()
+                .ok_or("upstream embedding is not a list of numbers")?;
+            *embeddings.get_mut(index).ok_or_else(|| format!("upstream returned embedding {} for {} inputs", index, inputs.len()))? = Some(embedding);
+        }
+        embeddings
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| RuntimeError::Backend(format!("upstream returned {} embeddings for {} inputs", data.len(), inputs.len())))
+    }
+}
@@ -0,0 +1,158 @@
+//! Reader for the header of safetensors weight files: an 8-byte little-endian length, then a
+//! JSON object mapping tensor names to dtype, shape and byte range within the data that
+//! follows. Headers are checked in full before a backend maps the weights, so truncated
+//! downloads and corrupt shards fail with a clear message instead of deep inside a loader.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use crate::api::dto::{SafetensorsFileInfo, SafetensorsInspection, TensorInfo};
+
+// The format caps headers at 100MB
+const MAX_HEADER_BYTES: u64 = 100 << 20;
+
+/// Bytes per element, None for dtypes the format does not define.
+pub fn dtype_size(dtype: &str) -> Option<u64> {
+    Some(match dtype {
+        "BOOL" | "U8" | "I8" | "F8_E5M2" | "F8_E4M3" => 1,
+        "I16" | "U16" | "F16" | "BF16" => 2,
+        "I32" | "U32" | "F32" => 4,
+        "I64" | "U64" | "F64" => 8,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct SafetensorsHeader {
+    // In data order
+    pub tensors: Vec<TensorInfo>,
+    pub metadata: BTreeMap<String, String>,
+    pub file_bytes: u64,
+}
+
+/// Parses and validates the header of one file: known dtypes, byte ranges matching each
+/// tensor's shape, and data that covers the rest of the file without gaps or overlaps.
+pub fn read_header(path: &Path) -> Result<SafetensorsHeader, String> {
+    let fail = |e: String| format!("{}: {}", path.display(), e);
+    let mut file = std::fs::File::open(path).map_err(|e| fail(e.to_string()))?;
+    let file_bytes = file.metadata().map_err(|e| fail(e.to_string()))?.len();
+    let mut len = [0u8; 8];
+    file.read_exact(&mut len).map_err(|_| fail("too short for a safetensors header".to_string()))?;
+    let header_len = u64::from_le_bytes(len);
+    if header_len > MAX_HEADER_BYTES || header_len > file_bytes - 8 {
+        return Err(fail(format!("header length {} does not fit a {} byte file", header_len, file_bytes)));
+    }
+    let mut header = vec![0u8; header_len as usize];
+    file.read_exact(&mut header).map_err(|e| fail(e.to_string()))?;
+    let header: Value = serde_json::from_slice(&header).map_err(|e| fail(format!("invalid header: {}", e)))?;
+    let entries = header.as_object().ok_or_else(|| fail("header is not a JSON object".to_string()))?;
+
+    let mut tensors = Vec::new();
+    let mut ranges = Vec::new();
+    let mut metadata = BTreeMap::new();
+    for (name, entry) in entries {
+        if name == "__metadata__" {
+            for (key, value) in entry.as_object().into_iter().flatten() {
+                metadata.insert(key.clone(), value.as_str().map_or_else(|| value.to_string(), str::to_string));
+            }
+            continue;
+        }
+        let invalid = |why: &str| fail(format!("tensor {}: {}", name, why));
+        let dtype = entry["dtype"].as_str().ok_or_else(|| invalid("no dtype"))?;
+        let size = dtype_size(dtype).ok_or_else(|| invalid(&format!("unknown dtype {}", dtype)))?;
+        let shape = entry["shape"]
+            .as_array()
+            .and_then(|dims| dims.iter().map(Value::as_u64).collect::<Option<Vec<u64>>>())
+            .ok_or_else(|| invalid("shape is not a list of sizes"))?;
+        let (start, end) = match entry["data_offsets"].as_array().map(Vec::as_slice) {
+            Some([start, end]) => start.as_u64().zip(end.as_u64()).ok_or_else(|| invalid("bad data_offsets"))?,
+            _ => return Err(invalid("bad data_offsets")),
+        };
+        let expected = shape.iter().try_fold(size, |n, &dim| n.checked_mul(dim)).ok_or_else(|| invalid("shape overflows"))?;
+        if end < start || end - start != expected {
+            return Err(invalid(&format!("{} bytes for a {} {:?} tensor", end.saturating_sub(start), dtype, shape)));
+        }
+        ranges.push((start, end, tensors.len()));
+        tensors.push(TensorInfo { name: name.clone(), dtype: dtype.to_string(), shape, file: String::new() });
+    }
+
+    // Tensors tile the data exactly
+    ranges.sort();
+    let mut next = 0;
+    for &(start, end, index) in &ranges {
+        if start != next {
+            return Err(fail(format!("tensor {} starts at byte {}, expected {}", tensors[index].name, start, next)));
+        }
+        next = end;
+    }
+    let data_bytes = file_bytes - 8 - header_len;
+    if next != data_bytes {
+        return Err(fail(format!("tensors cover {} bytes but the file holds {}; truncated?", next, data_bytes)));
+    }
+    let tensors = ranges.iter().map(|&(_, _, index)| tensors[index].clone()).collect();
+    Ok(SafetensorsHeader { tensors, metadata, file_bytes })
+}
+
+/// Weight files of `path`: the file itself, or a directory's `*.safetensors` files, sorted.
+/// A sharded checkpoint's `model.safetensors.index.json` must only name shards that exist.
+pub fn weight_files(path: &Path) -> Result<Vec<std::path::PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<_> = std::fs::read_dir(path)
+        .map_err(|e| format!("Failed to list {}: {}", path.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|file| file.extension().is_some_and(|ext| ext == "safetensors"))
+        .collect();
+    if files.is_empty() {
+        return Err(format!("No .safetensors weights in {}", path.display()));
+    }
+    files.sort();
+    let index = path.join("model.safetensors.index.json");
+    if let Ok(index) = std::fs::read_to_string(&index) {
+        let index: Value = serde_json::from_str(&index).map_err(|e| format!("Invalid model.safetensors.index.json: {}", e))?;
+        let shards: HashSet<&str> = index["weight_map"].as_object().into_iter().flatten().filter_map(|(_, file)| file.as_str()).collect();
+        if let Some(missing) = shards.iter().find(|shard| !path.join(shard).is_file()) {
+            return Err(format!("Shard {} listed in model.safetensors.index.json is missing", missing));
+        }
+    }
+    Ok(files)
+}
+
+/// Validates every weight file of `path` and summarises its tensors.
+pub fn inspect(path: &str) -> Result<SafetensorsInspection, String> {
+    let root = Path::new(path);
+    let mut inspection = SafetensorsInspection {
+        object: "model.inspection".to_string(),
+        path: path.to_string(),
+        files: Vec::new(),
+        parameters: 0,
+        dtypes: BTreeMap::new(),
+        tensors: Vec::new(),
+    };
+    let mut names = HashSet::new();
+    for file in weight_files(root)? {
+        let header = read_header(&file)?;
+        let file_name = file.strip_prefix(root).unwrap_or(&file).display().to_string();
+        let file_name = if file_name.is_empty() { file.file_name().unwrap_or_default().to_string_lossy().into_owned() } else { file_name };
+        inspection.files.push(SafetensorsFileInfo {
+            file: file_name.clone(),
+            tensors: header.tensors.len(),
+            bytes: header.file_bytes,
+            metadata: header.metadata,
+        });
+        for mut tensor in header.tensors {
+            if !names.insert(tensor.name.clone()) {
+                return Err(format!("tensor {} appears in more than one file", tensor.name));
+            }
+            let count: u64 = tensor.shape.iter().product();
+            inspection.parameters += count;
+            *inspection.dtypes.entry(tensor.dtype.clone()).or_default() += count;
+            tensor.file = file_name.clone();
+            inspection.tensors.push(tensor);
+        }
+    }
+    Ok(inspection)
+}
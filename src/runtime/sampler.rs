@@ -46,3 +46,187 @@ pub fn sample_token_index_from_logits(
     }
     Some(indices[0])
 }
+
+/// Generated tokens the repetition penalty looks back over.
+pub const REPETITION_WINDOW: usize = 64;
+
+/// Mirostat settings: sampling that steers towards a constant surprise (`tau`, in bits)
+/// instead of truncating to a fixed number or mass of tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mirostat {
+    // 1 or 2
+    pub version: u8,
+    pub tau: f32,
+    // How fast the target adapts
+    pub eta: f32,
+}
+
+/// Stateful token sampler for one generation: repetition penalty, then either mirostat or
+/// top-k, typical, top-p and min-p truncation, then temperature. A temperature of 0 is greedy.
+pub struct Sampler {
+    temperature: f32,
+    top_p: f32,
+    top_k: u32,
+    min_p: f32,
+    typical_p: f32,
+    repetition_penalty: f32,
+    mirostat: Option<Mirostat>,
+    // Mirostat's running surprise limit
+    mu: f32,
+    recent: std::collections::VecDeque<usize>,
+    rng: StdRng,
+}
+
+impl Sampler {
+    pub fn new(options: &super::GenerationOptions, rng: StdRng) -> Self {
+        Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            min_p: options.min_p,
+            typical_p: options.typical_p,
+            repetition_penalty: options.repetition_penalty,
+            mirostat: options.mirostat,
+            mu: options.mirostat.map_or(0.0, |m| 2.0 * m.tau),
+            recent: std::collections::VecDeque::with_capacity(REPETITION_WINDOW),
+            rng,
+        }
+    }
+
+    /// Picks the next token from `logits` and remembers it for the repetition penalty.
+    pub fn sample(&mut self, logits: &[f32]) -> Option<usize> {
+        if logits.is_empty() {
+            return None;
+        }
+        let mut candidates: Vec<(usize, f32)> = logits.iter().copied().enumerate().collect();
+        if self.repetition_penalty != 1.0 {
+            let recent: std::collections::HashSet<usize> = self.recent.iter().copied().collect();
+            for token in recent {
+                if let Some((_, logit)) = candidates.get_mut(token) {
+                    *logit = if *logit > 0.0 { *logit / self.repetition_penalty } else { *logit * self.repetition_penalty };
+                }
+            }
+        }
+        let token = if self.temperature <= 0.0 {
+            candidates.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|&(i, _)| i)?
+        } else if let Some(mirostat) = self.mirostat {
+            self.sample_mirostat(candidates, mirostat)
+        } else {
+            self.sample_truncated(candidates)
+        };
+        self.accept(token);
+        Some(token)
+    }
+
+    /// Records a token for the repetition penalty without sampling it.
+    pub fn accept(&mut self, token: usize) {
+        if self.recent.len() == REPETITION_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(token);
+    }
+
+    fn sample_truncated(&mut self, mut candidates: Vec<(usize, f32)>) -> usize {
+        if self.top_k > 0 && (self.top_k as usize) < candidates.len() {
+            let k = self.top_k as usize;
+            candidates.select_nth_unstable_by(k - 1, |a, b| b.1.total_cmp(&a.1));
+            candidates.truncate(k);
+        }
+        sort_descending(&mut candidates);
+        let mut probs = softmax(&candidates, 1.0);
+        if self.typical_p < 1.0 {
+            let entropy: f32 = probs.iter().filter(|&&p| p > 0.0).map(|&p| -p * p.ln()).sum();
+            let mut order: Vec<usize> = (0..probs.len()).collect();
+            order.sort_by(|&a, &b| (-probs[a].ln() - entropy).abs().total_cmp(&(-probs[b].ln() - entropy).abs()));
+            let keep = mass_prefix(order.iter().map(|&i| probs[i]), self.typical_p);
+            let mut kept: Vec<usize> = order[..keep].to_vec();
+            kept.sort_unstable();
+            candidates = kept.iter().map(|&i| candidates[i]).collect();
+            probs = softmax(&candidates, 1.0);
+        }
+        if self.top_p < 1.0 {
+            let keep = mass_prefix(probs.iter().copied(), self.top_p);
+            candidates.truncate(keep);
+            probs.truncate(keep);
+        }
+        if self.min_p > 0.0 {
+            let floor = self.min_p * probs[0];
+            let keep = probs.iter().take_while(|&&p| p >= floor).count().max(1);
+            candidates.truncate(keep);
+        }
+        let probs = softmax(&candidates, self.temperature);
+        candidates[self.pick(&probs)].0
+    }
+
+    fn sample_mirostat(&mut self, mut candidates: Vec<(usize, f32)>, mirostat: Mirostat) -> usize {
+        sort_descending(&mut candidates);
+        let probs = softmax(&candidates, self.temperature);
+        let keep = if mirostat.version == 1 {
+            // Estimate the Zipf exponent from the top tokens, then the k that gives surprise mu
+            let m = 100.min(probs.len() - 1);
+            let (mut sum_ti_bi, mut sum_ti_sq) = (0.0f32, 0.0f32);
+            for i in 0..m {
+                let t = ((i + 2) as f32 / (i + 1) as f32).ln();
+                let b = (probs[i] / probs[i + 1]).ln();
+                if b.is_finite() {
+                    sum_ti_bi += t * b;
+                    sum_ti_sq += t * t;
+                }
+            }
+            let s_hat = if sum_ti_sq > 0.0 { sum_ti_bi / sum_ti_sq } else { 1.0 };
+            let epsilon = s_hat - 1.0;
+            let n = probs.len() as f32;
+            let k = ((epsilon * 2f32.powf(self.mu)) / (1.0 - n.powf(-epsilon))).powf(1.0 / s_hat);
+            if k.is_finite() { (k.round() as usize).clamp(1, probs.len()) } else { probs.len() }
+        } else {
+            probs.iter().take_while(|&&p| -p.log2() <= self.mu).count().max(1)
+        };
+        candidates.truncate(keep);
+        let probs = softmax(&candidates, self.temperature);
+        let index = self.pick(&probs);
+        let surprise = -probs[index].log2();
+        self.mu -= mirostat.eta * (surprise - mirostat.tau);
+        candidates[index].0
+    }
+
+    fn pick(&mut self, probs: &[f32]) -> usize {
+        let mut r = self.rng.r#gen::<f32>();
+        for (i, &p) in probs.iter().enumerate() {
+            if r <= p {
+                return i;
+            }
+            r -= p;
+        }
+        0
+    }
+}
+
+fn sort_descending(candidates: &mut [(usize, f32)]) {
+    candidates.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+}
+
+// Probabilities of `candidates` at `temperature`, in the same order
+fn softmax(candidates: &[(usize, f32)], temperature: f32) -> Vec<f32> {
+    let t = temperature.max(1e-6);
+    let max = candidates.iter().map(|&(_, l)| l).fold(f32::NEG_INFINITY, f32::max);
+    let mut probs: Vec<f32> = candidates.iter().map(|&(_, l)| ((l - max) / t).exp()).collect();
+    let sum: f32 = probs.iter().sum();
+    for p in &mut probs {
+        *p /= sum;
+    }
+    probs
+}
+
+// How many leading probabilities it takes to reach `mass`; at least one
+fn mass_prefix(probs: impl Iterator<Item = f32>, mass: f32) -> usize {
+    let mut cumulative = 0.0;
+    let mut count = 0;
+    for p in probs {
+        cumulative += p;
+        count += 1;
+        if cumulative >= mass {
+            break;
+        }
+    }
+    count.max(1)
+}
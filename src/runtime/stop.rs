@@ -0,0 +1,90 @@
+//! Stop sequences: generated text is watched for any of the request's stop strings, and
+//! generation ends where the first one starts. The stop string itself is never emitted.
+
+use std::sync::{Arc, Mutex};
+
+/// Most stop sequences a request may set, as OpenAI allows.
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+/// End-of-turn tokens of common chat formats (Llama 3, ChatML, Phi, Gemma, GPT-style). Chat
+/// models emit these rather than, or before, their EOS token; generation ends at them too.
+pub const END_OF_TURN_MARKERS: &[&str] = &["<|eot_id|>", "<|end_of_text|>", "<|im_end|>", "<|end|>", "<end_of_turn>", "<|endoftext|>"];
+
+/// The stop string that ended a generation, filled in by its `StopMatcher`. Clones share it, so
+/// it can travel in `GenerationOptions` and be read once the runtime returns.
+#[derive(Debug, Clone, Default)]
+pub struct MatchedStop(Arc<Mutex<Option<String>>>);
+
+impl MatchedStop {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, stop: &str) {
+        *self.0.lock().unwrap() = Some(stop.to_string());
+    }
+}
+
+/// Matches stop sequences across streamed pieces. Text that could be the start of a stop
+/// sequence is held back until the next pieces show whether it is one.
+#[derive(Debug, Default)]
+pub struct StopMatcher {
+    stops: Vec<String>,
+    held: String,
+    stopped: bool,
+    report: Option<MatchedStop>,
+}
+
+impl StopMatcher {
+    pub fn new(stops: &[String]) -> Self {
+        Self { stops: stops.iter().filter(|s| !s.is_empty()).cloned().collect(), ..Self::default() }
+    }
+
+    /// Also records the stop string that ends generation in `matched`.
+    pub fn reporting_to(mut self, matched: &MatchedStop) -> Self {
+        self.report = Some(matched.clone());
+        self
+    }
+
+    /// Adds a generated piece and returns the text that can be emitted. Once a stop sequence
+    /// is found this is the text before it, `is_stopped` turns true and later pieces are dropped.
+    pub fn push(&mut self, piece: &str) -> String {
+        if self.stopped {
+            return String::new();
+        }
+        if self.stops.is_empty() {
+            return piece.to_string();
+        }
+        self.held.push_str(piece);
+        if let Some((at, stop)) = self.stops.iter().filter_map(|stop| Some((self.held.find(stop.as_str())?, stop))).min_by_key(|(at, _)| *at) {
+            self.stopped = true;
+            if let Some(report) = &self.report {
+                report.set(stop);
+            }
+            self.held.truncate(at);
+            return std::mem::take(&mut self.held);
+        }
+        let keep = self.stops.iter().map(|stop| partial_match(&self.held, stop)).max().unwrap_or(0);
+        let rest = self.held.split_off(self.held.len() - keep);
+        std::mem::replace(&mut self.held, rest)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Text still held back, for when generation ends without reaching a stop sequence.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+}
+
+// Length of the longest end of `text` that is a proper start of `stop`
+fn partial_match(text: &str, stop: &str) -> usize {
+    stop.char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .filter(|&n| text.ends_with(&stop[..n]))
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,223 @@
+//! Preprocessing shared by vision runtimes, between image fetching and the encoder: resizing,
+//! padding or cropping to the encoder's square input, normalization and, for `detail: high`,
+//! tiling so fine detail survives the downscale.
+
+use crate::runtime::image::RgbImage;
+
+pub const CLIP_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+pub const CLIP_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+
+/// The `detail` of an `image_url` part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageDetail {
+    /// High when the image is larger than one encoder tile, low otherwise.
+    #[default]
+    Auto,
+    /// One downscaled view of the whole image.
+    Low,
+    /// The downscaled view followed by full-resolution tiles.
+    High,
+}
+
+impl ImageDetail {
+    /// Unknown values fall back to `auto`, as OpenAI does.
+    pub fn parse(detail: Option<&str>) -> Self {
+        match detail {
+            Some("low") => ImageDetail::Low,
+            Some("high") => ImageDetail::High,
+            _ => ImageDetail::Auto,
+        }
+    }
+}
+
+/// An image a request refers to, as it reaches a multimodal runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInput {
+    pub url: String,
+    pub detail: ImageDetail,
+}
+
+/// How non-square images are made square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquareMode {
+    /// Letterbox with the mean colour, keeping the whole image (LLaVA).
+    Pad,
+    /// Scale the shorter side to fit and cut the centre out (CLIP).
+    CenterCrop,
+}
+
+/// Input format of a vision encoder.
+#[derive(Debug, Clone)]
+pub struct VisionInputSpec {
+    // Side of the square the encoder takes
+    pub size: usize,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub square: SquareMode,
+    // Most tiles for `detail: high`, besides the overview; 0 disables tiling
+    pub max_tiles: usize,
+}
+
+impl VisionInputSpec {
+    /// CLIP normalization at `size`, with `max_tiles` from VISION_MAX_TILES (default 4).
+    pub fn clip(size: usize, square: SquareMode) -> Self {
+        let max_tiles = crate::config::var("VISION_MAX_TILES").ok().and_then(|v| v.parse().ok()).unwrap_or(4);
+        Self { size, mean: CLIP_MEAN, std: CLIP_STD, square, max_tiles }
+    }
+}
+
+/// Encoder inputs for one image: normalized CHW tensors of `size * size * 3` floats, the
+/// overview first, then any tiles row by row.
+pub fn preprocess(image: &RgbImage, detail: ImageDetail, spec: &VisionInputSpec) -> Vec<Vec<f32>> {
+    let size = spec.size as u32;
+    let mut views = vec![normalize(&to_square(image, size, spec), spec)];
+    let high = match detail {
+        ImageDetail::High => true,
+        ImageDetail::Low => false,
+        ImageDetail::Auto => image.width > size || image.height > size,
+    };
+    if !high || spec.max_tiles == 0 {
+        return views;
+    }
+    let (cols, rows) = select_grid(image.width, image.height, size, spec.max_tiles);
+    if cols * rows == 1 {
+        return views; // the tile would repeat the overview
+    }
+    let canvas = letterbox(image, cols as u32 * size, rows as u32 * size, fill_color(spec));
+    for row in 0..rows as u32 {
+        for col in 0..cols as u32 {
+            views.push(normalize(&crop(&canvas, col * size, row * size, size, size), spec));
+        }
+    }
+    views
+}
+
+/// Tile grid (columns, rows) of at most `max_tiles` tiles that shows the most of the image at
+/// full resolution, preferring the least padding on ties (LLaVA-NeXT's "anyres").
+pub fn select_grid(width: u32, height: u32, size: u32, max_tiles: usize) -> (usize, usize) {
+    let original = width as f64 * height as f64;
+    let mut best = (1, 1);
+    let (mut best_effective, mut best_waste) = (-1.0, f64::MAX);
+    for cols in 1..=max_tiles.max(1) {
+        for rows in 1..=max_tiles.max(1) / cols {
+            let (canvas_w, canvas_h) = ((cols as u32 * size) as f64, (rows as u32 * size) as f64);
+            let scale = (canvas_w / width as f64).min(canvas_h / height as f64);
+            let effective = (width as f64 * scale * height as f64 * scale).min(original);
+            let waste = canvas_w * canvas_h - effective;
+            if effective > best_effective || (effective == best_effective && waste < best_waste) {
+                (best, best_effective, best_waste) = ((cols, rows), effective, waste);
+            }
+        }
+    }
+    best
+}
+
+fn fill_color(spec: &VisionInputSpec) -> [u8; 3] {
+    spec.mean.map(|m| (m * 255.0).round() as u8)
+}
+
+fn to_square(image: &RgbImage, size: u32, spec: &VisionInputSpec) -> RgbImage {
+    match spec.square {
+        SquareMode::Pad => letterbox(image, size, size, fill_color(spec)),
+        SquareMode::CenterCrop => {
+            let scale = (size as f64 / image.width as f64).max(size as f64 / image.height as f64);
+            let (w, h) = (((image.width as f64 * scale).round() as u32).max(size), ((image.height as f64 * scale).round() as u32).max(size));
+            let resized = resize(image, w, h);
+            crop(&resized, (w - size) / 2, (h - size) / 2, size, size)
+        }
+    }
+}
+
+/// `image` scaled to fit inside `width` x `height`, centred on `fill`.
+pub fn letterbox(image: &RgbImage, width: u32, height: u32, fill: [u8; 3]) -> RgbImage {
+    let scale = (width as f64 / image.width as f64).min(height as f64 / image.height as f64);
+    let w = ((image.width as f64 * scale).round() as u32).clamp(1, width);
+    let h = ((image.height as f64 * scale).round() as u32).clamp(1, height);
+    let resized = resize(image, w, h);
+    let mut pixels: Vec<u8> = fill.iter().copied().cycle().take(width as usize * height as usize * 3).collect();
+    let (x0, y0) = (((width - w) / 2) as usize, ((height - h) / 2) as usize);
+    for y in 0..h as usize {
+        let src = &resized.pixels[y * w as usize * 3..(y + 1) * w as usize * 3];
+        let start = ((y0 + y) * width as usize + x0) * 3;
+        pixels[start..start + src.len()].copy_from_slice(src);
+    }
+    RgbImage { width, height, pixels }
+}
+
+pub fn crop(image: &RgbImage, x: u32, y: u32, width: u32, height: u32) -> RgbImage {
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in y..y + height {
+        let start = (row as usize * image.width as usize + x as usize) * 3;
+        pixels.extend_from_slice(&image.pixels[start..start + width as usize * 3]);
+    }
+    RgbImage { width, height, pixels }
+}
+
+/// Resamples with a triangle filter widened by the scale factor when shrinking, so downscaled
+/// photos average their pixels instead of aliasing (as PIL's bilinear resize does).
+pub fn resize(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    if (width, height) == (image.width, image.height) {
+        return image.clone();
+    }
+    let horizontal = resample_weights(image.width as usize, width as usize);
+    let vertical = resample_weights(image.height as usize, height as usize);
+    let (src_w, dst_w) = (image.width as usize, width as usize);
+
+    // Rows first into floats, then columns
+    let mut rows = vec![0.0f32; image.height as usize * dst_w * 3];
+    for y in 0..image.height as usize {
+        for (x, (start, weights)) in horizontal.iter().enumerate() {
+            for c in 0..3 {
+                rows[(y * dst_w + x) * 3 + c] = weights.iter().enumerate().map(|(i, w)| w * image.pixels[(y * src_w + start + i) * 3 + c] as f32).sum();
+            }
+        }
+    }
+    let mut pixels = Vec::with_capacity(dst_w * height as usize * 3);
+    for (start, weights) in &vertical {
+        for x in 0..dst_w {
+            for c in 0..3 {
+                let value: f32 = weights.iter().enumerate().map(|(i, w)| w * rows[((start + i) * dst_w + x) * 3 + c]).sum();
+                pixels.push(value.round().clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+    RgbImage { width, height, pixels }
+}
+
+// Per output position: first source index and normalized weights from there on
+fn resample_weights(src: usize, dst: usize) -> Vec<(usize, Vec<f32>)> {
+    let scale = src as f32 / dst as f32;
+    let support = scale.max(1.0);
+    (0..dst)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let start = ((center - support).floor().max(0.0)) as usize;
+            let end = ((center + support).ceil() as usize).min(src);
+            let mut weights: Vec<f32> = (start..end)
+                .map(|j| (1.0 - ((j as f32 + 0.5 - center) / support).abs()).max(0.0))
+                .collect();
+            let total: f32 = weights.iter().sum();
+            if total > 0.0 {
+                weights.iter_mut().for_each(|w| *w /= total);
+            } else {
+                // Degenerate window: take the nearest pixel
+                weights = vec![0.0; end - start];
+                let nearest = (center as usize).clamp(start, end - 1);
+                weights[nearest - start] = 1.0;
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+/// CHW floats, scaled to [0, 1] and normalized per channel.
+pub fn normalize(image: &RgbImage, spec: &VisionInputSpec) -> Vec<f32> {
+    let plane = image.width as usize * image.height as usize;
+    let mut out = vec![0.0f32; plane * 3];
+    for (i, pixel) in image.pixels.chunks_exact(3).enumerate() {
+        for c in 0..3 {
+            out[c * plane + i] = (pixel[c] as f32 / 255.0 - spec.mean[c]) / spec.std[c];
+        }
+    }
+    out
+}
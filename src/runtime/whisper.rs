@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+use crate::api::dto::TranscriptionSegment;
+use crate::runtime::{AudioTranscriptionRuntime, RuntimeError, Transcription, TranscriptionOptions};
+
+/// whisper.cpp through `whisper-rs`; loads a ggml whisper model (e.g. `ggml-base.en.bin`).
+pub struct WhisperRuntime {
+    context: Arc<WhisperContext>,
+}
+
+impl WhisperRuntime {
+    pub fn new(model_path: &str) -> Result<Self, String> {
+        let mut params = WhisperContextParameters::default();
+        params.use_gpu = !crate::runtime::accel::compat_mode();
+        let context = WhisperContext::new_with_params(model_path, params)
+            .map_err(|e| format!("Failed to load whisper model {}: {}", model_path, e))?;
+        Ok(Self { context: Arc::new(context) })
+    }
+}
+
+#[async_trait]
+impl AudioTranscriptionRuntime for WhisperRuntime {
+    async fn transcribe(&self, samples: &[f32], options: &TranscriptionOptions) -> Result<Transcription, RuntimeError> {
+        let context = self.context.clone();
+        let samples = samples.to_vec();
+        let options = options.clone();
+        // Decoding is CPU-bound and synchronous; keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            let mut state = context.create_state().map_err(|e| format!("whisper state error: {}", e))?;
+            let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+            params.set_language(Some(options.language.as_deref().unwrap_or("auto")));
+            if let Some(prompt) = &options.prompt {
+                params.set_initial_prompt(prompt);
+            }
+            params.set_temperature(options.temperature);
+            params.set_print_progress(false);
+            params.set_print_realtime(false);
+            state.full(params, &samples).map_err(|e| format!("whisper decode error: {}", e))?;
+
+            let n = state.full_n_segments().map_err(|e| format!("whisper error: {}", e))?;
+            let mut segments = Vec::with_capacity(n as usize);
+            for i in 0..n {
+                let text = state.full_get_segment_text_lossy(i).map_err(|e| format!("whisper error: {}", e))?;
+                // Timestamps are in centiseconds
+                let start = state.full_get_segment_t0(i).map_err(|e| format!("whisper error: {}", e))? as f32 / 100.0;
+                let end = state.full_get_segment_t1(i).map_err(|e| format!("whisper error: {}", e))? as f32 / 100.0;
+                segments.push(TranscriptionSegment { id: i as usize, start, end, text: text.trim().to_string() });
+            }
+            let language = state
+                .full_lang_id_from_state()
+                .ok()
+                .and_then(whisper_rs::get_lang_str)
+                .map(String::from);
+            Ok::<_, RuntimeError>(Transcription {
+                text: segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "),
+                language,
+                segments,
+            })
+        })
+        .await
+        .map_err(|e| RuntimeError::Backend(format!("whisper task failed: {}", e)))?
+    }
+}
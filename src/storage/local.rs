@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+use std::{io::ErrorKind, path::PathBuf};
+
+use crate::storage::{validate_key, ObjectInfo, Storage};
+
+/// Objects as files below a root directory, one file per key.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// The root is created on the first write.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    fn backend(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| format!("create {:?}: {}", parent, e))?;
+        }
+        // Write-then-rename so readers never see a partially written object
+        let tmp = path.with_file_name(format!(".{}.{}.tmp", path.file_name().unwrap_or_default().to_string_lossy(), uuid::Uuid::new_v4()));
+        tokio::fs::write(&tmp, bytes).await.map_err(|e| format!("write {:?}: {}", tmp, e))?;
+        tokio::fs::rename(&tmp, &path).await.map_err(|e| format!("rename into {:?}: {}", path, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = self.path(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("read {:?}: {}", path, e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, String> {
+        let path = self.path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("delete {:?}: {}", path, e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, String> {
+        let mut objects = Vec::new();
+        let mut pending = vec![(self.root.clone(), String::new())];
+        while let Some((dir, key_prefix)) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("list {:?}: {}", dir, e)),
+            };
+            while let Some(entry) = entries.next_entry().await.map_err(|e| format!("list {:?}: {}", dir, e))? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // In-progress writes
+                if name.starts_with('.') {
+                    continue;
+                }
+                let key = format!("{}{}", key_prefix, name);
+                let metadata = entry.metadata().await.map_err(|e| format!("stat {:?}: {}", entry.path(), e))?;
+                if metadata.is_dir() {
+                    // Only descend into directories that can contain matching keys
+                    let dir_key = format!("{}/", key);
+                    if dir_key.starts_with(prefix) || prefix.starts_with(&dir_key) {
+                        pending.push((entry.path(), dir_key));
+                    }
+                } else if key.starts_with(prefix) {
+                    objects.push(ObjectInfo { key, size: metadata.len() });
+                }
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tokio::sync::RwLock;
+
+use crate::storage::{validate_key, ObjectInfo, Storage};
+
+/// Objects kept in process memory; for tests and deployments that need no durability.
+#[derive(Default)]
+pub struct MemoryStorage {
+    objects: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        validate_key(key)?;
+        self.objects.write().await.insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        validate_key(key)?;
+        Ok(self.objects.read().await.get(key).cloned())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, String> {
+        validate_key(key)?;
+        Ok(self.objects.write().await.remove(key).is_some())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, String> {
+        let objects = self.objects.read().await;
+        Ok(objects
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, bytes)| ObjectInfo { key: key.clone(), size: bytes.len() as u64 })
+            .collect())
+    }
+}
@@ -0,0 +1,139 @@
+//! One persistence layer for everything the server writes outside memory (uploaded files, image
+//! artifacts, batch job state, audit exports).
+//!
+//! The backend is configured once from the environment and shared, so every feature stores its
+//! objects under its own key prefix in the same place with the same credentials.
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+pub mod local;
+pub mod memory;
+pub mod s3;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::{S3Config, S3Storage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+}
+
+/// Flat key/value object store. Keys are `/`-separated relative paths such as
+/// `files/file-abc123`; see `validate_key`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Backend name for logs and metrics ("local", "memory", "s3").
+    fn backend(&self) -> &'static str;
+
+    /// Stores `bytes` under `key`, replacing any previous object atomically.
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String>;
+
+    /// The object under `key`, or None if there is none.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// Removes the object under `key`; returns whether it existed.
+    async fn delete(&self, key: &str) -> Result<bool, String>;
+
+    /// Objects whose key starts with `prefix`, sorted by key.
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, String>;
+}
+
+/// Rejects keys that could escape the storage root or map ambiguously onto backends: empty
+/// keys, absolute paths, empty or dot-prefixed segments (which covers `..`; the local backend
+/// uses dot files for in-progress writes), backslashes and control characters.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    let valid = !key.is_empty()
+        && !key.chars().any(|c| c.is_control() || c == '\\')
+        && key.split('/').all(|segment| !segment.is_empty() && !segment.starts_with('.'));
+    if valid { Ok(()) } else { Err(format!("invalid storage key {:?}", key)) }
+}
+
+/// A view of another store with every key placed under `prefix`, so each feature owns a
+/// namespace without knowing where the shared backend puts it.
+pub struct Scoped {
+    inner: Arc<dyn Storage>,
+    prefix: String,
+}
+
+impl Scoped {
+    pub fn new(inner: Arc<dyn Storage>, prefix: &str) -> Self {
+        Self { inner, prefix: format!("{}/", prefix.trim_end_matches('/')) }
+    }
+}
+
+#[async_trait]
+impl Storage for Scoped {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        validate_key(key)?;
+        self.inner.put(&format!("{}{}", self.prefix, key), bytes).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        validate_key(key)?;
+        self.inner.get(&format!("{}{}", self.prefix, key)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, String> {
+        validate_key(key)?;
+        self.inner.delete(&format!("{}{}", self.prefix, key)).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, String> {
+        let objects = self.inner.list(&format!("{}{}", self.prefix, prefix)).await?;
+        Ok(objects
+            .into_iter()
+            .filter_map(|o| Some(ObjectInfo { key: o.key.strip_prefix(&self.prefix)?.to_string(), size: o.size }))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    Local { root: String },
+    Memory,
+    S3(S3Config),
+}
+
+impl StorageConfig {
+    /// ENV: STORAGE_BACKEND (local | memory | s3, default local), STORAGE_PATH (local root,
+    /// default ./data); the s3 backend reads `S3Config::from_env`.
+    pub fn from_env() -> Result<Self, String> {
+        match crate::config::var("STORAGE_BACKEND").as_deref().unwrap_or("local") {
+            "local" => Ok(StorageConfig::Local { root: crate::config::var("STORAGE_PATH").unwrap_or_else(|_| "./data".to_string()) }),
+            "memory" => Ok(StorageConfig::Memory),
+            "s3" => S3Config::from_env().map(StorageConfig::S3),
+            other => Err(format!("unknown STORAGE_BACKEND {:?} (expected local, memory or s3)", other)),
+        }
+    }
+
+    pub fn build(self) -> Arc<dyn Storage> {
+        match self {
+            StorageConfig::Local { root } => Arc::new(LocalStorage::new(root)),
+            StorageConfig::Memory => Arc::new(MemoryStorage::default()),
+            StorageConfig::S3(config) => Arc::new(S3Storage::new(config)),
+        }
+    }
+}
+
+static STORAGE: Lazy<Arc<dyn Storage>> = Lazy::new(|| match StorageConfig::from_env() {
+    Ok(config) => config.build(),
+    Err(e) => panic!("storage configuration: {}", e),
+});
+
+/// The server-wide store. Panics on an invalid configuration, so call it once at startup.
+pub fn global() -> Arc<dyn Storage> {
+    STORAGE.clone()
+}
+
+/// The server-wide store restricted to `prefix` (e.g. "files", "images", "batches").
+pub fn scoped(prefix: &str) -> Arc<dyn Storage> {
+    Arc::new(Scoped::new(global(), prefix))
+}
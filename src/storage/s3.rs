@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+
+use crate::outbound;
+use crate::storage::{validate_key, ObjectInfo, Storage};
+
+// Objects read back through the API are bounded like any other outbound body
+const MAX_OBJECT_BYTES: usize = 1 << 30;
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    // Base URL; path-style addressing (`<endpoint>/<bucket>/<key>`) also covers MinIO and R2
+    pub endpoint: String,
+    // Prepended to every key, e.g. "llm-serving/"
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl S3Config {
+    /// ENV: STORAGE_S3_BUCKET (required), STORAGE_S3_REGION (default us-east-1),
+    /// STORAGE_S3_ENDPOINT (default https://s3.<region>.amazonaws.com), STORAGE_S3_PREFIX,
+    /// AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY (required) and AWS_SESSION_TOKEN
+    pub fn from_env() -> Result<Self, String> {
+        let required = |name: &str| crate::config::var(name).ok().filter(|v| !v.is_empty()).ok_or_else(|| format!("{} is required for the s3 storage backend", name));
+        let region = crate::config::var("STORAGE_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        Ok(Self {
+            bucket: required("STORAGE_S3_BUCKET")?,
+            endpoint: crate::config::var("STORAGE_S3_ENDPOINT")
+                .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region))
+                .trim_end_matches('/')
+                .to_string(),
+            region,
+            prefix: crate::config::var("STORAGE_S3_PREFIX").unwrap_or_default(),
+            access_key_id: required("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required("AWS_SECRET_ACCESS_KEY")?,
+            session_token: crate::config::var("AWS_SESSION_TOKEN").ok().filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+/// Objects in an S3-compatible bucket, signed with AWS Signature Version 4.
+pub struct S3Storage {
+    config: S3Config,
+    client: outbound::OutboundClient,
+}
+
+impl S3Storage {
+    pub fn new(config: S3Config) -> Self {
+        Self { config, client: outbound::client("storage") }
+    }
+
+    // `key` None addresses the bucket itself (listing)
+    async fn send(&self, method: Method, key: Option<&str>, query: &[(&str, String)], body: Vec<u8>) -> Result<outbound::OutboundResponse, String> {
+        let mut path = format!("/{}", uri_encode(&self.config.bucket, true));
+        if let Some(key) = key {
+            path.push_str(&format!("/{}", uri_encode(&format!("{}{}", self.config.prefix, key), false)));
+        }
+        let url = reqwest::Url::parse(&format!("{}{}", self.config.endpoint, path)).map_err(|e| format!("invalid S3 endpoint: {}", e))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (uri_encode(k, true), uri_encode(v, true))).collect();
+        query.sort();
+        let query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let amz_date = amz_timestamp(now);
+        let payload_hash = hex(&Sha256::digest(&body));
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.config.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sign(&self.config, method.as_str(), &path, &query, &headers, &payload_hash, &amz_date);
+
+        let url = if query.is_empty() { format!("{}{}", self.config.endpoint, path) } else { format!("{}{}?{}", self.config.endpoint, path, query) };
+        let mut request = self.client.request(method, &url).header("authorization", authorization).body(body);
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        self.client.send(request).await
+    }
+
+    async fn error(response: outbound::OutboundResponse, action: &str, key: &str) -> String {
+        let status = response.status();
+        let body = response.bytes_limited(64 * 1024).await.unwrap_or_default();
+        let code = capture(&CODE, &String::from_utf8_lossy(&body)).into_iter().next().unwrap_or_default();
+        format!("S3 {} {} failed: {} {}", action, key, status, code)
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    fn backend(&self) -> &'static str {
+        "s3"
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        validate_key(key)?;
+        let response = self.send(Method::PUT, Some(key), &[], bytes).await?;
+        if !response.status().is_success() {
+            return Err(Self::error(response, "put", key).await);
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        validate_key(key)?;
+        let response = self.send(Method::GET, Some(key), &[], Vec::new()).await?;
+        match response.status() {
+            status if status.is_success() => response.bytes_limited(MAX_OBJECT_BYTES).await.map(Some),
+            reqwest::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(Self::error(response, "get", key).await),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, String> {
+        validate_key(key)?;
+        // S3 deletes are idempotent and do not report whether the object existed; a HEAD
+        // answers that without pulling the body
+        let response = self.send(Method::HEAD, Some(key), &[], Vec::new()).await?;
+        let existed = match response.status() {
+            status if status.is_success() => true,
+            reqwest::StatusCode::NOT_FOUND => false,
+            _ => return Err(Self::error(response, "head", key).await),
+        };
+        let response = self.send(Method::DELETE, Some(key), &[], Vec::new()).await?;
+        if !response.status().is_success() {
+            return Err(Self::error(response, "delete", key).await);
+        }
+        Ok(existed)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, String> {
+        let mut objects = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", format!("{}{}", self.config.prefix, prefix))];
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send(Method::GET, None, &query, Vec::new()).await?;
+            if !response.status().is_success() {
+                return Err(Self::error(response, "list", prefix).await);
+            }
+            let body = String::from_utf8_lossy(&response.bytes_limited(16 * 1024 * 1024).await?).to_string();
+            for contents in capture(&CONTENTS, &body) {
+                let key = xml_unescape(&capture(&KEY, &contents).into_iter().next().unwrap_or_default());
+                let size = capture(&SIZE, &contents).into_iter().next().and_then(|s| s.parse().ok()).unwrap_or(0);
+                if let Some(key) = key.strip_prefix(&self.config.prefix) {
+                    objects.push(ObjectInfo { key: key.to_string(), size });
+                }
+            }
+            continuation = capture(&NEXT_TOKEN, &body).into_iter().next().map(|t| xml_unescape(&t));
+            if continuation.is_none() {
+                break;
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+}
+
+// ListObjectsV2 responses are small and flat; a few patterns beat an XML parser dependency
+static CONTENTS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<Contents>(.*?)</Contents>").unwrap());
+static KEY: Lazy<Regex> = Lazy::new(|| Regex::new(r"<Key>(.*?)</Key>").unwrap());
+static SIZE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<Size>(\d+)</Size>").unwrap());
+static NEXT_TOKEN: Lazy<Regex> = Lazy::new(|| Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap());
+static CODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<Code>(.*?)</Code>").unwrap());
+
+fn capture(pattern: &Regex, text: &str) -> Vec<String> {
+    pattern.captures_iter(text).map(|c| c[1].to_string()).collect()
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters; `/` too when `encode_slash`.
+pub fn uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `YYYYMMDDTHHMMSSZ` for a Unix timestamp.
+pub fn amz_timestamp(unix_secs: u64) -> String {
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let days = (unix_secs / 86_400) as i64;
+    let secs = unix_secs % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// The SigV4 `Authorization` header value. `headers` must have lowercase names and is signed
+/// in full.
+pub fn sign(
+    config: &S3Config,
+    method: &str,
+    canonical_path: &str,
+    canonical_query: &str,
+    headers: &[(&str, String)],
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    let mut headers: Vec<&(&str, String)> = headers.iter().collect();
+    headers.sort_by_key(|(name, _)| *name);
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, canonical_path, canonical_query, canonical_headers, signed_headers, payload_hash);
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes())));
+    let key = [date, config.region.as_str(), "s3", "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", config.secret_access_key).into_bytes(), |key, part| hmac(&key, part));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac(&key, &string_to_sign))
+    )
+}
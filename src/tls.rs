@@ -0,0 +1,372 @@
+//! HTTPS for the HTTP listeners, so the server can face clients without a TLS-terminating
+//! proxy. The certificate is read from PEM files and read again whenever they change, so a
+//! renewed certificate is picked up by new connections without a restart. With a client CA
+//! bundle, read again the same way, the listeners also verify client certificates and pass each
+//! request on with the certificate's identity, which authentication then treats like an API key.
+
+use axum::{extract::Request, http::HeaderValue, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use metrics::counter;
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::danger::HandshakeSignatureValid,
+        pki_types::{CertificateDer, PrivateKeyDer, UnixTime},
+        server::{
+            danger::{ClientCertVerified, ClientCertVerifier},
+            ClientHello, ResolvesServerCert, WebPkiClientVerifier,
+        },
+        sign::CertifiedKey,
+        DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme,
+    },
+    TlsAcceptor,
+};
+use tower::ServiceExt;
+
+use crate::api::auth::CLIENT_PRINCIPAL_HEADER;
+
+// Clients that have not finished the handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ENV: TLS_CERT_PATH and TLS_KEY_PATH, PEM files of the certificate chain and its private key;
+/// when both are set the HTTP listeners serve HTTPS only
+pub fn paths_from_env() -> Result<Option<(PathBuf, PathBuf)>, String> {
+    match (path_var("TLS_CERT_PATH"), path_var("TLS_KEY_PATH")) {
+        (Some(cert), Some(key)) => Ok(Some((cert, key))),
+        (None, None) if client_auth_enabled() => Err("TLS_CLIENT_CA_PATH needs TLS_CERT_PATH and TLS_KEY_PATH".to_string()),
+        (None, None) => Ok(None),
+        _ => Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string()),
+    }
+}
+
+fn path_var(name: &str) -> Option<PathBuf> {
+    crate::config::var(name).ok().filter(|p| !p.is_empty()).map(PathBuf::from)
+}
+
+/// ENV: TLS_CLIENT_CA_PATH, PEM bundle of the CAs client certificates must chain to; when set
+/// the HTTPS listeners verify client certificates, and TLS_CLIENT_AUTH says whether one is
+/// `required` (default) or `optional`, leaving clients without one to API keys
+pub fn client_auth_enabled() -> bool {
+    path_var("TLS_CLIENT_CA_PATH").is_some()
+}
+
+/// The client certificate check `TLS_CLIENT_CA_PATH` and `TLS_CLIENT_AUTH` ask for, if any.
+pub fn client_verifier_from_env() -> Result<Option<Arc<ClientCaStore>>, String> {
+    let Some(path) = path_var("TLS_CLIENT_CA_PATH") else { return Ok(None) };
+    let required = match crate::config::var("TLS_CLIENT_AUTH").as_deref() {
+        Ok("required") | Err(_) => true,
+        Ok("optional") => false,
+        Ok(other) => return Err(format!("TLS_CLIENT_AUTH must be required or optional, not {:?}", other)),
+    };
+    ClientCaStore::load(&path, required).map(Some)
+}
+
+/// Verifies client certificates against the CAs in the PEM bundle at `ca_path`; without
+/// `required`, clients may also connect without a certificate.
+pub fn client_verifier(ca_path: &Path, required: bool) -> Result<Arc<dyn ClientCertVerifier>, String> {
+    let pem = std::fs::read(ca_path).map_err(|e| format!("read {}: {}", ca_path.display(), e))?;
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+        let cert = cert.map_err(|e| format!("parse {}: {}", ca_path.display(), e))?;
+        roots.add(cert).map_err(|e| format!("{}: {}", ca_path.display(), e))?;
+    }
+    if roots.is_empty() {
+        return Err(format!("{} holds no certificate", ca_path.display()));
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+    let builder = if required { builder } else { builder.allow_unauthenticated() };
+    builder.build().map_err(|e| format!("{}: {}", ca_path.display(), e))
+}
+
+/// Identity of a verified client certificate: its first URI name (a SPIFFE ID, say), else its
+/// first DNS name, else a digest of the certificate.
+pub fn principal(cert: &CertificateDer<'_>) -> String {
+    let names = webpki::EndEntityCert::try_from(cert)
+        .ok()
+        .and_then(|cert| cert.valid_uri_names().chain(cert.valid_dns_names()).next().map(str::to_string));
+    names.unwrap_or_else(|| format!("sha256:{}", &format!("{:x}", Sha256::digest(cert.as_ref()))[..16]))
+}
+
+/// ENV: TLS_RELOAD_INTERVAL_SECS, how often the certificate files are checked for changes
+/// (default 60; 0 turns reloading off)
+fn reload_interval() -> Option<Duration> {
+    let secs = crate::config::var("TLS_RELOAD_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// The certificate handed to new connections, replaced when its files change. A certificate
+/// that fails to load leaves the previous one in place.
+pub struct CertStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    // Modification times of the files the current certificate was read from
+    seen: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl std::fmt::Debug for CertStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertStore").field("cert_path", &self.cert_path).field("key_path", &self.key_path).finish()
+    }
+}
+
+impl CertStore {
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Arc<Self>, String> {
+        let seen = (modified(cert_path), modified(key_path));
+        let key = certified_key(cert_path, key_path)?;
+        Ok(Arc::new(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(Arc::new(key)),
+            seen: Mutex::new(seen),
+        }))
+    }
+
+    /// Reads the files again if they changed since the last read; true when a new certificate
+    /// was installed.
+    pub fn reload(&self) -> Result<bool, String> {
+        let now = (modified(&self.cert_path), modified(&self.key_path));
+        let mut seen = self.seen.lock().unwrap();
+        if *seen == now {
+            return Ok(false);
+        }
+        // Remembered even on failure, so a half-written pair is reported once and read again
+        // when the second file lands
+        *seen = now;
+        let key = certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(key);
+        Ok(true)
+    }
+
+    /// Checks the files every `TLS_RELOAD_INTERVAL_SECS` for as long as the store is in use.
+    pub fn watch(self: &Arc<Self>) {
+        let Some(interval) = reload_interval() else { return };
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(store) = store.upgrade() else { return };
+                match store.reload() {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        counter!("tls_cert_reloads_total", 1, "outcome" => "ok");
+                        tracing::info!("reloaded TLS certificate {}", store.cert_path.display());
+                    }
+                    Err(e) => {
+                        counter!("tls_cert_reloads_total", 1, "outcome" => "error");
+                        tracing::error!("TLS certificate reload failed, keeping the previous one: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Server settings that take the certificate from this store on every handshake and check
+    /// client certificates with `client_verifier`, if given.
+    pub fn server_config(self: &Arc<Self>, client_verifier: Option<Arc<dyn ClientCertVerifier>>) -> Arc<rustls::ServerConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions");
+        let builder = match client_verifier {
+            Some(verifier) => builder.with_client_cert_verifier(verifier),
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+}
+
+/// The client certificate check against a CA bundle, replaced when the bundle changes so CAs
+/// can be rotated without a restart. A bundle that fails to load leaves the previous check in
+/// place.
+pub struct ClientCaStore {
+    path: PathBuf,
+    required: bool,
+    current: RwLock<Arc<dyn ClientCertVerifier>>,
+    // Modification time of the bundle the current check was read from
+    seen: Mutex<Option<SystemTime>>,
+}
+
+impl std::fmt::Debug for ClientCaStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientCaStore").field("path", &self.path).field("required", &self.required).finish()
+    }
+}
+
+impl ClientCaStore {
+    /// Checks client certificates against the bundle at `path` (see [`client_verifier`]).
+    pub fn load(path: &Path, required: bool) -> Result<Arc<Self>, String> {
+        let seen = modified(path);
+        let verifier = client_verifier(path, required)?;
+        Ok(Arc::new(Self { path: path.to_path_buf(), required, current: RwLock::new(verifier), seen: Mutex::new(seen) }))
+    }
+
+    /// Reads the bundle again if it changed since the last read; true when a new check was
+    /// installed.
+    pub fn reload(&self) -> Result<bool, String> {
+        let now = modified(&self.path);
+        let mut seen = self.seen.lock().unwrap();
+        if *seen == now {
+            return Ok(false);
+        }
+        *seen = now;
+        let verifier = client_verifier(&self.path, self.required)?;
+        *self.current.write().unwrap() = verifier;
+        Ok(true)
+    }
+
+    /// Checks the bundle every `TLS_RELOAD_INTERVAL_SECS` for as long as the store is in use.
+    pub fn watch(self: &Arc<Self>) {
+        let Some(interval) = reload_interval() else { return };
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(store) = store.upgrade() else { return };
+                match store.reload() {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        counter!("tls_client_ca_reloads_total", 1, "outcome" => "ok");
+                        tracing::info!("reloaded TLS client CAs {}", store.path.display());
+                    }
+                    Err(e) => {
+                        counter!("tls_client_ca_reloads_total", 1, "outcome" => "error");
+                        tracing::error!("TLS client CA reload failed, keeping the previous ones: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    fn current(&self) -> Arc<dyn ClientCertVerifier> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl ClientCertVerifier for ClientCaStore {
+    fn client_auth_mandatory(&self) -> bool {
+        self.required
+    }
+
+    // The bundle may change between handshakes, so no CA names are hinted; clients then offer
+    // whichever certificate they have
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(&self, end_entity: &CertificateDer<'_>, intermediates: &[CertificateDer<'_>], now: UnixTime) -> Result<ClientCertVerified, rustls::Error> {
+        self.current().verify_client_cert(end_entity, intermediates, now)
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.current().verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.current().verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.current().supported_verify_schemes()
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, _hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, String> {
+    let read = |path: &Path| std::fs::read(path).map_err(|e| format!("read {}: {}", path.display(), e));
+    let certs = rustls_pemfile::certs(&mut read(cert_path)?.as_slice())
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .map_err(|e| format!("parse {}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("{} holds no certificate", cert_path.display()));
+    }
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut read(key_path)?.as_slice())
+        .map_err(|e| format!("parse {}: {}", key_path.display(), e))?
+        .ok_or_else(|| format!("{} holds no private key", key_path.display()))?;
+    let signer = rustls::crypto::ring::sign::any_supported_type(&key).map_err(|e| format!("{}: {}", key_path.display(), e))?;
+    let key = CertifiedKey::new(certs, signer);
+    key.keys_match().map_err(|e| format!("{} does not match {}: {}", key_path.display(), cert_path.display(), e))?;
+    Ok(key)
+}
+
+/// Serves `app` over HTTPS on `listener` until `signal` resolves, then waits for the
+/// connections in progress to finish, like `axum::serve(..).with_graceful_shutdown(..)`.
+pub async fn serve(listener: TcpListener, app: Router, tls: Arc<rustls::ServerConfig>, signal: impl Future<Output = ()>) -> std::io::Result<()> {
+    let acceptor = TlsAcceptor::from(tls);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(signal);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Out of file descriptors and the like; the next accept may succeed
+                    tracing::warn!("accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    counter!("tls_handshake_failures_total", 1);
+                    tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+                Err(_) => {
+                    counter!("tls_handshake_failures_total", 1);
+                    return;
+                }
+            };
+            // Every request names the connection's client certificate, and only that: a header of
+            // the same name sent by the client is dropped
+            let principal = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()).map(principal);
+            let principal = principal.and_then(|p| HeaderValue::from_str(&p).ok());
+            let service = TowerToHyperService::new(app.map_request(move |mut req: Request<hyper::body::Incoming>| {
+                req.headers_mut().remove(CLIENT_PRINCIPAL_HEADER);
+                if let Some(principal) = &principal {
+                    req.headers_mut().insert(CLIENT_PRINCIPAL_HEADER, principal.clone());
+                }
+                req
+            }));
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection.into_owned()).await {
+                tracing::debug!("connection from {} ended: {}", peer, e);
+            }
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
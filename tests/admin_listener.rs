@@ -0,0 +1,49 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{api::app::routers, engine::CoreEngine};
+use metrics_exporter_prometheus::PrometheusBuilder;
+
+fn apps(separate_admin: bool) -> (Router, Option<Router>) {
+    routers(Arc::new(CoreEngine::new()), PrometheusBuilder::new().build_recorder().handle(), separate_admin)
+}
+
+async fn status(app: &Router, method: &str, uri: &str) -> StatusCode {
+    let body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(if method == "POST" { Body::from(body.to_string()) } else { Body::empty() })
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn a_separate_admin_listener_takes_the_management_routes_off_the_public_one() {
+    let (public, admin) = apps(true);
+    let admin = admin.unwrap();
+    for uri in ["/admin/models", "/admin/metrics", "/admin/usage", "/admin/state/export"] {
+        assert_eq!(status(&public, "GET", uri).await, StatusCode::NOT_FOUND, "{}", uri);
+        assert_eq!(status(&admin, "GET", uri).await, StatusCode::OK, "{}", uri);
+    }
+    assert_eq!(status(&public, "POST", "/v1/chat/completions").await, StatusCode::OK);
+    assert_eq!(status(&admin, "POST", "/v1/chat/completions").await, StatusCode::NOT_FOUND);
+    assert_eq!(status(&public, "GET", "/health").await, StatusCode::OK);
+    assert_eq!(status(&admin, "GET", "/health").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn without_one_the_public_listener_serves_everything() {
+    let (public, admin) = apps(false);
+    assert!(admin.is_none());
+    assert_eq!(status(&public, "GET", "/admin/models").await, StatusCode::OK);
+    assert_eq!(status(&public, "GET", "/admin/metrics").await, StatusCode::OK);
+    assert_eq!(status(&public, "POST", "/v1/chat/completions").await, StatusCode::OK);
+}
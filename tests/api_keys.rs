@@ -0,0 +1,123 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{middleware, routing::{get, post}, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::auth::require_admin,
+    api::routes::{admin_keys_create, admin_keys_get, admin_keys_list, admin_keys_revoke, admin_state_export, chat_completions},
+    engine::CoreEngine,
+    storage::MemoryStorage,
+};
+
+async fn call(app: &Router, method: &str, uri: &str, key: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let body = body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty);
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+fn app(engine: Arc<CoreEngine>) -> Router {
+    let admin = Router::new()
+        .route("/admin/keys", post(admin_keys_create).get(admin_keys_list))
+        .route("/admin/keys/:id", get(admin_keys_get))
+        .route("/admin/keys/:id/revoke", post(admin_keys_revoke))
+        .route("/admin/state/export", get(admin_state_export))
+        .route_layer(middleware::from_fn_with_state(engine.clone(), require_admin));
+    Router::new().route("/v1/chat/completions", post(chat_completions)).merge(admin).with_state(engine)
+}
+
+#[tokio::test]
+async fn created_keys_are_accepted_until_revoked() {
+    let app = app(Arc::new(CoreEngine::with_storage(Arc::new(MemoryStorage::default()))));
+    let chat = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+
+    // Without API_KEYS or stored keys auth is off, so the first key can be created anonymously
+    let (status, created) = call(&app, "POST", "/admin/keys", None, Some(json!({"name": "ops", "scopes": ["admin", "inference"]}))).await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    let secret = created["key"].as_str().unwrap().to_string();
+    assert!(secret.starts_with("sk-"));
+    assert_eq!(created["object"], "api_key");
+    assert_eq!(created["scopes"], json!(["admin", "inference"]));
+    assert_eq!(created["created_by"], "anonymous");
+    assert!(created["id"].as_str().unwrap().starts_with("key_"));
+    let redacted = created["redacted_value"].as_str().unwrap();
+    assert!(secret.starts_with(&redacted[..5]) && secret.ends_with(&redacted[redacted.len() - 2..]), "{}", redacted);
+    assert!(created.get("revoked_at").is_none());
+
+    // From now on requests need a key
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", None, Some(chat.clone())).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", Some(&secret), Some(chat.clone())).await.0, StatusCode::OK);
+    assert_eq!(call(&app, "GET", "/admin/keys", None, None).await.0, StatusCode::UNAUTHORIZED);
+
+    let (status, second) = call(&app, "POST", "/admin/keys", Some(&secret), Some(json!({"name": "  web app "}))).await;
+    assert_eq!(status, StatusCode::OK, "{}", second);
+    assert_eq!((&second["name"], &second["scopes"]), (&json!("web app"), &json!(["inference"])));
+    assert!(second["created_by"].as_str().unwrap().starts_with("key-"));
+    let second_secret = second["key"].as_str().unwrap().to_string();
+    let second_id = second["id"].as_str().unwrap().to_string();
+
+    // An inference key reaches inference routes only
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", Some(&second_secret), Some(chat.clone())).await.0, StatusCode::OK);
+    let (status, body) = call(&app, "GET", "/admin/keys", Some(&second_secret), None).await;
+    assert_eq!((status, &body["error"]["type"]), (StatusCode::FORBIDDEN, &json!("permission_error")));
+
+    for invalid in [json!({"name": ""}), json!({"name": "x", "scopes": []}), json!({"name": "x", "scopes": ["root"]})] {
+        assert_eq!(call(&app, "POST", "/admin/keys", Some(&secret), Some(invalid.clone())).await.0, StatusCode::BAD_REQUEST, "{}", invalid);
+    }
+
+    // Listings never carry secrets or digests
+    let (status, list) = call(&app, "GET", "/admin/keys", Some(&secret), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(list["object"], "list");
+    let data = list["data"].as_array().unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(data.iter().all(|k| k.get("key").is_none() && k.get("hash").is_none()));
+    assert!(!list.to_string().contains(&secret));
+    let (status, exported) = call(&app, "GET", "/admin/state/export", Some(&secret), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(exported["state"]["api_key_hashes"].as_array().unwrap().len(), 2);
+
+    // A revoked key is refused, but stays listed
+    let (status, revoked) = call(&app, "POST", &format!("/admin/keys/{}/revoke", second_id), Some(&secret), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let revoked_at = revoked["revoked_at"].as_u64().unwrap();
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", Some(&second_secret), Some(chat.clone())).await.0, StatusCode::UNAUTHORIZED);
+    let (_, again) = call(&app, "POST", &format!("/admin/keys/{}/revoke", second_id), Some(&secret), None).await;
+    assert_eq!(again["revoked_at"], revoked_at);
+    let (status, fetched) = call(&app, "GET", &format!("/admin/keys/{}", second_id), Some(&secret), None).await;
+    assert_eq!((status, &fetched["revoked_at"]), (StatusCode::OK, &json!(revoked_at)));
+
+    assert_eq!(call(&app, "GET", "/admin/keys/key_missing", Some(&secret), None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn replicas_sharing_the_storage_agree_on_keys() {
+    let storage = Arc::new(MemoryStorage::default());
+    let other = Arc::new(CoreEngine::with_storage(storage.clone()));
+    let (first, second) = (app(Arc::new(CoreEngine::with_storage(storage))), app(other.clone()));
+    let chat = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+    let (_, admin) = call(&first, "POST", "/admin/keys", None, Some(json!({"name": "ops", "scopes": ["admin", "inference"]}))).await;
+    let admin = admin["key"].as_str().unwrap().to_string();
+    // Auth turns on for the other replica with its next read of the keys
+    assert_eq!(other.keys().load().await.unwrap(), 1);
+    let (_, app_key) = call(&first, "POST", "/admin/keys", Some(&admin), Some(json!({"name": "app"}))).await;
+    let (secret, id) = (app_key["key"].as_str().unwrap().to_string(), app_key["id"].as_str().unwrap().to_string());
+
+    // The other replica finds a key it has not seen yet in the storage
+    assert_eq!(call(&second, "POST", "/v1/chat/completions", Some(&secret), Some(chat.clone())).await.0, StatusCode::OK);
+    assert_eq!(call(&second, "POST", "/v1/chat/completions", Some("sk-guess"), Some(chat.clone())).await.0, StatusCode::UNAUTHORIZED);
+
+    // and refuses it once revoked, from its next read of the keys (every API_KEY_CACHE_SECS)
+    assert_eq!(call(&first, "POST", &format!("/admin/keys/{}/revoke", id), Some(&admin), None).await.0, StatusCode::OK);
+    assert_eq!(call(&first, "POST", "/v1/chat/completions", Some(&secret), Some(chat.clone())).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(other.keys().load().await.unwrap(), 1);
+    assert_eq!(call(&second, "POST", "/v1/chat/completions", Some(&secret), Some(chat)).await.0, StatusCode::UNAUTHORIZED);
+}
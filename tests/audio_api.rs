@@ -0,0 +1,94 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::post, Router};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{api::routes::audio_transcriptions, engine::CoreEngine};
+
+const BOUNDARY: &str = "test-boundary";
+
+// Two seconds of a 440 Hz stereo tone at 44.1 kHz
+fn wav_bytes() -> Vec<u8> {
+    let spec = hound::WavSpec { channels: 2, sample_rate: 44_100, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+    let mut cursor = std::io::Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+    for i in 0..88_200 {
+        let sample = ((i as f32 * 440.0 * std::f32::consts::TAU / 44_100.0).sin() * 8000.0) as i16;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+    cursor.into_inner()
+}
+
+fn multipart(fields: &[(&str, &str)], file: Option<&[u8]>) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", BOUNDARY, name, value).bytes());
+    }
+    if let Some(file) = file {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\nContent-Type: audio/wav\r\n\r\n", BOUNDARY).bytes());
+        body.extend_from_slice(file);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{}--\r\n", BOUNDARY).bytes());
+    body
+}
+
+async fn send(app: &Router, body: Vec<u8>) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/audio/transcriptions")
+        .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn transcribes_wav_uploads_in_each_format() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/audio/transcriptions", post(audio_transcriptions)).with_state(engine);
+    let wav = wav_bytes();
+
+    let (status, body) = send(&app, multipart(&[("model", "dummy-audio")], Some(&wav))).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    // Resampled to 16 kHz mono, the duration is preserved
+    assert_eq!(v["text"], "Transcribed 2.0s of audio");
+
+    let (status, body) = send(&app, multipart(&[("model", "dummy-audio"), ("response_format", "verbose_json"), ("language", "de")], Some(&wav))).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["task"], "transcribe");
+    assert_eq!(v["language"], "de");
+    assert!((v["duration"].as_f64().unwrap() - 2.0).abs() < 0.01);
+    assert_eq!(v["segments"][0]["end"], v["duration"]);
+
+    let (status, body) = send(&app, multipart(&[("model", "dummy-audio"), ("response_format", "srt")], Some(&wav))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("1\n00:00:00,000 --> 00:00:02,000\nTranscribed"));
+
+    let (status, body) = send(&app, multipart(&[("model", "dummy-audio"), ("response_format", "vtt")], Some(&wav))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:02.000"));
+}
+
+#[tokio::test]
+async fn rejects_missing_or_undecodable_audio() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/audio/transcriptions", post(audio_transcriptions)).with_state(engine);
+
+    let (status, _) = send(&app, multipart(&[("model", "dummy-audio")], None)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = send(&app, multipart(&[("model", "dummy-audio")], Some(b"ID3 not a wav"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("WAV"));
+    let (status, _) = send(&app, multipart(&[("model", "missing")], Some(&wav_bytes()))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,244 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::{get, post}, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{batches_cancel, batches_create, batches_get, batches_list, batches_output, files_content, files_upload},
+    engine::CoreEngine,
+};
+
+const BOUNDARY: &str = "test-boundary";
+
+fn app() -> Router {
+    app_for(engine())
+}
+
+fn engine() -> Arc<CoreEngine> {
+    // No other test in this binary uses the process-wide storage, so it can be pointed at memory
+    unsafe { std::env::set_var("STORAGE_BACKEND", "memory") };
+    Arc::new(CoreEngine::new())
+}
+
+fn app_for(engine: Arc<CoreEngine>) -> Router {
+    Router::new()
+        .route("/v1/batches", post(batches_create).get(batches_list))
+        .route("/v1/batches/:id", get(batches_get))
+        .route("/v1/batches/:id/cancel", post(batches_cancel))
+        .route("/v1/batches/:id/output", get(batches_output))
+        .route("/v1/files", post(files_upload))
+        .route("/v1/files/:id/content", get(files_content))
+        .with_state(engine)
+}
+
+fn upload(endpoint: &str, lines: &[Value]) -> Vec<u8> {
+    let jsonl: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+    let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"endpoint\"\r\n\r\n{}\r\n", BOUNDARY, endpoint).into_bytes();
+    body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"in.jsonl\"\r\n\r\n", BOUNDARY).bytes());
+    body.extend(jsonl.bytes());
+    body.extend(format!("\r\n--{}--\r\n", BOUNDARY).bytes());
+    body
+}
+
+async fn call(app: &Router, method: &str, uri: &str, body: Option<Vec<u8>>) -> (StatusCode, String) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(body) = &body {
+        let content_type = if body.starts_with(b"{") { "application/json".to_string() } else { format!("multipart/form-data; boundary={}", BOUNDARY) };
+        request = request.header("content-type", content_type);
+    }
+    let response = app.clone().oneshot(request.body(Body::from(body.unwrap_or_default())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn chat_line(custom_id: &str, content: &str) -> Value {
+    json!({
+        "custom_id": custom_id,
+        "method": "POST",
+        "url": "/v1/chat/completions",
+        "body": {"model": "dummy-model", "messages": [{"role": "user", "content": content}]}
+    })
+}
+
+async fn wait_for(app: &Router, id: &str, status: &str) -> Value {
+    for _ in 0..200 {
+        let (_, body) = call(app, "GET", &format!("/v1/batches/{}", id), None).await;
+        let batch: Value = serde_json::from_str(&body).unwrap();
+        if batch["status"] == status {
+            return batch;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("batch {} never reached {}", id, status);
+}
+
+#[tokio::test]
+async fn batch_runs_in_background_and_returns_jsonl_output() {
+    let app = app();
+    let lines = [chat_line("first", "one"), chat_line("second", "two")];
+    let (status, body) = call(&app, "POST", "/v1/batches", Some(upload("/v1/chat/completions", &lines))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let batch: Value = serde_json::from_str(&body).unwrap();
+    let id = batch["id"].as_str().unwrap();
+    assert!(id.starts_with("batch_"));
+    assert_eq!(batch["object"], "batch");
+    assert_eq!(batch["request_counts"]["total"], 2);
+
+    let batch = wait_for(&app, id, "completed").await;
+    assert_eq!(batch["request_counts"], json!({"total": 2, "completed": 2, "failed": 0}));
+    assert!(batch["completed_at"].as_u64().is_some());
+    // The output is also an ordinary file
+    let (status, _) = call(&app, "GET", &format!("/v1/files/{}/content", batch["output_file_id"].as_str().unwrap()), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, output) = call(&app, "GET", &format!("/v1/batches/{}/output", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["custom_id"], "first");
+    assert_eq!(results[0]["response"]["status_code"], 200);
+    assert_eq!(results[0]["response"]["body"]["choices"][0]["message"]["content"], "Echo: one");
+    assert_eq!(results[1]["custom_id"], "second");
+
+    let (_, body) = call(&app, "GET", "/v1/batches", None).await;
+    let list: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list["object"], "list");
+    assert!(list["data"].as_array().unwrap().iter().any(|b| b["id"] == id));
+
+    // Finished batches cannot be cancelled
+    let (status, _) = call(&app, "POST", &format!("/v1/batches/{}/cancel", id), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn batch_input_is_validated_up_front() {
+    let app = app();
+    let cases = [
+        ("/v1/images/generations", vec![chat_line("a", "x")], "endpoint must be one of"),
+        ("/v1/chat/completions", vec![chat_line("a", "x"), chat_line("a", "y")], "line 2: custom_id must be non-empty and unique"),
+        ("/v1/embeddings", vec![chat_line("a", "x")], "line 1: url /v1/chat/completions does not match"),
+        ("/v1/chat/completions", vec![], "batch input contains no requests"),
+        (
+            "/v1/chat/completions",
+            vec![chat_line("a", "x"), json!({"custom_id": "b", "method": "POST", "url": "/v1/chat/completions", "body": {"model": "dummy-model", "messages": [{"role": "user", "content": "y"}], "max_tokens": 1_000_000}})],
+            "line 2: max_tokens must be between 1 and",
+        ),
+        (
+            "/v1/embeddings",
+            vec![json!({"custom_id": "a", "method": "POST", "url": "/v1/embeddings", "body": {"model": "dummy-embedding", "input": ""}})],
+            "line 1: input must not be empty",
+        ),
+    ];
+    for (endpoint, lines, expected) in cases {
+        let (status, body) = call(&app, "POST", "/v1/batches", Some(upload(endpoint, &lines))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains(expected), "{} does not mention {:?}", body, expected);
+    }
+
+    let (status, _) = call(&app, "GET", "/v1/batches/batch_missing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn embeddings_batch_reports_failed_requests_per_line() {
+    let app = app();
+    let lines = [
+        json!({"custom_id": "ok", "method": "POST", "url": "/v1/embeddings", "body": {"model": "dummy-embedding", "input": "hello"}}),
+        json!({"custom_id": "bad", "method": "POST", "url": "/v1/embeddings", "body": {"model": "no-such-model", "input": "hello"}}),
+    ];
+    let (status, body) = call(&app, "POST", "/v1/batches", Some(upload("/v1/embeddings", &lines))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let id = serde_json::from_str::<Value>(&body).unwrap()["id"].as_str().unwrap().to_string();
+
+    let batch = wait_for(&app, &id, "completed").await;
+    assert_eq!(batch["request_counts"], json!({"total": 2, "completed": 1, "failed": 1}));
+    let (_, output) = call(&app, "GET", &format!("/v1/batches/{}/output", id), None).await;
+    let results: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(results[0]["response"]["status_code"], 200);
+    assert_eq!(results[1]["response"]["status_code"], 404);
+    assert_eq!(results[1]["response"]["body"]["error"]["code"], "model_not_found");
+}
+
+#[tokio::test]
+async fn batch_can_run_a_previously_uploaded_file() {
+    let app = app();
+    let jsonl = format!("{}\n", chat_line("only", "from a file"));
+    let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n", BOUNDARY).into_bytes();
+    body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"in.jsonl\"\r\n\r\n{}\r\n--{}--\r\n", BOUNDARY, jsonl, BOUNDARY).bytes());
+    let (status, body) = call(&app, "POST", "/v1/files", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let file_id = serde_json::from_str::<Value>(&body).unwrap()["id"].as_str().unwrap().to_string();
+
+    let request = json!({"input_file_id": file_id, "endpoint": "/v1/chat/completions", "completion_window": "24h"});
+    let (status, body) = call(&app, "POST", "/v1/batches", Some(request.to_string().into_bytes())).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let batch: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(batch["input_file_id"], file_id.as_str());
+    let batch = wait_for(&app, batch["id"].as_str().unwrap(), "completed").await;
+    assert_eq!(batch["request_counts"]["completed"], 1);
+
+    let request = json!({"input_file_id": "file-missing", "endpoint": "/v1/chat/completions"});
+    let (status, _) = call(&app, "POST", "/v1/batches", Some(request.to_string().into_bytes())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn shutdown_leaves_a_running_batch_in_progress_for_the_next_run() {
+    let engine = engine();
+    let app = app_for(engine.clone());
+    // The dummy model echoes the prompt a word at a time, so each long, distinct line takes a while
+    let lines: Vec<Value> = (0..20)
+        .map(|i| {
+            let mut line = chat_line(&format!("line-{}", i), &format!("{}{}", i, " word".repeat(500)));
+            line["body"]["max_tokens"] = json!(8192);
+            line
+        })
+        .collect();
+    let (status, body) = call(&app, "POST", "/v1/batches", Some(upload("/v1/chat/completions", &lines))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let id = serde_json::from_str::<Value>(&body).unwrap()["id"].as_str().unwrap().to_string();
+    wait_for(&app, &id, "in_progress").await;
+
+    assert!(engine.shutdown(std::time::Duration::from_secs(10)).await);
+    // The lines the shutdown turned away are not recorded as failures, and the batch is not finished
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let (_, body) = call(&app, "GET", &format!("/v1/batches/{}", id), None).await;
+    let batch: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(batch["status"], "in_progress", "{}", batch);
+    assert_eq!(batch["request_counts"]["failed"], 0, "{}", batch);
+    assert!(batch["request_counts"]["completed"].as_u64().unwrap() < 20, "{}", batch);
+}
+
+#[tokio::test]
+async fn idempotency_keys_for_batches_belong_to_the_caller() {
+    let app = app();
+    let key = uuid::Uuid::new_v4().to_string();
+    let create = |token: &str, content: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/batches")
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .header("authorization", format!("Bearer {}", token))
+            .header("idempotency-key", &key)
+            .body(Body::from(upload("/v1/chat/completions", &[chat_line("only", content)])))
+            .unwrap()
+    };
+    let batch_id = |response: axum::response::Response| async move {
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<Value>(&bytes).unwrap()["id"].as_str().unwrap().to_string()
+    };
+
+    let first = batch_id(app.clone().oneshot(create("tenant-a", "one")).await.unwrap()).await;
+    let retry = app.clone().oneshot(create("tenant-a", "one")).await.unwrap();
+    assert!(retry.headers().contains_key("idempotent-replayed"));
+    assert_eq!(batch_id(retry).await, first);
+
+    // Another caller with the same key and a different file gets a batch of its own
+    let other = app.clone().oneshot(create("tenant-b", "two")).await.unwrap();
+    assert!(!other.headers().contains_key("idempotent-replayed"));
+    assert_ne!(batch_id(other).await, first);
+}
@@ -0,0 +1,35 @@
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tokio_util::sync::CancellationToken;
+
+use llm_serving::{
+    api::dto::{ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent},
+    engine::CoreEngine,
+};
+
+fn chat_request(model: &str) -> ChatCompletionRequest {
+    ChatCompletionRequest {
+        model: model.to_string(),
+        messages: vec![ChatCompletionMessage { role: "user".to_string(), content: ChatMessageContent::Text(uuid::Uuid::new_v4().to_string()) }],
+        max_tokens: Some(8),
+        ..Default::default()
+    }
+}
+
+// The only test in this binary, which owns its process-wide metrics recorder
+#[tokio::test]
+async fn cache_metrics_label_only_models_that_exist() {
+    let handle = PrometheusBuilder::new().install_recorder().unwrap();
+    let engine = CoreEngine::new();
+
+    engine.process_chat_request(chat_request("dummy-model"), None, CancellationToken::new()).await.unwrap();
+    for i in 0..3 {
+        let made_up = format!("made-up-model-{}", i);
+        assert!(engine.process_chat_request(chat_request(&made_up), None, CancellationToken::new()).await.is_err());
+    }
+
+    let rendered = handle.render();
+    let lines: Vec<&str> = rendered.lines().filter(|line| line.starts_with("cache_")).collect();
+    assert!(lines.iter().any(|line| line.starts_with("cache_miss_total{") && line.contains("model=\"dummy-model\"")), "{}", rendered);
+    assert!(lines.iter().any(|line| line.contains("model=\"unknown\"") && line.ends_with(" 3")), "{}", rendered);
+    assert!(!rendered.contains("made-up-model"), "{}", rendered);
+}
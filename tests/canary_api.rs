@@ -0,0 +1,149 @@
+use axum::{routing::{get, post}, Router};
+use axum::http::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+
+use llm_serving::{
+    api::routes::{admin_canaries_audit, admin_canaries_create, admin_canaries_list, admin_canaries_remove, admin_models_load, admin_splits_create, admin_splits_list, chat_completions},
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+#[tokio::test]
+async fn canary_rolls_back_when_error_rate_exceeds_threshold() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/canaries", post(admin_canaries_create).get(admin_canaries_list))
+        .route("/admin/canaries/audit", get(admin_canaries_audit))
+        .with_state(engine);
+
+    // An upstream that refuses connections fails every request, making it a reliably broken canary
+    let broken = json!({"model": "broken", "kind": "llm", "path": "http://127.0.0.1:1"});
+    let (status, _) = send(&app, "POST", "/admin/models/load", Some(broken)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let canary = json!({
+        "model": "prod",
+        "baseline": "dummy-model",
+        "canary": "broken",
+        "weight": 1.0,
+        "policy": {"max_error_rate": 0.5, "min_requests": 1}
+    });
+    let mut unknown = canary.clone();
+    unknown["canary"] = json!("missing");
+    let (status, v) = send(&app, "POST", "/admin/canaries", Some(unknown)).await;
+    assert_eq!((status, v["error"]["code"].as_str()), (StatusCode::NOT_FOUND, Some("model_not_found")), "{}", v);
+    let (status, v) = send(&app, "POST", "/admin/canaries", Some(canary)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["status"], "active");
+
+    // The failed generation is an error for its caller
+    let chat = json!({"model": "prod", "messages": [{"role": "user", "content": "hello"}]});
+    let (status, _) = send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (_, v) = send(&app, "GET", "/admin/canaries", None).await;
+    assert_eq!(v["data"][0]["status"], "rolled_back");
+    assert_eq!(v["data"][0]["canary_stats"]["errors"], 1);
+
+    // Traffic now goes to the baseline
+    let chat = json!({"model": "prod", "messages": [{"role": "user", "content": "again"}]});
+    let (_, v) = send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    assert_eq!(v["model"], "dummy-model");
+    assert!(v["choices"][0]["message"]["content"].as_str().unwrap().starts_with("Echo:"));
+
+    let (_, v) = send(&app, "GET", "/admin/canaries/audit", None).await;
+    let actions: Vec<&str> = v["data"].as_array().unwrap().iter().map(|d| d["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, vec!["created", "rollback"]);
+}
+
+#[tokio::test]
+async fn windowed_policies_forget_old_failures() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/canaries", post(admin_canaries_create).get(admin_canaries_list))
+        .with_state(engine);
+    let broken = json!({"model": "broken", "kind": "llm", "path": "http://127.0.0.1:1"});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(broken)).await.0, StatusCode::OK);
+    let canary = json!({
+        "model": "prod",
+        "baseline": "dummy-model",
+        "canary": "broken",
+        "weight": 1.0,
+        "policy": {"max_error_rate": 0.5, "min_requests": 2, "window_secs": 1}
+    });
+    assert_eq!(send(&app, "POST", "/admin/canaries", Some(canary)).await.0, StatusCode::OK);
+
+    let chat = json!({"model": "prod", "messages": [{"role": "user", "content": "hello"}]});
+    send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await;
+    tokio::time::pause();
+    tokio::time::advance(std::time::Duration::from_millis(1100)).await;
+    tokio::time::resume();
+    // The first failure has left the window, so this one alone is too few to judge
+    send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await;
+    let (_, v) = send(&app, "GET", "/admin/canaries", None).await;
+    assert_eq!(v["data"][0]["status"], "active");
+    assert_eq!(v["data"][0]["canary_stats"]["errors"], 2);
+
+    send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    let (_, v) = send(&app, "GET", "/admin/canaries", None).await;
+    assert_eq!(v["data"][0]["status"], "rolled_back");
+}
+
+#[tokio::test]
+async fn canaries_on_a_traffic_split_move_its_share_back_to_the_baseline() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/canaries", post(admin_canaries_create).get(admin_canaries_list))
+        .route("/admin/canaries/remove", post(admin_canaries_remove))
+        .route("/admin/splits", post(admin_splits_create).get(admin_splits_list))
+        .with_state(engine);
+    let broken = json!({"model": "broken", "kind": "llm", "path": "http://127.0.0.1:1"});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(broken)).await.0, StatusCode::OK);
+    let other = json!({"model": "other", "kind": "llm", "path": "http://127.0.0.1:1"});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(other)).await.0, StatusCode::OK);
+    let split = json!({"model": "prod", "variants": [{"model": "dummy-model", "percent": 50}, {"model": "broken", "percent": 50}]});
+    assert_eq!(send(&app, "POST", "/admin/splits", Some(split)).await.0, StatusCode::OK);
+
+    // Canaries need both models in the split
+    let canary = |baseline: &str| json!({
+        "model": "prod",
+        "baseline": baseline,
+        "canary": "broken",
+        "weight": 1.0,
+        "policy": {"max_error_rate": 0.5, "min_requests": 1}
+    });
+    let (status, v) = send(&app, "POST", "/admin/canaries", Some(canary("other"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(v["error"]["message"].as_str().unwrap().contains("other is not a variant"), "{}", v);
+    assert_eq!(send(&app, "POST", "/admin/canaries", Some(canary("dummy-model"))).await.0, StatusCode::OK);
+    let (_, v) = send(&app, "GET", "/admin/splits", None).await;
+    assert_eq!(v["data"][0]["variants"][1]["percent"], 100.0);
+
+    let chat = json!({"model": "prod", "messages": [{"role": "user", "content": "hello"}]});
+    let (status, _) = send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let (_, v) = send(&app, "GET", "/admin/canaries", None).await;
+    assert_eq!(v["data"][0]["status"], "rolled_back");
+    let (_, v) = send(&app, "GET", "/admin/splits", None).await;
+    assert_eq!(v["data"][0]["variants"][0]["percent"], 100.0);
+    assert_eq!(v["data"][0]["variants"][1]["percent"], 0.0);
+
+    let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["model"], "dummy-model");
+
+    // Removing the deployment gives the split back the shares it had before
+    assert_eq!(send(&app, "POST", "/admin/canaries/remove", Some(json!({"model": "prod"}))).await.0, StatusCode::OK);
+    let (_, v) = send(&app, "GET", "/admin/splits", None).await;
+    assert_eq!(v["data"][0]["variants"][0]["percent"], 50.0);
+    assert_eq!(v["data"][0]["variants"][1]["percent"], 50.0);
+}
@@ -106,6 +106,8 @@ async fn chat_completions_stream_sends_sse_and_done() {
 
     assert!(body_text.contains("chat.completion.chunk"));
     assert!(body_text.contains("[DONE]"));
+    // role chunk + one chunk per streamed token ("Echo: ", "stream ", "please") + finish chunk
+    assert_eq!(body_text.matches("chat.completion.chunk").count(), 5);
 }
 
 #[tokio::test]
@@ -239,3 +241,517 @@ async fn admin_can_load_and_unload_llm_model() {
     let resp = app.clone().oneshot(req).await.unwrap();
     assert_eq!(resp.status(), StatusCode::OK);
 }
+
+#[tokio::test]
+async fn cancelled_stream_request_produces_no_chunks() {
+    let engine = CoreEngine::new();
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(16);
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+
+    let request: llm_serving::api::dto::ChatCompletionRequest = serde_json::from_value(json!({
+        "model": "dummy-model",
+        "messages": [{"role": "user", "content": "never sent"}],
+        "stream": true
+    })).unwrap();
+    let _ = engine.process_chat_request(request, Some(tx), cancel).await;
+
+    // The worker drops the request without emitting anything once the client is gone
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn server_info_reports_capabilities_and_clock() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/server/info", axum::routing::get(llm_serving::api::routes::server_info))
+        .with_state(engine);
+
+    let request = Request::builder().uri("/v1/server/info").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(v["object"], "server.info");
+    assert_eq!(v["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(v["capabilities"]["chat"], true);
+    assert_eq!(v["capabilities"]["tool_calling"], false);
+    assert!(v["endpoints"].as_array().unwrap().iter().any(|e| e == "POST /v1/chat/completions"));
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert!(v["time"].as_u64().unwrap().abs_diff(now) <= 5);
+}
+
+#[tokio::test]
+async fn admin_stats_reports_acceleration() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/admin/stats", axum::routing::get(llm_serving::api::routes::admin_stats))
+        .with_state(engine);
+
+    let request = Request::builder().uri("/admin/stats").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+
+    let accel = &v["acceleration"];
+    assert_eq!(accel["target_arch"], std::env::consts::ARCH);
+    assert_eq!(accel["build_profile"], if cfg!(debug_assertions) { "debug" } else { "release" });
+    // Anything compiled in must also be reported as supported, or this test binary would not run
+    assert_eq!(accel["unsupported_by_cpu"], serde_json::json!([]));
+    assert_eq!(v["queued"], 0);
+}
+
+#[tokio::test]
+async fn chat_and_embeddings_report_token_usage() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(engine);
+
+    let post_json = |uri: &str, payload: Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+
+    let payload = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "count my tokens"}]});
+    let response = app.clone().oneshot(post_json("/v1/chat/completions", payload)).await.unwrap();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+    let usage = &v["usage"];
+    assert!(usage["prompt_tokens"].as_u64().unwrap() > 0);
+    assert!(usage["completion_tokens"].as_u64().unwrap() > 0);
+    assert_eq!(
+        usage["total_tokens"].as_u64().unwrap(),
+        usage["prompt_tokens"].as_u64().unwrap() + usage["completion_tokens"].as_u64().unwrap()
+    );
+
+    // Streaming reports usage in a trailing chunk only when asked to
+    let payload = json!({
+        "model": "dummy-model",
+        "messages": [{"role": "user", "content": "count my tokens"}],
+        "stream": true,
+        "stream_options": {"include_usage": true}
+    });
+    let response = app.clone().oneshot(post_json("/v1/chat/completions", payload)).await.unwrap();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body_text = String::from_utf8(body_bytes.to_vec()).unwrap();
+    let usage_chunk: Value = body_text
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+        .find(|c| c.get("usage").is_some())
+        .unwrap();
+    assert_eq!(usage_chunk["choices"].as_array().unwrap().len(), 0);
+    assert_eq!(usage_chunk["usage"], v["usage"]);
+
+    let payload = json!({"model": "dummy-embedding", "input": ["hello", "world"]});
+    let response = app.clone().oneshot(post_json("/v1/embeddings", payload)).await.unwrap();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(v["usage"]["prompt_tokens"], 4);
+    assert_eq!(v["usage"]["total_tokens"], 4);
+}
+
+#[tokio::test]
+async fn embeddings_accept_string_array_and_token_inputs() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(engine);
+
+    for (input, expected) in [
+        (json!("hello"), 1),
+        (json!(["hello", "world"]), 2),
+        (json!([15339, 1917]), 1),
+        (json!([[15339], [1917, 0]]), 2),
+    ] {
+        let payload = json!({"model": "dummy-embedding", "input": input});
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "input {}", input);
+        let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+        assert_eq!(v["data"].as_array().unwrap().len(), expected, "input {}", input);
+    }
+}
+
+#[tokio::test]
+async fn embeddings_accept_mixed_text_and_image_inputs() {
+    use base64::Engine as _;
+
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(engine);
+
+    let png = llm_serving::runtime::image::encode_png(&[255, 0, 0, 0, 0, 255], 2, 1).unwrap();
+    let data_uri = format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png));
+    let payload = json!({"model": "dummy-embedding", "input": [
+        "a cat",
+        {"type": "image_url", "image_url": {"url": data_uri}},
+        {"type": "text", "text": "a cat"}
+    ]});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/embeddings")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+    let data = v["data"].as_array().unwrap();
+    assert_eq!(data.len(), 3);
+    // Vectors come back in input order, whatever their kind
+    assert_eq!(data[0]["embedding"], data[2]["embedding"]);
+    assert_ne!(data[0]["embedding"], data[1]["embedding"]);
+    assert_eq!(data[1]["index"], 1);
+    // Two 2-token texts plus one image
+    assert_eq!(v["usage"]["prompt_tokens"], 89);
+
+    let payload = json!({"model": "dummy-embedding", "input": [{"type": "image_url", "image_url": {"url": "data:image/png;base64,aGk="}}]});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/embeddings")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn embeddings_base64_encoding_matches_float_output() {
+    use base64::Engine as _;
+
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(engine);
+
+    let mut results = Vec::new();
+    for format in ["float", "base64"] {
+        let payload = json!({"model": "dummy-embedding", "input": "hello", "encoding_format": format});
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+        results.push(v["data"][0]["embedding"].clone());
+    }
+
+    let floats: Vec<f32> = results[0].as_array().unwrap().iter().map(|x| x.as_f64().unwrap() as f32).collect();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(results[1].as_str().unwrap()).unwrap();
+    let decoded: Vec<f32> = bytes.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect();
+    assert_eq!(decoded, floats);
+}
+
+#[tokio::test]
+async fn similarity_compares_texts_and_vectors() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/similarity", post(llm_serving::api::routes::similarity))
+        .with_state(engine);
+
+    let send = |payload: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/v1/similarity")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body_bytes).unwrap())
+        }
+    };
+
+    let (status, v) = send(json!({"model": "dummy-embedding", "a": ["same", "other"], "b": ["same"]})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["data"].as_array().unwrap().len(), 2);
+    assert!((v["data"][0][0].as_f64().unwrap() - 1.0).abs() < 1e-5);
+    assert!(v["usage"]["prompt_tokens"].as_u64().unwrap() > 0);
+
+    let (status, v) = send(json!({"model": "dummy-embedding", "a": [[1.0, 0.0]], "b": [[0.0, 2.0], [3.0, 0.0]]})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["data"], json!([[0.0, 1.0]]));
+
+    let (status, _) = send(json!({"model": "dummy-embedding", "a": [[1.0, 0.0]], "b": [[1.0, 0.0, 0.0]]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn embeddings_dimensions_truncates_and_renormalizes() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(engine);
+
+    let request = |dimensions: usize| {
+        let payload = json!({"model": "dummy-embedding", "input": "hello", "dimensions": dimensions});
+        Request::builder()
+            .method("POST")
+            .uri("/v1/embeddings")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request(64)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+    let embedding: Vec<f64> = v["data"][0]["embedding"].as_array().unwrap().iter().map(|x| x.as_f64().unwrap()).collect();
+    assert_eq!(embedding.len(), 64);
+    let norm: f64 = embedding.iter().map(|x| x * x).sum::<f64>().sqrt();
+    assert!((norm - 1.0).abs() < 1e-4);
+
+    // The dummy model has 384 dimensions
+    let response = app.clone().oneshot(request(1000)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn rerank_orders_documents_by_relevance() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/rerank", post(llm_serving::api::routes::rerank))
+        .with_state(engine);
+
+    let send = |payload: Value| {
+        let app = app.clone();
+        async move {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/v1/rerank")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body_bytes).unwrap())
+        }
+    };
+
+    let documents = ["cats sleep a lot", "rust async runtimes", "the tokio async runtime for rust"];
+    let (status, v) = send(json!({
+        "model": "dummy-rerank",
+        "query": "tokio rust runtime",
+        "documents": documents,
+        "top_n": 2,
+        "return_documents": true
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let results = v["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["index"], 2);
+    assert_eq!(results[0]["document"]["text"], documents[2]);
+    assert!(results[0]["relevance_score"].as_f64().unwrap() > results[1]["relevance_score"].as_f64().unwrap());
+    assert!(v["usage"]["prompt_tokens"].as_u64().unwrap() > 0);
+
+    let (status, _) = send(json!({"model": "dummy-rerank", "query": "q", "documents": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn llm_load_takes_gpu_offload_options() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/admin/models/load", post(llm_serving::api::routes::admin_models_load))
+        .with_state(engine);
+    let load = |payload: Value| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/admin/models/load")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+
+    // Without a path (or the llama feature) this loads the dummy runtime; the options still parse
+    let status = load(json!({"model": "gpu-llm", "kind": "llm", "n_gpu_layers": 99, "main_gpu": 1, "split_mode": "row"})).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = load(json!({"model": "gpu-llm", "kind": "llm", "split_mode": "diagonal"})).await;
+    assert!(status.is_client_error(), "{}", status);
+}
+
+#[tokio::test]
+async fn llm_load_takes_context_options() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/admin/models/load", post(llm_serving::api::routes::admin_models_load))
+        .with_state(engine);
+    let load = |payload: Value| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/admin/models/load")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+
+    let status = load(json!({
+        "model": "long-llm", "kind": "llm",
+        "n_ctx": 32768, "n_batch": 1024, "rope_freq_base": 1000000.0, "rope_freq_scale": 0.25
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(load(json!({"model": "long-llm", "kind": "llm", "n_ctx": 0})).await, StatusCode::BAD_REQUEST);
+    assert_eq!(load(json!({"model": "long-llm", "kind": "llm", "rope_freq_scale": -1.0})).await, StatusCode::BAD_REQUEST);
+    assert_eq!(load(json!({"model": "embed", "kind": "embedding", "embedding_dim": 0})).await, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn chat_completions_check_the_grammar() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine);
+    let chat = |grammar: &str| {
+        let app = app.clone();
+        let payload = json!({
+            "model": "dummy-model",
+            "messages": [{"role": "user", "content": "yes or no?"}],
+            "grammar": grammar
+        });
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+
+    assert_eq!(chat(r#"root ::= "yes" | "no""#).await, StatusCode::OK);
+    assert_eq!(chat(r#"root ::= answer"#).await, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn chat_completions_check_sampling_options() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine);
+    let chat = |sampling: Value| {
+        let app = app.clone();
+        let mut payload = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+        payload.as_object_mut().unwrap().extend(sampling.as_object().unwrap().clone());
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+
+    let status = chat(json!({"top_k": 40, "min_p": 0.05, "typical_p": 0.9, "repetition_penalty": 1.1, "mirostat": 2, "mirostat_tau": 4.0})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(chat(json!({"mirostat": 3})).await, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(chat(json!({"typical_p": 0.0})).await, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(chat(json!({"repetition_penalty": -1.0})).await, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn chat_completions_end_at_stop_sequences() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine);
+    let chat = |stop: Value| {
+        let app = app.clone();
+        let payload = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "one two three"}], "stop": stop});
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            let resp = app.oneshot(req).await.unwrap();
+            let status = resp.status();
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null))
+        }
+    };
+
+    let (status, body) = chat(json!(" three")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["choices"][0]["message"]["content"], "Echo: one two");
+    assert_eq!(body["choices"][0]["finish_reason"], "stop");
+    let (_, body) = chat(json!(["zzz", "two"])).await;
+    assert_eq!(body["choices"][0]["message"]["content"], "Echo: one ");
+    let (status, _) = chat(json!(["a", "b", "c", "d", "e"])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn finish_reason_tells_length_from_stop() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine);
+    let finish_reason = |max_tokens: u32, stream: bool| {
+        let app = app.clone();
+        let payload = json!({
+            "model": "dummy-model",
+            "messages": [{"role": "user", "content": "a fairly long question"}],
+            "max_tokens": max_tokens,
+            "stream": stream
+        });
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/v1/chat/completions")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            let resp = app.oneshot(req).await.unwrap();
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            let text = String::from_utf8(bytes.to_vec()).unwrap();
+            if !stream {
+                return serde_json::from_str::<Value>(&text).unwrap()["choices"][0]["finish_reason"].clone();
+            }
+            text.lines()
+                .filter_map(|line| line.strip_prefix("data: "))
+                .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+                .map(|chunk| chunk["choices"][0]["finish_reason"].clone())
+                .find(|reason| !reason.is_null())
+                .unwrap()
+        }
+    };
+
+    // The dummy echo is cut to max_tokens characters, which still counts as at least one token
+    assert_eq!(finish_reason(1, false).await, "length");
+    assert_eq!(finish_reason(1, true).await, "length");
+    assert_eq!(finish_reason(500, false).await, "stop");
+    assert_eq!(finish_reason(500, true).await, "stop");
+}
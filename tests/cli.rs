@@ -0,0 +1,46 @@
+use clap::Parser;
+
+use llm_serving::{
+    cli::Cli,
+    config::{clear_overrides, set_overrides, var},
+};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from([&["llm-serving"], args].concat()).unwrap()
+}
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+#[test]
+fn flags_map_to_the_settings_they_override() {
+    assert_eq!(cli(&[]).env_vars("0.0.0.0:3000"), vars(&[]));
+    assert_eq!(cli(&["--port", "8080"]).env_vars("0.0.0.0:3000"), vars(&[("LISTEN_ADDR", "0.0.0.0:8080")]));
+    assert_eq!(cli(&["--host", "127.0.0.1"]).env_vars("0.0.0.0:9000"), vars(&[("LISTEN_ADDR", "127.0.0.1:9000")]));
+    assert_eq!(cli(&["--host", "::1", "--port", "80"]).env_vars("0.0.0.0:3000"), vars(&[("LISTEN_ADDR", "[::1]:80")]));
+    assert_eq!(
+        cli(&["--workers", "8", "--admin-addr", "127.0.0.1:9090", "--grpc-addr", "0.0.0.0:50052"]).env_vars("0.0.0.0:3000"),
+        vars(&[("ADMIN_LISTEN_ADDR", "127.0.0.1:9090"), ("GRPC_ADDR", "0.0.0.0:50052"), ("ENGINE_WORKERS", "8")])
+    );
+    assert!(Cli::try_parse_from(["llm-serving", "--port", "http"]).is_err());
+    assert!(Cli::try_parse_from(["llm-serving", "--listen", "0.0.0.0:1"]).is_err());
+    assert!(Cli::try_parse_from(["llm-serving", "--workers", "0"]).is_err());
+
+    // Variables only this test uses
+    unsafe { std::env::set_var("CLI_TEST_SET", "env") };
+    set_overrides(vars(&[("CLI_TEST_SET", "cli")]));
+    assert_eq!(var("CLI_TEST_SET").unwrap(), "cli");
+    clear_overrides();
+    assert_eq!(var("CLI_TEST_SET").unwrap(), "env");
+}
+
+#[test]
+fn model_flags_name_their_models() {
+    let models = cli(&["--model", "/models/llama-3-8b.Q4_K_M.gguf", "--model", "chat=http://localhost:8000"]).model_requests().unwrap();
+    assert_eq!(models.len(), 2);
+    assert_eq!((models[0].model.as_str(), models[0].kind.as_str()), ("llama-3-8b.Q4_K_M", "llm"));
+    assert_eq!((models[1].model.as_str(), models[1].path.as_deref()), ("chat", Some("http://localhost:8000")));
+    assert!(cli(&["--model", "http://localhost:8000"]).model_requests().unwrap_err().contains("name=path"));
+    assert!(cli(&["--model", "chat="]).model_requests().is_err());
+}
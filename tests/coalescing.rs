@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use llm_serving::{
+    api::dto::{CacheStatus, ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent},
+    engine::{scheduler::PREFILL_WEIGHT, CoreEngine},
+};
+use tokio_util::sync::CancellationToken;
+
+fn chat(content: &str, client: &str) -> ChatCompletionRequest {
+    ChatCompletionRequest {
+        model: "dummy-model".to_string(),
+        messages: vec![ChatCompletionMessage { role: "user".to_string(), content: ChatMessageContent::Text(content.to_string()) }],
+        max_tokens: Some(16),
+        client_id: Some(client.to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn identical_concurrent_requests_share_one_generation() {
+    let engine = Arc::new(CoreEngine::new());
+    let requests = (0..5).map(|_| engine.process_chat_request(chat("same question", "alice"), None, CancellationToken::new()));
+    let responses = futures::future::join_all(requests).await;
+    let responses: Vec<_> = responses.into_iter().map(|r| r.unwrap()).collect();
+    // Every caller got the one response
+    assert!(responses.iter().all(|r| r.id == responses[0].id));
+    // and is charged for it, though only one generation was queued
+    let usage = engine.usage_report().await.unwrap();
+    let alice = usage.data.iter().find(|c| c.client == "alice").unwrap();
+    assert_eq!(alice.requests, 5);
+    let usage = &responses[0].usage;
+    let cost = usage.completion_tokens as f64 + usage.prompt_tokens as f64 * PREFILL_WEIGHT;
+    assert!((alice.actual_cost - 5.0 * cost).abs() < 1e-6, "{:?}", alice);
+
+    // Different requests still generate separately
+    let requests = ["first", "second"].map(|content| engine.process_chat_request(chat(content, "alice"), None, CancellationToken::new()));
+    for response in futures::future::join_all(requests).await {
+        assert!(response.is_ok());
+    }
+    assert_eq!(engine.usage_report().await.unwrap().data.iter().map(|c| c.requests).sum::<u64>(), 7);
+}
+
+#[tokio::test]
+async fn clients_do_not_wait_on_each_others_generations() {
+    let engine = Arc::new(CoreEngine::new());
+    let requests = ["alice", "bob"].map(|client| engine.process_chat_request(chat("same question", client), None, CancellationToken::new()));
+    let [alice, bob] = futures::future::join_all(requests).await.try_into().unwrap();
+    assert_ne!(alice.unwrap().id, bob.unwrap().id);
+
+    // Once generated, the response is there for anyone
+    let carol = engine.process_chat_request(chat("same question", "carol"), None, CancellationToken::new()).await.unwrap();
+    assert!(matches!(carol.cache, CacheStatus::Hit));
+}
@@ -0,0 +1,40 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary compiles this module and uses only some of it
+#![allow(dead_code)]
+
+use axum::{
+    body::Body,
+    http::{request, Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use tower::util::ServiceExt; // for `oneshot`
+
+/// Sends a JSON request (no body when `body` is None) and returns the status with the decoded
+/// JSON response, `Value::Null` when the response is not JSON.
+pub async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    respond(app, json_request(method, uri), body.map_or_else(Body::empty, |b| Body::from(b.to_string()))).await
+}
+
+/// `send` with `key` as the bearer API key.
+pub async fn send_as(app: &Router, key: &str, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let builder = json_request(method, uri).header("authorization", format!("Bearer {}", key));
+    respond(app, builder, body.map_or_else(Body::empty, |b| Body::from(b.to_string()))).await
+}
+
+/// POSTs `body` as is, for bodies that are not valid JSON.
+pub async fn send_raw(app: &Router, uri: &str, body: &str) -> (StatusCode, Value) {
+    respond(app, json_request("POST", uri), Body::from(body.to_string())).await
+}
+
+fn json_request(method: &str, uri: &str) -> request::Builder {
+    Request::builder().method(method).uri(uri).header("content-type", "application/json")
+}
+
+async fn respond(app: &Router, builder: request::Builder, body: Body) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(builder.body(body).unwrap()).await.unwrap();
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
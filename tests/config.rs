@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use llm_serving::{
+    cli::Cli,
+    config::{var, Config},
+    engine::CoreEngine,
+};
+
+const TOML: &str = r#"
+[server]
+listen = "127.0.0.1:8080"
+admin_listen = "127.0.0.1:9090"
+api_keys = ["a", "b"]
+
+[engine]
+workers = 2
+
+[cache]
+prefix_cache_entries = 0
+response_cache_ttl_secs = 30
+
+[[models]]
+model = "chat"
+kind = "llm"
+n_ctx = 4096
+chat_template = "llama3"
+
+[[models]]
+model = "vectors"
+kind = "embedding"
+embedding_dim = 16
+
+[env]
+STORAGE_BACKEND = "fs"
+"#;
+
+const YAML: &str = r#"
+server:
+  listen: "127.0.0.1:8080"
+  admin_listen: "127.0.0.1:9090"
+  api_keys: [a, b]
+engine:
+  workers: 2
+cache:
+  prefix_cache_entries: 0
+  response_cache_ttl_secs: 30
+models:
+  - model: chat
+    kind: llm
+    n_ctx: 4096
+    chat_template: llama3
+  - model: vectors
+    kind: embedding
+    embedding_dim: 16
+env:
+  STORAGE_BACKEND: fs
+"#;
+
+#[test]
+fn toml_and_yaml_files_map_to_the_same_settings() {
+    let toml = Config::from_toml(TOML).unwrap();
+    let yaml = Config::from_yaml(YAML).unwrap();
+    let expected: Vec<(String, String)> = [
+        ("LISTEN_ADDR", "127.0.0.1:8080"),
+        ("ADMIN_LISTEN_ADDR", "127.0.0.1:9090"),
+        ("API_KEYS", "a,b"),
+        ("ENGINE_WORKERS", "2"),
+        ("PREFIX_CACHE_ENTRIES", "0"),
+        ("RESPONSE_CACHE_TTL_SECS", "30"),
+        ("STORAGE_BACKEND", "fs"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    assert_eq!(toml.env_vars(), expected);
+    assert_eq!(yaml.env_vars(), expected);
+    assert_eq!(yaml.models[0].options.n_ctx, Some(4096));
+    assert_eq!(yaml.models[1].options.embedding_dim, Some(16));
+
+    let err = |text: &str| Config::from_toml(text).unwrap_err();
+    assert!(err("[server]\nlisten_addr = \"0.0.0.0:1\"").contains("unknown field"));
+    assert!(err("[server]\nlisten = \"localhost\"").contains("server.listen"));
+    assert!(err("[server]\nadmin_listen = \":9090\"").contains("server.admin_listen"));
+    assert!(err("[server]\ntls_cert = \"cert.pem\"").contains("tls_key"));
+    assert!(err("[engine]\nworkers = 0").contains("engine.workers"));
+    assert!(err("[[models]]\nmodel = \"m\"\nkind = \"weights\"").contains("unknown kind"));
+    assert!(err("[[models]]\nmodel = \"m\"\nkind = \"llm\"\nn_ctx = 0").contains("n_ctx"));
+}
+
+#[test]
+fn config_path_comes_from_the_command_line() {
+    let args = |args: &[&str]| Cli::try_parse_from([&["llm-serving"], args].concat()).unwrap().config_path();
+    assert_eq!(args(&["--config", "server.toml"]), Some(PathBuf::from("server.toml")));
+    assert_eq!(args(&["--config=server.yaml"]), Some(PathBuf::from("server.yaml")));
+
+    let dir = std::env::temp_dir().join(format!("config-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("server.yml"), YAML).unwrap();
+    std::fs::write(dir.join("server.toml"), TOML).unwrap();
+    assert_eq!(Config::load(&dir.join("server.yml")).unwrap().engine.workers, Some(2));
+    assert_eq!(Config::load(&dir.join("server.toml")).unwrap().engine.workers, Some(2));
+    assert!(Config::load(&dir.join("missing.toml")).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn the_environment_overrides_the_file() {
+    let config = Config::from_toml("[env]\nCONFIG_TEST_SET = \"file\"\nCONFIG_TEST_UNSET = \"file\"").unwrap();
+    // Variables only this test uses
+    unsafe { std::env::set_var("CONFIG_TEST_SET", "env") };
+    config.install();
+    assert_eq!(var("CONFIG_TEST_SET").unwrap(), "env");
+    assert_eq!(var("CONFIG_TEST_UNSET").unwrap(), "file");
+    assert!(std::env::var("CONFIG_TEST_UNSET").is_err());
+    assert!(var("CONFIG_TEST_MISSING").is_err());
+}
+
+#[tokio::test]
+async fn models_are_preloaded_in_order() {
+    let engine = CoreEngine::new();
+    Config::from_toml(TOML).unwrap().preload(&engine).await.unwrap();
+    let models = engine.list_models().await;
+    assert!(models.llm.contains(&"chat".to_string()));
+    assert!(models.embedding.contains(&"vectors".to_string()));
+    let chat = models.models.iter().find(|m| m.name == "chat").unwrap();
+    assert_eq!(chat.options.as_ref().unwrap().chat_template.as_deref(), Some("llama3"));
+}
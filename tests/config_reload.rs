@@ -0,0 +1,124 @@
+use llm_serving::{
+    config::{var, Config},
+    engine::CoreEngine,
+};
+
+const BEFORE: &str = r#"
+[[models]]
+model = "chat"
+kind = "llm"
+
+[[models]]
+model = "vectors"
+kind = "embedding"
+embedding_dim = 16
+
+[[models]]
+model = "retired"
+kind = "llm"
+
+[env]
+RELOAD_TEST_SETTING = "before"
+"#;
+
+// `chat` gets a template that does not render, `vectors` a new dimension, `retired` goes
+// and `fresh` comes
+const BROKEN: &str = r#"
+[[models]]
+model = "chat"
+kind = "llm"
+chat_template = "{% if %}"
+
+[[models]]
+model = "vectors"
+kind = "embedding"
+embedding_dim = 32
+
+[[models]]
+model = "fresh"
+kind = "llm"
+
+[env]
+RELOAD_TEST_SETTING = "after"
+"#;
+
+// The same with a template that renders
+const AFTER: &str = r#"
+[[models]]
+model = "chat"
+kind = "llm"
+chat_template = "chatml"
+
+[[models]]
+model = "vectors"
+kind = "embedding"
+embedding_dim = 32
+
+[[models]]
+model = "fresh"
+kind = "llm"
+
+[env]
+RELOAD_TEST_SETTING = "after"
+"#;
+
+async fn names(engine: &CoreEngine) -> Vec<String> {
+    engine.list_models().await.models.into_iter().map(|m| m.name).collect()
+}
+
+async fn embedding_dim(engine: &CoreEngine, name: &str) -> Option<u32> {
+    let models = engine.list_models().await.models;
+    models.into_iter().find(|m| m.name == name).and_then(|m| m.options?.embedding_dim)
+}
+
+#[tokio::test]
+async fn reloading_applies_settings_and_model_changes_all_or_nothing() {
+    let dir = std::env::temp_dir().join(format!("config-reload-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.toml");
+    std::fs::write(&path, BEFORE).unwrap();
+
+    let engine = CoreEngine::new();
+    engine.apply_config(path.clone(), Config::load(&path).unwrap()).await.unwrap();
+    assert_eq!(var("RELOAD_TEST_SETTING").unwrap(), "before");
+    let report = engine.reload_config().await.unwrap();
+    assert_eq!(report.unchanged, ["chat", "vectors", "retired"]);
+    assert!(report.loaded.is_empty() && report.unloaded.is_empty());
+
+    // One model that cannot load keeps every model and setting as it was
+    std::fs::write(&path, BROKEN).unwrap();
+    let err = engine.reload_config().await.unwrap_err();
+    assert!(err.contains("chat"), "{}", err);
+    assert_eq!(var("RELOAD_TEST_SETTING").unwrap(), "before");
+    let listed = names(&engine).await;
+    assert!(listed.contains(&"retired".to_string()) && !listed.contains(&"fresh".to_string()), "{:?}", listed);
+    assert_eq!(embedding_dim(&engine, "vectors").await, Some(16));
+
+    std::fs::write(&path, AFTER).unwrap();
+    let report = engine.reload_config().await.unwrap();
+    assert_eq!(var("RELOAD_TEST_SETTING").unwrap(), "after");
+    assert_eq!(report.loaded, ["chat", "vectors", "fresh"]);
+    assert_eq!(report.unloaded, ["retired"]);
+    assert!(report.unchanged.is_empty());
+    let listed = names(&engine).await;
+    assert!(listed.contains(&"fresh".to_string()) && !listed.contains(&"retired".to_string()), "{:?}", listed);
+    assert_eq!(embedding_dim(&engine, "vectors").await, Some(32));
+
+    // A model unloaded by hand is loaded again
+    engine.unload_model("llm", "fresh").await.unwrap();
+    let report = engine.reload_config().await.unwrap();
+    assert_eq!(report.loaded, ["fresh"]);
+    assert_eq!(report.unchanged, ["chat", "vectors"]);
+
+    // A file that no longer parses changes nothing
+    std::fs::write(&path, "[server]\nlisten = \"nowhere\"").unwrap();
+    assert!(engine.reload_config().await.unwrap_err().contains("server.listen"));
+    assert_eq!(var("RELOAD_TEST_SETTING").unwrap(), "after");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn reloading_needs_a_configuration_file() {
+    let err = CoreEngine::new().reload_config().await.unwrap_err();
+    assert!(err.contains("without a configuration file"), "{}", err);
+}
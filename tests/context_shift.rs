@@ -0,0 +1,20 @@
+use llm_serving::runtime::context_shift::{apply, plan, ContextShift};
+
+#[test]
+fn shifts_only_when_the_context_is_full() {
+    assert_eq!(plan(100, 128, 10), None);
+    assert_eq!(plan(128, 128, 10), Some(ContextShift { n_keep: 10, n_discard: 59 }));
+}
+
+#[test]
+fn kept_prefix_is_capped_to_half_the_window() {
+    assert_eq!(plan(128, 128, 200), Some(ContextShift { n_keep: 64, n_discard: 32 }));
+}
+
+#[test]
+fn apply_keeps_the_prefix_and_the_most_recent_tokens() {
+    let tokens: Vec<u32> = (0..10).collect();
+    let shift = plan(10, 10, 2).unwrap();
+    assert_eq!(shift, ContextShift { n_keep: 2, n_discard: 4 });
+    assert_eq!(apply(&tokens, shift), vec![0, 1, 6, 7, 8, 9]);
+}
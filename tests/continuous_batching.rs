@@ -0,0 +1,159 @@
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use tokio::sync::mpsc;
+
+use llm_serving::{
+    engine::continuous::{ContinuousBatcher, ContinuousBatchingConfig},
+    runtime::{BatchDecodeRuntime, GenerationOptions, LlmRuntime, RuntimeError, SequenceId, StepOutput},
+};
+
+// Counts down from the prompt's number, one token per step, and remembers how full steps were
+#[derive(Default)]
+struct CountdownDecoder {
+    sequences: Mutex<HashMap<SequenceId, u32>>,
+    step_sizes: Mutex<Vec<usize>>,
+}
+
+impl BatchDecodeRuntime for CountdownDecoder {
+    fn max_sequences(&self) -> usize {
+        8
+    }
+
+    fn add_sequence(&self, id: SequenceId, prompt: &str, _options: &GenerationOptions) -> Result<(), RuntimeError> {
+        let n = prompt.parse().map_err(|_| RuntimeError::InvalidInput(format!("not a number: {}", prompt)))?;
+        self.sequences.lock().unwrap().insert(id, n);
+        Ok(())
+    }
+
+    fn step(&self, ids: &[SequenceId]) -> Vec<StepOutput> {
+        self.step_sizes.lock().unwrap().push(ids.len());
+        std::thread::sleep(Duration::from_millis(2));
+        let mut sequences = self.sequences.lock().unwrap();
+        ids.iter()
+            .map(|id| {
+                let n = sequences.get_mut(id).unwrap();
+                if *n == 0 {
+                    return StepOutput::Finished;
+                }
+                *n -= 1;
+                StepOutput::Piece(format!("{} ", *n + 1))
+            })
+            .collect()
+    }
+
+    fn remove_sequence(&self, id: SequenceId) {
+        self.sequences.lock().unwrap().remove(&id);
+    }
+
+    fn accepts(&self, options: &GenerationOptions) -> bool {
+        options.grammar.is_none()
+    }
+}
+
+struct CountdownRuntime(Arc<CountdownDecoder>);
+
+#[async_trait]
+impl LlmRuntime for CountdownRuntime {
+    async fn generate(&self, _prompt: &str, _options: &GenerationOptions) -> Result<String, RuntimeError> {
+        Err(RuntimeError::Backend("only batched decoding is supported".to_string()))
+    }
+
+    fn batch_decoder(&self) -> Option<Arc<dyn BatchDecodeRuntime>> {
+        Some(self.0.clone())
+    }
+}
+
+fn options() -> GenerationOptions {
+    GenerationOptions::from_request(None, None, None)
+}
+
+#[tokio::test]
+async fn concurrent_generations_share_decode_steps() {
+    let decoder = Arc::new(CountdownDecoder::default());
+    let runtime = CountdownRuntime(decoder.clone());
+    let batcher = ContinuousBatcher::new(ContinuousBatchingConfig { max_sequences: 4 });
+
+    let options = options();
+    let results = futures::future::join_all(
+        ["3", "10", "5", "1", "7", "2"].map(|prompt| batcher.generate("countdown", &runtime, prompt, &options, None)),
+    )
+    .await;
+    let texts: Vec<String> = results.into_iter().map(Result::unwrap).collect();
+    assert_eq!(texts[0], "3 2 1 ");
+    assert_eq!(texts[1], "10 9 8 7 6 5 4 3 2 1 ");
+    assert_eq!(texts[3], "1 ");
+
+    let step_sizes = decoder.step_sizes.lock().unwrap();
+    assert!(step_sizes.iter().all(|&n| n <= 4), "{:?}", step_sizes);
+    assert!(step_sizes.iter().any(|&n| n > 1), "{:?}", step_sizes);
+    // Finished sequences make room for waiting ones instead of the batch draining first
+    assert!(step_sizes.len() < 10 + 7, "{:?}", step_sizes);
+    assert!(decoder.sequences.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn cancelled_generation_leaves_the_batch() {
+    let decoder = Arc::new(CountdownDecoder::default());
+    let runtime = CountdownRuntime(decoder.clone());
+    let batcher = ContinuousBatcher::new(ContinuousBatchingConfig { max_sequences: 4 });
+
+    let (cancelled, uncancelled) = (options(), options());
+    let (tx, mut rx) = mpsc::channel(64);
+    let stream = batcher.generate_stream("countdown", &runtime, "1000", &cancelled, tx, None);
+    let other = batcher.generate("countdown", &runtime, "20", &uncancelled, None);
+    let cancel_after_first = async {
+        assert_eq!(rx.recv().await.as_deref(), Some("1000 "));
+        cancelled.cancel.cancel();
+        while rx.recv().await.is_some() {}
+    };
+    let (stream, other, _) = tokio::join!(stream, other, cancel_after_first);
+    assert_eq!(stream, Ok(()));
+    assert!(other.unwrap().starts_with("20 19 "));
+    assert!(decoder.sequences.lock().unwrap().is_empty());
+
+    // Admission errors reach the caller
+    let error = batcher.generate("countdown", &runtime, "many", &uncancelled, None).await.unwrap_err();
+    assert_eq!(error, RuntimeError::InvalidInput("not a number: many".to_string()));
+}
+
+#[tokio::test]
+async fn generations_the_decoder_declines_use_the_runtime() {
+    let decoder = Arc::new(CountdownDecoder::default());
+    let runtime = CountdownRuntime(decoder.clone());
+    let batcher = ContinuousBatcher::new(ContinuousBatchingConfig { max_sequences: 4 });
+
+    let mut constrained = options();
+    constrained.grammar = Some(r#"root ::= "1""#.to_string());
+    let error = batcher.generate("countdown", &runtime, "1", &constrained, None).await.unwrap_err();
+    assert_eq!(error, RuntimeError::Backend("only batched decoding is supported".to_string()));
+    assert!(decoder.step_sizes.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn slow_readers_pause_without_holding_up_new_generations() {
+    let decoder = Arc::new(CountdownDecoder::default());
+    let runtime = CountdownRuntime(decoder.clone());
+    let batcher = ContinuousBatcher::new(ContinuousBatchingConfig { max_sequences: 4 });
+
+    let options = options();
+    let (tx, mut rx) = mpsc::channel(1);
+    let slow = batcher.generate_stream("countdown", &runtime, "5", &options, tx, None);
+    let reader = async {
+        assert_eq!(rx.recv().await.as_deref(), Some("5 "));
+        // The reader is behind: its sequence sits out, and the scheduler waits rather than stepping
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let paused_steps = decoder.step_sizes.lock().unwrap().len();
+        // A generation arriving meanwhile is admitted and decoded
+        let other = batcher.generate("countdown", &runtime, "2", &options, None).await.unwrap();
+        assert_eq!(other, "2 1 ");
+        assert!(decoder.step_sizes.lock().unwrap().len() > paused_steps);
+        let mut rest = String::new();
+        while let Some(piece) = rx.recv().await {
+            rest.push_str(&piece);
+        }
+        rest
+    };
+    let (slow, rest) = tokio::join!(slow, reader);
+    assert_eq!(slow, Ok(()));
+    assert_eq!(rest, "4 3 2 1 ");
+}
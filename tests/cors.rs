@@ -0,0 +1,76 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::{cors, routes::chat_completions},
+    config::Config,
+    engine::CoreEngine,
+};
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn app(origins: &[&str]) -> Router {
+    let layer = cors::layer(&strings(origins), &[], &[], false, Some(60)).unwrap().unwrap();
+    Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(Arc::new(CoreEngine::new())).layer(layer)
+}
+
+#[tokio::test]
+async fn preflights_and_streams_carry_cors_headers_for_allowed_origins() {
+    let app = app(&["https://app.example.com"]);
+    let preflight = |origin: &str| {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/v1/chat/completions")
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "authorization,content-type")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let resp = app.clone().oneshot(preflight("https://app.example.com")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let headers = resp.headers();
+    assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+    assert_eq!(headers["access-control-allow-headers"], "authorization,content-type");
+    assert!(headers["access-control-allow-methods"].to_str().unwrap().contains("POST"));
+    assert_eq!(headers["access-control-max-age"], "60");
+
+    let resp = app.clone().oneshot(preflight("https://evil.example.com")).await.unwrap();
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+
+    // A streamed answer carries the headers on its head
+    let body = json!({"model": "dummy-model", "stream": true, "messages": [{"role": "user", "content": "hi"}]});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("origin", "https://app.example.com")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app.oneshot(request).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    assert_eq!(resp.headers()["access-control-allow-origin"], "https://app.example.com");
+    assert!(resp.headers()["access-control-expose-headers"].to_str().unwrap().contains("x-cache"));
+}
+
+#[test]
+fn cors_settings_are_checked() {
+    assert!(cors::layer(&[], &[], &[], false, None).unwrap().is_none());
+    assert!(cors::layer(&strings(&["*"]), &[], &[], true, None).unwrap_err().contains("explicit"));
+    assert!(cors::layer(&strings(&["*"]), &[], &strings(&["GET", "not a method"]), false, None).is_err());
+
+    let config = Config::from_toml("[cors]\nallowed_origins = [\"https://a.example\", \"https://b.example\"]\nmax_age_secs = 30").unwrap();
+    let vars = config.env_vars();
+    assert!(vars.contains(&("CORS_ALLOWED_ORIGINS".to_string(), "https://a.example,https://b.example".to_string())));
+    assert!(Config::from_toml("[cors]\nallowed_origins = [\"*\"]\nallow_credentials = true").unwrap_err().contains("cors"));
+}
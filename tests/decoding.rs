@@ -0,0 +1,32 @@
+use llm_serving::runtime::decoding::{end_tokens, TextStream};
+
+// Byte-level vocabulary: token n is byte n, as with byte-fallback tokenizers
+fn decode(tokens: &[u32]) -> Result<String, String> {
+    let bytes: Vec<u8> = tokens.iter().map(|&t| t as u8).collect();
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[test]
+fn text_stream_waits_for_whole_characters() {
+    let mut text = TextStream::default();
+    assert_eq!(text.push(b'h' as u32, decode).unwrap(), "h");
+    // "é" is two bytes; the first alone decodes as a replacement character
+    let [first, second] = "é".as_bytes() else { unreachable!() };
+    assert_eq!(text.push(*first as u32, decode).unwrap(), "");
+    assert_eq!(text.push(*second as u32, decode).unwrap(), "é");
+    assert_eq!(text.push(*first as u32, decode).unwrap(), "");
+    // Generation ended mid-character: what is left still comes out
+    assert_eq!(text.finish(decode).unwrap(), "\u{FFFD}");
+}
+
+#[test]
+fn end_tokens_come_from_the_config_and_the_vocabulary() {
+    let vocab = |token: &str| match token {
+        "<|eot_id|>" => Some(7),
+        "</s>" => Some(2),
+        _ => None,
+    };
+    assert_eq!(end_tokens(&serde_json::json!({ "eos_token_id": [2, 9] }), vocab), vec![2, 7, 9]);
+    assert_eq!(end_tokens(&serde_json::json!({ "eos_token_id": 9 }), vocab), vec![2, 7, 9]);
+    assert_eq!(end_tokens(&serde_json::Value::Null, |_| None), Vec::<u32>::new());
+}
@@ -0,0 +1,63 @@
+use llm_serving::runtime::{
+    diffusion::{chw_to_rgb, guide, latent_preview, EulerScheduler},
+    image::{encode_png, parse_size, png_dimensions},
+};
+
+#[test]
+fn encoded_png_is_well_formed() {
+    let rgb: Vec<u8> = (0..4 * 3 * 3).map(|i| i as u8).collect();
+    let png = encode_png(&rgb, 4, 3).unwrap();
+    assert_eq!(png_dimensions(&png).unwrap(), (4, 3));
+    // IEND with its fixed CRC closes the file
+    assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    assert!(encode_png(&rgb, 5, 3).is_err());
+
+    // Images larger than one stored deflate block still encode
+    let big = vec![7u8; 200 * 200 * 3];
+    assert_eq!(png_dimensions(&encode_png(&big, 200, 200).unwrap()).unwrap(), (200, 200));
+}
+
+#[test]
+fn sizes_parse_as_width_by_height() {
+    assert_eq!(parse_size("512x768").unwrap(), (512, 768));
+    assert!(parse_size("512").is_err());
+    assert!(parse_size("0x512").is_err());
+}
+
+#[test]
+fn euler_schedule_runs_from_full_noise_to_clean() {
+    let scheduler = EulerScheduler::new(25);
+    assert_eq!(scheduler.timesteps().len(), 25);
+    assert_eq!(scheduler.timesteps()[0], 999.0);
+    assert_eq!(*scheduler.timesteps().last().unwrap(), 0.0);
+    let sigmas = scheduler.sigmas();
+    assert_eq!(sigmas.len(), 26);
+    assert!((sigmas[0] - 14.61).abs() < 0.01, "sigma_max {}", sigmas[0]);
+    assert_eq!(sigmas[25], 0.0);
+    assert!(sigmas.windows(2).all(|w| w[0] > w[1]));
+
+    // Stepping with the exact noise removes it entirely by the last step
+    let noise = vec![0.5f32; 4];
+    let mut latents: Vec<f32> = noise.iter().map(|n| n * sigmas[0]).collect();
+    for index in 0..25 {
+        scheduler.step(&noise, index, &mut latents);
+    }
+    assert!(latents.iter().all(|x| x.abs() < 1e-4));
+}
+
+#[test]
+fn guidance_and_pixel_conversion() {
+    assert_eq!(guide(&[1.0, 0.0], &[2.0, 1.0], 7.5), vec![8.5, 7.5]);
+    // 2x1 image: red channel plane, then green, then blue
+    assert_eq!(chw_to_rgb(&[1.0, -1.0, 0.0, 0.0, -1.0, 1.0], 2, 1), vec![255, 128, 0, 0, 128, 255]);
+}
+
+#[test]
+fn latent_preview_is_rgb_at_latent_resolution() {
+    // 2x1 latent: zeros stay mid-grey, a strong first channel reads as bright
+    let latents = [0.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    let rgb = latent_preview(&latents, 2, 1);
+    assert_eq!(rgb.len(), 2 * 3);
+    assert_eq!(&rgb[..3], &[128, 128, 128]);
+    assert!(rgb[3..].iter().all(|&c| c > 200));
+}
@@ -0,0 +1,141 @@
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use llm_serving::{
+    api::routes::{admin_downloads_cancel, admin_downloads_create, admin_downloads_get, admin_downloads_list, admin_models_list},
+    engine::{
+        hub::{fetch_with_progress, HubConfig, Progress},
+        CoreEngine,
+    },
+};
+
+mod common;
+use common::send;
+
+fn content() -> Vec<u8> {
+    b"0123456789".repeat(1000)
+}
+
+// Hub stand-in that honours ranged requests; `slow.gguf` never finishes
+async fn serve(ranges: Arc<Mutex<Vec<String>>>) -> String {
+    let app = Router::new().route(
+        "/:owner/:name/resolve/:revision/*file",
+        get(move |headers: HeaderMap, Path((_, _, _, file)): Path<(String, String, String, String)>| async move {
+            if file == "slow.gguf" {
+                let first = futures::stream::once(async { Ok::<_, std::io::Error>(vec![0u8; 1000]) });
+                return Body::from_stream(futures::StreamExt::chain(first, futures::stream::pending())).into_response();
+            }
+            let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+            let start = header("range").and_then(|range| {
+                ranges.lock().unwrap().push(range.clone());
+                range.strip_prefix("bytes=")?.trim_end_matches('-').parse::<usize>().ok()
+            });
+            match start {
+                Some(start) if header("if-range").as_deref() == Some("\"v1\"") => {
+                    (StatusCode::PARTIAL_CONTENT, [("etag", "\"v1\"")], content()[start..].to_vec()).into_response()
+                }
+                _ => ([("etag", "\"v1\"")], content()).into_response(),
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+async fn wait_for(app: &Router, id: &str, done: impl Fn(&Value) -> bool) -> Value {
+    for _ in 0..200 {
+        let (_, job) = send(app, "GET", &format!("/admin/downloads/{}", id), None).await;
+        if done(&job) {
+            return job;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("download {} did not get there", id);
+}
+
+#[tokio::test]
+async fn downloads_run_in_the_background_and_load_the_model() {
+    let cache_dir = std::env::temp_dir().join(format!("download-cache-{}", uuid::Uuid::new_v4().simple()));
+    // The only test here that reads the hub settings from the environment
+    unsafe {
+        std::env::set_var("HF_ENDPOINT", serve(Arc::default()).await);
+        std::env::set_var("MODEL_CACHE_DIR", &cache_dir);
+    }
+    let app = Router::new()
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/downloads", post(admin_downloads_create).get(admin_downloads_list))
+        .route("/admin/downloads/:id", get(admin_downloads_get))
+        .route("/admin/downloads/:id/cancel", post(admin_downloads_cancel))
+        .with_state(Arc::new(CoreEngine::new()));
+
+    let create = json!({"model": "tiny", "kind": "llm", "hf_repo": "acme/tiny-GGUF", "filename": "tiny.Q4_K_M.gguf"});
+    let (status, job) = send(&app, "POST", "/admin/downloads", Some(create)).await;
+    assert_eq!(status, StatusCode::OK, "{}", job);
+    assert_eq!(job["object"], "model.download");
+    let job = wait_for(&app, job["id"].as_str().unwrap(), |job| job["status"] != "downloading" && job["status"] != "loading").await;
+    assert_eq!(job["status"], "completed", "{}", job);
+    assert_eq!(job["downloaded_bytes"], 10_000);
+    assert_eq!(job["total_bytes"], 10_000);
+    assert_eq!(job["files_completed"], 1);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert!(models["llm"].as_array().unwrap().iter().any(|m| m == "tiny"), "{}", models);
+
+    // Cancelled downloads keep what they received
+    let create = json!({"model": "slow", "kind": "llm", "hf_repo": "acme/tiny-GGUF", "filename": "slow.gguf"});
+    let (_, job) = send(&app, "POST", "/admin/downloads", Some(create)).await;
+    let id = job["id"].as_str().unwrap().to_string();
+    wait_for(&app, &id, |job| job["downloaded_bytes"] == 1000).await;
+    let (status, _) = send(&app, "POST", &format!("/admin/downloads/{}/cancel", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    wait_for(&app, &id, |job| job["status"] == "cancelled").await;
+    assert!(cache_dir.join("acme--tiny-GGUF/main/slow.gguf.part").exists());
+    let (status, _) = send(&app, "POST", &format!("/admin/downloads/{}/cancel", id), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, list) = send(&app, "GET", "/admin/downloads", None).await;
+    assert_eq!(list["data"].as_array().unwrap().len(), 2);
+    let (status, _) = send(&app, "POST", "/admin/downloads", Some(json!({"model": "x", "kind": "llm"}))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, "GET", "/admin/downloads/download-missing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let _ = std::fs::remove_dir_all(&cache_dir);
+}
+
+#[tokio::test]
+async fn partial_downloads_resume_where_they_stopped() {
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let config = HubConfig {
+        endpoint: serve(ranges.clone()).await,
+        cache_dir: std::env::temp_dir().join(format!("download-cache-{}", uuid::Uuid::new_v4().simple())),
+        token: None,
+    };
+    let dir = config.cache_dir.join("acme--tiny-GGUF/main");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("tiny.gguf.part"), &content()[..4000]).unwrap();
+    std::fs::write(dir.join("tiny.gguf.part.etag"), "\"v1\"").unwrap();
+
+    let progress = Progress::default();
+    let path = fetch_with_progress(&config, "acme/tiny-GGUF", Some("tiny.gguf"), None, &progress, &CancellationToken::new()).await.unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), content());
+    assert_eq!(*ranges.lock().unwrap(), ["bytes=4000-"]);
+    assert_eq!(progress.total_bytes.load(std::sync::atomic::Ordering::Relaxed), 10_000);
+    assert!(!dir.join("tiny.gguf.part").exists() && !dir.join("tiny.gguf.part.etag").exists());
+
+    // A partial file whose version changed upstream is downloaded again in full
+    std::fs::write(dir.join("other.gguf.part"), b"stale").unwrap();
+    std::fs::write(dir.join("other.gguf.part.etag"), "\"v0\"").unwrap();
+    let path = fetch_with_progress(&config, "acme/tiny-GGUF", Some("other.gguf"), None, &Progress::default(), &CancellationToken::new()).await.unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), content());
+    let _ = std::fs::remove_dir_all(&config.cache_dir);
+}
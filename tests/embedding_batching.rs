@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+use std::time::Duration;
+
+use llm_serving::{
+    engine::{batching::{BatchingConfig, EmbeddingBatcher}, embeddings::EmbeddingBatch},
+    runtime::{EmbeddingRuntime, RuntimeError},
+};
+
+#[derive(Default)]
+struct CountingRuntime {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl EmbeddingRuntime for CountingRuntime {
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, RuntimeError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(inputs.iter().map(|t| vec![t.len() as f32]).collect())
+    }
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_one_embed_call() {
+    let runtime = Arc::new(CountingRuntime::default());
+    let batcher = Arc::new(EmbeddingBatcher::new(BatchingConfig { max_batch_size: 16, max_wait: Duration::from_millis(50) }));
+
+    let handles: Vec<_> = (1..=4)
+        .map(|n| {
+            let batcher = batcher.clone();
+            let runtime: Arc<dyn EmbeddingRuntime> = runtime.clone();
+            tokio::spawn(async move {
+                let texts = vec!["x".repeat(n); n];
+                batcher.embed("m", runtime, EmbeddingBatch::Texts(texts)).await
+            })
+        })
+        .collect();
+
+    for (n, handle) in (1..=4).zip(handles) {
+        let vectors = handle.await.unwrap().unwrap();
+        // Each caller gets back exactly its own inputs, in order
+        assert_eq!(vectors, vec![vec![n as f32]; n]);
+    }
+    assert_eq!(runtime.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn batch_size_caps_coalescing() {
+    let runtime = Arc::new(CountingRuntime::default());
+    let batcher = Arc::new(EmbeddingBatcher::new(BatchingConfig { max_batch_size: 2, max_wait: Duration::from_millis(50) }));
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let batcher = batcher.clone();
+            let runtime: Arc<dyn EmbeddingRuntime> = runtime.clone();
+            tokio::spawn(async move { batcher.embed("m", runtime, EmbeddingBatch::Texts(vec!["a".into()])).await })
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.await.unwrap().unwrap().len(), 1);
+    }
+    assert_eq!(runtime.calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,116 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::get, Router};
+use std::time::Duration;
+use futures::StreamExt;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::api::limits::{EndpointClass, EndpointLimitLayer, EndpointLimits};
+
+fn get_request(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn streaming_response_holds_its_slot_until_the_body_is_done() {
+    let limits = EndpointLimits { max_concurrency: 1, timeout: None };
+    let app = Router::new()
+        .route("/stream", get(|| async {
+            let chunks = futures::stream::iter(["a", "b"]).map(Ok::<_, std::convert::Infallible>);
+            Body::from_stream(chunks)
+        }))
+        .route_layer(EndpointLimitLayer::new(EndpointClass::Chat, limits));
+
+    let first = app.clone().oneshot(get_request("/stream")).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+
+    // The first body has not been consumed, so the only slot is still taken
+    let second = app.clone().oneshot(get_request("/stream")).await.unwrap();
+    assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let body = axum::body::to_bytes(first.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"ab");
+    let third = app.clone().oneshot(get_request("/stream")).await.unwrap();
+    assert_eq!(third.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn classes_do_not_share_slots_and_slow_handlers_time_out() {
+    let chat = Router::new()
+        .route("/chat", get(|| async { "chat" }))
+        .route_layer(EndpointLimitLayer::new(EndpointClass::Chat, EndpointLimits { max_concurrency: 1, timeout: None }));
+    let embeddings = Router::new()
+        .route("/embed", get(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "late"
+        }))
+        .route_layer(EndpointLimitLayer::new(
+            EndpointClass::Embeddings,
+            EndpointLimits { max_concurrency: 1, timeout: Some(Duration::from_millis(50)) },
+        ));
+    let app = Router::new().merge(chat).merge(embeddings);
+
+    // A chat response whose body is never read does not hold up other classes
+    let held = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    let embed = app.clone().oneshot(get_request("/embed")).await.unwrap();
+    assert_eq!(embed.status(), StatusCode::GATEWAY_TIMEOUT);
+    // A timed-out request gives its slot back
+    let embed = app.clone().oneshot(get_request("/embed")).await.unwrap();
+    assert_eq!(embed.status(), StatusCode::GATEWAY_TIMEOUT);
+    drop(held);
+
+    let chat = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    assert_eq!(chat.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn limits_can_change_while_serving() {
+    let layer = EndpointLimitLayer::new(EndpointClass::Chat, EndpointLimits { max_concurrency: 1, timeout: None });
+    let app = Router::new().route("/chat", get(|| async { "chat" })).route_layer(layer.clone());
+
+    let first = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    let second = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    layer.set_limits(EndpointLimits { max_concurrency: 2, timeout: None });
+    let second = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    assert_eq!(second.status(), StatusCode::OK);
+
+    // Held slots are retired as they come back
+    layer.set_limits(EndpointLimits { max_concurrency: 1, timeout: None });
+    drop(first);
+    tokio::task::yield_now().await;
+    let third = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    assert_eq!(third.status(), StatusCode::SERVICE_UNAVAILABLE);
+    drop(second);
+    let third = app.clone().oneshot(get_request("/chat")).await.unwrap();
+    assert_eq!(third.status(), StatusCode::OK);
+}
+
+#[test]
+fn lowering_and_raising_limits_keeps_the_count_exact() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let layer = EndpointLimitLayer::new(EndpointClass::Chat, EndpointLimits { max_concurrency: 2, timeout: None });
+    let app = Router::new().route("/chat", get(|| async { "chat" })).route_layer(layer.clone());
+    let call = || runtime.block_on(app.clone().oneshot(get_request("/chat"))).unwrap();
+
+    let (first, second) = (call(), call());
+    assert_eq!((first.status(), second.status()), (StatusCode::OK, StatusCode::OK));
+    // Lowered and raised back while both slots are held: still two
+    layer.set_limits(EndpointLimits { max_concurrency: 1, timeout: None });
+    layer.set_limits(EndpointLimits { max_concurrency: 2, timeout: None });
+    assert_eq!(call().status(), StatusCode::SERVICE_UNAVAILABLE);
+    drop(first);
+    let third = call();
+    assert_eq!(third.status(), StatusCode::OK);
+    assert_eq!(call().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // Lowered again: the slot released next is retired, the one after is free again
+    layer.set_limits(EndpointLimits { max_concurrency: 1, timeout: None });
+    drop(second);
+    assert_eq!(call().status(), StatusCode::SERVICE_UNAVAILABLE);
+    drop(third);
+    let fourth = call();
+    assert_eq!(fourth.status(), StatusCode::OK);
+    assert_eq!(call().status(), StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,97 @@
+use axum::{routing::{get, post}, Router};
+use axum::http::{Request, StatusCode};
+use axum::body::Body;
+use tower::util::ServiceExt; // for `oneshot`
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{
+    api::routes::{admin_evals_create, admin_evals_history, admin_evals_list, admin_evals_run, admin_evals_run_get},
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+#[tokio::test]
+async fn eval_run_scores_dataset_and_records_history() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/admin/evals", post(admin_evals_create).get(admin_evals_list))
+        .route("/admin/evals/:id/runs", post(admin_evals_run).get(admin_evals_history))
+        .route("/admin/evals/runs/:run_id", get(admin_evals_run_get))
+        .with_state(engine);
+
+    let dataset = json!({
+        "name": "smoke",
+        "cases": [
+            {"prompt": "hi", "grader": {"type": "exact_match", "expected": "Echo: hi"}},
+            {"prompt": "abc", "grader": {"type": "regex", "pattern": "^Echo: a"}},
+            {"prompt": "xyz", "grader": {"type": "exact_match", "expected": "nope"}}
+        ]
+    });
+    let (status, v) = send(&app, "POST", "/admin/evals", Some(dataset)).await;
+    assert_eq!(status, StatusCode::OK);
+    let dataset_id = v["id"].as_str().unwrap().to_string();
+    assert_eq!(v["cases"], 3);
+
+    let (status, v) = send(&app, "POST", &format!("/admin/evals/{}/runs", dataset_id), Some(json!({"model": "dummy-model", "min_score": 0.5}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let run_id = v["id"].as_str().unwrap().to_string();
+
+    let mut run = Value::Null;
+    for _ in 0..50 {
+        let (_, v) = send(&app, "GET", &format!("/admin/evals/runs/{}", run_id), None).await;
+        if v["status"] == "completed" { run = v; break; }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(run["status"], "completed");
+    assert_eq!(run["passed"], 2);
+    assert_eq!(run["gate_passed"], true);
+
+    let (status, v) = send(&app, "GET", &format!("/admin/evals/{}/runs", dataset_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["runs"].as_array().unwrap().len(), 1);
+    assert_eq!(v["runs"][0]["model"], "dummy-model");
+
+    let (status, v) = send(&app, "POST", "/admin/evals/missing/runs", Some(json!({"model": "dummy-model"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(v["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn eval_dataset_creation_is_idempotent_per_key() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/admin/evals", post(admin_evals_create).get(admin_evals_list))
+        .with_state(engine);
+
+    let dataset = json!({"name": "idem", "cases": [{"prompt": "hi", "grader": {"type": "exact_match", "expected": "Echo: hi"}}]});
+    let post_with_key = |body: Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/admin/evals")
+            .header("content-type", "application/json")
+            .header("idempotency-key", "eval-create-once")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    let first = app.clone().oneshot(post_with_key(dataset.clone())).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    let first: Value = serde_json::from_slice(&axum::body::to_bytes(first.into_body(), usize::MAX).await.unwrap()).unwrap();
+
+    let retry = app.clone().oneshot(post_with_key(dataset)).await.unwrap();
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.headers().get("idempotent-replayed").unwrap(), "true");
+    let retry: Value = serde_json::from_slice(&axum::body::to_bytes(retry.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(first["id"], retry["id"]);
+
+    let (_, v) = send(&app, "GET", "/admin/evals", None).await;
+    assert_eq!(v["data"].as_array().unwrap().len(), 1);
+
+    // Reusing the key for a different payload is rejected
+    let other = json!({"name": "other", "cases": [{"prompt": "x", "grader": {"type": "regex", "pattern": "x"}}]});
+    let conflict = app.clone().oneshot(post_with_key(other)).await.unwrap();
+    assert_eq!(conflict.status(), StatusCode::BAD_REQUEST);
+}
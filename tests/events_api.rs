@@ -0,0 +1,51 @@
+use axum::{routing::{get, post}, Router};
+use axum::http::{Request, StatusCode};
+use axum::body::Body;
+use futures::StreamExt;
+use tower::util::ServiceExt; // for `oneshot`
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{
+    api::routes::{admin_events, admin_models_load, chat_completions},
+    engine::CoreEngine,
+};
+
+#[tokio::test]
+async fn admin_events_streams_typed_engine_events() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/events", get(admin_events))
+        .with_state(engine);
+
+    let response = app.clone().oneshot(Request::builder().uri("/admin/events").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/event-stream"));
+    let mut body = response.into_body().into_data_stream();
+
+    let post_json = |uri: &str, payload: Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+    app.clone().oneshot(post_json("/admin/models/load", json!({"model": "extra", "kind": "llm"}))).await.unwrap();
+    let chat = json!({"model": "extra", "messages": [{"role": "user", "content": "hi"}]});
+    app.clone().oneshot(post_json("/v1/chat/completions", chat)).await.unwrap();
+
+    let mut events: Vec<Value> = Vec::new();
+    while events.len() < 3 {
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(2), body.next()).await.unwrap().unwrap().unwrap();
+        let text = String::from_utf8(frame.to_vec()).unwrap();
+        events.extend(text.lines().filter_map(|l| l.strip_prefix("data: ")).map(|d| serde_json::from_str::<Value>(d).unwrap()));
+    }
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(types, vec!["model_loaded", "request_started", "request_finished"]);
+    assert_eq!(events[2]["outcome"], "ok");
+    assert_eq!(events[1]["request_id"], events[2]["request_id"]);
+    assert!(events[0]["seq"].as_u64().unwrap() < events[2]["seq"].as_u64().unwrap());
+}
@@ -0,0 +1,92 @@
+use axum::{routing::post, Router};
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{
+    api::routes::{admin_fallbacks_create, admin_fallbacks_list, admin_fallbacks_remove, admin_models_load, chat_completions},
+    engine::{fallback::FallbackRouter, CoreEngine},
+};
+
+mod common;
+use common::send;
+
+fn app() -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/fallbacks", post(admin_fallbacks_create).get(admin_fallbacks_list))
+        .route("/admin/fallbacks/remove", post(admin_fallbacks_remove))
+        .with_state(Arc::new(CoreEngine::new()))
+}
+
+// A remote backend whose upstream refuses connections
+async fn load_unreachable(app: &Router, name: &str) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    let load = json!({"model": name, "kind": "llm", "path": format!("http://127.0.0.1:{}", port)});
+    let (status, _) = send(app, "POST", "/admin/models/load", Some(load)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn failed_backends_hand_requests_to_the_next() {
+    let app = app();
+    load_unreachable(&app, "local").await;
+    let chain = json!({"model": "gateway", "backends": [{"model": "local"}, {"model": "dummy-model"}]});
+    let (status, v) = send(&app, "POST", "/admin/fallbacks", Some(chain)).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+
+    let chat = json!({"model": "gateway", "messages": [{"role": "user", "content": "hello"}]});
+    let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["choices"][0]["message"]["content"], "Echo: hello");
+
+    let (_, v) = send(&app, "GET", "/admin/fallbacks", None).await;
+    let stats = &v["data"][0]["stats"];
+    assert_eq!(stats[0]["model"], "local");
+    assert_eq!(stats[0]["errors"], 1);
+    assert_eq!(stats[1]["served"], 1);
+    assert_eq!(stats[1]["inflight"], 0);
+
+    let (status, _) = send(&app, "POST", "/admin/fallbacks/remove", Some(json!({"model": "gateway"}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let chat = json!({"model": "gateway", "messages": [{"role": "user", "content": "anyone there?"}]});
+    let (status, _) = send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    assert_ne!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn chains_are_checked_when_created() {
+    let app = app();
+    let create = |chain: Value| send(&app, "POST", "/admin/fallbacks", Some(chain));
+    let (status, v) = create(json!({"model": "gateway", "backends": [{"model": "missing"}]})).await;
+    assert_eq!((status, v["error"]["code"].as_str()), (StatusCode::NOT_FOUND, Some("model_not_found")), "{}", v);
+    let (status, _) = create(json!({"model": "gateway", "backends": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create(json!({"model": "dummy-model", "backends": [{"model": "dummy-model"}]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create(json!({"model": "gateway", "backends": [{"model": "dummy-model", "max_inflight": 0}]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = create(json!({"model": "gateway", "backends": [{"model": "dummy-model", "max_inflight": 2}]})).await;
+    assert_eq!(status, StatusCode::OK);
+    // Chains do not nest
+    let (status, v) = create(json!({"model": "outer", "backends": [{"model": "gateway"}]})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(v.to_string().contains("itself a fallback chain"), "{}", v);
+}
+
+#[test]
+fn env_chains_parse_with_optional_limits() {
+    let chains = FallbackRouter::parse_env("chat=local:4, remote; other=a").unwrap();
+    assert_eq!(chains.len(), 2);
+    assert_eq!(chains[0].model, "chat");
+    assert_eq!(chains[0].backends[0].model, "local");
+    assert_eq!(chains[0].backends[0].max_inflight, Some(4));
+    assert_eq!(chains[0].backends[1].model, "remote");
+    assert_eq!(chains[0].backends[1].max_inflight, None);
+    assert!(FallbackRouter::parse_env("chat").is_err());
+    assert!(FallbackRouter::parse_env("chat=local:many").is_err());
+}
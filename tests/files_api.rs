@@ -0,0 +1,133 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::{get, post}, Router};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{files_content, files_delete, files_get, files_list, files_upload},
+    engine::CoreEngine,
+};
+
+const BOUNDARY: &str = "test-boundary";
+
+fn app() -> Router {
+    // No other test in this binary uses the process-wide storage, so it can be pointed at memory
+    unsafe { std::env::set_var("STORAGE_BACKEND", "memory") };
+    Router::new()
+        .route("/v1/files", post(files_upload).get(files_list))
+        .route("/v1/files/:id", get(files_get).delete(files_delete))
+        .route("/v1/files/:id/content", get(files_content))
+        .with_state(Arc::new(CoreEngine::new()))
+}
+
+fn upload(purpose: &str, filename: &str, content: &str) -> Vec<u8> {
+    let mut body = format!("--{}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\n{}\r\n", BOUNDARY, purpose).into_bytes();
+    body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n", BOUNDARY, filename).bytes());
+    body.extend(content.bytes());
+    body.extend(format!("\r\n--{}--\r\n", BOUNDARY).bytes());
+    body
+}
+
+async fn call(app: &Router, method: &str, uri: &str, key: &str, body: Option<Vec<u8>>) -> (StatusCode, String) {
+    let mut request = Request::builder().method(method).uri(uri).header("authorization", format!("Bearer {}", key));
+    if body.is_some() {
+        request = request.header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY));
+    }
+    let response = app.clone().oneshot(request.body(Body::from(body.unwrap_or_default())).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn files_can_be_uploaded_listed_read_and_deleted() {
+    let app = app();
+    let (status, body) = call(&app, "POST", "/v1/files", "alice", Some(upload("user_data", "notes.txt", "hello files"))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let file: Value = serde_json::from_str(&body).unwrap();
+    let id = file["id"].as_str().unwrap();
+    assert!(id.starts_with("file-"));
+    assert_eq!(file["object"], "file");
+    assert_eq!(file["bytes"], 11);
+    assert_eq!(file["filename"], "notes.txt");
+    assert_eq!(file["purpose"], "user_data");
+
+    let (status, body) = call(&app, "GET", &format!("/v1/files/{}", id), "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), file);
+    let (status, body) = call(&app, "GET", &format!("/v1/files/{}/content", id), "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "hello files");
+
+    let (_, body) = call(&app, "GET", "/v1/files?purpose=user_data", "alice", None).await;
+    let list: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list["object"], "list");
+    assert!(list["data"].as_array().unwrap().iter().any(|f| f["id"] == id));
+    let (_, body) = call(&app, "GET", "/v1/files?purpose=batch", "alice", None).await;
+    assert!(!serde_json::from_str::<Value>(&body).unwrap()["data"].as_array().unwrap().iter().any(|f| f["id"] == id));
+
+    let (status, body) = call(&app, "DELETE", &format!("/v1/files/{}", id), "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["deleted"], true);
+    let (status, _) = call(&app, "GET", &format!("/v1/files/{}", id), "alice", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn files_are_private_to_their_uploader() {
+    let app = app();
+    let (_, body) = call(&app, "POST", "/v1/files", "alice", Some(upload("batch", "in.jsonl", "{}"))).await;
+    let id = serde_json::from_str::<Value>(&body).unwrap()["id"].as_str().unwrap().to_string();
+
+    for (method, uri) in [("GET", format!("/v1/files/{}", id)), ("GET", format!("/v1/files/{}/content", id)), ("DELETE", format!("/v1/files/{}", id))] {
+        let (status, _) = call(&app, method, &uri, "mallory", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, uri);
+    }
+    let (_, body) = call(&app, "GET", "/v1/files", "mallory", None).await;
+    assert!(!serde_json::from_str::<Value>(&body).unwrap()["data"].as_array().unwrap().iter().any(|f| f["id"] == id));
+    let (status, _) = call(&app, "GET", &format!("/v1/files/{}", id), "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn files_reject_unknown_purposes_and_ids() {
+    let app = app();
+    let (status, body) = call(&app, "POST", "/v1/files", "alice", Some(upload("secrets", "x.txt", "x"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("purpose must be one of"), "{}", body);
+
+    let (status, _) = call(&app, "GET", "/v1/files/..%2Fbatches", "alice", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn retried_uploads_with_an_idempotency_key_store_one_file() {
+    let app = app();
+    let key = uuid::Uuid::new_v4().to_string();
+    let upload_with_key = |owner: &str, content: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/files")
+            .header("authorization", format!("Bearer {}", owner))
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .header("idempotency-key", &key)
+            .body(Body::from(upload("user_data", "retry.txt", content)))
+            .unwrap()
+    };
+
+    let first = app.clone().oneshot(upload_with_key("carol", "once")).await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    let first: Value = serde_json::from_slice(&axum::body::to_bytes(first.into_body(), usize::MAX).await.unwrap()).unwrap();
+    let retry = app.clone().oneshot(upload_with_key("carol", "once")).await.unwrap();
+    assert!(retry.headers().contains_key("idempotent-replayed"));
+    let retry: Value = serde_json::from_slice(&axum::body::to_bytes(retry.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(retry, first);
+    let (_, body) = call(&app, "GET", "/v1/files", "carol", None).await;
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["data"].as_array().unwrap().len(), 1);
+
+    // A different file under the same key is refused
+    let conflict = app.clone().oneshot(upload_with_key("carol", "twice")).await.unwrap();
+    assert_eq!(conflict.status(), StatusCode::BAD_REQUEST);
+}
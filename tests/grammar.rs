@@ -0,0 +1,25 @@
+use llm_serving::runtime::grammar::validate;
+
+#[test]
+fn accepts_well_formed_grammars() {
+    let json = r#"
+        # A JSON object of string fields
+        root   ::= "{" ws (pair ("," ws pair)*)? "}"
+        pair   ::= string ":" ws string ws
+        string ::= "\"" ([^"\\] | "\\" ["\\/bfnrt])* "\""
+        ws     ::= [ \t\n]{0,4}
+    "#;
+    assert_eq!(validate(json), Ok(()));
+    assert_eq!(validate(r#"root ::= "yes" | "no""#), Ok(()));
+}
+
+#[test]
+fn rejects_broken_grammars() {
+    assert_eq!(validate(r#"answer ::= "yes""#), Err("grammar has no root rule".to_string()));
+    assert_eq!(validate(r#"root ::= "a" tail"#), Err("grammar references undefined rule tail".to_string()));
+    assert_eq!(validate(r#"root ::= "unclosed"#), Err("grammar has an unterminated string".to_string()));
+    assert_eq!(validate(r#"root ::= [a-z"#), Err("grammar has an unterminated character class".to_string()));
+    assert_eq!(validate(r#"root ::= ("a" | "b""#), Err("grammar has unbalanced parentheses".to_string()));
+    // Rule names inside literals and comments are not references
+    assert_eq!(validate("root ::= \"tail\" # tail\n"), Ok(()));
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "grpc")]
+
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tonic::{Code, Request};
+
+use llm_serving::{
+    api::dto::CreateApiKeyRequest,
+    engine::CoreEngine,
+    storage::MemoryStorage,
+    grpc::{
+        pb::{inference_client::InferenceClient, models_client::ModelsClient, ChatMessage, ChatRequest, EmbedRequest, ListModelsRequest, LoadModelRequest},
+        serve, serve_inference_with_shutdown,
+    },
+};
+
+async fn start() -> String {
+    start_with(Arc::new(CoreEngine::new())).await
+}
+
+async fn start_with(engine: Arc<CoreEngine>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(engine, listener));
+    format!("http://{}", addr)
+}
+
+fn with_key<T>(message: T, key: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert("authorization", format!("Bearer {}", key).parse().unwrap());
+    request
+}
+
+fn chat(content: &str) -> ChatRequest {
+    ChatRequest {
+        model: "dummy-model".to_string(),
+        messages: vec![ChatMessage { role: "user".to_string(), content: content.to_string(), image_urls: Vec::new() }],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn chat_and_stream_share_the_engine() {
+    let mut client = InferenceClient::connect(start().await).await.unwrap();
+
+    let response = client.chat(chat("hi")).await.unwrap().into_inner();
+    assert_eq!(response.content, "Echo: hi");
+    assert_eq!(response.finish_reason, "stop");
+    assert!(response.usage.unwrap().completion_tokens > 0);
+
+    let mut stream = client.chat_stream(chat("stream please")).await.unwrap().into_inner();
+    let mut text = String::new();
+    let mut last = None;
+    while let Some(chunk) = stream.message().await.unwrap() {
+        text.push_str(&chunk.delta);
+        last = Some(chunk);
+    }
+    assert_eq!(text, "Echo: stream please");
+    // The final chunk carries usage
+    assert!(last.unwrap().usage.unwrap().total_tokens > 0);
+
+    let status = client.chat(ChatRequest { model: "missing".to_string(), ..chat("hi") }).await.unwrap_err();
+    assert!(status.message().contains("not found"), "{}", status.message());
+}
+
+#[tokio::test]
+async fn invalid_requests_are_invalid_arguments() {
+    let mut client = InferenceClient::connect(start().await).await.unwrap();
+    let cases = [
+        (ChatRequest { messages: Vec::new(), ..chat("hi") }, "messages"),
+        (ChatRequest { temperature: Some(3.0), ..chat("hi") }, "temperature"),
+        (ChatRequest { max_tokens: Some(0), ..chat("hi") }, "max_tokens"),
+    ];
+    for (request, param) in cases {
+        let status = client.chat(request.clone()).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status.message());
+        assert!(status.message().starts_with(param), "{}", status.message());
+        let status = client.chat_stream(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status.message());
+    }
+
+    let embed = |inputs: Vec<&str>, dimensions| EmbedRequest { model: "dummy-embedding".to_string(), inputs: inputs.into_iter().map(String::from).collect(), dimensions, priority: None };
+    for (request, param) in [(embed(Vec::new(), None), "input"), (embed(vec!["a", ""], None), "input"), (embed(vec!["a"], Some(0)), "dimensions")] {
+        let status = client.embed(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status.message());
+        assert!(status.message().starts_with(param), "{}", status.message());
+    }
+}
+
+#[tokio::test]
+async fn embeddings_and_model_management() {
+    let addr = start().await;
+    let mut inference = InferenceClient::connect(addr.clone()).await.unwrap();
+    let mut models = ModelsClient::connect(addr).await.unwrap();
+
+    let request = EmbedRequest { model: "dummy-embedding".to_string(), inputs: vec!["a".to_string(), "b".to_string()], dimensions: Some(8), priority: None };
+    let response = inference.embed(request).await.unwrap().into_inner();
+    assert_eq!(response.data.len(), 2);
+    assert_eq!(response.data[0].values.len(), 8);
+
+    let load = LoadModelRequest { model: "grpc-embed".to_string(), kind: "embedding".to_string(), ..Default::default() };
+    models.load(load).await.unwrap();
+    let listed = models.list(ListModelsRequest {}).await.unwrap().into_inner().models;
+    assert!(listed.iter().any(|m| m.name == "grpc-embed" && m.kind == "embedding"));
+}
+
+#[tokio::test]
+async fn model_management_needs_the_admin_scope() {
+    // Stored keys turn auth on for this engine only
+    let engine = Arc::new(CoreEngine::with_storage(Arc::new(MemoryStorage::default())));
+    let key = |scope: &str| engine.keys().create("test", CreateApiKeyRequest { name: scope.to_string(), scopes: Some(vec![scope.to_string()]) });
+    let (app, ops) = (key("inference").await.unwrap().key, key("admin").await.unwrap().key);
+    let addr = start_with(engine.clone()).await;
+    let mut inference = InferenceClient::connect(addr.clone()).await.unwrap();
+    let mut models = ModelsClient::connect(addr).await.unwrap();
+
+    assert_eq!(inference.chat(with_key(chat("hi"), &app)).await.unwrap().into_inner().content, "Echo: hi");
+    let status = models.list(with_key(ListModelsRequest {}, &app)).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status.message());
+    let load = LoadModelRequest { model: "grpc-denied".to_string(), kind: "embedding".to_string(), ..Default::default() };
+    assert_eq!(models.load(with_key(load, &app)).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(models.list(ListModelsRequest {}).await.unwrap_err().code(), Code::Unauthenticated);
+
+    // Admin keys manage models but have no inference scope
+    assert!(models.list(with_key(ListModelsRequest {}, &ops)).await.is_ok());
+    assert_eq!(inference.chat(with_key(chat("hi"), &ops)).await.unwrap_err().code(), Code::PermissionDenied);
+}
+
+#[tokio::test]
+async fn inference_only_servers_leave_model_management_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve_inference_with_shutdown(Arc::new(CoreEngine::new()), listener, std::future::pending()));
+    let mut inference = InferenceClient::connect(addr.clone()).await.unwrap();
+    let mut models = ModelsClient::connect(addr).await.unwrap();
+
+    assert_eq!(inference.chat(chat("hi")).await.unwrap().into_inner().content, "Echo: hi");
+    assert_eq!(models.list(ListModelsRequest {}).await.unwrap_err().code(), Code::Unimplemented);
+}
@@ -0,0 +1,125 @@
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{HeaderMap, Request, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{admin_models_list, admin_models_load},
+    engine::{
+        hub::{fetch, HubConfig},
+        CoreEngine,
+    },
+};
+
+// Hub stand-in holding one private repository, `acme/tiny-GGUF`
+async fn serve(downloads: Arc<AtomicUsize>) -> String {
+    let authorized = |headers: &HeaderMap| headers.get("authorization").and_then(|v| v.to_str().ok()) == Some("Bearer hf-test");
+    let app = Router::new()
+        .route(
+            "/:owner/:name/resolve/:revision/*file",
+            get(move |headers: HeaderMap, Path((owner, name, revision, file)): Path<(String, String, String, String)>| async move {
+                if !authorized(&headers) {
+                    return StatusCode::UNAUTHORIZED.into_response();
+                }
+                if (owner.as_str(), name.as_str()) != ("acme", "tiny-GGUF") {
+                    return StatusCode::NOT_FOUND.into_response();
+                }
+                downloads.fetch_add(1, Ordering::SeqCst);
+                format!("{}@{}", file, revision).into_response()
+            }),
+        )
+        .route(
+            "/api/models/:owner/:name/revision/:revision",
+            get(|| async { Json(json!({ "siblings": [{ "rfilename": "config.json" }, { "rfilename": "onnx/model.onnx" }] })) }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+fn config(endpoint: &str, token: Option<&str>) -> HubConfig {
+    HubConfig {
+        endpoint: endpoint.to_string(),
+        cache_dir: std::env::temp_dir().join(format!("hub-cache-{}", uuid::Uuid::new_v4().simple())),
+        token: token.map(str::to_string),
+    }
+}
+
+#[tokio::test]
+async fn files_are_downloaded_once_into_the_cache() {
+    let downloads = Arc::new(AtomicUsize::new(0));
+    let config = config(&serve(downloads.clone()).await, Some("hf-test"));
+
+    let path = fetch(&config, "acme/tiny-GGUF", Some("tiny.Q4_K_M.gguf"), None).await.unwrap();
+    assert_eq!(path, config.cache_dir.join("acme--tiny-GGUF/main/tiny.Q4_K_M.gguf"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "tiny.Q4_K_M.gguf@main");
+    fetch(&config, "acme/tiny-GGUF", Some("tiny.Q4_K_M.gguf"), None).await.unwrap();
+    assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+    // Without a filename the whole repository comes down as a directory
+    let dir = fetch(&config, "acme/tiny-GGUF", None, Some("v1")).await.unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("onnx/model.onnx")).unwrap(), "onnx/model.onnx@v1");
+    assert!(dir.join("config.json").exists());
+    let _ = std::fs::remove_dir_all(&config.cache_dir);
+}
+
+#[tokio::test]
+async fn failed_downloads_leave_nothing_behind() {
+    let config = config(&serve(Arc::new(AtomicUsize::new(0))).await, None);
+    let err = fetch(&config, "acme/tiny-GGUF", Some("tiny.gguf"), None).await.unwrap_err();
+    assert!(err.contains("401") && err.contains("HF_TOKEN"), "{}", err);
+    assert!(!config.cache_dir.join("acme--tiny-GGUF").exists());
+
+    assert!(fetch(&config, "acme/tiny-GGUF", Some("../escape.gguf"), None).await.is_err());
+    assert!(fetch(&config, "not-a-repo", Some("tiny.gguf"), None).await.is_err());
+}
+
+#[tokio::test]
+async fn load_requests_can_name_a_hub_repository() {
+    let endpoint = serve(Arc::new(AtomicUsize::new(0))).await;
+    let cache_dir = std::env::temp_dir().join(format!("hub-cache-{}", uuid::Uuid::new_v4().simple()));
+    // The only test here that reads the hub settings from the environment
+    unsafe {
+        std::env::set_var("HF_ENDPOINT", &endpoint);
+        std::env::set_var("HF_TOKEN", "hf-test");
+        std::env::set_var("MODEL_CACHE_DIR", &cache_dir);
+    }
+    let app = Router::new()
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/load", post(admin_models_load))
+        .with_state(Arc::new(CoreEngine::new()));
+    let load = |payload: Value| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/admin/models/load")
+                .header("content-type", "application/json")
+                .body(Body::from(payload.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+
+    let status = load(json!({"model": "tiny", "kind": "llm", "hf_repo": "acme/tiny-GGUF", "filename": "tiny.Q8_0.gguf"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(cache_dir.join("acme--tiny-GGUF/main/tiny.Q8_0.gguf").exists());
+
+    let missing = json!({"model": "other", "kind": "llm", "hf_repo": "acme/missing", "filename": "x.gguf"});
+    assert_eq!(load(missing).await, StatusCode::BAD_REQUEST);
+    let both = json!({"model": "other", "kind": "llm", "path": "/models/x.gguf", "hf_repo": "acme/tiny-GGUF"});
+    assert_eq!(load(both).await, StatusCode::BAD_REQUEST);
+    assert_eq!(load(json!({"model": "other", "kind": "llm", "filename": "x.gguf"})).await, StatusCode::BAD_REQUEST);
+    let _ = std::fs::remove_dir_all(&cache_dir);
+}
@@ -0,0 +1,63 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{api::routes::chat_completions, config::{clear_overrides, set_overrides}, engine::CoreEngine};
+
+async fn send(app: &Router, token: Option<&str>, key: &str) -> (StatusCode, bool) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .header("idempotency-key", key);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hello"}], "max_tokens": 8});
+    let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    (response.status(), response.headers().contains_key("idempotent-replayed"))
+}
+
+// The store is process-wide and reads its settings once, so this binary holds a single test
+#[tokio::test]
+async fn stored_results_are_bounded_and_persisted() {
+    let dir = std::env::temp_dir().join(format!("idempotency-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("store.jsonl");
+    set_overrides(vec![
+        ("IDEMPOTENCY_STORE_PATH".to_string(), path.display().to_string()),
+        ("IDEMPOTENCY_MAX_ENTRIES".to_string(), "2".to_string()),
+    ]);
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(Arc::new(CoreEngine::new()));
+
+    for key in ["first", "second", "third"] {
+        assert_eq!(send(&app, Some("tenant"), key).await, (StatusCode::OK, false));
+    }
+    // Only the two newest results are kept
+    assert_eq!(send(&app, Some("tenant"), "third").await, (StatusCode::OK, true));
+    assert_eq!(send(&app, Some("tenant"), "first").await, (StatusCode::OK, false));
+
+    // Anonymous callers cannot be told apart, so their keys are refused
+    assert_eq!(send(&app, None, "anonymous").await.0, StatusCode::BAD_REQUEST);
+
+    // Stored results reach the file, which is rewritten with just the live ones as it grows
+    let mut keys = Vec::new();
+    for _ in 0..100 {
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        keys = text.lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["key"].as_str().unwrap().to_string()).collect();
+        if keys.last().is_some_and(|key| key.ends_with(":first")) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(keys.last().unwrap().ends_with(":first"), "{:?}", keys);
+    assert!(keys.len() <= 4, "{:?}", keys);
+    assert!(keys.iter().all(|key| key.starts_with("chat_completions:key-")), "{:?}", keys);
+    clear_overrides();
+}
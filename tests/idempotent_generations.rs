@@ -0,0 +1,60 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{chat_completions, embeddings},
+    engine::CoreEngine,
+};
+
+async fn send(app: &Router, uri: &str, token: &str, key: &str, body: &Value) -> (StatusCode, bool, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token))
+        .header("idempotency-key", key)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let replayed = response.headers().contains_key("idempotent-replayed");
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, replayed, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn retries_with_the_same_key_replay_the_first_response() {
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(Arc::new(CoreEngine::new()));
+    let key = uuid::Uuid::new_v4().to_string();
+    let chat = |content: &str| json!({"model": "dummy-model", "messages": [{"role": "user", "content": content}], "max_tokens": 8});
+
+    let (status, replayed, first) = send(&app, "/v1/chat/completions", "tenant-a", &key, &chat("hello")).await;
+    assert_eq!((status, replayed), (StatusCode::OK, false));
+    let (status, replayed, retry) = send(&app, "/v1/chat/completions", "tenant-a", &key, &chat("hello")).await;
+    assert_eq!((status, replayed), (StatusCode::OK, true));
+    assert_eq!(retry, first);
+
+    // Reusing the key for a different request is a client bug
+    let (status, _, v) = send(&app, "/v1/chat/completions", "tenant-a", &key, &chat("goodbye")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(v["error"]["message"].as_str().unwrap().contains("different request body"));
+
+    // Keys are scoped to the caller's API key
+    let (status, replayed, _) = send(&app, "/v1/chat/completions", "tenant-b", &key, &chat("hello")).await;
+    assert_eq!((status, replayed), (StatusCode::OK, false));
+
+    let input = json!({"model": "dummy-embedding", "input": "hello"});
+    assert!(!send(&app, "/v1/embeddings", "tenant-a", &key, &input).await.1);
+    let (status, replayed, _) = send(&app, "/v1/embeddings", "tenant-a", &key, &input).await;
+    assert_eq!((status, replayed), (StatusCode::OK, true));
+}
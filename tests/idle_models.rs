@@ -0,0 +1,163 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{admin_models_list, admin_models_load, admin_models_unload, chat_completions},
+    api::dto::ModelOptions,
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+async fn entry(app: &Router, name: &str) -> Option<Value> {
+    let (_, models) = send(app, "GET", "/admin/models", None).await;
+    models["models"].as_array().unwrap().iter().find(|m| m["name"] == name).cloned()
+}
+
+// Distinct prompts, so no answer comes from the response cache
+fn chat(model: &str) -> Value {
+    let content = uuid::Uuid::new_v4().to_string();
+    json!({"model": model, "messages": [{"role": "user", "content": content}], "max_tokens": 2})
+}
+
+#[tokio::test]
+async fn idle_models_are_unloaded_and_loaded_again_on_demand() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/models/unload", post(admin_models_unload))
+        .with_state(engine.clone());
+
+    let load = json!({"model": "sleepy", "kind": "llm", "idle_ttl_secs": 1});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    let load = json!({"model": "pinned", "kind": "llm", "idle_ttl_secs": 0});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "POST", "/v1/chat/completions", Some(chat("sleepy"))).await.0, StatusCode::OK);
+    assert!(entry(&app, "sleepy").await.unwrap()["last_used_at"].is_u64());
+    assert!(entry(&app, "pinned").await.unwrap().get("last_used_at").is_none());
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    engine.unload_idle_models().await;
+    let sleepy = entry(&app, "sleepy").await.unwrap();
+    assert_eq!(sleepy["status"], "idle");
+    // Listed with what it serves once loaded again
+    assert_eq!(sleepy["capabilities"], json!(["chat"]));
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert!(models["llm"].as_array().unwrap().iter().any(|m| m == "sleepy"));
+    assert_eq!(entry(&app, "pinned").await.unwrap()["status"], "ready");
+    assert_eq!(entry(&app, "dummy-model").await.unwrap()["status"], "ready");
+
+    // Concurrent requests load it once and are all answered
+    let requests = (0..3).map(|_| send(&app, "POST", "/v1/chat/completions", Some(chat("sleepy"))));
+    for (status, v) in futures::future::join_all(requests).await {
+        assert_eq!(status, StatusCode::OK, "{}", v);
+    }
+    assert_eq!(entry(&app, "sleepy").await.unwrap()["status"], "ready");
+
+    // An idle model can still be unloaded for good
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    engine.unload_idle_models().await;
+    assert_eq!(entry(&app, "sleepy").await.unwrap()["status"], "idle");
+    let unload = json!({"model": "sleepy", "kind": "llm"});
+    assert_eq!(send(&app, "POST", "/admin/models/unload", Some(unload)).await.0, StatusCode::OK);
+    assert!(entry(&app, "sleepy").await.is_none());
+    assert_eq!(send(&app, "POST", "/v1/chat/completions", Some(chat("sleepy"))).await.0, StatusCode::NOT_FOUND);
+}
+
+// OpenAI-compatible upstream answering each request once `release` is notified
+async fn held_upstream(received: Arc<AtomicUsize>, release: Arc<Notify>) -> String {
+    let app = Router::new().route(
+        "/v1/chat/completions",
+        post(move || async move {
+            received.fetch_add(1, Ordering::SeqCst);
+            release.notified().await;
+            axum::Json(json!({ "choices": [{ "message": { "role": "assistant", "content": "hi" } }] }))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+#[tokio::test]
+async fn models_serving_requests_are_not_unloaded_as_idle() {
+    let (received, release) = (Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+    let engine = Arc::new(CoreEngine::new());
+    let options = ModelOptions { idle_ttl_secs: Some(1), ..Default::default() };
+    engine.load_model("llm", "slow", Some(&held_upstream(received.clone(), release.clone()).await), &options).await.unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .with_state(engine.clone());
+
+    let request = tokio::spawn({
+        let app = app.clone();
+        async move { send(&app, "POST", "/v1/chat/completions", Some(chat("slow"))).await }
+    });
+    while received.load(Ordering::SeqCst) == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    // Past its TTL since the request started, but still answering it
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(engine.unload_idle_models().await.is_empty());
+    assert_eq!(entry(&app, "slow").await.unwrap()["status"], "ready");
+
+    release.notify_one();
+    assert_eq!(request.await.unwrap().0, StatusCode::OK);
+    // The TTL starts over from the end of the request
+    assert!(engine.unload_idle_models().await.is_empty());
+    assert_eq!(entry(&app, "slow").await.unwrap()["status"], "ready");
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    engine.unload_idle_models().await;
+    assert_eq!(entry(&app, "slow").await.unwrap()["status"], "idle");
+}
+
+#[tokio::test]
+async fn a_failed_reload_reaches_a_streaming_client() {
+    let dir = std::env::temp_dir().join(format!("idle-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let weights = dir.join("model.gguf");
+    std::fs::write(&weights, vec![0u8; 2 << 20]).unwrap();
+    let engine = Arc::new(CoreEngine::new());
+    let options = ModelOptions { idle_ttl_secs: Some(1), ..Default::default() };
+    engine.load_model("llm", "heavy", weights.to_str(), &options).await.unwrap();
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine.clone());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    // Unloaded now unless the periodic sweep got to it first
+    engine.unload_idle_models().await;
+
+    // The budget shrank below the model meanwhile, so loading it again fails
+    llm_serving::config::set_overrides(vec![("MODEL_RAM_BUDGET_MB".to_string(), "1".to_string())]);
+    let mut body = chat("heavy");
+    body["stream"] = json!(true);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    llm_serving::config::clear_overrides();
+    let text = String::from_utf8(bytes.to_vec()).unwrap();
+    assert!(text.contains("Failed to load idle model heavy"), "{}", text);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,33 @@
+use llm_serving::runtime::image::{decode_image, encode_png};
+
+#[test]
+fn png_round_trips_through_the_encoder() {
+    let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 7) as u8).collect();
+    let image = decode_image(&encode_png(&rgb, 7, 5).unwrap()).unwrap();
+    assert_eq!((image.width, image.height), (7, 5));
+    assert_eq!(image.pixels, rgb);
+}
+
+#[test]
+fn baseline_jpeg_decodes_close_to_its_source() {
+    // 40x24 gradient, 4:2:0, quality 90, restart interval of 2 MCUs (tests/fixtures/gradient.jpg)
+    let image = decode_image(include_bytes!("fixtures/gradient.jpg")).unwrap();
+    assert_eq!((image.width, image.height), (40, 24));
+    for y in 0..24usize {
+        for x in 0..40usize {
+            let expected = [255 * x / 39, 255 * y / 23, 128];
+            let actual = &image.pixels[(y * 40 + x) * 3..][..3];
+            for c in 0..3 {
+                let error = (actual[c] as i32 - expected[c] as i32).abs();
+                assert!(error <= 12, "pixel {},{} channel {}: {} vs {}", x, y, c, actual[c], expected[c]);
+            }
+        }
+    }
+}
+
+#[test]
+fn unknown_and_truncated_images_are_rejected() {
+    assert!(decode_image(b"GIF89a").unwrap_err().contains("expected PNG or JPEG"));
+    let jpeg = include_bytes!("fixtures/gradient.jpg");
+    assert!(decode_image(&jpeg[..200]).is_err());
+}
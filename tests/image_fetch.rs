@@ -0,0 +1,79 @@
+use axum::{routing::get, Router};
+use std::time::Duration;
+
+use llm_serving::{
+    outbound::{check_public_url, is_public_ip},
+    runtime::image_fetch::{ImageFetchConfig, ImageFetcher},
+};
+
+async fn serve() -> u16 {
+    let app = Router::new()
+        .route("/cat.png", get(|| async { "PNGBYTES" }))
+        .route("/big.png", get(|| async { vec![0u8; 4096] }))
+        .route("/slow.png", get(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "late"
+        }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    port
+}
+
+fn config(allow_private: bool) -> ImageFetchConfig {
+    ImageFetchConfig { max_bytes: 1024, timeout: Duration::from_millis(300), allow_private }
+}
+
+#[test]
+fn only_public_addresses_count_as_public() {
+    for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fe80::1", "fd00::1", "::ffff:127.0.0.1", "64:ff9b::a00:1"] {
+        assert!(!is_public_ip(ip.parse().unwrap()), "{} should not be public", ip);
+    }
+    for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+        assert!(is_public_ip(ip.parse().unwrap()), "{} should be public", ip);
+    }
+
+    let check = |url: &str| check_public_url(&reqwest::Url::parse(url).unwrap());
+    assert!(check("https://example.com/a.png").is_ok());
+    assert!(check("http://127.0.0.1/a.png").is_err());
+    assert!(check("http://[::1]:8080/a.png").is_err());
+    assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+    assert!(check("file:///etc/passwd").is_err());
+}
+
+#[tokio::test]
+async fn private_hosts_are_blocked_by_default() {
+    let port = serve().await;
+    let fetcher = ImageFetcher::new(config(false));
+    let err = fetcher.fetch(&format!("http://127.0.0.1:{}/cat.png", port)).await.unwrap_err();
+    assert!(err.contains("not a public address"), "{}", err);
+    // A name that resolves to loopback is refused at resolution time
+    let err = fetcher.fetch(&format!("http://localhost:{}/cat.png", port)).await.unwrap_err();
+    assert!(err.contains("does not resolve to a public address"), "{}", err);
+    assert!(fetcher.fetch("ftp://example.com/cat.png").await.is_err());
+}
+
+#[tokio::test]
+async fn fetches_are_capped_in_size_and_time() {
+    let port = serve().await;
+    let fetcher = ImageFetcher::new(config(true));
+    let urls = vec![format!("http://127.0.0.1:{}/cat.png", port), "data:image/png;base64,aGk=".to_string()];
+    assert_eq!(fetcher.fetch_all(&urls).await.unwrap(), vec![b"PNGBYTES".to_vec(), b"hi".to_vec()]);
+
+    let err = fetcher.fetch(&format!("http://127.0.0.1:{}/big.png", port)).await.unwrap_err();
+    assert!(err.contains("exceeds 1024 bytes"), "{}", err);
+    let err = fetcher.fetch(&format!("http://127.0.0.1:{}/slow.png", port)).await.unwrap_err();
+    assert!(err.contains("timed out"), "{}", err);
+    let err = fetcher.fetch_all(&[urls[1].clone(), format!("http://127.0.0.1:{}/missing.png", port)]).await.unwrap_err();
+    assert!(err.starts_with("image 1:") && err.contains("HTTP 404"), "{}", err);
+}
+
+#[tokio::test]
+async fn data_uris_must_be_base64_images() {
+    let fetcher = ImageFetcher::new(config(false));
+    assert_eq!(fetcher.fetch("data:image/jpeg;base64,aGk=").await.unwrap(), b"hi");
+    assert!(fetcher.fetch("data:text/html;base64,aGk=").await.unwrap_err().contains("expected an image"));
+    assert!(fetcher.fetch("data:image/png,rawbytes").await.unwrap_err().contains("base64"));
+    let big = format!("data:image/png;base64,{}", "A".repeat(4000));
+    assert!(fetcher.fetch(&big).await.unwrap_err().contains("exceeds"));
+}
@@ -0,0 +1,76 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::{get, post}, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::{artifacts::{ArtifactConfig, ArtifactStore}, routes::{images_artifact, images_generations}},
+    engine::CoreEngine,
+    storage::MemoryStorage,
+};
+
+async fn get_path(app: &Router, uri: &str) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    (status, axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec())
+}
+
+#[tokio::test]
+async fn url_response_format_serves_signed_artifacts() {
+    // No other test in this binary uses the process-wide storage, so it can be pointed at memory
+    unsafe { std::env::set_var("STORAGE_BACKEND", "memory") };
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/images/generations", post(images_generations))
+        .route("/v1/images/artifacts/:name", get(images_artifact))
+        .with_state(engine);
+
+    let payload = json!({"model": "dummy-image", "prompt": "a cat", "size": "256x256", "response_format": "url"});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/images/generations")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let v: Value = serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert!(v["data"][0]["b64_json"].is_null());
+    let url = v["data"][0]["url"].as_str().unwrap();
+    let path = url.strip_prefix("http://localhost:3000").unwrap();
+    assert!(path.contains("?expires="));
+
+    let (status, bytes) = get_path(&app, path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bytes, b"DUMMY_PNG:256x256:");
+
+    // Tampered or missing signatures look like a missing image
+    let (rest, last) = path.split_at(path.len() - 1);
+    let tampered = format!("{}{}", rest, if last == "0" { "1" } else { "0" });
+    assert_eq!(get_path(&app, &tampered).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get_path(&app, path.split('?').next().unwrap()).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn expired_links_and_static_links() {
+    let signed = ArtifactStore::new(
+        Arc::new(MemoryStorage::default()),
+        ArtifactConfig { base_url: "https://img.test".to_string(), signing_key: b"k".to_vec(), url_ttl_secs: 60 },
+    );
+    let url = signed.put_image(b"png".to_vec()).await.unwrap();
+    let name = url.strip_prefix("https://img.test/v1/images/artifacts/").unwrap().split('?').next().unwrap().to_string();
+    let signature = url.split("signature=").nth(1).unwrap();
+    assert!(signed.get_image(&name, Some(1), Some(signature)).await.unwrap().is_none());
+
+    let storage = Arc::new(MemoryStorage::default());
+    let fixed = ArtifactStore::new(
+        storage,
+        ArtifactConfig { base_url: "https://img.test".to_string(), signing_key: b"k".to_vec(), url_ttl_secs: 0 },
+    );
+    let url = fixed.put_image(b"png".to_vec()).await.unwrap();
+    assert!(!url.contains('?'));
+    let name = url.rsplit('/').next().unwrap();
+    assert_eq!(fixed.get_image(name, None, None).await.unwrap().as_deref(), Some(&b"png"[..]));
+}
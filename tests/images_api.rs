@@ -6,7 +6,7 @@ use serde_json::{json, Value};
 use std::sync::Arc;
 
 use llm_serving::{
-    api::routes::{images_generations},
+    api::routes::{images_edits, images_generations, images_variations},
     engine::CoreEngine,
 };
 
@@ -40,3 +40,171 @@ async fn images_generations_returns_b64_list() {
     assert_eq!(data.len(), 2);
     assert!(data[0]["b64_json"].as_str().is_some());
 }
+
+async fn generate(app: &Router, payload: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/images/generations")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn images_generations_accepts_sampling_params() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/images/generations", post(images_generations)).with_state(engine);
+
+    let (status, v) = generate(&app, json!({
+        "model": "dummy-image",
+        "prompt": "a lighthouse at dusk",
+        "negative_prompt": "blurry, text",
+        "n": 2,
+        "steps": 30,
+        "guidance_scale": 6.5,
+        "seed": 42
+    }))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let decoded: Vec<String> = v["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| String::from_utf8(base64::Engine::decode(&base64::engine::general_purpose::STANDARD, d["b64_json"].as_str().unwrap()).unwrap()).unwrap())
+        .collect();
+    assert!(decoded[0].contains("seed=42"));
+    assert!(decoded[1].contains("seed=43"));
+
+    let (status, v) = generate(&app, json!({"model": "dummy-image", "prompt": "x", "steps": 0})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(v.to_string().contains("steps must be between 1 and 150"));
+
+    let (status, v) = generate(&app, json!({"model": "dummy-image", "prompt": "x", "guidance_scale": 31.0})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(v.to_string().contains("guidance_scale must be between 0 and 30"));
+}
+
+#[tokio::test]
+async fn images_generations_streams_progress_then_images() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/images/generations", post(images_generations)).with_state(engine);
+
+    let payload = json!({"model": "dummy-image", "prompt": "a fox", "n": 2, "steps": 3, "stream": true, "previews": true});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/images/generations")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let events: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().filter_map(|l| l.strip_prefix("data: ")).collect();
+
+    assert_eq!(events.last(), Some(&"[DONE]"));
+    let events: Vec<Value> = events[..events.len() - 1].iter().map(|e| serde_json::from_str(e).unwrap()).collect();
+    let progress: Vec<&Value> = events.iter().filter(|e| e["type"] == "image_generation.progress").collect();
+    assert_eq!(progress.len(), 6);
+    assert_eq!(progress[0]["step"], 1);
+    assert_eq!(progress[0]["total_steps"], 3);
+    assert_eq!(progress[5]["image_index"], 1);
+    assert!(progress[0]["preview_b64_json"].as_str().is_some());
+    let completed = events.last().unwrap();
+    assert_eq!(completed["type"], "image_generation.completed");
+    assert_eq!(completed["data"].as_array().unwrap().len(), 2);
+
+    // Parameter errors are still plain 400s
+    let (status, _) = generate(&app, json!({"model": "dummy-image", "prompt": "x", "steps": 500, "stream": true})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+const BOUNDARY: &str = "test-boundary";
+
+// Signature and IHDR chunk only; enough for dimension checks
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    bytes.extend(width.to_be_bytes());
+    bytes.extend(height.to_be_bytes());
+    bytes.extend([8, 6, 0, 0, 0]);
+    bytes
+}
+
+fn multipart(fields: &[(&str, &str)], files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", BOUNDARY, name, value).bytes());
+    }
+    for (name, file) in files {
+        body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}.png\"\r\nContent-Type: image/png\r\n\r\n", BOUNDARY, name, name).bytes());
+        body.extend_from_slice(file);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{}--\r\n", BOUNDARY).bytes());
+    body
+}
+
+async fn send_upload(app: &Router, uri: &str, body: Vec<u8>) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn images_edits_accepts_image_and_mask() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/images/edits", post(images_edits)).with_state(engine);
+    let image = png(64, 64);
+
+    let fields = [("model", "dummy-image"), ("prompt", "add a hat"), ("n", "2")];
+    let (status, body) = send_upload(&app, "/v1/images/edits", multipart(&fields, &[("image", &image), ("mask", &png(64, 64))])).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    let data = v["data"].as_array().unwrap();
+    assert_eq!(data.len(), 2);
+    let decoded = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, data[0]["b64_json"].as_str().unwrap()).unwrap();
+    assert!(String::from_utf8(decoded).unwrap().contains(":edit:"));
+
+    let (status, body) = send_upload(&app, "/v1/images/edits", multipart(&fields, &[("image", &image), ("mask", &png(32, 64))])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("mask is 32x64"));
+
+    let (status, body) = send_upload(&app, "/v1/images/edits", multipart(&fields, &[("image", b"GIF89a not a png")])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("expected PNG"));
+
+    let (status, _) = send_upload(&app, "/v1/images/edits", multipart(&[("model", "dummy-image"), ("prompt", "add a hat")], &[])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn images_variations_requires_a_square_png() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/images/variations", post(images_variations)).with_state(engine);
+
+    let fields = [("model", "dummy-image"), ("n", "3"), ("size", "256x256")];
+    let (status, body) = send_upload(&app, "/v1/images/variations", multipart(&fields, &[("image", &png(128, 128))])).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["data"].as_array().unwrap().len(), 3);
+
+    let (status, body) = send_upload(&app, "/v1/images/variations", multipart(&fields, &[("image", &png(128, 64))])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("must be square"));
+
+    let fields = [("model", "dummy-image"), ("n", "11")];
+    let (status, body) = send_upload(&app, "/v1/images/variations", multipart(&fields, &[("image", &png(128, 128))])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("n must be between 1 and 10"));
+}
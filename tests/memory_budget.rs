@@ -0,0 +1,98 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::{path::Path, sync::Arc, time::Duration};
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{admin_models_list, admin_models_load, chat_completions},
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+const MB: u64 = 1024 * 1024;
+
+async fn status(app: &Router, name: &str) -> Value {
+    let (_, models) = send(app, "GET", "/admin/models", None).await;
+    models["models"].as_array().unwrap().iter().find(|m| m["name"] == name).map_or(Value::Null, |m| m["status"].clone())
+}
+
+async fn load(app: &Router, name: &str, dir: &Path, size: u64) -> (StatusCode, Value) {
+    let path = dir.join(format!("{}.gguf", name));
+    std::fs::write(&path, vec![0u8; size as usize]).unwrap();
+    let (status, v) = send(app, "POST", "/admin/models/load", Some(json!({"model": name, "kind": "llm", "path": path}))).await;
+    // Models loaded in the same millisecond would tie for least recently used
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    (status, v)
+}
+
+async fn chat(app: &Router, model: &str) -> StatusCode {
+    let content = uuid::Uuid::new_v4().to_string();
+    let body = json!({"model": model, "messages": [{"role": "user", "content": content}], "max_tokens": 2});
+    let status = send(app, "POST", "/v1/chat/completions", Some(body)).await.0;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    status
+}
+
+#[tokio::test]
+async fn loads_over_the_budget_evict_the_least_recently_used_model() {
+    // The only test in this binary, so nothing else sees the budget
+    unsafe { std::env::set_var("MODEL_RAM_BUDGET_MB", "3") };
+    let dir = std::env::temp_dir().join(format!("memory-budget-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/load", post(admin_models_load))
+        .with_state(Arc::new(CoreEngine::new()));
+
+    assert_eq!(load(&app, "first", &dir, 3 * MB / 2).await.0, StatusCode::OK);
+    assert_eq!(load(&app, "second", &dir, 3 * MB / 2).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert_eq!(models["memory"]["ram_used_bytes"], 3 * MB);
+    assert_eq!(models["memory"]["ram_budget_bytes"], 3 * MB);
+    let first = models["models"].as_array().unwrap().iter().find(|m| m["name"] == "first").unwrap();
+    assert_eq!(first["ram_bytes"], 3 * MB / 2);
+    // Built-in models take nothing
+    let dummy = models["models"].as_array().unwrap().iter().find(|m| m["name"] == "dummy-model").unwrap();
+    assert!(dummy.get("ram_bytes").is_none());
+
+    // `second` is now the least recently used and makes room
+    assert_eq!(chat(&app, "first").await, StatusCode::OK);
+    assert_eq!(load(&app, "third", &dir, 3 * MB / 2).await.0, StatusCode::OK);
+    assert_eq!(status(&app, "second").await, "idle");
+    assert_eq!(status(&app, "first").await, "ready");
+
+    // Loaded again on demand, in place of `first`
+    assert_eq!(chat(&app, "second").await, StatusCode::OK);
+    assert_eq!((status(&app, "first").await, status(&app, "second").await), (json!("idle"), json!("ready")));
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert_eq!(models["memory"]["ram_used_bytes"], 3 * MB);
+    assert_eq!(models["memory"]["ram_reserved_bytes"], 0);
+
+    // A model larger than the whole budget is refused and nothing is evicted for it
+    let (code, v) = load(&app, "huge", &dir, 4 * MB).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    assert!(v.to_string().contains("over the budget"), "{}", v);
+    assert_eq!(status(&app, "huge").await, Value::Null);
+    assert_eq!((status(&app, "second").await, status(&app, "third").await), (json!("ready"), json!("ready")));
+
+    // `third` is the least recently used, but a model serving a request is not made idle
+    let content = vec!["word"; 500].join(" ");
+    let body = json!({"model": "third", "messages": [{"role": "user", "content": content}], "max_tokens": 8192, "stream": true});
+    let request = Request::builder().method("POST").uri("/v1/chat/completions").header("content-type", "application/json");
+    // Left unread, so the generation waits on the client
+    let streaming = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    assert_eq!(streaming.status(), StatusCode::OK);
+    assert_eq!(chat(&app, "second").await, StatusCode::OK);
+    assert_eq!(load(&app, "fourth", &dir, 3 * MB / 2).await.0, StatusCode::OK);
+    assert_eq!((status(&app, "second").await, status(&app, "third").await), (json!("idle"), json!("ready")));
+    drop(streaming);
+    let _ = std::fs::remove_dir_all(&dir);
+}
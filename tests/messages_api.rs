@@ -0,0 +1,121 @@
+use axum::{routing::post, Router};
+use axum::http::{Request, StatusCode};
+use axum::body::Body;
+use tower::util::ServiceExt; // for `oneshot`
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{api::routes::messages, engine::CoreEngine};
+
+fn app() -> Router {
+    Router::new().route("/v1/messages", post(messages)).with_state(Arc::new(CoreEngine::new()))
+}
+
+async fn send(app: &Router, payload: Value) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header("content-type", "application/json")
+        .header("anthropic-version", "2023-06-01")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body_bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn messages_returns_anthropic_message() {
+    let (status, body) = send(&app(), json!({
+        "model": "dummy-model",
+        "max_tokens": 64,
+        "system": [{"type": "text", "text": "be brief"}],
+        "messages": [
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "hello"},
+            {"role": "user", "content": [{"type": "text", "text": "what now?"}]}
+        ]
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["type"], "message");
+    assert_eq!(v["role"], "assistant");
+    assert!(v["id"].as_str().unwrap().starts_with("msg_"));
+    assert_eq!(v["content"], json!([{"type": "text", "text": "Echo: what now?"}]));
+    assert_eq!(v["stop_reason"], "end_turn");
+    assert!(v["stop_sequence"].is_null());
+    assert!(v["usage"]["input_tokens"].as_u64().unwrap() > 0);
+    assert!(v["usage"]["output_tokens"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn messages_stream_emits_anthropic_events() {
+    let (status, body) = send(&app(), json!({
+        "model": "dummy-model",
+        "max_tokens": 64,
+        "stream": true,
+        "messages": [{"role": "user", "content": "stream please"}]
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = body.lines().filter_map(|l| l.strip_prefix("event: ")).collect();
+    assert_eq!(
+        names,
+        [
+            "message_start", "content_block_start", "ping",
+            "content_block_delta", "content_block_delta", "content_block_delta",
+            "content_block_stop", "message_delta", "message_stop",
+        ]
+    );
+    let data: Vec<Value> = body.lines().filter_map(|l| l.strip_prefix("data: ")).map(|d| serde_json::from_str(d).unwrap()).collect();
+    let text: String = data.iter().filter(|e| e["type"] == "content_block_delta").map(|e| e["delta"]["text"].as_str().unwrap()).collect();
+    assert_eq!(text, "Echo: stream please");
+    let delta = data.iter().find(|e| e["type"] == "message_delta").unwrap();
+    assert_eq!(delta["delta"]["stop_reason"], "end_turn");
+    assert!(delta["usage"]["output_tokens"].as_u64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn messages_report_the_stop_sequence_that_ended_generation() {
+    let request = json!({
+        "model": "dummy-model",
+        "max_tokens": 64,
+        "stop_sequences": ["world"],
+        "messages": [{"role": "user", "content": "hello world again"}]
+    });
+    let (status, body) = send(&app(), request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["content"][0]["text"], "Echo: hello ");
+    assert_eq!(v["stop_reason"], "stop_sequence");
+    assert_eq!(v["stop_sequence"], "world");
+
+    let mut request = request;
+    request["stream"] = json!(true);
+    let (status, body) = send(&app(), request).await;
+    assert_eq!(status, StatusCode::OK);
+    let data: Vec<Value> = body.lines().filter_map(|l| l.strip_prefix("data: ")).map(|d| serde_json::from_str(d).unwrap()).collect();
+    let delta = data.iter().find(|e| e["type"] == "message_delta").unwrap();
+    assert_eq!(delta["delta"]["stop_reason"], "stop_sequence");
+    assert_eq!(delta["delta"]["stop_sequence"], "world");
+}
+
+#[tokio::test]
+async fn messages_errors_use_the_anthropic_envelope() {
+    let (status, body) = send(&app(), json!({
+        "model": "dummy-model",
+        "max_tokens": 64,
+        "messages": [{"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "42"}]}]
+    })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["type"], "error");
+    assert_eq!(v["error"]["type"], "invalid_request_error");
+
+    let (status, _) = send(&app(), json!({
+        "model": "dummy-model",
+        "max_tokens": 64,
+        "messages": [{"role": "system", "content": "no"}]
+    })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,109 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::{dto::ModelOptions, routes::chat_completions},
+    engine::CoreEngine,
+};
+
+// OpenAI-compatible upstream that fails every request with `status`
+async fn failing_upstream(status: StatusCode) -> String {
+    let app = Router::new().route("/v1/chat/completions", post(move || async move { (status, "out of memory") }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+async fn app() -> Router {
+    let engine = Arc::new(CoreEngine::new());
+    let upstream = failing_upstream(StatusCode::INTERNAL_SERVER_ERROR).await;
+    let options = ModelOptions { fallback_model: Some("dummy-model".to_string()), ..Default::default() };
+    engine.load_model("llm", "flaky", Some(&upstream), &options).await.unwrap();
+    engine.load_model("llm", "broken", Some(&upstream), &Default::default()).await.unwrap();
+    // Rejects every request as invalid, which another model would not accept either
+    let picky = failing_upstream(StatusCode::BAD_REQUEST).await;
+    engine.load_model("llm", "picky", Some(&picky), &options).await.unwrap();
+    Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine)
+}
+
+// Status, `X-Fallback-From` and body of a chat request
+async fn chat(app: &Router, model: &str, stream: bool) -> (StatusCode, Option<String>, String) {
+    // Distinct prompts, so no answer comes from the response cache
+    let body = json!({"model": model, "stream": stream, "messages": [{"role": "user", "content": uuid::Uuid::new_v4().to_string()}]});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(request).await.unwrap();
+    let status = resp.status();
+    let from = resp.headers().get("x-fallback-from").map(|v| v.to_str().unwrap().to_string());
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, from, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn failed_generations_are_answered_by_the_fallback_model() {
+    let app = app().await;
+    let (status, from, body) = chat(&app, "flaky", false).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(from.as_deref(), Some("flaky"));
+    let v: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(v["model"], "dummy-model");
+    assert!(v["choices"][0]["message"]["content"].as_str().unwrap().starts_with("Echo:"), "{}", v);
+
+    // The stream comes from the fallback model alone
+    let (status, _, body) = chat(&app, "flaky", true).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains("\"flaky\"") && !body.contains("error"), "{}", body);
+    assert!(body.contains("\"model\":\"dummy-model\"") && body.contains("Echo"), "{}", body);
+    assert!(body.trim_end().ends_with("data: [DONE]"), "{}", body);
+}
+
+#[tokio::test]
+async fn failures_without_a_fallback_model_are_errors_rather_than_content() {
+    let app = app().await;
+    let (status, from, body) = chat(&app, "broken", false).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+    assert_eq!(from, None);
+
+    let (status, _, body) = chat(&app, "broken", true).await;
+    assert_eq!(status, StatusCode::OK);
+    let last = body.lines().rfind(|line| line.starts_with("data: ")).unwrap();
+    let error: Value = serde_json::from_str(last.trim_start_matches("data: ")).unwrap();
+    assert!(error["error"]["message"].as_str().unwrap().contains("500"), "{}", body);
+    assert!(!body.contains("[DONE]") && !body.contains("[error"), "{}", body);
+}
+
+#[tokio::test]
+async fn requests_at_fault_are_not_retried_on_the_fallback_model() {
+    let app = app().await;
+    let (status, from, body) = chat(&app, "picky", false).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(from, None);
+
+    let (status, _, body) = chat(&app, "picky", true).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains("dummy-model") && body.contains("invalid_request_error"), "{}", body);
+}
+
+#[tokio::test]
+async fn fallback_models_must_be_loaded() {
+    let engine = CoreEngine::new();
+    let upstream = failing_upstream(StatusCode::INTERNAL_SERVER_ERROR).await;
+    let options = |fallback: &str| ModelOptions { fallback_model: Some(fallback.to_string()), ..Default::default() };
+    let e = engine.load_model("llm", "flaky", Some(&upstream), &options("missing")).await.unwrap_err();
+    assert!(e.contains("fallback_model missing is not a loaded LLM"), "{}", e);
+    let e = engine.load_model("llm", "flaky", Some(&upstream), &options("flaky")).await.unwrap_err();
+    assert!(e.contains("its own fallback_model"), "{}", e);
+    assert!(!engine.list_models().await.llm.contains(&"flaky".to_string()));
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use llm_serving::{api::dto::ModelOptions, config::{clear_overrides, set_overrides}, engine::CoreEngine};
+
+const MB: u64 = 1024 * 1024;
+
+fn string(out: &mut Vec<u8>, value: &str) {
+    out.extend((value.len() as u64).to_le_bytes());
+    out.extend(value.as_bytes());
+}
+
+fn uint32(out: &mut Vec<u8>, key: &str, value: u32) {
+    string(out, key);
+    out.extend(4u32.to_le_bytes());
+    out.extend(value.to_le_bytes());
+}
+
+// A GGUF file of a 4-layer llama with 8 heads of 32 dimensions sharing 2 KV heads, padded
+// with `weights` bytes; each KV cache cell takes 4 layers * (64 + 64) widths * 2 bytes = 1KB
+fn gguf(dir: &Path, name: &str, weights: u64) -> PathBuf {
+    let mut out = b"GGUF".to_vec();
+    out.extend(3u32.to_le_bytes());
+    out.extend(0u64.to_le_bytes());
+    out.extend(7u64.to_le_bytes());
+    string(&mut out, "general.architecture");
+    out.extend(8u32.to_le_bytes());
+    string(&mut out, "llama");
+    // Skipped on the way: a float, and an array of strings like a tokenizer's
+    string(&mut out, "llama.rope.freq_base");
+    out.extend(6u32.to_le_bytes());
+    out.extend(10000f32.to_le_bytes());
+    string(&mut out, "tokenizer.ggml.tokens");
+    out.extend(9u32.to_le_bytes());
+    out.extend(8u32.to_le_bytes());
+    out.extend(3u64.to_le_bytes());
+    for token in ["<s>", "</s>", "hello"] {
+        string(&mut out, token);
+    }
+    uint32(&mut out, "llama.block_count", 4);
+    uint32(&mut out, "llama.embedding_length", 256);
+    uint32(&mut out, "llama.attention.head_count", 8);
+    uint32(&mut out, "llama.attention.head_count_kv", 2);
+    out.resize(out.len() + weights as usize, 0);
+    let path = dir.join(format!("{}.gguf", name));
+    std::fs::write(&path, out).unwrap();
+    path
+}
+
+async fn footprint(engine: &CoreEngine, name: &str) -> (Option<u64>, Option<u64>) {
+    let models = engine.list_models().await.models;
+    let model = models.iter().find(|m| m.name == name).unwrap();
+    (model.ram_bytes, model.vram_bytes)
+}
+
+#[tokio::test]
+async fn gguf_models_count_their_kv_cache_and_offloaded_layers() {
+    // The only test in this binary, so nothing else sees the budget
+    set_overrides(vec![("MODEL_RAM_BUDGET_MB".to_string(), "1024".to_string()), ("MODEL_VRAM_BUDGET_MB".to_string(), "1024".to_string())]);
+    let dir = std::env::temp_dir().join(format!("model-footprint-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let engine = CoreEngine::new();
+    let weights = std::fs::metadata(gguf(&dir, "probe", 0)).unwrap().len() + MB;
+
+    // Batched generations share one context of n_ctx cells
+    let options = ModelOptions { n_ctx: Some(1024), ..ModelOptions::default() };
+    let path = gguf(&dir, "batched", MB);
+    engine.load_model("llm", "batched", path.to_str(), &options).await.unwrap();
+    assert_eq!(footprint(&engine, "batched").await, (Some(weights + MB), None));
+
+    // Generations that decode alone take a context each, one per pooled session
+    let options = ModelOptions { n_ctx: Some(1024), context_shift: true, ..ModelOptions::default() };
+    let path = gguf(&dir, "sessions", MB);
+    engine.load_model("llm", "sessions", path.to_str(), &options).await.unwrap();
+    assert_eq!(footprint(&engine, "sessions").await, (Some(weights + 2 * MB), None));
+
+    // Half the layers on the GPU put half the model in VRAM
+    let options = ModelOptions { n_ctx: Some(1024), n_gpu_layers: Some(2), ..ModelOptions::default() };
+    let path = gguf(&dir, "offloaded", MB);
+    engine.load_model("llm", "offloaded", path.to_str(), &options).await.unwrap();
+    let half = (weights + MB) / 2;
+    assert_eq!(footprint(&engine, "offloaded").await, (Some(weights + MB - half), Some(half)));
+
+    // A file whose metadata cannot be read counts its size alone
+    let path = dir.join("opaque.gguf");
+    std::fs::write(&path, vec![0u8; MB as usize]).unwrap();
+    engine.load_model("llm", "opaque", path.to_str(), &options).await.unwrap();
+    assert_eq!(footprint(&engine, "opaque").await, (None, Some(MB)));
+
+    clear_overrides();
+    let _ = std::fs::remove_dir_all(&dir);
+}
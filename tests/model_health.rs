@@ -0,0 +1,140 @@
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+use llm_serving::{
+    api::routes::{admin_models_list, chat_completions},
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+// OpenAI-compatible upstream that answers while `up` is set and fails otherwise
+async fn upstream(up: Arc<AtomicBool>) -> String {
+    let app = Router::new().route(
+        "/v1/chat/completions",
+        post(move || async move {
+            if !up.load(Ordering::Relaxed) {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "out of memory"));
+            }
+            Ok(Json(json!({ "choices": [{ "message": { "role": "assistant", "content": "hi" } }] })))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+async fn entry(app: &Router, name: &str) -> Value {
+    let (_, v) = send(app, "GET", "/admin/models", None).await;
+    v["models"].as_array().unwrap().iter().find(|m| m["name"] == name).cloned().unwrap()
+}
+
+// Upstream that fails every request, each only once `release` is notified
+async fn stalled_upstream(received: Arc<AtomicUsize>, release: Arc<Notify>) -> String {
+    let app = Router::new().route(
+        "/v1/chat/completions",
+        post(move || async move {
+            received.fetch_add(1, Ordering::SeqCst);
+            release.notified().await;
+            (StatusCode::INTERNAL_SERVER_ERROR, "out of memory")
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+fn chat() -> Value {
+    // Distinct prompts, so no answer comes from the response cache
+    json!({"model": "flaky", "messages": [{"role": "user", "content": uuid::Uuid::new_v4().to_string()}]})
+}
+
+#[tokio::test]
+async fn models_failing_health_checks_are_taken_out_until_they_pass() {
+    let up = Arc::new(AtomicBool::new(false));
+    let engine = Arc::new(CoreEngine::new());
+    engine.load_model("llm", "flaky", Some(&upstream(up.clone()).await), &Default::default()).await.unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .with_state(engine.clone());
+
+    // Below the threshold the model keeps serving, with the failure on show
+    assert!(engine.check_model_health().await.is_empty());
+    assert!(engine.check_model_health().await.is_empty());
+    let flaky = entry(&app, "flaky").await;
+    assert_eq!(flaky["status"], "ready");
+    assert!(flaky["probe_error"].as_str().unwrap().contains("500"), "{}", flaky);
+
+    assert_eq!(engine.check_model_health().await, vec![("flaky".to_string(), false)]);
+    assert_eq!(entry(&app, "flaky").await["status"], "unhealthy");
+    let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat())).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(v["error"]["message"].as_str().unwrap().starts_with("Model is unhealthy: flaky"), "{}", v);
+    // Healthy models are untouched
+    assert_eq!(entry(&app, "dummy-model").await["status"], "ready");
+
+    up.store(true, Ordering::Relaxed);
+    assert_eq!(engine.check_model_health().await, vec![("flaky".to_string(), true)]);
+    let flaky = entry(&app, "flaky").await;
+    assert_eq!(flaky["status"], "ready");
+    assert!(flaky.get("probe_error").is_none());
+    let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat())).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+}
+
+#[tokio::test]
+async fn models_serving_requests_are_not_probed() {
+    let (received, release) = (Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+    let engine = Arc::new(CoreEngine::new());
+    engine.load_model("llm", "flaky", Some(&stalled_upstream(received.clone(), release.clone()).await), &Default::default()).await.unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .with_state(engine.clone());
+
+    let request = tokio::spawn({
+        let app = app.clone();
+        async move { send(&app, "POST", "/v1/chat/completions", Some(chat())).await }
+    });
+    while received.load(Ordering::SeqCst) == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    // The probes would queue behind the request; none runs, so none fails
+    for _ in 0..3 {
+        assert!(engine.check_model_health().await.is_empty());
+    }
+    assert_eq!(received.load(Ordering::SeqCst), 1);
+    let flaky = entry(&app, "flaky").await;
+    assert_eq!(flaky["status"], "ready");
+    assert!(flaky.get("probe_error").is_none(), "{}", flaky);
+
+    release.notify_one();
+    request.await.unwrap();
+    // Idle again, the model is probed
+    let probe = tokio::spawn({
+        let engine = engine.clone();
+        async move { engine.check_model_health().await }
+    });
+    while received.load(Ordering::SeqCst) < 2 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    release.notify_one();
+    assert!(probe.await.unwrap().is_empty());
+    assert!(entry(&app, "flaky").await["probe_error"].as_str().unwrap().contains("500"));
+}
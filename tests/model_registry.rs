@@ -0,0 +1,166 @@
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use llm_serving::{
+    api::{
+        dto::ModelOptions,
+        routes::{admin_models_list, admin_models_load, admin_models_unload, chat_completions, embeddings},
+    },
+    config::set_overrides,
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+fn entry<'a>(models: &'a Value, name: &str) -> Option<&'a Value> {
+    models["models"].as_array().unwrap().iter().find(|m| m["name"] == name)
+}
+
+#[tokio::test]
+async fn models_are_listed_with_capabilities_and_load_parameters() {
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/models/unload", post(admin_models_unload))
+        .with_state(Arc::new(CoreEngine::new()));
+
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    let dummy = entry(&models, "dummy-model").unwrap();
+    assert_eq!(dummy["backend"], "dummy");
+    assert_eq!(dummy["status"], "ready");
+    assert_eq!(dummy["capabilities"], json!(["chat", "vision"]));
+    assert!(dummy.get("path").is_none() && dummy.get("options").is_none());
+    assert!(models["multimodal"].as_array().unwrap().iter().any(|m| m == "dummy-model"));
+
+    let load = json!({"model": "notes", "kind": "embedding", "embedding_dim": 64});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    let notes = entry(&models, "notes").unwrap();
+    assert_eq!((notes["kind"].as_str(), notes["capabilities"].clone()), (Some("embedding"), json!(["embeddings"])));
+    assert_eq!(notes["options"], json!({"embedding_dim": 64}));
+    assert!(models["embedding"].as_array().unwrap().iter().any(|m| m == "notes"));
+
+    // Loading another kind under the name replaces the model rather than adding a second one
+    let load = json!({"model": "notes", "kind": "llm"});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert_eq!(entry(&models, "notes").unwrap()["capabilities"], json!(["chat"]));
+    assert!(!models["embedding"].as_array().unwrap().iter().any(|m| m == "notes"));
+    let chat = json!({"model": "notes", "messages": [{"role": "user", "content": "hi"}], "max_tokens": 2});
+    assert_eq!(send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await.0, StatusCode::OK);
+
+    // Unloading a kind the model does not serve is refused and leaves it in place
+    let unload = |kind: &str| json!({"model": "notes", "kind": kind});
+    assert_eq!(send(&app, "POST", "/admin/models/unload", Some(unload("embedding"))).await.0, StatusCode::BAD_REQUEST);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert!(entry(&models, "notes").is_some());
+    assert_eq!(send(&app, "POST", "/admin/models/unload", Some(unload("llm"))).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert!(entry(&models, "notes").is_none());
+    assert_eq!(send(&app, "POST", "/admin/models/unload", Some(unload("llm"))).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "POST", "/admin/models/unload", Some(unload("weights"))).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(json!({"model": "x", "kind": "weights"}))).await.0, StatusCode::BAD_REQUEST);
+}
+
+// A model hub that has no repositories; the first lookup takes a while to say so
+async fn empty_hub() -> String {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let app = Router::new().fallback(move || {
+        let lookups = lookups.clone();
+        async move {
+            if lookups.fetch_add(1, Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+            }
+            StatusCode::NOT_FOUND
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn failed_loads_of_a_new_name_leave_nothing_behind() {
+    let cache = std::env::temp_dir().join(format!("model-registry-{}", uuid::Uuid::new_v4().simple()));
+    set_overrides(vec![("HF_ENDPOINT".to_string(), empty_hub().await), ("MODEL_CACHE_DIR".to_string(), cache.display().to_string())]);
+    let engine = Arc::new(CoreEngine::new());
+    let options: ModelOptions = serde_json::from_value(json!({"hf_repo": "nobody/nothing"})).unwrap();
+    let load = |engine: Arc<CoreEngine>, options: ModelOptions| tokio::spawn(async move { engine.load_model("llm", "missing", None, &options).await });
+
+    // A second load begins while the first is still looking the repository up
+    let first = load(engine.clone(), options.clone());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let second = load(engine.clone(), options);
+    assert!(second.await.unwrap().is_err());
+    assert!(first.await.unwrap().is_err());
+    let models = engine.list_models().await.models;
+    let left = models.iter().find(|m| m.name == "missing").map(|m| (m.backend.clone(), m.status));
+    assert!(left.is_none(), "{:?}", left);
+    let _ = std::fs::remove_dir_all(&cache);
+}
+
+#[tokio::test]
+async fn one_model_serves_every_endpoint_it_is_capable_of() {
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/load", post(admin_models_load))
+        .with_state(Arc::new(CoreEngine::new()));
+
+    // An LLM can serve embeddings from its hidden states as well
+    let load = json!({"model": "both", "kind": "llm", "embeddings": true});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert_eq!(entry(&models, "both").unwrap()["capabilities"], json!(["chat", "embeddings"]));
+    assert!(models["embedding"].as_array().unwrap().iter().any(|m| m == "both"));
+    let (status, v) = send(&app, "POST", "/v1/embeddings", Some(json!({"model": "both", "input": "hello"}))).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+    assert_eq!(v["data"][0]["embedding"].as_array().unwrap().len(), 384);
+    let chat = json!({"model": "both", "messages": [{"role": "user", "content": "hi"}], "max_tokens": 2});
+    assert_eq!(send(&app, "POST", "/v1/chat/completions", Some(chat)).await.0, StatusCode::OK);
+    let load = json!({"model": "x", "kind": "rerank", "embeddings": true});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::BAD_REQUEST);
+
+    // A vision model answers text-only chats too
+    let load = json!({"model": "eyes", "kind": "multimodal"});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    assert_eq!(entry(&models, "eyes").unwrap()["capabilities"], json!(["chat", "vision"]));
+    let chat = json!({"model": "eyes", "messages": [{"role": "user", "content": "hi"}], "max_tokens": 50});
+    let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat)).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+    assert!(v["choices"][0]["message"]["content"].as_str().unwrap().starts_with("Echo(Vision)"), "{}", v);
+    let (status, _) = send(&app, "POST", "/v1/embeddings", Some(json!({"model": "eyes", "input": "hello"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn loads_report_how_long_the_warmup_took() {
+    let app = Router::new().route("/admin/models/load", post(admin_models_load)).with_state(Arc::new(CoreEngine::new()));
+    let load = |body: Value| send(&app, "POST", "/admin/models/load", Some(body));
+
+    let (status, v) = load(json!({"model": "warm", "kind": "llm", "embeddings": true})).await;
+    assert_eq!((status, v["status"].as_str()), (StatusCode::OK, Some("ok")));
+    assert!(v["warmup_ms"].is_u64(), "{}", v);
+    assert!(load(json!({"model": "vectors", "kind": "embedding"})).await.1["warmup_ms"].is_u64());
+
+    // Opted out, nothing to warm up, or an upstream that is warm already
+    assert!(load(json!({"model": "cold", "kind": "llm", "warmup": false})).await.1.get("warmup_ms").is_none());
+    assert!(load(json!({"model": "ranker", "kind": "rerank"})).await.1.get("warmup_ms").is_none());
+    assert!(load(json!({"model": "far", "kind": "llm", "path": "http://127.0.0.1:1"})).await.1.get("warmup_ms").is_none());
+}
@@ -0,0 +1,106 @@
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{
+    api::routes::{admin_models_list, admin_models_swap, admin_models_swaps_get, admin_models_swaps_list, chat_completions},
+    config::set_overrides,
+    engine::CoreEngine,
+};
+
+mod common;
+use common::send;
+
+// OpenAI-compatible upstream that answers every chat with `answer`
+async fn upstream(answer: &'static str) -> String {
+    let app = Router::new().route(
+        "/v1/chat/completions",
+        post(move || async move { Json(json!({ "choices": [{ "message": { "role": "assistant", "content": answer } }] })) }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+// Content of a chat with "chat", which must succeed
+async fn chat(app: &Router) -> String {
+    // Distinct prompts, so no answer comes from the response cache
+    let body = json!({"model": "chat", "messages": [{"role": "user", "content": uuid::Uuid::new_v4().to_string()}]});
+    let (status, v) = send(app, "POST", "/v1/chat/completions", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+    v["choices"][0]["message"]["content"].as_str().unwrap().to_string()
+}
+
+// Swaps "chat" to `body` and serves requests until the swap finishes, returning its record
+async fn swap(app: &Router, body: Value) -> Value {
+    let (status, v) = send(app, "POST", "/admin/models/swap", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+    assert_eq!(v["status"], "loading");
+    let uri = format!("/admin/models/swaps/{}", v["id"].as_str().unwrap());
+    for _ in 0..200 {
+        chat(app).await;
+        let (_, v) = send(app, "GET", &uri, None).await;
+        if v["status"] != "loading" {
+            return v;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("swap did not finish");
+}
+
+#[tokio::test]
+async fn swaps_replace_a_model_without_interrupting_it() {
+    set_overrides(vec![("MODEL_SWAP_HISTORY".to_string(), "2".to_string())]);
+    let engine = Arc::new(CoreEngine::new());
+    engine.load_model("llm", "chat", Some(&upstream("v1").await), &Default::default()).await.unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/models/swap", post(admin_models_swap))
+        .route("/admin/models/swaps", get(admin_models_swaps_list))
+        .route("/admin/models/swaps/:id", get(admin_models_swaps_get))
+        .with_state(engine);
+    assert_eq!(chat(&app).await, "v1");
+
+    let v2 = upstream("v2").await;
+    let first = swap(&app, json!({"model": "chat", "kind": "llm", "path": v2})).await;
+    assert_eq!(first["status"], "completed", "{}", first);
+    assert_eq!(first["previous_backend"], "remote");
+    assert_eq!(chat(&app).await, "v2");
+
+    // A version that fails to load (remote models serve no embeddings) leaves the running one in place
+    let done = swap(&app, json!({"model": "chat", "kind": "llm", "path": upstream("v3").await, "embeddings": true})).await;
+    assert_eq!(done["status"], "failed");
+    assert!(done["error"].as_str().unwrap().contains("cannot serve embeddings"), "{}", done);
+    assert_eq!(chat(&app).await, "v2");
+    let (_, models) = send(&app, "GET", "/admin/models", None).await;
+    let entry = models["models"].as_array().unwrap().iter().find(|m| m["name"] == "chat").unwrap();
+    assert_eq!((entry["status"].as_str(), entry["path"].as_str()), (Some("ready"), Some(v2.as_str())));
+
+    let (_, list) = send(&app, "GET", "/admin/models/swaps", None).await;
+    assert_eq!(list["data"].as_array().unwrap().len(), 2);
+    assert_eq!(send(&app, "GET", "/admin/models/swaps/swap-missing", None).await.0, StatusCode::NOT_FOUND);
+
+    // Past MODEL_SWAP_HISTORY, the oldest finished swaps are forgotten
+    let done = swap(&app, json!({"model": "chat", "kind": "llm", "path": upstream("v4").await})).await;
+    assert_eq!(done["status"], "completed", "{}", done);
+    let (_, list) = send(&app, "GET", "/admin/models/swaps", None).await;
+    let ids: Vec<&Value> = list["data"].as_array().unwrap().iter().map(|s| &s["id"]).collect();
+    assert_eq!(ids.len(), 2);
+    assert!(!ids.contains(&&first["id"]), "{}", list);
+    let uri = format!("/admin/models/swaps/{}", first["id"].as_str().unwrap());
+    assert_eq!(send(&app, "GET", &uri, None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn swaps_need_a_loaded_model() {
+    let app = Router::new().route("/admin/models/swap", post(admin_models_swap)).with_state(Arc::new(CoreEngine::new()));
+    let (status, v) = send(&app, "POST", "/admin/models/swap", Some(json!({"model": "missing", "kind": "llm", "path": "/m.gguf"}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(v["error"]["message"].as_str().unwrap().contains("not found"), "{}", v);
+}
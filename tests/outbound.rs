@@ -0,0 +1,56 @@
+use axum::{routing::get, Router};
+
+use llm_serving::outbound;
+
+#[tokio::test]
+async fn outbound_client_fetches_with_body_limit() {
+    let app = Router::new().route("/blob", get(|| async { "0123456789" }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = outbound::client("test");
+    let url = format!("http://localhost:{}/blob", addr.port());
+
+    let response = client.send(client.get(&url)).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.bytes_limited(64).await.unwrap(), b"0123456789");
+
+    let response = client.send(client.get(&url)).await.unwrap();
+    assert!(response.bytes_limited(4).await.is_err());
+
+    assert_eq!(outbound::config().max_connections_per_host, 32);
+}
+
+#[tokio::test]
+async fn host_limits_forget_hosts_with_nothing_in_flight() {
+    let limits = outbound::HostLimits::new(1);
+    let held = limits.acquire("a.example").await;
+    for i in 0..100 {
+        drop(limits.acquire(&format!("host-{}.example", i)).await);
+    }
+    // Only the host still holding a permit, and the last one, which nothing has pruned yet
+    assert_eq!(limits.len(), 2);
+
+    // A released host's slot is there again for the next request
+    drop(held);
+    drop(limits.acquire("a.example").await);
+    assert_eq!(limits.len(), 1);
+}
+
+#[tokio::test]
+async fn dns_cache_is_bounded_and_drops_expired_answers() {
+    // IP literals resolve without a DNS server
+    let resolver = outbound::CachingResolver::with_capacity(std::time::Duration::from_secs(60), 2);
+    for host in ["127.0.0.1", "127.0.0.2", "127.0.0.3"] {
+        resolver.lookup(host).await.unwrap();
+    }
+    assert_eq!(resolver.len(), 2);
+
+    let resolver = outbound::CachingResolver::with_capacity(std::time::Duration::from_millis(20), 100);
+    resolver.lookup("127.0.0.1").await.unwrap();
+    resolver.lookup("127.0.0.2").await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+    resolver.lookup("127.0.0.3").await.unwrap();
+    assert_eq!(resolver.len(), 1);
+}
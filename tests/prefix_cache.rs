@@ -0,0 +1,45 @@
+use llm_serving::runtime::prefix_cache::PrefixCache;
+
+#[test]
+fn lookup_finds_the_longest_cached_prefix() {
+    let mut cache = PrefixCache::new(4);
+    cache.insert(vec![1, 2], "system").unwrap();
+    cache.insert(vec![1, 2, 3, 4], "turn").unwrap();
+    cache.insert(vec![9], "other").unwrap();
+
+    assert_eq!(cache.lookup(&[1, 2, 3, 4, 5]), Some((4, &"turn")));
+    assert_eq!(cache.lookup(&[1, 2, 3, 7]), Some((2, &"system")));
+    assert_eq!(cache.lookup(&[1, 2, 3, 4]), Some((4, &"turn")));
+    assert_eq!(cache.lookup(&[2, 1]), None);
+    assert_eq!(cache.lookup(&[]), None);
+}
+
+#[test]
+fn least_recently_used_prefix_is_evicted_first() {
+    let mut cache = PrefixCache::new(2);
+    cache.insert(vec![1], 10).unwrap();
+    cache.insert(vec![2], 20).unwrap();
+    assert!(cache.is_full());
+    assert_eq!(cache.insert(vec![3], 30), Err(30));
+
+    // Using [1] makes [2] the oldest
+    assert!(cache.lookup(&[1, 5]).is_some());
+    assert_eq!(cache.evict(), Some(20));
+    cache.insert(vec![3], 30).unwrap();
+    assert!(cache.touch(&[3]));
+    assert!(!cache.touch(&[3, 4]));
+    assert_eq!(cache.evict(), Some(10));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn duplicate_and_disabled_inserts_hand_the_value_back() {
+    let mut cache = PrefixCache::new(2);
+    cache.insert(vec![1, 2], 'a').unwrap();
+    assert_eq!(cache.insert(vec![1, 2], 'b'), Err('b'));
+
+    let mut disabled: PrefixCache<u32, char> = PrefixCache::new(0);
+    assert_eq!(disabled.insert(vec![1], 'a'), Err('a'));
+    assert!(disabled.is_empty());
+    assert_eq!(disabled.evict(), None);
+}
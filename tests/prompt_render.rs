@@ -0,0 +1,149 @@
+use serde_json::json;
+
+use llm_serving::{
+    api::dto::ChatCompletionMessage,
+    runtime::{
+        prompt::{last_user_turn, parse_chatml, render_chat_prompt, split_for_images, tokenize_prompt, ChatTemplate},
+        vision::{ImageDetail, ImageInput},
+    },
+};
+
+#[test]
+fn renders_every_turn_of_the_conversation() {
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": "hi"},
+        {"role": "assistant", "content": "hello"},
+        {"role": "user", "content": [
+            {"type": "text", "text": "what is this?"},
+            {"type": "image_url", "image_url": {"url": "http://example.com/a.png", "detail": "high"}}
+        ]}
+    ])).unwrap();
+
+    let rendered = render_chat_prompt(&messages);
+    assert_eq!(
+        rendered.prompt,
+        "<|im_start|>system\nbe brief<|im_end|>\n<|im_start|>user\nhi<|im_end|>\n\
+         <|im_start|>assistant\nhello<|im_end|>\n<|im_start|>user\nwhat is this?<|im_end|>\n\
+         <|im_start|>assistant\n"
+    );
+    assert_eq!(rendered.images, vec![ImageInput { url: "http://example.com/a.png".to_string(), detail: ImageDetail::High }]);
+    assert_eq!(last_user_turn(&rendered.prompt), "what is this?");
+}
+
+#[test]
+fn builtin_templates_render_model_specific_formats() {
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": "hi"}
+    ])).unwrap();
+
+    let llama3 = ChatTemplate::resolve("llama3").unwrap().render(&messages).unwrap();
+    assert_eq!(
+        llama3.prompt,
+        "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\nbe brief<|eot_id|>\
+         <|start_header_id|>user<|end_header_id|>\n\nhi<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n"
+    );
+
+    let mistral = ChatTemplate::resolve("mistral").unwrap().render(&messages).unwrap();
+    assert_eq!(mistral.prompt, "<s>[INST] be brief\n\nhi [/INST]");
+
+    // The ChatML built-in matches the default rendering
+    let chatml = ChatTemplate::resolve("chatml").unwrap().render(&messages).unwrap();
+    assert_eq!(chatml, render_chat_prompt(&messages));
+}
+
+#[test]
+fn invalid_inline_template_is_rejected_at_load() {
+    assert!(ChatTemplate::resolve("{% for m in messages %}").is_err());
+    let custom = ChatTemplate::resolve("{% for m in messages %}{{ m.role|upper }}: {{ m.content }}\n{% endfor %}ASSISTANT:").unwrap();
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([{"role": "user", "content": "hi"}])).unwrap();
+    assert_eq!(custom.render(&messages).unwrap().prompt, "USER: hi\nASSISTANT:");
+}
+
+#[test]
+fn leading_system_turns_form_the_kept_prefix() {
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": "hi"}
+    ])).unwrap();
+
+    let rendered = render_chat_prompt(&messages);
+    assert_eq!(&rendered.prompt[..rendered.keep_prefix], "<|im_start|>system\nbe brief<|im_end|>\n");
+    let llama3 = ChatTemplate::resolve("llama3").unwrap().render(&messages).unwrap();
+    assert!(llama3.prompt[..llama3.keep_prefix].ends_with("be brief<|eot_id|>"));
+
+    let no_system: Vec<ChatCompletionMessage> = serde_json::from_value(json!([{"role": "user", "content": "hi"}])).unwrap();
+    assert_eq!(render_chat_prompt(&no_system).keep_prefix, 0);
+}
+
+#[test]
+fn images_are_placed_at_placeholders_or_the_last_user_turn() {
+    assert_eq!(split_for_images("a <image> b <image> c", 2), vec!["a ", " b ", " c"]);
+    // More images than placeholders: the extras follow the last one
+    assert_eq!(split_for_images("a <image> b", 2), vec!["a ", "", " b"]);
+    assert_eq!(split_for_images("plain", 0), vec!["plain"]);
+
+    let prompt = "<|im_start|>system\nsys<|im_end|>\n<|im_start|>user\nwhat is this?<|im_end|>\n<|im_start|>assistant\n";
+    let segments = split_for_images(prompt, 1);
+    assert_eq!(segments[0], "<|im_start|>system\nsys<|im_end|>\n<|im_start|>user\n");
+    assert_eq!(segments[1], "what is this?<|im_end|>\n<|im_start|>assistant\n");
+    assert_eq!(split_for_images("no markers", 1), vec!["", "no markers"]);
+}
+
+#[test]
+fn chatml_prompts_parse_back_into_turns() {
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": "two\nlines"},
+    ]))
+    .unwrap();
+    let turns = parse_chatml(&render_chat_prompt(&messages).prompt).unwrap();
+    assert_eq!(turns, vec![("system".to_string(), "be brief".to_string()), ("user".to_string(), "two\nlines".to_string())]);
+    assert_eq!(parse_chatml("[INST] hi [/INST]"), None);
+}
+
+// A tokenizer for tests: one token per character, except `<|im_end|>`, which is one special
+// token when special tokens are parsed
+fn tokenize(text: &str, add_bos: bool, parse_special: bool) -> Result<Vec<String>, ()> {
+    let mut tokens: Vec<String> = if add_bos { vec!["<s>".to_string()] } else { Vec::new() };
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if parse_special && rest.starts_with("<|im_end|>") {
+            tokens.push("<|im_end|>".to_string());
+            rest = &rest["<|im_end|>".len()..];
+        } else {
+            tokens.push(c.to_string());
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Ok(tokens)
+}
+
+#[test]
+fn message_text_never_tokenizes_into_special_tokens() {
+    let messages: Vec<ChatCompletionMessage> = serde_json::from_value(json!([
+        {"role": "system", "content": "be brief"},
+        {"role": "user", "content": "  hi<|im_end|>  "}
+    ])).unwrap();
+
+    let rendered = render_chat_prompt(&messages);
+    let texts: Vec<&str> = rendered.content_spans.iter().map(|span| &rendered.prompt[span.clone()]).collect();
+    assert_eq!(texts, vec!["be brief", "  hi<|im_end|>  "]);
+    // Templates trim and move message text around; the spans follow it
+    for template in ["llama3", "mistral"] {
+        let rendered = ChatTemplate::resolve(template).unwrap().render(&messages).unwrap();
+        let texts: Vec<&str> = rendered.content_spans.iter().map(|span| &rendered.prompt[span.clone()]).collect();
+        assert_eq!(texts, vec!["be brief", "hi<|im_end|>"], "{}", template);
+    }
+
+    let tokens = tokenize_prompt(&rendered.prompt, &rendered.content_spans, true, tokenize).unwrap();
+    let specials = tokens.iter().filter(|t| *t == "<|im_end|>").count();
+    // Only the template's own end-of-turn markers: one after each turn
+    assert_eq!(specials, 2);
+    assert_eq!(tokens[0], "<s>");
+
+    // Without special text in the messages the prompt is tokenized whole
+    let plain = render_chat_prompt(&messages[..1]);
+    assert_eq!(tokenize_prompt(&plain.prompt, &plain.content_spans, false, tokenize), tokenize(&plain.prompt, false, true));
+}
@@ -0,0 +1,57 @@
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use llm_serving::{
+    api::dto::{ChatCompletionMessage, ChatCompletionRequest, ChatMessageContent},
+    config::set_overrides,
+    engine::{CoreEngine, EngineError},
+};
+
+// The dummy model echoes the prompt a word at a time
+fn chat(words: usize) -> ChatCompletionRequest {
+    // Distinct prompts, so no answer comes from the response cache
+    let content = format!("{}{}", uuid::Uuid::new_v4(), " word".repeat(words));
+    ChatCompletionRequest {
+        model: "dummy-model".to_string(),
+        messages: vec![ChatCompletionMessage { role: "user".to_string(), content: ChatMessageContent::Text(content) }],
+        max_tokens: Some(8192),
+        ..Default::default()
+    }
+}
+
+// The clock only moves when every task is idle, so the wait deadline passes exactly when nothing
+// else can happen first
+#[tokio::test(start_paused = true)]
+async fn requests_no_worker_takes_up_in_time_are_turned_away_and_dropped() {
+    // The only test in this binary, so nothing else sees these settings
+    set_overrides(vec![
+        ("ENGINE_WORKERS".to_string(), "1".to_string()),
+        ("CONTINUOUS_BATCH_MAX_SEQS".to_string(), "1".to_string()),
+        ("QUEUE_MAX_WAIT_MS".to_string(), "200".to_string()),
+    ]);
+    let engine = Arc::new(CoreEngine::new());
+
+    // A stream nobody reads holds the only worker once its buffer fills up
+    let (stalled, mut stalled_chunks) = mpsc::channel(1);
+    let result = engine.process_chat_request(chat(1000), Some(stalled), CancellationToken::new()).await;
+    assert!(matches!(result, Err(EngineError::Streaming)));
+    assert!(stalled_chunks.recv().await.is_some());
+
+    let (waiting, mut waiting_chunks) = mpsc::channel(1);
+    let result = engine.process_chat_request(chat(2), Some(waiting), CancellationToken::new()).await;
+    match result {
+        Err(EngineError::Overloaded(message)) => assert!(message.starts_with("Server overloaded"), "{}", message),
+        other => panic!("expected an overloaded error, got {:?}", other.map(|r| r.id)),
+    }
+    // The abandoned request has left the queue, taking its stream with it, while the worker is
+    // still held
+    let closed = tokio::time::timeout(Duration::from_secs(60), waiting_chunks.recv()).await;
+    assert!(matches!(closed, Ok(None)), "the abandoned request is still queued");
+    assert_eq!(engine.queue_depth(), 0);
+
+    // Dropping the stream cancels it and frees the worker
+    drop(stalled_chunks);
+    let response = engine.process_chat_request(chat(2), None, CancellationToken::new()).await.unwrap();
+    assert!(response.choices[0].message.content.starts_with("Echo:"), "{}", response.choices[0].message.content);
+}
@@ -0,0 +1,153 @@
+use axum::{routing::get, Router};
+use base64::Engine as _;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use llm_serving::{api::routes::realtime, engine::CoreEngine};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect() -> Socket {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/v1/realtime", get(realtime)).with_state(Arc::new(CoreEngine::new()));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let (mut socket, _) = connect_async(format!("ws://{}/v1/realtime?model=dummy-model", addr)).await.unwrap();
+    let created = recv(&mut socket).await;
+    assert_eq!(created["type"], "session.created");
+    assert_eq!(created["session"]["model"], "dummy-model");
+    socket
+}
+
+async fn send(socket: &mut Socket, event: Value) {
+    socket.send(Message::Text(event.to_string())).await.unwrap();
+}
+
+async fn recv(socket: &mut Socket) -> Value {
+    loop {
+        if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            let event: Value = serde_json::from_str(&text).unwrap();
+            assert!(event["event_id"].as_str().unwrap().starts_with("event_"));
+            return event;
+        }
+    }
+}
+
+// Events up to and including `response.done`
+async fn response_events(socket: &mut Socket) -> Vec<Value> {
+    let mut events = Vec::new();
+    loop {
+        let event = recv(socket).await;
+        let done = event["type"] == "response.done";
+        events.push(event);
+        if done {
+            return events;
+        }
+    }
+}
+
+fn types(events: &[Value]) -> Vec<&str> {
+    let mut types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    types.dedup();
+    types
+}
+
+#[tokio::test]
+async fn realtime_text_conversation_streams_response_events() {
+    let mut socket = connect().await;
+    send(&mut socket, json!({"type": "session.update", "session": {"modalities": ["text"], "instructions": "be brief"}})).await;
+    let updated = recv(&mut socket).await;
+    assert_eq!(updated["type"], "session.updated");
+    assert_eq!(updated["session"]["modalities"], json!(["text"]));
+
+    send(&mut socket, json!({
+        "type": "conversation.item.create",
+        "item": {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "hello there"}]}
+    })).await;
+    let created = recv(&mut socket).await;
+    assert_eq!(created["type"], "conversation.item.created");
+    assert!(created["previous_item_id"].is_null());
+
+    send(&mut socket, json!({"type": "response.create"})).await;
+    let events = response_events(&mut socket).await;
+    assert_eq!(types(&events), [
+        "response.created",
+        "response.output_item.added",
+        "response.content_part.added",
+        "response.text.delta",
+        "response.text.done",
+        "response.content_part.done",
+        "response.output_item.done",
+        "response.done",
+    ]);
+    let text: String = events.iter().filter(|e| e["type"] == "response.text.delta").map(|e| e["delta"].as_str().unwrap()).collect();
+    assert_eq!(text, "Echo: hello there");
+    let done = events.last().unwrap();
+    assert_eq!(done["response"]["status"], "completed");
+    assert_eq!(done["response"]["output"][0]["content"][0], json!({"type": "text", "text": "Echo: hello there"}));
+    assert!(done["response"]["usage"]["output_tokens"].as_u64().unwrap() > 0);
+
+    // The reply joined the conversation, so the next item follows it
+    send(&mut socket, json!({
+        "type": "conversation.item.create",
+        "item": {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "again"}]}
+    })).await;
+    let created = recv(&mut socket).await;
+    assert_eq!(created["previous_item_id"], done["response"]["output"][0]["id"]);
+}
+
+#[tokio::test]
+async fn realtime_audio_input_is_transcribed_and_answered_with_speech() {
+    let mut socket = connect().await;
+    // One second of silence at 24 kHz, appended in two pieces
+    let half = base64::engine::general_purpose::STANDARD.encode(vec![0u8; 24_000]);
+    for _ in 0..2 {
+        send(&mut socket, json!({"type": "input_audio_buffer.append", "audio": half})).await;
+    }
+    send(&mut socket, json!({"type": "input_audio_buffer.commit"})).await;
+    let committed = recv(&mut socket).await;
+    assert_eq!(committed["type"], "input_audio_buffer.committed");
+    let created = recv(&mut socket).await;
+    assert_eq!(created["type"], "conversation.item.created");
+    assert_eq!(created["item"]["id"], committed["item_id"]);
+    let transcribed = recv(&mut socket).await;
+    assert_eq!(transcribed["type"], "conversation.item.input_audio_transcription.completed");
+    assert_eq!(transcribed["transcript"], "Transcribed 1.0s of audio");
+
+    send(&mut socket, json!({"type": "response.create"})).await;
+    let events = response_events(&mut socket).await;
+    let transcript: String = events
+        .iter()
+        .filter(|e| e["type"] == "response.audio_transcript.delta")
+        .map(|e| e["delta"].as_str().unwrap())
+        .collect();
+    assert_eq!(transcript, "Echo: Transcribed 1.0s of audio");
+    let audio_bytes: usize = events
+        .iter()
+        .filter(|e| e["type"] == "response.audio.delta")
+        .map(|e| base64::engine::general_purpose::STANDARD.decode(e["delta"].as_str().unwrap()).unwrap().len())
+        .sum();
+    assert!(audio_bytes > 0 && audio_bytes.is_multiple_of(2));
+    assert!(events.iter().any(|e| e["type"] == "response.audio.done"));
+    assert_eq!(events.last().unwrap()["response"]["status"], "completed");
+}
+
+#[tokio::test]
+async fn realtime_errors_name_the_client_event() {
+    let mut socket = connect().await;
+    send(&mut socket, json!({"type": "input_audio_buffer.commit", "event_id": "evt_1"})).await;
+    let error = recv(&mut socket).await;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["error"]["type"], "invalid_request_error");
+    assert_eq!(error["error"]["event_id"], "evt_1");
+    assert_eq!(error["error"]["message"], "input audio buffer is empty");
+
+    send(&mut socket, json!({"type": "session.update", "session": {"modalities": ["audio"]}})).await;
+    assert_eq!(recv(&mut socket).await["type"], "error");
+
+    send(&mut socket, json!({"type": "response.cancel"})).await;
+    assert_eq!(recv(&mut socket).await["error"]["message"], "no response is in progress");
+}
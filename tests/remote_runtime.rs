@@ -0,0 +1,116 @@
+use axum::{http::HeaderMap, routing::post, Json, Router};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use llm_serving::{
+    api::dto::{ModelOptions, RemoteApi},
+    runtime::{
+        remote::{RemoteConfig, RemoteRuntime},
+        EmbeddingRuntime, GenerationOptions, LlmRuntime, RuntimeError,
+    },
+};
+
+// OpenAI-compatible upstream that echoes what it was sent
+async fn serve() -> String {
+    async fn chat(headers: HeaderMap, Json(body): Json<Value>) -> axum::response::Response {
+        use axum::response::IntoResponse;
+        if headers.get("authorization").and_then(|v| v.to_str().ok()) != Some("Bearer sk-test") {
+            return (axum::http::StatusCode::UNAUTHORIZED, "bad key").into_response();
+        }
+        let last = body["messages"].as_array().and_then(|m| m.last()).cloned().unwrap_or_default();
+        let reply = format!("{} said {}", last["role"].as_str().unwrap_or_default(), last["content"].as_str().unwrap_or_default());
+        if body["stream"] == json!(true) {
+            let mut events: String = reply
+                .split_inclusive(' ')
+                .map(|word| format!("data: {}\n\n", json!({ "choices": [{ "delta": { "content": word } }] })))
+                .collect();
+            events.push_str("data: [DONE]\n\n");
+            return ([("content-type", "text/event-stream")], events).into_response();
+        }
+        Json(json!({ "model": body["model"], "choices": [{ "message": { "role": "assistant", "content": reply } }] })).into_response()
+    }
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat))
+        .route("/v1/completions", post(|Json(body): Json<Value>| async move {
+            Json(json!({ "choices": [{ "text": format!("{}|{}|{}", body["model"], body["prompt"], body["stop"]) }] }))
+        }))
+        .route("/v1/embeddings", post(|Json(body): Json<Value>| async move {
+            // Out of order, as servers may return them
+            let data: Vec<Value> = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, text)| json!({ "index": i, "embedding": [text.as_str().unwrap().len() as f32, 1.0] }))
+                .collect();
+            Json(json!({ "data": data }))
+        }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://127.0.0.1:{}", port)
+}
+
+fn runtime(base_url: &str, api: RemoteApi, key: Option<&str>) -> RemoteRuntime {
+    let mut config = RemoteConfig::new(base_url, "served-name", &ModelOptions { upstream_api: Some(api), ..Default::default() }).unwrap();
+    config.api_key = key.map(str::to_string);
+    RemoteRuntime::new(config)
+}
+
+const PROMPT: &str = "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nhello there<|im_end|>\n<|im_start|>assistant\n";
+
+#[tokio::test]
+async fn chat_generations_are_forwarded_and_streamed() {
+    let base = serve().await;
+    let remote = runtime(&base, RemoteApi::Chat, Some("sk-test"));
+    let options = GenerationOptions::from_request(Some(16), None, None);
+    assert_eq!(remote.generate(PROMPT, &options).await.unwrap(), "user said hello there");
+
+    let (tx, mut rx) = mpsc::channel(16);
+    remote.generate_stream(PROMPT, &options, tx).await.unwrap();
+    let mut pieces = Vec::new();
+    while let Some(piece) = rx.recv().await {
+        pieces.push(piece);
+    }
+    assert_eq!(pieces, ["user ", "said ", "hello ", "there"]);
+
+    let err = runtime(&base, RemoteApi::Chat, Some("wrong")).generate(PROMPT, &options).await.unwrap_err();
+    // The upstream rejecting our key is our fault, not the caller's
+    assert!(matches!(&err, RuntimeError::Backend(message) if message.contains("401") && message.contains("bad key")), "{:?}", err);
+}
+
+#[tokio::test]
+async fn completions_send_the_rendered_prompt() {
+    // A /v1 root is taken as is
+    let remote = runtime(&format!("{}/v1/", serve().await), RemoteApi::Completions, None);
+    let mut options = GenerationOptions::from_request(Some(16), None, None);
+    options.stop = vec!["###".to_string()];
+    let text = remote.generate("Once upon", &options).await.unwrap();
+    assert_eq!(text, "\"served-name\"|\"Once upon\"|[\"###\"]");
+}
+
+#[tokio::test]
+async fn embeddings_come_back_in_input_order() {
+    let remote = runtime(&serve().await, RemoteApi::Chat, None);
+    let embeddings = remote.embed(&["a".to_string(), "abc".to_string()]).await.unwrap();
+    assert_eq!(embeddings, vec![vec![1.0, 1.0], vec![3.0, 1.0]]);
+}
+
+#[test]
+fn upstream_urls_must_be_http() {
+    assert!(RemoteConfig::new("ftp://example.com", "m", &ModelOptions::default()).is_err());
+    assert!(RemoteConfig::new("not a url", "m", &ModelOptions::default()).is_err());
+    let config = RemoteConfig::new("https://api.example.com", "m", &ModelOptions::default()).unwrap();
+    assert_eq!(config.base_url, "https://api.example.com/v1");
+    assert_eq!(config.model, "m");
+}
+
+#[test]
+fn upstream_keys_come_only_from_remote_key_variables() {
+    let named = |env: &str| ModelOptions { upstream_api_key_env: Some(env.to_string()), ..Default::default() };
+    for env in ["DATABASE_PASSWORD", "API_KEYS", "REMOTE_KEY_", "remote_key_openai"] {
+        assert!(RemoteConfig::new("https://api.example.com", "m", &named(env)).is_err(), "{}", env);
+    }
+    assert!(RemoteConfig::new("https://api.example.com", "m", &named("REMOTE_KEY_OPENAI")).is_ok());
+}
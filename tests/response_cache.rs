@@ -0,0 +1,70 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{api::routes::chat_completions, engine::CoreEngine};
+
+async fn chat(app: &Router, body: &Value, cache_control: Option<&str>) -> (StatusCode, String, Value) {
+    let mut builder = Request::builder().method("POST").uri("/v1/chat/completions").header("content-type", "application/json");
+    if let Some(cache_control) = cache_control {
+        builder = builder.header("cache-control", cache_control);
+    }
+    let response = app.clone().oneshot(builder.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let cache = response.headers().get("x-cache").map(|v| v.to_str().unwrap().to_string()).unwrap_or_default();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, cache, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn clients_can_bypass_the_response_cache() {
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(Arc::new(CoreEngine::new()));
+    let content = uuid::Uuid::new_v4().to_string();
+    let body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": content}], "max_tokens": 8});
+
+    let (status, cache, first) = chat(&app, &body, None).await;
+    assert_eq!((status, cache.as_str()), (StatusCode::OK, "MISS"));
+    let (_, cache, cached) = chat(&app, &body, None).await;
+    assert_eq!(cache, "HIT");
+    assert_eq!(cached["id"], first["id"]);
+    // Nothing about the cache leaks into the body
+    assert!(cached.get("cache").is_none());
+
+    // Opting out generates anew, by header or by body flag
+    let (_, cache, fresh) = chat(&app, &body, Some("no-cache")).await;
+    assert_eq!(cache, "BYPASS");
+    assert_ne!(fresh["id"], first["id"]);
+    let mut uncached = body.clone();
+    uncached["cache"] = json!(false);
+    let (_, cache, fresh) = chat(&app, &uncached, Some("max-age=0")).await;
+    assert_eq!(cache, "BYPASS");
+    assert_ne!(fresh["id"], first["id"]);
+
+    // ...and a bypassed response is not stored
+    let content = uuid::Uuid::new_v4().to_string();
+    let body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": content}], "max_tokens": 8});
+    assert_eq!(chat(&app, &body, Some("no-store")).await.1, "BYPASS");
+    assert_eq!(chat(&app, &body, None).await.1, "MISS");
+}
+
+#[tokio::test]
+async fn models_loaded_without_the_response_cache_always_generate() {
+    let engine = Arc::new(CoreEngine::new());
+    let options = serde_json::from_value(json!({"response_cache": false})).unwrap();
+    engine.load_model("llm", "uncached", None, &options).await.unwrap();
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine);
+    let content = uuid::Uuid::new_v4().to_string();
+    let body = json!({"model": "uncached", "messages": [{"role": "user", "content": content}], "max_tokens": 8});
+
+    let (status, cache, first) = chat(&app, &body, None).await;
+    assert_eq!((status, cache.as_str()), (StatusCode::OK, "BYPASS"));
+    let (_, cache, second) = chat(&app, &body, None).await;
+    assert_eq!(cache, "BYPASS");
+    assert_ne!(second["id"], first["id"]);
+}
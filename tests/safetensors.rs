@@ -0,0 +1,106 @@
+use axum::{routing::post, Router};
+use axum::http::{Request, StatusCode};
+use axum::body::Body;
+use tower::util::ServiceExt; // for `oneshot`
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use llm_serving::{api::routes::admin_models_inspect, runtime::safetensors::{inspect, read_header}};
+
+// A safetensors file with the given header and `data` bytes of tensor data
+fn write(path: &Path, header: Value, data: usize) {
+    let header = header.to_string();
+    let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.resize(bytes.len() + data, 0);
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("safetensors-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn sharded_checkpoints_are_summarised_per_dtype() {
+    let dir = temp_dir();
+    write(&dir.join("model-00001-of-00002.safetensors"), json!({
+        "__metadata__": {"format": "pt"},
+        "embed.weight": {"dtype": "BF16", "shape": [4, 2], "data_offsets": [0, 16]},
+        "norm.weight": {"dtype": "F32", "shape": [2], "data_offsets": [16, 24]},
+    }), 24);
+    write(&dir.join("model-00002-of-00002.safetensors"), json!({
+        "lm_head.weight": {"dtype": "BF16", "shape": [2, 4], "data_offsets": [0, 16]},
+    }), 16);
+    std::fs::write(dir.join("model.safetensors.index.json"), json!({"weight_map": {
+        "embed.weight": "model-00001-of-00002.safetensors",
+        "lm_head.weight": "model-00002-of-00002.safetensors",
+    }}).to_string()).unwrap();
+
+    let inspection = inspect(dir.to_str().unwrap()).unwrap();
+    assert_eq!(inspection.parameters, 18);
+    assert_eq!(inspection.dtypes["BF16"], 16);
+    assert_eq!(inspection.dtypes["F32"], 2);
+    assert_eq!(inspection.files[0].metadata["format"], "pt");
+    let norm = inspection.tensors.iter().find(|t| t.name == "norm.weight").unwrap();
+    assert_eq!((norm.dtype.as_str(), norm.shape.as_slice(), norm.file.as_str()), ("F32", &[2][..], "model-00001-of-00002.safetensors"));
+
+    std::fs::remove_file(dir.join("model-00002-of-00002.safetensors")).unwrap();
+    let err = inspect(dir.to_str().unwrap()).unwrap_err();
+    assert!(err.contains("missing"), "{}", err);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn corrupt_headers_are_rejected() {
+    let dir = temp_dir();
+    let file = dir.join("model.safetensors");
+    let check = |header: Value, data: usize| {
+        write(&file, header, data);
+        read_header(&file).unwrap_err()
+    };
+    let tensor = |dtype: &str, shape: Value, offsets: Value| json!({"w": {"dtype": dtype, "shape": shape, "data_offsets": offsets}});
+
+    assert!(check(tensor("F32", json!([2]), json!([0, 8])), 4).contains("truncated"));
+    assert!(check(tensor("F32", json!([3]), json!([0, 8])), 8).contains("bytes for a F32"));
+    assert!(check(tensor("F12", json!([2]), json!([0, 8])), 8).contains("unknown dtype"));
+    assert!(check(tensor("F32", json!([2]), json!([4, 12])), 12).contains("starts at byte 4"));
+    let overlapping = json!({
+        "a": {"dtype": "U8", "shape": [4], "data_offsets": [0, 4]},
+        "b": {"dtype": "U8", "shape": [4], "data_offsets": [2, 6]},
+    });
+    assert!(check(overlapping, 6).contains("expected 4"));
+
+    std::fs::write(&file, u64::MAX.to_le_bytes()).unwrap();
+    assert!(read_header(&file).unwrap_err().contains("header length"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn admin_can_inspect_weights() {
+    let dir = temp_dir();
+    let file = dir.join("model.safetensors");
+    write(&file, json!({"w": {"dtype": "F16", "shape": [3, 2], "data_offsets": [0, 12]}}), 12);
+    let app = Router::new().route("/admin/models/inspect", post(admin_models_inspect));
+    let inspect = |path: &Path| {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/admin/models/inspect")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({"path": path}).to_string()))
+            .unwrap();
+        app.clone().oneshot(req)
+    };
+
+    let resp = inspect(&file).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(v["files"][0]["file"], "model.safetensors");
+    assert_eq!(v["tensors"][0], json!({"name": "w", "dtype": "F16", "shape": [3, 2], "file": "model.safetensors"}));
+    assert_eq!(v["dtypes"], json!({"F16": 6}));
+
+    assert_eq!(inspect(&dir.join("missing.safetensors")).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use llm_serving::{
+    api::dto::{ChatCompletionMessage, ChatMessageContent, StrikeAction},
+    engine::safety::{SafetyLedger, SafetyPolicy},
+};
+
+fn policy(state_path: Option<String>) -> SafetyPolicy {
+    SafetyPolicy {
+        blocklist: SafetyPolicy::blocklist_regex(&["forbidden".to_string()]),
+        warn_strikes: 1,
+        throttle_strikes: 2,
+        block_strikes: 3,
+        throttle_interval: Duration::from_secs(60),
+        state_path,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn blocklist_matches_whole_words_case_insensitively() {
+    let policy = policy(None);
+    let says = |text: &str| vec![ChatCompletionMessage { role: "user".to_string(), content: ChatMessageContent::Text(text.to_string()) }];
+    assert_eq!(policy.violation(&says("this is FORBIDDEN talk")), Some("forbidden".to_string()));
+    assert_eq!(policy.violation(&says("unforbiddenness is fine")), None);
+    assert_eq!(SafetyPolicy::default().violation(&says("forbidden")), None);
+}
+
+#[test]
+fn strikes_escalate_from_warn_to_throttle_to_block() {
+    let ledger = SafetyLedger::new(policy(None));
+    assert!(ledger.admit("key-a").is_ok());
+
+    assert_eq!(ledger.record_violation("key-a"), (1, StrikeAction::Warn));
+    assert!(ledger.admit("key-a").is_ok());
+
+    assert_eq!(ledger.record_violation("key-a"), (2, StrikeAction::Throttle));
+    // The previous admitted request starts the throttle interval
+    assert!(ledger.admit("key-a").unwrap_err().contains("Throttled"));
+
+    assert_eq!(ledger.record_violation("key-a"), (3, StrikeAction::Block));
+    assert!(ledger.admit("key-a").unwrap_err().contains("suspended"));
+    // Other subjects are unaffected
+    assert!(ledger.admit("key-b").is_ok());
+
+    let report = ledger.snapshot();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].subject, "key-a");
+    assert_eq!(report[0].action, StrikeAction::Block);
+
+    assert!(ledger.pardon("key-a"));
+    assert!(ledger.admit("key-a").is_ok());
+    assert!(ledger.snapshot().is_empty());
+}
+
+#[test]
+fn strikes_survive_a_restart_when_persisted() {
+    let path = std::env::temp_dir().join(format!("safety-{}.json", std::process::id()));
+    let path_str = path.to_string_lossy().to_string();
+
+    let ledger = SafetyLedger::new(policy(Some(path_str.clone())));
+    ledger.record_violation("key-a/user-1");
+    ledger.record_violation("key-a/user-1");
+    drop(ledger);
+
+    let restored = SafetyLedger::new(policy(Some(path_str)));
+    let report = restored.snapshot();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].strikes, 2);
+    assert_eq!(report[0].action, StrikeAction::Throttle);
+    let _ = std::fs::remove_file(path);
+}
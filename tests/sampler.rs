@@ -0,0 +1,52 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use llm_serving::runtime::{sampler::{Mirostat, Sampler}, GenerationOptions};
+
+fn sampler(configure: impl FnOnce(&mut GenerationOptions)) -> Sampler {
+    let mut options = GenerationOptions::from_request(None, None, None);
+    configure(&mut options);
+    Sampler::new(&options, StdRng::seed_from_u64(7))
+}
+
+#[test]
+fn truncation_keeps_only_likely_tokens() {
+    let logits = [1.0, 3.0, 2.9, 0.5];
+    let mut greedy = sampler(|o| o.temperature = 0.0);
+    assert_eq!(greedy.sample(&logits), Some(1));
+
+    let mut top_k = sampler(|o| o.top_k = 2);
+    let mut min_p = sampler(|o| o.min_p = 0.5);
+    for _ in 0..50 {
+        assert!(matches!(top_k.sample(&logits), Some(1 | 2)));
+        assert!(matches!(min_p.sample(&logits), Some(1 | 2)));
+    }
+    let mut narrow = sampler(|o| o.top_p = 0.01);
+    assert_eq!(narrow.sample(&logits), Some(1));
+    assert_eq!(greedy.sample(&[]), None);
+}
+
+#[test]
+fn repetition_penalty_discourages_recent_tokens() {
+    let logits = [2.0, 1.9];
+    let mut plain = sampler(|o| o.temperature = 0.0);
+    assert_eq!(plain.sample(&logits), Some(0));
+    assert_eq!(plain.sample(&logits), Some(0));
+
+    let mut penalized = sampler(|o| {
+        o.temperature = 0.0;
+        o.repetition_penalty = 1.5;
+    });
+    assert_eq!(penalized.sample(&logits), Some(0));
+    assert_eq!(penalized.sample(&logits), Some(1));
+}
+
+#[test]
+fn mirostat_with_a_low_target_surprise_stays_on_the_likeliest_token() {
+    let logits = [0.5, 2.0, 1.0, 1.5];
+    for version in [1, 2] {
+        let mut mirostat = sampler(|o| o.mirostat = Some(Mirostat { version, tau: 0.1, eta: 0.1 }));
+        for _ in 0..20 {
+            assert_eq!(mirostat.sample(&logits), Some(1), "mirostat v{}", version);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use axum::{routing::{get, post}, Router};
+use axum::http::{Request, StatusCode};
+use axum::body::Body;
+use tower::util::ServiceExt; // for `oneshot`
+use serde_json::{json, Value};
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use llm_serving::{
+    api::{auth::priority, dto::Priority, error::AppError, routes::{admin_usage, chat_completions}},
+    engine::{scheduler::{Backlog, CostModel, FairQueue, KeyConcurrency, PriorityQueue, QueueLimits}, CoreEngine, EngineError},
+};
+
+#[test]
+fn cheap_requests_are_not_stuck_behind_an_expensive_one() {
+    let mut queue = FairQueue::default();
+    queue.push("bulk", 4000.0, "bulk-1");
+    queue.push("bulk", 4000.0, "bulk-2");
+    for i in 0..10 {
+        queue.push("chatty", 10.0, if i == 0 { "chatty-first" } else { "chatty" });
+    }
+
+    let order: Vec<&str> = std::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(order[0], "chatty-first");
+    // All ten small requests (100 tokens total) are served before the first 4k request
+    assert!(order[..10].iter().all(|r| r.starts_with("chatty")));
+    assert_eq!(&order[10..], &["bulk-1", "bulk-2"]);
+}
+
+#[test]
+fn passing_over_a_busy_client_keeps_its_order() {
+    let mut queue = FairQueue::default();
+    for i in 0..10_000 {
+        queue.push("busy", 1.0, format!("busy-{}", i));
+    }
+    queue.push("quiet", 5000.0, "quiet".to_string());
+    // Each client is listed once, however much it has queued
+    let mut clients: Vec<&str> = queue.clients().collect();
+    clients.sort();
+    assert_eq!(clients, ["busy", "quiet"]);
+
+    assert_eq!(queue.pop_first(|client| client != "busy").as_deref(), Some("quiet"));
+    assert_eq!(queue.pop_first(|client| client != "busy"), None);
+    assert_eq!(queue.len(), 10_000);
+    let order: Vec<String> = std::iter::from_fn(|| queue.pop()).collect();
+    assert!(order.iter().enumerate().all(|(i, item)| *item == format!("busy-{}", i)));
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn higher_priorities_are_served_first() {
+    let mut queue = PriorityQueue::default();
+    queue.push(Priority::Low, "batch", 1.0, "low");
+    queue.push(Priority::Normal, "web", 4000.0, "normal");
+    queue.push(Priority::High, "bulk", 4000.0, "high-1");
+    queue.push(Priority::High, "chatty", 10.0, "high-2");
+    assert_eq!(queue.len(), 4);
+
+    // Fair between clients within a priority, strict between priorities
+    let order: Vec<&str> = std::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(order, ["high-2", "high-1", "normal", "low"]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn waiting_requests_move_up_a_priority_at_a_time() {
+    let aging = Duration::from_secs(30);
+    let mut queue = PriorityQueue::default();
+    queue.push(Priority::Low, "batch", 1.0, "low");
+    queue.push(Priority::Normal, "web", 1.0, "normal");
+    let start = Instant::now();
+    queue.push(Priority::High, "chatty", 1.0, "high-1");
+
+    // Not waited long enough yet
+    queue.age(start + aging / 2, aging);
+    assert_eq!(queue.pop(), Some("high-1"));
+
+    // One interval in, the normal request joins the high ones and the low one the normal ones
+    let later = start + aging;
+    queue.age(later, aging);
+    queue.push(Priority::High, "chatty", 1.0, "high-2");
+    assert_eq!(queue.pop(), Some("normal"));
+    assert_eq!(queue.pop(), Some("high-2"));
+    assert_eq!(queue.len(), 1);
+
+    // Another interval and the low request reaches the top, ahead of anything new
+    queue.age(later + aging, aging);
+    queue.push(Priority::Normal, "web", 1.0, "normal-2");
+    assert_eq!(queue.pop(), Some("low"));
+    assert_eq!(queue.pop(), Some("normal-2"));
+}
+
+#[test]
+fn a_key_holding_its_share_of_workers_waits_for_the_others() {
+    let concurrency = Arc::new(KeyConcurrency::new(4));
+    let mut queue = PriorityQueue::default();
+    let mut running = Vec::new();
+    for i in 0..6 {
+        queue.push(Priority::Normal, "chatty", 1.0, format!("chatty-{}", i));
+    }
+    // Alone, the chatty key may take every worker
+    for _ in 0..3 {
+        let share = concurrency.fair_share(queue.clients());
+        assert_eq!(share, 4);
+        queue.pop_fair(|client| concurrency.running(client) < share).unwrap();
+        running.push(concurrency.start("chatty"));
+    }
+
+    // Once another key queues up, the two split the workers and the cheaper, earlier chatty
+    // requests no longer go first
+    queue.push(Priority::Normal, "quiet", 100.0, "quiet-0".to_string());
+    queue.push(Priority::Normal, "quiet", 100.0, "quiet-1".to_string());
+    let share = concurrency.fair_share(queue.clients());
+    assert_eq!(share, 2);
+    assert_eq!(queue.pop_fair(|client| concurrency.running(client) < share).unwrap(), "quiet-0");
+    running.push(concurrency.start("quiet"));
+    assert_eq!(queue.pop_fair(|client| concurrency.running(client) < share).unwrap(), "quiet-1");
+    let quiet = concurrency.start("quiet");
+    assert_eq!(concurrency.running("quiet"), 2);
+
+    // With nobody under their share, the queue stays work-conserving
+    assert_eq!(queue.pop_fair(|client| concurrency.running(client) < share).unwrap(), "chatty-3");
+    drop(quiet);
+    assert_eq!(concurrency.running("quiet"), 1);
+    drop(running);
+    assert_eq!(concurrency.running("chatty"), 0);
+}
+
+#[test]
+fn a_full_queue_turns_requests_away_with_retry_after() {
+    use axum::response::IntoResponse;
+
+    let backlog = Arc::new(Backlog::default());
+    let limits = QueueLimits { max_depth: Some(3), max_per_key: Some(2), max_wait: None };
+    let first = backlog.admit("chatty", limits).unwrap();
+    let _second = backlog.admit("chatty", limits).unwrap();
+    // The key's own share is full: 429
+    let err = backlog.admit("chatty", limits).err().unwrap();
+    assert!(matches!(err, EngineError::QuotaExceeded(_)), "{:?}", err);
+    let response = AppError::from(err).into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "1");
+
+    // The whole queue is full: 503
+    let _quiet = backlog.admit("quiet", limits).unwrap();
+    let err = backlog.admit("other", limits).err().unwrap();
+    assert!(matches!(err, EngineError::Overloaded(_)), "{:?}", err);
+    let response = AppError::from(err).into_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.headers().contains_key("retry-after"));
+    assert_eq!(backlog.len(), 3);
+
+    // Dispatching a request makes room again, once
+    first.release();
+    first.release();
+    let _other = backlog.admit("other", limits).unwrap();
+    assert_eq!(backlog.snapshot()["chatty"], 1);
+    drop(first);
+    assert_eq!(backlog.len(), 3);
+    // Other engine errors keep their own status
+    let response = AppError::from(EngineError::InvalidInput("bad input".to_string())).into_response();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!response.headers().contains_key("retry-after"));
+}
+
+#[test]
+fn requests_cannot_ask_for_more_than_their_key_allows() {
+    // Only this test reads the setting
+    llm_serving::config::set_overrides(vec![("API_KEY_PRIORITIES".to_string(), "interactive=high, background=low".to_string())]);
+    let key = |key: Option<&str>| {
+        let mut headers = axum::http::HeaderMap::new();
+        if let Some(key) = key {
+            headers.insert("authorization", format!("Bearer {}", key).parse().unwrap());
+        }
+        headers
+    };
+    assert_eq!(priority(&key(Some("interactive")), None), Priority::High);
+    assert_eq!(priority(&key(Some("interactive")), Some(Priority::Low)), Priority::Low);
+    assert_eq!(priority(&key(Some("background")), Some(Priority::High)), Priority::Low);
+    assert_eq!(priority(&key(Some("unlisted")), Some(Priority::High)), Priority::Normal);
+    assert_eq!(priority(&key(None), None), Priority::Normal);
+}
+
+#[test]
+fn cost_model_learns_completion_length_per_model() {
+    let model = CostModel::default();
+    assert_eq!(model.estimate_chat("m", Some(4096), 0), 4096.0);
+    model.observe_completion("m", 50);
+    // History lowers the prediction, but never above the request's own cap
+    assert_eq!(model.estimate_chat("m", Some(4096), 0), 50.0);
+    assert_eq!(model.estimate_chat("m", Some(10), 0), 10.0);
+}
+
+#[tokio::test]
+async fn admin_usage_reports_cost_per_key() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/usage", get(admin_usage))
+        .with_state(engine);
+
+    let payload = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hello"}], "max_tokens": 20});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+    let request = Request::builder().uri("/admin/usage").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(v["cost_model"]["default_max_tokens"], 100);
+    let anonymous = &v["data"][0];
+    assert_eq!(anonymous["client"], "anonymous");
+    assert_eq!(anonymous["requests"], 1);
+    assert!(anonymous["estimated_cost"].as_f64().unwrap() >= 20.0);
+    assert!(anonymous["actual_cost"].as_f64().unwrap() > 0.0);
+    assert_eq!((&anonymous["queued"], &anonymous["running"]), (&json!(0), &json!(0)));
+    assert!(v["cost_model"]["avg_completion_tokens"]["dummy-model"].as_f64().is_some());
+}
+
+#[tokio::test]
+async fn timed_out_generations_are_still_charged() {
+    // Upstream that accepts the request and never answers
+    let upstream = Router::new().route("/v1/chat/completions", post(std::future::pending::<String>));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+    let engine = Arc::new(CoreEngine::new());
+    engine.load_model("llm", "stuck", Some(&url), &Default::default()).await.unwrap();
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/usage", get(admin_usage))
+        .with_state(engine);
+
+    let payload = json!({"model": "stuck", "messages": [{"role": "user", "content": "hello there"}], "timeout_ms": 50});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::GATEWAY_TIMEOUT);
+
+    let request = Request::builder().uri("/admin/usage").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert!(v["data"][0]["actual_cost"].as_f64().unwrap() > 0.0);
+}
@@ -0,0 +1,68 @@
+use axum::{
+    http::StatusCode,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+use llm_serving::{
+    api::{
+        app::routers,
+        auth::require_admin,
+        routes::{admin_keys_create, admin_models_list, chat_completions, embeddings},
+    },
+    config::set_overrides,
+    engine::CoreEngine,
+    storage::MemoryStorage,
+};
+use metrics_exporter_prometheus::PrometheusBuilder;
+
+mod common;
+use common::send_as;
+
+#[tokio::test]
+async fn admin_routes_need_an_admin_key_once_there_are_any() {
+    set_overrides(vec![("API_KEYS".to_string(), "app".to_string())]);
+    let engine = Arc::new(CoreEngine::with_storage(Arc::new(MemoryStorage::default())));
+    // Like the server's, one layer guards every admin route
+    let admin = Router::new()
+        .route("/admin/models", get(admin_models_list))
+        .route("/admin/keys", post(admin_keys_create))
+        .route_layer(middleware::from_fn_with_state(engine.clone(), require_admin));
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .merge(admin)
+        .with_state(engine.clone());
+    let chat = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+
+    // Without admin keys, API_KEYS stays the one list for everything
+    assert_eq!(send_as(&app, "app", "GET", "/admin/models", None).await.0, StatusCode::OK);
+    let (status, created) = send_as(&app, "app", "POST", "/admin/keys", Some(json!({"name": "ops", "scopes": ["admin"]}))).await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+
+    // A stored admin key takes admin away from API_KEYS
+    assert_eq!(send_as(&app, "app", "GET", "/admin/models", None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send_as(&app, created["key"].as_str().unwrap(), "GET", "/admin/models", None).await.0, StatusCode::OK);
+
+    set_overrides(vec![("ADMIN_API_KEYS".to_string(), "ops".to_string())]);
+    let (status, body) = send_as(&app, "app", "GET", "/admin/models", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!((body["error"]["type"].as_str(), body["error"]["code"].as_str()), (Some("permission_error"), Some("insufficient_scope")));
+    assert!(body["error"]["message"].as_str().unwrap().contains("\"admin\""), "{}", body);
+    assert_eq!(send_as(&app, "app", "POST", "/v1/chat/completions", Some(chat.clone())).await.0, StatusCode::OK);
+    assert_eq!(send_as(&app, "app", "POST", "/v1/embeddings", Some(json!({"model": "dummy-embedding", "input": "hi"}))).await.0, StatusCode::OK);
+
+    // Admin keys hold every scope; unknown keys are still 401
+    assert_eq!(send_as(&app, "ops", "GET", "/admin/models", None).await.0, StatusCode::OK);
+    assert_eq!(send_as(&app, "ops", "POST", "/v1/chat/completions", Some(chat)).await.0, StatusCode::OK);
+    assert_eq!(send_as(&app, "other", "GET", "/admin/models", None).await.0, StatusCode::UNAUTHORIZED);
+
+    // The server's own router puts the metrics scrape behind the admin scope too
+    let (public, _) = routers(engine, PrometheusBuilder::new().build_recorder().handle(), false);
+    assert_eq!(send_as(&public, "app", "GET", "/admin/metrics", None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send_as(&public, "other", "GET", "/admin/metrics", None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send_as(&public, "ops", "GET", "/admin/metrics", None).await.0, StatusCode::OK);
+}
@@ -0,0 +1,27 @@
+use llm_serving::runtime::pool::Pool;
+
+#[test]
+fn leased_items_return_to_the_pool() {
+    let pool = Pool::new(2);
+    assert_eq!(pool.take(), None::<String>);
+    {
+        let mut leased = pool.lease("fresh".to_string());
+        leased.push_str(" and used");
+    }
+    assert_eq!(pool.idle(), 1);
+    assert_eq!(pool.take().as_deref(), Some("fresh and used"));
+    assert_eq!(pool.idle(), 0);
+}
+
+#[test]
+fn pool_keeps_at_most_its_capacity() {
+    let pool = Pool::new(2);
+    let leases: Vec<_> = (0..3).map(|i| pool.lease(i)).collect();
+    drop(leases);
+    assert_eq!(pool.idle(), 2);
+
+    let disabled = Pool::new(0);
+    disabled.put(1);
+    assert_eq!(disabled.take(), None);
+}
+
@@ -0,0 +1,105 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{chat_completions, embeddings},
+    engine::CoreEngine,
+};
+
+// The dummy model echoes the prompt a word at a time
+fn chat(stream: bool, words: usize) -> Request<Body> {
+    // Distinct prompts, so no answer comes from the response cache
+    let content = format!("{}{}", uuid::Uuid::new_v4(), " word".repeat(words));
+    let body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": content}], "max_tokens": 8192, "stream": stream});
+    Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn shutdown_lets_running_requests_finish_and_turns_new_ones_away() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine.clone());
+
+    let running = tokio::spawn(app.clone().oneshot(chat(false, 200)));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let shutdown = tokio::spawn({
+        let engine = engine.clone();
+        async move { engine.shutdown(Duration::from_secs(10)).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(engine.is_draining());
+
+    let refused = app.clone().oneshot(chat(false, 2)).await.unwrap();
+    assert_eq!(refused.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let bytes = axum::body::to_bytes(refused.into_body(), usize::MAX).await.unwrap();
+    let v: Value = serde_json::from_slice(&bytes).unwrap();
+    assert!(v["error"]["message"].as_str().unwrap().starts_with("Server is shutting down"), "{}", v);
+
+    assert_eq!(running.await.unwrap().unwrap().status(), StatusCode::OK);
+    assert!(shutdown.await.unwrap());
+    let models = engine.list_models().await;
+    assert!(models.llm.is_empty() && models.embedding.is_empty(), "{:?}", models.llm);
+}
+
+#[tokio::test]
+async fn generations_still_running_at_the_drain_timeout_are_cancelled() {
+    let engine = Arc::new(CoreEngine::new());
+    let app = Router::new().route("/v1/chat/completions", post(chat_completions)).with_state(engine.clone());
+
+    // A stream nobody reads never finishes on its own
+    let stalled = app.clone().oneshot(chat(true, 1000)).await.unwrap();
+    assert_eq!(stalled.status(), StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let drained = tokio::time::timeout(Duration::from_secs(5), engine.shutdown(Duration::from_millis(200))).await.unwrap();
+    assert!(!drained);
+    // The cancelled stream ends rather than hanging
+    let body = tokio::time::timeout(Duration::from_secs(5), axum::body::to_bytes(stalled.into_body(), usize::MAX)).await.unwrap();
+    assert!(body.is_ok());
+}
+
+#[tokio::test]
+async fn an_idle_engine_shuts_down_at_once() {
+    let engine = CoreEngine::new();
+    let started = std::time::Instant::now();
+    assert!(engine.shutdown(Duration::from_secs(10)).await);
+    assert!(started.elapsed() < Duration::from_millis(20), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn requests_other_than_generations_are_cancelled_at_the_drain_timeout_too() {
+    // An embeddings upstream that never answers
+    let upstream = Router::new().route("/v1/embeddings", post(std::future::pending::<()>));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+    let engine = Arc::new(CoreEngine::new());
+    engine.load_model("embedding", "stalled", Some(&url), &Default::default()).await.unwrap();
+    let app = Router::new().route("/v1/embeddings", post(embeddings)).with_state(engine.clone());
+
+    let body = json!({"model": "stalled", "input": "hello"});
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/embeddings")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let stalled = tokio::spawn(app.oneshot(request));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let drained = tokio::time::timeout(Duration::from_secs(5), engine.shutdown(Duration::from_millis(200))).await.unwrap();
+    assert!(!drained);
+    let response = tokio::time::timeout(Duration::from_secs(5), stalled).await.unwrap().unwrap().unwrap();
+    assert!(response.status().is_server_error(), "{}", response.status());
+}
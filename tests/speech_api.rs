@@ -0,0 +1,65 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::post, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{api::routes::audio_speech, engine::CoreEngine};
+
+async fn send(app: &Router, body: Value) -> (StatusCode, Option<String>, Vec<u8>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/audio/speech")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response.headers().get("content-type").map(|v| v.to_str().unwrap().to_string());
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, content_type, bytes.to_vec())
+}
+
+fn app() -> Router {
+    Router::new().route("/v1/audio/speech", post(audio_speech)).with_state(Arc::new(CoreEngine::new()))
+}
+
+#[tokio::test]
+async fn synthesizes_wav_and_raw_pcm() {
+    let app = app();
+    let (status, content_type, body) = send(&app, json!({"model": "dummy-tts", "input": "hello there", "voice": "alloy"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("audio/wav"));
+    let reader = hound::WavReader::new(std::io::Cursor::new(&body)).unwrap();
+    assert_eq!(reader.spec().channels, 1);
+    assert_eq!(reader.spec().sample_rate, 24_000);
+    let wav_samples = reader.len() as usize;
+    assert!(wav_samples > 0);
+
+    // Raw pcm is 16-bit; doubling the speed halves the length
+    let (status, content_type, body) =
+        send(&app, json!({"model": "dummy-tts", "input": "hello there", "voice": "nova", "response_format": "pcm", "speed": 2.0})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type.as_deref(), Some("audio/pcm"));
+    assert_eq!(body.len() / 2, wav_samples / 2);
+}
+
+#[tokio::test]
+async fn rejects_invalid_speech_requests() {
+    let app = app();
+    let cases = [
+        (json!({"model": "dummy-tts", "input": "hi", "voice": "robot"}), "Unknown voice"),
+        (json!({"model": "dummy-tts", "input": "  ", "voice": "alloy"}), "input must not be empty"),
+        (json!({"model": "dummy-tts", "input": "hi", "voice": "alloy", "speed": 5.0}), "speed must be between"),
+        (json!({"model": "dummy-tts", "input": "hi", "voice": "alloy", "response_format": "mp3"}), "mp3 is not supported"),
+    ];
+    for (body, expected) in cases {
+        let (status, _, body) = send(&app, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8(body).unwrap().contains(expected), "expected {}", expected);
+    }
+    let (status, _, body) = send(&app, json!({"model": "missing", "input": "hi", "voice": "alloy"})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(String::from_utf8(body).unwrap().contains("not found"));
+}
@@ -0,0 +1,107 @@
+use axum::{routing::post, Router};
+use axum::http::StatusCode;
+use serde_json::json;
+use std::sync::Arc;
+
+use llm_serving::{
+    api::dto::{CreateSplitRequest, SplitVariant},
+    api::routes::{admin_models_load, admin_splits_create, admin_splits_list, admin_splits_remove, chat_completions},
+    engine::{splits::TrafficSplitter, CoreEngine},
+};
+
+mod common;
+use common::send;
+
+fn app() -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/admin/models/load", post(admin_models_load))
+        .route("/admin/splits", post(admin_splits_create).get(admin_splits_list))
+        .route("/admin/splits/remove", post(admin_splits_remove))
+        .with_state(Arc::new(CoreEngine::new()))
+}
+
+#[tokio::test]
+async fn splits_spread_requests_and_compare_variants() {
+    let app = app();
+    // A variant whose upstream refuses connections fails every request it gets
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    let load = json!({"model": "v2", "kind": "llm", "path": format!("http://127.0.0.1:{}", port)});
+    assert_eq!(send(&app, "POST", "/admin/models/load", Some(load)).await.0, StatusCode::OK);
+
+    let split = json!({"model": "ab", "variants": [{"model": "dummy-model", "percent": 70}, {"model": "v2", "percent": 30}]});
+    let (status, v) = send(&app, "POST", "/admin/splits", Some(split)).await;
+    assert_eq!(status, StatusCode::OK, "{}", v);
+
+    // The same prompt every time: the response cache stays out of the comparison
+    let chat = json!({"model": "ab", "messages": [{"role": "user", "content": "hello"}]});
+    let mut served = 0;
+    for _ in 0..60 {
+        let (status, v) = send(&app, "POST", "/v1/chat/completions", Some(chat.clone())).await;
+        if status == StatusCode::OK {
+            assert_eq!(v["model"], "dummy-model");
+            served += 1;
+        }
+    }
+
+    let (_, v) = send(&app, "GET", "/admin/splits", None).await;
+    let stats = &v["data"][0]["stats"];
+    assert_eq!((stats[0]["model"].as_str(), stats[1]["model"].as_str()), (Some("dummy-model"), Some("v2")));
+    assert_eq!(stats[0]["requests"], served);
+    assert_eq!(stats[0]["errors"], 0);
+    assert_eq!(stats[1]["requests"], 60 - served);
+    assert_eq!(stats[1]["errors"], 60 - served);
+    // Both variants got traffic; each staying idle in 60 requests has odds below 1e-9
+    assert!(served > 0 && served < 60, "{}", served);
+    assert_eq!(stats[1]["error_rate"], 1.0);
+
+    assert_eq!(send(&app, "POST", "/admin/splits/remove", Some(json!({"model": "ab"}))).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "POST", "/admin/splits/remove", Some(json!({"model": "ab"}))).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn splits_need_loaded_variants_adding_up_to_100() {
+    let app = app();
+    for (variants, expected, error) in [
+        (json!([{"model": "dummy-model", "percent": 90}]), StatusCode::BAD_REQUEST, "add up to 90"),
+        (json!([{"model": "dummy-model", "percent": 50}, {"model": "missing", "percent": 50}]), StatusCode::NOT_FOUND, "Model missing not found"),
+        (json!([{"model": "dummy-model", "percent": 50}, {"model": "dummy-model", "percent": 50}]), StatusCode::BAD_REQUEST, "appears twice"),
+        (json!([]), StatusCode::BAD_REQUEST, "at least one variant"),
+    ] {
+        let (status, v) = send(&app, "POST", "/admin/splits", Some(json!({"model": "ab", "variants": variants}))).await;
+        assert_eq!(status, expected);
+        assert!(v["error"]["message"].as_str().unwrap().contains(error), "{}", v);
+    }
+}
+
+#[test]
+fn env_splits_parse() {
+    let splits = TrafficSplitter::parse_env("chat=v1:90, v2:10; other=a:50,b:50").unwrap();
+    assert_eq!(splits.len(), 2);
+    assert_eq!(splits[0].model, "chat");
+    assert_eq!((splits[0].variants[1].model.as_str(), splits[0].variants[1].percent), ("v2", 10.0));
+    assert!(TrafficSplitter::parse_env("chat=v1").is_err());
+    assert!(TrafficSplitter::parse_env("chat=v1:most").is_err());
+}
+
+#[tokio::test]
+async fn concurrent_outcomes_are_all_counted() {
+    let engine = CoreEngine::new();
+    let variants = vec![SplitVariant { model: "dummy-model".to_string(), percent: 100.0 }];
+    engine.create_split(CreateSplitRequest { model: "ab".to_string(), variants }).await.unwrap();
+    let splits = engine.splits();
+    std::thread::scope(|scope| {
+        for t in 0..8 {
+            scope.spawn(move || {
+                for _ in 0..1000 {
+                    splits.record("ab", "dummy-model", t % 2 == 0, 2.0);
+                }
+            });
+        }
+    });
+    let stats = &splits.list()[0].stats[0];
+    assert_eq!((stats.requests, stats.errors), (8000, 4000));
+    assert_eq!((stats.error_rate, stats.avg_latency_ms), (0.5, 2.0));
+}
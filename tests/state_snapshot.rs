@@ -0,0 +1,35 @@
+use serde_json::Value;
+
+use llm_serving::{api::dto::StateSnapshot, config::set_overrides, engine::CoreEngine};
+
+fn snapshot(value: &Value) -> StateSnapshot {
+    serde_json::from_value(value.clone()).unwrap()
+}
+
+#[tokio::test]
+async fn imports_need_the_signature_of_their_own_state() {
+    // The only test in this binary, so nothing else sees the key
+    set_overrides(vec![("STATE_SIGNING_KEY".to_string(), "snapshot-key".to_string())]);
+    let engine = CoreEngine::new();
+    let exported = serde_json::to_value(engine.export_state().await.unwrap()).unwrap();
+    let signature = exported["signature"].as_str().unwrap().to_string();
+    assert_eq!(signature.len(), 64);
+    assert!(engine.import_state(snapshot(&exported)).await.is_ok());
+
+    let mut tampered = exported.clone();
+    tampered["state"]["quotas"]["requests_per_minute"] = 1_000_000.into();
+    assert_eq!(engine.import_state(snapshot(&tampered)).await.unwrap_err(), "snapshot signature mismatch");
+
+    // One digit off, not hex, cut short or empty
+    let last = signature.chars().last().unwrap();
+    let flipped = format!("{}{}", &signature[..63], if last == '0' { '1' } else { '0' });
+    for forged in [flipped, "z".repeat(64), signature[..62].to_string(), String::new()] {
+        let mut forged_snapshot = exported.clone();
+        forged_snapshot["signature"] = forged.clone().into();
+        assert_eq!(engine.import_state(snapshot(&forged_snapshot)).await.unwrap_err(), "snapshot signature mismatch", "{}", forged);
+    }
+
+    let mut unsigned = exported.clone();
+    unsigned.as_object_mut().unwrap().remove("signature");
+    assert_eq!(engine.import_state(snapshot(&unsigned)).await.unwrap_err(), "snapshot is not signed");
+}
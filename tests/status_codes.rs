@@ -0,0 +1,68 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::routes::{chat_completions, embeddings, messages},
+    config::set_overrides,
+    engine::CoreEngine,
+};
+
+async fn send(app: &Router, uri: &str, key: Option<&str>, body: Value) -> (StatusCode, Option<String>, Value) {
+    let mut builder = Request::builder().method("POST").uri(uri).header("content-type", "application/json");
+    if let Some(key) = key {
+        builder = builder.header("authorization", format!("Bearer {}", key));
+    }
+    let resp = app.clone().oneshot(builder.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+    let status = resp.status();
+    let challenge = resp.headers().get("www-authenticate").map(|v| v.to_str().unwrap().to_string());
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+    (status, challenge, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn auth_rate_limit_and_missing_model_errors_have_their_own_status() {
+    set_overrides(vec![("API_KEYS".to_string(), "secret".to_string()), ("RATE_LIMIT_PER_MINUTE".to_string(), "3".to_string())]);
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .route("/v1/messages", post(messages))
+        .with_state(Arc::new(CoreEngine::new()));
+    let chat = |model: &str| json!({"model": model, "messages": [{"role": "user", "content": "hi"}]});
+
+    let (status, challenge, body) = send(&app, "/v1/chat/completions", None, chat("dummy-model")).await;
+    assert_eq!((status, challenge.as_deref()), (StatusCode::UNAUTHORIZED, Some("Bearer")));
+    assert_eq!(body, json!({"error": {"message": "Unauthorized", "type": "authentication_error", "param": null, "code": "invalid_api_key"}}));
+    assert_eq!(send(&app, "/v1/chat/completions", Some("wrong"), chat("dummy-model")).await.0, StatusCode::UNAUTHORIZED);
+    let (status, _, body) = send(&app, "/v1/messages", None, json!({"model": "dummy-model", "max_tokens": 8, "messages": [{"role": "user", "content": "hi"}]})).await;
+    assert_eq!((status, body["error"]["type"].as_str()), (StatusCode::UNAUTHORIZED, Some("authentication_error")));
+
+    assert_eq!(send(&app, "/v1/chat/completions", Some("secret"), chat("dummy-model")).await.0, StatusCode::OK);
+    let (status, _, body) = send(&app, "/v1/chat/completions", Some("secret"), chat("missing")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"]["message"].as_str().unwrap().contains("Model missing not found"), "{}", body);
+    assert_eq!((body["error"]["type"].as_str(), body["error"]["code"].as_str()), (Some("invalid_request_error"), Some("model_not_found")));
+    assert_eq!(send(&app, "/v1/embeddings", Some("secret"), json!({"model": "missing", "input": "hi"})).await.0, StatusCode::NOT_FOUND);
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .header("authorization", "Bearer secret")
+        .body(Body::from(chat("dummy-model").to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    // At 3 a minute the next request is let through within 20 seconds, not the queue's default wait
+    let retry_after: u64 = resp.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((2..=20).contains(&retry_after), "{}", retry_after);
+    let body: Value = serde_json::from_slice(&axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["error"]["message"], "Rate limit exceeded");
+    assert_eq!((body["error"]["type"].as_str(), body["error"]["code"].as_str()), (Some("rate_limit_error"), Some("rate_limit_exceeded")));
+}
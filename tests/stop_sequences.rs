@@ -0,0 +1,38 @@
+use llm_serving::runtime::stop::StopMatcher;
+
+fn stops(list: &[&str]) -> StopMatcher {
+    StopMatcher::new(&list.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn stops_at_a_sequence_split_across_pieces() {
+    let mut matcher = stops(&["\nUser:", "###"]);
+    assert_eq!(matcher.push("The answer is 4."), "The answer is 4.");
+    // Could be the start of "\nUser:", so it waits
+    assert_eq!(matcher.push("\nUs"), "");
+    assert_eq!(matcher.push("er: and"), "");
+    assert!(matcher.is_stopped());
+    assert_eq!(matcher.push("more"), "");
+    assert_eq!(matcher.finish(), "");
+}
+
+#[test]
+fn releases_held_text_that_turns_out_not_to_stop() {
+    let mut matcher = stops(&["</answer>"]);
+    assert_eq!(matcher.push("a </"), "a ");
+    assert_eq!(matcher.push("b> c"), "</b> c");
+    assert_eq!(matcher.push(" </ans"), " ");
+    assert!(!matcher.is_stopped());
+    // Generation ended without completing the stop sequence
+    assert_eq!(matcher.finish(), "</ans");
+}
+
+#[test]
+fn earliest_sequence_wins_and_no_sequences_pass_everything() {
+    let mut matcher = stops(&["world", "lo"]);
+    assert_eq!(matcher.push("hello world"), "hel");
+
+    let mut matcher = stops(&[]);
+    assert_eq!(matcher.push("héllo"), "héllo");
+    assert_eq!(matcher.finish(), "");
+}
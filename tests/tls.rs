@@ -0,0 +1,200 @@
+use axum::{http::HeaderMap, routing::get, Router};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio_util::sync::CancellationToken;
+
+use llm_serving::{
+    api::auth::{authorize_request, client_id, AuthError, Scope, CLIENT_PRINCIPAL_HEADER},
+    config::{clear_overrides, set_overrides},
+    engine::keys::KeyStore,
+    storage::MemoryStorage,
+    tls::{self, CertStore, ClientCaStore},
+};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+// Writes `contents` to `path` with a modification time `age` ago, so every write is seen as
+// a change however quickly they follow each other
+fn write(path: &Path, contents: &[u8], age: u64) {
+    std::fs::write(path, contents).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+}
+
+// Whether a client trusting only the certificate in `fixture` reaches the server
+async fn trusted_by(fixture_name: &str, addr: std::net::SocketAddr) -> bool {
+    let pem = std::fs::read(fixture(fixture_name)).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&pem).unwrap())
+        .tls_built_in_root_certs(false)
+        .resolve("localhost", addr)
+        .build()
+        .unwrap();
+    match client.get(format!("https://localhost:{}/health", addr.port())).send().await {
+        Ok(resp) => resp.text().await.unwrap() == "ok",
+        Err(_) => false,
+    }
+}
+
+#[tokio::test]
+async fn https_serves_the_certificate_on_disk_and_picks_up_renewals() {
+    let dir = std::env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    write(&cert, &std::fs::read(fixture("tls_a.crt")).unwrap(), 30);
+    write(&key, &std::fs::read(fixture("tls_a.key")).unwrap(), 30);
+
+    let store = CertStore::load(&cert, &key).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/health", get(|| async { "ok" }));
+    let stopping = CancellationToken::new();
+    let server = tokio::spawn(tls::serve(listener, app, store.server_config(None), stopping.clone().cancelled_owned()));
+
+    assert!(trusted_by("tls_a.crt", addr).await);
+    assert!(!trusted_by("tls_b.crt", addr).await);
+    assert!(!store.reload().unwrap());
+
+    // A renewed certificate reaches new connections without a restart
+    write(&cert, &std::fs::read(fixture("tls_b.crt")).unwrap(), 20);
+    write(&key, &std::fs::read(fixture("tls_b.key")).unwrap(), 20);
+    assert!(store.reload().unwrap());
+    assert!(trusted_by("tls_b.crt", addr).await);
+    assert!(!trusted_by("tls_a.crt", addr).await);
+
+    // One that does not load leaves the current one serving
+    write(&key, &std::fs::read(fixture("tls_a.key")).unwrap(), 10);
+    assert!(store.reload().unwrap_err().contains("does not match"));
+    assert!(trusted_by("tls_b.crt", addr).await);
+
+    stopping.cancel();
+    server.await.unwrap().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn certificates_need_a_key() {
+    let err = CertStore::load(&fixture("tls_a.crt"), &fixture("tls_a.crt")).unwrap_err();
+    assert!(err.contains("no private key"), "{}", err);
+    assert!(CertStore::load(&fixture("missing.crt"), &fixture("tls_a.key")).is_err());
+}
+
+// Serves the principal each request arrives with over HTTPS that checks client certificates
+async fn mtls_server(required: bool) -> std::net::SocketAddr {
+    let store = CertStore::load(&fixture("tls_a.crt"), &fixture("tls_a.key")).unwrap();
+    let clients = tls::client_verifier(&fixture("tls_client_ca.crt"), required).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route(
+        "/whoami",
+        get(|headers: HeaderMap| async move { headers.get(CLIENT_PRINCIPAL_HEADER).map_or("none".to_string(), |v| v.to_str().unwrap().to_string()) }),
+    );
+    tokio::spawn(tls::serve(listener, app, store.server_config(Some(clients)), std::future::pending()));
+    addr
+}
+
+// The principal the server saw, or None when the connection was refused
+async fn whoami(addr: std::net::SocketAddr, identity: Option<&str>) -> Option<String> {
+    let pem = std::fs::read(fixture("tls_a.crt")).unwrap();
+    let mut client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&pem).unwrap())
+        .tls_built_in_root_certs(false)
+        .resolve("localhost", addr);
+    if let Some(identity) = identity {
+        let pem = match identity {
+            "tls_a" => [std::fs::read(fixture("tls_a.crt")).unwrap(), std::fs::read(fixture("tls_a.key")).unwrap()].concat(),
+            name => std::fs::read(fixture(name)).unwrap(),
+        };
+        client = client.identity(reqwest::Identity::from_pem(&pem).unwrap());
+    }
+    let resp = client.build().unwrap().get(format!("https://localhost:{}/whoami", addr.port()))
+        .header(CLIENT_PRINCIPAL_HEADER, "spoofed")
+        .send()
+        .await
+        .ok()?;
+    Some(resp.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn client_certificates_name_their_principal() {
+    let addr = mtls_server(true).await;
+    assert_eq!(whoami(addr, Some("tls_client.pem")).await.as_deref(), Some("spiffe://test/router"));
+    // No certificate, or one from another CA, gets no connection
+    assert_eq!(whoami(addr, None).await, None);
+    assert_eq!(whoami(addr, Some("tls_a")).await, None);
+
+    // Optional certificates leave the others to API keys, and nobody names their own principal
+    let addr = mtls_server(false).await;
+    assert_eq!(whoami(addr, None).await.as_deref(), Some("none"));
+    assert_eq!(whoami(addr, Some("tls_client.pem")).await.as_deref(), Some("spiffe://test/router"));
+}
+
+#[tokio::test]
+async fn verified_principals_stand_in_for_api_keys() {
+    let keys = KeyStore::new(Arc::new(MemoryStorage::default()));
+    let mut headers = HeaderMap::new();
+    headers.insert(CLIENT_PRINCIPAL_HEADER, "spiffe://test/router".parse().unwrap());
+    set_overrides(vec![("API_KEYS".to_string(), "secret".to_string())]);
+    // Without client certificate checks the header is anybody's to send
+    assert_eq!(authorize_request(&keys, &headers, Scope::Inference).await.unwrap_err(), AuthError::Unauthorized);
+    assert_eq!(client_id(&headers), "anonymous");
+
+    let ca = fixture("tls_client_ca.crt").display().to_string();
+    set_overrides(vec![("TLS_CLIENT_CA_PATH".to_string(), ca)]);
+    // Certificates hold the inference scope, and admin only when listed
+    assert!(authorize_request(&keys, &headers, Scope::Inference).await.is_ok());
+    assert_eq!(authorize_request(&keys, &headers, Scope::Admin).await.unwrap_err(), AuthError::Forbidden(Scope::Admin));
+    set_overrides(vec![("TLS_ADMIN_PRINCIPALS".to_string(), "spiffe://test/ops, spiffe://test/router".to_string())]);
+    assert!(authorize_request(&keys, &headers, Scope::Admin).await.is_ok());
+    assert_eq!(client_id(&headers), "cert:spiffe://test/router");
+
+    // A key sent along is checked like any other, and names the caller
+    headers.insert("authorization", "Bearer guess".parse().unwrap());
+    assert_eq!(authorize_request(&keys, &headers, Scope::Inference).await.unwrap_err(), AuthError::Unauthorized);
+    headers.insert("authorization", "Bearer secret".parse().unwrap());
+    assert!(authorize_request(&keys, &headers, Scope::Inference).await.is_ok());
+    assert!(client_id(&headers).starts_with("key-"));
+    headers.remove("authorization");
+
+    // Listing principals turns the others away
+    set_overrides(vec![("TLS_PRINCIPALS".to_string(), "spiffe://test/web".to_string()), ("TLS_ADMIN_PRINCIPALS".to_string(), String::new())]);
+    assert_eq!(authorize_request(&keys, &headers, Scope::Inference).await.unwrap_err(), AuthError::Unauthorized);
+    headers.insert(CLIENT_PRINCIPAL_HEADER, "spiffe://test/web".parse().unwrap());
+    assert!(authorize_request(&keys, &headers, Scope::Inference).await.is_ok());
+    clear_overrides();
+}
+
+#[tokio::test]
+async fn client_cas_are_read_again_when_they_change() {
+    let dir = std::env::temp_dir().join(format!("tls-ca-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let bundle = dir.join("clients.pem");
+    write(&bundle, &std::fs::read(fixture("tls_client_ca.crt")).unwrap(), 30);
+
+    let cas = ClientCaStore::load(&bundle, true).unwrap();
+    let store = CertStore::load(&fixture("tls_a.crt"), &fixture("tls_a.key")).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/whoami", get(|headers: HeaderMap| async move { headers[CLIENT_PRINCIPAL_HEADER].to_str().unwrap().to_string() }));
+    tokio::spawn(tls::serve(listener, app, store.server_config(Some(cas.clone())), std::future::pending()));
+    assert_eq!(whoami(addr, Some("tls_client.pem")).await.as_deref(), Some("spiffe://test/router"));
+    assert_eq!(whoami(addr, Some("tls_client_b.pem")).await, None);
+    assert!(!cas.reload().unwrap());
+
+    // Rotated to the other CA, new connections need its certificates
+    write(&bundle, &std::fs::read(fixture("tls_client_ca_b.crt")).unwrap(), 20);
+    assert!(cas.reload().unwrap());
+    assert_eq!(whoami(addr, Some("tls_client_b.pem")).await.as_deref(), Some("spiffe://test/ops"));
+    assert_eq!(whoami(addr, Some("tls_client.pem")).await, None);
+
+    // A bundle that does not load leaves the current CAs in place
+    write(&bundle, b"not a certificate", 10);
+    assert!(cas.reload().is_err());
+    assert_eq!(whoami(addr, Some("tls_client_b.pem")).await.as_deref(), Some("spiffe://test/ops"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use llm_serving::engine::tools::{ToolLoopBudget, ToolLoopLimits};
+
+#[test]
+fn tool_loop_stops_at_the_first_exhausted_limit() {
+    let limits = ToolLoopLimits { max_iterations: 2, max_tool_time: Duration::from_millis(100), max_total_tokens: 1000 };
+
+    let mut budget = ToolLoopBudget::new(limits.clone());
+    assert!(budget.begin_iteration().is_ok());
+    assert!(budget.begin_iteration().is_ok());
+    assert!(budget.begin_iteration().is_err());
+    assert_eq!(budget.stats().iterations, 2);
+
+    let mut budget = ToolLoopBudget::new(limits.clone());
+    assert!(budget.record_tool(Duration::from_millis(60)).is_ok());
+    assert!(budget.record_tool(Duration::from_millis(60)).is_err());
+    assert_eq!(budget.stats().tool_calls, 2);
+
+    let mut budget = ToolLoopBudget::new(limits.tightened(None, Some(500)));
+    assert!(budget.record_tokens(200, 200).is_ok());
+    assert!(budget.record_tokens(50, 100).is_err());
+    assert!(budget.stats().stopped_reason.as_deref().unwrap().contains("token budget of 500"));
+}
@@ -0,0 +1,94 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::{routing::{get, post}, Router};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::util::ServiceExt; // for `oneshot`
+
+use llm_serving::{
+    api::{dto::CreateApiKeyRequest, routes::{admin_usage, chat_completions, embeddings}},
+    engine::{events::EngineEvent, CoreEngine},
+    storage::{MemoryStorage, Storage},
+};
+
+fn router(engine: Arc<CoreEngine>) -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .route("/admin/usage", get(admin_usage))
+        .with_state(engine)
+}
+
+async fn call(app: &Router, method: &str, uri: &str, key: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+    if let Some(key) = key {
+        request = request.header("authorization", format!("Bearer {}", key));
+    }
+    let body = body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty);
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn metered(app: &Router, query: &str) -> Vec<Value> {
+    let (status, usage) = call(app, "GET", &format!("/admin/usage{}", query), None, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", usage);
+    usage["metered"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn usage_is_metered_per_key_and_model_and_persisted() {
+    let storage = Arc::new(MemoryStorage::default());
+    let engine = Arc::new(CoreEngine::with_storage(storage.clone()));
+    let key = |name: &str| engine.keys().create("test", CreateApiKeyRequest { name: name.to_string(), scopes: None });
+    let (alice, bob) = (key("alice").await.unwrap(), key("bob").await.unwrap());
+    let app = router(engine.clone());
+    let mut events = engine.events().subscribe();
+
+    let chat = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hello there"}]});
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", Some(&alice.key), Some(chat.clone())).await.0, StatusCode::OK);
+    // Served from the response cache, and metered as it is answered
+    assert_eq!(call(&app, "POST", "/v1/chat/completions", Some(&alice.key), Some(chat)).await.0, StatusCode::OK);
+    let embedding = json!({"model": "dummy-embedding", "input": "hello"});
+    assert_eq!(call(&app, "POST", "/v1/embeddings", Some(&bob.key), Some(embedding)).await.0, StatusCode::OK);
+
+    // Generated requests are metered once their worker is done, just before it reports them finished
+    let mut finished = 0;
+    while finished < 2 {
+        if let EngineEvent::RequestFinished { .. } = events.recv().await.unwrap().event {
+            finished += 1;
+        }
+    }
+    let all = metered(&app, "").await;
+    assert_eq!(all.len(), 2);
+    assert!(all.iter().any(|row| row["key"] == bob.info.id.as_str() && row["model"] == "dummy-embedding" && row["requests"] == 1 && row["completion_tokens"] == 0));
+
+    // Stored keys are reported by the id /admin/keys lists them under
+    let rows = metered(&app, &format!("?key={}", alice.info.id)).await;
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!((&row["model"], &row["requests"], &row["errors"]), (&json!("dummy-model"), &json!(2), &json!(0)));
+    let (prompt, completion) = (row["prompt_tokens"].as_u64().unwrap(), row["completion_tokens"].as_u64().unwrap());
+    assert!(prompt > 0 && completion > 0, "{}", row);
+    assert_eq!(row["total_tokens"].as_u64().unwrap(), prompt + completion);
+    assert!(row["avg_latency_ms"].as_f64().is_some());
+
+    // Windows cover whole hours
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert_eq!(metered(&app, &format!("?key={}&from={}&to={}", alice.info.id, now - 60, now + 1)).await.len(), 1);
+    assert!(metered(&app, &format!("?from={}", now + 7200)).await.is_empty());
+    assert!(metered(&app, &format!("?to={}", now - 7200)).await.is_empty());
+    assert_eq!(call(&app, "GET", &format!("/admin/usage?from={}&to={}", now, now), None, None).await.0, StatusCode::BAD_REQUEST);
+
+    // Another replica sharing the storage reads the usage once it is written, skipping files it
+    // cannot make sense of
+    let other = router(Arc::new(CoreEngine::with_storage(storage.clone())));
+    assert!(metered(&other, "").await.is_empty());
+    engine.metering().flush().await.unwrap();
+    storage.put(&format!("usage/{}/corrupt.json", now / 3600 * 3600), b"{".to_vec()).await.unwrap();
+    let rows = metered(&other, &format!("?key={}", alice.info.id)).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!((&rows[0]["requests"], &rows[0]["prompt_tokens"], &rows[0]["completion_tokens"]), (&json!(2), &json!(prompt), &json!(completion)));
+    assert_eq!(metered(&app, "").await, metered(&other, "").await);
+}
@@ -0,0 +1,118 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    http::StatusCode,
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use llm_serving::{api::routes::{chat_completions, embeddings, messages}, config::set_overrides, engine::CoreEngine};
+
+mod common;
+use common::{send, send_raw};
+
+fn app() -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/messages", post(messages))
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(Arc::new(CoreEngine::new()))
+        .layer(DefaultBodyLimit::max(4096))
+}
+
+// A chat with "hi", with `extra` merged into the body
+async fn chat(app: &Router, extra: Value) -> (StatusCode, Value) {
+    let mut body = json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}]});
+    body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    send(app, "POST", "/v1/chat/completions", Some(body)).await
+}
+
+#[tokio::test]
+async fn invalid_chat_requests_are_rejected_with_the_field_at_fault() {
+    let app = app();
+    assert_eq!(chat(&app, json!({"temperature": 2.0, "top_p": 1.0, "n": 1, "max_tokens": 16})).await.0, StatusCode::OK);
+
+    let cases = [
+        (json!({"messages": []}), "messages"),
+        (json!({"messages": [{"role": "wizard", "content": "hi"}]}), "messages[0].role"),
+        (json!({"temperature": 2.5}), "temperature"),
+        (json!({"temperature": -0.1}), "temperature"),
+        (json!({"top_p": 0.0}), "top_p"),
+        (json!({"top_p": 1.5}), "top_p"),
+        (json!({"n": 5}), "n"),
+        (json!({"max_tokens": 0}), "max_tokens"),
+        (json!({"max_tokens": 4_000_000}), "max_tokens"),
+    ];
+    for (extra, param) in cases {
+        let (status, body) = chat(&app, extra.clone()).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", extra);
+        assert_eq!(body["error"]["param"], param, "{}", body);
+        assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+    }
+
+    // Malformed JSON and wrong types are 422s too, without a field
+    let (status, body) = send_raw(&app, "/v1/chat/completions", "{\"model\": ").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["error"]["param"].is_null(), "{}", body);
+    let (status, body) = chat(&app, json!({"temperature": "hot"})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["error"]["message"].as_str().unwrap().contains("temperature"), "{}", body);
+}
+
+#[tokio::test]
+async fn oversized_bodies_and_unknown_fields_are_rejected() {
+    let app = app();
+    let (status, body) = chat(&app, json!({"user": "x".repeat(8192)})).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body["error"]["message"].as_str().unwrap().contains("larger than"), "{}", body);
+
+    // Ignored unless REJECT_UNKNOWN_FIELDS is set
+    assert_eq!(chat(&app, json!({"frobnicate": true})).await.0, StatusCode::OK);
+    set_overrides(vec![("REJECT_UNKNOWN_FIELDS".to_string(), "1".to_string())]);
+    let (status, body) = chat(&app, json!({"frobnicate": true})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["param"], "frobnicate");
+    // Fields set by the server, never by the body, are unknown as well
+    assert_eq!(chat(&app, json!({"client_id": "someone-else"})).await.1["error"]["param"], "client_id");
+    assert_eq!(chat(&app, json!({"max_tokens": 8, "stop": ["\n"], "cache": false})).await.0, StatusCode::OK);
+    set_overrides(vec![("REJECT_UNKNOWN_FIELDS".to_string(), "0".to_string())]);
+}
+
+#[tokio::test]
+async fn messages_and_embeddings_are_validated_too() {
+    let app = app();
+    let message = |extra: Value| {
+        let mut body = json!({"model": "dummy-model", "max_tokens": 16, "messages": [{"role": "user", "content": "hi"}]});
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        body
+    };
+    assert_eq!(send(&app, "POST", "/v1/messages", Some(message(json!({})))).await.0, StatusCode::OK);
+    for (extra, field) in [(json!({"messages": []}), "messages"), (json!({"temperature": 2.5}), "temperature"), (json!({"max_tokens": 0}), "max_tokens")] {
+        let (status, body) = send(&app, "POST", "/v1/messages", Some(message(extra.clone()))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", extra);
+        // In Anthropic's envelope
+        assert_eq!((&body["type"], &body["error"]["type"]), (&json!("error"), &json!("invalid_request_error")), "{}", body);
+        assert!(body["error"]["message"].as_str().unwrap().contains(field), "{}", body);
+    }
+    let (status, body) = send_raw(&app, "/v1/messages", "{\"model\": ").await;
+    assert_eq!((status, &body["type"]), (StatusCode::UNPROCESSABLE_ENTITY, &json!("error")), "{}", body);
+
+    let embed = |input: Value, extra: Value| {
+        let mut body = json!({"model": "dummy-embedding", "input": input});
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        body
+    };
+    assert_eq!(send(&app, "POST", "/v1/embeddings", Some(embed(json!(["a", "b"]), json!({"dimensions": 4})))).await.0, StatusCode::OK);
+    for (input, extra, param) in [
+        (json!(""), json!({}), "input"),
+        (json!([]), json!({}), "input"),
+        (json!(["a", ""]), json!({}), "input"),
+        (json!([[1, 2], []]), json!({}), "input"),
+        (json!("a"), json!({"dimensions": 0}), "dimensions"),
+    ] {
+        let (status, body) = send(&app, "POST", "/v1/embeddings", Some(embed(input.clone(), extra))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", input);
+        assert_eq!(body["error"]["param"], param, "{}", body);
+    }
+}
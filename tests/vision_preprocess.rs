@@ -0,0 +1,81 @@
+use llm_serving::runtime::{
+    image::RgbImage,
+    vision::{letterbox, preprocess, resize, select_grid, ImageDetail, SquareMode, VisionInputSpec, CLIP_MEAN, CLIP_STD},
+};
+
+fn solid(width: u32, height: u32, rgb: [u8; 3]) -> RgbImage {
+    RgbImage { width, height, pixels: rgb.iter().copied().cycle().take(width as usize * height as usize * 3).collect() }
+}
+
+fn spec(size: usize, square: SquareMode, max_tiles: usize) -> VisionInputSpec {
+    VisionInputSpec { size, mean: CLIP_MEAN, std: CLIP_STD, square, max_tiles }
+}
+
+#[test]
+fn detail_parses_with_auto_fallback() {
+    assert_eq!(ImageDetail::parse(Some("low")), ImageDetail::Low);
+    assert_eq!(ImageDetail::parse(Some("high")), ImageDetail::High);
+    assert_eq!(ImageDetail::parse(Some("ultra")), ImageDetail::Auto);
+    assert_eq!(ImageDetail::parse(None), ImageDetail::Auto);
+}
+
+#[test]
+fn downscaling_averages_instead_of_aliasing() {
+    // One-pixel black and white stripes average to grey rather than picking one colour
+    let mut stripes = solid(8, 2, [0, 0, 0]);
+    for x in (1..8).step_by(2) {
+        for y in 0..2 {
+            let i = (y * 8 + x) * 3;
+            stripes.pixels[i..i + 3].copy_from_slice(&[255, 255, 255]);
+        }
+    }
+    let small = resize(&stripes, 2, 1);
+    assert!(small.pixels.iter().all(|&v| (100..=155).contains(&v)), "{:?}", small.pixels);
+}
+
+#[test]
+fn letterbox_keeps_aspect_and_fills_with_the_mean() {
+    let image = letterbox(&solid(20, 10, [255, 0, 0]), 10, 10, [1, 2, 3]);
+    assert_eq!((image.width, image.height), (10, 10));
+    let at = |x: usize, y: usize| &image.pixels[(y * 10 + x) * 3..(y * 10 + x) * 3 + 3];
+    assert_eq!(at(5, 0), [1, 2, 3]);
+    assert_eq!(at(5, 9), [1, 2, 3]);
+    assert_eq!(at(5, 5), [255, 0, 0]);
+}
+
+#[test]
+fn grid_follows_the_aspect_ratio() {
+    assert_eq!(select_grid(100, 100, 336, 4), (1, 1));
+    assert_eq!(select_grid(1344, 336, 336, 4), (4, 1));
+    assert_eq!(select_grid(336, 672, 336, 4), (1, 2));
+    assert_eq!(select_grid(1000, 1000, 336, 4), (2, 2));
+    assert_eq!(select_grid(1000, 1000, 336, 0), (1, 1));
+}
+
+#[test]
+fn views_depend_on_detail_and_size() {
+    let large = solid(64, 16, [255, 255, 255]);
+    let small = solid(8, 8, [255, 255, 255]);
+    let spec = spec(16, SquareMode::Pad, 4);
+
+    assert_eq!(preprocess(&large, ImageDetail::Low, &spec).len(), 1);
+    // 64x16 over 16px tiles: the overview plus a 4x1 grid
+    let views = preprocess(&large, ImageDetail::High, &spec);
+    assert_eq!(views.len(), 5);
+    assert!(views.iter().all(|v| v.len() == 3 * 16 * 16));
+    assert_eq!(preprocess(&large, ImageDetail::Auto, &spec).len(), 5);
+    assert_eq!(preprocess(&small, ImageDetail::Auto, &spec).len(), 1);
+    // A 1x1 grid would only repeat the overview
+    assert_eq!(preprocess(&small, ImageDetail::High, &spec).len(), 1);
+}
+
+#[test]
+fn center_crop_fills_the_square_and_normalizes_chw() {
+    let image = solid(40, 20, [255, 0, 0]);
+    let views = preprocess(&image, ImageDetail::Low, &spec(10, SquareMode::CenterCrop, 0));
+    let plane = 100;
+    let red = (1.0 - CLIP_MEAN[0]) / CLIP_STD[0];
+    let green = (0.0 - CLIP_MEAN[1]) / CLIP_STD[1];
+    assert!(views[0][..plane].iter().all(|v| (v - red).abs() < 1e-4));
+    assert!(views[0][plane..2 * plane].iter().all(|v| (v - green).abs() < 1e-4));
+}
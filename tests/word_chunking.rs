@@ -0,0 +1,10 @@
+use llm_serving::engine::chunking::WordChunker;
+
+#[test]
+fn word_chunker_only_emits_whole_words() {
+    let mut chunker = WordChunker::default();
+    let tokens = ["Hel", "lo wo", "rld, ", "how", " are", " yo", "u"];
+    let emitted: Vec<String> = tokens.iter().filter_map(|t| chunker.push(t)).collect();
+    assert_eq!(emitted, vec!["Hello ", "world, ", "how ", "are "]);
+    assert_eq!(chunker.finish().as_deref(), Some("you"));
+}
@@ -0,0 +1,151 @@
+use axum::{routing::get, Router};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use llm_serving::{api::routes::websocket, engine::CoreEngine};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect() -> Socket {
+    connect_to(Arc::new(CoreEngine::new()), None).await
+}
+
+async fn connect_to(engine: Arc<CoreEngine>, key: Option<&str>) -> Socket {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/v1/ws", get(websocket)).with_state(engine);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let mut request = format!("ws://{}/v1/ws", addr).into_client_request().unwrap();
+    if let Some(key) = key {
+        request.headers_mut().insert("authorization", format!("Bearer {}", key).parse().unwrap());
+    }
+    let (socket, _) = connect_async(request).await.unwrap();
+    socket
+}
+
+async fn send(socket: &mut Socket, frame: Value) {
+    socket.send(Message::Text(frame.to_string())).await.unwrap();
+}
+
+async fn recv(socket: &mut Socket) -> Value {
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            _ => continue,
+        }
+    }
+}
+
+fn chat(id: &str, content: &str) -> Value {
+    json!({
+        "type": "chat",
+        "id": id,
+        "request": {"model": "dummy-model", "messages": [{"role": "user", "content": content}]}
+    })
+}
+
+#[tokio::test]
+async fn ws_multiplexes_chat_requests_by_id() {
+    let mut socket = connect().await;
+    send(&mut socket, chat("a", "first")).await;
+    send(&mut socket, chat("b", "second")).await;
+
+    let mut text: HashMap<String, String> = HashMap::new();
+    let mut done = Vec::new();
+    while done.len() < 2 {
+        let frame = recv(&mut socket).await;
+        let id = frame["id"].as_str().unwrap().to_string();
+        match frame["type"].as_str().unwrap() {
+            "chat.chunk" => {
+                assert_eq!(frame["chunk"]["object"], "chat.completion.chunk");
+                assert!(!done.contains(&id), "chunk after chat.done for {}", id);
+                if let Some(delta) = frame["chunk"]["choices"][0]["delta"]["content"].as_str() {
+                    text.entry(id).or_default().push_str(delta);
+                }
+            }
+            "chat.done" => done.push(id),
+            other => panic!("unexpected frame {}: {}", other, frame),
+        }
+    }
+    assert_eq!(text["a"], "Echo: first");
+    assert_eq!(text["b"], "Echo: second");
+
+    // A finished id can be reused
+    send(&mut socket, chat("a", "again")).await;
+    let frame = recv(&mut socket).await;
+    assert_eq!(frame["id"], "a");
+    assert_eq!(frame["type"], "chat.chunk");
+}
+
+#[tokio::test]
+async fn ws_reports_bad_frames_and_unknown_cancels() {
+    let mut socket = connect().await;
+
+    send(&mut socket, json!({"type": "bogus"})).await;
+    let frame = recv(&mut socket).await;
+    assert_eq!(frame["type"], "error");
+    assert!(frame.get("id").is_none());
+    assert!(frame["message"].as_str().unwrap().starts_with("invalid message"));
+
+    send(&mut socket, json!({"type": "cancel", "id": "nope"})).await;
+    let frame = recv(&mut socket).await;
+    assert_eq!(frame, json!({"type": "error", "id": "nope", "message": "no request with this id is in flight", "error_type": "invalid_request_error"}));
+
+    // Engine failures carry the type and code the HTTP API answers them with
+    send(&mut socket, json!({"type": "chat", "id": "m", "request": {"model": "missing", "messages": [{"role": "user", "content": "hi"}]}})).await;
+    let frame = recv(&mut socket).await;
+    assert_eq!((frame["type"].as_str(), frame["id"].as_str()), (Some("error"), Some("m")));
+    assert_eq!((frame["error_type"].as_str(), frame["code"].as_str()), (Some("invalid_request_error"), Some("model_not_found")));
+
+    // The connection survives both
+    send(&mut socket, chat("ok", "still here")).await;
+    assert_eq!(recv(&mut socket).await["id"], "ok");
+}
+
+#[tokio::test]
+async fn ws_requests_cannot_ask_for_more_priority_than_their_key_allows() {
+    // Only this test reads the setting
+    llm_serving::config::set_overrides(vec![("API_KEY_PRIORITIES".to_string(), "background=low".to_string())]);
+    let engine = Arc::new(CoreEngine::new());
+    let mut events = engine.events().subscribe();
+    let mut socket = connect_to(engine, Some("background")).await;
+
+    let mut frame = chat("p", "urgent");
+    frame["request"]["priority"] = json!("high");
+    send(&mut socket, frame).await;
+    while recv(&mut socket).await["type"] != "chat.done" {}
+
+    loop {
+        let event = serde_json::to_value(events.recv().await.unwrap()).unwrap();
+        if event["type"] == "request_started" {
+            assert_eq!(event["priority"], "low");
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn ws_frames_are_validated_like_http_requests() {
+    let mut socket = connect().await;
+    for (request, param) in [
+        (json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}], "max_tokens": 1_000_000}), "max_tokens"),
+        (json!({"model": "dummy-model", "messages": [{"role": "user", "content": "hi"}], "temperature": 5.0}), "temperature"),
+        (json!({"model": "dummy-model", "messages": []}), "messages"),
+    ] {
+        send(&mut socket, json!({"type": "chat", "id": "v", "request": request})).await;
+        let frame = recv(&mut socket).await;
+        assert_eq!((frame["type"].as_str(), frame["id"].as_str()), (Some("error"), Some("v")), "{}", frame);
+        assert!(frame["message"].as_str().unwrap().contains(param), "{}", frame);
+    }
+
+    // Refused requests were never in flight, so their id is free
+    send(&mut socket, chat("v", "fine")).await;
+    assert_eq!(recv(&mut socket).await["type"], "chat.chunk");
+}